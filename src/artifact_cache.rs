@@ -0,0 +1,122 @@
+//! Caches the libraries cargo built in `target/maturin-cache`, so that building an unchanged
+//! project again for the same interpreter and options skips cargo and only packages the wheel.
+//!
+//! The cache is opt-in with `[tool.maturin] cache = true` or `MATURIN_CACHE=1`, because unlike
+//! cargo's own freshness check the key misses some inputs: path dependencies outside of the
+//! workspace, files ignored by git that build scripts or `include_bytes!` read, `rerun-if-changed`
+//! paths outside of the project and environment variables other than those in
+//! [BUILD_ENV_PREFIXES], e.g. `CC`, `CFLAGS` or `PKG_CONFIG_PATH`.
+use crate::compile::BuildArtifact;
+use crate::wheel_manifest;
+use crate::{BuildContext, PythonInterpreter};
+use anyhow::{Context, Result};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Overrides `[tool.maturin.cache]`
+const CACHE_ENV_VAR: &str = "MATURIN_CACHE";
+
+/// Environment variables that can change what cargo builds
+const BUILD_ENV_PREFIXES: &[&str] = &["CARGO_", "RUST", "PYO3_", "MACOSX_DEPLOYMENT_TARGET"];
+
+/// The cache directory in the target directory
+pub fn cache_dir(target_dir: &Path) -> PathBuf {
+    target_dir.join("maturin-cache")
+}
+
+/// Whether the cache is used, `[tool.maturin.cache]` unless `MATURIN_CACHE` is set
+pub(crate) fn cache_enabled(pyproject_cache: bool) -> bool {
+    match env::var(CACHE_ENV_VAR) {
+        Ok(value) => value == "1" || value.eq_ignore_ascii_case("true"),
+        Err(_) => pyproject_cache,
+    }
+}
+
+/// The library of the last build of one interpreter, target and set of options
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheEntry {
+    /// The hash of the sources and options the library was built from
+    source_hash: String,
+    /// The file name of the library in the cache directory of the entry
+    file_name: String,
+    /// The library search paths from the build scripts
+    linked_paths: Vec<String>,
+}
+
+/// The cache directory of the builds for the interpreter, target, options and toolchain. Each of
+/// them only keeps the library of its last build, so the cache doesn't grow with every change.
+fn entry_dir(context: &BuildContext, python_interpreter: Option<&PythonInterpreter>) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update(context.target.target_triple());
+    hasher.update(wheel_manifest::build_options(context));
+    hasher.update(&context.target.rustc_version.short_version_string);
+    if let Some(python) = python_interpreter {
+        hasher.update(format!(
+            "{} {}.{}{} {:?}",
+            python.interpreter_kind, python.major, python.minor, python.abiflags, python.soabi
+        ));
+    }
+    let mut build_env: Vec<(String, String)> = env::vars()
+        .filter(|(key, _)| {
+            BUILD_ENV_PREFIXES
+                .iter()
+                .any(|prefix| key.starts_with(prefix))
+        })
+        .collect();
+    build_env.sort();
+    for (key, value) in build_env {
+        hasher.update(format!("{}={}\n", key, value));
+    }
+    let key = format!("{:x}", hasher.finalize());
+    cache_dir(&context.target_dir).join(&key[..16])
+}
+
+/// The library cargo built from the same sources for the interpreter, if it's cached
+pub fn load(
+    context: &BuildContext,
+    python_interpreter: Option<&PythonInterpreter>,
+    source_hash: &str,
+) -> Option<BuildArtifact> {
+    let dir = entry_dir(context, python_interpreter);
+    let contents = fs::read_to_string(dir.join("entry.json")).ok()?;
+    let entry: CacheEntry = serde_json::from_str(&contents).ok()?;
+    let path = dir.join(&entry.file_name);
+    if entry.source_hash != source_hash || !path.is_file() {
+        return None;
+    }
+    Some(BuildArtifact {
+        path,
+        linked_paths: entry.linked_paths,
+    })
+}
+
+/// Caches the library cargo built for the interpreter, replacing the one of an earlier build
+pub fn store(
+    context: &BuildContext,
+    python_interpreter: Option<&PythonInterpreter>,
+    source_hash: &str,
+    artifact: &BuildArtifact,
+) -> Result<()> {
+    let dir = entry_dir(context, python_interpreter);
+    fs::create_dir_all(&dir)?;
+    let file_name = artifact
+        .path
+        .file_name()
+        .context("Invalid library path")?
+        .to_string_lossy()
+        .to_string();
+    fs::copy(&artifact.path, dir.join(&file_name))?;
+    let entry = CacheEntry {
+        source_hash: source_hash.to_string(),
+        file_name,
+        linked_paths: artifact.linked_paths.clone(),
+    };
+    fs::write(
+        dir.join("entry.json"),
+        serde_json::to_string_pretty(&entry)?,
+    )
+    .with_context(|| format!("Failed to write the cache entry in {}", dir.display()))
+}
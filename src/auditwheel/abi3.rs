@@ -0,0 +1,178 @@
+//! Checks that an abi3 library only uses the stable ABI of its minimum Python version, like
+//! [abi3audit](https://github.com/trailofbits/abi3audit) does
+use super::AuditWheelError;
+use anyhow::{bail, Result};
+use fs_err as fs;
+use goblin::mach::{Mach, MachO};
+use goblin::Object;
+use once_cell::sync::Lazy;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// The functions and data of the stable ABI with the minor version of Python 3 that added them,
+/// generated from CPython's `Misc/stable_abi.toml` by the `update-stable-abi` workflow
+static STABLE_ABI: Lazy<HashMap<&'static str, u8>> = Lazy::new(|| {
+    include_str!("stable-abi.txt")
+        .lines()
+        .filter_map(|line| {
+            let (name, version) = line.split_once(' ')?;
+            let minor = version.strip_prefix("3.")?.parse().ok()?;
+            Some((name, minor))
+        })
+        .collect()
+});
+
+/// Whether the symbol belongs to the Python C API
+fn is_python_symbol(name: &str) -> bool {
+    name.starts_with("Py") || name.starts_with("_Py")
+}
+
+/// The Python C API symbols the library imports from the interpreter
+fn python_imports(buffer: &[u8]) -> Result<BTreeSet<String>> {
+    let mut imports = BTreeSet::new();
+    match Object::parse(buffer).map_err(AuditWheelError::GoblinError)? {
+        Object::Elf(elf) => {
+            for sym in elf.dynsyms.iter() {
+                if sym.st_shndx != goblin::elf::section_header::SHN_UNDEF as usize {
+                    continue;
+                }
+                if let Some(name) = elf.dynstrtab.get_at(sym.st_name) {
+                    if is_python_symbol(name) {
+                        imports.insert(name.to_string());
+                    }
+                }
+            }
+        }
+        Object::Mach(mach) => {
+            let machos = match mach {
+                Mach::Binary(macho) => vec![macho],
+                Mach::Fat(fat) => {
+                    let mut machos = Vec::new();
+                    for arch in fat.iter_arches() {
+                        let arch = arch.map_err(AuditWheelError::GoblinError)?;
+                        machos.push(
+                            MachO::parse(arch.slice(buffer), 0)
+                                .map_err(AuditWheelError::GoblinError)?,
+                        );
+                    }
+                    machos
+                }
+            };
+            for macho in machos {
+                for sym in macho.symbols() {
+                    let (name, nlist) = sym.map_err(AuditWheelError::GoblinError)?;
+                    // Mach-O symbols have a leading underscore
+                    let name = name.strip_prefix('_').unwrap_or(name);
+                    if nlist.is_undefined() && is_python_symbol(name) {
+                        imports.insert(name.to_string());
+                    }
+                }
+            }
+        }
+        Object::PE(pe) => {
+            for import in &pe.imports {
+                let dll = import.dll.to_ascii_lowercase();
+                if dll.starts_with("python3") && dll.ends_with(".dll") {
+                    imports.insert(import.name.to_string());
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(imports)
+}
+
+/// The imported symbols that aren't part of the stable ABI of Python `major.min_minor`
+fn non_limited_api_symbols(imports: BTreeSet<String>, major: u8, min_minor: u8) -> Vec<String> {
+    imports
+        .into_iter()
+        .filter(|name| match STABLE_ABI.get(name.as_str()) {
+            Some(&added) => major != 3 || added > min_minor,
+            None => true,
+        })
+        .collect()
+}
+
+/// Fails with the symbols of `imports` that aren't part of the stable ABI of Python
+/// `major.min_minor`
+fn check_limited_api(imports: BTreeSet<String>, major: u8, min_minor: u8) -> Result<()> {
+    let symbols = non_limited_api_symbols(imports, major, min_minor);
+    if !symbols.is_empty() {
+        bail!(AuditWheelError::NonLimitedApiError(
+            format!("{}.{}", major, min_minor),
+            symbols,
+        ));
+    }
+    Ok(())
+}
+
+/// Fails if the abi3 library uses Python symbols outside of the stable ABI of its minimum Python
+/// version, which makes the wheel fail to import, or worse crash, on some of the versions it
+/// claims to support. `--skip-auditwheel` skips the check.
+pub fn audit_abi3(artifact: &Path, major: u8, min_minor: u8) -> Result<()> {
+    let buffer = fs::read(artifact).map_err(AuditWheelError::IoError)?;
+    check_limited_api(python_imports(&buffer)?, major, min_minor)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_non_limited_api_symbols() {
+        let imports: BTreeSet<String> = [
+            "PyModule_Create2",
+            "Py_NewRef",
+            "PyObject_Vectorcall",
+            "_PyLong_AsByteArray",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        assert_eq!(
+            non_limited_api_symbols(imports.clone(), 3, 7),
+            ["PyObject_Vectorcall", "Py_NewRef", "_PyLong_AsByteArray"]
+        );
+        assert_eq!(
+            non_limited_api_symbols(imports.clone(), 3, 10),
+            ["PyObject_Vectorcall", "_PyLong_AsByteArray"]
+        );
+        assert_eq!(
+            non_limited_api_symbols(imports, 3, 12),
+            ["_PyLong_AsByteArray"]
+        );
+        let imports: BTreeSet<String> = ["PyType_GetModuleByDef".to_string()].into();
+        assert_eq!(
+            non_limited_api_symbols(imports.clone(), 3, 11),
+            ["PyType_GetModuleByDef"]
+        );
+        assert!(non_limited_api_symbols(imports, 3, 13).is_empty());
+    }
+
+    #[test]
+    fn test_check_limited_api() {
+        let imports: BTreeSet<String> = ["PyModule_Create2", "Py_NewRef"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert!(check_limited_api(imports.clone(), 3, 10).is_ok());
+        let err = check_limited_api(imports, 3, 7).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(AuditWheelError::NonLimitedApiError(version, symbols))
+                if version == "3.7" && symbols == &["Py_NewRef"]),
+            "{:?}",
+            err
+        );
+        assert!(err.to_string().contains("--skip-auditwheel"));
+    }
+
+    #[test]
+    fn test_audit_abi3() {
+        // A library without Python imports passes, a file that isn't one fails
+        let exe = std::env::current_exe().unwrap();
+        assert!(audit_abi3(&exe, 3, 7).is_ok());
+        let not_a_library = tempfile::NamedTempFile::new().unwrap();
+        fs::write(not_a_library.path(), b"not a library").unwrap();
+        assert!(audit_abi3(not_a_library.path(), 3, 7).is_err());
+    }
+}
@@ -52,11 +52,30 @@ pub enum AuditWheelError {
     /// This platform tag isn't defined by auditwheel yet
     #[error("{0} compatibility policy is not defined by auditwheel yet, pass `--skip-auditwheel` to proceed anyway")]
     UndefinedPolicy(String),
+    /// The abi3 library uses symbols outside of the stable ABI of its minimum Python version.
+    /// Contains the minimum version and the offending symbols.
+    #[error(
+    "Your library is built for abi3 Python ≥ {0}, but uses symbols that are not part of the stable ABI of that version: {1:?}. Raise the abi3 version or avoid the non-limited API, or pass `--skip-auditwheel` to proceed anyway",
+    )]
+    NonLimitedApiError(String, Vec<String>),
     /// Failed to analyze external shared library dependencies of the wheel
     #[error("Failed to analyze external shared library dependencies of the wheel")]
     DependencyAnalysisError(#[source] lddtree::Error),
 }
 
+impl AuditWheelError {
+    /// Whether the library violates the requested policy, as opposed to being unusable
+    /// everywhere (e.g. linking libpython) or failing to be analyzed
+    pub fn is_policy_violation(&self) -> bool {
+        matches!(
+            self,
+            AuditWheelError::VersionedSymbolTooNewError(..)
+                | AuditWheelError::BlackListedSymbolsError(..)
+                | AuditWheelError::UnsupportedArchitecture(..)
+        )
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct VersionedLibrary {
     /// library name
@@ -259,9 +278,10 @@ fn get_default_platform_policies() -> Vec<Policy> {
 pub fn auditwheel_rs(
     artifact: &BuildArtifact,
     target: &Target,
-    platform_tag: Option<PlatformTag>,
+    platform_tag: Option<&PlatformTag>,
+    extra_policies: &[Policy],
 ) -> Result<(Policy, bool), AuditWheelError> {
-    if !target.is_linux() || platform_tag == Some(PlatformTag::Linux) {
+    if !target.is_linux() || platform_tag == Some(&PlatformTag::Linux) {
         return Ok((Policy::default(), false));
     }
     let path = &artifact.path;
@@ -296,6 +316,15 @@ pub fn auditwheel_rs(
             }
             policies
         }
+        Some(platform_tag @ PlatformTag::Custom(_)) => {
+            Policy::from_platform_tag(platform_tag, extra_policies)
+                .map(|mut policy| {
+                    policy.fixup_musl_libc_so_name(target.target_arch());
+                    policy
+                })
+                .into_iter()
+                .collect()
+        }
         Some(PlatformTag::Linux) => unreachable!(),
     };
     let mut highest_policy = None;
@@ -325,7 +354,8 @@ pub fn auditwheel_rs(
 
     let policy = if let Some(platform_tag) = platform_tag {
         let tag = platform_tag.to_string();
-        let mut policy = Policy::from_name(&tag).ok_or(AuditWheelError::UndefinedPolicy(tag))?;
+        let mut policy = Policy::from_platform_tag(platform_tag, extra_policies)
+            .ok_or(AuditWheelError::UndefinedPolicy(tag))?;
         policy.fixup_musl_libc_so_name(target.target_arch());
 
         if let Some(highest_policy) = highest_policy {
@@ -415,31 +445,61 @@ pub fn get_sysroot_path(target: &Target) -> Result<PathBuf> {
     Ok(PathBuf::from("/"))
 }
 
+/// The policy of the newest manylinux or musllinux version, whose libraries every linux system
+/// has, to find the libraries to bundle into wheels with the linux tag
+fn base_system_policy(target: &Target) -> Policy {
+    let policies = if target.is_musl_target() {
+        &*MUSLLINUX_POLICIES
+    } else {
+        &*MANYLINUX_POLICIES
+    };
+    let mut policy = policies
+        .iter()
+        .filter(|policy| policy.name != "linux")
+        .min_by_key(|policy| policy.priority)
+        .cloned()
+        .unwrap_or_default();
+    policy.fixup_musl_libc_so_name(target.target_arch());
+    policy
+}
+
 /// For the given compilation result, return the manylinux platform and the external libs
-/// we need to add to repair it
+/// we need to add to repair it. With `repair`, wheels with the linux tag get the libs outside
+/// of the base system as well.
 pub fn get_policy_and_libs(
     artifact: &BuildArtifact,
-    platform_tag: Option<PlatformTag>,
+    platform_tag: Option<&PlatformTag>,
     target: &Target,
+    extra_policies: &[Policy],
+    repair: bool,
 ) -> Result<(Policy, Vec<Library>)> {
-    let (policy, should_repair) =
-        auditwheel_rs(artifact, target, platform_tag).with_context(|| {
+    let (policy, should_repair) = auditwheel_rs(artifact, target, platform_tag, extra_policies)
+        .with_context(|| {
             if let Some(platform_tag) = platform_tag {
                 format!("Error ensuring {} compliance", platform_tag)
             } else {
                 "Error checking for manylinux/musllinux compliance".to_string()
             }
         })?;
-    let external_libs = if should_repair {
+    let repair_policy = if should_repair {
+        Some(policy.clone())
+    } else if repair && target.is_linux() && policy.platform_tag() == PlatformTag::Linux {
+        Some(base_system_policy(target))
+    } else {
+        None
+    };
+    let external_libs = if let Some(repair_policy) = repair_policy {
         let sysroot = get_sysroot_path(target).unwrap_or_else(|_| PathBuf::from("/"));
         let ld_paths = artifact.linked_paths.iter().map(PathBuf::from).collect();
-        find_external_libs(&artifact.path, &policy, sysroot, ld_paths).with_context(|| {
-            if let Some(platform_tag) = platform_tag {
-                format!("Error repairing wheel for {} compliance", platform_tag)
-            } else {
-                "Error repairing wheel for manylinux/musllinux compliance".to_string()
-            }
-        })?
+        find_external_libs(&artifact.path, &repair_policy, sysroot, ld_paths).with_context(
+            || {
+                if let Some(platform_tag) = platform_tag {
+                    format!("Error repairing wheel for {} compliance", platform_tag)
+                } else {
+                    "Error repairing wheel for manylinux/musllinux compliance".to_string()
+                }
+            },
+        )?
     } else {
         Vec::new()
     };
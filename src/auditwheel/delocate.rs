@@ -0,0 +1,294 @@
+//! Bundles the dylibs a macOS library links from outside of the system into the wheel, like
+//! [delocate](https://github.com/matthew-brett/delocate) does, so that the wheel works on
+//! machines without e.g. Homebrew
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use goblin::mach::{Mach, MachO};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A dylib from outside of the system that was copied to be bundled into the wheel
+#[derive(Debug, Clone)]
+pub struct BundledDylib {
+    /// Where the dylib was found
+    pub source: PathBuf,
+    /// The copy with the rewritten install names
+    pub copy: PathBuf,
+    /// The file name in the libs directory of the wheel
+    pub name: String,
+}
+
+/// Whether the dylib comes with macOS, so it must not be bundled. libpython is provided by the
+/// interpreter that loads the extension.
+fn is_system_dylib(install_name: &str) -> bool {
+    install_name.starts_with("/usr/lib/")
+        || install_name.starts_with("/System/")
+        || install_name.contains("libpython")
+        || install_name.contains("Python.framework")
+}
+
+/// The install names of the dylibs and the rpaths of a Mach-O file, over all architectures of a
+/// fat binary
+fn load_commands(path: &Path) -> Result<(Vec<String>, Vec<String>)> {
+    let buffer = fs::read(path)?;
+    let slices = match Mach::parse(&buffer)
+        .with_context(|| format!("Failed to parse {} as Mach-O file", path.display()))?
+    {
+        Mach::Binary(_) => vec![&buffer[..]],
+        Mach::Fat(fat) => fat
+            .iter_arches()
+            .map(|arch| Ok(arch?.slice(&buffer)))
+            .collect::<Result<Vec<_>>>()?,
+    };
+    let mut libs = Vec::new();
+    let mut rpaths = Vec::new();
+    for slice in slices {
+        let macho = MachO::parse(slice, 0)?;
+        // goblin lists the file itself as first library
+        for lib in macho.libs.iter().skip(1) {
+            if !libs.iter().any(|known| known == lib) {
+                libs.push(lib.to_string());
+            }
+        }
+        for rpath in macho.rpaths {
+            if !rpaths.iter().any(|known| known == rpath) {
+                rpaths.push(rpath.to_string());
+            }
+        }
+    }
+    Ok((libs, rpaths))
+}
+
+/// Finds the dylib of an install name linked by the file in `loader_dir`. `@rpath` is looked up
+/// in the rpaths of the file, then in the search paths of the build.
+fn resolve_install_name(
+    install_name: &str,
+    loader_dir: &Path,
+    rpaths: &[String],
+    search_paths: &[PathBuf],
+) -> Option<PathBuf> {
+    let substitute = |path: &str| -> PathBuf {
+        if let Some(rest) = path
+            .strip_prefix("@loader_path/")
+            .or_else(|| path.strip_prefix("@executable_path/"))
+        {
+            loader_dir.join(rest)
+        } else {
+            PathBuf::from(path)
+        }
+    };
+    let candidates: Vec<PathBuf> = if let Some(rest) = install_name.strip_prefix("@rpath/") {
+        rpaths
+            .iter()
+            .map(|rpath| substitute(rpath))
+            .chain(search_paths.iter().cloned())
+            .map(|dir| dir.join(rest))
+            .collect()
+    } else {
+        vec![substitute(install_name)]
+    };
+    candidates.into_iter().find(|candidate| candidate.is_file())
+}
+
+/// The dylibs from outside of the system that a Mach-O file links, with their install names
+fn external_dylibs(path: &Path, search_paths: &[PathBuf]) -> Result<Vec<(String, PathBuf)>> {
+    let (libs, rpaths) = load_commands(path)?;
+    let loader_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut dylibs = Vec::new();
+    for install_name in libs {
+        if is_system_dylib(&install_name) {
+            continue;
+        }
+        let dylib = resolve_install_name(&install_name, loader_dir, &rpaths, search_paths)
+            .with_context(|| {
+                format!(
+                    "Cannot bundle {}, which {} links, because it could not be located",
+                    install_name,
+                    path.display()
+                )
+            })?;
+        let dylib = fs::canonicalize(&dylib)?;
+        dylibs.push((install_name, dylib));
+    }
+    Ok(dylibs)
+}
+
+/// Runs `install_name_tool` with the arguments on the file and signs it again, since changing
+/// the load commands invalidates the signature, which arm64 requires
+fn rewrite(file: &Path, args: &[(&str, String, Option<String>)]) -> Result<()> {
+    if args.is_empty() {
+        return Ok(());
+    }
+    let mut cmd = Command::new("install_name_tool");
+    for (option, first, second) in args {
+        cmd.arg(option).arg(first);
+        if let Some(second) = second {
+            cmd.arg(second);
+        }
+    }
+    cmd.arg(file);
+    run(cmd, "install_name_tool")?;
+
+    let mut cmd = Command::new("codesign");
+    cmd.args(["--force", "--sign", "-"]).arg(file);
+    run(cmd, "codesign")
+}
+
+fn run(mut cmd: Command, program: &str) -> Result<()> {
+    let output = cmd.output().with_context(|| {
+        format!(
+            "Failed to execute '{}', which is part of the Xcode command line tools. \
+            Hint: Try `xcode-select --install`",
+            program
+        )
+    })?;
+    if !output.status.success() {
+        bail!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+/// Copies the dylibs from outside of the system that the artifacts link, directly or through
+/// other dylibs, to `temp_dir` and rewrites the load commands of the artifacts and the copies to
+/// load them from the libs directory, which is at `libs_dir_from_artifact` relative to the
+/// artifacts in the wheel. The artifacts are modified in place, so they must be copies.
+pub fn delocate(
+    artifacts: &[&Path],
+    search_paths: &[PathBuf],
+    temp_dir: &Path,
+    libs_dir_from_artifact: &Path,
+) -> Result<Vec<BundledDylib>> {
+    // The dylibs to bundle by their path, with the install names each file links them by
+    let mut bundled: HashMap<PathBuf, BundledDylib> = HashMap::new();
+    let mut links: Vec<(PathBuf, Vec<(String, PathBuf)>)> = Vec::new();
+    let mut queue: VecDeque<PathBuf> = artifacts.iter().map(|path| path.to_path_buf()).collect();
+    while let Some(file) = queue.pop_front() {
+        let dylibs = external_dylibs(&file, search_paths)?;
+        for (_, dylib) in &dylibs {
+            if bundled.contains_key(dylib) {
+                continue;
+            }
+            let name = dylib
+                .file_name()
+                .and_then(OsStr::to_str)
+                .context("Invalid dylib file name")?
+                .to_string();
+            if let Some(other) = bundled.values().find(|other| other.name == name) {
+                bail!(
+                    "Cannot bundle both {} and {}, since they have the same file name",
+                    other.source.display(),
+                    dylib.display()
+                );
+            }
+            let copy = temp_dir.join(&name);
+            fs::copy(dylib, &copy)?;
+            // Homebrew installs the dylibs read-only, which install_name_tool can't modify
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&copy, std::fs::Permissions::from_mode(0o755))?;
+            }
+            bundled.insert(
+                dylib.clone(),
+                BundledDylib {
+                    source: dylib.clone(),
+                    copy,
+                    name,
+                },
+            );
+            queue.push_back(dylib.clone());
+        }
+        links.push((file, dylibs));
+    }
+
+    for (file, dylibs) in links {
+        let copy = bundled.get(&file);
+        let prefix = if copy.is_some() {
+            // The bundled dylibs are next to each other
+            PathBuf::from("@loader_path")
+        } else {
+            Path::new("@loader_path").join(libs_dir_from_artifact)
+        };
+        let mut args = Vec::new();
+        if let Some(copy) = copy {
+            args.push(("-id", format!("@loader_path/{}", copy.name), None));
+        }
+        for (install_name, dylib) in dylibs {
+            let name = &bundled[&dylib].name;
+            let new_name = prefix.join(name).to_string_lossy().to_string();
+            args.push(("-change", install_name, Some(new_name)));
+        }
+        let target = copy.map_or(file.clone(), |copy| copy.copy.clone());
+        rewrite(&target, &args).with_context(|| {
+            format!("Failed to rewrite the load commands of {}", file.display())
+        })?;
+    }
+
+    let mut bundled: Vec<BundledDylib> = bundled.into_values().collect();
+    bundled.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(bundled)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_system_dylib() {
+        assert!(is_system_dylib("/usr/lib/libSystem.B.dylib"));
+        assert!(is_system_dylib(
+            "/System/Library/Frameworks/CoreFoundation.framework/Versions/A/CoreFoundation"
+        ));
+        assert!(is_system_dylib("@rpath/libpython3.11.dylib"));
+        assert!(!is_system_dylib(
+            "/opt/homebrew/opt/openssl@3/lib/libssl.3.dylib"
+        ));
+        assert!(!is_system_dylib("@rpath/libfoo.dylib"));
+    }
+
+    #[test]
+    fn test_resolve_install_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib_dir = dir.path().join("lib");
+        let deps_dir = dir.path().join("deps");
+        fs::create_dir_all(&lib_dir).unwrap();
+        fs::create_dir_all(&deps_dir).unwrap();
+        fs::write(lib_dir.join("libfoo.dylib"), "").unwrap();
+        fs::write(deps_dir.join("libbar.dylib"), "").unwrap();
+
+        let absolute = lib_dir.join("libfoo.dylib");
+        assert_eq!(
+            resolve_install_name(absolute.to_str().unwrap(), dir.path(), &[], &[]),
+            Some(absolute.clone())
+        );
+        assert_eq!(
+            resolve_install_name("@loader_path/lib/libfoo.dylib", dir.path(), &[], &[]),
+            Some(absolute)
+        );
+        // From the rpaths of the file, then the search paths
+        let rpaths = ["@loader_path/lib".to_string()];
+        assert_eq!(
+            resolve_install_name("@rpath/libfoo.dylib", dir.path(), &rpaths, &[]),
+            Some(lib_dir.join("libfoo.dylib"))
+        );
+        assert_eq!(
+            resolve_install_name(
+                "@rpath/libbar.dylib",
+                dir.path(),
+                &rpaths,
+                &[deps_dir.clone()]
+            ),
+            Some(deps_dir.join("libbar.dylib"))
+        );
+        assert_eq!(
+            resolve_install_name("@rpath/libbaz.dylib", dir.path(), &rpaths, &[deps_dir]),
+            None
+        );
+    }
+}
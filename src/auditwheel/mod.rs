@@ -1,10 +1,13 @@
+mod abi3;
 mod audit;
+pub mod delocate;
 mod musllinux;
 pub mod patchelf;
 mod platform_tag;
 mod policy;
 mod repair;
 
+pub use abi3::audit_abi3;
 pub use audit::*;
 pub use platform_tag::PlatformTag;
 pub use policy::{Policy, MANYLINUX_POLICIES, MUSLLINUX_POLICIES};
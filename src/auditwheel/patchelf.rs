@@ -1,80 +1,364 @@
+//! Rewrites the dynamic section of ELF files the way patchelf does, so repairing wheels doesn't
+//! need an external tool.
+//!
+//! Edits that fit into the existing dynamic section and only use strings `.dynstr` already
+//! contains are written in place. Otherwise the new string table and dynamic section are written
+//! to a segment appended to the file, together with a copy of the program headers that has an
+//! extra `PT_LOAD` entry for it. Editing the same file again replaces that segment instead of
+//! appending another one.
 use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use goblin::container::{Container, Ctx, Endian};
+use goblin::elf::dynamic::{
+    Dyn, DT_NEEDED, DT_NULL, DT_RPATH, DT_RUNPATH, DT_SONAME, DT_STRSZ, DT_STRTAB,
+};
+use goblin::elf::program_header::{PF_R, PF_W, PT_DYNAMIC, PT_LOAD, PT_PHDR};
+use goblin::elf::section_header::SHT_DYNAMIC;
+use goblin::elf::{Elf, ProgramHeader, SectionHeader};
+use scroll::ctx::IntoCtx;
+use scroll::Pwrite;
 use std::ffi::OsStr;
 use std::path::Path;
-use std::process::Command;
 
-/// Replace a declared dependency on a dynamic library with another one (`DT_NEEDED`)
-pub fn replace_needed<O: AsRef<OsStr>, N: AsRef<OsStr>>(
-    file: impl AsRef<Path>,
-    old_new_pairs: &[(O, N)],
-) -> Result<()> {
-    let mut cmd = Command::new("patchelf");
-    for (old, new) in old_new_pairs {
-        cmd.arg("--replace-needed").arg(old).arg(new);
+/// The smallest page size segments are aligned to, when the linker chose a smaller `p_align`
+const MIN_PAGE_SIZE: u64 = 0x1000;
+
+/// The value of an entry of the dynamic section, with the strings resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DynValue {
+    Str(String),
+    Val(u64),
+}
+
+/// An entry of the dynamic section
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DynEntry {
+    tag: u64,
+    value: DynValue,
+}
+
+impl DynEntry {
+    fn string(tag: u64, value: impl Into<String>) -> Self {
+        DynEntry {
+            tag,
+            value: DynValue::Str(value.into()),
+        }
     }
-    cmd.arg(file.as_ref());
-    let output = cmd
-        .output()
-        .context("Failed to execute 'patchelf', did you install it? Hint: Try `pip install maturin[patchelf]` (or just `pip install patchelf`)")?;
-    if !output.status.success() {
-        bail!(
-            "patchelf --replace-needed failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
+
+    fn str(&self) -> Option<&str> {
+        match &self.value {
+            DynValue::Str(value) => Some(value),
+            DynValue::Val(_) => None,
+        }
     }
+}
+
+/// Whether the value of the entry is an offset into the string table
+fn is_string_tag(tag: u64) -> bool {
+    matches!(tag, DT_NEEDED | DT_SONAME | DT_RPATH | DT_RUNPATH)
+}
+
+/// The offset of `value` in the string table, also as the suffix of a longer string
+fn find_string(strtab: &[u8], value: &str) -> Option<usize> {
+    let value = value.as_bytes();
+    strtab
+        .windows(value.len() + 1)
+        .position(|window| window[value.len()] == 0 && &window[..value.len()] == value)
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    (value + align - 1) / align * align
+}
+
+/// Parses the dynamic section of `file`, lets `edit` change its entries and writes the result
+/// back
+fn modify_dynamic(file: &Path, edit: impl FnOnce(&mut Vec<DynEntry>)) -> Result<()> {
+    let data = fs::read(file)?;
+    let data = rewrite_dynamic(&data, edit)
+        .with_context(|| format!("Failed to rewrite the ELF file {}", file.display()))?;
+    fs::write(file, data)?;
     Ok(())
 }
 
-/// Change `SONAME` of a dynamic library
-pub fn set_soname<S: AsRef<OsStr>>(file: impl AsRef<Path>, soname: &S) -> Result<()> {
-    let mut cmd = Command::new("patchelf");
-    cmd.arg("--set-soname").arg(soname).arg(file.as_ref());
-    let output = cmd
-        .output()
-        .context("Failed to execute 'patchelf', did you install it?")?;
-    if !output.status.success() {
-        bail!(
-            "patchelf --set-soname failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
+/// Returns `data` with the entries of the dynamic section changed by `edit`
+fn rewrite_dynamic(data: &[u8], edit: impl FnOnce(&mut Vec<DynEntry>)) -> Result<Vec<u8>> {
+    let elf = Elf::parse(data).context("Failed to parse the ELF file")?;
+    let ctx = Ctx::new(
+        if elf.is_64 {
+            Container::Big
+        } else {
+            Container::Little
+        },
+        if elf.little_endian {
+            Endian::Little
+        } else {
+            Endian::Big
+        },
+    );
+    let dynamic = elf
+        .dynamic
+        .as_ref()
+        .context("The file has no dynamic section")?;
+    let dynamic_phdr = elf
+        .program_headers
+        .iter()
+        .find(|phdr| phdr.p_type == PT_DYNAMIC)
+        .context("The file has no dynamic segment")?;
+    let strtab = data
+        .get(dynamic.info.strtab..dynamic.info.strtab + dynamic.info.strsz)
+        .context("The dynamic string table is out of bounds")?
+        .to_vec();
+
+    let mut entries = Vec::new();
+    for dyn_ in dynamic.dyns.iter().filter(|dyn_| dyn_.d_tag != DT_NULL) {
+        let value = if is_string_tag(dyn_.d_tag) {
+            let value = elf
+                .dynstrtab
+                .get_at(dyn_.d_val as usize)
+                .with_context(|| format!("Invalid string offset {:#x}", dyn_.d_val))?;
+            DynValue::Str(value.to_string())
+        } else {
+            DynValue::Val(dyn_.d_val)
+        };
+        entries.push(DynEntry {
+            tag: dyn_.d_tag,
+            value,
+        });
     }
-    Ok(())
+    edit(&mut entries);
+
+    let dyn_size = Dyn::size(ctx.container);
+    let slots = dynamic_phdr.p_filesz as usize / dyn_size;
+    let in_place = entries.len() < slots
+        && entries
+            .iter()
+            .filter_map(DynEntry::str)
+            .all(|value| find_string(&strtab, value).is_some());
+    if in_place {
+        let mut data = data.to_vec();
+        let mut offset = dynamic_phdr.p_offset as usize;
+        for dyn_ in serialize_entries(&entries, &strtab, None)? {
+            offset += data.pwrite_with(dyn_, offset, ctx)?;
+        }
+        // Fill the unused slots, the loader stops at the first DT_NULL
+        while offset < dynamic_phdr.p_offset as usize + slots * dyn_size {
+            offset += data.pwrite_with(Dyn { d_tag: 0, d_val: 0 }, offset, ctx)?;
+        }
+        return Ok(data);
+    }
+
+    let mut phdrs = elf.program_headers.clone();
+    let mut base_len = data.len();
+    // Replace the segment of an earlier rewrite instead of appending another one. It starts with
+    // the program headers, which in a linked file are inside the first segment at offset 0.
+    if let Some(index) = phdrs.iter().rposition(|phdr| {
+        phdr.p_type == PT_LOAD
+            && phdr.p_offset > 0
+            && phdr.p_offset == elf.header.e_phoff
+            && phdr.p_offset + phdr.p_filesz == data.len() as u64
+    }) {
+        base_len = phdrs.remove(index).p_offset as usize;
+    }
+    let loads: Vec<&ProgramHeader> = phdrs.iter().filter(|p| p.p_type == PT_LOAD).collect();
+    let first_load = loads
+        .iter()
+        .min_by_key(|phdr| phdr.p_vaddr)
+        .context("The file has no loadable segment")?;
+    // The kernel of older linux versions computes the address of the program headers of an
+    // executable from the first segment, so the new segment keeps its distance between file
+    // offset and address
+    let bias = first_load.p_vaddr.wrapping_sub(first_load.p_offset);
+    let align = loads
+        .iter()
+        .map(|phdr| phdr.p_align)
+        .max()
+        .unwrap_or_default()
+        .max(MIN_PAGE_SIZE);
+    let end = loads
+        .iter()
+        .map(|phdr| phdr.p_vaddr + phdr.p_memsz)
+        .max()
+        .unwrap_or_default();
+    let offset = align_up((base_len as u64).max(end.wrapping_sub(bias)), align);
+    let vaddr = offset.wrapping_add(bias);
+
+    let mut new_strtab = strtab.clone();
+    for value in entries.iter().filter_map(DynEntry::str) {
+        if find_string(&new_strtab, value).is_none() {
+            new_strtab.extend_from_slice(value.as_bytes());
+            new_strtab.push(0);
+        }
+    }
+    let phdr_size = ProgramHeader::size(ctx);
+    let phdrs_len = (phdrs.len() + 1) * phdr_size;
+    let strtab_offset = offset + phdrs_len as u64;
+    let dynamic_offset = align_up(strtab_offset + new_strtab.len() as u64, dyn_size as u64);
+    let dyns = serialize_entries(
+        &entries,
+        &new_strtab,
+        Some((strtab_offset.wrapping_add(bias), new_strtab.len() as u64)),
+    )?;
+    // With a spare slot, so adding an entry later can be done in place
+    let dynamic_len = ((dyns.len() + 1) * dyn_size) as u64;
+    let segment_len = dynamic_offset + dynamic_len - offset;
+
+    for phdr in &mut phdrs {
+        match phdr.p_type {
+            PT_PHDR => {
+                phdr.p_offset = offset;
+                phdr.p_vaddr = vaddr;
+                phdr.p_paddr = vaddr;
+                phdr.p_filesz = phdrs_len as u64;
+                phdr.p_memsz = phdrs_len as u64;
+            }
+            PT_DYNAMIC => {
+                phdr.p_offset = dynamic_offset;
+                phdr.p_vaddr = dynamic_offset.wrapping_add(bias);
+                phdr.p_paddr = dynamic_offset.wrapping_add(bias);
+                phdr.p_filesz = dynamic_len;
+                phdr.p_memsz = dynamic_len;
+            }
+            _ => {}
+        }
+    }
+    // The loader expects the loadable segments sorted by address
+    let last_load = phdrs
+        .iter()
+        .rposition(|phdr| phdr.p_type == PT_LOAD)
+        .unwrap();
+    phdrs.insert(
+        last_load + 1,
+        ProgramHeader {
+            p_type: PT_LOAD,
+            // The loader writes the relocated addresses into the dynamic section
+            p_flags: PF_R | PF_W,
+            p_offset: offset,
+            p_vaddr: vaddr,
+            p_paddr: vaddr,
+            p_filesz: segment_len,
+            p_memsz: segment_len,
+            p_align: align,
+        },
+    );
+
+    let mut new_data = data[..base_len].to_vec();
+    new_data.resize((offset + segment_len) as usize, 0);
+    let mut phdr_offset = offset as usize;
+    for phdr in phdrs.iter().cloned() {
+        phdr_offset += new_data.pwrite_with(phdr, phdr_offset, ctx)?;
+    }
+    new_data[strtab_offset as usize..strtab_offset as usize + new_strtab.len()]
+        .copy_from_slice(&new_strtab);
+    let mut dyn_offset = dynamic_offset as usize;
+    for dyn_ in dyns {
+        dyn_offset += new_data.pwrite_with(dyn_, dyn_offset, ctx)?;
+    }
+
+    // Keep the section headers in sync for tools like readelf and strip
+    let dynamic_section = elf
+        .section_headers
+        .iter()
+        .position(|shdr| shdr.sh_type == SHT_DYNAMIC);
+    if let Some(dynamic_section) = dynamic_section {
+        let strtab_section = elf.section_headers[dynamic_section].sh_link as usize;
+        let sections: [(usize, u64, u64); 2] = [
+            (dynamic_section, dynamic_offset, dynamic_len),
+            (strtab_section, strtab_offset, new_strtab.len() as u64),
+        ];
+        for (index, offset, size) in sections {
+            let shdr = match elf.section_headers.get(index) {
+                Some(shdr) => shdr,
+                None => continue,
+            };
+            let shdr = SectionHeader {
+                sh_offset: offset,
+                sh_addr: offset.wrapping_add(bias),
+                sh_size: size,
+                ..shdr.clone()
+            };
+            let shdr_offset = elf.header.e_shoff as usize + index * SectionHeader::size(ctx);
+            new_data.pwrite_with(shdr, shdr_offset, ctx)?;
+        }
+    }
+
+    let mut header = elf.header;
+    header.e_phoff = offset;
+    header.e_phnum = phdrs.len() as u16;
+    header.into_ctx(&mut new_data[..], ctx);
+    Ok(new_data)
 }
 
-/// Remove a `RPATH` from executables and libraries
-pub fn remove_rpath(file: impl AsRef<Path>) -> Result<()> {
-    let mut cmd = Command::new("patchelf");
-    cmd.arg("--remove-rpath").arg(file.as_ref());
-    let output = cmd
-        .output()
-        .context("Failed to execute 'patchelf', did you install it?")?;
-    if !output.status.success() {
-        bail!(
-            "patchelf --remove-rpath failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
+/// The raw entries for `entries` with the strings in `strtab`, ending with DT_NULL. For a new
+/// string table, `new_strtab` has its address and size.
+fn serialize_entries(
+    entries: &[DynEntry],
+    strtab: &[u8],
+    new_strtab: Option<(u64, u64)>,
+) -> Result<Vec<Dyn>> {
+    let mut dyns = Vec::with_capacity(entries.len() + 1);
+    for entry in entries {
+        let d_val = match (&entry.value, entry.tag, new_strtab) {
+            (DynValue::Str(value), _, _) => find_string(strtab, value)
+                .with_context(|| format!("{} is missing from the string table", value))?
+                as u64,
+            (DynValue::Val(_), DT_STRTAB, Some((address, _))) => address,
+            (DynValue::Val(_), DT_STRSZ, Some((_, size))) => size,
+            (DynValue::Val(value), _, _) => *value,
+        };
+        dyns.push(Dyn {
+            d_tag: entry.tag,
+            d_val,
+        });
     }
-    Ok(())
+    dyns.push(Dyn {
+        d_tag: DT_NULL,
+        d_val: 0,
+    });
+    Ok(dyns)
 }
 
-/// Change the `RPATH` of executables and libraries
-pub fn set_rpath<S: AsRef<OsStr>>(file: impl AsRef<Path>, rpath: &S) -> Result<()> {
-    remove_rpath(&file)?;
-    let mut cmd = Command::new("patchelf");
-    cmd.arg("--force-rpath")
-        .arg("--set-rpath")
-        .arg(rpath)
-        .arg(file.as_ref());
-    let output = cmd
-        .output()
-        .context("Failed to execute 'patchelf', did you install it?")?;
-    if !output.status.success() {
-        bail!(
-            "patchelf --set-rpath failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
+/// Replaces the entry with `tag` in place, or adds one if there is none
+fn set_string_entry(entries: &mut Vec<DynEntry>, tag: u64, value: &str) {
+    match entries.iter_mut().find(|entry| entry.tag == tag) {
+        Some(entry) => entry.value = DynValue::Str(value.to_string()),
+        None => entries.push(DynEntry::string(tag, value)),
     }
-    Ok(())
+}
+
+/// Replace a declared dependency on a dynamic library with another one (`DT_NEEDED`)
+pub fn replace_needed<O: AsRef<OsStr>, N: AsRef<OsStr>>(
+    file: impl AsRef<Path>,
+    old_new_pairs: &[(O, N)],
+) -> Result<()> {
+    modify_dynamic(file.as_ref(), |entries| {
+        for entry in entries.iter_mut().filter(|entry| entry.tag == DT_NEEDED) {
+            let new = old_new_pairs
+                .iter()
+                .find(|(old, _)| entry.str() == old.as_ref().to_str());
+            if let Some((_, new)) = new {
+                entry.value = DynValue::Str(new.as_ref().to_string_lossy().into_owned());
+            }
+        }
+    })
+}
+
+/// Change `SONAME` of a dynamic library
+pub fn set_soname<S: AsRef<OsStr>>(file: impl AsRef<Path>, soname: &S) -> Result<()> {
+    let soname = soname.as_ref().to_string_lossy();
+    modify_dynamic(file.as_ref(), |entries| {
+        set_string_entry(entries, DT_SONAME, &soname)
+    })
+}
+
+/// Change the `RPATH` of executables and libraries, replacing a `RUNPATH` like
+/// `patchelf --force-rpath`, since a `RUNPATH` doesn't apply to the dependencies of the
+/// dependencies
+pub fn set_rpath<S: AsRef<OsStr>>(file: impl AsRef<Path>, rpath: &S) -> Result<()> {
+    let rpath = rpath.as_ref().to_string_lossy();
+    modify_dynamic(file.as_ref(), |entries| {
+        entries.retain(|entry| entry.tag != DT_RUNPATH);
+        set_string_entry(entries, DT_RPATH, &rpath)
+    })
 }
 
 /// Get the `RPATH` of executables and libraries
@@ -94,3 +378,65 @@ pub fn get_rpath(file: impl AsRef<Path>) -> Result<Vec<String>> {
         Err(e) => bail!("Failed to parse ELF file at '{}': {}", file.display(), e),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_find_string() {
+        let strtab = b"\0libc.so.6\0libfoo.so\0";
+        assert_eq!(find_string(strtab, "libc.so.6"), Some(1));
+        assert_eq!(find_string(strtab, "foo.so"), Some(14));
+        assert_eq!(find_string(strtab, ""), Some(0));
+        assert_eq!(find_string(strtab, "libc.so"), None);
+    }
+
+    /// Rewrites a copy of the test executable, which links libc dynamically
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    #[test]
+    fn test_rewrite_dynamic() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = temp_dir.path().join("test");
+        fs::copy(std::env::current_exe().unwrap(), &file).unwrap();
+        let count_loads = |data: &[u8]| {
+            Elf::parse(data)
+                .unwrap()
+                .program_headers
+                .iter()
+                .filter(|phdr| phdr.p_type == PT_LOAD)
+                .count()
+        };
+        let original = fs::read(&file).unwrap();
+        let original_loads = count_loads(&original);
+        let libc = Elf::parse(&original)
+            .unwrap()
+            .libraries
+            .into_iter()
+            .find(|lib| lib.starts_with("libc."))
+            .unwrap()
+            .to_string();
+
+        set_soname(&file, &"libtest-12345678.so").unwrap();
+        replace_needed(&file, &[(&libc, "libc-12345678.so.6")]).unwrap();
+        set_rpath(&file, &"$ORIGIN/../test.libs").unwrap();
+        let data = fs::read(&file).unwrap();
+        let elf = Elf::parse(&data).unwrap();
+        assert_eq!(elf.soname, Some("libtest-12345678.so"));
+        assert!(elf.libraries.contains(&"libc-12345678.so.6"));
+        assert!(!elf.libraries.contains(&libc.as_str()));
+        assert_eq!(elf.rpaths, vec!["$ORIGIN/../test.libs"]);
+        assert!(elf.runpaths.is_empty());
+        // All the edits share one new segment
+        assert_eq!(count_loads(&data), original_loads + 1);
+
+        // Removing an entry doesn't need more space
+        let removed = rewrite_dynamic(&data, |entries| {
+            entries.retain(|entry| entry.tag != DT_RPATH)
+        })
+        .unwrap();
+        assert!(Elf::parse(&removed).unwrap().rpaths.is_empty());
+        assert_eq!(removed.len(), data.len());
+    }
+}
@@ -4,7 +4,7 @@ use std::fmt;
 use std::str::FromStr;
 
 /// Decides how to handle manylinux and musllinux compliance
-#[derive(Serialize, Debug, Clone, Eq, PartialEq, Copy, Ord, PartialOrd)]
+#[derive(Serialize, Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum PlatformTag {
     /// Use the manylinux_x_y tag
     Manylinux {
@@ -22,6 +22,8 @@ pub enum PlatformTag {
     },
     /// Use the native linux tag
     Linux,
+    /// A custom tag with a policy from `[tool.maturin.audit] extra-policy`
+    Custom(String),
 }
 
 impl PlatformTag {
@@ -50,6 +52,8 @@ impl PlatformTag {
                     Vec::new()
                 }
             }
+            // Extra policies can't have aliases
+            PlatformTag::Custom(_) => Vec::new(),
             PlatformTag::Musllinux { .. } => Vec::new(),
             PlatformTag::Linux => Vec::new(),
         }
@@ -57,11 +61,16 @@ impl PlatformTag {
 
     /// Is this a portable linux platform tag
     ///
-    /// Only manylinux and musllinux are portable
+    /// Only manylinux, musllinux and custom tags, which are audited with their policy, are portable
     pub fn is_portable(&self) -> bool {
         !matches!(self, PlatformTag::Linux)
     }
 
+    /// Is this a custom platform tag
+    pub fn is_custom(&self) -> bool {
+        matches!(self, PlatformTag::Custom(_))
+    }
+
     /// Is this a manylinux platform tag
     pub fn is_manylinux(&self) -> bool {
         matches!(self, PlatformTag::Manylinux { .. })
@@ -78,16 +87,18 @@ impl PlatformTag {
             PlatformTag::Manylinux { x, y } => (*x, *y) >= (2, 17),
             PlatformTag::Musllinux { .. } => true,
             PlatformTag::Linux => true,
+            PlatformTag::Custom(_) => true,
         }
     }
 }
 
 impl fmt::Display for PlatformTag {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
+        match self {
             PlatformTag::Manylinux { x, y } => write!(f, "manylinux_{}_{}", x, y),
             PlatformTag::Musllinux { x, y } => write!(f, "musllinux_{}_{}", x, y),
             PlatformTag::Linux => write!(f, "linux"),
+            PlatformTag::Custom(name) => write!(f, "{}", name),
         }
     }
 }
@@ -114,6 +125,15 @@ impl FromStr for PlatformTag {
                         .and_then(|y| y.parse::<u16>().ok())
                         .ok_or("invalid musllinux option")?;
                     Ok(PlatformTag::Musllinux { x, y })
+                } else if !value.starts_with("manylinux")
+                    && value.starts_with(|c: char| c.is_ascii_lowercase())
+                    && value
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+                {
+                    // The policies of custom tags are only known once pyproject.toml is read,
+                    // so the build options check that there is one
+                    Ok(PlatformTag::Custom(value.clone()))
                 } else {
                     let value = value.strip_prefix("manylinux_").unwrap_or(&value);
                     let mut parts = value.split('_');
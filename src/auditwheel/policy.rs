@@ -1,31 +1,46 @@
 use crate::auditwheel::PlatformTag;
+use crate::data::{read_refreshed, Dataset};
 use crate::target::Arch;
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
 use once_cell::sync::Lazy;
 use serde::Deserialize;
 use std::cmp::{Ordering, PartialOrd};
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::path::Path;
 
 /// The policies (allowed symbols) for the different manylinux tags, sorted from highest
 /// priority to lowest
 pub static MANYLINUX_POLICIES: Lazy<Vec<Policy>> = Lazy::new(|| {
     // https://github.com/pypa/auditwheel/blob/master/auditwheel/policy/manylinux-policy.json
-    let mut policies: Vec<Policy> = serde_json::from_slice(include_bytes!("manylinux-policy.json"))
+    let policies: Vec<Policy> = serde_json::from_slice(include_bytes!("manylinux-policy.json"))
         .expect("invalid manylinux policy.json file");
-    policies.sort_by_key(|policy| -policy.priority);
-    policies
+    with_refreshed_policies(policies, Dataset::ManylinuxPolicy)
 });
 
 /// The policies (allowed symbols) for the different musllinux tags, sorted from highest
 /// priority to lowest
 pub static MUSLLINUX_POLICIES: Lazy<Vec<Policy>> = Lazy::new(|| {
     // https://github.com/pypa/auditwheel/blob/master/auditwheel/policy/musllinux-policy.json
-    let mut policies: Vec<Policy> = serde_json::from_slice(include_bytes!("musllinux-policy.json"))
+    let policies: Vec<Policy> = serde_json::from_slice(include_bytes!("musllinux-policy.json"))
         .expect("invalid musllinux policy.json file");
+    with_refreshed_policies(policies, Dataset::MusllinuxPolicy)
+});
+
+/// Replaces the built-in policies with the ones from `maturin self update-data`, keeping built-in
+/// policies the refreshed data doesn't know yet, and sorts them by priority
+fn with_refreshed_policies(mut policies: Vec<Policy>, dataset: Dataset) -> Vec<Policy> {
+    if let Some(refreshed) = read_refreshed(dataset) {
+        let refreshed: Vec<Policy> =
+            serde_json::from_str(&refreshed).expect("refreshed policies were validated");
+        policies.retain(|policy| !refreshed.iter().any(|x| x.name == policy.name));
+        policies.extend(refreshed);
+    }
     policies.sort_by_key(|policy| -policy.priority);
     policies
-});
+}
 
 /// Manylinux policy
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
@@ -33,16 +48,22 @@ pub struct Policy {
     /// platform tag name
     pub name: String,
     /// platform tag aliases
+    #[serde(default)]
     pub aliases: Vec<String>,
     /// policy priority. Tags supporting more platforms have higher priority
+    #[serde(default)]
     pub priority: i64,
+    /// The built-in policy this one extends, only for extra policies
+    #[serde(default)]
+    pub base: Option<String>,
     /// platform architecture to symbol versions map
-    #[serde(rename = "symbol_versions")]
+    #[serde(rename = "symbol_versions", default)]
     pub symbol_versions: HashMap<String, HashMap<String, HashSet<String>>>,
     /// whitelisted libraries
-    #[serde(rename = "lib_whitelist")]
+    #[serde(rename = "lib_whitelist", default)]
     pub lib_whitelist: HashSet<String>,
     /// blacklisted symbols of whitelisted libraries
+    #[serde(default)]
     pub blacklist: HashMap<String, HashSet<String>>,
 }
 
@@ -92,9 +113,115 @@ impl Policy {
             .cloned()
     }
 
+    /// Get the policy of a platform tag, looking up custom tags in `extra_policies`
+    pub fn from_platform_tag(
+        platform_tag: &PlatformTag,
+        extra_policies: &[Policy],
+    ) -> Option<Self> {
+        match platform_tag {
+            PlatformTag::Custom(name) => extra_policies
+                .iter()
+                .find(|policy| &policy.name == name)
+                .cloned(),
+            _ => Policy::from_name(&platform_tag.to_string()),
+        }
+    }
+
+    /// Reads the policies of custom platform tags from a json file in the format of auditwheel's
+    /// policy files. A policy with a `base` extends that built-in policy with its symbol versions,
+    /// libraries and blacklisted symbols.
+    pub fn load_extra_policies(path: &Path) -> Result<Vec<Policy>> {
+        let policies: Vec<Policy> = serde_json::from_str(&fs::read_to_string(path)?)
+            .with_context(|| format!("Invalid policy file {}", path.display()))?;
+        let mut extra_policies: Vec<Policy> = Vec::new();
+        for policy in policies {
+            let policy = policy
+                .resolve_base()
+                .with_context(|| format!("Invalid policy file {}", path.display()))?;
+            if extra_policies
+                .iter()
+                .any(|existing| existing.name == policy.name)
+            {
+                bail!(
+                    "Invalid policy file {}: the policy `{}` is defined twice",
+                    path.display(),
+                    policy.name
+                );
+            }
+            extra_policies.push(policy);
+        }
+        Ok(extra_policies)
+    }
+
+    /// Validates an extra policy and merges it with its base policy
+    fn resolve_base(self) -> Result<Self> {
+        if !self
+            .name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            || !self.name.starts_with(|c: char| c.is_ascii_lowercase())
+        {
+            bail!(
+                "The policy name `{}` may only contain lowercase ASCII letters, digits and `_` \
+                and must start with a letter",
+                self.name
+            );
+        }
+        if MANYLINUX_POLICIES
+            .iter()
+            .chain(MUSLLINUX_POLICIES.iter())
+            .any(|builtin| builtin.name == self.name || builtin.aliases.contains(&self.name))
+            || self.name.starts_with("manylinux")
+            || self.name.starts_with("musllinux")
+        {
+            bail!(
+                "The policy `{}` would shadow a manylinux or musllinux policy",
+                self.name
+            );
+        }
+        if !self.aliases.is_empty() {
+            bail!(
+                "The policy `{}` has aliases, which custom platform tags don't support",
+                self.name
+            );
+        }
+        let base_name = match &self.base {
+            Some(base_name) => base_name,
+            None => return Ok(self),
+        };
+        let mut policy = MANYLINUX_POLICIES
+            .iter()
+            .chain(MUSLLINUX_POLICIES.iter())
+            .find(|p| &p.name == base_name || p.aliases.contains(base_name))
+            .cloned()
+            .with_context(|| {
+                format!(
+                    "The base `{}` of the policy `{}` is not a manylinux or musllinux policy",
+                    base_name, self.name
+                )
+            })?;
+        policy.name = self.name;
+        policy.base = self.base;
+        policy.aliases = Vec::new();
+        policy.priority = self.priority;
+        for (arch, symbol_versions) in self.symbol_versions {
+            let arch_versions = policy.symbol_versions.entry(arch).or_default();
+            for (library, versions) in symbol_versions {
+                arch_versions.entry(library).or_default().extend(versions);
+            }
+        }
+        policy.lib_whitelist.extend(self.lib_whitelist);
+        for (library, symbols) in self.blacklist {
+            policy.blacklist.entry(library).or_default().extend(symbols);
+        }
+        Ok(policy)
+    }
+
     pub(crate) fn fixup_musl_libc_so_name(&mut self, target_arch: Arch) {
         // Fixup musl libc lib_whitelist
-        if self.name.starts_with("musllinux") && self.lib_whitelist.remove("libc.so") {
+        let musllinux = self.name.starts_with("musllinux")
+            || matches!(&self.base, Some(base) if base.starts_with("musllinux"));
+        if musllinux && self.lib_whitelist.remove("libc.so") {
             let new_soname = match target_arch {
                 Arch::Aarch64 => "libc.musl-aarch64.so.1",
                 Arch::Armv6L => "libc.musl-armhf.so.1",
@@ -115,7 +242,7 @@ impl Policy {
 
 #[cfg(test)]
 mod test {
-    use super::{Arch, Policy, MANYLINUX_POLICIES, MUSLLINUX_POLICIES};
+    use super::{Arch, PlatformTag, Policy, MANYLINUX_POLICIES, MUSLLINUX_POLICIES};
     use pretty_assertions::assert_eq;
 
     #[test]
@@ -148,6 +275,46 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_load_extra_policies() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let policy_file = tmp_dir.path().join("policy.json");
+        std::fs::write(
+            &policy_file,
+            r#"[{
+                "name": "acme_amazonlinux2023",
+                "priority": 50,
+                "base": "manylinux_2_28",
+                "symbol_versions": {"x86_64": {"GLIBC": ["2.34"]}},
+                "lib_whitelist": ["libssl.so.3"]
+            }]"#,
+        )
+        .unwrap();
+        let extra_policies = Policy::load_extra_policies(&policy_file).unwrap();
+        assert!(Policy::from_name("acme_amazonlinux2023").is_none());
+
+        let tag: PlatformTag = "acme_amazonlinux2023".parse().unwrap();
+        let policy = Policy::from_platform_tag(&tag, &extra_policies).unwrap();
+        let base = Policy::from_name("manylinux_2_28").unwrap();
+        assert_eq!(policy.priority, 50);
+        assert!(policy.aliases.is_empty());
+        assert!(policy.lib_whitelist.contains("libssl.so.3"));
+        assert!(policy.lib_whitelist.is_superset(&base.lib_whitelist));
+        let glibc = &policy.symbol_versions["x86_64"]["GLIBC"];
+        assert!(glibc.contains("2.34"));
+        assert!(glibc.contains("2.28"));
+        assert_eq!(policy.platform_tag(), tag);
+        let typo: PlatformTag = "acme_amazonlinux2032".parse().unwrap();
+        assert!(Policy::from_platform_tag(&typo, &extra_policies).is_none());
+
+        std::fs::write(&policy_file, r#"[{"name": "manylinux_2_99"}]"#).unwrap();
+        assert!(Policy::load_extra_policies(&policy_file).is_err());
+        std::fs::write(&policy_file, r#"[{"name": "acme", "base": "centos"}]"#).unwrap();
+        assert!(Policy::load_extra_policies(&policy_file).is_err());
+        std::fs::write(&policy_file, r#"[{"name": "acme"}, {"name": "acme"}]"#).unwrap();
+        assert!(Policy::load_extra_policies(&policy_file).is_err());
+    }
+
     #[test]
     fn test_policy_musllinux_fixup_libc_so_name() {
         let mut policy = Policy::from_name("musllinux_1_1").unwrap();
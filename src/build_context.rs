@@ -1,17 +1,31 @@
-use crate::auditwheel::{get_policy_and_libs, patchelf, relpath};
-use crate::auditwheel::{PlatformTag, Policy};
-use crate::build_options::CargoOptions;
-use crate::compile::warn_missing_py_init;
+use crate::artifact_cache;
+use crate::auditwheel::{audit_abi3, AuditWheelError, PlatformTag, Policy};
+use crate::auditwheel::{delocate, get_policy_and_libs, patchelf, relpath};
+use crate::build_options::{cargo_profile, CargoOptions, InterpreterSearch};
+use crate::classifiers::check_classifiers;
+use crate::compile::{
+    build_plan, macos_minimum_versions, mingw_runtime_dependencies, warn_missing_py_init,
+};
+use crate::dynamic_metadata::run_dynamic_hooks;
+use crate::hooks::{run_hooks, HookStage};
+use crate::installer::write_installers;
 use crate::module_writer::{
-    add_data, write_bin, write_bindings_module, write_cffi_module, write_python_part,
-    write_wasm_launcher, WheelWriter,
+    add_data, wheel_file_name, write_bin, write_bindings_module, write_cffi_module,
+    write_python_part, write_wasm_launcher, ScriptWrappers, WheelWriter,
 };
 use crate::project_layout::ProjectLayout;
 use crate::python_interpreter::InterpreterKind;
 use crate::source_distribution::source_distribution;
+use crate::stats;
+use crate::tags::{self, Compatibility, WheelAbi};
+use crate::target::Arch;
+use crate::targets::{PlannedInterpreter, PlannedWheel};
+use crate::wheel_manifest::{self, ManifestEntry, WheelManifest};
+use crate::zipapp::write_zipapp;
 use crate::{
-    compile, pyproject_toml::Format, BuildArtifact, Metadata21, ModuleWriter, PyProjectToml,
-    PythonInterpreter, Target,
+    compile,
+    pyproject_toml::{CargoLockInclusion, Format, LtoConfig},
+    BuildArtifact, Metadata21, ModuleWriter, PyProjectToml, PythonInterpreter, Target,
 };
 use anyhow::{anyhow, bail, Context, Result};
 use cargo_metadata::Metadata;
@@ -19,12 +33,15 @@ use fs_err as fs;
 use ignore::overrides::{Override, OverrideBuilder};
 use lddtree::Library;
 use normpath::PathExt;
+use once_cell::sync::OnceCell;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::io;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 /// The way the rust code is used in the wheel
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -133,6 +150,14 @@ fn bin_wasi_helper(
     Ok(metadata21)
 }
 
+/// The directory for intermediate files, inside the cargo target directory so that nothing
+/// is written into the source tree, which may be read-only
+pub(crate) fn scratch_dir(target_dir: &Path) -> Result<PathBuf> {
+    let scratch_dir = target_dir.join("maturin");
+    fs::create_dir_all(&scratch_dir)?;
+    Ok(scratch_dir)
+}
+
 /// Contains all the metadata required to build the crate
 #[derive(Clone)]
 pub struct BuildContext {
@@ -167,20 +192,64 @@ pub struct BuildContext {
     pub strip: bool,
     /// Skip checking the linked libraries for manylinux/musllinux compliance
     pub skip_auditwheel: bool,
+    /// Tag the wheel as linux instead of failing when it violates the requested policy
+    pub fallback_to_linux: bool,
+    /// Bundle the external shared libraries also into wheels with the linux tag and on macOS
+    pub repair: bool,
     /// When compiling for manylinux, use zig as linker to ensure glibc version compliance
     pub zig: bool,
+    /// Run the cargo build in a container of cross for the target
+    pub use_container: bool,
     /// Whether to use the the manylinux/musllinux or use the native linux tag (off)
     pub platform_tag: Vec<PlatformTag>,
-    /// The available python interpreter
-    pub interpreter: Vec<PythonInterpreter>,
+    /// The policies of custom platform tags from `[tool.maturin.audit] extra-policy`
+    pub extra_policies: Vec<Policy>,
+    /// The available python interpreters, found on first use by [BuildContext::interpreters]
+    pub(crate) interpreter: OnceCell<Vec<PythonInterpreter>>,
+    /// How to find the python interpreters
+    pub(crate) interpreter_search: InterpreterSearch,
     /// Cargo.toml as resolved by [cargo_metadata]
     pub cargo_metadata: Metadata,
     /// Whether to use universal2 or use the native macOS tag (off)
     pub universal2: bool,
     /// Build editable wheels
     pub editable: bool,
+    /// Optional build number of the wheel, see PEP 427
+    pub build_tag: Option<String>,
     /// Cargo build options
     pub cargo_options: CargoOptions,
+    /// Record the duration, cargo cache hits and wheel sizes for `maturin stats`
+    pub record_stats: bool,
+    /// Reuse the library from `target/maturin-cache` when the sources and options didn't change
+    pub artifact_cache: bool,
+    /// Additional flags for `RUSTFLAGS` from pyproject.toml
+    pub rustflags: Vec<String>,
+    /// The linker from `[tool.maturin.target.<triple>]`
+    pub linker: Option<String>,
+    /// Additional linker arguments from `[tool.maturin.target.<triple>]`
+    pub link_args: Vec<String>,
+    /// `[tool.maturin.lto]`, already added to the rustflags, linker, linker arguments and
+    /// environment variables
+    pub lto: LtoConfig,
+    /// Environment variables from `[tool.maturin.env]` for cargo and the python subprocesses
+    pub env: BTreeMap<String, String>,
+    /// The requirements from `[tool.maturin.target.<..>.metadata]` that were added to
+    /// `metadata21`, they only apply to the wheels
+    pub target_requires_dist: Vec<String>,
+    /// Include the dependencies from `cargo vendor` in the source distribution
+    pub vendor: bool,
+    /// Only include files committed to git in the source distribution
+    pub sdist_from_git: bool,
+    /// Whether the Cargo.lock is packed into the source distribution
+    pub include_cargo_lock: CargoLockInclusion,
+    /// Reuse the wheels in the output directory that an earlier build wrote from the same
+    /// sources, instead of building them again
+    pub skip_existing: bool,
+    /// The hash of the sources for the wheel manifest, computed on first use
+    pub(crate) source_hash: OnceCell<String>,
+    /// The builds of this target that `--shard` assigned to this job, see
+    /// [BuildContext::build_names]. All of them if `None`.
+    pub selected_builds: Option<HashSet<String>>,
 }
 
 /// The wheel file location and its Python version tag (e.g. `py3`).
@@ -189,7 +258,298 @@ pub struct BuildContext {
 /// they bind against (e.g. `cp37`).
 pub type BuiltWheelMetadata = (PathBuf, String);
 
+/// The name of the build of a cffi wheel
+const CFFI_BUILD_NAME: &str = "cffi";
+
+/// The name of the build of a wheel for the interpreter, e.g. `CPython 3.10`, or
+/// `CPython 3.10 (mingw_x86_64_ucrt)` for a MinGW python of the same version
+fn interpreter_build_name(python_interpreter: &PythonInterpreter) -> String {
+    let name = format!(
+        "{} {}.{}{}",
+        python_interpreter.interpreter_kind,
+        python_interpreter.major,
+        python_interpreter.minor,
+        python_interpreter.abiflags,
+    );
+    match python_interpreter.mingw_platform() {
+        Some(mingw_platform) => format!("{} ({})", name, mingw_platform),
+        None => name,
+    }
+}
+
+/// The name of the build of an abi3 wheel
+fn abi3_build_name(major: u8, min_minor: u8) -> String {
+    format!("abi3 Python ≥ {}.{}", major, min_minor)
+}
+
+/// The name of the build of a wheel with a binary, for an interpreter or not
+fn bin_build_name(python_interpreter: Option<&PythonInterpreter>) -> String {
+    match python_interpreter {
+        Some(python_interpreter) => format!("bin {}", interpreter_build_name(python_interpreter)),
+        None => "bin".to_string(),
+    }
+}
+
+/// The wheels built for each target in one run, written as json with `maturin build --report`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildReport {
+    /// The wheels of each target, in the order they were built
+    pub targets: Vec<TargetReport>,
+    /// The source distribution, if one was built
+    pub sdist: Option<PathBuf>,
+}
+
+/// The wheels built for one target
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetReport {
+    /// The target triple
+    pub target: String,
+    /// The built wheels
+    pub wheels: Vec<WheelReport>,
+}
+
+/// A built wheel
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WheelReport {
+    /// The location of the wheel
+    pub path: PathBuf,
+    /// The python version tag, e.g. `cp37` or `py3`
+    pub tag: String,
+}
+
+impl BuildReport {
+    /// Adds the wheels built for a target
+    pub fn add_target(&mut self, target: &Target, wheels: &[BuiltWheelMetadata]) {
+        self.targets.push(TargetReport {
+            target: target.target_triple().to_string(),
+            wheels: wheels
+                .iter()
+                .map(|(path, tag)| WheelReport {
+                    path: path.clone(),
+                    tag: tag.clone(),
+                })
+                .collect(),
+        });
+    }
+
+    /// Formats the wheels of all targets, one target per line
+    pub fn summary(&self) -> String {
+        let wheels: usize = self.targets.iter().map(|x| x.wheels.len()).sum();
+        let mut summary = format!(
+            "📦 Built {} wheel(s) for {} target(s):\n",
+            wheels,
+            self.targets.len()
+        );
+        for target in &self.targets {
+            let names: Vec<_> = target
+                .wheels
+                .iter()
+                .filter_map(|wheel| wheel.path.file_name())
+                .map(|name| name.to_string_lossy())
+                .collect();
+            summary.push_str(&format!("  - {}: {}\n", target.target, names.join(", ")));
+        }
+        summary
+    }
+
+    /// Writes the report as json
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize the report")?;
+        fs::write(path, json)
+            .with_context(|| format!("Failed to write the build report to {}", path.display()))
+    }
+}
+
 impl BuildContext {
+    /// The python interpreters to build for. They are searched for on the first call, so that
+    /// e.g. building a source distribution works without python
+    pub fn interpreters(&self) -> Result<&[PythonInterpreter]> {
+        let interpreters = self.interpreter.get_or_try_init(|| {
+            self.interpreter_search
+                .find_interpreters(&self.bridge, &self.target)
+        })?;
+        Ok(interpreters)
+    }
+
+    /// Fails if a classifier is unknown to PyPI, for builds of distributions meant to be
+    /// uploaded. The other commands only warn.
+    pub fn check_classifiers(&self) -> Result<()> {
+        check_classifiers(&self.metadata21.classifiers)
+    }
+
+    /// Computes the fields of `[tool.maturin.dynamic]` with their hooks, the callables run with
+    /// the first python interpreter of the build
+    pub(crate) fn run_dynamic_hooks(&mut self) -> Result<()> {
+        let hooks = match self.pyproject_toml.as_ref().and_then(|x| x.dynamic_hooks()) {
+            Some(hooks) => hooks,
+            None => return Ok(()),
+        };
+        let python = if hooks.values().any(|hook| hook.callable.is_some()) {
+            self.interpreters()?
+                .iter()
+                .find(|interpreter| interpreter.runnable)
+                .map(|interpreter| interpreter.executable.clone())
+        } else {
+            None
+        };
+        if let Some(pyproject) = &self.pyproject_toml {
+            run_dynamic_hooks(
+                &mut self.metadata21,
+                self.pyproject_toml_path.parent().unwrap(),
+                pyproject,
+                python.as_deref(),
+                &self.env,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The hash of the sources and options, computed on first use
+    fn source_hash(&self) -> Result<&str> {
+        let source_hash = self
+            .source_hash
+            .get_or_try_init(|| wheel_manifest::source_hash(self))?;
+        Ok(source_hash)
+    }
+
+    /// The builds of this target, one per wheel, with their name, e.g. `CPython 3.10`, and the
+    /// interpreter they are built with
+    fn builds(&self) -> Result<Vec<(String, Option<&PythonInterpreter>)>> {
+        Ok(match &self.bridge {
+            BridgeModel::Cffi => vec![(CFFI_BUILD_NAME.to_string(), None)],
+            BridgeModel::Bin(None) => vec![(bin_build_name(None), None)],
+            BridgeModel::Bin(Some(..)) => self
+                .interpreters()?
+                .iter()
+                .map(|interp| (bin_build_name(Some(interp)), Some(interp)))
+                .collect(),
+            BridgeModel::Bindings(..) => self
+                .interpreters()?
+                .iter()
+                .map(|interp| (interpreter_build_name(interp), Some(interp)))
+                .collect(),
+            BridgeModel::BindingsAbi3(major, minor) => {
+                let interpreters = self.interpreters()?;
+                let mut builds = Vec::new();
+                // Like in `build_wheels`, one build for all abi3 capable interpreters
+                if let Some(interp) = interpreters.iter().find(|x| x.has_stable_api()) {
+                    builds.push((abi3_build_name(*major, *minor), Some(interp)));
+                }
+                builds.extend(
+                    interpreters
+                        .iter()
+                        .filter(|interp| !interp.has_stable_api())
+                        .map(|interp| (interpreter_build_name(interp), Some(interp))),
+                );
+                builds
+            }
+        })
+    }
+
+    /// The cargo profile the libraries are built with, e.g. `release` or `dev`
+    pub fn cargo_profile(&self) -> &str {
+        cargo_profile(&self.cargo_options, self.release)
+    }
+
+    /// The names of the builds of this target, one per wheel, e.g. `CPython 3.10`
+    pub fn build_names(&self) -> Result<Vec<String>> {
+        Ok(self.builds()?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    /// The wheels [BuildContext::build_wheels] would build, without compiling anything, for
+    /// `maturin targets`. On linux, auditwheel picks the platform tag from the built library
+    /// unless it was passed explicitly, so the wheels show the linux tag in that case.
+    pub fn planned_wheels(&self) -> Result<Vec<PlannedWheel>> {
+        let platform_tags = self.platform_tags(&Policy::default());
+        let mut wheels = Vec::new();
+        for (build, python_interpreter) in self.builds()? {
+            let abi = match (&self.bridge, python_interpreter) {
+                (BridgeModel::Cffi | BridgeModel::Bin(None), _) => WheelAbi::None,
+                (BridgeModel::BindingsAbi3(major, minor), interpreter) => WheelAbi::Abi3 {
+                    major: *major,
+                    minor: *minor,
+                    interpreter,
+                },
+                (_, Some(interp)) => WheelAbi::Interpreter(interp),
+                (_, None) => unreachable!(),
+            };
+            let tag =
+                tags::compute(abi, &self.target, &self.compatibility(&platform_tags))?.to_string();
+            let file_name = wheel_file_name(&self.metadata21, self.build_tag.as_deref(), &tag);
+            let platform_tag_from_auditwheel = self.platform_tag.is_empty()
+                && self.target.is_linux()
+                && !self.skip_auditwheel
+                && python_interpreter.map_or(true, |interp| interp.support_portable_wheels());
+            wheels.push(PlannedWheel {
+                build,
+                interpreter: python_interpreter.map(|interp| PlannedInterpreter {
+                    kind: interp.interpreter_kind.to_string(),
+                    version: format!("{}.{}", interp.major, interp.minor),
+                    executable: Some(interp.executable.clone())
+                        .filter(|executable| !executable.as_os_str().is_empty()),
+                }),
+                tag,
+                path: self.out.join(&file_name),
+                file_name,
+                platform_tag_from_auditwheel,
+            });
+        }
+        Ok(wheels)
+    }
+
+    /// Builds a wheel with `build`. With `--skip-existing`, the wheel an earlier build recorded in
+    /// the manifest of the output directory from the same version and sources is reused instead,
+    /// and new wheels are recorded. Only then the sources are hashed, which is costly for large
+    /// workspaces. `name` tells the builds for one target apart, e.g. by the interpreter.
+    /// Returns `None` if `--shard` assigned the build to another job.
+    fn build_or_reuse(
+        &self,
+        name: &str,
+        build: impl FnOnce() -> Result<BuiltWheelMetadata>,
+    ) -> Result<Option<BuiltWheelMetadata>> {
+        if let Some(selected_builds) = &self.selected_builds {
+            if !selected_builds.contains(name) {
+                return Ok(None);
+            }
+        }
+        if !self.skip_existing {
+            return build().map(Some);
+        }
+        let build_name = format!("{} {}", self.target.target_triple(), name);
+        let source_hash = self.source_hash()?;
+        let manifest = WheelManifest::load(&self.out);
+        if let Some((wheel_path, tag)) = manifest.find(
+            &self.out,
+            &build_name,
+            &self.metadata21.version,
+            source_hash,
+        ) {
+            println!(
+                "🔁 Skipping the build for {}, {} is up-to-date",
+                name,
+                wheel_path.display()
+            );
+            return Ok(Some((wheel_path, tag)));
+        }
+        let (wheel_path, tag) = build()?;
+        let wheel = wheel_path
+            .file_name()
+            .context("Invalid wheel path")?
+            .to_string_lossy()
+            .to_string();
+        WheelManifest::record(
+            &self.out,
+            ManifestEntry {
+                build: build_name,
+                version: self.metadata21.version.clone(),
+                source_hash: source_hash.to_string(),
+                wheel,
+                tag: tag.clone(),
+            },
+        )?;
+        Ok(Some((wheel_path, tag)))
+    }
+
     /// Checks which kind of bindings we have (pyo3/rust-cypthon or cffi or bin) and calls the
     /// correct builder.
     pub fn build_wheels(&self) -> Result<Vec<BuiltWheelMetadata>> {
@@ -197,21 +557,26 @@ impl BuildContext {
 
         fs::create_dir_all(&self.out)
             .context("Failed to create the target directory for the wheels")?;
+        if self.editable {
+            self.check_editable_writable()?;
+        }
 
+        let start = Instant::now();
+        stats::reset_cargo_artifacts();
         let wheels = match &self.bridge {
             BridgeModel::Cffi => self.build_cffi_wheel()?,
             BridgeModel::Bin(None) => self.build_bin_wheel(None)?,
-            BridgeModel::Bin(Some(..)) => self.build_bin_wheels(&self.interpreter)?,
-            BridgeModel::Bindings(..) => self.build_binding_wheels(&self.interpreter)?,
+            BridgeModel::Bin(Some(..)) => self.build_bin_wheels(self.interpreters()?)?,
+            BridgeModel::Bindings(..) => self.build_binding_wheels(self.interpreters()?)?,
             BridgeModel::BindingsAbi3(major, minor) => {
                 let abi3_interps: Vec<_> = self
-                    .interpreter
+                    .interpreters()?
                     .iter()
                     .filter(|interp| interp.has_stable_api())
                     .cloned()
                     .collect();
                 let non_abi3_interps: Vec<_> = self
-                    .interpreter
+                    .interpreters()?
                     .iter()
                     .filter(|interp| !interp.has_stable_api())
                     .cloned()
@@ -230,6 +595,7 @@ impl BuildContext {
                         .map(|interp| match interp.interpreter_kind {
                             InterpreterKind::CPython => interp.implmentation_name.to_string(),
                             InterpreterKind::PyPy => "PyPy".to_string(),
+                            InterpreterKind::GraalPy => "GraalPy".to_string(),
                         })
                         .collect();
                     eprintln!(
@@ -242,6 +608,23 @@ impl BuildContext {
             }
         };
 
+        if self.cargo_options.verbose > 0 {
+            for (wheel_path, _) in &wheels {
+                print_wheel_size_breakdown(wheel_path)?;
+            }
+        }
+
+        if self.record_stats {
+            if let Err(err) = stats::record_build(self, start.elapsed(), &wheels) {
+                eprintln!(
+                    "⚠️  Warning: Failed to record the build statistics: {:#}",
+                    err
+                );
+            }
+        }
+
+        let wheel_paths: Vec<_> = wheels.iter().map(|(path, _)| path.clone()).collect();
+        self.run_post_build_hooks("wheel", &wheel_paths)?;
         Ok(wheels)
     }
 
@@ -255,12 +638,36 @@ impl BuildContext {
                 let sdist_path =
                     source_distribution(self, pyproject, self.excludes(Format::Sdist)?)
                         .context("Failed to build source distribution")?;
+                self.run_post_build_hooks("sdist", std::slice::from_ref(&sdist_path))?;
                 Ok(Some((sdist_path, "source".to_string())))
             }
             None => Ok(None),
         }
     }
 
+    /// Runs the post-build hooks from `[tool.maturin.hooks]`, the pre-build hooks run before
+    /// the project is resolved, see [crate::BuildOptions::run_pre_build_hooks]
+    fn run_post_build_hooks(&self, format: &str, artifacts: &[PathBuf]) -> Result<()> {
+        let commands = match self
+            .pyproject_toml
+            .as_ref()
+            .and_then(|x| x.hooks())
+            .and_then(|hooks| hooks.post_build.as_deref())
+        {
+            Some(commands) => commands,
+            None => return Ok(()),
+        };
+        run_hooks(
+            HookStage::PostBuild,
+            commands,
+            self.pyproject_toml_path.parent().unwrap(),
+            format,
+            self.target.target_triple(),
+            artifacts,
+            &self.env,
+        )
+    }
+
     fn auditwheel(
         &self,
         artifact: &BuildArtifact,
@@ -287,26 +694,133 @@ impl BuildContext {
         let mut musllinux: Vec<_> = platform_tag
             .iter()
             .filter(|tag| tag.is_musllinux())
-            .copied()
             .collect();
         musllinux.sort();
         let mut others: Vec<_> = platform_tag
             .iter()
             .filter(|tag| !tag.is_musllinux())
-            .copied()
             .collect();
         others.sort();
 
         if self.bridge.is_bin() && !musllinux.is_empty() {
-            return get_policy_and_libs(artifact, Some(musllinux[0]), &self.target);
+            return get_policy_and_libs(
+                artifact,
+                Some(musllinux[0]),
+                &self.target,
+                &self.extra_policies,
+                self.repair,
+            );
         }
 
         let tag = others.get(0).or_else(|| musllinux.get(0)).copied();
-        get_policy_and_libs(artifact, tag, &self.target)
+        let result = get_policy_and_libs(
+            artifact,
+            tag,
+            &self.target,
+            &self.extra_policies,
+            self.repair,
+        );
+        if self.fallback_to_linux {
+            if let Some(violation) = result.as_ref().err().and_then(|err| {
+                err.downcast_ref::<AuditWheelError>()
+                    .filter(|err| err.is_policy_violation())
+            }) {
+                eprintln!(
+                    "⚠️  Warning: {}. Falling back to the linux tag, \
+                    you won't be able to upload those wheels to PyPI.",
+                    violation
+                );
+                return Ok((Policy::default(), Vec::new()));
+            }
+        }
+        result
+    }
+
+    /// The platform tags of a wheel whose libraries satisfy `policy`: the requested tags, or
+    /// the tag of the policy if none were requested or the wheel fell back to the linux tag
+    fn platform_tags(&self, policy: &Policy) -> Vec<PlatformTag> {
+        let fell_back = self.fallback_to_linux
+            && !self.skip_auditwheel
+            && self.target.is_linux()
+            && policy.platform_tag() == PlatformTag::Linux;
+        if self.platform_tag.is_empty() || fell_back {
+            vec![policy.platform_tag()]
+        } else {
+            self.platform_tag.clone()
+        }
+    }
+
+    /// Describes what [BuildContext::build_wheels] would do without compiling anything, for
+    /// `maturin build --plan`: the enabled features and for each interpreter the cargo invocation,
+    /// its environment, the artifact path and the crates that were never built
+    pub fn build_plan(&self) -> Result<String> {
+        let mut plan = format!("📋 Build plan for {}\n", self.target.target_triple());
+        let root_features = self.cargo_metadata.resolve.as_ref().and_then(|resolve| {
+            let root = self.cargo_metadata.root_package()?;
+            resolve.nodes.iter().find(|node| node.id == root.id)
+        });
+        if let Some(root) = root_features {
+            let features = if root.features.is_empty() {
+                "none".to_string()
+            } else {
+                root.features.join(", ")
+            };
+            plan.push_str(&format!("Features of {}: {}\n", self.crate_name, features));
+        }
+        let interpreters: Vec<(String, Option<&PythonInterpreter>)> = match &self.bridge {
+            BridgeModel::Cffi | BridgeModel::Bin(None) => vec![(String::new(), None)],
+            BridgeModel::Bin(Some(..)) | BridgeModel::Bindings(..) => self
+                .interpreters()?
+                .iter()
+                .map(|interp| (format!(" for {}", interp), Some(interp)))
+                .collect(),
+            BridgeModel::BindingsAbi3(major, minor) => {
+                let mut interpreters = Vec::new();
+                // Like in `build_wheels`, one build for all abi3 capable interpreters
+                if let Some(interp) = self.interpreters()?.iter().find(|x| x.has_stable_api()) {
+                    interpreters.push((format!(" for abi3 (cp{}{})", major, minor), Some(interp)));
+                }
+                for interp in self.interpreters()?.iter().filter(|x| !x.has_stable_api()) {
+                    interpreters.push((format!(" for {}", interp), Some(interp)));
+                }
+                interpreters
+            }
+        };
+        for (description, python_interpreter) in interpreters {
+            plan.push_str(&format!("Build{}:\n", description));
+            plan.push_str(&build_plan(self, python_interpreter, &self.bridge)?);
+        }
+        Ok(plan)
+    }
+
+    /// The directory for intermediate files, see [scratch_dir]
+    pub(crate) fn scratch_dir(&self) -> Result<PathBuf> {
+        scratch_dir(&self.target_dir)
+    }
+
+    /// Editable installs copy the native library next to the python source, so fail before
+    /// compiling if that isn't possible instead of with a permission error at the end
+    fn check_editable_writable(&self) -> Result<()> {
+        let dir = match &self.project_layout.python_module {
+            Some(_) if self.project_layout.rust_module.is_dir() => &self.project_layout.rust_module,
+            Some(python_module) => python_module,
+            // Pure rust projects are installed from the wheel like non-editable ones
+            None => return Ok(()),
+        };
+        if let Err(err) = tempfile::tempfile_in(dir) {
+            bail!(
+                "Editable installs copy the native library into {}, which isn't writable ({}). \
+                 The source tree may be read-only, build a wheel with `maturin build` and install \
+                 it instead or use a writable copy of the source",
+                dir.display(),
+                err
+            );
+        }
+        Ok(())
     }
 
     /// Add library search paths in Cargo target directory rpath when building in editable mode
-    fn add_rpath(&self, artifacts: &[&BuildArtifact]) -> Result<()> {
+    fn add_rpath(&self, artifacts: &[BuildArtifact]) -> Result<()> {
         if self.editable && self.target.is_linux() {
             for artifact in artifacts {
                 if artifact.linked_paths.is_empty() {
@@ -332,31 +846,31 @@ impl BuildContext {
         Ok(())
     }
 
+    /// Bundles the external libraries the artifacts link into the wheel. The artifacts are
+    /// replaced by rewritten copies if any were bundled.
     fn add_external_libs(
         &self,
         writer: &mut WheelWriter,
-        artifacts: &[&BuildArtifact],
+        artifacts: &mut [BuildArtifact],
         ext_libs: &[Vec<Library>],
     ) -> Result<()> {
         if self.editable {
             return self.add_rpath(artifacts);
         }
+        if self.target.is_macos() {
+            if self.repair && !self.skip_auditwheel {
+                return self.add_external_dylibs(writer, artifacts);
+            }
+            return Ok(());
+        }
         if ext_libs.iter().all(|libs| libs.is_empty()) {
             return Ok(());
         }
-        // Put external libs to ${module_name}.libs directory
-        // See https://github.com/pypa/auditwheel/issues/89
-        let mut libs_dir = self
-            .project_layout
-            .python_module
-            .as_ref()
-            .and_then(|py| py.file_name().map(|s| s.to_os_string()))
-            .unwrap_or_else(|| self.module_name.clone().into());
-        libs_dir.push(".libs");
-        let libs_dir = PathBuf::from(libs_dir);
+        self.copy_artifacts_for_repair(artifacts)?;
+        let libs_dir = self.libs_dir();
         writer.add_directory(&libs_dir)?;
 
-        let temp_dir = tempfile::tempdir()?;
+        let temp_dir = tempfile::tempdir_in(self.scratch_dir()?)?;
         let mut soname_map = HashMap::new();
         let mut libs_copied = HashSet::new();
         for lib in ext_libs.iter().flatten() {
@@ -433,13 +947,12 @@ impl BuildContext {
             println!("    {}", lib_path.display());
         }
 
-        // Currently artifact .so file always resides at ${module_name}/${module_name}.so
-        let artifact_dir = Path::new(&self.module_name);
-        for artifact in artifacts {
+        let artifact_dir = self.artifact_dir();
+        for artifact in artifacts.iter() {
             let mut new_rpaths = patchelf::get_rpath(&artifact.path)?;
             // TODO: clean existing rpath entries if it's not pointed to a location within the wheel
             // See https://github.com/pypa/auditwheel/blob/353c24250d66951d5ac7e60b97471a6da76c123f/src/auditwheel/repair.py#L160
-            let new_rpath = Path::new("$ORIGIN").join(relpath(&libs_dir, artifact_dir));
+            let new_rpath = Path::new("$ORIGIN").join(relpath(&libs_dir, &artifact_dir));
             new_rpaths.push(new_rpath.to_str().unwrap().to_string());
             let new_rpath = new_rpaths.join(":");
             patchelf::set_rpath(&artifact.path, &new_rpath)?;
@@ -447,6 +960,87 @@ impl BuildContext {
         Ok(())
     }
 
+    /// Copies the artifacts to the scratch directory, so that bundling the external libraries
+    /// rewrites the copies and not cargo's artifacts or the cached ones
+    fn copy_artifacts_for_repair(&self, artifacts: &mut [BuildArtifact]) -> Result<()> {
+        let repair_dir = self.scratch_dir()?.join("repair");
+        fs::create_dir_all(&repair_dir)?;
+        for artifact in artifacts {
+            let copy = repair_dir.join(artifact.path.file_name().unwrap());
+            fs::copy(&artifact.path, &copy)?;
+            artifact.path = copy;
+        }
+        Ok(())
+    }
+
+    /// The directory in the wheel the native library is written to, see
+    /// [write_bindings_module]: the directory of the rust module in mixed projects, e.g.
+    /// `foo/bar` for `foo.bar`, and `${module_name}` otherwise
+    fn artifact_dir(&self) -> PathBuf {
+        match &self.project_layout.python_module {
+            Some(python_module) => self
+                .project_layout
+                .rust_module
+                .strip_prefix(python_module.parent().unwrap())
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|_| PathBuf::from(&self.module_name)),
+            None => PathBuf::from(&self.module_name),
+        }
+    }
+
+    /// The directory in the wheel the external libraries are copied to, `${module_name}.libs`
+    /// next to the package, see https://github.com/pypa/auditwheel/issues/89
+    fn libs_dir(&self) -> PathBuf {
+        let mut libs_dir = self
+            .project_layout
+            .python_module
+            .as_ref()
+            .and_then(|py| py.file_name().map(|s| s.to_os_string()))
+            .unwrap_or_else(|| self.module_name.clone().into());
+        libs_dir.push(".libs");
+        PathBuf::from(libs_dir)
+    }
+
+    /// Copies the dylibs from outside of macOS that the artifacts link into the wheel and
+    /// rewrites the load commands to use them, like delocate does
+    fn add_external_dylibs(
+        &self,
+        writer: &mut WheelWriter,
+        artifacts: &mut [BuildArtifact],
+    ) -> Result<()> {
+        let libs_dir = self.libs_dir();
+        let artifact_dir = self.artifact_dir();
+        self.copy_artifacts_for_repair(artifacts)?;
+        let artifact_paths: Vec<&Path> = artifacts.iter().map(|a| a.path.as_path()).collect();
+        let search_paths: Vec<PathBuf> = artifacts
+            .iter()
+            .flat_map(|artifact| artifact.linked_paths.iter().map(PathBuf::from))
+            .collect();
+        let temp_dir = tempfile::tempdir_in(self.scratch_dir()?)?;
+        let bundled = delocate::delocate(
+            &artifact_paths,
+            &search_paths,
+            temp_dir.path(),
+            &relpath(&libs_dir, &artifact_dir),
+        )?;
+        if bundled.is_empty() {
+            return Ok(());
+        }
+
+        writer.add_directory(&libs_dir)?;
+        for dylib in &bundled {
+            writer.add_file_with_permissions(libs_dir.join(&dylib.name), &dylib.copy, 0o755)?;
+        }
+        println!(
+            "🖨  Copied external shared libraries to package {} directory:",
+            libs_dir.display()
+        );
+        for dylib in &bundled {
+            println!("    {}", dylib.source.display());
+        }
+        Ok(())
+    }
+
     fn add_pth(&self, writer: &mut WheelWriter) -> Result<()> {
         if self.editable {
             writer.add_pth(&self.project_layout, &self.metadata21)?;
@@ -471,27 +1065,89 @@ impl BuildContext {
         Ok(None)
     }
 
+    /// How to handle scripts without a file extension in the data directory
+    fn script_wrappers(&self) -> ScriptWrappers {
+        if !self.target.is_windows() {
+            ScriptWrappers::Skip
+        } else if self
+            .pyproject_toml
+            .as_ref()
+            .map_or(false, |pyproject| pyproject.script_wrappers())
+        {
+            ScriptWrappers::Generate
+        } else {
+            ScriptWrappers::Warn
+        }
+    }
+
+    /// Whether file names in the wheel are normalized to unicode NFC
+    fn normalize_unicode(&self) -> bool {
+        self.pyproject_toml
+            .as_ref()
+            .map_or(true, |pyproject| pyproject.unicode_normalization())
+    }
+
+    /// Creates the writer of a wheel with the settings from pyproject.toml
+    fn wheel_writer(
+        &self,
+        tag: &str,
+        metadata21: &Metadata21,
+        tags: &[String],
+    ) -> Result<WheelWriter> {
+        let config = self
+            .pyproject_toml
+            .as_ref()
+            .and_then(|pyproject| pyproject.wheel_config())
+            .cloned()
+            .unwrap_or_default();
+        let mut writer = WheelWriter::with_config(
+            tag,
+            &self.out,
+            metadata21,
+            tags,
+            self.build_tag.as_deref(),
+            self.excludes(Format::Wheel)?,
+            self.normalize_unicode(),
+            config,
+        )?;
+        let skip_duplicate_contents = self
+            .pyproject_toml
+            .as_ref()
+            .map_or(false, |pyproject| pyproject.skip_duplicate_contents());
+        writer.set_skip_duplicate_contents(skip_duplicate_contents);
+        Ok(writer)
+    }
+
+    /// The platform compatibility of the wheels with `platform_tags`
+    fn compatibility(&self, platform_tags: &[PlatformTag]) -> Compatibility {
+        Compatibility {
+            platform_tags: platform_tags.to_vec(),
+            universal2: self.universal2,
+        }
+    }
+
     fn write_binding_wheel_abi3(
         &self,
-        artifact: BuildArtifact,
+        python_interpreter: Option<&PythonInterpreter>,
+        mut artifact: BuildArtifact,
         platform_tags: &[PlatformTag],
         ext_libs: Vec<Library>,
         major: u8,
         min_minor: u8,
     ) -> Result<BuiltWheelMetadata> {
-        let platform = self
-            .target
-            .get_platform_tag(platform_tags, self.universal2)?;
-        let tag = format!("cp{}{}-abi3-{}", major, min_minor, platform);
+        let abi = WheelAbi::Abi3 {
+            major,
+            minor: min_minor,
+            interpreter: python_interpreter,
+        };
+        let tag = tags::compute(abi, &self.target, &self.compatibility(platform_tags))?.to_string();
 
-        let mut writer = WheelWriter::new(
-            &tag,
-            &self.out,
-            &self.metadata21,
-            &[tag.clone()],
-            self.excludes(Format::Wheel)?,
+        let mut writer = self.wheel_writer(&tag, &self.metadata21, &[tag.clone()])?;
+        self.add_external_libs(
+            &mut writer,
+            std::slice::from_mut(&mut artifact),
+            &[ext_libs],
         )?;
-        self.add_external_libs(&mut writer, &[&artifact], &[ext_libs])?;
 
         write_bindings_module(
             &mut writer,
@@ -506,7 +1162,12 @@ impl BuildContext {
         .context("Failed to add the files to the wheel")?;
 
         self.add_pth(&mut writer)?;
-        add_data(&mut writer, self.project_layout.data.as_deref())?;
+        add_data(
+            &mut writer,
+            &self.project_layout.data,
+            self.excludes(Format::Wheel)?.as_ref(),
+            self.script_wrappers(),
+        )?;
         let wheel_path = writer.finish()?;
         Ok((wheel_path, format!("cp{}{}", major, min_minor)))
     }
@@ -519,57 +1180,57 @@ impl BuildContext {
         major: u8,
         min_minor: u8,
     ) -> Result<Vec<BuiltWheelMetadata>> {
-        let mut wheels = Vec::new();
         // On windows, we have picked an interpreter to set the location of python.lib,
         // otherwise it's none
         let python_interpreter = interpreters.get(0);
-        let artifact = self.compile_cdylib(
-            python_interpreter,
-            Some(&self.project_layout.extension_name),
-        )?;
-        let (policy, external_libs) =
-            self.auditwheel(&artifact, &self.platform_tag, python_interpreter)?;
-        let platform_tags = if self.platform_tag.is_empty() {
-            vec![policy.platform_tag()]
-        } else {
-            self.platform_tag.clone()
-        };
-        let (wheel_path, tag) = self.write_binding_wheel_abi3(
-            artifact,
-            &platform_tags,
-            external_libs,
-            major,
-            min_minor,
-        )?;
+        let name = abi3_build_name(major, min_minor);
+        let wheel = self.build_or_reuse(&name, || {
+            let artifact = self.compile_cdylib(
+                python_interpreter,
+                Some(&self.project_layout.extension_name),
+            )?;
+            if !self.skip_auditwheel {
+                audit_abi3(&artifact.path, major, min_minor)?;
+            }
+            let (policy, external_libs) =
+                self.auditwheel(&artifact, &self.platform_tag, python_interpreter)?;
+            let platform_tags = self.platform_tags(&policy);
+            let (wheel_path, tag) = self.write_binding_wheel_abi3(
+                python_interpreter,
+                artifact,
+                &platform_tags,
+                external_libs,
+                major,
+                min_minor,
+            )?;
 
-        println!(
-            "📦 Built wheel for abi3 Python ≥ {}.{} to {}",
-            major,
-            min_minor,
-            wheel_path.display()
-        );
-        wheels.push((wheel_path, tag));
+            println!(
+                "📦 Built wheel for abi3 Python ≥ {}.{} to {}",
+                major,
+                min_minor,
+                wheel_path.display()
+            );
+            Ok((wheel_path, tag))
+        })?;
 
-        Ok(wheels)
+        Ok(wheel.into_iter().collect())
     }
 
     fn write_binding_wheel(
         &self,
         python_interpreter: &PythonInterpreter,
-        artifact: BuildArtifact,
+        mut artifact: BuildArtifact,
         platform_tags: &[PlatformTag],
         ext_libs: Vec<Library>,
     ) -> Result<BuiltWheelMetadata> {
         let tag = python_interpreter.get_tag(&self.target, platform_tags, self.universal2)?;
 
-        let mut writer = WheelWriter::new(
-            &tag,
-            &self.out,
-            &self.metadata21,
-            &[tag.clone()],
-            self.excludes(Format::Wheel)?,
+        let mut writer = self.wheel_writer(&tag, &self.metadata21, &[tag.clone()])?;
+        self.add_external_libs(
+            &mut writer,
+            std::slice::from_mut(&mut artifact),
+            &[ext_libs],
         )?;
-        self.add_external_libs(&mut writer, &[&artifact], &[ext_libs])?;
 
         write_bindings_module(
             &mut writer,
@@ -584,7 +1245,12 @@ impl BuildContext {
         .context("Failed to add the files to the wheel")?;
 
         self.add_pth(&mut writer)?;
-        add_data(&mut writer, self.project_layout.data.as_deref())?;
+        add_data(
+            &mut writer,
+            &self.project_layout.data,
+            self.excludes(Format::Wheel)?.as_ref(),
+            self.script_wrappers(),
+        )?;
         let wheel_path = writer.finish()?;
         Ok((
             wheel_path,
@@ -605,33 +1271,24 @@ impl BuildContext {
     ) -> Result<Vec<BuiltWheelMetadata>> {
         let mut wheels = Vec::new();
         for python_interpreter in interpreters {
-            let artifact = self.compile_cdylib(
-                Some(python_interpreter),
-                Some(&self.project_layout.extension_name),
-            )?;
-            let (policy, external_libs) =
-                self.auditwheel(&artifact, &self.platform_tag, Some(python_interpreter))?;
-            let platform_tags = if self.platform_tag.is_empty() {
-                vec![policy.platform_tag()]
-            } else {
-                self.platform_tag.clone()
-            };
-            let (wheel_path, tag) = self.write_binding_wheel(
-                python_interpreter,
-                artifact,
-                &platform_tags,
-                external_libs,
-            )?;
-            println!(
-                "📦 Built wheel for {} {}.{}{} to {}",
-                python_interpreter.interpreter_kind,
-                python_interpreter.major,
-                python_interpreter.minor,
-                python_interpreter.abiflags,
-                wheel_path.display()
-            );
-
-            wheels.push((wheel_path, tag));
+            let name = interpreter_build_name(python_interpreter);
+            wheels.extend(self.build_or_reuse(&name, || {
+                let artifact = self.compile_cdylib(
+                    Some(python_interpreter),
+                    Some(&self.project_layout.extension_name),
+                )?;
+                let (policy, external_libs) =
+                    self.auditwheel(&artifact, &self.platform_tag, Some(python_interpreter))?;
+                let platform_tags = self.platform_tags(&policy);
+                let (wheel_path, tag) = self.write_binding_wheel(
+                    python_interpreter,
+                    artifact,
+                    &platform_tags,
+                    external_libs,
+                )?;
+                println!("📦 Built wheel for {} to {}", name, wheel_path.display());
+                Ok((wheel_path, tag))
+            })?);
         }
 
         Ok(wheels)
@@ -646,16 +1303,40 @@ impl BuildContext {
         python_interpreter: Option<&PythonInterpreter>,
         extension_name: Option<&str>,
     ) -> Result<BuildArtifact> {
-        let artifacts = compile(self, python_interpreter, &self.bridge)
-            .context("Failed to build a native library through cargo")?;
-        let error_msg = "Cargo didn't build a cdylib. Did you miss crate-type = [\"cdylib\"] \
-                 in the lib section of your Cargo.toml?";
-        let artifacts = artifacts.get(0).context(error_msg)?;
-
-        let mut artifact = artifacts
-            .get("cdylib")
-            .cloned()
-            .ok_or_else(|| anyhow!(error_msg,))?;
+        let cached = if self.artifact_cache {
+            artifact_cache::load(self, python_interpreter, self.source_hash()?)
+        } else {
+            None
+        };
+        let mut artifact = match cached {
+            Some(artifact) => {
+                println!(
+                    "🔁 Reusing {} from the cache, the sources didn't change",
+                    artifact.path.display()
+                );
+                artifact
+            }
+            None => {
+                let artifacts = compile(self, python_interpreter, &self.bridge)
+                    .context("Failed to build a native library through cargo")?;
+                let error_msg = "Cargo didn't build a cdylib. Did you miss crate-type = \
+                    [\"cdylib\"] in the lib section of your Cargo.toml?";
+                let artifacts = artifacts.get(0).context(error_msg)?;
+                let artifact = artifacts
+                    .get("cdylib")
+                    .cloned()
+                    .ok_or_else(|| anyhow!(error_msg,))?;
+                if self.artifact_cache {
+                    artifact_cache::store(
+                        self,
+                        python_interpreter,
+                        self.source_hash()?,
+                        &artifact,
+                    )?;
+                }
+                artifact
+            }
+        };
 
         if let Some(extension_name) = extension_name {
             // globin has an issue parsing MIPS64 ELF, see https://github.com/m4b/goblin/issues/274
@@ -663,6 +1344,25 @@ impl BuildContext {
             let _ = warn_missing_py_init(&artifact.path, extension_name);
         }
 
+        if self.target.is_windows_gnu() {
+            if let Ok(dlls) = mingw_runtime_dependencies(&artifact.path) {
+                if !dlls.is_empty() {
+                    eprintln!(
+                        "⚠️  Warning: {} links the MinGW runtime dynamically ({}), which isn't \
+                        installed with python. Link it statically, e.g. with \
+                        `-C link-arg=-static`, or the module will fail to import. \
+                        See `maturin doctor` for the caveats of windows-gnu.",
+                        artifact.path.display(),
+                        dlls.join(", ")
+                    );
+                }
+            }
+        }
+
+        if self.target.is_macos() {
+            self.check_macos_deployment_target(&artifact.path, python_interpreter)?;
+        }
+
         if self.editable || self.skip_auditwheel {
             return Ok(artifact);
         }
@@ -676,9 +1376,62 @@ impl BuildContext {
         Ok(artifact)
     }
 
+    /// The macOS deployment target of the interpreter, if the platform tag of the wheel uses it.
+    /// Binaries without python bindings are tagged independent of the interpreter.
+    pub(crate) fn interpreter_macos_deployment_target<'a>(
+        &self,
+        python_interpreter: Option<&'a PythonInterpreter>,
+    ) -> Option<&'a str> {
+        match self.bridge {
+            BridgeModel::Bin(None) => None,
+            _ => python_interpreter.and_then(|python| python.macos_deployment_target.as_deref()),
+        }
+    }
+
+    /// Fails if the library or binary requires a newer macOS than the platform tag of the wheel
+    /// claims, e.g. because a build script or `-mmacosx-version-min` in the flags of a C
+    /// dependency overrode the deployment target, since pip would install it on a macOS
+    /// version it can't be loaded on
+    fn check_macos_deployment_target(
+        &self,
+        artifact: &Path,
+        python_interpreter: Option<&PythonInterpreter>,
+    ) -> Result<()> {
+        let (x86_64, arm64) = self.target.macos_deployment_targets(
+            self.interpreter_macos_deployment_target(python_interpreter),
+            self.universal2,
+        )?;
+        for (arch, (major, minor)) in macos_minimum_versions(artifact)? {
+            let (tag_major, tag_minor) = match arch {
+                Arch::Aarch64 => arm64,
+                _ => x86_64,
+            };
+            if (major, minor) > (tag_major, tag_minor) {
+                bail!(
+                    "{} requires macOS {}.{} on {}, but the wheel would be tagged for macOS {}.{} \
+                    (macosx_{}_{}). Raise the deployment target to {}.{} with \
+                    MACOSX_DEPLOYMENT_TARGET, `--macos-deployment-target` or \
+                    `macos-deployment-target` in `[tool.maturin]`, or make sure nothing overrides \
+                    it, e.g. -mmacosx-version-min in CFLAGS or a build script.",
+                    artifact.display(),
+                    major,
+                    minor,
+                    arch,
+                    tag_major,
+                    tag_minor,
+                    tag_major,
+                    tag_minor,
+                    major,
+                    minor
+                );
+            }
+        }
+        Ok(())
+    }
+
     fn write_cffi_wheel(
         &self,
-        artifact: BuildArtifact,
+        mut artifact: BuildArtifact,
         platform_tags: &[PlatformTag],
         ext_libs: Vec<Library>,
     ) -> Result<BuiltWheelMetadata> {
@@ -686,14 +1439,12 @@ impl BuildContext {
             .target
             .get_universal_tags(platform_tags, self.universal2)?;
 
-        let mut writer = WheelWriter::new(
-            &tag,
-            &self.out,
-            &self.metadata21,
-            &tags,
-            self.excludes(Format::Wheel)?,
+        let mut writer = self.wheel_writer(&tag, &self.metadata21, &tags)?;
+        self.add_external_libs(
+            &mut writer,
+            std::slice::from_mut(&mut artifact),
+            &[ext_libs],
         )?;
-        self.add_external_libs(&mut writer, &[&artifact], &[ext_libs])?;
 
         write_cffi_module(
             &mut writer,
@@ -702,29 +1453,25 @@ impl BuildContext {
             &self.target_dir,
             &self.module_name,
             &artifact.path,
-            &self.interpreter[0].executable,
+            &self.interpreters()?[0].executable,
             self.editable,
             self.pyproject_toml.as_ref(),
+            &self.env,
         )?;
 
         self.add_pth(&mut writer)?;
-        add_data(&mut writer, self.project_layout.data.as_deref())?;
+        add_data(
+            &mut writer,
+            &self.project_layout.data,
+            self.excludes(Format::Wheel)?.as_ref(),
+            self.script_wrappers(),
+        )?;
         let wheel_path = writer.finish()?;
         Ok((wheel_path, "py3".to_string()))
     }
 
     /// Builds a wheel with cffi bindings
     pub fn build_cffi_wheel(&self) -> Result<Vec<BuiltWheelMetadata>> {
-        let mut wheels = Vec::new();
-        let artifact = self.compile_cdylib(None, None)?;
-        let (policy, external_libs) = self.auditwheel(&artifact, &self.platform_tag, None)?;
-        let platform_tags = if self.platform_tag.is_empty() {
-            vec![policy.platform_tag()]
-        } else {
-            self.platform_tag.clone()
-        };
-        let (wheel_path, tag) = self.write_cffi_wheel(artifact, &platform_tags, external_libs)?;
-
         // Warn if cffi isn't specified in the requirements
         if !self
             .metadata21
@@ -738,10 +1485,17 @@ impl BuildContext {
             );
         }
 
-        println!("📦 Built wheel to {}", wheel_path.display());
-        wheels.push((wheel_path, tag));
-
-        Ok(wheels)
+        let wheel = self.build_or_reuse(CFFI_BUILD_NAME, || {
+            let artifact = self.compile_cdylib(None, None)?;
+            let (policy, external_libs) = self.auditwheel(&artifact, &self.platform_tag, None)?;
+            let platform_tags = self.platform_tags(&policy);
+            let (wheel_path, tag) =
+                self.write_cffi_wheel(artifact, &platform_tags, external_libs)?;
+            println!("📦 Built wheel to {}", wheel_path.display());
+            Ok((wheel_path, tag))
+        })?;
+
+        Ok(wheel.into_iter().collect())
     }
 
     fn write_bin_wheel(
@@ -796,13 +1550,7 @@ impl BuildContext {
             self.metadata21.clone()
         };
 
-        let mut writer = WheelWriter::new(
-            &tag,
-            &self.out,
-            &metadata21,
-            &tags,
-            self.excludes(Format::Wheel)?,
-        )?;
+        let mut writer = self.wheel_writer(&tag, &metadata21, &tags)?;
 
         if let Some(python_module) = &self.project_layout.python_module {
             if self.target.is_wasi() {
@@ -811,23 +1559,33 @@ impl BuildContext {
                 bail!("Sorry, adding python code to a wasm binary is currently not supported")
             }
             if !self.editable {
-                write_python_part(&mut writer, python_module, self.pyproject_toml.as_ref())
-                    .context("Failed to add the python module to the package")?;
+                write_python_part(
+                    &mut writer,
+                    python_module,
+                    &self.project_layout.extra_python_modules,
+                    self.pyproject_toml.as_ref(),
+                )
+                .context("Failed to add the python module to the package")?;
             }
         }
 
-        let mut artifacts_ref = Vec::with_capacity(artifacts.len());
-        for (artifact, bin_name) in &artifacts_and_files {
-            artifacts_ref.push(*artifact);
+        // Rewrite the binaries before they are written to the wheel
+        let mut artifacts = artifacts.to_vec();
+        self.add_external_libs(&mut writer, &mut artifacts, ext_libs)?;
+        for (artifact, (_, bin_name)) in artifacts.iter().zip(&artifacts_and_files) {
             write_bin(&mut writer, &artifact.path, &self.metadata21, bin_name)?;
             if self.target.is_wasi() {
                 write_wasm_launcher(&mut writer, &self.metadata21, bin_name)?;
             }
         }
-        self.add_external_libs(&mut writer, &artifacts_ref, ext_libs)?;
 
         self.add_pth(&mut writer)?;
-        add_data(&mut writer, self.project_layout.data.as_deref())?;
+        add_data(
+            &mut writer,
+            &self.project_layout.data,
+            self.excludes(Format::Wheel)?.as_ref(),
+            self.script_wrappers(),
+        )?;
         let wheel_path = writer.finish()?;
         Ok((wheel_path, "py3".to_string()))
     }
@@ -839,44 +1597,108 @@ impl BuildContext {
         &self,
         python_interpreter: Option<&PythonInterpreter>,
     ) -> Result<Vec<BuiltWheelMetadata>> {
-        let mut wheels = Vec::new();
-        let artifacts = compile(self, python_interpreter, &self.bridge)
-            .context("Failed to build a native library through cargo")?;
-        if artifacts.is_empty() {
-            bail!("Cargo didn't build a binary")
+        let name = bin_build_name(python_interpreter);
+        let wheel = self.build_or_reuse(&name, || {
+            let artifacts = compile(self, python_interpreter, &self.bridge)
+                .context("Failed to build a native library through cargo")?;
+            if artifacts.is_empty() {
+                bail!("Cargo didn't build a binary")
+            }
+
+            let mut policies = Vec::with_capacity(artifacts.len());
+            let mut ext_libs = Vec::new();
+            let mut artifact_paths = Vec::with_capacity(artifacts.len());
+            for artifact in artifacts {
+                let artifact = artifact
+                    .get("bin")
+                    .cloned()
+                    .ok_or_else(|| anyhow!("Cargo didn't build a binary"))?;
+
+                if self.target.is_macos() {
+                    self.check_macos_deployment_target(&artifact.path, python_interpreter)?;
+                }
+
+                let (policy, external_libs) =
+                    self.auditwheel(&artifact, &self.platform_tag, None)?;
+                policies.push(policy);
+                ext_libs.push(external_libs);
+                artifact_paths.push(artifact);
+            }
+            let policy = policies.iter().min_by_key(|p| p.priority).unwrap();
+            let platform_tags = self.platform_tags(policy);
+
+            let (wheel_path, tag) = self.write_bin_wheel(
+                python_interpreter,
+                &artifact_paths,
+                &platform_tags,
+                &ext_libs,
+            )?;
+            println!("📦 Built wheel to {}", wheel_path.display());
+            Ok((wheel_path, tag))
+        })?;
+
+        Ok(wheel.into_iter().collect())
+    }
+
+    /// Writes a self-extracting installer script for each wheel, which installs the wheel on
+    /// machines without access to a package index
+    pub fn build_installers(&self, wheels: &[BuiltWheelMetadata]) -> Result<Vec<PathBuf>> {
+        let mut installers = Vec::new();
+        for (wheel_path, _) in wheels {
+            for installer in write_installers(wheel_path)? {
+                println!("📦 Built installer to {}", installer.display());
+                installers.push(installer);
+            }
         }
+        Ok(installers)
+    }
 
-        let mut policies = Vec::with_capacity(artifacts.len());
-        let mut ext_libs = Vec::new();
-        let mut artifact_paths = Vec::with_capacity(artifacts.len());
+    /// Builds a [PEP 441](https://peps.python.org/pep-0441/) zipapp (`.pyz`) for every binary
+    ///
+    /// This is a single file distribution outside of package indexes, the zipapp only contains
+    /// the binary and not the python source or the data directory.
+    pub fn build_zipapps(&self) -> Result<Vec<PathBuf>> {
+        let python_interpreter = match &self.bridge {
+            BridgeModel::Bin(None) => None,
+            BridgeModel::Bin(Some(..)) => self.interpreters()?.first(),
+            _ => bail!(
+                "Zipapps can only be built for bin bindings, not for {}",
+                self.bridge
+            ),
+        };
+        if self.target.is_wasi() {
+            bail!("Zipapps of wasm binaries are not supported");
+        }
+        fs::create_dir_all(&self.out)
+            .context("Failed to create the target directory for the zipapps")?;
+        let artifacts = compile(self, python_interpreter, &self.bridge)
+            .context("Failed to build a native library through cargo")?;
+        let mut zipapps = Vec::new();
         for artifact in artifacts {
             let artifact = artifact
                 .get("bin")
-                .cloned()
                 .ok_or_else(|| anyhow!("Cargo didn't build a binary"))?;
-
-            let (policy, external_libs) = self.auditwheel(&artifact, &self.platform_tag, None)?;
-            policies.push(policy);
-            ext_libs.push(external_libs);
-            artifact_paths.push(artifact);
+            let bin_name = artifact
+                .path
+                .file_stem()
+                .and_then(|name| name.to_str())
+                .context("binary produced by cargo has non-utf8 filename")?;
+            let zipapp_path = self.out.join(format!(
+                "{}-{}-{}.pyz",
+                bin_name,
+                self.metadata21.get_version_escaped(),
+                self.target.target_triple()
+            ));
+            let file_name = artifact
+                .path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .context("binary produced by cargo has non-utf8 filename")?;
+            write_zipapp(&zipapp_path, &artifact.path, file_name)?;
+            println!("📦 Built zipapp to {}", zipapp_path.display());
+            zipapps.push(zipapp_path);
         }
-        let policy = policies.iter().min_by_key(|p| p.priority).unwrap();
-        let platform_tags = if self.platform_tag.is_empty() {
-            vec![policy.platform_tag()]
-        } else {
-            self.platform_tag.clone()
-        };
-
-        let (wheel_path, tag) = self.write_bin_wheel(
-            python_interpreter,
-            &artifact_paths,
-            &platform_tags,
-            &ext_libs,
-        )?;
-        println!("📦 Built wheel to {}", wheel_path.display());
-        wheels.push((wheel_path, tag));
-
-        Ok(wheels)
+        Ok(zipapps)
     }
 
     /// Builds a wheel that contains a binary
@@ -902,3 +1724,124 @@ pub fn hash_file(path: impl AsRef<Path>) -> Result<String, io::Error> {
     let hex = format!("{:x}", hasher.finalize());
     Ok(hex)
 }
+
+/// How many of the largest wheel entries [print_wheel_size_breakdown] shows
+const SIZE_BREAKDOWN_ENTRIES: usize = 10;
+
+/// Formats a number of bytes with a binary unit, e.g. `1.5 MiB`
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Returns the uncompressed and compressed size of every file in the wheel,
+/// sorted from largest to smallest uncompressed size
+fn wheel_size_breakdown(wheel_path: &Path) -> Result<Vec<(String, u64, u64)>> {
+    let file = fs::File::open(wheel_path)?;
+    let mut archive = zip::ZipArchive::new(file)
+        .with_context(|| format!("Failed to open {} as zip archive", wheel_path.display()))?;
+    let mut entries = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        entries.push((
+            entry.name().to_string(),
+            entry.size(),
+            entry.compressed_size(),
+        ));
+    }
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(entries)
+}
+
+/// Prints a table of the largest files in the wheel, which helps to spot
+/// accidentally included test data or debug symbols
+fn print_wheel_size_breakdown(wheel_path: &Path) -> Result<()> {
+    let entries = wheel_size_breakdown(wheel_path)?;
+    let total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    let total_compressed: u64 = entries.iter().map(|(_, _, compressed)| compressed).sum();
+    println!(
+        "📊 Largest files in {}:",
+        wheel_path
+            .file_name()
+            .unwrap_or(wheel_path.as_os_str())
+            .to_string_lossy()
+    );
+    println!("{:>12} {:>12}  File", "Size", "Compressed");
+    for (name, size, compressed) in entries.iter().take(SIZE_BREAKDOWN_ENTRIES) {
+        println!(
+            "{:>12} {:>12}  {}",
+            format_size(*size),
+            format_size(*compressed),
+            name
+        );
+    }
+    if entries.len() > SIZE_BREAKDOWN_ENTRIES {
+        println!(
+            "{:>12} {:>12}  ({} more files)",
+            "",
+            "",
+            entries.len() - SIZE_BREAKDOWN_ENTRIES
+        );
+    }
+    println!(
+        "{:>12} {:>12}  Total ({} files)",
+        format_size(total),
+        format_size(total_compressed),
+        entries.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_report() {
+        let mut report = BuildReport::default();
+        for (triple, wheel) in [
+            (
+                "x86_64-unknown-linux-gnu",
+                "hello-0.1.0-py3-none-manylinux_2_17_x86_64.whl",
+            ),
+            (
+                "aarch64-unknown-linux-gnu",
+                "hello-0.1.0-py3-none-manylinux_2_17_aarch64.whl",
+            ),
+        ] {
+            let target = Target::from_target_triple(Some(triple.to_string())).unwrap();
+            report.add_target(&target, &[(PathBuf::from(wheel), "py3".to_string())]);
+        }
+        assert_eq!(
+            report.summary(),
+            "📦 Built 2 wheel(s) for 2 target(s):\n  \
+            - x86_64-unknown-linux-gnu: hello-0.1.0-py3-none-manylinux_2_17_x86_64.whl\n  \
+            - aarch64-unknown-linux-gnu: hello-0.1.0-py3-none-manylinux_2_17_aarch64.whl\n"
+        );
+        let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["targets"][1]["target"], "aarch64-unknown-linux-gnu");
+        assert_eq!(json["targets"][1]["wheels"][0]["tag"], "py3");
+        assert!(json["sdist"].is_null());
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+    }
+}
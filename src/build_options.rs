@@ -1,16 +1,24 @@
-use crate::auditwheel::PlatformTag;
+use crate::artifact_cache::cache_enabled;
+use crate::auditwheel::{PlatformTag, Policy};
 use crate::build_context::BridgeModel;
+use crate::check::check_platform_markers;
 use crate::cross_compile::{find_sysconfigdata, parse_sysconfigdata};
+use crate::hooks::{run_hooks, HookStage};
+use crate::lto::lto_options;
 use crate::project_layout::ProjectResolver;
-use crate::pyproject_toml::ToolMaturin;
+use crate::pyproject_toml::{CargoLockInclusion, LtoConfig, ToolMaturin};
 use crate::python_interpreter::{InterpreterConfig, InterpreterKind, MINIMUM_PYTHON_MINOR};
-use crate::{BuildContext, Metadata21, PythonInterpreter, Target};
+use crate::stats::stats_enabled;
+use crate::target::host_target_triple;
+use crate::{BuildContext, ConfigError, Metadata21, PyProjectToml, PythonInterpreter, Target};
 use anyhow::{bail, format_err, Context, Result};
 use cargo_metadata::{Metadata, Node};
+use once_cell::sync::OnceCell;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::env;
+use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
 
@@ -52,6 +60,10 @@ pub struct CargoOptions {
     #[arg(long, value_name = "PROFILE-NAME")]
     pub profile: Option<String>,
 
+    /// Use the options of a profile in `[tool.maturin.profiles]` of pyproject.toml
+    #[arg(long, value_name = "NAME", env = "MATURIN_PROFILE")]
+    pub maturin_profile: Option<String>,
+
     /// Space or comma separated list of features to activate
     #[arg(short = 'F', long, action = clap::ArgAction::Append)]
     pub features: Vec<String>,
@@ -65,9 +77,20 @@ pub struct CargoOptions {
     pub no_default_features: bool,
 
     /// Build for the target triple
-    #[arg(long, value_name = "TRIPLE", env = "CARGO_BUILD_TARGET")]
+    #[arg(skip)]
     pub target: Option<String>,
 
+    /// Build for the target triple, `maturin build` and `maturin publish` accept multiple targets
+    #[arg(
+        long = "target",
+        value_name = "TRIPLE",
+        env = "CARGO_BUILD_TARGET",
+        value_delimiter = ',',
+        action = clap::ArgAction::Append
+    )]
+    #[serde(skip)]
+    pub targets: Vec<String>,
+
     /// Directory for all generated artifacts
     #[arg(long, value_name = "DIRECTORY")]
     pub target_dir: Option<PathBuf>,
@@ -134,7 +157,8 @@ pub struct BuildOptions {
     ///
     /// Options are `manylinux` tags (for example `manylinux2014`/`manylinux_2_24`)
     /// or `musllinux` tags (for example `musllinux_1_2`)
-    /// and `linux` for the native linux tag.
+    /// and `linux` for the native linux tag. Custom tags need a policy in
+    /// `[tool.maturin.audit] extra-policy`.
     ///
     /// Note that `manylinux1` and `manylinux2010` is unsupported by the rust compiler.
     /// Wheels with the native `linux` tag will be rejected by pypi,
@@ -174,6 +198,21 @@ pub struct BuildOptions {
     #[arg(long = "skip-auditwheel")]
     pub skip_auditwheel: bool,
 
+    /// Tag the wheel as linux with a warning instead of failing when the library
+    /// violates the requested manylinux/musllinux policy
+    ///
+    /// Wheels with the linux tag can't be uploaded to PyPI.
+    #[arg(long, conflicts_with = "skip_auditwheel")]
+    pub fallback_to_linux: bool,
+
+    /// Bundle the shared libraries the extension links that aren't part of the base system
+    /// into the wheel also when it's tagged linux, and the dylibs from outside of macOS
+    ///
+    /// manylinux and musllinux wheels always bundle them. On macOS, this needs
+    /// install_name_tool and codesign of the Xcode command line tools.
+    #[arg(long, conflicts_with = "skip_auditwheel")]
+    pub repair: bool,
+
     /// For manylinux targets, use zig to ensure compliance for the chosen manylinux version
     ///
     /// Default to manylinux2014/manylinux_2_17 if you do not specify an `--compatibility`
@@ -182,11 +221,59 @@ pub struct BuildOptions {
     #[arg(long)]
     pub zig: bool,
 
+    /// Run the cargo build in a container of cross (https://github.com/cross-rs/cross) with a
+    /// toolchain for the target, e.g. to build wheels for other architectures
+    ///
+    /// Everything else, such as finding the python interpreters and writing the wheels, still
+    /// runs on the host. Requires cross and docker or podman to be installed.
+    #[arg(long, conflicts_with = "zig")]
+    pub use_container: bool,
+
     /// Control whether to build universal2 wheel for macOS or not.
     /// Only applies to macOS targets, do nothing otherwise.
     #[arg(long)]
     pub universal2: bool,
 
+    /// The minimum macOS version to build for, e.g. 10.12
+    ///
+    /// Defaults to MACOSX_DEPLOYMENT_TARGET, `macos-deployment-target` in pyproject.toml and then
+    /// the version the python interpreter was built for. Fails if the linked library requires a
+    /// newer macOS.
+    #[arg(long, value_name = "VERSION")]
+    pub macos_deployment_target: Option<String>,
+
+    /// An optional build number for the wheel, see PEP 427
+    ///
+    /// Must start with a digit, it's used to republish a wheel with the same version
+    #[arg(long, value_name = "N")]
+    pub build_tag: Option<String>,
+
+    /// Publish the build under another distribution name, e.g. for a fork or an internal mirror
+    ///
+    /// The file names of the wheels and the source distribution and the metadata use this name,
+    /// the module names stay the same
+    #[arg(long, value_name = "NAME")]
+    pub dist_name: Option<String>,
+
+    /// Only include files committed to git in the source distribution, like `git archive`
+    ///
+    /// Untracked and ignored files are left out, and building fails if packaged files have
+    /// uncommitted changes
+    #[arg(long)]
+    pub sdist_from_git: bool,
+
+    /// Whether to include the Cargo.lock in the source distribution, overriding
+    /// `include-cargo-lock` in pyproject.toml
+    #[arg(long, value_enum, value_name = "WHEN")]
+    pub include_cargo_lock: Option<CargoLockInclusion>,
+
+    /// Directory for everything the build generates: the cargo target directory (unless
+    /// `--target-dir` is given), intermediate files and the wheels (unless `--out` is given)
+    ///
+    /// With a build directory per configuration, the same checkout can be built concurrently
+    #[arg(long, value_name = "DIRECTORY")]
+    pub build_dir: Option<PathBuf>,
+
     /// Cargo build options
     #[command(flatten)]
     pub cargo: CargoOptions,
@@ -206,27 +293,46 @@ impl DerefMut for BuildOptions {
     }
 }
 
-impl BuildOptions {
+/// How the python interpreters are found. The search is deferred until
+/// [BuildContext::interpreters] needs them, so building a source distribution doesn't require
+/// python.
+#[derive(Debug, Clone)]
+pub(crate) struct InterpreterSearch {
+    /// Find interpreters from the host machine
+    find_interpreter: bool,
+    /// The interpreters given with `--interpreter`
+    user_interpreters: Vec<PathBuf>,
+    /// The interpreters to look for, `python3` if none were given and we're not cross compiling
+    interpreters: Vec<PathBuf>,
+    /// The minimum python minor version, for `--find-interpreter`
+    min_python_minor: Option<usize>,
+    /// Whether pyo3's `generate-import-lib` feature is enabled
+    generate_import_lib: bool,
+}
+
+impl InterpreterSearch {
     /// Finds the appropriate amount for python versions for each [BridgeModel].
-    fn find_interpreters(
+    pub(crate) fn find_interpreters(
         &self,
         bridge: &BridgeModel,
-        interpreter: &[PathBuf],
         target: &Target,
-        min_python_minor: Option<usize>,
-        generate_import_lib: bool,
     ) -> Result<Vec<PythonInterpreter>> {
+        let interpreter = &self.interpreters[..];
+        let min_python_minor = self.min_python_minor;
+        let generate_import_lib = self.generate_import_lib;
         match bridge {
             BridgeModel::Bindings(binding_name, _) | BridgeModel::Bin(Some((binding_name, _))) => {
                 let mut native_interpreters = false;
                 let mut interpreters = Vec::new();
                 if let Some(config_file) = env::var_os("PYO3_CONFIG_FILE") {
                     if !binding_name.starts_with("pyo3") {
-                        bail!("Only pyo3 bindings can be configured with PYO3_CONFIG_FILE");
+                        bail!(ConfigError::new(
+                            "Only pyo3 bindings can be configured with PYO3_CONFIG_FILE"
+                        ));
                     }
                     let interpreter_config =
                         InterpreterConfig::from_pyo3_config(config_file.as_ref(), target)
-                            .context("Invalid PYO3_CONFIG_FILE")?;
+                            .context(ConfigError::new("Invalid PYO3_CONFIG_FILE"))?;
                     interpreters.push(PythonInterpreter::from_config(interpreter_config));
                 } else if binding_name.starts_with("pyo3") && target.cross_compiling() {
                     if let Some(cross_lib_dir) = env::var_os("PYO3_CROSS_LIB_DIR") {
@@ -237,7 +343,7 @@ impl BuildOptions {
                             min_python_minor,
                         )?;
                         let host_python = &host_interpreters[0];
-                        println!(
+                        eprintln!(
                             "🐍 Using host {} for cross-compiling preparation",
                             host_python
                         );
@@ -277,6 +383,8 @@ impl BuildOptions {
                             .and_then(|tag| {
                                 if tag.starts_with("pypy") {
                                     Some(InterpreterKind::PyPy)
+                                } else if tag.starts_with("graalpy") {
+                                    Some(InterpreterKind::GraalPy)
                                 } else if tag.starts_with("cpython") {
                                     Some(InterpreterKind::CPython)
                                 } else {
@@ -299,11 +407,21 @@ impl BuildOptions {
                             runnable: false,
                             implmentation_name: interpreter_kind.to_string().to_ascii_lowercase(),
                             soabi: soabi.cloned(),
+                            macos_framework: false,
+                            macos_link_args: None,
+                            macos_deployment_target: None,
                         });
                     } else {
                         if interpreter.is_empty() && !self.find_interpreter {
                             bail!("Couldn't find any python interpreters. Please specify at least one with -i");
                         }
+                        if target.is_windows() && !generate_import_lib {
+                            eprintln!(
+                                "⚠️  Warning: Cross compiling for {} without PYO3_CROSS_LIB_DIR \
+                                requires pyo3's `generate-import-lib` feature to link against python",
+                                target.target_triple()
+                            );
+                        }
                         interpreters =
                             find_interpreter_in_sysconfig(interpreter, target, min_python_minor)?;
                     }
@@ -334,9 +452,9 @@ impl BuildOptions {
                     .collect::<Vec<String>>()
                     .join(", ");
                 if native_interpreters {
-                    println!("🐍 Found {}", interpreters_str);
+                    eprintln!("🐍 Found {}", interpreters_str);
                 } else {
-                    println!("🐍 Found cross compiling target {}", interpreters_str);
+                    eprintln!("🐍 Found cross compiling target {}", interpreters_str);
                 }
 
                 Ok(interpreters)
@@ -344,7 +462,7 @@ impl BuildOptions {
             BridgeModel::Cffi => {
                 let interpreter =
                     find_single_python_interpreter(bridge, interpreter, target, "cffi")?;
-                println!("🐍 Using {} to generate the cffi bindings", interpreter);
+                eprintln!("🐍 Using {} to generate the cffi bindings", interpreter);
                 Ok(vec![interpreter])
             }
             BridgeModel::Bin(None) => Ok(vec![]),
@@ -363,7 +481,7 @@ impl BuildOptions {
                     if env::var_os("PYO3_CROSS_LIB_DIR").is_some() {
                         // PYO3_CROSS_LIB_DIR should point to the `libs` directory inside base_prefix
                         // when cross compiling, so we fake a python interpreter matching it
-                        println!("⚠️  Cross-compiling is poorly supported");
+                        eprintln!("⚠️  Cross-compiling is poorly supported");
                         Ok(vec![PythonInterpreter {
                             config: InterpreterConfig {
                                 major: *major as usize,
@@ -379,12 +497,15 @@ impl BuildOptions {
                             runnable: false,
                             implmentation_name: "cpython".to_string(),
                             soabi: None,
+                            macos_framework: false,
+                            macos_link_args: None,
+                            macos_deployment_target: None,
                         }])
                     } else if let Some(interp) = interpreters.get(0) {
-                        println!("🐍 Using {} to generate to link bindings (With abi3, an interpreter is only required on windows)", interp);
+                        eprintln!("🐍 Using {} to generate to link bindings (With abi3, an interpreter is only required on windows)", interp);
                         Ok(interpreters)
                     } else if generate_import_lib {
-                        println!("🐍 Not using a specific python interpreter (Automatically generating windows import library)");
+                        eprintln!("🐍 Not using a specific python interpreter (Automatically generating windows import library)");
                         // fake a python interpreter
                         Ok(vec![PythonInterpreter {
                             config: InterpreterConfig {
@@ -401,6 +522,9 @@ impl BuildOptions {
                             runnable: false,
                             implmentation_name: "cpython".to_string(),
                             soabi: None,
+                            macos_framework: false,
+                            macos_link_args: None,
+                            macos_deployment_target: None,
                         }])
                     } else {
                         bail!("Failed to find a python interpreter");
@@ -419,15 +543,15 @@ impl BuildOptions {
                             Some(*minor as usize),
                         )
                         .unwrap_or_default();
-                        if interps.is_empty() && !self.interpreter.is_empty() {
+                        if interps.is_empty() && !self.user_interpreters.is_empty() {
                             // Print error when user supplied `--interpreter` option
                             Err(err)
                         } else {
                             Ok(interps)
                         }
                     })?;
-                    println!("🐍 Not using a specific python interpreter");
-                    if self.interpreter.is_empty() {
+                    eprintln!("🐍 Not using a specific python interpreter");
+                    if self.user_interpreters.is_empty() {
                         // Fake one to make `BuildContext::build_wheels` happy for abi3 when no cpython/pypy found on host
                         // The python interpreter config doesn't matter, as it's not used for anything
                         Ok(vec![PythonInterpreter {
@@ -445,6 +569,9 @@ impl BuildOptions {
                             runnable: false,
                             implmentation_name: "cpython".to_string(),
                             soabi: None,
+                            macos_framework: false,
+                            macos_link_args: None,
+                            macos_deployment_target: None,
                         }])
                     } else if target.cross_compiling() {
                         let mut interps = Vec::with_capacity(found_interpreters.len());
@@ -482,10 +609,135 @@ impl BuildOptions {
             }
         }
     }
+}
+
+impl BuildOptions {
+    /// Runs the pre-build hooks of `[tool.maturin.hooks]` once for the whole build of the
+    /// `formats`, before the project is resolved, so they can generate the python sources and
+    /// the data directory. The `[tool.maturin.'cfg(...)']` tables and `[tool.maturin.env]` are
+    /// those of the first target.
+    pub fn run_pre_build_hooks(&self, formats: &[&str]) -> Result<()> {
+        let (_, pyproject_file) =
+            ProjectResolver::resolve_manifest_paths(self.manifest_path.clone(), &self.cargo)?;
+        if !pyproject_file.is_file() {
+            return Ok(());
+        }
+        let mut pyproject = PyProjectToml::new(&pyproject_file)
+            .context(ConfigError::new("pyproject.toml is invalid"))?;
+        let mut targets = self.cargo.targets.clone();
+        if targets.is_empty() {
+            targets.extend(self.cargo.target.clone());
+        }
+        if targets.is_empty() {
+            targets = pyproject.targets().to_vec();
+        }
+        if targets.is_empty() {
+            targets.push(host_target_triple()?);
+        }
+        if pyproject.has_cfg_configs() {
+            pyproject.apply_cfg_configs(&Target::from_target_triple(Some(targets[0].clone()))?)?;
+        }
+        if let Some(name) = &self.cargo.maturin_profile {
+            pyproject.apply_maturin_profile(name)?;
+        }
+        let commands = match pyproject
+            .hooks()
+            .and_then(|hooks| hooks.pre_build.as_deref())
+        {
+            Some(commands) => commands,
+            None => return Ok(()),
+        };
+        run_hooks(
+            HookStage::PreBuild,
+            commands,
+            pyproject_file.parent().unwrap(),
+            &formats.join(","),
+            &targets.join(","),
+            &[],
+            &pyproject.build_env(&targets[0]),
+        )
+    }
 
     /// Tries to fill the missing metadata for a BuildContext by querying cargo and python
     pub fn into_build_context(
+        mut self,
+        release: bool,
+        strip: bool,
+        editable: bool,
+    ) -> Result<BuildContext> {
+        match self.cargo.targets.len() {
+            0 => {}
+            1 if self.cargo.target.is_none() => self.cargo.target = self.cargo.targets.pop(),
+            _ => bail!(ConfigError::new(format!(
+                "Can't build for multiple targets ({}) here, only `maturin build` and \
+                `maturin publish` support this",
+                self.cargo.targets.join(", ")
+            ))),
+        }
+        let resolver = ProjectResolver::resolve(self.manifest_path.clone(), self.cargo.clone())?;
+        self.into_build_context_with(resolver, release, strip, editable)
+    }
+
+    /// Creates one BuildContext for each target given with `--target` or, without that, in
+    /// `[tool.maturin] targets`. The project is only resolved once unless there are
+    /// `[tool.maturin.target.<triple>]` tables, which change the features for each target.
+    pub fn into_build_contexts(
+        mut self,
+        release: bool,
+        strip: bool,
+        editable: bool,
+    ) -> Result<Vec<BuildContext>> {
+        let mut targets = mem::take(&mut self.cargo.targets);
+        if targets.is_empty() {
+            targets.extend(self.cargo.target.clone());
+        }
+        self.cargo.target = targets.first().cloned();
+        let resolver = ProjectResolver::resolve(self.manifest_path.clone(), self.cargo.clone())?;
+        if targets.is_empty() {
+            if let Some(pyproject) = &resolver.pyproject_toml {
+                targets = pyproject.targets().to_vec();
+            }
+        }
+        if targets.len() <= 1 && self.cargo.target == targets.first().cloned() {
+            return Ok(vec![
+                self.into_build_context_with(resolver, release, strip, editable)?
+            ]);
+        }
+
+        let has_target_configs = resolver
+            .pyproject_toml
+            .as_ref()
+            .map(|x| x.has_target_configs())
+            .unwrap_or_default();
+        let mut build_contexts = Vec::with_capacity(targets.len());
+        for target in targets {
+            let mut build_options = self.clone();
+            build_options.cargo.target = Some(target.clone());
+            let target_resolver = if resolver.cargo_options.target.as_ref() == Some(&target) {
+                resolver.clone()
+            } else if has_target_configs {
+                ProjectResolver::resolve(
+                    build_options.manifest_path.clone(),
+                    build_options.cargo.clone(),
+                )?
+            } else {
+                let mut target_resolver = resolver.clone();
+                target_resolver.cargo_options.target = Some(target);
+                target_resolver
+            };
+            build_contexts.push(build_options.into_build_context_with(
+                target_resolver,
+                release,
+                strip,
+                editable,
+            )?);
+        }
+        Ok(build_contexts)
+    }
+
+    fn into_build_context_with(
         self,
+        resolver: ProjectResolver,
         release: bool,
         strip: bool,
         editable: bool,
@@ -497,11 +749,11 @@ impl BuildOptions {
             pyproject_toml_path,
             pyproject_toml,
             module_name,
-            metadata21,
+            mut metadata21,
             mut cargo_options,
             cargo_metadata,
             mut pyproject_toml_maturin_options,
-        } = ProjectResolver::resolve(self.manifest_path.clone(), self.cargo.clone())?;
+        } = resolver;
         let pyproject = pyproject_toml.as_ref();
 
         let bridge = find_bridge(
@@ -517,13 +769,17 @@ impl BuildOptions {
         )?;
 
         if !bridge.is_bin() && module_name.contains('-') {
-            bail!(
+            bail!(ConfigError::new(
                 "The module name must not contains a minus \
                  (Make sure you have set an appropriate [lib] name in your Cargo.toml)"
-            );
+            ));
         }
 
-        let mut target_triple = self.target.clone();
+        if let BridgeModel::BindingsAbi3(major, minor) = bridge {
+            apply_abi3_requires_python(&mut metadata21, major, minor);
+        }
+
+        let mut target_triple = cargo_options.target.clone();
 
         let mut universal2 = self.universal2;
         // Also try to determine universal2 from ARCHFLAGS environment variable
@@ -551,23 +807,40 @@ impl BuildOptions {
             }
         };
 
-        let target = Target::from_target_triple(target_triple)?;
+        let mut target = Target::from_target_triple(target_triple)?;
+        // The environment variable takes precedence over pyproject.toml, only the flag over both
+        let macos_deployment_target = match self.macos_deployment_target.clone() {
+            Some(deployment_target) => Some(deployment_target),
+            None if env::var_os("MACOSX_DEPLOYMENT_TARGET").is_some() => None,
+            None => pyproject.and_then(|x| {
+                if x.macos_deployment_target().is_some() {
+                    pyproject_toml_maturin_options.push("macos-deployment-target");
+                }
+                x.macos_deployment_target().map(ToString::to_string)
+            }),
+        };
+        target.set_macos_deployment_target(macos_deployment_target);
+        if target.is_macos() {
+            // Fail early on an invalid version
+            target.macos_deployment_targets(None, universal2)?;
+        }
 
-        let wheel_dir = match self.out {
-            Some(ref dir) => dir.clone(),
-            None => PathBuf::from(&cargo_metadata.target_directory).join("wheels"),
+        let wheel_dir = match (&self.out, &self.build_dir) {
+            (Some(dir), _) => dir.clone(),
+            (None, Some(build_dir)) => build_dir.join("wheels"),
+            (None, None) => PathBuf::from(&cargo_metadata.target_directory).join("wheels"),
         };
 
         let generate_import_lib = is_generating_import_lib(&cargo_metadata)?;
-        let interpreter = if self.find_interpreter {
+        let interpreter_search = if self.find_interpreter {
             // Auto-detect interpreters
-            self.find_interpreters(
-                &bridge,
-                &[],
-                &target,
-                get_min_python_minor(&metadata21),
+            InterpreterSearch {
+                find_interpreter: true,
+                user_interpreters: self.interpreter.clone(),
+                interpreters: Vec::new(),
+                min_python_minor: get_min_python_minor(&metadata21),
                 generate_import_lib,
-            )?
+            }
         } else {
             // User given list of interpreters
             let interpreter = if self.interpreter.is_empty() && !target.cross_compiling() {
@@ -582,7 +855,13 @@ impl BuildOptions {
             } else {
                 self.interpreter.clone()
             };
-            self.find_interpreters(&bridge, &interpreter, &target, None, generate_import_lib)?
+            InterpreterSearch {
+                find_interpreter: false,
+                user_interpreters: self.interpreter.clone(),
+                interpreters: interpreter,
+                min_python_minor: None,
+                generate_import_lib,
+            }
         };
 
         if cargo_options.args.is_empty() {
@@ -597,6 +876,24 @@ impl BuildOptions {
         let strip = pyproject.map(|x| x.strip()).unwrap_or_default() || strip;
         let skip_auditwheel =
             pyproject.map(|x| x.skip_auditwheel()).unwrap_or_default() || self.skip_auditwheel;
+        let fallback_to_linux =
+            pyproject.map(|x| x.fallback_to_linux()).unwrap_or_default() || self.fallback_to_linux;
+        let repair = pyproject.map(|x| x.repair()).unwrap_or_default() || self.repair;
+        let use_container =
+            pyproject.map(|x| x.container()).unwrap_or_default() || self.use_container;
+        if use_container && (target.is_msvc() || target.is_macos()) {
+            bail!(ConfigError::new(format!(
+                "cross has no containers for {}, so it can't be built in a container",
+                target.target_triple()
+            )));
+        }
+        // The build script of rust-cpython runs the interpreter, which only exists on the host
+        if use_container && bridge.is_bindings("rust-cpython") {
+            bail!(ConfigError::new(
+                "rust-cpython bindings can't be built in a container, \
+                only pyo3 gets the configuration of the interpreter from a file"
+            ));
+        }
         let platform_tags = if self.platform_tag.is_empty() {
             let compatibility = pyproject
                 .and_then(|x| {
@@ -631,7 +928,25 @@ impl BuildOptions {
             self.platform_tag
         };
 
+        let extra_policies = match pyproject.and_then(|x| x.extra_policy()) {
+            Some(extra_policy) => {
+                let extra_policy = pyproject_toml_path.parent().unwrap().join(extra_policy);
+                Policy::load_extra_policies(&extra_policy)?
+            }
+            None => Vec::new(),
+        };
         for platform_tag in &platform_tags {
+            // Any lowercase name parses as custom tag, so this catches typos
+            if platform_tag.is_custom()
+                && Policy::from_platform_tag(platform_tag, &extra_policies).is_none()
+            {
+                bail!(ConfigError::new(format!(
+                    "Unknown platform tag `{}`, expected a manylinux or musllinux tag, `linux` \
+                    or a custom tag with a policy in the file of `[tool.maturin.audit] extra-policy` \
+                    in pyproject.toml",
+                    platform_tag
+                )));
+            }
             if !platform_tag.is_supported() {
                 eprintln!(
                     "⚠️  Warning: {} is unsupported by the Rust compiler.",
@@ -644,18 +959,18 @@ impl BuildOptions {
             BridgeModel::Bin(None) => {
                 // Only support two different kind of platform tags when compiling to musl target without any binding crates
                 if platform_tags.iter().any(|tag| tag.is_musllinux()) && !target.is_musl_target() {
-                    bail!(
+                    bail!(ConfigError::new(format!(
                         "Cannot mix musllinux and manylinux platform tags when compiling to {}",
                         target.target_triple()
-                    );
+                    )));
                 }
 
                 #[allow(clippy::comparison_chain)]
                 if platform_tags.len() > 2 {
-                    bail!(
+                    bail!(ConfigError::new(format!(
                         "Expected only one or two platform tags but found {}",
                         platform_tags.len()
-                    );
+                    )));
                 } else if platform_tags.len() == 2 {
                     // The two platform tags can't be the same kind
                     let tag_types = platform_tags
@@ -663,26 +978,48 @@ impl BuildOptions {
                         .map(|tag| tag.is_musllinux())
                         .collect::<HashSet<_>>();
                     if tag_types.len() == 1 {
-                        bail!(
+                        bail!(ConfigError::new(format!(
                             "Expected only one platform tag but found {}",
                             platform_tags.len()
-                        );
+                        )));
                     }
                 }
             }
             _ => {
                 if platform_tags.len() > 1 {
-                    bail!(
+                    bail!(ConfigError::new(format!(
                         "Expected only one platform tag but found {}",
                         platform_tags.len()
-                    );
+                    )));
                 }
             }
         }
 
         // linux tag can not be mixed with manylinux and musllinux tags
         if platform_tags.len() > 1 && platform_tags.iter().any(|tag| !tag.is_portable()) {
-            bail!("Cannot mix linux and manylinux/musllinux platform tags",);
+            bail!(ConfigError::new(
+                "Cannot mix linux and manylinux/musllinux platform tags"
+            ));
+        }
+
+        let build_tag = match self.build_tag {
+            Some(build_tag) => Some(build_tag),
+            None => pyproject.and_then(|x| x.build_tag()).map(|build_tag| {
+                pyproject_toml_maturin_options.push("build-tag");
+                build_tag.to_string()
+            }),
+        };
+        if let Some(build_tag) = &build_tag {
+            validate_build_tag(build_tag)?;
+        }
+
+        if let Some(dist_name) = &self.dist_name {
+            validate_dist_name(dist_name)?;
+            eprintln!(
+                "🏷  Using the distribution name `{}` instead of `{}`",
+                dist_name, metadata21.name
+            );
+            metadata21.name = dist_name.clone();
         }
 
         if !pyproject_toml_maturin_options.is_empty() {
@@ -692,14 +1029,91 @@ impl BuildOptions {
             );
         }
 
-        let target_dir = self
-            .cargo
-            .target_dir
-            .clone()
-            .unwrap_or_else(|| cargo_metadata.target_directory.clone().into_std_path_buf());
+        let target_dir = match (&self.cargo.target_dir, &self.build_dir) {
+            (Some(target_dir), _) => target_dir.clone(),
+            (None, Some(build_dir)) => {
+                let target_dir = build_dir.join("target");
+                cargo_options.target_dir = Some(target_dir.clone());
+                target_dir
+            }
+            (None, None) => cargo_metadata.target_directory.clone().into_std_path_buf(),
+        };
         let crate_name = cargo_toml.package.name;
+        let mut rustflags = pyproject
+            .map(|x| x.rustflags().to_vec())
+            .unwrap_or_default();
+        if !rustflags.is_empty() {
+            pyproject_toml_maturin_options.push("rustflags");
+        }
+        let target_config = pyproject
+            .and_then(|x| x.target_config(target.target_triple()))
+            .cloned()
+            .unwrap_or_default();
+        if target_config.linker.is_some() {
+            pyproject_toml_maturin_options.push("linker");
+        }
+        let mut link_args = target_config.link_args.unwrap_or_default();
+        if !link_args.is_empty() {
+            pyproject_toml_maturin_options.push("link-args");
+        }
+        let mut build_env = pyproject
+            .map(|x| x.build_env(target.target_triple()))
+            .unwrap_or_default();
+        if !build_env.is_empty() {
+            pyproject_toml_maturin_options.push("env");
+        }
+        let lto = pyproject.and_then(|x| x.lto()).cloned().unwrap_or_default();
+        if lto != LtoConfig::default() {
+            pyproject_toml_maturin_options.push("lto");
+        }
+        let lto_options = lto_options(
+            &lto,
+            &target,
+            self.zig,
+            cargo_profile(&cargo_options, release),
+        )?;
+        rustflags.extend(lto_options.rustflags);
+        let linker = target_config.linker.or(lto_options.linker);
+        link_args.extend(lto_options.link_args);
+        // `[tool.maturin.env]` takes precedence
+        for (key, value) in lto_options.env {
+            build_env.entry(key).or_insert(value);
+        }
+        // Only the wheels get the requirements of the target, not the source distribution
+        let mut target_requires_dist = Vec::new();
+        for dependency in pyproject
+            .map(|x| x.target_dependencies(&target))
+            .transpose()?
+            .unwrap_or_default()
+        {
+            if !metadata21.requires_dist.contains(&dependency) {
+                metadata21.requires_dist.push(dependency.clone());
+                target_requires_dist.push(dependency);
+            }
+        }
+        // The requirements of the target are only added to the wheels of matching targets
+        for warning in check_platform_markers(
+            metadata21
+                .requires_dist
+                .iter()
+                .filter(|x| !target_requires_dist.contains(x)),
+            target.target_os(),
+        ) {
+            eprintln!("⚠️  Warning: {}", warning);
+        }
+        let record_stats = stats_enabled(pyproject.map(|x| x.stats()).unwrap_or_default());
+        let artifact_cache = cache_enabled(pyproject.map_or(false, |x| x.cache()));
+        let vendor = pyproject.map(|x| x.sdist_vendor()).unwrap_or_default();
+        let sdist_from_git =
+            self.sdist_from_git || pyproject.map(|x| x.sdist_from_git()).unwrap_or_default();
+        let include_cargo_lock = match self.include_cargo_lock {
+            Some(include_cargo_lock) => include_cargo_lock,
+            None => pyproject
+                .map(|x| x.include_cargo_lock())
+                .unwrap_or_default(),
+        };
 
-        Ok(BuildContext {
+        let mut build_context = BuildContext {
             target,
             bridge,
             project_layout,
@@ -714,29 +1128,96 @@ impl BuildOptions {
             release,
             strip,
             skip_auditwheel,
+            fallback_to_linux,
+            repair,
             zig: self.zig,
+            use_container,
             platform_tag: platform_tags,
-            interpreter,
+            extra_policies,
+            interpreter: OnceCell::new(),
+            interpreter_search,
             cargo_metadata,
             universal2,
             editable,
+            build_tag,
             cargo_options,
-        })
+            record_stats,
+            artifact_cache,
+            rustflags,
+            linker,
+            link_args,
+            lto,
+            env: build_env,
+            target_requires_dist,
+            vendor,
+            sdist_from_git,
+            include_cargo_lock,
+            skip_existing: false,
+            source_hash: OnceCell::new(),
+            selected_builds: None,
+        };
+        build_context.run_dynamic_hooks()?;
+        Ok(build_context)
+    }
+}
+
+/// The cargo profile of the build: `--profile`, otherwise `release` or `dev` for `--release`
+pub(crate) fn cargo_profile(cargo_options: &CargoOptions, release: bool) -> &str {
+    match &cargo_options.profile {
+        Some(profile) => profile.as_str(),
+        None if release => "release",
+        None => "dev",
     }
 }
 
+/// Checks that the build tag is a valid wheel filename component as specified in
+/// <https://peps.python.org/pep-0427/#file-name-convention>
+fn validate_build_tag(build_tag: &str) -> Result<()> {
+    if !build_tag.starts_with(|c: char| c.is_ascii_digit()) {
+        bail!(ConfigError::new(format!(
+            "Invalid build tag '{}', the build tag must start with a digit",
+            build_tag
+        )));
+    }
+    if !build_tag
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    {
+        bail!(ConfigError::new(format!(
+            "Invalid build tag '{}', the build tag may only contain ASCII letters, digits, `_` and `.`",
+            build_tag
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that the distribution name is a valid project name as specified in
+/// <https://packaging.python.org/en/latest/specifications/name-normalization/>
+fn validate_dist_name(dist_name: &str) -> Result<()> {
+    let is_alphanumeric = |c: char| c.is_ascii_alphanumeric();
+    if !dist_name.starts_with(is_alphanumeric)
+        || !dist_name.ends_with(is_alphanumeric)
+        || !dist_name
+            .chars()
+            .all(|c| is_alphanumeric(c) || c == '-' || c == '_' || c == '.')
+    {
+        bail!(ConfigError::new(format!(
+            "Invalid distribution name '{}', the name may only contain ASCII letters, digits, \
+            `-`, `_` and `.` and must start and end with a letter or digit",
+            dist_name
+        )));
+    }
+    Ok(())
+}
+
 /// Uses very simple PEP 440 subset parsing to determine the
 /// minimum supported python minor version for interpreter search
 fn get_min_python_minor(metadata21: &Metadata21) -> Option<usize> {
     if let Some(requires_python) = &metadata21.requires_python {
-        let regex = Regex::new(r#">=3\.(\d+)(?:\.\d)?"#).unwrap();
-        if let Some(captures) = regex.captures(requires_python) {
-            let min_python_minor = captures[1]
-                .parse::<usize>()
-                .expect("Regex must only match usize");
+        if let Some(min_python_minor) = parse_min_python_minor(requires_python) {
             Some(min_python_minor)
         } else {
-            println!(
+            eprintln!(
                 "⚠️ Couldn't parse the value of requires-python, \
                     not taking it into account when searching for python interpreter. \
                     Note: Only `>=3.x.y` is currently supported."
@@ -748,6 +1229,35 @@ fn get_min_python_minor(metadata21: &Metadata21) -> Option<usize> {
     }
 }
 
+/// Returns the minor version of a `>=3.x.y` lower bound in requires-python
+fn parse_min_python_minor(requires_python: &str) -> Option<usize> {
+    let regex = Regex::new(r#">=3\.(\d+)(?:\.\d)?"#).unwrap();
+    regex.captures(requires_python).map(|captures| {
+        captures[1]
+            .parse::<usize>()
+            .expect("Regex must only match usize")
+    })
+}
+
+/// abi3 wheels don't work on python versions older than their minimum version, so
+/// `Requires-Python` defaults to that version and we warn when it allows older versions
+pub(crate) fn apply_abi3_requires_python(metadata21: &mut Metadata21, major: u8, minor: u8) {
+    match &metadata21.requires_python {
+        None => metadata21.requires_python = Some(format!(">={}.{}", major, minor)),
+        Some(requires_python) => {
+            if let Some(min_python_minor) = parse_min_python_minor(requires_python) {
+                if min_python_minor < minor as usize {
+                    eprintln!(
+                        "⚠️  Warning: requires-python `{}` allows python 3.{}, but the abi3 wheel \
+                        only supports python {}.{} and newer",
+                        requires_python, min_python_minor, major, minor
+                    );
+                }
+            }
+        }
+    }
+}
+
 /// pyo3 supports building abi3 wheels if the unstable-api feature is not selected
 fn has_abi3(cargo_metadata: &Metadata) -> Result<Option<(u8, u8)>> {
     let resolve = cargo_metadata
@@ -777,14 +1287,14 @@ fn has_abi3(cargo_metadata: &Metadata) -> Result<Option<(u8, u8)>> {
                         ))
                     })
                     .collect::<Result<Vec<(u8, u8)>>>()
-                    .context(format!("Bogus {} cargo features", lib))?
+                    .context(ConfigError::new(format!("Bogus {} cargo features", lib)))?
                     .into_iter()
                     .min();
                 if abi3_selected && min_abi3_version.is_none() {
-                    bail!(
+                    bail!(ConfigError::new(
                         "You have selected the `abi3` feature but not a minimum version (e.g. the `abi3-py36` feature). \
                         maturin needs a minimum version feature to build abi3 wheels."
-                    )
+                    ))
                 }
                 return Ok(min_abi3_version);
             }
@@ -796,7 +1306,7 @@ fn has_abi3(cargo_metadata: &Metadata) -> Result<Option<(u8, u8)>> {
 
 /// pyo3 0.16.4+ supports building abi3 wheels without a working Python interpreter for Windows
 /// when `generate-import-lib` feature is enabled
-fn is_generating_import_lib(cargo_metadata: &Metadata) -> Result<bool> {
+pub(crate) fn is_generating_import_lib(cargo_metadata: &Metadata) -> Result<bool> {
     let resolve = cargo_metadata
         .resolve
         .as_ref()
@@ -889,10 +1399,10 @@ pub fn find_bridge(cargo_metadata: &Metadata, bridge: Option<&str>) -> Result<Br
             BridgeModel::Bin(find_bindings(&deps, &packages))
         } else {
             if !deps.contains_key(bindings) {
-                bail!(
+                bail!(ConfigError::new(format!(
                     "The bindings crate {} was not found in the dependencies list",
                     bindings
-                );
+                )));
             }
 
             BridgeModel::Bindings(bindings.to_string(), MINIMUM_PYTHON_MINOR)
@@ -908,11 +1418,13 @@ pub fn find_bridge(cargo_metadata: &Metadata, bridge: Option<&str>) -> Result<Br
     } else if targets.contains(&"bin") {
         BridgeModel::Bin(find_bindings(&deps, &packages))
     } else {
-        bail!("Couldn't detect the binding type; Please specify them with --bindings/-b")
+        bail!(ConfigError::new(
+            "Couldn't detect the binding type; Please specify them with --bindings/-b"
+        ))
     };
 
     if !(bridge.is_bindings("pyo3") || bridge.is_bindings("pyo3-ffi")) {
-        println!("🔗 Found {} bindings", bridge);
+        eprintln!("🔗 Found {} bindings", bridge);
     }
 
     for &lib in PYO3_BINDING_CRATES.iter() {
@@ -929,13 +1441,13 @@ pub fn find_bridge(cargo_metadata: &Metadata, bridge: Option<&str>) -> Result<Br
             }
 
             return if let Some((major, minor)) = has_abi3(cargo_metadata)? {
-                println!(
+                eprintln!(
                     "🔗 Found {} bindings with abi3 support for Python ≥ {}.{}",
                     lib, major, minor
                 );
                 Ok(BridgeModel::BindingsAbi3(major, minor))
             } else {
-                println!("🔗 Found {} bindings", lib);
+                eprintln!("🔗 Found {} bindings", lib);
                 Ok(bridge)
             };
         }
@@ -958,10 +1470,10 @@ fn find_single_python_interpreter(
     } else if interpreter.len() == 1 {
         interpreter[0].clone()
     } else {
-        bail!(
+        bail!(ConfigError::new(format!(
             "You can only specify one python interpreter for {}",
             bridge_name
-        );
+        )));
     };
 
     let interpreter = PythonInterpreter::check_executable(executable, target, bridge)
@@ -1198,6 +1710,7 @@ mod test {
     use std::path::Path;
 
     use super::*;
+    use crate::ErrorCategory;
 
     #[test]
     fn test_find_bridge_pyo3() {
@@ -1307,6 +1820,103 @@ mod test {
         ));
     }
 
+    #[test]
+    fn test_into_build_contexts() {
+        let build_options = BuildOptions {
+            cargo: CargoOptions {
+                manifest_path: Some("test-crates/hello-world/Cargo.toml".into()),
+                targets: vec![
+                    "x86_64-unknown-linux-gnu".to_string(),
+                    "aarch64-unknown-linux-gnu".to_string(),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(build_options
+            .clone()
+            .into_build_context(false, false, false)
+            .is_err());
+        let build_contexts = build_options
+            .into_build_contexts(false, false, false)
+            .unwrap();
+        let targets: Vec<_> = build_contexts
+            .iter()
+            .map(|x| x.target.target_triple())
+            .collect();
+        assert_eq!(
+            targets,
+            ["x86_64-unknown-linux-gnu", "aarch64-unknown-linux-gnu"]
+        );
+        assert_eq!(build_contexts[0].metadata21, build_contexts[1].metadata21);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_pre_build_hooks() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        fs_err::create_dir(tmp_dir.path().join("src")).unwrap();
+        fs_err::write(tmp_dir.path().join("src/lib.rs"), "").unwrap();
+        fs_err::write(
+            tmp_dir.path().join("Cargo.toml"),
+            "[package]\nname = \"foo\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        fs_err::write(
+            tmp_dir.path().join("pyproject.toml"),
+            indoc::indoc!(
+                r#"
+                [build-system]
+                requires = ["maturin>=0.14,<0.15"]
+                build-backend = "maturin"
+
+                [tool.maturin]
+                python-source = "python"
+
+                [tool.maturin.hooks]
+                pre-build = ["mkdir -p python/foo && echo $MATURIN_BUILD_FORMAT $MATURIN_TARGET >> hook.txt"]
+                "#
+            ),
+        )
+        .unwrap();
+        let build_options = BuildOptions {
+            cargo: CargoOptions {
+                manifest_path: Some(tmp_dir.path().join("Cargo.toml")),
+                targets: vec![
+                    "x86_64-unknown-linux-gnu".to_string(),
+                    "aarch64-unknown-linux-gnu".to_string(),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        build_options
+            .run_pre_build_hooks(&["sdist", "wheel"])
+            .unwrap();
+        // The hook runs once for all targets and formats and can create the python sources
+        assert_eq!(
+            fs_err::read_to_string(tmp_dir.path().join("hook.txt")).unwrap(),
+            "sdist,wheel x86_64-unknown-linux-gnu,aarch64-unknown-linux-gnu\n"
+        );
+        assert!(tmp_dir.path().join("python/foo").is_dir());
+    }
+
+    #[test]
+    fn test_interpreters_are_searched_lazily() {
+        let build_options = BuildOptions {
+            interpreter: vec!["maturin-test-no-such-python".into()],
+            cargo: CargoOptions {
+                manifest_path: Some("test-crates/pyo3-pure/Cargo.toml".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let build_context = build_options
+            .into_build_context(false, false, false)
+            .unwrap();
+        assert!(build_context.interpreters().is_err());
+    }
+
     #[test]
     fn test_old_extra_feature_args() {
         let cargo_extra_args = CargoOptions {
@@ -1367,4 +1977,45 @@ mod test {
                 .unwrap();
         assert_eq!(get_min_python_minor(&metadata21), None);
     }
+
+    #[test]
+    fn test_apply_abi3_requires_python() {
+        let mut metadata21 = Metadata21::default();
+        apply_abi3_requires_python(&mut metadata21, 3, 8);
+        assert_eq!(metadata21.requires_python.as_deref(), Some(">=3.8"));
+
+        // An explicit value is kept, even if it contradicts the abi3 version
+        let mut metadata21 = Metadata21 {
+            requires_python: Some(">=3.6, <4".to_string()),
+            ..Default::default()
+        };
+        apply_abi3_requires_python(&mut metadata21, 3, 8);
+        assert_eq!(metadata21.requires_python.as_deref(), Some(">=3.6, <4"));
+        assert_eq!(parse_min_python_minor(">=3.10.1"), Some(10));
+        assert_eq!(parse_min_python_minor("~=3.8"), None);
+    }
+
+    #[test]
+    fn test_validate_build_tag() {
+        assert!(validate_build_tag("1").is_ok());
+        assert!(validate_build_tag("2_abc").is_ok());
+        assert!(validate_build_tag("abc").is_err());
+        assert_eq!(
+            ErrorCategory::of(&validate_build_tag("abc").unwrap_err()),
+            ErrorCategory::Config
+        );
+        assert!(validate_build_tag("").is_err());
+        assert!(validate_build_tag("1-2").is_err());
+    }
+
+    #[test]
+    fn test_validate_dist_name() {
+        assert!(validate_dist_name("internal-foo").is_ok());
+        assert!(validate_dist_name("Foo.Bar_2").is_ok());
+        assert!(validate_dist_name("a").is_ok());
+        assert!(validate_dist_name("").is_err());
+        assert!(validate_dist_name("-foo").is_err());
+        assert!(validate_dist_name("foo.").is_err());
+        assert!(validate_dist_name("foo bar").is_err());
+    }
 }
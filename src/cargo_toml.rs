@@ -1,3 +1,4 @@
+use crate::ConfigError;
 use anyhow::{Context, Result};
 use fs_err as fs;
 use serde::{Deserialize, Serialize};
@@ -36,10 +37,11 @@ impl CargoToml {
             "Can't read Cargo.toml at {}",
             manifest_file.as_ref().display(),
         ))?;
-        let cargo_toml = toml_edit::easy::from_str(&contents).context(format!(
-            "Failed to parse Cargo.toml at {}",
-            manifest_file.as_ref().display()
-        ))?;
+        let cargo_toml =
+            toml_edit::easy::from_str(&contents).context(ConfigError::new(format!(
+                "Failed to parse Cargo.toml at {}",
+                manifest_file.as_ref().display()
+            )))?;
         Ok(cargo_toml)
     }
 
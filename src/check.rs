@@ -0,0 +1,824 @@
+//! Checks built distributions before uploading them like `twine check`, optionally reporting on
+//! each rule of the binary distribution format specification
+use crate::classifiers::{is_known_classifier, suggest_classifier};
+use crate::readme::{looks_like_markdown, rst_problems, validate_content_type};
+use crate::target::Os;
+use crate::verify::{parse_csv, parse_key_values, verify_distribution, WheelFilename};
+use crate::WheelReader;
+use anyhow::{bail, Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+/// The top level categories of the trove classifiers, see <https://pypi.org/classifiers/>
+const CLASSIFIER_CATEGORIES: &[&str] = &[
+    "Development Status",
+    "Environment",
+    "Framework",
+    "Intended Audience",
+    "License",
+    "Natural Language",
+    "Operating System",
+    "Programming Language",
+    "Topic",
+    "Typing",
+];
+
+/// Packages that only install or only work on some platforms, as the normalized name or a name
+/// prefix ending with `-`, the platform and a marker that selects it
+const PLATFORM_SPECIFIC_PACKAGES: &[(&str, &str, &str)] = &[
+    ("pywin32", "Windows", "sys_platform == \"win32\""),
+    ("pypiwin32", "Windows", "sys_platform == \"win32\""),
+    ("pywin32-ctypes", "Windows", "sys_platform == \"win32\""),
+    ("pywinpty", "Windows", "sys_platform == \"win32\""),
+    ("comtypes", "Windows", "sys_platform == \"win32\""),
+    ("wmi", "Windows", "sys_platform == \"win32\""),
+    ("windows-curses", "Windows", "sys_platform == \"win32\""),
+    ("pyobjc", "macOS", "sys_platform == \"darwin\""),
+    ("pyobjc-", "macOS", "sys_platform == \"darwin\""),
+    ("appnope", "macOS", "sys_platform == \"darwin\""),
+    ("pyinotify", "Linux", "sys_platform == \"linux\""),
+    ("systemd-python", "Linux", "sys_platform == \"linux\""),
+    ("evdev", "Linux", "sys_platform == \"linux\""),
+    ("uvloop", "Unix", "sys_platform != \"win32\""),
+];
+
+/// Whether a package of [PLATFORM_SPECIFIC_PACKAGES] is available on `os`
+fn is_available(platform: &str, os: Os) -> bool {
+    match platform {
+        "Windows" => os == Os::Windows,
+        "macOS" => os == Os::Macos,
+        "Linux" => os == Os::Linux,
+        "Unix" => !matches!(os, Os::Windows | Os::Emscripten | Os::Wasi),
+        _ => true,
+    }
+}
+
+/// The operating system of a wheel by its platform tag, `None` for `any` and unknown tags
+fn wheel_os(filename: &WheelFilename) -> Option<Os> {
+    let platform_tag = filename.platform_tag.as_str();
+    if platform_tag.starts_with("win") {
+        Some(Os::Windows)
+    } else if platform_tag.starts_with("macosx_") {
+        Some(Os::Macos)
+    } else if platform_tag.starts_with("linux_")
+        || platform_tag.starts_with("manylinux")
+        || platform_tag.starts_with("musllinux_")
+    {
+        Some(Os::Linux)
+    } else {
+        None
+    }
+}
+
+/// The marker variables that distinguish platforms
+const PLATFORM_MARKER_VARIABLES: &[&str] = &["sys_platform", "platform_system", "os_name"];
+
+/// The subdirectories of the `.data` directory of a wheel
+const DATA_DIRS: &[&str] = &["purelib", "platlib", "headers", "scripts", "data"];
+
+/// Check wheels and source distributions before uploading them
+#[derive(Debug, Clone, clap::Parser)]
+pub struct CheckOptions {
+    /// Report on each rule of the binary distribution format specification,
+    /// see <https://packaging.python.org/en/latest/specifications/binary-distribution-format/>
+    #[arg(long)]
+    pub spec: bool,
+    /// The wheels and source distributions to check
+    #[arg(value_name = "FILE", required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+/// The outcome of checking a single rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Status {
+    /// The rule is satisfied
+    Pass,
+    /// A recommendation of the specification isn't followed
+    Warn,
+    /// A requirement of the specification isn't met
+    Fail,
+}
+
+/// The result of checking a wheel against a single rule of the specification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RuleResult {
+    /// A short description of the rule
+    pub(crate) rule: &'static str,
+    pub(crate) status: Status,
+    /// Why the rule is not met
+    pub(crate) details: Vec<String>,
+}
+
+impl RuleResult {
+    fn new(rule: &'static str, failures: Vec<String>, warnings: Vec<String>) -> Self {
+        let status = if !failures.is_empty() {
+            Status::Fail
+        } else if !warnings.is_empty() {
+            Status::Warn
+        } else {
+            Status::Pass
+        };
+        let mut details = failures;
+        details.extend(warnings);
+        Self {
+            rule,
+            status,
+            details,
+        }
+    }
+}
+
+/// Checks the filename escaping rules
+fn check_filename(filename: &WheelFilename) -> RuleResult {
+    let mut failures = Vec::new();
+    let mut warnings = Vec::new();
+    let is_escaped = |component: &str| {
+        component
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    };
+    if !is_escaped(&filename.distribution) {
+        failures.push(format!(
+            "The distribution {} contains characters other than letters, digits, `_` and `.`",
+            filename.distribution
+        ));
+    } else if filename.distribution.contains('.')
+        || filename
+            .distribution
+            .chars()
+            .any(|c| c.is_ascii_uppercase())
+    {
+        warnings.push(format!(
+            "The distribution {} should be normalized to {}",
+            filename.distribution,
+            filename.distribution.replace('.', "_").to_lowercase()
+        ));
+    }
+    if filename.version.contains('-')
+        || !filename
+            .version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.+!".contains(c))
+    {
+        failures.push(format!("The version {} is not escaped", filename.version));
+    } else if let Some(version) = pep440::Version::parse(&filename.version) {
+        if version.normalize() != filename.version {
+            warnings.push(format!(
+                "The version {} should be normalized to {}",
+                filename.version,
+                version.normalize()
+            ));
+        }
+    }
+    if let Some(build_tag) = &filename.build_tag {
+        if !build_tag.starts_with(|c: char| c.is_ascii_digit()) {
+            failures.push(format!(
+                "The build tag {} doesn't start with a digit",
+                build_tag
+            ));
+        }
+    }
+    RuleResult::new(
+        "Filename components are escaped and normalized",
+        failures,
+        warnings,
+    )
+}
+
+/// Checks that the archive paths are relative and use forward slashes
+fn check_archive_paths(names: &[String]) -> RuleResult {
+    let mut failures = Vec::new();
+    for name in names {
+        if name.contains('\\') {
+            failures.push(format!("{} contains a backslash", name));
+        }
+        let path = Path::new(name);
+        if path
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            failures.push(format!("{} is not a plain relative path", name));
+        }
+    }
+    RuleResult::new(
+        "Archive paths are relative and use forward slashes",
+        failures,
+        Vec::new(),
+    )
+}
+
+/// Checks that there is exactly one matching `.dist-info` directory and that the `.data`
+/// directory only has the allowed subdirectories
+fn check_directories(names: &[String], filename: &WheelFilename) -> (RuleResult, RuleResult) {
+    let top_level: HashSet<&str> = names
+        .iter()
+        .filter_map(|name| name.split('/').next())
+        .collect();
+    let expected = format!("{}-{}", filename.distribution, filename.version);
+
+    let mut failures = Vec::new();
+    let mut dist_info: Vec<&&str> = top_level
+        .iter()
+        .filter(|dir| dir.ends_with(".dist-info"))
+        .collect();
+    dist_info.sort();
+    match dist_info.as_slice() {
+        [dir] if dir.eq_ignore_ascii_case(&format!("{}.dist-info", expected)) => {}
+        [dir] => failures.push(format!(
+            "The .dist-info directory {} doesn't match the filename, expected {}.dist-info",
+            dir, expected
+        )),
+        [] => failures.push("There is no .dist-info directory".to_string()),
+        dirs => failures.push(format!(
+            "There are multiple .dist-info directories: {}",
+            dirs.iter().map(|dir| **dir).collect::<Vec<_>>().join(", ")
+        )),
+    }
+    let dist_info_rule = RuleResult::new(
+        "There is exactly one {distribution}-{version}.dist-info directory",
+        failures,
+        Vec::new(),
+    );
+
+    let mut failures = Vec::new();
+    for dir in top_level.iter().filter(|dir| dir.ends_with(".data")) {
+        if !dir.eq_ignore_ascii_case(&format!("{}.data", expected)) {
+            failures.push(format!(
+                "The .data directory {} doesn't match the filename, expected {}.data",
+                dir, expected
+            ));
+        }
+        let mut subdirs: Vec<&str> = names
+            .iter()
+            .filter_map(|name| name.strip_prefix(&format!("{}/", dir)))
+            .filter_map(|name| name.split('/').next())
+            .filter(|subdir| !subdir.is_empty() && !DATA_DIRS.contains(subdir))
+            .collect();
+        subdirs.sort_unstable();
+        subdirs.dedup();
+        for subdir in subdirs {
+            failures.push(format!(
+                "{}/{} is not one of {}",
+                dir,
+                subdir,
+                DATA_DIRS.join(", ")
+            ));
+        }
+    }
+    let data_rule = RuleResult::new(
+        "The .data directory only contains purelib, platlib, headers, scripts and data",
+        failures,
+        Vec::new(),
+    );
+    (dist_info_rule, data_rule)
+}
+
+/// Checks the keys of the WHEEL file
+fn check_wheel_file(content: Option<&str>, filename: &WheelFilename) -> RuleResult {
+    let content = match content {
+        Some(content) => content,
+        None => {
+            return RuleResult::new(
+                "WHEEL has Wheel-Version, Generator, Root-Is-Purelib and Tag",
+                vec!["The WHEEL file is missing".to_string()],
+                Vec::new(),
+            )
+        }
+    };
+    let fields = parse_key_values(content);
+    let get = |key: &str| {
+        fields
+            .iter()
+            .filter(|(field, _)| *field == key)
+            .map(|(_, value)| *value)
+            .collect::<Vec<_>>()
+    };
+    let mut failures = Vec::new();
+    let mut warnings = Vec::new();
+    match get("Wheel-Version").as_slice() {
+        [version] if version.starts_with("1.") => {}
+        [version] => failures.push(format!("Unsupported Wheel-Version {}", version)),
+        _ => failures.push("There must be exactly one Wheel-Version".to_string()),
+    }
+    if get("Generator").is_empty() {
+        warnings.push("Generator is missing".to_string());
+    }
+    match get("Root-Is-Purelib").as_slice() {
+        ["true"] | ["false"] => {}
+        _ => failures.push("Root-Is-Purelib must be either true or false".to_string()),
+    }
+    let tags: HashSet<String> = get("Tag").into_iter().map(ToString::to_string).collect();
+    let mut missing: Vec<String> = filename.tags().difference(&tags).cloned().collect();
+    missing.sort();
+    for tag in missing {
+        failures.push(format!("The tag {} from the filename is missing", tag));
+    }
+    if get("Build").first().copied() != filename.build_tag.as_deref() {
+        failures.push("Build doesn't match the build tag of the filename".to_string());
+    }
+    RuleResult::new(
+        "WHEEL has Wheel-Version, Generator, Root-Is-Purelib and Tag",
+        failures,
+        warnings,
+    )
+}
+
+/// Checks the CSV quoting and the entries of RECORD
+fn check_record(
+    content: Option<&str>,
+    record_path: &str,
+    files: &HashMap<&str, &[u8]>,
+) -> (RuleResult, RuleResult) {
+    let content = match content {
+        Some(content) => content,
+        None => {
+            let missing = vec!["The RECORD file is missing".to_string()];
+            return (
+                RuleResult::new("RECORD is valid CSV", missing.clone(), Vec::new()),
+                RuleResult::new(
+                    "RECORD lists every file with a sha256 or stronger hash",
+                    missing,
+                    Vec::new(),
+                ),
+            );
+        }
+    };
+    let mut csv_failures = Vec::new();
+    let mut failures = Vec::new();
+    let mut recorded = HashSet::new();
+    let records = match parse_csv(content) {
+        Ok(records) => records,
+        Err(line) => {
+            csv_failures.push(format!("Line {} has invalid quoting", line));
+            Vec::new()
+        }
+    };
+    for (line, fields) in records {
+        if fields.len() != 3 {
+            csv_failures.push(format!(
+                "Line {} has {} fields instead of path, hash and size, \
+                paths with commas must be quoted",
+                line,
+                fields.len()
+            ));
+            continue;
+        }
+        let (path, hash, size) = (&fields[0], &fields[1], &fields[2]);
+        recorded.insert(path.clone());
+        if path == record_path {
+            if !hash.is_empty() || !size.is_empty() {
+                failures.push("The RECORD entry of RECORD itself must be empty".to_string());
+            }
+            continue;
+        }
+        match hash.split_once('=') {
+            Some(("sha256", _)) | Some(("sha384", _)) | Some(("sha512", _)) => {}
+            Some((algorithm, _)) => failures.push(format!(
+                "{} uses the hash algorithm {}, which is too weak",
+                path, algorithm
+            )),
+            None => failures.push(format!("{} has no hash", path)),
+        }
+    }
+    if !recorded.contains(record_path) {
+        failures.push("RECORD doesn't list itself".to_string());
+    }
+    let mut unrecorded: Vec<&&str> = files
+        .keys()
+        .filter(|path| {
+            !(recorded.contains(**path)
+                || path.ends_with("RECORD.jws")
+                || path.ends_with("RECORD.p7s"))
+        })
+        .collect();
+    unrecorded.sort();
+    for path in unrecorded {
+        failures.push(format!("{} is not listed", path));
+    }
+    (
+        RuleResult::new("RECORD is valid CSV", csv_failures, Vec::new()),
+        RuleResult::new(
+            "RECORD lists every file with a sha256 or stronger hash",
+            failures,
+            Vec::new(),
+        ),
+    )
+}
+
+/// Checks a wheel against the binary distribution format specification
+pub(crate) fn spec_report(path: &Path) -> Result<Vec<RuleResult>> {
+    let wheel_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context("Wheel path must be a valid UTF-8 file name")?;
+    let filename = match WheelFilename::parse(wheel_name) {
+        Some(filename) => filename,
+        None => {
+            return Ok(vec![RuleResult::new(
+                "The filename is {distribution}-{version}(-{build tag})?-{python tag}-{abi tag}-{platform tag}.whl",
+                vec![format!("{} is not a valid wheel filename", wheel_name)],
+                Vec::new(),
+            )])
+        }
+    };
+    let wheel = WheelReader::open(path)?;
+    let names: Vec<String> = wheel.names().map(ToString::to_string).collect();
+    let files = wheel.file_map();
+    let dist_info_dir = format!("{}-{}.dist-info", filename.distribution, filename.version);
+    let read_text = |name: &str| {
+        files
+            .get(format!("{}/{}", dist_info_dir, name).as_str())
+            .and_then(|content| std::str::from_utf8(content).ok())
+    };
+
+    let mut results = vec![check_filename(&filename), check_archive_paths(&names)];
+    let (dist_info_rule, data_rule) = check_directories(&names, &filename);
+    results.push(dist_info_rule);
+    let missing: Vec<String> = ["METADATA", "WHEEL", "RECORD"]
+        .iter()
+        .filter(|name| read_text(name).is_none())
+        .map(|name| format!("{}/{} is missing or not UTF-8", dist_info_dir, name))
+        .collect();
+    results.push(RuleResult::new(
+        "The .dist-info directory contains METADATA, WHEEL and RECORD",
+        missing,
+        Vec::new(),
+    ));
+    results.push(check_wheel_file(read_text("WHEEL"), &filename));
+    let (csv_rule, record_rule) = check_record(
+        read_text("RECORD"),
+        &format!("{}/RECORD", dist_info_dir),
+        &files,
+    );
+    results.push(csv_rule);
+    results.push(record_rule);
+    results.push(data_rule);
+    Ok(results)
+}
+
+/// Prints the specification report of a wheel and returns whether all rules passed
+fn print_spec_report(path: &Path) -> Result<bool> {
+    if !path.to_string_lossy().ends_with(".whl") {
+        bail!(
+            "{} is not a wheel, only wheels can be checked against the specification",
+            path.display()
+        );
+    }
+    let results = spec_report(path)?;
+    eprintln!("📋 {}", path.display());
+    for result in &results {
+        let icon = match result.status {
+            Status::Pass => "✅",
+            Status::Warn => "⚠️ ",
+            Status::Fail => "❌",
+        };
+        eprintln!("  {} {}", icon, result.rule);
+        for detail in &result.details {
+            eprintln!("      - {}", detail);
+        }
+    }
+    Ok(results.iter().all(|result| result.status != Status::Fail))
+}
+
+/// Checks the metadata for problems that make PyPI reject the upload or render the project
+/// page incorrectly, returns the errors and the warnings
+pub(crate) fn check_metadata(metadata: &python_pkginfo::Metadata) -> (Vec<String>, Vec<String>) {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    if metadata.name.is_empty() {
+        errors.push("The Name field is missing".to_string());
+    }
+    match pep440::Version::parse(&metadata.version) {
+        Some(version) => {
+            if !version.local.is_empty() {
+                errors.push(format!(
+                    "The version {} is a local version, which PyPI rejects",
+                    metadata.version
+                ));
+            }
+            if version.normalize() != metadata.version {
+                warnings.push(format!(
+                    "The version {} is not normalized, it should be {}",
+                    metadata.version,
+                    version.normalize()
+                ));
+            }
+        }
+        None => errors.push(format!(
+            "The version {} is not a valid PEP 440 version",
+            metadata.version
+        )),
+    }
+
+    match &metadata.summary {
+        Some(summary) if summary.contains('\n') => {
+            errors.push("The summary must be a single line".to_string())
+        }
+        Some(summary) if summary.chars().count() > 512 => {
+            errors.push("The summary must be at most 512 characters long".to_string())
+        }
+        Some(_) => {}
+        None => warnings.push("The summary is missing".to_string()),
+    }
+
+    match &metadata.description {
+        Some(description) => {
+            let mime = match &metadata.description_content_type {
+                Some(content_type) => match validate_content_type(content_type) {
+                    Ok(mime) => mime,
+                    Err(err) => {
+                        errors.push(err.to_string());
+                        ""
+                    }
+                },
+                None => {
+                    warnings.push(
+                        "Description-Content-Type is missing, PyPI renders the long description \
+                        as reStructuredText"
+                            .to_string(),
+                    );
+                    "text/x-rst"
+                }
+            };
+            if mime == "text/x-rst" {
+                for problem in rst_problems(description) {
+                    errors.push(format!(
+                        "The long description won't render on PyPI: {}",
+                        problem
+                    ));
+                }
+                if looks_like_markdown(description) {
+                    warnings.push(
+                        "The long description is rendered as reStructuredText, \
+                        but looks like markdown"
+                            .to_string(),
+                    );
+                }
+            }
+        }
+        None => warnings.push("The long description is missing".to_string()),
+    }
+
+    for classifier in &metadata.classifiers {
+        let parts: Vec<&str> = classifier.split(" :: ").map(str::trim).collect();
+        if parts.len() < 2 || parts.iter().any(|part| part.is_empty()) {
+            errors.push(format!("Invalid classifier \"{}\"", classifier));
+        } else if parts[0] == "Private" {
+            errors.push(format!(
+                "PyPI rejects the private classifier \"{}\"",
+                classifier
+            ));
+        } else if !CLASSIFIER_CATEGORIES.contains(&parts[0]) {
+            errors.push(format!(
+                "The classifier \"{}\" has the unknown category {}",
+                classifier, parts[0]
+            ));
+        } else if !is_known_classifier(classifier) {
+            match suggest_classifier(classifier) {
+                Some(suggestion) => errors.push(format!(
+                    "Unknown classifier \"{}\", did you mean \"{}\"?",
+                    classifier, suggestion
+                )),
+                None => errors.push(format!("Unknown classifier \"{}\"", classifier)),
+            }
+        }
+    }
+    (errors, warnings)
+}
+
+/// Warns about requirements of wheels for `os` on packages that only exist on other platforms,
+/// such as `pywin32` in a linux wheel, that have no environment marker restricting them to their
+/// platform, since installing the wheel fails
+pub(crate) fn check_platform_markers<'a>(
+    requires_dist: impl IntoIterator<Item = &'a String>,
+    os: Os,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for requirement in requires_dist {
+        let (specifier, marker) = match requirement.split_once(';') {
+            Some((specifier, marker)) => (specifier, marker),
+            None => (requirement.as_str(), ""),
+        };
+        let name = specifier
+            .trim()
+            .split(|c: char| !(c.is_ascii_alphanumeric() || "-_.".contains(c)))
+            .next()
+            .unwrap_or_default()
+            .to_lowercase()
+            .replace(['_', '.'], "-");
+        let platform_specific = PLATFORM_SPECIFIC_PACKAGES.iter().find(|(package, ..)| {
+            if package.ends_with('-') {
+                name.starts_with(package)
+            } else {
+                name == *package
+            }
+        });
+        if let Some((_, platform, example)) = platform_specific {
+            if !is_available(platform, os)
+                && !PLATFORM_MARKER_VARIABLES
+                    .iter()
+                    .any(|variable| marker.contains(variable))
+            {
+                warnings.push(format!(
+                    "{} is only available on {}, but the requirement `{}` also applies to the \
+                    {} wheels, add a marker such as `; {}`",
+                    name,
+                    platform,
+                    requirement.trim(),
+                    os,
+                    example
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// Checks that PyPI accepts the platform tag of the wheel
+fn check_tags(filename: &WheelFilename) -> Vec<String> {
+    filename
+        .platform_tag
+        .split('.')
+        .filter(|platform_tag| platform_tag.starts_with("linux_"))
+        .map(|platform_tag| {
+            format!(
+                "PyPI rejects the platform tag {}, build with a manylinux or musllinux \
+                compatibility instead",
+                platform_tag
+            )
+        })
+        .collect()
+}
+
+/// Runs the integrity, metadata and tag checks, returns the errors and the warnings
+fn distribution_problems(path: &Path) -> Result<(Vec<String>, Vec<String>)> {
+    let mut errors = verify_distribution(path)?;
+    let distribution = python_pkginfo::Distribution::new(path)
+        .with_context(|| format!("Failed to read the metadata of {}", path.display()))?;
+    let (metadata_errors, mut warnings) = check_metadata(distribution.metadata());
+    errors.extend(metadata_errors);
+    if let Some(filename) = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(WheelFilename::parse)
+    {
+        errors.extend(check_tags(&filename));
+        // Source distributions may be built anywhere, the requirements of a wheel only need to
+        // install on its platform
+        if let Some(os) = wheel_os(&filename) {
+            warnings.extend(check_platform_markers(
+                &distribution.metadata().requires_dist,
+                os,
+            ));
+        }
+    }
+    Ok((errors, warnings))
+}
+
+/// Checks the given distributions and fails if any of them has problems
+pub fn check(options: &CheckOptions) -> Result<()> {
+    let mut failed = 0;
+    for path in &options.files {
+        let passed = if options.spec {
+            print_spec_report(path)?
+        } else {
+            let (errors, warnings) = distribution_problems(path)
+                .with_context(|| format!("Failed to check {}", path.display()))?;
+            if errors.is_empty() {
+                eprintln!("✅ {} passed all checks", path.display());
+            } else {
+                eprintln!("❌ {} has {} problem(s):", path.display(), errors.len());
+                for error in &errors {
+                    eprintln!("  - {}", error);
+                }
+            }
+            for warning in &warnings {
+                eprintln!("  ⚠️  Warning: {}", warning);
+            }
+            errors.is_empty()
+        };
+        if !passed {
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        bail!(
+            "{} of {} distribution(s) failed the checks",
+            failed,
+            options.files.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::module_writer::{test_metadata, test_wheel_writer};
+    use crate::ModuleWriter;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_check_metadata() {
+        let metadata = python_pkginfo::Metadata::parse(
+            b"Metadata-Version: 2.1\n\
+            Name: foo\n\
+            Version: 1.0.0+local\n\
+            Summary: Foo\n\
+            Classifier: Private :: Do Not Upload\n\
+            Classifier: Programming Language :: Rust\n\
+            Classifier: Languages :: Rust\n\
+            Classifier: Topic :: Software Development :: Libraries :: Python Module\n\
+            Description-Content-Type: text/x-rst\n\
+            \n\
+            A long title\n\
+            =====\n",
+        )
+        .unwrap();
+        let (errors, warnings) = check_metadata(&metadata);
+        assert_eq!(
+            errors,
+            [
+                "The version 1.0.0+local is a local version, which PyPI rejects",
+                "The long description won't render on PyPI: line 2: Title underline too short",
+                "PyPI rejects the private classifier \"Private :: Do Not Upload\"",
+                "The classifier \"Languages :: Rust\" has the unknown category Languages",
+                "Unknown classifier \"Topic :: Software Development :: Libraries :: Python Module\", \
+                did you mean \"Topic :: Software Development :: Libraries :: Python Modules\"?",
+            ]
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_check_platform_markers() {
+        let requires_dist = [
+            "pywin32>=305".to_string(),
+            "pywin32-ctypes; sys_platform == 'win32'".to_string(),
+            "PyObjC_Framework.Cocoa ; python_version >= '3.8'".to_string(),
+            "uvloop; platform_system != 'Windows'".to_string(),
+            "cffi>=1.15".to_string(),
+        ];
+        assert_eq!(
+            check_platform_markers(&requires_dist, Os::Linux),
+            [
+                "pywin32 is only available on Windows, but the requirement `pywin32>=305` \
+                also applies to the Linux wheels, add a marker such as `; sys_platform == \"win32\"`",
+                "pyobjc-framework-cocoa is only available on macOS, but the requirement \
+                `PyObjC_Framework.Cocoa ; python_version >= '3.8'` also applies to the Linux \
+                wheels, add a marker such as `; sys_platform == \"darwin\"`",
+            ]
+        );
+        // Projects that only support windows don't need markers in their windows wheels
+        assert_eq!(check_platform_markers(&requires_dist, Os::Windows).len(), 1);
+        assert!(check_platform_markers(&requires_dist[..1], Os::Windows).is_empty());
+    }
+
+    #[test]
+    fn test_check_tags() {
+        let filename = WheelFilename::parse("foo-0.1.0-cp37-abi3-linux_x86_64.whl").unwrap();
+        assert_eq!(check_tags(&filename).len(), 1);
+        let filename =
+            WheelFilename::parse("foo-0.1.0-cp37-abi3-manylinux_2_17_x86_64.whl").unwrap();
+        assert!(check_tags(&filename).is_empty());
+    }
+
+    #[test]
+    fn test_spec_report() -> Result<()> {
+        let metadata = test_metadata();
+        let tmp_dir = TempDir::new()?;
+        let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+        writer.add_bytes("foo/__init__.py", b"a = 1")?;
+        let wheel_path = writer.finish()?;
+        let results = spec_report(&wheel_path)?;
+        assert!(results.iter().all(|result| result.status == Status::Pass));
+
+        // An invalid .data subdirectory that is also missing in RECORD
+        let file = fs_err::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&wheel_path)?;
+        let mut zip = zip::ZipWriter::new_append(file)?;
+        zip.start_file(
+            "foo-0.1.0.data/lib/a.txt",
+            zip::write::FileOptions::default(),
+        )?;
+        zip.write_all(b"a")?;
+        zip.finish()?;
+        let failed: Vec<&str> = spec_report(&wheel_path)?
+            .into_iter()
+            .filter(|result| result.status == Status::Fail)
+            .map(|result| result.rule)
+            .collect();
+        assert_eq!(
+            failed,
+            [
+                "RECORD lists every file with a sha256 or stronger hash",
+                "The .data directory only contains purelib, platlib, headers, scripts and data"
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+//! Validates trove classifiers against the list PyPI accepts, see <https://pypi.org/classifiers/>
+use crate::data::{read_refreshed, Dataset};
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+
+/// The known trove classifiers, one per line, vendored from the `trove-classifiers` package and
+/// extended by the ones from `maturin self update-data`
+static CLASSIFIERS: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    let mut classifiers: HashSet<&'static str> = include_str!("classifiers.txt").lines().collect();
+    if let Some(refreshed) = read_refreshed(Dataset::Classifiers) {
+        classifiers.extend(Box::leak(refreshed.into_boxed_str()).lines());
+    }
+    classifiers
+});
+
+/// Whether PyPI knows the classifier. Private classifiers (`Private :: ...`) are never known,
+/// they exist to make PyPI reject the upload.
+pub(crate) fn is_known_classifier(classifier: &str) -> bool {
+    CLASSIFIERS.contains(classifier)
+}
+
+/// Returns the known classifier closest to `classifier`, if there is one that is close enough
+/// to be a likely typo
+pub(crate) fn suggest_classifier(classifier: &str) -> Option<&'static str> {
+    let lowercase = classifier.to_lowercase();
+    // Allow roughly one typo per word, but never suggest something entirely different
+    let max_distance = (classifier.len() / 6).max(2);
+    CLASSIFIERS
+        .iter()
+        .map(|known| (levenshtein(&lowercase, &known.to_lowercase()), *known))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, known)| known)
+}
+
+/// Warns about the classifiers PyPI doesn't know, for the commands that don't build
+/// distributions to upload
+pub fn warn_unknown_classifiers(classifiers: &[String]) {
+    if let Err(err) = check_classifiers(classifiers) {
+        eprintln!("⚠️  Warning: {}", err);
+    }
+}
+
+/// Fails if any of the classifiers is unknown to PyPI, which would otherwise only be noticed
+/// when the upload is rejected. Private classifiers are allowed, since they are meant to prevent
+/// accidental uploads.
+pub fn check_classifiers(classifiers: &[String]) -> Result<()> {
+    let unknown: Vec<String> = classifiers
+        .iter()
+        .filter(|classifier| !classifier.starts_with("Private ::"))
+        .filter(|classifier| !is_known_classifier(classifier))
+        .map(|classifier| match suggest_classifier(classifier) {
+            Some(suggestion) => format!("  - \"{}\", did you mean \"{}\"?", classifier, suggestion),
+            None => format!("  - \"{}\"", classifier),
+        })
+        .collect();
+    if !unknown.is_empty() {
+        bail!(
+            "PyPI will reject the upload because of unknown classifiers:\n{}\n\
+            See https://pypi.org/classifiers/ for the list of valid classifiers",
+            unknown.join("\n")
+        );
+    }
+    Ok(())
+}
+
+/// The number of single character insertions, deletions and substitutions to turn `a` into `b`
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_classifiers() {
+        let valid = [
+            "Programming Language :: Rust".to_string(),
+            "Programming Language :: Python :: Implementation :: CPython".to_string(),
+            "License :: OSI Approved :: MIT License".to_string(),
+            "Private :: Do Not Upload".to_string(),
+        ];
+        check_classifiers(&valid).unwrap();
+
+        let invalid = [
+            "Programming Language :: Rust".to_string(),
+            "Licence :: OSI Approved :: MIT License".to_string(),
+            "Topic :: Something Made Up".to_string(),
+        ];
+        let err = check_classifiers(&invalid).unwrap_err().to_string();
+        assert!(err.contains(
+            "\"Licence :: OSI Approved :: MIT License\", did you mean \
+            \"License :: OSI Approved :: MIT License\"?"
+        ));
+        assert!(err.contains("  - \"Topic :: Something Made Up\"\n"));
+        assert!(!err.contains("Programming Language :: Rust"));
+    }
+
+    #[test]
+    fn test_suggest_classifier() {
+        assert_eq!(
+            suggest_classifier("Development Status :: 5 - Production/stable"),
+            Some("Development Status :: 5 - Production/Stable")
+        );
+        assert_eq!(
+            suggest_classifier("Operating System :: OS independent"),
+            Some("Operating System :: OS Independent")
+        );
+        assert_eq!(suggest_classifier("Rust"), None);
+    }
+}
@@ -1,22 +1,50 @@
 use crate::build_context::BridgeModel;
-use crate::target::{Arch, RUST_1_64_0};
+use crate::import_lib::generate_import_lib;
+use crate::lto::check_clang;
+use crate::stats;
+use crate::target::{Arch, RUST_1_63_0, RUST_1_64_0};
 use crate::{BuildContext, PlatformTag, PythonInterpreter, Target};
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{anyhow, Context, Result};
 use fat_macho::FatWriter;
 use fs_err::{self as fs, File};
 use std::collections::HashMap;
 use std::env;
-use std::io::{BufReader, Read};
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
-use std::process::Stdio;
+use std::process::{ChildStderr, Command, ExitStatus, Stdio};
 use std::str;
+use std::thread;
+use thiserror::Error;
+use tracing::{debug, Level};
 
 /// The first version of pyo3 that supports building Windows abi3 wheel
 /// without `PYO3_NO_PYTHON` environment variable
 const PYO3_ABI3_NO_PYTHON_VERSION: (u64, u64, u64) = (0, 16, 4);
 
 /// crate types excluding `bin`, `cdylib` and `proc-macro`
-const LIB_CRATE_TYPES: [&str; 4] = ["lib", "dylib", "rlib", "staticlib"];
+pub(crate) const LIB_CRATE_TYPES: [&str; 4] = ["lib", "dylib", "rlib", "staticlib"];
+
+/// The MinGW runtime libraries a windows-gnu build can link dynamically. They're neither part of
+/// windows nor installed with python.
+const MINGW_RUNTIME_DLLS: [&str; 4] = [
+    "libgcc_s_seh-1.dll",
+    "libgcc_s_dw2-1.dll",
+    "libwinpthread-1.dll",
+    "libstdc++-6.dll",
+];
+
+/// The target of the log events with the output of cargo, which is already on the console
+pub const CARGO_LOG_TARGET: &str = "cargo";
+
+/// cargo exited with an error while building the crate
+#[derive(Error, Debug)]
+#[error(r#"Cargo build finished with "{status}": `{command}`"#)]
+pub struct CargoBuildError {
+    status: ExitStatus,
+    command: String,
+}
 
 /// A cargo build artifact
 #[derive(Debug, Clone)]
@@ -36,6 +64,20 @@ pub fn compile(
     python_interpreter: Option<&PythonInterpreter>,
     bindings_crate: &BridgeModel,
 ) -> Result<Vec<HashMap<String, BuildArtifact>>> {
+    let targets = binding_targets(context, bindings_crate);
+    if context.target.is_macos() && context.universal2 {
+        compile_universal2(context, python_interpreter, bindings_crate, &targets)
+    } else {
+        compile_targets(context, python_interpreter, bindings_crate, &targets)
+    }
+}
+
+/// The cargo targets of the root package that get built, the binaries for bin bindings and
+/// otherwise the library
+fn binding_targets<'a>(
+    context: &'a BuildContext,
+    bindings_crate: &BridgeModel,
+) -> Vec<&'a cargo_metadata::Target> {
     let root_pkg = context.cargo_metadata.root_package().unwrap();
     let mut targets: Vec<_> = root_pkg
         .targets
@@ -58,10 +100,285 @@ pub fn compile(
             targets.push(target);
         }
     }
-    if context.target.is_macos() && context.universal2 {
-        compile_universal2(context, python_interpreter, bindings_crate, &targets)
+    targets
+}
+
+/// Describes the `cargo rustc` invocations of [compile] without running them: the command line,
+/// the environment variables maturin sets and the path of the artifact. For universal2 builds, it
+/// describes the build for the native architecture.
+pub fn build_plan(
+    context: &BuildContext,
+    python_interpreter: Option<&PythonInterpreter>,
+    bindings_crate: &BridgeModel,
+) -> Result<String> {
+    let mut plan = String::new();
+    for binding_target in binding_targets(context, bindings_crate) {
+        let build_command =
+            cargo_build_command(context, python_interpreter, bindings_crate, binding_target)?;
+        let args: Vec<String> = build_command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+        let quoted: Vec<String> = args.iter().map(|arg| shell_quote(arg)).collect();
+        plan.push_str(&format!(
+            "  Command: {} {}\n",
+            build_command.get_program().to_string_lossy(),
+            quoted.join(" ")
+        ));
+        let mut envs: Vec<_> = build_command
+            .get_envs()
+            .filter_map(|(key, value)| Some((key.to_string_lossy(), value?.to_string_lossy())))
+            .collect();
+        envs.sort();
+        for (key, value) in envs {
+            plan.push_str(&format!("  Environment: {}={}\n", key, shell_quote(&value)));
+        }
+        let artifact_dir = cargo_artifact_dir(&args, &context.target_dir);
+        plan.push_str(&format!(
+            "  Artifact: {}\n",
+            artifact_dir
+                .join(artifact_file_name(
+                    &context.target,
+                    binding_target,
+                    bindings_crate
+                ))
+                .display()
+        ));
+        // Build scripts and proc macros of builds with `--target` are built for the host, in
+        // `<target-dir>/<profile>`
+        let mut artifact_dirs = vec![artifact_dir.clone()];
+        if let Some(index) = args.iter().position(|arg| arg == "--target") {
+            let mut host_args = args.clone();
+            host_args.drain(index..(index + 2).min(args.len()));
+            artifact_dirs.push(cargo_artifact_dir(&host_args, &context.target_dir));
+        }
+        let unbuilt = unbuilt_packages(context, &artifact_dirs);
+        let changed = changed_packages(context, &artifact_dirs);
+        if !unbuilt.is_empty() {
+            plan.push_str(&format!(
+                "  Rebuilds: never built in this target directory: {}\n",
+                unbuilt.join(", ")
+            ));
+        }
+        if !changed.is_empty() {
+            plan.push_str(&format!(
+                "  Rebuilds: sources changed since the last build: {}\n",
+                changed.join(", ")
+            ));
+        }
+        if unbuilt.is_empty() && changed.is_empty() {
+            plan.push_str(
+                "  Rebuilds: nothing, unless the features or flags of a crate changed since the \
+                last build\n",
+            );
+        }
+    }
+    Ok(plan)
+}
+
+/// The directory cargo puts the artifacts in, `<target-dir>[/<triple>]/<profile>`, from the
+/// arguments of `cargo rustc`
+fn cargo_artifact_dir(args: &[String], default_target_dir: &Path) -> PathBuf {
+    let value = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|index| args.get(index + 1))
+    };
+    let mut artifact_dir = value("--target-dir")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| default_target_dir.to_path_buf());
+    if let Some(target_triple) = value("--target") {
+        artifact_dir.push(target_triple);
+    }
+    let profile_dir = match value("--profile").map(String::as_str) {
+        Some("dev") | Some("test") => "debug",
+        Some("bench") => "release",
+        Some(profile) => profile,
+        None if args.iter().any(|arg| arg == "--release") => "release",
+        None => "debug",
+    };
+    artifact_dir.join(profile_dir)
+}
+
+/// The file name of the library or binary that cargo builds for the target
+fn artifact_file_name(
+    target: &Target,
+    binding_target: &cargo_metadata::Target,
+    bindings_crate: &BridgeModel,
+) -> String {
+    if bindings_crate.is_bin() {
+        if target.is_windows() {
+            format!("{}.exe", binding_target.name)
+        } else {
+            binding_target.name.clone()
+        }
     } else {
-        compile_targets(context, python_interpreter, bindings_crate, &targets)
+        let name = binding_target.name.replace('-', "_");
+        if target.is_windows() {
+            format!("{}.dll", name)
+        } else if target.is_macos() {
+            format!("lib{}.dylib", name)
+        } else if target.is_emscripten() {
+            format!("{}.wasm", name)
+        } else {
+            format!("lib{}.so", name)
+        }
+    }
+}
+
+/// The packages of the dependency graph that have no fingerprint in the artifact directories, so
+/// cargo has never built them there
+fn unbuilt_packages(context: &BuildContext, artifact_dirs: &[PathBuf]) -> Vec<String> {
+    let mut fingerprints = Vec::new();
+    for artifact_dir in artifact_dirs {
+        if let Ok(entries) = fs::read_dir(artifact_dir.join(".fingerprint")) {
+            fingerprints.extend(
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.file_name().to_string_lossy().to_string()),
+            );
+        }
+    }
+    let resolve = match &context.cargo_metadata.resolve {
+        Some(resolve) => resolve,
+        None => return Vec::new(),
+    };
+    let mut unbuilt: Vec<String> = resolve
+        .nodes
+        .iter()
+        .filter_map(|node| {
+            let package = context
+                .cargo_metadata
+                .packages
+                .iter()
+                .find(|package| package.id == node.id)?;
+            let prefix = format!("{}-", package.name);
+            // Fingerprint directories are named `<package name>-<hash>`
+            let built = fingerprints.iter().any(|fingerprint| {
+                fingerprint
+                    .strip_prefix(&prefix)
+                    .map_or(false, |hash| !hash.contains('-'))
+            });
+            (!built).then(|| package.name.clone())
+        })
+        .collect();
+    unbuilt.sort();
+    unbuilt.dedup();
+    unbuilt
+}
+
+/// The local packages with a source file that is newer than the dep-info file cargo wrote when
+/// it last built them, which is how cargo decides that a path dependency is dirty. The dep-info
+/// files `deps/<crate name>-<hash>.d` list the source files of each build of a crate.
+fn changed_packages(context: &BuildContext, artifact_dirs: &[PathBuf]) -> Vec<String> {
+    let mut dep_infos = Vec::new();
+    for artifact_dir in artifact_dirs {
+        if let Ok(entries) = fs::read_dir(artifact_dir.join("deps")) {
+            dep_infos.extend(
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().map_or(false, |ext| ext == "d")),
+            );
+        }
+    }
+    let modified = |path: &Path| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    let mut changed: Vec<String> = context
+        .cargo_metadata
+        .packages
+        .iter()
+        .filter(|package| package.source.is_none())
+        .filter(|package| {
+            package
+                .targets
+                .iter()
+                .filter(|target| !target.kind.iter().any(|kind| kind == "custom-build"))
+                .any(|target| {
+                    let prefix = format!("{}-", target.name.replace('-', "_"));
+                    // Builds with other features or flags have other hashes, the newest dep-info
+                    // file is the one of the last build
+                    let last_build = dep_infos
+                        .iter()
+                        .filter(|path| {
+                            path.file_stem()
+                                .and_then(|stem| stem.to_str())
+                                .and_then(|stem| stem.strip_prefix(&prefix))
+                                .map_or(false, |hash| !hash.contains('-'))
+                        })
+                        .filter_map(|path| Some((modified(path)?, path)))
+                        .max();
+                    let (built, dep_info) = match last_build {
+                        Some(last_build) => last_build,
+                        None => return false,
+                    };
+                    let contents = fs::read_to_string(dep_info).unwrap_or_default();
+                    // cargo runs rustc in the workspace root, so the workspace members have
+                    // relative paths
+                    dep_info_sources(&contents).iter().any(|source| {
+                        modified(
+                            &context
+                                .cargo_metadata
+                                .workspace_root
+                                .as_std_path()
+                                .join(source),
+                        )
+                        .map_or(true, |time| time > built)
+                    })
+                })
+        })
+        .map(|package| package.name.clone())
+        .collect();
+    changed.sort();
+    changed.dedup();
+    changed
+}
+
+/// The source files of the first rule of a makefile style dep-info file written by rustc,
+/// `<output>: <source> <source>...`, where spaces in paths are escaped with a backslash
+fn dep_info_sources(contents: &str) -> Vec<PathBuf> {
+    let rule = match contents
+        .lines()
+        .next()
+        .and_then(|line| line.split_once(": "))
+    {
+        Some((_, rule)) => rule,
+        None => return Vec::new(),
+    };
+    let mut sources = Vec::new();
+    let mut source = String::new();
+    let mut chars = rule.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // Other backslashes are path separators on windows
+            '\\' if chars.peek() == Some(&' ') => source.extend(chars.next()),
+            ' ' => {
+                if !source.is_empty() {
+                    sources.push(PathBuf::from(std::mem::take(&mut source)));
+                }
+            }
+            c => source.push(c),
+        }
+    }
+    if !source.is_empty() {
+        sources.push(PathBuf::from(source));
+    }
+    sources
+}
+
+/// Quotes an argument for a posix shell if it contains special characters
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_=.,/:@+%".contains(c))
+    {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
     }
 }
 
@@ -79,6 +396,9 @@ fn compile_universal2(
     };
     let mut aarch64_context = context.clone();
     aarch64_context.target = Target::from_target_triple(Some("aarch64-apple-darwin".to_string()))?;
+    aarch64_context
+        .target
+        .set_macos_deployment_target(context.target.requested_macos_deployment_target());
 
     let aarch64_artifacts = compile_targets(
         &aarch64_context,
@@ -89,6 +409,9 @@ fn compile_universal2(
     .context("Failed to build a aarch64 library through cargo")?;
     let mut x86_64_context = context.clone();
     x86_64_context.target = Target::from_target_triple(Some("x86_64-apple-darwin".to_string()))?;
+    x86_64_context
+        .target
+        .set_macos_deployment_target(context.target.requested_macos_deployment_target());
 
     let x86_64_artifacts =
         compile_targets(&x86_64_context, python_interpreter, bindings_crate, targets)
@@ -164,17 +487,38 @@ fn compile_targets(
     Ok(artifacts)
 }
 
-fn compile_target(
+/// Configures the `cargo rustc` invocation that builds the target for the interpreter
+fn cargo_build_command(
     context: &BuildContext,
     python_interpreter: Option<&PythonInterpreter>,
     bindings_crate: &BridgeModel,
     binding_target: &cargo_metadata::Target,
-) -> Result<HashMap<String, BuildArtifact>> {
+) -> Result<Command> {
     let target = &context.target;
 
     let mut cargo_rustc: cargo_options::Rustc = context.cargo_options.clone().into();
     cargo_rustc.message_format = vec!["json".to_string()];
 
+    // Each interpreter has a different pyo3 configuration, so with a shared target directory
+    // cargo would rebuild pyo3 and relink the crate every time we switch interpreters. With one
+    // target directory per interpreter, rebuilding for all of them only recompiles what changed.
+    // Builds for a single interpreter, including `maturin develop`, keep the target directory
+    // they share with `cargo build` and `cargo test`. cross mounts only the target directory of
+    // the build into the container, so container builds keep everything in it
+    if let Some(python_interpreter) = python_interpreter.filter(|_| !context.use_container) {
+        if context.interpreters()?.len() > 1
+            && matches!(
+                bindings_crate,
+                BridgeModel::Bindings(..) | BridgeModel::Bin(Some(..))
+            )
+        {
+            cargo_rustc.target_dir = Some(interpreter_target_dir(
+                &context.scratch_dir()?,
+                python_interpreter,
+            ));
+        }
+    }
+
     // --release and --profile are conflicting options
     if context.release && cargo_rustc.profile.is_none() {
         cargo_rustc.release = true;
@@ -193,7 +537,8 @@ fn compile_target(
         }
     }
 
-    let mut rust_flags = env::var_os("RUSTFLAGS");
+    // The rustflags maturin adds, see `add_rustflags`
+    let mut rust_flags = context.rustflags.clone();
 
     // We need to pass --bin / --lib
     match bindings_crate {
@@ -206,9 +551,7 @@ fn compile_target(
             // We must only do this for libraries as it breaks binaries
             // For some reason this value is ignored when passed as rustc argument
             if context.target.is_musl_target() {
-                rust_flags
-                    .get_or_insert_with(Default::default)
-                    .push(" -C target-feature=-crt-static");
+                rust_flags.extend(["-C".to_string(), "target-feature=-crt-static".to_string()]);
             }
         }
     }
@@ -228,21 +571,24 @@ fn compile_target(
             };
             let macos_dylib_install_name =
                 format!("link-args=-Wl,-install_name,@rpath/{}", so_filename);
-            let mac_args = [
-                "-C".to_string(),
-                "link-arg=-undefined".to_string(),
-                "-C".to_string(),
-                "link-arg=dynamic_lookup".to_string(),
-                "-C".to_string(),
-                macos_dylib_install_name,
-            ];
-            cargo_rustc.args.extend(mac_args);
+            for arg in macos_link_args(bindings_crate, python_interpreter) {
+                cargo_rustc
+                    .args
+                    .extend(["-C".to_string(), format!("link-arg={}", arg)]);
+            }
+            cargo_rustc
+                .args
+                .extend(["-C".to_string(), macos_dylib_install_name]);
         }
     } else if target.is_emscripten() {
-        let flags = rust_flags.get_or_insert_with(Default::default);
         // Allow user to override these default flags
-        if !flags.to_string_lossy().contains("link-native-libraries") {
-            flags.push(" -Z link-native-libraries=no");
+        let user_flags = env::var("RUSTFLAGS").unwrap_or_default();
+        if !user_flags.contains("link-native-libraries")
+            && !rust_flags
+                .iter()
+                .any(|flag| flag.contains("link-native-libraries"))
+        {
+            rust_flags.extend(["-Z".to_string(), "link-native-libraries=no".to_string()]);
         }
         let mut emscripten_args = Vec::new();
         // Allow user to override these default settings
@@ -271,6 +617,14 @@ fn compile_target(
             .extend(["-C".to_string(), "link-arg=-s".to_string()]);
     }
 
+    for link_arg in &context.link_args {
+        cargo_rustc
+            .args
+            .extend(["-C".to_string(), format!("link-arg={}", link_arg)]);
+    }
+
+    let rustflags_env = add_rustflags(context, &mut cargo_rustc, rust_flags);
+
     let target_triple = target.target_triple();
     let mut build_command = if target.is_msvc() && target.cross_compiling() {
         let mut build = cargo_xwin::Rustc::from(cargo_rustc);
@@ -281,7 +635,8 @@ fn compile_target(
         let mut build = cargo_zigbuild::Rustc::from(cargo_rustc);
         if !context.zig {
             build.disable_zig_linker = true;
-            if target.user_specified {
+            // cross picks the container by the target
+            if target.user_specified || context.use_container {
                 build.target = vec![target_triple.to_string()];
             }
         } else {
@@ -315,22 +670,28 @@ fn compile_target(
         }
     }
 
-    build_command
-        // We need to capture the json messages
-        .stdout(Stdio::piped())
-        // We can't get colored human and json messages from rustc as they are mutually exclusive,
-        // but forwarding stderr is still useful in case there some non-json error
-        .stderr(Stdio::inherit());
+    // Variables that are already set in the environment are left out of `context.env`
+    build_command.envs(&context.env);
 
-    if let Some(flags) = rust_flags {
+    if let Some(flags) = rustflags_env {
         build_command.env("RUSTFLAGS", flags);
     }
 
+    // zig sets its own linker
+    if let Some(linker) = context.linker.as_ref().filter(|_| !context.zig) {
+        let linker_env = format!(
+            "CARGO_TARGET_{}_LINKER",
+            target_triple.to_uppercase().replace('-', "_")
+        );
+        if env::var_os(&linker_env).is_none() {
+            build_command.env(linker_env, linker);
+        }
+    }
+
     if let BridgeModel::BindingsAbi3(_, _) = bindings_crate {
-        let is_pypy = python_interpreter
-            .map(|p| p.interpreter_kind.is_pypy())
-            .unwrap_or(false);
-        if !is_pypy && !target.is_windows() {
+        // PyPy and GraalPy don't support abi3, their wheels are built against the interpreter
+        let has_stable_api = python_interpreter.map_or(true, |p| p.has_stable_api());
+        if has_stable_api && !target.is_windows() {
             let pyo3_ver = pyo3_version(&context.cargo_metadata)
                 .context("Failed to get pyo3 version from cargo metadata")?;
             if pyo3_ver < PYO3_ABI3_NO_PYTHON_VERSION {
@@ -343,12 +704,17 @@ fn compile_target(
 
     // Setup `PYO3_CONFIG_FILE` if we are cross compiling for pyo3 bindings
     if let Some(interpreter) = python_interpreter {
-        // Target python interpreter isn't runnable when cross compiling
-        if interpreter.runnable {
+        // Target python interpreter isn't runnable when cross compiling. The embeddable
+        // distribution is, but may lack sysconfig, which the build script of pyo3 relies on.
+        // The interpreters of the host don't exist in a container, so container builds always
+        // get a config file, which is in the target directory that is mounted into it.
+        let embeddable = interpreter.embeddable_pth().is_some();
+        let abi3 = matches!(bindings_crate, BridgeModel::BindingsAbi3(_, _));
+        if interpreter.runnable && !embeddable && !context.use_container {
             if bindings_crate.is_bindings("pyo3")
                 || bindings_crate.is_bindings("pyo3-ffi")
                 || (matches!(bindings_crate, BridgeModel::BindingsAbi3(_, _))
-                    && interpreter.interpreter_kind.is_pypy())
+                    && !interpreter.has_stable_api())
             {
                 build_command.env("PYO3_PYTHON", &interpreter.executable);
             }
@@ -357,19 +723,29 @@ fn compile_target(
             build_command.env("PYTHON_SYS_EXECUTABLE", &interpreter.executable);
         } else if (bindings_crate.is_bindings("pyo3")
             || bindings_crate.is_bindings("pyo3-ffi")
-            || (matches!(bindings_crate, BridgeModel::BindingsAbi3(_, _))
-                && interpreter.interpreter_kind.is_pypy()))
+            || (abi3 && (!interpreter.has_stable_api() || embeddable)))
             && env::var_os("PYO3_CONFIG_FILE").is_none()
         {
-            let pyo3_config = interpreter.pyo3_config_file();
-            let maturin_target_dir = context.target_dir.join("maturin");
-            let config_file = maturin_target_dir.join(format!(
+            let mut pyo3_config = interpreter.pyo3_config_file();
+            if embeddable {
+                let (lib_dir, lib_name) = embeddable_python_lib(
+                    context,
+                    interpreter,
+                    abi3 && interpreter.has_stable_api(),
+                )?;
+                write!(
+                    pyo3_config,
+                    "\nlib_dir={}\nlib_name={}",
+                    lib_dir.display(),
+                    lib_name
+                )?;
+            }
+            let config_file = context.scratch_dir()?.join(format!(
                 "pyo3-config-{}-{}.{}.txt",
                 target.target_triple(),
                 interpreter.major,
                 interpreter.minor
             ));
-            fs::create_dir_all(&maturin_target_dir)?;
             fs::write(&config_file, pyo3_config).with_context(|| {
                 format!(
                     "Failed to create pyo3 config file at '{}'",
@@ -384,18 +760,75 @@ fn compile_target(
         build_command.env("PYO3_CROSS_LIB_DIR", lib_dir);
     }
 
-    // Set default macOS deployment target version
-    if target.is_macos() && env::var_os("MACOSX_DEPLOYMENT_TARGET").is_none() {
-        let min_version = match target.target_arch() {
-            Arch::Aarch64 => "11.0",
-            _ => "10.7",
-        };
-        build_command.env("MACOSX_DEPLOYMENT_TARGET", min_version);
+    // Use the same macOS deployment target as the platform tag of the wheel
+    if target.is_macos() {
+        let (major, minor) = target.macos_deployment_target(
+            context.interpreter_macos_deployment_target(python_interpreter),
+            context.universal2,
+        )?;
+        build_command.env("MACOSX_DEPLOYMENT_TARGET", format!("{}.{}", major, minor));
+    }
+
+    if context.use_container {
+        build_command = container_build_command(&build_command, &context.target_dir);
+        // An image in the environment or in the configuration of cross takes precedence
+        let image_env = format!(
+            "CROSS_TARGET_{}_IMAGE",
+            target_triple.to_uppercase().replace('-', "_")
+        );
+        let cross_config = env::var_os("CROSS_CONFIG").is_some()
+            || context
+                .cargo_metadata
+                .workspace_root
+                .join("Cross.toml")
+                .is_file();
+        if env::var_os(&image_env).is_none() && !cross_config {
+            if let Some(image) = manylinux_cross_image(target, &context.platform_tag) {
+                build_command.env(image_env, image);
+            }
+        }
+    }
+
+    Ok(build_command)
+}
+
+fn compile_target(
+    context: &BuildContext,
+    python_interpreter: Option<&PythonInterpreter>,
+    bindings_crate: &BridgeModel,
+    binding_target: &cargo_metadata::Target,
+) -> Result<HashMap<String, BuildArtifact>> {
+    if context.lto.linker_plugin {
+        check_clang(&context.lto, &context.target)?;
+    }
+    let mut build_command =
+        cargo_build_command(context, python_interpreter, bindings_crate, binding_target)?;
+    // We need to capture the json messages
+    build_command.stdout(Stdio::piped());
+    // We can't get colored human and json messages from rustc as they are mutually exclusive,
+    // but forwarding stderr is still useful in case there some non-json error. With a log file,
+    // it passes through maturin to also end up there, except on a terminal, where cargo would
+    // lose its progress bar. The compiler messages are logged from the json messages anyway.
+    let log_cargo_output = tracing::enabled!(target: CARGO_LOG_TARGET, Level::DEBUG)
+        && !console::user_attended_stderr();
+    if log_cargo_output {
+        build_command.stderr(Stdio::piped());
+    } else {
+        build_command.stderr(Stdio::inherit());
     }
+    debug!("Running {:?}", build_command);
 
-    let mut cargo_build = build_command
-        .spawn()
-        .context("Failed to run `cargo rustc`")?;
+    let mut cargo_build = build_command.spawn().with_context(|| {
+        if context.use_container {
+            "Failed to run `cross rustc`, install cross with `cargo install cross`"
+        } else {
+            "Failed to run `cargo rustc`"
+        }
+    })?;
+    let forward_stderr = cargo_build
+        .stderr
+        .take()
+        .map(|stderr| thread::spawn(move || forward_cargo_stderr(stderr)));
 
     let mut artifacts = HashMap::new();
     let mut linked_paths = Vec::new();
@@ -407,6 +840,7 @@ fn compile_target(
     for message in cargo_metadata::Message::parse_stream(BufReader::new(stream)) {
         match message.context("Failed to parse message coming from cargo")? {
             cargo_metadata::Message::CompilerArtifact(artifact) => {
+                stats::count_cargo_artifact(artifact.fresh);
                 let package_in_metadata = context
                     .cargo_metadata
                     .packages
@@ -433,14 +867,21 @@ fn compile_target(
 
                 // Extract the location of the .so/.dll/etc. from cargo's json output
                 if crate_name == &context.crate_name {
-                    let tuples = artifact
-                        .target
-                        .crate_types
+                    // windows-gnu lists an additional `.dll.a` import library for the dll,
+                    // msvc a `.dll.lib` and the `.pdb` debug info
+                    let filenames = artifact
+                        .filenames
                         .into_iter()
-                        .zip(artifact.filenames);
+                        .filter(|filename| !is_auxiliary_artifact(filename.as_str()));
+                    let tuples = artifact.target.crate_types.into_iter().zip(filenames);
                     for (crate_type, filename) in tuples {
+                        let path = if context.use_container {
+                            host_artifact_path(filename.into(), &build_command, &context.target_dir)
+                        } else {
+                            filename.into()
+                        };
                         let artifact = BuildArtifact {
-                            path: filename.into(),
+                            path,
                             linked_paths: Vec::new(),
                         };
                         artifacts.insert(crate_type, artifact);
@@ -460,6 +901,7 @@ fn compile_target(
             }
             cargo_metadata::Message::CompilerMessage(msg) => {
                 println!("{}", msg.message);
+                debug!(target: CARGO_LOG_TARGET, "{}", msg.message);
             }
             _ => (),
         }
@@ -473,18 +915,123 @@ fn compile_target(
     let status = cargo_build
         .wait()
         .expect("Failed to wait on cargo child process");
+    if let Some(forward_stderr) = forward_stderr {
+        forward_stderr
+            .join()
+            .expect("Forwarding the output of cargo panicked");
+    }
 
     if !status.success() {
-        bail!(
-            r#"Cargo build finished with "{}": `{:?}`"#,
+        return Err(CargoBuildError {
             status,
-            build_command,
-        )
+            command: format!("{:?}", build_command),
+        }
+        .into());
     }
 
     Ok(artifacts)
 }
 
+/// The target directory inside the containers of cross
+const CONTAINER_TARGET_DIR: &str = "/target";
+
+/// Runs the cargo command with cross instead, which runs it in a container for the target
+///
+/// cross only passes the variables listed in `CROSS_BUILD_ENV_PASSTHROUGH` into the container,
+/// so the ones maturin sets are added to it. Paths in the target directory, such as the pyo3
+/// configuration file, are changed to where the target directory is in the container.
+fn container_build_command(build_command: &Command, target_dir: &Path) -> Command {
+    let mut container_command = Command::new("cross");
+    container_command.args(build_command.get_args());
+    let mut passthrough: Vec<String> = env::var("CROSS_BUILD_ENV_PASSTHROUGH")
+        .map(|passthrough| passthrough.split(',').map(ToString::to_string).collect())
+        .unwrap_or_default();
+    for (key, value) in build_command.get_envs() {
+        match value {
+            Some(value) => {
+                match Path::new(value).strip_prefix(target_dir) {
+                    Ok(relative) => {
+                        container_command.env(key, Path::new(CONTAINER_TARGET_DIR).join(relative))
+                    }
+                    Err(_) => container_command.env(key, value),
+                };
+                passthrough.push(key.to_string_lossy().to_string());
+            }
+            None => {
+                container_command.env_remove(key);
+            }
+        }
+    }
+    if !passthrough.is_empty() {
+        container_command.env("CROSS_BUILD_ENV_PASSTHROUGH", passthrough.join(","));
+    }
+    if let Some(current_dir) = build_command.get_current_dir() {
+        container_command.current_dir(current_dir);
+    }
+    container_command
+}
+
+/// The [manylinux-cross](https://github.com/rust-cross/manylinux-cross) image for the requested
+/// manylinux tag, whose toolchain links against the glibc of that tag, unlike the default images
+/// of cross. musl targets keep the images of cross, which link musl statically.
+fn manylinux_cross_image(target: &Target, platform_tags: &[PlatformTag]) -> Option<String> {
+    let manylinux_2_28 = match platform_tags.iter().find(|tag| tag.is_manylinux())? {
+        PlatformTag::Manylinux { x: 2, y: 17 } => false,
+        PlatformTag::Manylinux { x: 2, y: 28 } => true,
+        _ => return None,
+    };
+    let arch = match target.target_arch() {
+        Arch::X86_64 => "x86_64",
+        Arch::Aarch64 => "aarch64",
+        Arch::Armv7L => "armv7",
+        Arch::Powerpc64Le => "ppc64le",
+        Arch::S390X => "s390x",
+        Arch::X86 if !manylinux_2_28 => "i686",
+        Arch::Powerpc64 if !manylinux_2_28 => "ppc64",
+        _ => return None,
+    };
+    let image = if manylinux_2_28 {
+        "manylinux_2_28-cross"
+    } else {
+        "manylinux2014-cross"
+    };
+    Some(format!("ghcr.io/rust-cross/{}:{}", image, arch))
+}
+
+/// cross mounts the target directory at `/target` in the container, so the artifact paths
+/// cargo reports have to be moved back into the target directory of the host
+fn host_artifact_path(
+    path: PathBuf,
+    build_command: &Command,
+    default_target_dir: &Path,
+) -> PathBuf {
+    let relative = match path.strip_prefix(CONTAINER_TARGET_DIR) {
+        Ok(relative) if !path.exists() => relative,
+        _ => return path,
+    };
+    let mut args = build_command.get_args();
+    let target_dir = args
+        .find(|arg| *arg == "--target-dir")
+        .and_then(|_| args.next())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| default_target_dir.to_path_buf());
+    target_dir.join(relative)
+}
+
+/// Copies the stderr of cargo to our stderr and into the log
+fn forward_cargo_stderr(stderr: ChildStderr) {
+    for line in BufReader::new(stderr).split(b'\n') {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end_matches('\r');
+        eprintln!("{}", line);
+        debug!(target: CARGO_LOG_TARGET, "{}", console::strip_ansi_codes(line));
+    }
+}
+
 /// Checks that the native library contains a function called `PyInit_<module name>` and warns
 /// if it's missing.
 ///
@@ -562,6 +1109,199 @@ pub fn warn_missing_py_init(artifact: &Path, module_name: &str) -> Result<()> {
     Ok(())
 }
 
+/// Returns the minimum macOS version of each architecture of a Mach-O library, from its
+/// `LC_BUILD_VERSION` or, for older deployment targets, `LC_VERSION_MIN_MACOSX` load command
+pub fn macos_minimum_versions(artifact: &Path) -> Result<Vec<(Arch, (usize, usize))>> {
+    use goblin::mach::constants::cputype::{CPU_TYPE_ARM64, CPU_TYPE_X86_64};
+    use goblin::mach::load_command::{CommandVariant, LC_BUILD_VERSION};
+    use goblin::mach::{Mach, MachO};
+
+    let buffer = fs::read(artifact)?;
+    let slices = match Mach::parse(&buffer)? {
+        Mach::Binary(_) => vec![&buffer[..]],
+        Mach::Fat(fat) => fat
+            .iter_arches()
+            .map(|arch| Ok(arch?.slice(&buffer)))
+            .collect::<Result<Vec<_>>>()?,
+    };
+    let mut versions = Vec::new();
+    for slice in slices {
+        let macho = MachO::parse(slice, 0)?;
+        let arch = match macho.header.cputype {
+            CPU_TYPE_ARM64 => Arch::Aarch64,
+            CPU_TYPE_X86_64 => Arch::X86_64,
+            _ => continue,
+        };
+        for load_command in &macho.load_commands {
+            let version = match load_command.command {
+                CommandVariant::VersionMinMacosx(command) => command.version,
+                // goblin doesn't parse this one, the version follows cmd, cmdsize and platform
+                CommandVariant::Unimplemented(header) if header.cmd == LC_BUILD_VERSION => {
+                    let bytes = slice
+                        .get(load_command.offset + 12..load_command.offset + 16)
+                        .context("Truncated LC_BUILD_VERSION load command")?;
+                    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                    if macho.little_endian {
+                        u32::from_le_bytes(bytes)
+                    } else {
+                        u32::from_be_bytes(bytes)
+                    }
+                }
+                _ => continue,
+            };
+            // X.Y.Z is encoded in nibbles xxxx.yy.zz
+            versions.push((
+                arch,
+                ((version >> 16) as usize, ((version >> 8) & 0xff) as usize),
+            ));
+        }
+    }
+    Ok(versions)
+}
+
+/// The linker arguments for the python symbols of an extension module on macOS. Usually the
+/// module doesn't link libpython, but leaves the symbols to be resolved when python loads it.
+/// That's required for the pythons without a libpython, such as the static builds of pyenv or
+/// conda, and for framework builds it avoids loading a second python when the module is imported
+/// by a different installation of the same version. Interpreters whose sysconfig links
+/// extensions explicitly against their framework or shared libpython get the same arguments,
+/// except for abi3 modules, which must not depend on a specific python version.
+fn macos_link_args(
+    bindings_crate: &BridgeModel,
+    python_interpreter: Option<&PythonInterpreter>,
+) -> Vec<String> {
+    match (
+        bindings_crate,
+        python_interpreter.and_then(|python| python.macos_link_args.as_ref()),
+    ) {
+        (BridgeModel::Bindings(..), Some(link_args)) => link_args.clone(),
+        _ => vec!["-undefined".to_string(), "dynamic_lookup".to_string()],
+    }
+}
+
+/// Passes the rustflags maturin adds to cargo with `--config build.rustflags=[...]`, so that
+/// cargo merges them with the `build.rustflags` of its config. Cargo ignores its config when
+/// `RUSTFLAGS` is set, and old cargo versions don't support `--config`, in these cases the
+/// returned value for `RUSTFLAGS` contains them instead.
+fn add_rustflags(
+    context: &BuildContext,
+    cargo_rustc: &mut cargo_options::Rustc,
+    rust_flags: Vec<String>,
+) -> Option<OsString> {
+    if rust_flags.is_empty() {
+        return None;
+    }
+    let user_flags =
+        env::var_os("RUSTFLAGS").or_else(|| context.env.get("RUSTFLAGS").map(OsString::from));
+    if user_flags.is_none()
+        && env::var_os("CARGO_ENCODED_RUSTFLAGS").is_none()
+        && context.target.rustc_version.semver >= RUST_1_63_0
+    {
+        cargo_rustc
+            .common
+            .config
+            .push(rustflags_config(&rust_flags));
+        return None;
+    }
+    let mut flags = user_flags.unwrap_or_default();
+    for flag in rust_flags {
+        if !flags.is_empty() {
+            flags.push(" ");
+        }
+        flags.push(flag);
+    }
+    Some(flags)
+}
+
+/// The `--config` value setting `build.rustflags` to `flags`
+fn rustflags_config(flags: &[String]) -> String {
+    let array: toml_edit::Array = flags.iter().map(String::as_str).collect();
+    format!("build.rustflags={}", array)
+}
+
+/// The directory and name of the python library to link for the embeddable distribution of
+/// CPython for windows, `python3` for abi3. It has no import libraries, so they're generated
+/// from the dlls for the msvc targets, while the MinGW linker links the dll directly.
+fn embeddable_python_lib(
+    context: &BuildContext,
+    interpreter: &PythonInterpreter,
+    abi3: bool,
+) -> Result<(PathBuf, String)> {
+    let python_dir = interpreter
+        .executable
+        .parent()
+        .context("The python executable has no parent directory")?;
+    let lib_name = if abi3 {
+        "python3".to_string()
+    } else {
+        format!("python{}{}", interpreter.major, interpreter.minor)
+    };
+    if !context.target.is_msvc() {
+        return Ok((python_dir.to_path_buf(), lib_name));
+    }
+    let lib_dir = context.scratch_dir()?.join(format!(
+        "python-libs-{}-{}.{}",
+        context.target.target_triple(),
+        interpreter.major,
+        interpreter.minor
+    ));
+    generate_import_lib(
+        python_dir,
+        &lib_name,
+        &context.target,
+        context.zig,
+        &lib_dir,
+    )?;
+    Ok((lib_dir, lib_name))
+}
+
+/// The cargo target directory for builds with the interpreter, e.g. `cpython-3.11` or `pypy-3.9`.
+/// MinGW pythons get their own, e.g. `cpython-3.11-mingw_x86_64_ucrt`, since they link another
+/// python library than the MSVC python of the same version.
+fn interpreter_target_dir(scratch_dir: &Path, python_interpreter: &PythonInterpreter) -> PathBuf {
+    let mut name = format!(
+        "{}-{}.{}{}",
+        python_interpreter.implmentation_name,
+        python_interpreter.major,
+        python_interpreter.minor,
+        python_interpreter.abiflags
+    );
+    if let Some(mingw_platform) = python_interpreter.mingw_platform() {
+        name.push('-');
+        name.push_str(mingw_platform);
+    }
+    scratch_dir.join(name)
+}
+
+/// Whether cargo emitted the file alongside the actual artifact, e.g. an import library
+fn is_auxiliary_artifact(filename: &str) -> bool {
+    [".dll.a", ".dll.lib", ".dll.exp", ".pdb"]
+        .iter()
+        .any(|extension| filename.ends_with(extension))
+}
+
+/// Returns the MinGW runtime DLLs a windows-gnu library links dynamically, which makes the module
+/// fail to import on machines without a MinGW installation in the `PATH`
+pub fn mingw_runtime_dependencies(artifact: &Path) -> Result<Vec<String>> {
+    let buffer = fs::read(artifact)?;
+    match goblin::Object::parse(&buffer)? {
+        goblin::Object::PE(pe) => Ok(filter_mingw_runtime_dlls(&pe.libraries)),
+        _ => Ok(Vec::new()),
+    }
+}
+
+fn filter_mingw_runtime_dlls(libraries: &[&str]) -> Vec<String> {
+    libraries
+        .iter()
+        .filter(|library| {
+            MINGW_RUNTIME_DLLS
+                .iter()
+                .any(|dll| dll.eq_ignore_ascii_case(library))
+        })
+        .map(|library| library.to_string())
+        .collect()
+}
+
 fn pyo3_version(cargo_metadata: &cargo_metadata::Metadata) -> Option<(u64, u64, u64)> {
     let packages: HashMap<&str, &cargo_metadata::Package> = cargo_metadata
         .packages
@@ -580,3 +1320,264 @@ fn pyo3_version(cargo_metadata: &cargo_metadata::Metadata) -> Option<(u64, u64,
         .or_else(|| packages.get("pyo3-ffi"))
         .map(|pkg| (pkg.version.major, pkg.version.minor, pkg.version.patch))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::python_interpreter::{InterpreterConfig, InterpreterKind};
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_container_build_command() {
+        let mut build_command = Command::new("cargo");
+        build_command
+            .args(["rustc", "--target", "aarch64-unknown-linux-gnu"])
+            .args(["--target-dir", "/project/target/maturin"])
+            .env(
+                "PYO3_CONFIG_FILE",
+                "/project/target/maturin/pyo3-config.txt",
+            )
+            .env("PYO3_NO_PYTHON", "1");
+        let container_command =
+            container_build_command(&build_command, Path::new("/project/target"));
+        assert_eq!(container_command.get_program(), "cross");
+        assert_eq!(
+            container_command.get_args().collect::<Vec<_>>(),
+            build_command.get_args().collect::<Vec<_>>()
+        );
+        let envs: HashMap<_, _> = container_command.get_envs().collect();
+        assert_eq!(
+            envs[OsStr::new("PYO3_CONFIG_FILE")],
+            Some(OsStr::new("/target/maturin/pyo3-config.txt"))
+        );
+        assert_eq!(envs[OsStr::new("PYO3_NO_PYTHON")], Some(OsStr::new("1")));
+        let passthrough = envs[OsStr::new("CROSS_BUILD_ENV_PASSTHROUGH")].unwrap();
+        assert!(passthrough.to_string_lossy().contains("PYO3_CONFIG_FILE"));
+
+        assert_eq!(
+            host_artifact_path(
+                PathBuf::from("/target/aarch64-unknown-linux-gnu/release/libfoo.so"),
+                &container_command,
+                Path::new("/project/target")
+            ),
+            Path::new("/project/target/maturin/aarch64-unknown-linux-gnu/release/libfoo.so")
+        );
+        assert_eq!(
+            host_artifact_path(
+                PathBuf::from("/target/release/libfoo.so"),
+                &Command::new("cross"),
+                Path::new("/project/target")
+            ),
+            Path::new("/project/target/release/libfoo.so")
+        );
+        // Paths that are already on the host are kept
+        assert_eq!(
+            host_artifact_path(
+                PathBuf::from("/project/target/release/libfoo.so"),
+                &container_command,
+                Path::new("/project/target")
+            ),
+            Path::new("/project/target/release/libfoo.so")
+        );
+    }
+
+    #[test]
+    fn test_manylinux_cross_image() {
+        let target = |triple: &str| Target::from_target_triple(Some(triple.to_string())).unwrap();
+        let aarch64 = target("aarch64-unknown-linux-gnu");
+        assert_eq!(
+            manylinux_cross_image(&aarch64, &[PlatformTag::manylinux2014()]).as_deref(),
+            Some("ghcr.io/rust-cross/manylinux2014-cross:aarch64")
+        );
+        assert_eq!(
+            manylinux_cross_image(&aarch64, &[PlatformTag::Manylinux { x: 2, y: 28 }]).as_deref(),
+            Some("ghcr.io/rust-cross/manylinux_2_28-cross:aarch64")
+        );
+        // Without a manylinux tag or an image for it, cross picks its own image
+        assert_eq!(manylinux_cross_image(&aarch64, &[]), None);
+        assert_eq!(
+            manylinux_cross_image(&aarch64, &[PlatformTag::Manylinux { x: 2, y: 24 }]),
+            None
+        );
+        assert_eq!(
+            manylinux_cross_image(
+                &target("i686-unknown-linux-gnu"),
+                &[PlatformTag::Manylinux { x: 2, y: 28 }]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_interpreter_target_dir() {
+        let interpreter = |interpreter_kind, minor, abiflags: &str| {
+            PythonInterpreter::from_config(InterpreterConfig {
+                major: 3,
+                minor,
+                interpreter_kind,
+                abiflags: abiflags.to_string(),
+                ext_suffix: ".so".to_string(),
+                abi_tag: None,
+                pointer_width: Some(64),
+            })
+        };
+        let scratch_dir = Path::new("target/maturin");
+        assert_eq!(
+            interpreter_target_dir(scratch_dir, &interpreter(InterpreterKind::CPython, 11, "")),
+            scratch_dir.join("cpython-3.11")
+        );
+        assert_eq!(
+            interpreter_target_dir(scratch_dir, &interpreter(InterpreterKind::CPython, 7, "m")),
+            scratch_dir.join("cpython-3.7m")
+        );
+        assert_eq!(
+            interpreter_target_dir(scratch_dir, &interpreter(InterpreterKind::PyPy, 9, "")),
+            scratch_dir.join("pypy-3.9")
+        );
+        let mut mingw = interpreter(InterpreterKind::CPython, 11, "");
+        mingw.platform = Some("mingw_x86_64_ucrt".to_string());
+        assert_eq!(
+            interpreter_target_dir(scratch_dir, &mingw),
+            scratch_dir.join("cpython-3.11-mingw_x86_64_ucrt")
+        );
+    }
+
+    #[test]
+    fn test_cargo_artifact_dir() {
+        let args = |args: &[&str]| args.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let target_dir = Path::new("target");
+        assert_eq!(
+            cargo_artifact_dir(&args(&["--lib"]), target_dir),
+            Path::new("target/debug")
+        );
+        assert_eq!(
+            cargo_artifact_dir(
+                &args(&["--release", "--target", "aarch64-unknown-linux-gnu"]),
+                target_dir
+            ),
+            Path::new("target/aarch64-unknown-linux-gnu/release")
+        );
+        assert_eq!(
+            cargo_artifact_dir(
+                &args(&["--profile", "dist", "--target-dir", "/tmp/build"]),
+                target_dir
+            ),
+            Path::new("/tmp/build/dist")
+        );
+        assert_eq!(
+            cargo_artifact_dir(&args(&["--profile", "dev"]), target_dir),
+            Path::new("target/debug")
+        );
+    }
+
+    #[test]
+    fn test_dep_info_sources() {
+        assert_eq!(
+            dep_info_sources(
+                "/t/debug/deps/foo-1a2b.d: src/lib.rs src/my\\ mod.rs\n\nsrc/lib.rs:\n"
+            ),
+            [PathBuf::from("src/lib.rs"), PathBuf::from("src/my mod.rs")]
+        );
+        assert_eq!(
+            dep_info_sources(r"C:\t\deps\foo-1a2b.d: C:\p\src\lib.rs"),
+            [PathBuf::from(r"C:\p\src\lib.rs")]
+        );
+        assert!(dep_info_sources("").is_empty());
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("--features"), "--features");
+        assert_eq!(shell_quote("link-arg=-s"), "link-arg=-s");
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_windows_gnu_artifacts() {
+        assert!(is_auxiliary_artifact("target/debug/libpyo3_pure.dll.a"));
+        assert!(is_auxiliary_artifact("target/debug/pyo3_pure.dll.lib"));
+        assert!(is_auxiliary_artifact("target/debug/pyo3_pure.pdb"));
+        assert!(!is_auxiliary_artifact("target/debug/pyo3_pure.dll"));
+        assert!(!is_auxiliary_artifact("target/debug/libpyo3_pure.rlib"));
+        assert_eq!(
+            filter_mingw_runtime_dlls(&[
+                "python3.dll",
+                "KERNEL32.dll",
+                "LIBSTDC++-6.DLL",
+                "libgcc_s_seh-1.dll"
+            ]),
+            ["LIBSTDC++-6.DLL", "libgcc_s_seh-1.dll"]
+        );
+    }
+
+    #[test]
+    fn test_macos_link_args() {
+        let mut python = PythonInterpreter::from_config(InterpreterConfig {
+            major: 3,
+            minor: 11,
+            interpreter_kind: InterpreterKind::CPython,
+            abiflags: String::new(),
+            ext_suffix: ".cpython-311-darwin.so".to_string(),
+            abi_tag: None,
+            pointer_width: Some(64),
+        });
+        let bindings = BridgeModel::Bindings("pyo3".to_string(), 7);
+        let abi3 = BridgeModel::BindingsAbi3(3, 7);
+        assert_eq!(
+            macos_link_args(&bindings, Some(&python)),
+            ["-undefined", "dynamic_lookup"]
+        );
+        python.macos_link_args = Some(vec![
+            "-F/Library/Frameworks".to_string(),
+            "-framework".to_string(),
+            "Python".to_string(),
+        ]);
+        assert_eq!(
+            macos_link_args(&bindings, Some(&python)),
+            ["-F/Library/Frameworks", "-framework", "Python"]
+        );
+        assert_eq!(
+            macos_link_args(&abi3, Some(&python)),
+            ["-undefined", "dynamic_lookup"]
+        );
+        assert_eq!(
+            macos_link_args(&abi3, None),
+            ["-undefined", "dynamic_lookup"]
+        );
+    }
+
+    #[test]
+    fn test_rustflags_config() {
+        let flags = [
+            "-C".to_string(),
+            "target-cpu=x86-64-v2".to_string(),
+            "--cfg".to_string(),
+            "feature=\"foo\"".to_string(),
+        ];
+        assert_eq!(
+            rustflags_config(&flags),
+            r#"build.rustflags=["-C", "target-cpu=x86-64-v2", "--cfg", "feature=\"foo\""]"#
+        );
+    }
+
+    #[test]
+    fn test_macos_minimum_versions() {
+        // A thin arm64 dylib header with only an LC_BUILD_VERSION for macOS 13.1
+        let mut macho = Vec::new();
+        for value in [0xfeedfacf_u32, 0x0100000c, 0, 6, 1, 24, 0, 0] {
+            macho.extend(value.to_le_bytes());
+        }
+        for value in [0x32_u32, 24, 1, 0x000d0100, 0x000d0100, 0] {
+            macho.extend(value.to_le_bytes());
+        }
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("libfoo.dylib");
+        fs::write(&path, macho).unwrap();
+        assert_eq!(
+            macos_minimum_versions(&path).unwrap(),
+            [(Arch::Aarch64, (13, 1))]
+        );
+    }
+}
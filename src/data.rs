@@ -0,0 +1,359 @@
+//! The datasets built into maturin, i.e. the manylinux and musllinux policies, the trove
+//! classifiers and the python versions to look for. `maturin self update-data` refreshes them
+//! into the user cache, so that e.g. a new manylinux policy doesn't need a new maturin release.
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Overrides the directory of the refreshed data, which defaults to `maturin/data` in the user
+/// cache directory
+const DATA_DIR_ENV_VAR: &str = "MATURIN_DATA_DIR";
+
+/// Options for `maturin self update-data`
+#[derive(Debug, Default, clap::Parser)]
+pub struct UpdateDataOptions {
+    /// Copy the data files from this directory instead of downloading them, e.g. from the data
+    /// directory of a machine with network access
+    #[arg(long, value_name = "DIR", conflicts_with = "reset")]
+    from: Option<PathBuf>,
+    /// Remove the refreshed data, so that the data built into maturin is used again
+    #[arg(long)]
+    reset: bool,
+}
+
+/// A dataset built into maturin that can be refreshed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Dataset {
+    /// auditwheel's manylinux policies
+    ManylinuxPolicy,
+    /// auditwheel's musllinux policies
+    MusllinuxPolicy,
+    /// The trove classifiers PyPI accepts
+    Classifiers,
+    /// The newest CPython and PyPy versions
+    PythonVersions,
+}
+
+impl Dataset {
+    const ALL: [Dataset; 4] = [
+        Dataset::ManylinuxPolicy,
+        Dataset::MusllinuxPolicy,
+        Dataset::Classifiers,
+        Dataset::PythonVersions,
+    ];
+
+    /// The name of the file in the data directory
+    fn file_name(self) -> &'static str {
+        match self {
+            Dataset::ManylinuxPolicy => "manylinux-policy.json",
+            Dataset::MusllinuxPolicy => "musllinux-policy.json",
+            Dataset::Classifiers => "classifiers.txt",
+            Dataset::PythonVersions => "python-versions.json",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Dataset::ManylinuxPolicy => "manylinux policies",
+            Dataset::MusllinuxPolicy => "musllinux policies",
+            Dataset::Classifiers => "trove classifiers",
+            Dataset::PythonVersions => "python versions",
+        }
+    }
+
+    /// Where the dataset is downloaded from
+    #[cfg_attr(not(feature = "upload"), allow(dead_code))]
+    fn urls(self) -> &'static [&'static str] {
+        match self {
+            Dataset::ManylinuxPolicy => &["https://raw.githubusercontent.com/pypa/auditwheel/main/src/auditwheel/policy/manylinux-policy.json"],
+            Dataset::MusllinuxPolicy => &["https://raw.githubusercontent.com/pypa/auditwheel/main/src/auditwheel/policy/musllinux-policy.json"],
+            Dataset::Classifiers => &["https://pypi.org/pypi?%3Aaction=list_classifiers"],
+            Dataset::PythonVersions => &[
+                "https://endoflife.date/api/python.json",
+                "https://downloads.python.org/pypy/versions.json",
+            ],
+        }
+    }
+
+    /// Turns the downloaded documents, one for each url, into the content of the data file
+    #[cfg_attr(not(feature = "upload"), allow(dead_code))]
+    fn convert(self, mut documents: Vec<String>) -> Result<String> {
+        match self {
+            Dataset::PythonVersions => {
+                let versions = PythonVersions::from_upstream(&documents[0], &documents[1])?;
+                Ok(serde_json::to_string_pretty(&versions)?)
+            }
+            _ => Ok(documents.remove(0)),
+        }
+    }
+
+    /// Checks that the content of a data file can be used
+    fn validate(self, content: &str) -> Result<()> {
+        match self {
+            Dataset::ManylinuxPolicy | Dataset::MusllinuxPolicy => {
+                let policies: Vec<crate::auditwheel::Policy> = serde_json::from_str(content)?;
+                if policies.is_empty() {
+                    bail!("There are no policies");
+                }
+                let prefix = if self == Dataset::ManylinuxPolicy {
+                    "manylinux_"
+                } else {
+                    "musllinux_"
+                };
+                for policy in &policies {
+                    // The linux policy is the fallback without a manylinux tag
+                    if policy.name != "linux"
+                        && (!policy.name.starts_with(prefix)
+                            || policy.name.parse::<crate::PlatformTag>().is_err())
+                    {
+                        bail!("`{}` is not a valid {} tag", policy.name, &prefix[..9]);
+                    }
+                }
+            }
+            Dataset::Classifiers => {
+                let classifiers: Vec<&str> = content.lines().collect();
+                if !classifiers.contains(&"Programming Language :: Python") {
+                    bail!("This is not a list of classifiers");
+                }
+                if let Some(invalid) = classifiers.iter().find(|x| !x.contains(" :: ")) {
+                    bail!("`{}` is not a classifier", invalid);
+                }
+            }
+            Dataset::PythonVersions => {
+                serde_json::from_str::<PythonVersions>(content)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The newest python versions to look for when searching interpreters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct PythonVersions {
+    /// The highest minor version of CPython 3
+    pub(crate) cpython: usize,
+    /// The highest minor version of python 3 implemented by PyPy
+    pub(crate) pypy: usize,
+}
+
+impl PythonVersions {
+    /// Reads the release cycles from endoflife.date and the PyPy releases from
+    /// downloads.python.org
+    #[cfg_attr(not(feature = "upload"), allow(dead_code))]
+    fn from_upstream(cpython_cycles: &str, pypy_releases: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Cycle {
+            cycle: String,
+        }
+        #[derive(Deserialize)]
+        struct PyPyRelease {
+            python_version: String,
+        }
+
+        let minor = |version: &str| -> Option<usize> {
+            version.strip_prefix("3.")?.split('.').next()?.parse().ok()
+        };
+        let cycles: Vec<Cycle> = serde_json::from_str(cpython_cycles)
+            .context("Failed to parse the CPython release cycles")?;
+        let pypy_releases: Vec<PyPyRelease> =
+            serde_json::from_str(pypy_releases).context("Failed to parse the PyPy releases")?;
+        let cpython = cycles
+            .iter()
+            .filter_map(|cycle| minor(&cycle.cycle))
+            .max()
+            .context("There are no CPython 3 release cycles")?;
+        let pypy = pypy_releases
+            .iter()
+            .filter_map(|release| minor(&release.python_version))
+            .max()
+            .context("There are no PyPy 3 releases")?;
+        Ok(Self {
+            // Be liberal and also look for the next version, which is usually in preview
+            cpython: cpython + 1,
+            pypy,
+        })
+    }
+}
+
+/// The directory of the refreshed data
+fn data_dir() -> Option<PathBuf> {
+    match env::var_os(DATA_DIR_ENV_VAR) {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => dirs::cache_dir().map(|dir| dir.join("maturin").join("data")),
+    }
+}
+
+/// Reads the refreshed copy of a dataset, `None` if there is none or it can't be used. The
+/// callers combine it with the built-in data, so that data a newer maturin ships with isn't lost
+/// to an older refresh.
+pub(crate) fn read_refreshed(dataset: Dataset) -> Option<String> {
+    let path = data_dir()?.join(dataset.file_name());
+    let content = fs::read_to_string(&path).ok()?;
+    match dataset.validate(&content) {
+        Ok(()) => Some(content),
+        Err(err) => {
+            eprintln!(
+                "⚠️  Warning: Ignoring the refreshed {} in {}: {}. \
+                Run `maturin self update-data` to download them again",
+                dataset.description(),
+                path.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+#[cfg(feature = "upload")]
+fn download(dataset: Dataset) -> Result<String> {
+    let agent = crate::upload::http_agent()?;
+    let documents = dataset
+        .urls()
+        .iter()
+        .map(|url| {
+            agent
+                .get(url)
+                .set(
+                    "User-Agent",
+                    &format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+                )
+                .call()
+                .map_err(anyhow::Error::from)
+                .and_then(|response| Ok(response.into_string()?))
+                .with_context(|| format!("Failed to download {}", url))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    dataset.convert(documents)
+}
+
+#[cfg(not(feature = "upload"))]
+fn download(_dataset: Dataset) -> Result<String> {
+    bail!(
+        "maturin was built without the upload feature and can't download the data, \
+        use `--from` with a directory containing the data files instead"
+    )
+}
+
+/// Refreshes the datasets in `data_dir`, from the same files in `from` or from upstream
+fn update_data_dir(data_dir: &Path, from: Option<&Path>) -> Result<()> {
+    let mut contents = Vec::new();
+    for dataset in Dataset::ALL {
+        let content = match from {
+            Some(from) => {
+                let path = from.join(dataset.file_name());
+                if !path.is_file() {
+                    eprintln!(
+                        "⚠️  Warning: {} doesn't exist, keeping the current {}",
+                        path.display(),
+                        dataset.description()
+                    );
+                    continue;
+                }
+                fs::read_to_string(&path)?
+            }
+            None => download(dataset)?,
+        };
+        dataset
+            .validate(&content)
+            .with_context(|| format!("The new {} are invalid", dataset.description()))?;
+        contents.push((dataset, content));
+    }
+    if contents.is_empty() {
+        bail!("None of the data files were found");
+    }
+
+    // Only write once everything was fetched, so a failed download doesn't leave a mix behind
+    fs::create_dir_all(data_dir)?;
+    for (dataset, content) in contents {
+        fs::write(data_dir.join(dataset.file_name()), content)?;
+        println!("📥 Updated the {}", dataset.description());
+    }
+    Ok(())
+}
+
+/// Refreshes the built-in datasets into the user cache, or removes them again with `--reset`
+pub fn update_data(options: UpdateDataOptions) -> Result<()> {
+    let data_dir = data_dir().with_context(|| {
+        format!(
+            "Failed to find the cache directory, set {} to a directory for the data",
+            DATA_DIR_ENV_VAR
+        )
+    })?;
+    if options.reset {
+        for dataset in Dataset::ALL {
+            let path = data_dir.join(dataset.file_name());
+            if path.is_file() {
+                fs::remove_file(path)?;
+            }
+        }
+        println!("🧹 Removed the refreshed data, maturin uses its built-in data again");
+        return Ok(());
+    }
+    update_data_dir(&data_dir, options.from.as_deref())?;
+    println!("✨ Wrote the refreshed data to {}", data_dir.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_python_versions_from_upstream() {
+        let cycles = r#"[
+            {"cycle": "3.12", "releaseDate": "2023-10-02", "latest": "3.12.0"},
+            {"cycle": "3.11", "releaseDate": "2022-10-24", "latest": "3.11.6"},
+            {"cycle": "2.7", "releaseDate": "2010-07-03", "latest": "2.7.18"}
+        ]"#;
+        let pypy = r#"[
+            {"pypy_version": "7.3.13", "python_version": "3.10.13", "stable": true},
+            {"pypy_version": "7.3.13", "python_version": "3.9.18", "stable": true},
+            {"pypy_version": "7.3.13", "python_version": "2.7.18", "stable": true}
+        ]"#;
+        assert_eq!(
+            PythonVersions::from_upstream(cycles, pypy).unwrap(),
+            PythonVersions {
+                cpython: 13,
+                pypy: 10
+            }
+        );
+        assert!(PythonVersions::from_upstream("[]", pypy).is_err());
+    }
+
+    #[test]
+    fn test_update_data_dir() {
+        let from = tempfile::tempdir().unwrap();
+        let data_dir = tempfile::tempdir().unwrap();
+        fs::write(
+            from.path().join("manylinux-policy.json"),
+            include_str!("auditwheel/manylinux-policy.json"),
+        )
+        .unwrap();
+        fs::write(
+            from.path().join("classifiers.txt"),
+            "Programming Language :: Python\nProgramming Language :: Rust\n",
+        )
+        .unwrap();
+        update_data_dir(data_dir.path(), Some(from.path())).unwrap();
+        assert!(data_dir.path().join("manylinux-policy.json").is_file());
+        assert!(data_dir.path().join("classifiers.txt").is_file());
+        assert!(!data_dir.path().join("python-versions.json").exists());
+
+        // Nothing is written if any of the files is invalid
+        fs::write(from.path().join("python-versions.json"), "{}").unwrap();
+        assert!(update_data_dir(data_dir.path(), Some(from.path())).is_err());
+        assert!(!data_dir.path().join("python-versions.json").exists());
+
+        fs::write(
+            from.path().join("musllinux-policy.json"),
+            r#"[{"name": "manylinux_2_17"}]"#,
+        )
+        .unwrap();
+        assert!(Dataset::MusllinuxPolicy
+            .validate(&fs::read_to_string(from.path().join("musllinux-policy.json")).unwrap())
+            .is_err());
+    }
+}
@@ -1,13 +1,23 @@
 use crate::build_options::CargoOptions;
+use crate::file_lock::FileLock;
+use crate::marker::{normalize_extra, references_extra, MarkerEnvironment};
+use crate::python_interpreter::mingw_target_triple;
 use crate::target::Arch;
 use crate::BuildOptions;
 use crate::PlatformTag;
 use crate::PythonInterpreter;
 use crate::Target;
+use crate::{Metadata21, WheelReader};
 use anyhow::{anyhow, bail, Context, Result};
-use std::path::Path;
+use fs_err as fs;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 use tempfile::TempDir;
+use tracing::debug;
 
 /// Installs a crate by compiling it and copying the shared library to site-packages.
 /// Also adds the dist-info directory to make sure pip and other tools detect the library
@@ -16,16 +26,34 @@ use tempfile::TempDir;
 #[allow(clippy::too_many_arguments)]
 pub fn develop(
     bindings: Option<String>,
-    cargo_options: CargoOptions,
+    mut cargo_options: CargoOptions,
     venv_dir: &Path,
     release: bool,
     strip: bool,
     extras: Vec<String>,
 ) -> Result<()> {
+    if cargo_options.targets.len() > 1 {
+        bail!("maturin develop can only build for a single target");
+    }
+    if cargo_options.target.is_none() {
+        cargo_options.target = cargo_options.targets.pop();
+    }
     let mut target_triple = cargo_options.target.as_ref().map(|x| x.to_string());
-    let target = Target::from_target_triple(cargo_options.target)?;
+    let target = Target::from_target_triple(cargo_options.target.clone())?;
     let python = target.get_venv_python(venv_dir);
 
+    // Concurrent pip installs into one venv corrupt the RECORD files, so the lock covers the
+    // dependencies as well as the build, which also copies the native library into the source
+    // tree for editable installs
+    let _lock = FileLock::acquire(
+        venv_dir.join(".maturin-develop.lock"),
+        DEVELOP_LOCK_TIMEOUT,
+        &format!(
+            "another maturin develop to finish installing into {}",
+            venv_dir.display()
+        ),
+    )?;
+
     // check python platform and architecture
     if !target.user_specified {
         match Command::new(&python)
@@ -41,6 +69,16 @@ pub fn develop(
                     } else if platform.contains("arm64") && target.target_arch() != Arch::Aarch64 {
                         target_triple = Some("aarch64-apple-darwin".to_string());
                     }
+                } else if let Some(gnu_target) = mingw_target_triple(&platform) {
+                    // The MinGW pythons of MSYS2 need extensions built with the MinGW toolchain
+                    if !target.is_windows_gnu() {
+                        eprintln!(
+                            "🐍 {} is a MinGW python, building for {}",
+                            python.display(),
+                            gnu_target
+                        );
+                        target_triple = Some(gnu_target.to_string());
+                    }
                 }
             }
             _ => eprintln!("⚠️  Warning: Failed to determine python platform"),
@@ -57,41 +95,55 @@ pub fn develop(
         bindings,
         out: Some(wheel_dir.path().to_path_buf()),
         skip_auditwheel: false,
+        fallback_to_linux: false,
+        repair: false,
         zig: false,
+        use_container: false,
         universal2: false,
+        macos_deployment_target: None,
+        build_tag: None,
+        build_dir: None,
+        dist_name: None,
+        sdist_from_git: false,
+        include_cargo_lock: None,
         cargo: CargoOptions {
             target: target_triple,
             ..cargo_options
         },
     };
 
+    build_options.run_pre_build_hooks(&["wheel"])?;
     let build_context = build_options.into_build_context(release, strip, true)?;
+    // The python runs with the variables of `[tool.maturin.env]` like the build
+    let env = &build_context.env;
+    let platlib = get_platlib(&python, env)?;
 
-    let interpreter = PythonInterpreter::check_executable(&python, &target, &build_context.bridge)?
-        .ok_or_else(|| {
-            anyhow!("Expected `python` to be a python interpreter inside a virtualenv ಠ_ಠ")
-        })?;
+    let interpreter =
+        PythonInterpreter::check_executable(&python, &build_context.target, &build_context.bridge)?
+            .ok_or_else(|| {
+                anyhow!("Expected `python` to be a python interpreter inside a virtualenv ಠ_ಠ")
+            })?;
 
     // Install dependencies
-    if !build_context.metadata21.requires_dist.is_empty() {
+    let environment = MarkerEnvironment::from_interpreter(&interpreter.executable, env)?;
+    let requirements = develop_requirements(&build_context.metadata21, &extras, &environment)?;
+    if !requirements.is_empty() {
+        if !extras.is_empty() {
+            println!(
+                "📦 Installing the dependencies with the extras {}",
+                extras.join(", ")
+            );
+        }
         let mut args = vec![
             "-m".to_string(),
             "pip".to_string(),
             "install".to_string(),
             "--disable-pip-version-check".to_string(),
         ];
-        args.extend(build_context.metadata21.requires_dist.iter().map(|x| {
-            let mut pkg = x.clone();
-            // Remove extra marker to make it installable with pip
-            for extra in &extras {
-                pkg = pkg
-                    .replace(&format!(" and extra == '{}'", extra), "")
-                    .replace(&format!("; extra == '{}'", extra), "");
-            }
-            pkg
-        }));
+        args.extend(requirements);
         let status = Command::new(&interpreter.executable)
             .args(&args)
+            .envs(env)
             .status()
             .context("Failed to run pip install")?;
         if !status.success() {
@@ -101,35 +153,25 @@ pub fn develop(
 
     let wheels = build_context.build_wheels()?;
     for (filename, _supported_version) in wheels.iter() {
-        let command = [
-            "-m",
-            "pip",
-            "--disable-pip-version-check",
-            "install",
-            "--no-deps",
-            "--force-reinstall",
-        ];
-        let output = Command::new(&python)
-            .args(command)
-            .arg(dunce::simplified(filename))
-            .output()
-            .context(format!("pip install failed with {:?}", python))?;
-        if !output.status.success() {
-            bail!(
-                "pip install in {} failed running {:?}: {}\n--- Stdout:\n{}\n--- Stderr:\n{}\n---\n",
-                venv_dir.display(),
-                &command,
-                output.status,
-                String::from_utf8_lossy(&output.stdout).trim(),
-                String::from_utf8_lossy(&output.stderr).trim(),
-            );
-        }
-        if !output.stderr.is_empty() {
+        pip_install_wheel(&python, venv_dir, filename, env)?;
+        if !corrupted_native_libraries(filename, &platlib)?.is_empty() {
             eprintln!(
-                "⚠️  Warning: pip raised a warning running {:?}:\n{}",
-                &command,
-                String::from_utf8_lossy(&output.stderr).trim(),
+                "⚠️  Warning: The installed native libraries don't match the wheel, reinstalling"
             );
+            pip_install_wheel(&python, venv_dir, filename, env)?;
+            let corrupted = corrupted_native_libraries(filename, &platlib)?;
+            if !corrupted.is_empty() {
+                bail!(
+                    "The installed copies of {} don't match the built wheel after reinstalling, \
+                    check the filesystem of {}",
+                    corrupted
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    venv_dir.display()
+                );
+            }
         }
         println!(
             "🛠  Installed {}-{}",
@@ -139,3 +181,251 @@ pub fn develop(
 
     Ok(())
 }
+
+/// Returns the requirements to install, which are the required dependencies and those of the
+/// selected extras whose environment markers match the interpreter. Pip can't install
+/// requirements with extra markers directly, so the markers are evaluated here.
+fn develop_requirements(
+    metadata21: &Metadata21,
+    extras: &[String],
+    environment: &MarkerEnvironment,
+) -> Result<Vec<String>> {
+    let provided: Vec<String> = metadata21
+        .provides_extra
+        .iter()
+        .map(|extra| normalize_extra(extra))
+        .collect();
+    let extras: Vec<String> = extras.iter().map(|extra| normalize_extra(extra)).collect();
+    for extra in &extras {
+        if !provided.contains(extra) {
+            if metadata21.provides_extra.is_empty() {
+                bail!(
+                    "The extra {} doesn't exist, the package has no extras",
+                    extra
+                );
+            }
+            bail!(
+                "The extra {} doesn't exist, available extras are {}",
+                extra,
+                metadata21.provides_extra.join(", ")
+            );
+        }
+    }
+
+    let mut requirements = Vec::new();
+    for requirement in &metadata21.requires_dist {
+        let (dependency, marker) = match requirement.split_once(';') {
+            Some((dependency, marker)) => (dependency.trim(), marker.trim()),
+            None => (requirement.trim(), ""),
+        };
+        if marker.is_empty() {
+            requirements.push(dependency.to_string());
+            continue;
+        }
+        // Required dependencies match without an extra, the others with one of the selected ones
+        let matches = std::iter::once("")
+            .chain(extras.iter().map(String::as_str))
+            .map(|extra| environment.evaluate(marker, extra))
+            .find(|matches| !matches!(matches, Ok(false)))
+            .unwrap_or(Ok(false));
+        match matches {
+            Ok(true) => requirements.push(dependency.to_string()),
+            Ok(false) => debug!("Skipping {}, its marker doesn't match", requirement),
+            Err(err) if !references_extra(marker) => {
+                // Leave it to pip, which may know markers we don't
+                debug!("Failed to evaluate the marker of {}: {}", requirement, err);
+                requirements.push(format!("{}; {}", dependency, marker));
+            }
+            Err(err) => eprintln!(
+                "⚠️  Warning: Skipping {}, failed to evaluate its marker: {}",
+                requirement, err
+            ),
+        }
+    }
+    Ok(requirements)
+}
+
+/// How long to wait for another `maturin develop` installing into the same virtualenv
+const DEVELOP_LOCK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Installs the wheel into the virtualenv with pip, replacing an existing installation
+fn pip_install_wheel(
+    python: &Path,
+    venv_dir: &Path,
+    filename: &Path,
+    env: &BTreeMap<String, String>,
+) -> Result<()> {
+    let command = [
+        "-m",
+        "pip",
+        "--disable-pip-version-check",
+        "install",
+        "--no-deps",
+        "--force-reinstall",
+    ];
+    let output = Command::new(python)
+        .args(command)
+        .arg(dunce::simplified(filename))
+        .envs(env)
+        .output()
+        .context(format!("pip install failed with {:?}", python))?;
+    if !output.status.success() {
+        bail!(
+            "pip install in {} failed running {:?}: {}\n--- Stdout:\n{}\n--- Stderr:\n{}\n---\n",
+            venv_dir.display(),
+            &command,
+            output.status,
+            String::from_utf8_lossy(&output.stdout).trim(),
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+    if !output.stderr.is_empty() {
+        eprintln!(
+            "⚠️  Warning: pip raised a warning running {:?}:\n{}",
+            &command,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+    Ok(())
+}
+
+/// Prints the directory pip installs native modules into as json. Outside of a virtualenv,
+/// Windows Store Pythons install into the user site-packages because their install directory
+/// is protected, which is impossible if the user site-packages are disabled.
+const PLATLIB_SCRIPT: &str = r#"
+import json, site, sys, sysconfig
+store = sys.prefix == sys.base_prefix and "\\windowsapps\\" in sys.base_prefix.lower()
+if store and site.ENABLE_USER_SITE:
+    platlib = site.getusersitepackages()
+else:
+    platlib = sysconfig.get_paths()["platlib"]
+print(json.dumps({"platlib": platlib, "protected": store and not site.ENABLE_USER_SITE}))
+"#;
+
+#[derive(Deserialize)]
+struct Platlib {
+    platlib: PathBuf,
+    protected: bool,
+}
+
+/// Returns the site-packages directory for platform specific packages of the interpreter
+fn get_platlib(python: &Path, env: &BTreeMap<String, String>) -> Result<PathBuf> {
+    let output = Command::new(python)
+        .arg("-c")
+        .arg(PLATLIB_SCRIPT)
+        .envs(env)
+        .output()
+        .context(format!("Failed to run {:?}", python))?;
+    if !output.status.success() {
+        bail!(
+            "Failed to determine the site-packages directory of {:?}: {}",
+            python,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let platlib: Platlib = serde_json::from_slice(&output.stdout)
+        .context("Failed to parse the site-packages directory")?;
+    if platlib.protected {
+        bail!(
+            "{} belongs to a Windows Store Python, which doesn't allow installing packages into \
+            its install directory {}. Please create a virtualenv with `python -m venv .venv`, \
+            activate it and run maturin develop again",
+            python.display(),
+            platlib.platlib.display()
+        );
+    }
+    Ok(platlib.platlib)
+}
+
+/// Compares the native libraries installed into `platlib` with those in the wheel and returns
+/// the installed copies that differ, e.g. because a network filesystem corrupted them
+fn corrupted_native_libraries(wheel_path: &Path, platlib: &Path) -> Result<Vec<PathBuf>> {
+    let wheel = WheelReader::open(wheel_path)?;
+    let mut corrupted = Vec::new();
+    for (name, content) in wheel.entries() {
+        let is_native_library = [".so", ".pyd", ".dll", ".dylib"]
+            .iter()
+            .any(|extension| name.ends_with(extension));
+        // Files in the `.data` directory are installed to other locations
+        let top_level = name.split('/').next().unwrap_or_default();
+        if !is_native_library || top_level.ends_with(".data") {
+            continue;
+        }
+        let installed = platlib.join(name);
+        let matches = fs::read(&installed)
+            .map(|installed| Sha256::digest(installed) == Sha256::digest(content))
+            .unwrap_or(false);
+        if !matches {
+            corrupted.push(installed);
+        }
+    }
+    Ok(corrupted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::module_writer::{test_metadata, test_wheel_writer};
+    use crate::ModuleWriter;
+
+    #[test]
+    fn test_develop_requirements() {
+        let environment = MarkerEnvironment::linux_cpython_310();
+        let metadata21 = Metadata21 {
+            requires_dist: vec![
+                "cffi".to_string(),
+                "tomli; python_version < '3.11'".to_string(),
+                "pywin32; sys_platform == 'win32'".to_string(),
+                "pytest; extra == 'tests'".to_string(),
+                "pytest-xdist; (sys_platform != 'win32') and extra == 'tests'".to_string(),
+                "sphinx; extra == \"docs\"".to_string(),
+                "typing-extensions; extra == 'tests' and python_version < '3.8'".to_string(),
+                "furo; extra == 'tests' or extra == 'docs'".to_string(),
+            ],
+            provides_extra: vec!["tests".to_string(), "Docs".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            develop_requirements(&metadata21, &[], &environment).unwrap(),
+            ["cffi", "tomli"]
+        );
+        assert_eq!(
+            develop_requirements(&metadata21, &["tests".to_string()], &environment).unwrap(),
+            ["cffi", "tomli", "pytest", "pytest-xdist", "furo"]
+        );
+        assert_eq!(
+            develop_requirements(&metadata21, &["docs".to_string()], &environment).unwrap(),
+            ["cffi", "tomli", "sphinx", "furo"]
+        );
+        let err =
+            develop_requirements(&metadata21, &["doc".to_string()], &environment).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The extra doc doesn't exist, available extras are tests, Docs"
+        );
+    }
+
+    #[test]
+    fn test_corrupted_native_libraries() {
+        let tmp_dir = TempDir::new().unwrap();
+        let metadata = test_metadata();
+        let mut writer =
+            test_wheel_writer(tmp_dir.path(), &metadata, "cp37-abi3-linux_x86_64").unwrap();
+        writer.add_bytes("foo/foo.abi3.so", b"native").unwrap();
+        writer.add_bytes("foo/__init__.py", b"").unwrap();
+        let wheel_path = writer.finish().unwrap();
+
+        let platlib = tmp_dir.path().join("site-packages");
+        fs::create_dir_all(platlib.join("foo")).unwrap();
+        fs::write(platlib.join("foo").join("foo.abi3.so"), b"native").unwrap();
+        assert!(corrupted_native_libraries(&wheel_path, &platlib)
+            .unwrap()
+            .is_empty());
+
+        fs::write(platlib.join("foo").join("foo.abi3.so"), b"nat\0ve").unwrap();
+        assert_eq!(
+            corrupted_native_libraries(&wheel_path, &platlib).unwrap(),
+            [platlib.join("foo/foo.abi3.so")]
+        );
+    }
+}
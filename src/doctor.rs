@@ -0,0 +1,230 @@
+//! Checks the build environment for known problems with a target, so they show up before a
+//! failing or, worse, a subtly broken build
+use crate::build_options::is_generating_import_lib;
+use crate::check::Status;
+use crate::Target;
+use anyhow::{bail, Context, Result};
+use cargo_metadata::MetadataCommand;
+use std::env;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Options for `maturin doctor`
+#[derive(Debug, clap::Parser)]
+pub struct DoctorOptions {
+    /// The target triple to check, defaults to the host
+    #[arg(long, value_name = "TRIPLE", env = "CARGO_BUILD_TARGET")]
+    target: Option<String>,
+    /// The path to the Cargo.toml, used to check the features of pyo3
+    #[arg(short = 'm', long = "manifest-path", value_name = "PATH")]
+    manifest_path: Option<PathBuf>,
+}
+
+/// The result of a single check of the build environment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Diagnostic {
+    pub(crate) status: Status,
+    pub(crate) title: String,
+    /// What the problem means and how to fix it
+    pub(crate) details: Vec<String>,
+}
+
+impl Diagnostic {
+    fn new(status: Status, title: impl Into<String>, details: &[&str]) -> Self {
+        Self {
+            status,
+            title: title.into(),
+            details: details.iter().map(|x| x.to_string()).collect(),
+        }
+    }
+}
+
+/// What is known about the environment for the windows-gnu checks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WindowsGnuEnvironment {
+    /// The linker, `None` if it doesn't need to be checked because rustup ships it
+    pub(crate) linker: Option<(String, bool)>,
+    /// Whether pyo3 generates the import libraries, `None` without a project
+    pub(crate) generate_import_lib: Option<bool>,
+    /// Whether `PYO3_CROSS_LIB_DIR` is set
+    pub(crate) cross_lib_dir: bool,
+}
+
+/// CPython for Windows is built with MSVC, while the windows-gnu targets use MinGW. Both share
+/// the C ABI of the python DLL, which is all pyo3 needs, but not the C runtime.
+pub(crate) fn windows_gnu_diagnostics(
+    target: &Target,
+    environment: &WindowsGnuEnvironment,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![Diagnostic::new(
+        Status::Warn,
+        format!(
+            "{} links against the MinGW instead of the MSVC C runtime of python",
+            target.target_triple()
+        ),
+        &[
+            "Extensions only share the C ABI of the python DLL with the interpreter, C runtime \
+            objects such as `FILE*` or memory from `malloc` must not be passed between them",
+            "The MinGW runtime DLLs (libgcc_s, libwinpthread, libstdc++) aren't installed with \
+            python, C and C++ dependencies must link them statically, e.g. with \
+            `-C link-arg=-static`, or the module fails to import",
+            "Use the matching `-pc-windows-msvc` target if you don't need MinGW",
+        ],
+    )];
+    if let Some((linker, found)) = &environment.linker {
+        diagnostics.push(if *found {
+            Diagnostic::new(Status::Pass, format!("Found the linker {}", linker), &[])
+        } else {
+            Diagnostic::new(
+                Status::Fail,
+                format!("The linker {} is missing", linker),
+                &[
+                    "Install mingw-w64, e.g. `apt install mingw-w64` or `brew install mingw-w64`",
+                    "A different linker can be set with CARGO_TARGET_<TRIPLE>_LINKER",
+                ],
+            )
+        });
+    }
+    if target.cross_compiling() {
+        diagnostics.push(match environment.generate_import_lib {
+            _ if environment.cross_lib_dir => Diagnostic::new(
+                Status::Pass,
+                "PYO3_CROSS_LIB_DIR points to the python import libraries",
+                &[],
+            ),
+            Some(true) => Diagnostic::new(
+                Status::Pass,
+                "pyo3 generates the python import libraries (`.dll.a`)",
+                &[],
+            ),
+            Some(false) => Diagnostic::new(
+                Status::Fail,
+                "There are no import libraries to link against python",
+                &["Enable the `generate-import-lib` feature of pyo3, \
+                    or set PYO3_CROSS_LIB_DIR to the `libs` directory of a windows python"],
+            ),
+            None => Diagnostic::new(
+                Status::Warn,
+                "Couldn't check how pyo3 links against python without a project",
+                &["Run `maturin doctor` in the project or pass --manifest-path"],
+            ),
+        });
+    }
+    diagnostics
+}
+
+/// Returns the linker that has to be installed to build for a windows-gnu target and whether it
+/// was found
+fn windows_gnu_linker(target: &Target) -> Option<(String, bool)> {
+    let triple = target.target_triple();
+    let linker_env = format!(
+        "CARGO_TARGET_{}_LINKER",
+        triple.to_uppercase().replace('-', "_")
+    );
+    let linker = match env::var(linker_env) {
+        Ok(linker) => linker,
+        // rustup ships a self-contained MinGW linker on windows
+        Err(_) if cfg!(windows) => return None,
+        Err(_) => format!("{}-w64-mingw32-gcc", triple.split('-').next().unwrap()),
+    };
+    let found = Command::new(&linker)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok();
+    Some((linker, found))
+}
+
+/// Checks the environment and prints the problems, fails if any of them prevent building
+pub fn doctor(options: DoctorOptions) -> Result<()> {
+    let target = Target::from_target_triple(options.target)?;
+    let manifest_path = options
+        .manifest_path
+        .or_else(|| Some(PathBuf::from("Cargo.toml")).filter(|x| x.is_file()));
+    let cargo_metadata = match manifest_path {
+        Some(manifest_path) => Some(
+            MetadataCommand::new()
+                .manifest_path(&manifest_path)
+                .exec()
+                .with_context(|| format!("Failed to run cargo metadata for {:?}", manifest_path))?,
+        ),
+        None => None,
+    };
+
+    let mut diagnostics = Vec::new();
+    if target.is_windows_gnu() {
+        let environment = WindowsGnuEnvironment {
+            linker: windows_gnu_linker(&target),
+            generate_import_lib: cargo_metadata
+                .as_ref()
+                .map(is_generating_import_lib)
+                .transpose()?,
+            cross_lib_dir: env::var_os("PYO3_CROSS_LIB_DIR").is_some(),
+        };
+        diagnostics.extend(windows_gnu_diagnostics(&target, &environment));
+    }
+
+    if diagnostics.is_empty() {
+        eprintln!("✅ No known problems for {}", target.target_triple());
+        return Ok(());
+    }
+    for diagnostic in &diagnostics {
+        let icon = match diagnostic.status {
+            Status::Pass => "✅",
+            Status::Warn => "⚠️ ",
+            Status::Fail => "❌",
+        };
+        eprintln!("{} {}", icon, diagnostic.title);
+        for detail in &diagnostic.details {
+            eprintln!("  - {}", detail);
+        }
+    }
+    let failed = diagnostics
+        .iter()
+        .filter(|x| x.status == Status::Fail)
+        .count();
+    if failed > 0 {
+        bail!(
+            "Found {} problem(s) building for {}",
+            failed,
+            target.target_triple()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_windows_gnu_diagnostics() {
+        let target = Target::from_target_triple(Some("x86_64-pc-windows-gnu".to_string())).unwrap();
+        let statuses = |environment: &WindowsGnuEnvironment| -> Vec<Status> {
+            windows_gnu_diagnostics(&target, environment)
+                .into_iter()
+                .map(|x| x.status)
+                .collect()
+        };
+        let mut environment = WindowsGnuEnvironment {
+            linker: Some(("x86_64-w64-mingw32-gcc".to_string(), true)),
+            generate_import_lib: Some(true),
+            cross_lib_dir: false,
+        };
+        assert_eq!(
+            statuses(&environment),
+            [Status::Warn, Status::Pass, Status::Pass]
+        );
+        environment.generate_import_lib = Some(false);
+        environment.linker = Some(("x86_64-w64-mingw32-gcc".to_string(), false));
+        assert_eq!(
+            statuses(&environment),
+            [Status::Warn, Status::Fail, Status::Fail]
+        );
+        environment.cross_lib_dir = true;
+        environment.linker = None;
+        assert_eq!(statuses(&environment), [Status::Warn, Status::Pass]);
+    }
+}
@@ -0,0 +1,707 @@
+//! Computes the fields listed in `project.dynamic` with the hooks from `[tool.maturin.dynamic]`
+//! and the version from `[tool.maturin.version]`
+use crate::module_writer::source_date_epoch;
+use crate::pyproject_toml::{DynamicHook, VersionConfig, VersionSource};
+use crate::{Metadata21, PyProjectToml};
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// The `[project]` fields which can be computed by a hook
+const DYNAMIC_FIELDS: &[&str] = &["version", "description", "readme", "requires-python"];
+
+/// Imports `module`, looks up the dotted `name` and prints its value, calling it if it's callable
+const CALLABLE_SCRIPT: &str = r#"
+import importlib
+import sys
+
+obj = importlib.import_module(sys.argv[1])
+for name in sys.argv[2].split("."):
+    obj = getattr(obj, name)
+if callable(obj):
+    obj = obj()
+sys.stdout.write(str(obj))
+"#;
+
+/// Checks the hooks from `[tool.maturin.dynamic]` and computes the version if
+/// `[tool.maturin.version]` is set. The hooks themselves only run when building, see
+/// [run_dynamic_hooks].
+///
+/// Every field with a hook must be listed in `project.dynamic`. Fields listed in
+/// `project.dynamic` without a hook keep the value from Cargo.toml.
+pub fn resolve_dynamic_metadata(
+    metadata21: &mut Metadata21,
+    pyproject_dir: &Path,
+    pyproject: &PyProjectToml,
+) -> Result<()> {
+    let dynamic = pyproject
+        .project
+        .as_ref()
+        .and_then(|project| project.dynamic.as_ref());
+    let sdist_version = sdist_version(pyproject_dir)?;
+    if let Some(version_config) = pyproject.version_config() {
+        if !dynamic.map_or(false, |dynamic| {
+            dynamic.iter().any(|field| field == "version")
+        }) {
+            bail!("tool.maturin.version is set, but version is not listed in project.dynamic");
+        }
+        if pyproject
+            .dynamic_hooks()
+            .map_or(false, |hooks| hooks.contains_key("version"))
+        {
+            bail!("tool.maturin.version and tool.maturin.dynamic.version can't be used together");
+        }
+        metadata21.version =
+            resolve_version(version_config, sdist_version.as_deref(), pyproject_dir)?;
+    }
+    let hooks = match pyproject.dynamic_hooks() {
+        Some(hooks) => hooks,
+        None => return Ok(()),
+    };
+    for (field, hook) in hooks {
+        if !DYNAMIC_FIELDS.contains(&field.as_str()) {
+            bail!(
+                "tool.maturin.dynamic.{} is not supported, the supported fields are {}",
+                field,
+                DYNAMIC_FIELDS.join(", ")
+            );
+        }
+        if !dynamic.map_or(false, |dynamic| dynamic.contains(field)) {
+            bail!(
+                "tool.maturin.dynamic.{} is set, but {} is not listed in project.dynamic",
+                field,
+                field
+            );
+        }
+        if hook.content_type.is_some() && field != "readme" {
+            bail!(
+                "tool.maturin.dynamic.{}.content-type is only supported for readme",
+                field
+            );
+        }
+        if let (Some(version), "version") = (&sdist_version, field.as_str()) {
+            debug!("Using the version {} from PKG-INFO", version);
+            metadata21.version = version.clone();
+        }
+    }
+    Ok(())
+}
+
+/// The version in PKG-INFO when building from a source distribution. It's authoritative, since
+/// e.g. `git describe` doesn't work outside of the repository anymore.
+fn sdist_version(pyproject_dir: &Path) -> Result<Option<String>> {
+    let sdist_pkg_info = pyproject_dir.join("PKG-INFO");
+    if !sdist_pkg_info.is_file() {
+        return Ok(None);
+    }
+    let pkg_info = fs::read(&sdist_pkg_info)?;
+    let pkg_info = python_pkginfo::Metadata::parse(&pkg_info)
+        .with_context(|| format!("Failed to parse {}", sdist_pkg_info.display()))?;
+    Ok(Some(pkg_info.version))
+}
+
+/// Runs the hooks from `[tool.maturin.dynamic]`, which [resolve_dynamic_metadata] checked, and
+/// stores their output in the metadata. `python` runs the `callable` hooks, it's the interpreter
+/// of the build. The hooks get the variables of `[tool.maturin.env]` in `env`.
+pub(crate) fn run_dynamic_hooks(
+    metadata21: &mut Metadata21,
+    pyproject_dir: &Path,
+    pyproject: &PyProjectToml,
+    python: Option<&Path>,
+    env: &BTreeMap<String, String>,
+) -> Result<()> {
+    let hooks = match pyproject.dynamic_hooks() {
+        Some(hooks) => hooks,
+        None => return Ok(()),
+    };
+    let sdist_version = sdist_version(pyproject_dir)?;
+    for (field, hook) in hooks {
+        if sdist_version.is_some() && field == "version" {
+            continue;
+        }
+        let value = run_hook(field, hook, pyproject_dir, python, env)?;
+        eprintln!("🧮 Computed dynamic {}", field);
+        // The hooks run again when building a wheel from the source distribution, so the
+        // fields are marked as `Dynamic` in PKG-INFO. The version must not be dynamic (PEP 643),
+        // it's fixed once the source distribution is built.
+        let core_metadata_fields: &[&str] = match field.as_str() {
+            "version" => {
+                metadata21.version = value;
+                &[]
+            }
+            "description" => {
+                metadata21.summary = Some(value);
+                &["Summary"]
+            }
+            "readme" => {
+                metadata21.description = Some(value);
+                // Guessed from the contents later if missing
+                metadata21.description_content_type = hook.content_type.clone();
+                &["Description", "Description-Content-Type"]
+            }
+            "requires-python" => {
+                metadata21.requires_python = Some(value);
+                &["Requires-Python"]
+            }
+            _ => unreachable!(),
+        };
+        for core_metadata_field in core_metadata_fields {
+            if !metadata21.dynamic.iter().any(|x| x == core_metadata_field) {
+                metadata21.dynamic.push(core_metadata_field.to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Computes the version from `[tool.maturin.version]` and writes it to the `write-to` files
+fn resolve_version(
+    version_config: &VersionConfig,
+    sdist_version: Option<&str>,
+    pyproject_dir: &Path,
+) -> Result<String> {
+    let version = match (sdist_version, version_config.source) {
+        (Some(version), _) => {
+            debug!("Using the version {} from PKG-INFO", version);
+            version.to_string()
+        }
+        (None, VersionSource::Git) => {
+            let version = git_version(
+                pyproject_dir,
+                version_config.local_version,
+                &version_config.write_to,
+            )?;
+            eprintln!("🧮 Computed version {} from the git tags", version);
+            version
+        }
+    };
+    for file in &version_config.write_to {
+        let contents = match file.extension().and_then(|extension| extension.to_str()) {
+            Some("py") => format!(
+                "# Generated by maturin from the git tags, don't edit\n__version__ = \"{}\"\n",
+                version
+            ),
+            Some("rs") => format!(
+                "// Generated by maturin from the git tags, don't edit\npub const VERSION: &str = \"{}\";\n",
+                version
+            ),
+            _ => bail!(
+                "tool.maturin.version.write-to only supports .py and .rs files, got {}",
+                file.display()
+            ),
+        };
+        let path = pyproject_dir.join(file);
+        // Don't touch the file if the version didn't change, which would make cargo rebuild
+        if fs::read_to_string(&path).ok().as_deref() != Some(contents.as_str()) {
+            fs::write(&path, contents).with_context(|| {
+                format!(
+                    "Failed to write the version to {}, which tool.maturin.version.write-to \
+                     requires. In a read-only source tree, the file must already contain the \
+                     version {}",
+                    path.display(),
+                    version
+                )
+            })?;
+        }
+    }
+    Ok(version)
+}
+
+/// Runs git in the pyproject.toml directory and returns its trimmed output
+fn run_git(pyproject_dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(pyproject_dir)
+        .output()
+        .with_context(|| format!("Failed to run `git {}`", args.join(" ")))?;
+    if !output.status.success() {
+        bail!(
+            "`git {}` failed with {}, is {} a git repository with at least one commit?\n--- Stderr:\n{}",
+            args.join(" "),
+            output.status,
+            pyproject_dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+    let stdout = String::from_utf8(output.stdout).context("git returned invalid utf-8 ಠ_ಠ")?;
+    Ok(stdout.trim().to_string())
+}
+
+/// Computes the version from the latest git tag. Changes to the `write_to` files don't count as
+/// uncommitted changes, since writing the version to them would otherwise change the version.
+fn git_version(pyproject_dir: &Path, local: bool, write_to: &[PathBuf]) -> Result<String> {
+    let describe = run_git(pyproject_dir, &["describe", "--tags", "--long", "--always"])?;
+    let mut status_args = vec![
+        "status".to_string(),
+        "--porcelain".to_string(),
+        "--untracked-files=no".to_string(),
+        "--".to_string(),
+        ":/".to_string(),
+    ];
+    for file in write_to {
+        status_args.push(format!(":(exclude){}", file.display()));
+    }
+    let status_args: Vec<&str> = status_args.iter().map(String::as_str).collect();
+    let dirty = !run_git(pyproject_dir, &status_args)?.is_empty();
+    // `<tag>-<distance>-g<hash>`, or only the hash without any tag
+    let mut parts = describe.rsplitn(3, '-');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(hash), Some(distance), Some(tag)) if hash.starts_with('g') => {
+            let distance = distance
+                .parse()
+                .with_context(|| format!("Unexpected `git describe` output {}", describe))?;
+            let local = local.then(|| (hash, build_date()));
+            format_git_version(Some(tag), distance, dirty, local)
+        }
+        _ => {
+            let distance = run_git(pyproject_dir, &["rev-list", "--count", "HEAD"])?
+                .parse()
+                .context("Unexpected `git rev-list --count` output")?;
+            let hash = format!("g{}", describe);
+            let local = local.then(|| (hash.as_str(), build_date()));
+            format_git_version(None, distance, dirty, local)
+        }
+    }
+}
+
+/// Formats the version like setuptools_scm: `1.2.3` on the tag `v1.2.3` and `1.2.4.dev5` five
+/// commits later or with uncommitted changes. Without any tag, the version is
+/// `0.1.dev<number of commits>`.
+///
+/// `local` is the commit hash and the build date for the local version segment like the default
+/// scheme of setuptools_scm, `1.2.4.dev5+g1234abc` and `1.2.4.dev5+g1234abc.d20221231` with
+/// uncommitted changes.
+fn format_git_version(
+    tag: Option<&str>,
+    distance: u64,
+    dirty: bool,
+    local: Option<(&str, String)>,
+) -> Result<String> {
+    let next = match tag {
+        Some(tag) => {
+            let version = pep440::Version::parse(tag.strip_prefix('v').unwrap_or(tag))
+                .filter(|version| version.local.is_empty())
+                .with_context(|| {
+                    format!(
+                        "The git tag {} is not a valid PEP 440 version without local part",
+                        tag
+                    )
+                })?
+                .normalize();
+            if distance == 0 && !dirty {
+                return Ok(version);
+            }
+            // Guess the next version by incrementing the last number, e.g. 1.2.3 -> 1.2.4
+            // or 1.0rc1 -> 1.0rc2
+            let prefix = version.trim_end_matches(|c: char| c.is_ascii_digit());
+            let last: u64 = version[prefix.len()..].parse().with_context(|| {
+                format!("Can't guess the next version after the git tag {}", tag)
+            })?;
+            format!("{}{}", prefix, last + 1)
+        }
+        None => "0.1".to_string(),
+    };
+    let version = match local {
+        Some((hash, date)) if dirty => format!("{}.dev{}+{}.d{}", next, distance, hash, date),
+        Some((hash, _)) => format!("{}.dev{}+{}", next, distance, hash),
+        None => format!("{}.dev{}", next, distance),
+    };
+    if pep440::Version::parse(&version).is_none() {
+        bail!(
+            "The version {} computed from the git tags is not a valid PEP 440 version",
+            version
+        );
+    }
+    Ok(version)
+}
+
+/// The current date as `YYYYMMDD`, or the date of `SOURCE_DATE_EPOCH` for reproducible builds
+fn build_date() -> String {
+    let seconds = source_date_epoch().unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default()
+    });
+    date_from_epoch(seconds, "")
+}
+
+/// Formats the UTC date of seconds since the unix epoch as `YYYYMMDD`, or e.g. `YYYY-MM-DD`
+/// with a `separator`
+pub(crate) fn date_from_epoch(seconds: u64, separator: &str) -> String {
+    // Converts days since the epoch to the civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}{}{:02}{}{:02}",
+        year, separator, month, separator, day
+    )
+}
+
+/// Runs a hook in the pyproject.toml directory and returns its output. Callables run with
+/// `python`, or the python on `PATH` if the build has no interpreter.
+fn run_hook(
+    field: &str,
+    hook: &DynamicHook,
+    pyproject_dir: &Path,
+    python: Option<&Path>,
+    env: &BTreeMap<String, String>,
+) -> Result<String> {
+    let mut command = match (&hook.command, &hook.callable) {
+        (Some(args), None) => {
+            if args.is_empty() {
+                bail!("tool.maturin.dynamic.{}.command must not be empty", field);
+            }
+            let mut command = Command::new(&args[0]);
+            command.args(&args[1..]);
+            command
+        }
+        (None, Some(callable)) => {
+            let (module, name) = callable.split_once(':').with_context(|| {
+                format!(
+                    "tool.maturin.dynamic.{}.callable must be in `module:name` form, got `{}`",
+                    field, callable
+                )
+            })?;
+            let default_python = Path::new(if cfg!(windows) { "python" } else { "python3" });
+            let mut command = Command::new(python.unwrap_or(default_python));
+            command.args(["-c", CALLABLE_SCRIPT, module, name]);
+            command
+        }
+        _ => bail!(
+            "tool.maturin.dynamic.{} must specify exactly one of `command` and `callable`",
+            field
+        ),
+    };
+    let output = command
+        .current_dir(pyproject_dir)
+        .envs(env)
+        .output()
+        .with_context(|| format!("Failed to run the hook for dynamic {}", field))?;
+    if !output.status.success() {
+        bail!(
+            "The hook for dynamic {} failed with {}\n--- Stdout:\n{}\n--- Stderr:\n{}",
+            field,
+            output.status,
+            String::from_utf8_lossy(&output.stdout).trim(),
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+    let value = str::from_utf8(&output.stdout)
+        .with_context(|| format!("The hook for dynamic {} returned invalid utf-8", field))?;
+    // Keep the line breaks of a readme, but not the trailing newline of `print`
+    let value = if field == "readme" {
+        value.trim_end()
+    } else {
+        value.trim()
+    };
+    if value.is_empty() {
+        bail!("The hook for dynamic {} didn't print anything", field);
+    }
+    Ok(value.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+
+    fn pyproject(dir: &Path, contents: &str) -> PyProjectToml {
+        let path = dir.join("pyproject.toml");
+        fs::write(&path, contents).unwrap();
+        PyProjectToml::new(path).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_dynamic_metadata() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pyproject = pyproject(
+            temp_dir.path(),
+            indoc!(
+                r##"
+                [build-system]
+                requires = ["maturin>=0.14,<0.15"]
+                build-backend = "maturin"
+
+                [project]
+                name = "foo"
+                dynamic = ["version", "readme"]
+
+                [tool.maturin.dynamic]
+                version = { command = ["echo", " 1.2.3 "] }
+                readme = { command = ["printf", "# Foo\n\nbar\n"], content-type = "text/markdown" }
+                "##
+            ),
+        );
+        let mut metadata21 = Metadata21 {
+            version: "0.1.0".to_string(),
+            ..Default::default()
+        };
+        resolve_dynamic_metadata(&mut metadata21, temp_dir.path(), &pyproject).unwrap();
+        // The hooks only run when building
+        assert_eq!(metadata21.version, "0.1.0");
+        assert!(metadata21.description.is_none());
+        run_dynamic_hooks(
+            &mut metadata21,
+            temp_dir.path(),
+            &pyproject,
+            None,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        assert_eq!(metadata21.version, "1.2.3");
+        assert_eq!(metadata21.description.as_deref(), Some("# Foo\n\nbar"));
+        assert_eq!(
+            metadata21.description_content_type.as_deref(),
+            Some("text/markdown")
+        );
+        assert_eq!(
+            metadata21.dynamic,
+            ["Description", "Description-Content-Type"]
+        );
+
+        // The version of a source distribution is kept
+        fs::write(
+            temp_dir.path().join("PKG-INFO"),
+            "Metadata-Version: 2.2\nName: foo\nVersion: 1.0.0\n",
+        )
+        .unwrap();
+        resolve_dynamic_metadata(&mut metadata21, temp_dir.path(), &pyproject).unwrap();
+        run_dynamic_hooks(
+            &mut metadata21,
+            temp_dir.path(),
+            &pyproject,
+            None,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        assert_eq!(metadata21.version, "1.0.0");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_dynamic_hooks_with_build_python() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pyproject = pyproject(
+            temp_dir.path(),
+            indoc!(
+                r#"
+                [build-system]
+                requires = ["maturin>=0.14,<0.15"]
+                build-backend = "maturin"
+
+                [project]
+                name = "foo"
+                version = "0.1.0"
+                dynamic = ["requires-python"]
+
+                [tool.maturin.dynamic]
+                requires-python = { callable = "foo:requires_python" }
+                "#
+            ),
+        );
+        let python = temp_dir.path().join("python");
+        fs::write(&python, "#!/bin/sh\necho \">=$MIN_PYTHON\"\n").unwrap();
+        fs::set_permissions(&python, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut metadata21 = Metadata21::default();
+        let env = BTreeMap::from([("MIN_PYTHON".to_string(), "3.9".to_string())]);
+        run_dynamic_hooks(
+            &mut metadata21,
+            temp_dir.path(),
+            &pyproject,
+            Some(&python),
+            &env,
+        )
+        .unwrap();
+        assert_eq!(metadata21.requires_python.as_deref(), Some(">=3.9"));
+        assert_eq!(metadata21.dynamic, ["Requires-Python"]);
+    }
+
+    #[test]
+    fn test_resolve_dynamic_metadata_not_dynamic() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pyproject = pyproject(
+            temp_dir.path(),
+            indoc!(
+                r#"
+                [build-system]
+                requires = ["maturin>=0.14,<0.15"]
+                build-backend = "maturin"
+
+                [project]
+                name = "foo"
+                version = "0.1.0"
+
+                [tool.maturin.dynamic]
+                version = { command = ["echo", "1.2.3"] }
+                "#
+            ),
+        );
+        let mut metadata21 = Metadata21::default();
+        let err =
+            resolve_dynamic_metadata(&mut metadata21, temp_dir.path(), &pyproject).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "tool.maturin.dynamic.version is set, but version is not listed in project.dynamic"
+        );
+    }
+
+    #[test]
+    fn test_format_git_version() {
+        let version = |tag, distance, dirty| {
+            let local = Some(("g1234abc", "20221231".to_string()));
+            format_git_version(tag, distance, dirty, local).unwrap()
+        };
+        assert_eq!(version(Some("v1.2.3"), 0, false), "1.2.3");
+        assert_eq!(version(Some("1.2.3"), 5, false), "1.2.4.dev5+g1234abc");
+        assert_eq!(
+            version(Some("v1.2.3"), 0, true),
+            "1.2.4.dev0+g1234abc.d20221231"
+        );
+        assert_eq!(version(Some("1.0rc1"), 2, false), "1.0rc2.dev2+g1234abc");
+        assert_eq!(version(None, 3, false), "0.1.dev3+g1234abc");
+        assert!(format_git_version(Some("release"), 0, false, None).is_err());
+        assert!(format_git_version(Some("1.0+local"), 0, false, None).is_err());
+
+        // Without a local version segment, which PyPI rejects
+        let version =
+            |tag, distance, dirty| format_git_version(tag, distance, dirty, None).unwrap();
+        assert_eq!(version(Some("v1.2.3"), 0, false), "1.2.3");
+        assert_eq!(version(Some("1.2.3"), 5, false), "1.2.4.dev5");
+        assert_eq!(version(Some("v1.2.3"), 0, true), "1.2.4.dev0");
+        assert_eq!(version(None, 3, false), "0.1.dev3");
+    }
+
+    #[test]
+    fn test_date_from_epoch() {
+        assert_eq!(date_from_epoch(0, ""), "19700101");
+        assert_eq!(date_from_epoch(951_782_400, "-"), "2000-02-29");
+        assert_eq!(date_from_epoch(1_672_531_199, ""), "20221231");
+    }
+
+    #[test]
+    fn test_git_version() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args([
+                    "-c",
+                    "user.name=maturin",
+                    "-c",
+                    "user.email=maturin@example.com",
+                ])
+                .args(args)
+                .current_dir(temp_dir.path())
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {:?} failed", args);
+        };
+        let pyproject = pyproject(
+            temp_dir.path(),
+            indoc!(
+                r#"
+                [build-system]
+                requires = ["maturin>=0.14,<0.15"]
+                build-backend = "maturin"
+
+                [project]
+                name = "foo"
+                dynamic = ["version"]
+
+                [tool.maturin.version]
+                source = "git"
+                write-to = ["_version.py", "version.rs"]
+                "#
+            ),
+        );
+        git(&["init", "-q"]);
+        git(&["add", "pyproject.toml"]);
+        git(&["commit", "-q", "-m", "init"]);
+        git(&["tag", "v1.2.3"]);
+        fs::write(
+            temp_dir.path().join(".gitignore"),
+            "_version.py\nversion.rs\n",
+        )
+        .unwrap();
+        git(&["add", ".gitignore"]);
+
+        let mut metadata21 = Metadata21::default();
+        // The staged .gitignore makes the tree dirty
+        resolve_dynamic_metadata(&mut metadata21, temp_dir.path(), &pyproject).unwrap();
+        assert_eq!(metadata21.version, "1.2.4.dev0");
+        git(&["commit", "-q", "-m", "ignore"]);
+        resolve_dynamic_metadata(&mut metadata21, temp_dir.path(), &pyproject).unwrap();
+        assert_eq!(metadata21.version, "1.2.4.dev1");
+        git(&["tag", "v1.2.4"]);
+        resolve_dynamic_metadata(&mut metadata21, temp_dir.path(), &pyproject).unwrap();
+        assert_eq!(metadata21.version, "1.2.4");
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("_version.py")).unwrap(),
+            "# Generated by maturin from the git tags, don't edit\n__version__ = \"1.2.4\"\n"
+        );
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("version.rs")).unwrap(),
+            "// Generated by maturin from the git tags, don't edit\npub const VERSION: &str = \"1.2.4\";\n"
+        );
+
+        // Writing the new version to committed `write-to` files doesn't make the tree dirty
+        git(&["add", "--force", "_version.py", "version.rs"]);
+        git(&["commit", "-q", "-m", "version"]);
+        git(&["tag", "v1.2.5"]);
+        resolve_dynamic_metadata(&mut metadata21, temp_dir.path(), &pyproject).unwrap();
+        assert_eq!(metadata21.version, "1.2.5");
+        resolve_dynamic_metadata(&mut metadata21, temp_dir.path(), &pyproject).unwrap();
+        assert_eq!(metadata21.version, "1.2.5");
+
+        // The local version segment is opt-in
+        let local_pyproject = self::pyproject(
+            temp_dir.path(),
+            indoc!(
+                r#"
+                [build-system]
+                requires = ["maturin>=0.14,<0.15"]
+                build-backend = "maturin"
+
+                [project]
+                name = "foo"
+                dynamic = ["version"]
+
+                [tool.maturin.version]
+                source = "git"
+                local-version = true
+                "#
+            ),
+        );
+        git(&["commit", "-q", "-am", "local version"]);
+        resolve_dynamic_metadata(&mut metadata21, temp_dir.path(), &local_pyproject).unwrap();
+        assert!(
+            metadata21.version.starts_with("1.2.6.dev1+g"),
+            "{}",
+            metadata21.version
+        );
+        // Clean trees don't have the date in the local version
+        assert!(!metadata21.version.split('+').nth(1).unwrap().contains('.'));
+    }
+}
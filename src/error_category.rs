@@ -0,0 +1,140 @@
+//! Stable exit codes for the categories of failures, so that scripts wrapping maturin can react
+//! to a failure without parsing the messages on stderr
+
+use crate::auditwheel::AuditWheelError;
+use crate::compile::CargoBuildError;
+#[cfg(feature = "upload")]
+use crate::upload::UploadError;
+use serde::Serialize;
+use std::fmt::Display;
+use thiserror::Error;
+
+/// Marks an error as caused by invalid configuration in Cargo.toml, pyproject.toml or the
+/// command line arguments. Used either as the error itself or as the context of another error.
+#[derive(Error, Debug)]
+#[error("{0}")]
+pub struct ConfigError(String);
+
+impl ConfigError {
+    /// An invalid configuration error with the given message
+    pub fn new(message: impl Display) -> Self {
+        Self(message.to_string())
+    }
+}
+
+/// What failed, which decides the exit code of maturin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCategory {
+    /// Anything that doesn't fall into the other categories
+    Other,
+    /// Invalid configuration in Cargo.toml, pyproject.toml or the command line arguments
+    Config,
+    /// cargo failed to build the crate
+    Compile,
+    /// The built library isn't compliant with the requested manylinux/musllinux policy
+    Audit,
+    /// Uploading to the package index failed
+    Upload,
+}
+
+impl ErrorCategory {
+    /// Finds the category of an error by the typed errors in its chain, falling back to
+    /// [ErrorCategory::Other]
+    pub fn of(err: &anyhow::Error) -> Self {
+        #[cfg(feature = "upload")]
+        if err.downcast_ref::<UploadError>().is_some() {
+            return ErrorCategory::Upload;
+        }
+        if err.downcast_ref::<AuditWheelError>().is_some() {
+            ErrorCategory::Audit
+        } else if err.downcast_ref::<CargoBuildError>().is_some() {
+            ErrorCategory::Compile
+        } else if err.downcast_ref::<ConfigError>().is_some() {
+            ErrorCategory::Config
+        } else {
+            ErrorCategory::Other
+        }
+    }
+
+    /// The exit code of maturin for this kind of failure. Invalid command line arguments also
+    /// exit with the code of [ErrorCategory::Config].
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCategory::Other => 1,
+            ErrorCategory::Config => 2,
+            ErrorCategory::Compile => 3,
+            ErrorCategory::Audit => 4,
+            ErrorCategory::Upload => 5,
+        }
+    }
+}
+
+/// The error maturin failed with, as printed by `--error-format json`
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    /// What failed
+    pub category: ErrorCategory,
+    /// The exit code of maturin
+    pub exit_code: i32,
+    /// The outermost error message
+    pub message: String,
+    /// The messages of the errors that caused it, outermost first
+    pub causes: Vec<String>,
+}
+
+impl ErrorReport {
+    /// Collects the category and messages of the error
+    pub fn new(err: &anyhow::Error) -> Self {
+        let category = ErrorCategory::of(err);
+        let mut chain = err.chain().map(|cause| cause.to_string());
+        Self {
+            category,
+            exit_code: category.exit_code(),
+            message: chain.next().unwrap_or_default(),
+            causes: chain.collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::{format_err, Context};
+
+    #[test]
+    fn test_error_category() {
+        let config: anyhow::Error = ConfigError::new("pyproject.toml is invalid").into();
+        assert_eq!(ErrorCategory::of(&config), ErrorCategory::Config);
+        // Found through the context of other errors
+        let nested = Err::<(), _>(format_err!("expected a string"))
+            .context(ConfigError::new("pyproject.toml is invalid"))
+            .context("Failed to build")
+            .unwrap_err();
+        assert_eq!(ErrorCategory::of(&nested), ErrorCategory::Config);
+        let audit = Err::<(), _>(AuditWheelError::LinksLibPythonError("libpython3.9".into()))
+            .context("Error ensuring manylinux_2_17 compliance")
+            .unwrap_err();
+        assert_eq!(ErrorCategory::of(&audit), ErrorCategory::Audit);
+        assert_eq!(
+            ErrorCategory::of(&format_err!("something else")),
+            ErrorCategory::Other
+        );
+    }
+
+    #[test]
+    fn test_error_report() {
+        let err = Err::<(), _>(format_err!("expected a string"))
+            .context(ConfigError::new("pyproject.toml is invalid"))
+            .unwrap_err();
+        assert_eq!(
+            serde_json::to_value(ErrorReport::new(&err)).unwrap(),
+            serde_json::json!({
+                "category": "config",
+                "exit_code": 2,
+                "message": "pyproject.toml is invalid",
+                "causes": ["expected a string"],
+            })
+        );
+    }
+}
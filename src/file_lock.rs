@@ -0,0 +1,78 @@
+//! Advisory locks between maturin processes, based on the OS file locks (flock/LockFileEx)
+use anyhow::{bail, Context, Result};
+use fs2::FileExt;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// An exclusive advisory lock on a lock file. The OS releases it when it's dropped or when the
+/// process exits, so a crashed process can't leave a stale lock behind. The lock file itself is
+/// kept, removing it would race with another process that just opened it.
+#[derive(Debug)]
+pub(crate) struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    /// Locks the lock file at `path`, waiting up to `timeout` for another process holding it.
+    /// `holder` describes what the other process is doing for the waiting message, e.g.
+    /// "another maturin develop to finish installing into .venv".
+    pub(crate) fn acquire(path: PathBuf, timeout: Duration, holder: &str) -> Result<FileLock> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open the lock file {}", path.display()))?;
+        let start = Instant::now();
+        let mut waiting = false;
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(FileLock { file }),
+                Err(err) if err.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {}
+                Err(err) => {
+                    return Err(err)
+                        .context(format!("Failed to lock the lock file {}", path.display()))
+                }
+            }
+            if start.elapsed() >= timeout {
+                bail!("Timed out waiting for {}", holder);
+            }
+            if !waiting {
+                waiting = true;
+                eprintln!("⏳ Waiting for {}", holder);
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_file_lock() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("foo.lock");
+        let lock = FileLock::acquire(path.clone(), Duration::ZERO, "foo").unwrap();
+        let err = FileLock::acquire(path.clone(), Duration::ZERO, "foo").unwrap_err();
+        assert_eq!(err.to_string(), "Timed out waiting for foo");
+        drop(lock);
+        let lock = FileLock::acquire(path.clone(), Duration::ZERO, "foo").unwrap();
+        drop(lock);
+
+        // A stale lock file without a process holding the lock doesn't block
+        std::fs::write(&path, "4294967295").unwrap();
+        FileLock::acquire(path, Duration::ZERO, "foo").unwrap();
+    }
+}
@@ -0,0 +1,288 @@
+//! The glob patterns of `include` and `license-files`, which are anchored at the pyproject.toml
+//! directory and support `**`, `{a,b}` alternatives and `!` negations
+use crate::ConfigError;
+use anyhow::{bail, Result};
+use globset::{GlobBuilder, GlobMatcher};
+use ignore::WalkBuilder;
+use std::path::{Component, Path, PathBuf};
+
+/// A single pattern, split into the literal directory it starts with and the glob for the rest
+#[derive(Debug)]
+struct PathGlob {
+    pattern: String,
+    negated: bool,
+    root: PathBuf,
+    /// `None` if the pattern is a literal path
+    glob: Option<GlobMatcher>,
+    /// How deep below `root` the glob can match, `None` with `**`
+    max_depth: Option<usize>,
+}
+
+impl PathGlob {
+    fn new(base_dir: &Path, pattern: &str) -> Result<Self> {
+        let (negated, glob) = if let Some(glob) = pattern.strip_prefix("\\!") {
+            // An escaped `!` at the start is part of the path, like in `.gitignore`
+            (false, format!("!{}", glob))
+        } else if let Some(glob) = pattern.strip_prefix('!') {
+            (true, glob.to_string())
+        } else {
+            (false, pattern.to_string())
+        };
+        let glob = glob.replace('\\', "/");
+        let components: Vec<&str> = glob.split('/').filter(|c| !c.is_empty()).collect();
+        let literal_len = components
+            .iter()
+            .take_while(|component| !component.contains(['*', '?', '[', '{']))
+            .count();
+        let root = components[..literal_len]
+            .iter()
+            .fold(base_dir.to_path_buf(), |root, component| {
+                root.join(component)
+            });
+        let rest = &components[literal_len..];
+        let matcher = if rest.is_empty() {
+            None
+        } else {
+            let rest = rest.join("/");
+            let glob = GlobBuilder::new(&rest)
+                .literal_separator(true)
+                .build()
+                .map_err(|err| {
+                    ConfigError::new(format!("Invalid glob pattern \"{}\": {}", pattern, err))
+                })?;
+            Some(glob.compile_matcher())
+        };
+        let max_depth = if rest.contains(&"**") {
+            None
+        } else {
+            Some(rest.len())
+        };
+        Ok(Self {
+            pattern: pattern.to_string(),
+            negated,
+            root,
+            glob: matcher,
+            max_depth,
+        })
+    }
+
+    fn is_match(&self, path: &Path) -> bool {
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative,
+            Err(_) => return false,
+        };
+        match &self.glob {
+            Some(glob) => {
+                // The patterns always use `/`, also on windows
+                let relative: Vec<_> = relative
+                    .components()
+                    .filter_map(|component| match component {
+                        Component::Normal(name) => Some(name.to_string_lossy()),
+                        _ => None,
+                    })
+                    .collect();
+                !relative.is_empty() && glob.is_match(relative.join("/"))
+            }
+            None => relative.as_os_str().is_empty(),
+        }
+    }
+
+    /// The files and directories below `root` that match, sorted by path
+    fn walk(&self) -> Result<Vec<PathBuf>> {
+        if self.glob.is_none() {
+            return Ok(if self.root.exists() {
+                vec![self.root.clone()]
+            } else {
+                Vec::new()
+            });
+        }
+        if !self.root.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut matches = Vec::new();
+        let walker = WalkBuilder::new(&self.root)
+            .standard_filters(false)
+            .follow_links(true)
+            .max_depth(self.max_depth)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+        for entry in walker {
+            let path = entry?.into_path();
+            if self.is_match(&path) {
+                matches.push(path);
+            }
+        }
+        Ok(matches)
+    }
+}
+
+/// A list of glob patterns relative to a base directory. A path is matched if the last pattern
+/// that matches it isn't negated, so `!` patterns remove the matches of the patterns before
+/// them, like in `.gitignore`.
+///
+/// Compared to the `glob` crate maturin used before, `{a,b}` are alternatives and a leading `!`
+/// negates the pattern, so literal braces or a leading `!` have to be escaped as `[{]` or `\!`.
+#[derive(Debug)]
+pub struct PathGlobs {
+    patterns: Vec<PathGlob>,
+}
+
+impl PathGlobs {
+    /// Parses the patterns, which are relative to `base_dir` and may point outside of it
+    pub fn new(base_dir: &Path, patterns: &[impl AsRef<str>]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| PathGlob::new(base_dir, pattern.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { patterns })
+    }
+
+    /// For each pattern that isn't negated, the pattern and the files and directories it
+    /// matches that no later negated pattern removes. Patterns that match nothing have no
+    /// matches, so they can be reported as likely typos.
+    pub fn matches(&self) -> Result<Vec<(&str, Vec<PathBuf>)>> {
+        let mut all_matches = Vec::new();
+        for (index, pattern) in self.patterns.iter().enumerate() {
+            if pattern.negated {
+                continue;
+            }
+            let matches: Vec<PathBuf> = pattern
+                .walk()?
+                .into_iter()
+                .filter(|path| {
+                    self.patterns[index + 1..]
+                        .iter()
+                        .rev()
+                        .find(|later| later.is_match(path))
+                        .map_or(true, |later| !later.negated)
+                })
+                .collect();
+            all_matches.push((pattern.pattern.as_str(), matches));
+        }
+        Ok(all_matches)
+    }
+}
+
+/// Fails if a pattern is only a negation without any pattern before it to negate
+pub fn check_negations(patterns: &[impl AsRef<str>]) -> Result<()> {
+    if let Some(first) = patterns.first() {
+        if first.as_ref().starts_with('!') {
+            bail!(ConfigError::new(format!(
+                "The glob pattern \"{}\" excludes files, but there is no pattern before it that \
+                includes them",
+                first.as_ref()
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fs_err as fs;
+
+    fn matches(base_dir: &Path, patterns: &[&str]) -> Vec<(String, Vec<String>)> {
+        PathGlobs::new(base_dir, patterns)
+            .unwrap()
+            .matches()
+            .unwrap()
+            .into_iter()
+            .map(|(pattern, matches)| {
+                let matches = matches
+                    .iter()
+                    .map(|path| {
+                        let relative = path.strip_prefix(base_dir).unwrap();
+                        relative.to_string_lossy().replace('\\', "/")
+                    })
+                    .collect();
+                (pattern.to_string(), matches)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_path_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_dir = dir.path();
+        fs::create_dir_all(base_dir.join("data").join("sub")).unwrap();
+        for file in [
+            "README.txt",
+            "NOTES.md",
+            "data/a.json",
+            "data/a.tmp",
+            "data/b.yaml",
+            "data/sub/c.json",
+        ] {
+            fs::write(base_dir.join(file), "").unwrap();
+        }
+
+        let to_strings =
+            |matches: &[&str]| -> Vec<String> { matches.iter().map(ToString::to_string).collect() };
+        // `*` doesn't cross directories, unlike in `.gitignore`
+        assert_eq!(
+            matches(base_dir, &["*.txt"]),
+            [("*.txt".to_string(), to_strings(&["README.txt"]))]
+        );
+        assert_eq!(
+            matches(base_dir, &["data/**/*.json"]),
+            [(
+                "data/**/*.json".to_string(),
+                to_strings(&["data/a.json", "data/sub/c.json"])
+            )]
+        );
+        assert_eq!(
+            matches(base_dir, &["data/*.{json,yaml}", "{README.txt,NOTES.md}"]),
+            [
+                (
+                    "data/*.{json,yaml}".to_string(),
+                    to_strings(&["data/a.json", "data/b.yaml"])
+                ),
+                (
+                    "{README.txt,NOTES.md}".to_string(),
+                    to_strings(&["NOTES.md", "README.txt"])
+                )
+            ]
+        );
+        // Negations remove the matches of the patterns before them
+        assert_eq!(
+            matches(base_dir, &["data/**/*", "!data/*.tmp", "!data/sub"]),
+            [(
+                "data/**/*".to_string(),
+                to_strings(&["data/a.json", "data/b.yaml", "data/sub/c.json"])
+            )]
+        );
+        // An escaped `!` is part of the file name
+        fs::write(base_dir.join("!important.txt"), "").unwrap();
+        assert_eq!(
+            matches(base_dir, &["\\!important.txt"]),
+            [(
+                "\\!important.txt".to_string(),
+                to_strings(&["!important.txt"])
+            )]
+        );
+        // Literal paths and patterns without matches
+        assert_eq!(
+            matches(base_dir, &["data/sub", "LICENSE*"]),
+            [
+                ("data/sub".to_string(), to_strings(&["data/sub"])),
+                ("LICENSE*".to_string(), Vec::new())
+            ]
+        );
+        // Outside of the base directory
+        assert_eq!(
+            matches(&base_dir.join("data"), &["../*.md"]),
+            [("../*.md".to_string(), to_strings(&["../NOTES.md"]))]
+        );
+    }
+
+    #[test]
+    fn test_invalid_path_globs() {
+        let err = PathGlobs::new(Path::new("."), &["data/{a,b"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Invalid glob pattern \"data/{a,b\""));
+        assert!(check_negations(&["!data"]).is_err());
+        assert!(check_negations(&["data", "!data/*.tmp"]).is_ok());
+    }
+}
@@ -0,0 +1,130 @@
+//! Runs the commands of `[tool.maturin.hooks]` before and after building wheels and source
+//! distributions, e.g. to generate assets that are packaged
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// When a hook runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HookStage {
+    /// Before anything is compiled or packaged
+    PreBuild,
+    /// After the wheels or the source distribution were written
+    PostBuild,
+}
+
+impl HookStage {
+    fn name(self) -> &'static str {
+        match self {
+            HookStage::PreBuild => "pre-build",
+            HookStage::PostBuild => "post-build",
+        }
+    }
+}
+
+/// Runs the hook commands one after another through the shell in the pyproject.toml directory,
+/// stopping at the first one that fails.
+///
+/// Besides `env`, the hooks get `MATURIN_HOOK` with the stage, `MATURIN_BUILD_FORMAT` with
+/// `wheel` or `sdist`, `MATURIN_TARGET` with the target triple and, after the build,
+/// `MATURIN_ARTIFACTS` with the paths of the built files, separated like in `PATH`.
+pub(crate) fn run_hooks(
+    stage: HookStage,
+    commands: &[String],
+    pyproject_dir: &Path,
+    format: &str,
+    target_triple: &str,
+    artifacts: &[PathBuf],
+    env: &BTreeMap<String, String>,
+) -> Result<()> {
+    for command in commands {
+        eprintln!("🪝 Running {} hook `{}`", stage.name(), command);
+        let mut shell = if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+        shell
+            .arg(command)
+            .current_dir(pyproject_dir)
+            .envs(env)
+            .env("MATURIN_HOOK", stage.name())
+            .env("MATURIN_BUILD_FORMAT", format)
+            .env("MATURIN_TARGET", target_triple);
+        if stage == HookStage::PostBuild {
+            let artifacts = env::join_paths(artifacts)
+                .context("The artifact paths can't be passed in MATURIN_ARTIFACTS")?;
+            shell.env("MATURIN_ARTIFACTS", artifacts);
+        }
+        let status = shell
+            .status()
+            .with_context(|| format!("Failed to run the {} hook `{}`", stage.name(), command))?;
+        if !status.success() {
+            bail!(
+                "The {} hook `{}` failed with {}",
+                stage.name(),
+                command,
+                status
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fs_err as fs;
+    use tempfile::TempDir;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_hooks() -> Result<()> {
+        let tmp_dir = TempDir::new()?;
+        let artifacts = [
+            tmp_dir.path().join("foo-0.1.0-py3-none-any.whl"),
+            tmp_dir.path().join("foo-0.1.0.tar.gz"),
+        ];
+        let env = BTreeMap::from([("FOO".to_string(), "bar".to_string())]);
+        run_hooks(
+            HookStage::PostBuild,
+            &[
+                "echo $MATURIN_HOOK $MATURIN_BUILD_FORMAT $MATURIN_TARGET $FOO > hook.txt"
+                    .to_string(),
+                "echo $MATURIN_ARTIFACTS >> hook.txt".to_string(),
+            ],
+            tmp_dir.path(),
+            "wheel",
+            "x86_64-unknown-linux-gnu",
+            &artifacts,
+            &env,
+        )?;
+        assert_eq!(
+            fs::read_to_string(tmp_dir.path().join("hook.txt"))?,
+            format!(
+                "post-build wheel x86_64-unknown-linux-gnu bar\n{}:{}\n",
+                artifacts[0].display(),
+                artifacts[1].display()
+            )
+        );
+
+        let result = run_hooks(
+            HookStage::PreBuild,
+            &["exit 3".to_string(), "touch never.txt".to_string()],
+            tmp_dir.path(),
+            "sdist",
+            "x86_64-unknown-linux-gnu",
+            &[],
+            &env,
+        );
+        assert!(result.is_err());
+        assert!(!tmp_dir.path().join("never.txt").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+//! Generates the import library of the python dll for the msvc targets from the dll itself, for
+//! the embeddable distribution of CPython for windows, which doesn't include the import libraries
+use crate::target::Arch;
+use crate::Target;
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use std::path::Path;
+use std::process::Command;
+use tracing::debug;
+
+/// The names of the functions and data the dll exports
+fn dll_exports(dll: &[u8]) -> Result<Vec<String>> {
+    let pe = goblin::pe::PE::parse(dll).context("Failed to parse the dll")?;
+    let exports: Vec<String> = pe
+        .exports
+        .iter()
+        .filter_map(|export| export.name)
+        .map(ToString::to_string)
+        .collect();
+    if exports.is_empty() {
+        bail!("The dll doesn't export anything");
+    }
+    Ok(exports)
+}
+
+/// The module-definition file for the exports of `dll_name`. The data exports aren't marked as
+/// `DATA`, which only adds unused thunks for them, rust imports them through their `__imp_`
+/// symbol like the functions.
+fn def_file(dll_name: &str, exports: &[String]) -> String {
+    let mut def = format!("LIBRARY {}\nEXPORTS\n", dll_name);
+    for export in exports {
+        def.push_str(export);
+        def.push('\n');
+    }
+    def
+}
+
+/// The command that turns the module-definition file into an import library: `zig dlltool`
+/// with `--zig`, otherwise `lib.exe` of Visual Studio if it's installed and else
+/// `llvm-dlltool`
+fn import_lib_command(target: &Target, zig: bool, def: &Path, lib: &Path) -> Result<Command> {
+    let (lib_machine, dlltool_machine) = match target.target_arch() {
+        Arch::X86_64 => ("X64", "i386:x86-64"),
+        Arch::X86 => ("X86", "i386"),
+        Arch::Aarch64 => ("ARM64", "arm64"),
+        arch => bail!("Can't generate the python import library for {}", arch),
+    };
+    let dlltool = |mut command: Command| {
+        command
+            .args(["-m", dlltool_machine, "-d"])
+            .arg(def)
+            .arg("-l")
+            .arg(lib);
+        command
+    };
+    if zig {
+        let (zig, zig_args) = cargo_zigbuild::Zig::find_zig()?;
+        let mut command = Command::new(zig);
+        command.args(zig_args).arg("dlltool");
+        return Ok(dlltool(command));
+    }
+    if let Some(lib_exe) = cc::windows_registry::find_tool(target.target_triple(), "lib.exe") {
+        let mut command = lib_exe.to_command();
+        command
+            .arg("/NOLOGO")
+            .arg(format!("/MACHINE:{}", lib_machine))
+            .arg(format!("/DEF:{}", def.display()))
+            .arg(format!("/OUT:{}", lib.display()));
+        return Ok(command);
+    }
+    Ok(dlltool(Command::new("llvm-dlltool")))
+}
+
+/// Writes `{lib_name}.lib` for `{lib_name}.dll` in `python_dir` to `out_dir`, unless it's already
+/// there
+pub(crate) fn generate_import_lib(
+    python_dir: &Path,
+    lib_name: &str,
+    target: &Target,
+    zig: bool,
+    out_dir: &Path,
+) -> Result<()> {
+    let lib = out_dir.join(format!("{}.lib", lib_name));
+    if lib.is_file() {
+        return Ok(());
+    }
+    let dll_name = format!("{}.dll", lib_name);
+    let dll = python_dir.join(&dll_name);
+    let exports = dll_exports(&fs::read(&dll)?)
+        .with_context(|| format!("Failed to read the exports of {}", dll.display()))?;
+    fs::create_dir_all(out_dir)?;
+    let def = out_dir.join(format!("{}.def", lib_name));
+    fs::write(&def, def_file(&dll_name, &exports))?;
+
+    let mut command = import_lib_command(target, zig, &def, &lib)?;
+    debug!(
+        "Generating the import library {}: {:?}",
+        lib.display(),
+        command
+    );
+    let output = command.output().with_context(|| {
+        format!(
+            "Failed to run {:?} to generate the import library of {}. Install the build tools \
+            of Visual Studio or llvm, or use --zig",
+            command.get_program(),
+            dll.display()
+        )
+    })?;
+    if !output.status.success() {
+        bail!(
+            "Failed to generate the import library of {}:\n{}{}",
+            dll.display(),
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_def_file() {
+        let exports = vec!["PyObject_Call".to_string(), "_Py_NoneStruct".to_string()];
+        assert_eq!(
+            def_file("python311.dll", &exports),
+            "LIBRARY python311.dll\nEXPORTS\nPyObject_Call\n_Py_NoneStruct\n"
+        );
+        assert!(dll_exports(b"not a dll").is_err());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_import_lib_command() {
+        let target =
+            Target::from_target_triple(Some("x86_64-pc-windows-msvc".to_string())).unwrap();
+        let command = import_lib_command(
+            &target,
+            false,
+            Path::new("python311.def"),
+            Path::new("python311.lib"),
+        )
+        .unwrap();
+        assert_eq!(command.get_program(), "llvm-dlltool");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "-m",
+                "i386:x86-64",
+                "-d",
+                "python311.def",
+                "-l",
+                "python311.lib"
+            ]
+        );
+    }
+}
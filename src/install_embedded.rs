@@ -0,0 +1,361 @@
+//! Installs wheels into the embeddable distribution of CPython for windows, which has neither pip
+//! nor site-packages and whose `sys.path` comes from the `python3XY._pth` file, for
+//! `maturin install-embedded`
+use crate::python_interpreter::embeddable_pth_file;
+use crate::verify::{binary_platform, parse_csv, WheelFilename};
+use crate::WheelReader;
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use sha2::{Digest, Sha256};
+use std::path::{Component, Path, PathBuf};
+
+/// The directory the wheels are installed to, relative to the embeddable distribution
+const SITE_PACKAGES: &str = r"Lib\site-packages";
+
+/// Options for `maturin install-embedded`
+#[derive(Debug, clap::Parser)]
+pub struct InstallEmbeddedOptions {
+    /// The wheels to install
+    #[arg(value_name = "WHEEL", required = true)]
+    wheels: Vec<PathBuf>,
+    /// The directory of the embeddable distribution, which contains python.exe and the
+    /// python3XY._pth file
+    #[arg(long = "python-dir", value_name = "DIR")]
+    python_dir: PathBuf,
+}
+
+/// Finds the `python3XY._pth` file of the embeddable distribution in `python_dir` and returns
+/// it with the minor python version from its name
+fn find_pth(python_dir: &Path) -> Result<(PathBuf, usize)> {
+    let entries = fs::read_dir(python_dir)
+        .with_context(|| format!("{} is not a directory", python_dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let minor = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix("python3"))
+            .and_then(|name| name.strip_suffix("._pth"))
+            .and_then(|minor| minor.parse::<usize>().ok());
+        if let Some(minor) = minor {
+            return Ok((embeddable_pth_file(python_dir, 3, minor), minor));
+        }
+    }
+    bail!(
+        "{} has no python3XY._pth file, is it the embeddable distribution of python?",
+        python_dir.display()
+    )
+}
+
+/// Checks that the wheel can be imported by CPython 3.`minor` and, unless it's unknown, the
+/// platform tag of its python.exe, e.g. `win_amd64`
+fn check_compatible(filename: &WheelFilename, minor: usize, platform: Option<&str>) -> Result<()> {
+    let python_compatible = filename.python_tag.split('.').any(|tag| {
+        let (implementation, version) = tag.split_at(tag.len().min(2));
+        let tag_minor = match version.strip_prefix('3') {
+            Some("") => 0,
+            Some(tag_minor) => match tag_minor.parse::<usize>() {
+                Ok(tag_minor) => tag_minor,
+                Err(_) => return false,
+            },
+            None => return false,
+        };
+        match implementation {
+            "py" => tag_minor <= minor,
+            "cp" if filename.abi_tag == "abi3" => tag_minor <= minor,
+            "cp" => tag_minor == minor,
+            _ => false,
+        }
+    });
+    if !python_compatible {
+        bail!(
+            "{}-{} is built for {}-{}, which isn't compatible with the embedded CPython 3.{}",
+            filename.distribution,
+            filename.version,
+            filename.python_tag,
+            filename.abi_tag,
+            minor
+        );
+    }
+    if let Some(platform) = platform {
+        if !filename
+            .platform_tag
+            .split('.')
+            .any(|tag| tag == "any" || tag == platform)
+        {
+            bail!(
+                "{}-{} is built for {}, but the embedded python is {}",
+                filename.distribution,
+                filename.version,
+                filename.platform_tag,
+                platform
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The platform tag of the python.exe in `python_dir`, if it can be read
+fn python_platform(python_dir: &Path) -> Option<&'static str> {
+    let content = fs::read(python_dir.join("python.exe")).ok()?;
+    match binary_platform(&content)? {
+        ("windows", archs) => archs.first().copied(),
+        _ => None,
+    }
+}
+
+/// Adds `Lib\site-packages` to the `._pth` file after the existing paths, unless it's already
+/// there. Returns whether the file was changed.
+fn update_pth(pth: &Path) -> Result<bool> {
+    let content = fs::read_to_string(pth)?;
+    let is_site_packages =
+        |line: &str| line.trim().replace('/', "\\").to_lowercase() == SITE_PACKAGES.to_lowercase();
+    if content.lines().any(is_site_packages) {
+        return Ok(false);
+    }
+    let newline = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut lines: Vec<&str> = content.lines().collect();
+    // Paths come before the comments and the `import site` line
+    let position = lines
+        .iter()
+        .rposition(|line| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#') && !line.starts_with("import ")
+        })
+        .map_or(0, |position| position + 1);
+    lines.insert(position, SITE_PACKAGES);
+    fs::write(pth, lines.join(newline) + newline)?;
+    Ok(true)
+}
+
+/// PEP 503 normalization of a distribution name, which the .dist-info directory uses escaped
+fn normalize(name: &str) -> String {
+    name.to_lowercase().replace(['-', '.'], "_")
+}
+
+/// Removes the files of an earlier installation of `distribution` listed in its RECORD
+fn remove_previous(site_packages: &Path, distribution: &str) -> Result<()> {
+    if !site_packages.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(site_packages)? {
+        let dist_info = entry?.path();
+        let dir_name = dist_info
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        let installed = match dir_name.strip_suffix(".dist-info") {
+            Some(stem) => stem.split('-').next().unwrap_or_default(),
+            None => continue,
+        };
+        if normalize(installed) != normalize(distribution) {
+            continue;
+        }
+        eprintln!("🗑  Removing the installed {}", dir_name);
+        if let Ok(record) = fs::read_to_string(dist_info.join("RECORD")) {
+            for (_, fields) in parse_csv(&record).unwrap_or_default() {
+                if let Some(path) = fields.first().and_then(|path| safe_path(path)) {
+                    let path = site_packages.join(path);
+                    if path.is_file() {
+                        fs::remove_file(path)?;
+                    }
+                }
+            }
+        }
+        if dist_info.is_dir() {
+            fs::remove_dir_all(dist_info)?;
+        }
+    }
+    Ok(())
+}
+
+/// The relative path of an archive or RECORD entry, `None` if it would leave the directory
+fn safe_path(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        Some(path.to_path_buf())
+    } else {
+        None
+    }
+}
+
+/// Extracts the wheel into `site_packages` and writes the RECORD of the installed files, the
+/// scripts, headers and data of the wheel are skipped since the embedded python doesn't use them
+fn install_wheel(wheel_path: &Path, filename: &WheelFilename, site_packages: &Path) -> Result<()> {
+    let wheel = WheelReader::open(wheel_path)?;
+    let dist_info_dir = wheel.dist_info_dir()?;
+    let data_dir = format!("{}.data", dist_info_dir.trim_end_matches(".dist-info"));
+    remove_previous(site_packages, &filename.distribution)?;
+
+    let record_path = format!("{}/RECORD", dist_info_dir);
+    let mut files: Vec<(String, &[u8])> = Vec::new();
+    for (name, content) in wheel.entries() {
+        if name == record_path {
+            continue;
+        }
+        let name = match name.strip_prefix(&format!("{}/", data_dir)) {
+            Some(data_path) => match data_path.split_once('/') {
+                Some(("purelib" | "platlib", rest)) => rest,
+                Some((key, _)) => {
+                    eprintln!(
+                        "⚠️  Warning: Skipping {}, the embedded python has no {} directory",
+                        name, key
+                    );
+                    continue;
+                }
+                None => continue,
+            },
+            None => name,
+        };
+        files.push((name.to_string(), content));
+    }
+    files.push((format!("{}/INSTALLER", dist_info_dir), b"maturin\n"));
+
+    let mut record = String::new();
+    for (name, content) in &files {
+        let path = safe_path(name).with_context(|| {
+            format!("{} contains the unsafe path {}", wheel_path.display(), name)
+        })?;
+        let path = site_packages.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, content)?;
+        let hash = base64::encode_config(Sha256::digest(content), base64::URL_SAFE_NO_PAD);
+        record.push_str(&format!("{},sha256={},{}\n", name, hash, content.len()));
+    }
+    record.push_str(&format!("{},,\n", record_path));
+    fs::write(site_packages.join(&record_path), record)?;
+    Ok(())
+}
+
+/// Installs the wheels into `Lib\site-packages` of the embeddable distribution and adds that
+/// directory to its `._pth` file. Fails if a wheel isn't compatible with the embedded python.
+pub fn install_embedded(options: InstallEmbeddedOptions) -> Result<()> {
+    let python_dir = &options.python_dir;
+    let (pth, minor) = find_pth(python_dir)?;
+    let platform = python_platform(python_dir);
+    let mut wheels = Vec::new();
+    for wheel_path in &options.wheels {
+        let filename = wheel_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(WheelFilename::parse)
+            .with_context(|| format!("{} is not a valid wheel filename", wheel_path.display()))?;
+        check_compatible(&filename, minor, platform)?;
+        wheels.push((wheel_path, filename));
+    }
+
+    let site_packages = python_dir.join(SITE_PACKAGES);
+    for (wheel_path, filename) in wheels {
+        install_wheel(wheel_path, &filename, &site_packages)?;
+        eprintln!(
+            "📦 Installed {}-{} to {}",
+            filename.distribution,
+            filename.version,
+            site_packages.display()
+        );
+    }
+    if update_pth(&pth)? {
+        eprintln!("📝 Added {} to {}", SITE_PACKAGES, pth.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::module_writer::test_wheel_writer;
+    use crate::{Metadata21, ModuleWriter};
+
+    #[test]
+    fn test_update_pth() {
+        let dir = tempfile::tempdir().unwrap();
+        let pth = dir.path().join("python311._pth");
+        fs::write(
+            &pth,
+            "python311.zip\r\n.\r\n\r\n# Uncomment to run site.main() automatically\r\n#import site\r\n",
+        )
+        .unwrap();
+        assert_eq!(find_pth(dir.path()).unwrap(), (pth.clone(), 11));
+        assert!(update_pth(&pth).unwrap());
+        assert_eq!(
+            fs::read_to_string(&pth).unwrap(),
+            "python311.zip\r\n.\r\nLib\\site-packages\r\n\r\n# Uncomment to run site.main() \
+            automatically\r\n#import site\r\n"
+        );
+        assert!(!update_pth(&pth).unwrap());
+    }
+
+    #[test]
+    fn test_check_compatible() {
+        let check = |wheel: &str, minor| {
+            check_compatible(
+                &WheelFilename::parse(wheel).unwrap(),
+                minor,
+                Some("win_amd64"),
+            )
+            .is_ok()
+        };
+        assert!(check("foo-0.1.0-cp311-cp311-win_amd64.whl", 11));
+        assert!(!check("foo-0.1.0-cp310-cp310-win_amd64.whl", 11));
+        assert!(check("foo-0.1.0-cp37-abi3-win_amd64.whl", 11));
+        assert!(!check("foo-0.1.0-cp312-abi3-win_amd64.whl", 11));
+        assert!(check("foo-0.1.0-py3-none-any.whl", 8));
+        assert!(!check("foo-0.1.0-pp39-pypy39_pp73-win_amd64.whl", 9));
+        assert!(!check("foo-0.1.0-cp311-cp311-win32.whl", 11));
+    }
+
+    #[test]
+    fn test_install_wheel() {
+        let dir = tempfile::tempdir().unwrap();
+        let metadata = Metadata21 {
+            name: "foo-bar".to_string(),
+            version: "0.1.0".to_string(),
+            ..Default::default()
+        };
+        let mut writer = test_wheel_writer(dir.path(), &metadata, "cp311-cp311-win_amd64").unwrap();
+        writer
+            .add_bytes("foo_bar/foo_bar.cp311-win_amd64.pyd", b"pyd")
+            .unwrap();
+        writer
+            .add_bytes("foo_bar-0.1.0.data/platlib/foo_bar.pth", b"foo_bar")
+            .unwrap();
+        writer
+            .add_bytes("foo_bar-0.1.0.data/scripts/foo", b"#!python")
+            .unwrap();
+        let wheel_path = writer.finish().unwrap();
+        let filename =
+            WheelFilename::parse(wheel_path.file_name().unwrap().to_str().unwrap()).unwrap();
+
+        let site_packages = dir.path().join("site-packages");
+        fs::create_dir_all(site_packages.join("foo_bar-0.0.1.dist-info")).unwrap();
+        fs::write(site_packages.join("old.py"), "").unwrap();
+        fs::write(
+            site_packages.join("foo_bar-0.0.1.dist-info").join("RECORD"),
+            "old.py,,\nfoo_bar-0.0.1.dist-info/RECORD,,\n",
+        )
+        .unwrap();
+        install_wheel(&wheel_path, &filename, &site_packages).unwrap();
+        assert!(!site_packages.join("old.py").exists());
+        assert!(!site_packages.join("foo_bar-0.0.1.dist-info").exists());
+        assert_eq!(
+            fs::read(site_packages.join("foo_bar/foo_bar.cp311-win_amd64.pyd")).unwrap(),
+            b"pyd"
+        );
+        assert!(site_packages.join("foo_bar.pth").is_file());
+        assert!(!site_packages.join("foo").exists());
+        let record =
+            fs::read_to_string(site_packages.join("foo_bar-0.1.0.dist-info/RECORD")).unwrap();
+        assert!(record.contains("foo_bar.pth,sha256="));
+        assert!(record.contains("foo_bar-0.1.0.dist-info/INSTALLER,sha256="));
+        assert!(record.ends_with("foo_bar-0.1.0.dist-info/RECORD,,\n"));
+    }
+}
@@ -0,0 +1,405 @@
+//! Self-extracting installer scripts, which install an embedded wheel without a package index
+use anyhow::{Context, Result};
+use fs_err as fs;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Installs the wheel embedded after the `MATURIN WHEEL PAYLOAD` marker of the script at
+/// `sys.argv[1]`. This is a small subset of what pip does, so it works without pip, packaging or
+/// any network access.
+const INSTALLER: &str = r##"import base64
+import configparser
+import csv
+import hashlib
+import io
+import os
+import re
+import shutil
+import sys
+import sysconfig
+import zipfile
+
+WHEEL_NAME = "{wheel_name}"
+WHEEL_HASH = "{wheel_hash}"
+
+
+def usage():
+    sys.exit("usage: <installer> [--user | --target <dir>]")
+
+
+def parse_args(argv):
+    user, target = False, None
+    args = iter(argv)
+    for arg in args:
+        if arg == "--user":
+            user = True
+        elif arg == "--target":
+            target = next(args, None) or usage()
+        else:
+            usage()
+    if user and target:
+        usage()
+    return user, target
+
+
+def read_payload(script):
+    with open(script, encoding="utf-8") as f:
+        content = f.read()
+    payload = content.rsplit("MATURIN WHEEL" + " PAYLOAD", 1)[1]
+    payload = "".join(c for c in payload if c.isalnum() or c in "+/=")
+    wheel = base64.b64decode(payload)
+    if hashlib.sha256(wheel).hexdigest() != WHEEL_HASH:
+        sys.exit("The embedded wheel is corrupted, was the installer modified?")
+    return wheel
+
+
+def check_python():
+    python_tag, abi_tag = WHEEL_NAME[:-4].split("-")[-3:-1]
+    current = sys.version_info[:2]
+    for tag in python_tag.split("."):
+        match = re.fullmatch(r"(cp|pp|py)(\d)(\d*)", tag)
+        if not match:
+            continue
+        implementation, major, minor = match.group(1), int(match.group(2)), match.group(3)
+        minor = int(minor or 0)
+        if implementation == "cp" and sys.implementation.name != "cpython":
+            continue
+        if implementation == "pp" and sys.implementation.name != "pypy":
+            continue
+        if implementation == "py" or abi_tag == "abi3":
+            if major == current[0] and minor <= current[1]:
+                return
+        elif (major, minor) == current:
+            return
+    sys.exit(
+        "{} is not compatible with python {} at {}".format(
+            WHEEL_NAME, sys.version.split()[0], sys.executable
+        )
+    )
+
+
+def scheme_paths(user, target):
+    if target:
+        target = os.path.abspath(target)
+        return {
+            "purelib": target,
+            "platlib": target,
+            "scripts": os.path.join(target, "bin"),
+            "data": target,
+            "include": os.path.join(target, "include"),
+        }
+    if user:
+        if hasattr(sysconfig, "get_preferred_scheme"):
+            scheme = sysconfig.get_preferred_scheme("user")
+        else:
+            scheme = os.name + "_user"
+        return sysconfig.get_paths(scheme)
+    return sysconfig.get_paths()
+
+
+def normalize(name):
+    return re.sub(r"[-_.]+", "_", name).lower()
+
+
+def is_inside(directory, path):
+    directory = os.path.realpath(directory)
+    try:
+        return os.path.commonpath([directory, os.path.realpath(path)]) == directory
+    except ValueError:
+        # Paths on different drives
+        return False
+
+
+def remove_previous(lib, distribution):
+    if not os.path.isdir(lib):
+        return
+    for entry in os.listdir(lib):
+        if not entry.endswith(".dist-info"):
+            continue
+        if normalize(entry.split("-")[0]) != normalize(distribution):
+            continue
+        print("Removing the installed " + entry)
+        record = os.path.join(lib, entry, "RECORD")
+        if os.path.isfile(record):
+            with open(record, newline="", encoding="utf-8") as f:
+                for row in csv.reader(f):
+                    path = os.path.normpath(os.path.join(lib, row[0]))
+                    # Absolute paths and `..` could point anywhere
+                    if is_inside(lib, path) and os.path.isfile(path):
+                        os.remove(path)
+        shutil.rmtree(os.path.join(lib, entry), ignore_errors=True)
+
+
+def write_file(path, content, mode):
+    os.makedirs(os.path.dirname(path), exist_ok=True)
+    with open(path, "wb") as f:
+        f.write(content)
+    if mode:
+        os.chmod(path, mode)
+
+
+def write_entry_points(archive, dist_info, scripts):
+    try:
+        content = archive.read(dist_info + "/entry_points.txt").decode("utf-8")
+    except KeyError:
+        return []
+    parser = configparser.ConfigParser(delimiters=("=",), interpolation=None)
+    parser.optionxform = str
+    parser.read_string(content)
+    installed = []
+    for section in ("console_scripts", "gui_scripts"):
+        if not parser.has_section(section):
+            continue
+        for name, value in parser.items(section):
+            module, _, function = value.partition(":")
+            function = function.split("[")[0].strip()
+            code = "import sys\nfrom {} import {}\nif __name__ == '__main__':\n    sys.exit({}())\n".format(
+                module.strip(), function.split(".")[0], function
+            )
+            if os.name == "nt":
+                path = os.path.join(scripts, name + "-script.py")
+                write_file(path, code.encode("utf-8"), None)
+                launcher = '@"{}" "%~dp0{}-script.py" %*\r\n'.format(sys.executable, name)
+                write_file(os.path.join(scripts, name + ".cmd"), launcher.encode("utf-8"), None)
+                installed += [path, os.path.join(scripts, name + ".cmd")]
+            else:
+                path = os.path.join(scripts, name)
+                shebang = "#!" + sys.executable + "\n"
+                write_file(path, (shebang + code).encode("utf-8"), 0o755)
+                installed.append(path)
+    return installed
+
+
+def install(wheel, paths):
+    archive = zipfile.ZipFile(io.BytesIO(wheel))
+    names = archive.namelist()
+    dist_info = next(
+        name.split("/")[0]
+        for name in names
+        if name.split("/")[0].endswith(".dist-info")
+    )
+    data_dir = dist_info[: -len(".dist-info")] + ".data"
+    distribution = dist_info.split("-")[0]
+    wheel_file = archive.read(dist_info + "/WHEEL").decode("utf-8").lower()
+    if "root-is-purelib: true" in wheel_file:
+        lib = paths["purelib"]
+    else:
+        lib = paths["platlib"]
+    remove_previous(lib, distribution)
+
+    installed = []
+    for name in names:
+        if name.endswith("/") or name == dist_info + "/RECORD":
+            continue
+        content = archive.read(name)
+        mode = (archive.getinfo(name).external_attr >> 16) & 0o777
+        if name.startswith(data_dir + "/"):
+            _, key, rest = name.split("/", 2)
+            if key == "headers":
+                path = os.path.join(paths["include"], distribution, rest)
+            elif key in ("purelib", "platlib", "scripts", "data"):
+                path = os.path.join(paths[key], rest)
+            else:
+                sys.exit("Unknown data directory {} in {}".format(key, WHEEL_NAME))
+            if key == "scripts":
+                if content.startswith(b"#!python"):
+                    rest_of_file = content.partition(b"\n")[2]
+                    content = b"#!" + sys.executable.encode() + b"\n" + rest_of_file
+                mode |= 0o755
+        else:
+            path = os.path.join(lib, name)
+        write_file(path, content, mode)
+        installed.append(path)
+    installed += write_entry_points(archive, dist_info, paths["scripts"])
+    installer = os.path.join(lib, dist_info, "INSTALLER")
+    write_file(installer, b"maturin\n", None)
+    installed.append(installer)
+
+    record = os.path.join(lib, dist_info, "RECORD")
+    with open(record, "w", newline="", encoding="utf-8") as f:
+        writer = csv.writer(f, lineterminator="\n")
+        for path in installed:
+            with open(path, "rb") as installed_file:
+                content = installed_file.read()
+            digest = base64.urlsafe_b64encode(hashlib.sha256(content).digest())
+            relative = os.path.relpath(path, lib).replace(os.sep, "/")
+            writer.writerow([relative, "sha256=" + digest.decode().rstrip("="), len(content)])
+        writer.writerow([os.path.relpath(record, lib).replace(os.sep, "/"), "", ""])
+    print("Installed {} to {}".format(WHEEL_NAME, lib))
+
+
+def main():
+    user, target = parse_args(sys.argv[2:])
+    wheel = read_payload(sys.argv[1])
+    check_python()
+    install(wheel, scheme_paths(user, target))
+
+
+main()
+"##;
+
+/// Runs the installer with python, the wheel is appended to the script
+const SHELL_SCRIPT: &str = r##"#!/bin/sh
+# Installs {wheel_name} without network access, the wheel is embedded at the end of this file.
+# Usage: sh {script_name} [--user | --target <dir>], set PYTHON to use another interpreter
+set -e
+exec "${PYTHON:-python3}" - "$0" "$@" <<'MATURIN_INSTALLER'
+{installer}
+MATURIN_INSTALLER
+"##;
+
+/// Pipes the installer to python, the wheel is appended to the script in a comment
+const POWERSHELL_SCRIPT: &str = r##"# Installs {wheel_name} without network access, the wheel is embedded at the end of this file.
+# Usage: .\{script_name} [--user | --target <dir>], set $env:PYTHON to use another interpreter
+$ErrorActionPreference = "Stop"
+$python = if ($env:PYTHON) { $env:PYTHON } else { "python" }
+$installer = @'
+{installer}
+'@
+$installer | & $python - $PSCommandPath @args
+exit $LASTEXITCODE
+"##;
+
+/// Writes installer scripts next to the wheel which install it without a package index, a shell
+/// script for unix wheels and a PowerShell script for windows wheels
+///
+/// The wheel is embedded as base64 at the end of the script and installed by a small python
+/// installer, which doesn't need pip.
+pub fn write_installers(wheel_path: &Path) -> Result<Vec<PathBuf>> {
+    let wheel_name = wheel_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context("Wheel path must be a valid UTF-8 file name")?;
+    let wheel_stem = wheel_name.trim_end_matches(".whl");
+    let platform_tag = wheel_stem.rsplit('-').next().unwrap_or_default();
+    let wheel = fs::read(wheel_path)?;
+    let installer = INSTALLER
+        .replace("{wheel_name}", wheel_name)
+        .replace("{wheel_hash}", &format!("{:x}", Sha256::digest(&wheel)));
+    let payload = base64::encode(&wheel)
+        .as_bytes()
+        .chunks(76)
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut scripts = Vec::new();
+    if !platform_tag.starts_with("win") {
+        scripts.push((
+            "sh",
+            SHELL_SCRIPT,
+            format!("# MATURIN WHEEL PAYLOAD\n{}\n", payload),
+        ));
+    }
+    if platform_tag.starts_with("win") || platform_tag == "any" {
+        scripts.push((
+            "ps1",
+            POWERSHELL_SCRIPT,
+            format!("<# MATURIN WHEEL PAYLOAD\n{}\n#>\n", payload),
+        ));
+    }
+    let mut installers = Vec::new();
+    for (extension, template, payload) in scripts {
+        let script_path = wheel_path.with_file_name(format!("{}.{}", wheel_stem, extension));
+        let script_name = script_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .context("Installer path must be a valid UTF-8 file name")?;
+        debug!("Writing installer {}", script_path.display());
+        let script = template
+            .replace("{wheel_name}", wheel_name)
+            .replace("{script_name}", script_name)
+            .replace("{installer}", installer.trim_end());
+        fs::write(&script_path, script + &payload)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&script_path, std::fs::Permissions::from_mode(0o755))?;
+        }
+        installers.push(script_path);
+    }
+    Ok(installers)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::module_writer::{test_metadata, test_wheel_writer};
+    use crate::ModuleWriter;
+    use std::process::Command;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_installers() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut metadata = test_metadata();
+        metadata
+            .scripts
+            .insert("foo".to_string(), "foo:main".to_string());
+        let mut writer = test_wheel_writer(temp_dir.path(), &metadata, "py3-none-any").unwrap();
+        writer
+            .add_bytes("foo/__init__.py", b"def main():\n    print('foo')\n")
+            .unwrap();
+        writer
+            .add_bytes_with_permissions(
+                "foo-0.1.0.data/scripts/bar",
+                b"#!python\nprint('bar')\n",
+                0o755,
+            )
+            .unwrap();
+        let wheel_path = writer.finish().unwrap();
+        let installers = write_installers(&wheel_path).unwrap();
+        assert_eq!(
+            installers,
+            [
+                temp_dir.path().join("foo-0.1.0-py3-none-any.sh"),
+                temp_dir.path().join("foo-0.1.0-py3-none-any.ps1")
+            ]
+        );
+
+        let target = temp_dir.path().join("target");
+        let install = || {
+            let output = Command::new("sh")
+                .arg(&installers[0])
+                .arg("--target")
+                .arg(&target)
+                .env("PYTHON", "python3")
+                .output()
+                .unwrap();
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        };
+        // The second run replaces the first installation
+        install();
+        install();
+        for script in ["foo", "bar"] {
+            let output = Command::new(target.join("bin").join(script))
+                .env("PYTHONPATH", &target)
+                .output()
+                .unwrap();
+            assert_eq!(
+                String::from_utf8_lossy(&output.stdout),
+                format!("{}\n", script)
+            );
+        }
+        let record = fs::read_to_string(target.join("foo-0.1.0.dist-info").join("RECORD")).unwrap();
+        assert!(record.contains("foo/__init__.py,sha256="));
+        assert!(record.contains("bin/foo,sha256="));
+        assert!(record.ends_with("foo-0.1.0.dist-info/RECORD,,\n"));
+
+        // Replacing an installation only removes files inside of the installation directory
+        let outside = temp_dir.path().join("outside.txt");
+        fs::write(&outside, "keep").unwrap();
+        fs::write(
+            target.join("foo-0.1.0.dist-info").join("RECORD"),
+            format!("../outside.txt,,\n{},,\n", outside.display()),
+        )
+        .unwrap();
+        install();
+        assert!(outside.is_file());
+    }
+}
@@ -25,38 +25,90 @@
 
 #![deny(missing_docs)]
 
-pub use crate::build_context::{BridgeModel, BuildContext, BuiltWheelMetadata};
+pub use crate::build_context::{
+    BridgeModel, BuildContext, BuildReport, BuiltWheelMetadata, TargetReport, WheelReport,
+};
 pub use crate::build_options::{BuildOptions, CargoOptions};
 pub use crate::cargo_toml::CargoToml;
-pub use crate::compile::{compile, BuildArtifact};
+pub use crate::check::{check, CheckOptions};
+pub use crate::compile::{compile, BuildArtifact, CARGO_LOG_TARGET};
+pub use crate::data::{update_data, UpdateDataOptions};
 pub use crate::develop::develop;
+pub use crate::doctor::{doctor, DoctorOptions};
+pub use crate::error_category::{ConfigError, ErrorCategory, ErrorReport};
+pub use crate::install_embedded::{install_embedded, InstallEmbeddedOptions};
 pub use crate::metadata::{Metadata21, WheelMetadata};
+pub use crate::migrate_config::{migrate_config, MigrateConfigOptions};
 pub use crate::module_writer::{
-    write_dist_info, ModuleWriter, PathWriter, SDistWriter, WheelWriter,
+    write_dist_info, ModuleWriter, PathWriter, SDistWriter, WheelReader, WheelWriter,
 };
 pub use crate::new_project::{init_project, new_project, GenerateProjectOptions};
 pub use crate::pyproject_toml::PyProjectToml;
 pub use crate::python_interpreter::PythonInterpreter;
+pub use crate::retry::{progress_path, RetryOptions, RunProgress};
+pub use crate::self_test::{self_test, SelfTestOptions};
+pub use crate::shard::Shard;
+pub use crate::show_metadata::{resolve_metadata, show_metadata, MetadataOptions};
+pub use crate::source_distribution::verify_source_distribution;
+pub use crate::stats::{stats, ArtifactSize, BuildRecord, StatsOptions};
+pub use crate::sync_metadata::{sync_metadata, SyncMetadataOptions};
 pub use crate::target::Target;
+pub use crate::targets::{
+    targets, PlannedInterpreter, PlannedTarget, PlannedWheel, TargetsOptions, TargetsReport,
+};
+pub use crate::toml_editor::TomlEditor;
 #[cfg(feature = "upload")]
 pub use crate::upload::{upload, upload_ui, PublishOpt, Registry, UploadError};
+pub use crate::verify::{verify, verify_distribution};
+pub use crate::verify_sdist::{verify_sdist, VerifySdistOptions};
 pub use auditwheel::PlatformTag;
 
+mod artifact_cache;
 mod auditwheel;
 mod build_context;
 mod build_options;
 mod cargo_toml;
+mod check;
+mod classifiers;
 mod compile;
 mod cross_compile;
+mod data;
 mod develop;
+mod doctor;
+mod dynamic_metadata;
+mod error_category;
+mod file_lock;
+mod globs;
+mod hooks;
+mod import_lib;
+mod install_embedded;
+mod installer;
+mod lto;
+mod marker;
 mod metadata;
+mod migrate_config;
 mod module_writer;
 mod new_project;
 mod polyfill;
 mod project_layout;
 pub mod pyproject_toml;
 mod python_interpreter;
+mod readme;
+mod retry;
+mod self_test;
+mod shard;
+mod show_metadata;
 mod source_distribution;
+mod stats;
+mod sync_metadata;
+pub mod tags;
 mod target;
+mod target_cfg;
+mod targets;
+mod toml_editor;
 #[cfg(feature = "upload")]
 mod upload;
+mod verify;
+mod verify_sdist;
+mod wheel_manifest;
+mod zipapp;
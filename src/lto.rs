@@ -0,0 +1,264 @@
+//! Link time optimization from `[tool.maturin.lto]`: the `lto` of the cargo profile and linker
+//! plugin LTO, which optimizes across rust and C/C++ code and needs clang and lld instead of the
+//! default toolchain of the target
+use crate::pyproject_toml::{LtoConfig, LtoMode};
+use crate::{ConfigError, Target};
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::env;
+use std::process::Command;
+
+/// What `[tool.maturin.lto]` adds to the build of one target
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct LtoOptions {
+    /// Flags for `RUSTFLAGS`
+    pub rustflags: Vec<String>,
+    /// The linker, unless `[tool.maturin.target.<triple>]` sets one
+    pub linker: Option<String>,
+    /// Additional arguments for the linker
+    pub link_args: Vec<String>,
+    /// Environment variables for cargo and the build scripts, without the ones that are already
+    /// set
+    pub env: BTreeMap<String, String>,
+}
+
+/// The clang that compiles the C code and links, `clang-cl` for the msvc targets
+fn clang(config: &LtoConfig, target: &Target) -> String {
+    match &config.clang {
+        Some(clang) => clang.clone(),
+        None if target.is_msvc() => "clang-cl".to_string(),
+        None => "clang".to_string(),
+    }
+}
+
+/// The C++ compiler that belongs to the clang, e.g. `clang++-17` for `clang-17`
+fn clang_cxx(clang: &str, target: &Target) -> String {
+    if target.is_msvc() {
+        return clang.to_string();
+    }
+    match clang.rfind("clang") {
+        Some(index) => format!("{}clang++{}", &clang[..index], &clang[index + 5..]),
+        None => clang.to_string(),
+    }
+}
+
+/// Translates `[tool.maturin.lto]` into the options for cargo, failing if the target doesn't
+/// support linker plugin LTO. `cargo_profile` is the profile whose `lto` is set.
+pub(crate) fn lto_options(
+    config: &LtoConfig,
+    target: &Target,
+    zig: bool,
+    cargo_profile: &str,
+) -> Result<LtoOptions> {
+    let mut options = LtoOptions::default();
+    let mut env_vars = Vec::new();
+    if let Some(mode) = config.mode {
+        env_vars.push((
+            format!(
+                "CARGO_PROFILE_{}_LTO",
+                cargo_profile.to_uppercase().replace('-', "_")
+            ),
+            mode.to_string(),
+        ));
+    }
+    if config.linker_plugin {
+        if zig {
+            bail!(ConfigError::new(
+                "linker-plugin in [tool.maturin.lto] doesn't work with --zig, which links with \
+                its own clang"
+            ));
+        }
+        if !(target.is_linux() || target.is_macos() || target.is_msvc()) {
+            bail!(ConfigError::new(format!(
+                "linker-plugin in [tool.maturin.lto] is only supported for linux, macOS and \
+                windows msvc targets, not for {}",
+                target.target_triple()
+            )));
+        }
+        if config.lld.is_some() && target.is_msvc() {
+            bail!(ConfigError::new(format!(
+                "lld in [tool.maturin.lto] is only used on linux and macOS, {} uses lld-link",
+                target.target_triple(),
+            )));
+        }
+        options.rustflags = vec!["-C".to_string(), "linker-plugin-lto".to_string()];
+        let clang = clang(config, target);
+        if target.is_msvc() {
+            options.linker = Some("lld-link".to_string());
+        } else {
+            options.linker = Some(clang.clone());
+        }
+        // Apple's ld64 can only read the bitcode of Apple's LLVM, not the one of rustc
+        if target.is_linux() || target.is_macos() {
+            let lld = config.lld.as_deref().unwrap_or("lld");
+            options.link_args.push(format!("-fuse-ld={}", lld));
+        }
+        // The C and C++ code compiled by the cc crate has to be LLVM bitcode as well
+        let flto = match config.mode {
+            Some(LtoMode::Fat) => "-flto",
+            _ => "-flto=thin",
+        };
+        let cc_target = target.target_triple().replace('-', "_");
+        env_vars.push((format!("CC_{}", cc_target), clang.clone()));
+        env_vars.push((format!("CXX_{}", cc_target), clang_cxx(&clang, target)));
+        env_vars.push((format!("CFLAGS_{}", cc_target), flto.to_string()));
+        env_vars.push((format!("CXXFLAGS_{}", cc_target), flto.to_string()));
+    }
+    for (key, value) in env_vars {
+        if env::var_os(&key).is_none() {
+            options.env.insert(key, value);
+        }
+    }
+    Ok(options)
+}
+
+/// Whether the output of `clang --version` is the one of Apple's clang
+fn is_apple_clang(output: &str) -> bool {
+    output.starts_with("Apple")
+}
+
+/// The major version in the output of `clang --version`, `None` for Apple's clang, whose
+/// versions don't match the LLVM ones
+fn parse_clang_major(output: &str) -> Option<u32> {
+    if is_apple_clang(output) {
+        return None;
+    }
+    let line = output.lines().next()?;
+    let version = line.split("clang version ").nth(1)?;
+    version.split('.').next()?.parse().ok()
+}
+
+/// Checks that clang can be run and warns if it uses another LLVM version than rustc, in which
+/// case the linker plugin may fail to read the bitcode of rustc. Apple's clang is an error on
+/// macOS, its LLVM never matches the one of rustc and it can't link with lld.
+pub(crate) fn check_clang(config: &LtoConfig, target: &Target) -> Result<()> {
+    let clang = clang(config, target);
+    let output = Command::new(&clang)
+        .arg("--version")
+        .output()
+        .with_context(|| {
+            format!(
+                "linker-plugin in [tool.maturin.lto] needs {}, but it couldn't be run",
+                clang
+            )
+        })?;
+    let version = String::from_utf8_lossy(&output.stdout);
+    if target.is_macos() && is_apple_clang(&version) {
+        bail!(ConfigError::new(format!(
+            "linker-plugin in [tool.maturin.lto] needs an LLVM clang on macOS, but {} is Apple's \
+            clang. Install LLVM, e.g. with `brew install llvm lld`, and set `clang` in \
+            [tool.maturin.lto] to its clang, e.g. `/opt/homebrew/opt/llvm/bin/clang`.",
+            clang
+        )));
+    }
+    let clang_major = parse_clang_major(&version);
+    let rustc_major = target
+        .rustc_version
+        .llvm_version
+        .as_ref()
+        .map(|version| version.major as u32);
+    if let (Some(clang_major), Some(rustc_major)) = (clang_major, rustc_major) {
+        if clang_major != rustc_major {
+            eprintln!(
+                "⚠️  Warning: {} uses LLVM {} but rustc uses LLVM {}, linker plugin LTO needs \
+                the same version. Set `clang` in [tool.maturin.lto] to a matching clang, e.g. \
+                `clang-{}`.",
+                clang, clang_major, rustc_major, rustc_major
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lto_options() {
+        let linux =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let config = LtoConfig {
+            mode: Some(LtoMode::Thin),
+            linker_plugin: true,
+            clang: Some("clang-17".to_string()),
+            lld: Some("lld-17".to_string()),
+        };
+        let options = lto_options(&config, &linux, false, "release-lto").unwrap();
+        assert_eq!(options.rustflags, ["-C", "linker-plugin-lto"]);
+        assert_eq!(options.linker.as_deref(), Some("clang-17"));
+        assert_eq!(options.link_args, ["-fuse-ld=lld-17"]);
+        assert_eq!(
+            options
+                .env
+                .get("CARGO_PROFILE_RELEASE_LTO_LTO")
+                .map(String::as_str),
+            Some("thin")
+        );
+        assert_eq!(
+            options
+                .env
+                .get("CXX_x86_64_unknown_linux_gnu")
+                .map(String::as_str),
+            Some("clang++-17")
+        );
+        assert!(lto_options(&config, &linux, true, "release").is_err());
+
+        let macos = Target::from_target_triple(Some("aarch64-apple-darwin".to_string())).unwrap();
+        let options = lto_options(&config, &macos, false, "release").unwrap();
+        assert_eq!(options.linker.as_deref(), Some("clang-17"));
+        assert_eq!(options.link_args, ["-fuse-ld=lld-17"]);
+
+        let msvc = Target::from_target_triple(Some("x86_64-pc-windows-msvc".to_string())).unwrap();
+        let config = LtoConfig {
+            mode: Some(LtoMode::Fat),
+            linker_plugin: true,
+            ..Default::default()
+        };
+        let options = lto_options(&config, &msvc, false, "release").unwrap();
+        assert_eq!(options.linker.as_deref(), Some("lld-link"));
+        assert!(options.link_args.is_empty());
+        let lld = LtoConfig {
+            lld: Some("lld-17".to_string()),
+            ..config.clone()
+        };
+        assert!(lto_options(&lld, &msvc, false, "release").is_err());
+        assert_eq!(
+            options
+                .env
+                .get("CFLAGS_x86_64_pc_windows_msvc")
+                .map(String::as_str),
+            Some("-flto")
+        );
+
+        let wasm = Target::from_target_triple(Some("wasm32-wasi".to_string())).unwrap();
+        assert!(lto_options(&config, &wasm, false, "release").is_err());
+        // Only the profile's lto works everywhere
+        let config = LtoConfig {
+            mode: Some(LtoMode::Off),
+            ..Default::default()
+        };
+        let options = lto_options(&config, &wasm, false, "dev").unwrap();
+        assert_eq!(
+            options.env.get("CARGO_PROFILE_DEV_LTO").map(String::as_str),
+            Some("off")
+        );
+        assert!(options.linker.is_none());
+    }
+
+    #[test]
+    fn test_parse_clang_major() {
+        assert_eq!(
+            parse_clang_major("clang version 17.0.6\nTarget: x86_64-pc-linux-gnu\n"),
+            Some(17)
+        );
+        assert_eq!(
+            parse_clang_major("Ubuntu clang version 14.0.0-1ubuntu1.1\n"),
+            Some(14)
+        );
+        let apple = "Apple clang version 15.0.0 (clang-1500.1.0.2.5)\n";
+        assert_eq!(parse_clang_major(apple), None);
+        assert!(is_apple_clang(apple));
+        assert!(!is_apple_clang("Homebrew clang version 17.0.6\n"));
+    }
+}
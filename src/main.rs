@@ -7,15 +7,24 @@ use anyhow::{bail, Context, Result};
 use cargo_zigbuild::Zig;
 use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use clap_complete::Generator;
+use maturin::pyproject_toml::CargoLockInclusion;
 use maturin::{
-    develop, init_project, new_project, write_dist_info, BridgeModel, BuildOptions, CargoOptions,
-    GenerateProjectOptions, PathWriter, PlatformTag, PythonInterpreter, Target,
+    check, develop, doctor, init_project, install_embedded, migrate_config, new_project, self_test,
+    show_metadata, stats, sync_metadata, targets, update_data, verify, verify_sdist,
+    verify_source_distribution, write_dist_info, BridgeModel, BuildContext, BuildOptions,
+    BuildReport, CargoOptions, CheckOptions, DoctorOptions, ErrorReport, GenerateProjectOptions,
+    InstallEmbeddedOptions, MetadataOptions, MigrateConfigOptions, PathWriter, PlatformTag,
+    PyProjectToml, PythonInterpreter, RetryOptions, RunProgress, SelfTestOptions, Shard,
+    StatsOptions, SyncMetadataOptions, Target, TargetsOptions, UpdateDataOptions,
+    VerifySdistOptions,
 };
 #[cfg(feature = "upload")]
 use maturin::{upload_ui, PublishOpt};
+use std::collections::HashSet;
 use std::env;
 use std::io;
-use std::path::PathBuf;
+use std::iter;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 #[derive(Debug, Parser)]
@@ -24,9 +33,23 @@ use std::str::FromStr;
     name = env!("CARGO_PKG_NAME"),
     display_order = 1,
 )]
-#[cfg_attr(feature = "cargo-clippy", allow(clippy::large_enum_variant))]
 /// Build and publish crates with pyo3, rust-cpython and cffi bindings as well
 /// as rust binaries as python packages
+struct Cli {
+    /// How to print the error if maturin fails, `json` prints it as a json object for scripts
+    #[arg(long, global = true, value_enum, default_value_t = ErrorFormat::Human)]
+    error_format: ErrorFormat,
+    /// Also write debug logs, including the cargo invocations and compiler messages, to this
+    /// file, e.g. to attach them to a bug report
+    #[cfg(feature = "log")]
+    #[arg(long, global = true, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Opt,
+}
+
+#[derive(Debug, Subcommand)]
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::large_enum_variant))]
 enum Opt {
     #[command(name = "build", alias = "b")]
     /// Build the crate into python packages
@@ -40,6 +63,33 @@ enum Opt {
         /// Build a source distribution
         #[arg(long)]
         sdist: bool,
+        /// Also build a single file zipapp (.pyz) of the binary, only for bin bindings
+        #[arg(long)]
+        zipapp: bool,
+        /// Also build self-extracting installer scripts embedding the wheels, which install them
+        /// without a package index
+        #[arg(long)]
+        installer: bool,
+        /// Write a json report of the wheels built for each target to the file
+        #[arg(long, value_name = "PATH")]
+        report: Option<PathBuf>,
+        /// Don't build the wheels that an earlier build wrote to the output directory from the
+        /// same version, sources and options, e.g. when re-running a matrix build after a partial
+        /// failure
+        #[arg(long)]
+        skip_existing: bool,
+        /// Only build the part of the wheels for all targets and interpreters that falls to this
+        /// job when the builds are split between `COUNT` parallel jobs, e.g. `--shard 2/5`.
+        /// The source distribution is only built by the first shard
+        #[arg(long, value_name = "INDEX/COUNT")]
+        shard: Option<Shard>,
+        /// Print the cargo invocations, their environment, the artifact paths and the crates
+        /// that were never built, without building anything
+        #[arg(
+            long,
+            conflicts_with_all = ["sdist", "zipapp", "installer", "report", "skip_existing", "shard"]
+        )]
+        plan: bool,
         #[command(flatten)]
         build: BuildOptions,
     },
@@ -108,6 +158,20 @@ enum Opt {
         /// directory in the project's target directory
         #[arg(short, long)]
         out: Option<PathBuf>,
+        /// Include the dependencies from `cargo vendor` and a `.cargo/config.toml` using them, so
+        /// the source distribution builds without network access
+        #[arg(long)]
+        vendor: bool,
+        /// Only include files committed to git, like `git archive`
+        #[arg(long)]
+        from_git: bool,
+        /// Whether to include the Cargo.lock, overriding `include-cargo-lock` in pyproject.toml
+        #[arg(long, value_enum, value_name = "WHEN")]
+        include_cargo_lock: Option<CargoLockInclusion>,
+        /// Build a wheel from the unpacked source distribution with this maturin as build
+        /// backend, failing if the source distribution is missing files
+        #[arg(long)]
+        verify: bool,
     },
     /// Create a new cargo project in an existing directory
     #[command(name = "init")]
@@ -138,11 +202,130 @@ enum Opt {
         #[arg(value_name = "FILE")]
         files: Vec<PathBuf>,
     },
+    /// Copy the metadata that differs between Cargo.toml and pyproject.toml
+    /// from the manifest set by `tool.maturin.metadata-source` into the other one
+    ///
+    /// Only shows a diff of the changes unless `--write` is passed
+    #[command(name = "sync-metadata")]
+    SyncMetadata {
+        #[command(flatten)]
+        options: SyncMetadataOptions,
+    },
+    /// Replace deprecated options in `[tool.maturin]` of pyproject.toml with their replacements
+    ///
+    /// Only shows a diff of the changes unless `--write` is passed
+    #[command(name = "migrate-config")]
+    MigrateConfig {
+        #[command(flatten)]
+        options: MigrateConfigOptions,
+    },
+    /// Install wheels into the embeddable distribution of python for windows
+    ///
+    /// Extracts the wheels to the site-packages directory in `Lib` and adds it to the
+    /// `python3XY._pth` file, since the embeddable distribution has neither pip nor site-packages
+    #[command(name = "install-embedded")]
+    InstallEmbedded {
+        #[command(flatten)]
+        options: InstallEmbeddedOptions,
+    },
+    /// Print the python package metadata of the project without building it
+    ///
+    /// Shows the metadata after merging Cargo.toml and pyproject.toml and
+    /// computing the version from git tags, as it would be written to the wheels.
+    /// The hooks of `[tool.maturin.dynamic]` only run when building.
+    #[command(name = "metadata")]
+    Metadata {
+        #[command(flatten)]
+        options: MetadataOptions,
+    },
+    /// Show the wheels `maturin build` would build for each target and
+    /// interpreter, without building anything
+    ///
+    /// With `--json`, the targets, interpreters, tags and wheel file names
+    /// can be used to generate a CI job matrix.
+    #[command(name = "targets")]
+    Targets {
+        #[command(flatten)]
+        options: TargetsOptions,
+    },
+    /// Show the recorded build statistics of the project
+    ///
+    /// Builds are only recorded with `stats = true` in `[tool.maturin]` or
+    /// `MATURIN_STATS=1`. The statistics are stored in the target directory
+    /// and never leave your machine.
+    #[command(name = "stats")]
+    Stats {
+        #[command(flatten)]
+        options: StatsOptions,
+    },
+    /// Resume the last failed `maturin build` or `maturin publish` of the project
+    ///
+    /// Runs the failed command again with the same arguments, reusing the
+    /// wheels of the targets that were already built, so only the failed
+    /// step and what comes after it is repeated.
+    #[command(name = "retry")]
+    Retry {
+        #[command(flatten)]
+        options: RetryOptions,
+    },
+    /// Check built wheels and source distributions for packaging problems
+    ///
+    /// Verifies the RECORD hashes and sizes, the METADATA and WHEEL files
+    /// and that the tags match the contained shared libraries.
+    #[command(name = "verify")]
+    Verify {
+        /// The wheels and source distributions to check
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Check that a source distribution is reproducible and complete
+    ///
+    /// Builds the source distribution again from the current tree and
+    /// compares the digests. If they differ, lists the files that are
+    /// missing, extra or changed compared to the include rules of the tree.
+    #[command(name = "verify-sdist")]
+    VerifySdist {
+        #[command(flatten)]
+        options: VerifySdistOptions,
+    },
+    /// Check wheels and source distributions before uploading them
+    ///
+    /// Runs the checks of `maturin verify` and, similar to `twine check`, checks
+    /// the metadata, the classifiers, the version, the platform tags and that the
+    /// long description renders on PyPI. Exits with an error if any check fails.
+    ///
+    /// With `--spec`, reports on each rule of the binary distribution format
+    /// specification instead.
+    #[command(name = "check")]
+    Check {
+        #[command(flatten)]
+        options: CheckOptions,
+    },
+    /// Check the build environment for known problems with a target
+    ///
+    /// Reports missing linkers and import libraries as well as ABI caveats, e.g. of
+    /// the MinGW (windows-gnu) targets. Exits with an error if a problem prevents
+    /// building.
+    #[command(name = "doctor")]
+    Doctor {
+        #[command(flatten)]
+        options: DoctorOptions,
+    },
+    /// Manage maturin itself
+    #[command(subcommand, name = "self")]
+    SelfCommand(SelfCommand),
     /// Backend for the PEP 517 integration. Not for human consumption
     ///
     /// The commands are meant to be called from the python PEP 517
-    #[command(subcommand)]
-    Pep517(Pep517Command),
+    #[command(name = "pep517", hide = true)]
+    Pep517 {
+        /// Only check that the running maturin matches the version required in the
+        /// pyproject.toml of the current directory and print the version
+        #[arg(long)]
+        version_check: bool,
+        #[command(subcommand)]
+        command: Option<Pep517Command>,
+    },
     /// Generate shell completions
     #[command(name = "completions", hide = true)]
     Completions {
@@ -158,7 +341,6 @@ enum Opt {
 ///
 /// The commands are meant to be called from the python PEP 517
 #[derive(Debug, Subcommand)]
-#[command(name = "pep517", hide = true)]
 enum Pep517Command {
     /// The implementation of prepare_metadata_for_build_wheel
     #[command(name = "write-dist-info")]
@@ -203,6 +385,38 @@ enum Pep517Command {
     },
 }
 
+/// Manage maturin itself
+#[derive(Debug, Subcommand)]
+enum SelfCommand {
+    /// Refresh the manylinux and musllinux policies, the trove classifiers and the python
+    /// versions built into maturin
+    ///
+    /// Downloads the latest data from upstream into the user cache, where maturin picks it up
+    /// in addition to its built-in data, so that e.g. a new manylinux policy can be used
+    /// without a new maturin release. Set `MATURIN_DATA_DIR` to use another directory.
+    #[command(name = "update-data")]
+    UpdateData {
+        #[command(flatten)]
+        options: UpdateDataOptions,
+    },
+    /// Check that this machine can build python packages
+    ///
+    /// Builds small pyo3, cffi and bin sample projects for the python interpreters found
+    /// and reports which kinds of bindings work. Since the samples are known to build, a
+    /// failure points to a problem of the build environment rather than of a project.
+    #[command(name = "test")]
+    Test {
+        #[command(flatten)]
+        options: SelfTestOptions,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ErrorFormat {
+    Human,
+    Json,
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 #[allow(clippy::enum_variant_names)]
 enum Shell {
@@ -233,7 +447,29 @@ impl FromStr for Shell {
 /// Dispatches into the native implementations of the PEP 517 functions
 ///
 /// The last line of stdout is used as return value from the python part of the implementation
-fn pep517(subcommand: Pep517Command) -> Result<()> {
+fn pep517(version_check: bool, subcommand: Option<Pep517Command>) -> Result<()> {
+    // A build frontend without build isolation may run any installed maturin, so make sure
+    // it's one the project asked for before doing anything
+    let pyproject_file = Path::new("pyproject.toml");
+    if pyproject_file.is_file() {
+        // The python frontend passes its interpreter to the commands that build wheels
+        let python = match &subcommand {
+            Some(Pep517Command::WriteDistInfo { build_options, .. })
+            | Some(Pep517Command::BuildWheel { build_options, .. }) => {
+                build_options.interpreter.first().map(PathBuf::as_path)
+            }
+            _ => None,
+        };
+        PyProjectToml::new(pyproject_file)?.check_maturin_version(python)?;
+    }
+    let subcommand = match subcommand {
+        Some(subcommand) => subcommand,
+        None if version_check => {
+            println!("{}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        None => bail!("Expected either --version-check or a pep517 subcommand"),
+    };
     match subcommand {
         Pep517Command::WriteDistInfo {
             build_options,
@@ -246,7 +482,7 @@ fn pep517(subcommand: Pep517Command) -> Result<()> {
             // Since afaik all other PEP 517 backends also return linux tagged wheels, we do so too
             let tags = match context.bridge {
                 BridgeModel::Bindings(..) | BridgeModel::Bin(Some(..)) => {
-                    vec![context.interpreter[0].get_tag(
+                    vec![context.interpreters()?[0].get_tag(
                         &context.target,
                         &[PlatformTag::Linux],
                         context.universal2,
@@ -267,7 +503,12 @@ fn pep517(subcommand: Pep517Command) -> Result<()> {
             };
 
             let mut writer = PathWriter::from_path(metadata_directory);
-            write_dist_info(&mut writer, &context.metadata21, &tags)?;
+            write_dist_info(
+                &mut writer,
+                &context.metadata21,
+                &tags,
+                context.build_tag.as_deref(),
+            )?;
             println!("{}", context.metadata21.get_dist_info_dir().display());
         }
         Pep517Command::BuildWheel {
@@ -275,6 +516,7 @@ fn pep517(subcommand: Pep517Command) -> Result<()> {
             strip,
             editable,
         } => {
+            build_options.run_pre_build_hooks(&["wheel"])?;
             let build_context = build_options.into_build_context(true, strip, editable)?;
             let wheels = build_context.build_wheels()?;
             assert_eq!(wheels.len(), 1);
@@ -292,6 +534,7 @@ fn pep517(subcommand: Pep517Command) -> Result<()> {
                 },
                 ..Default::default()
             };
+            build_options.run_pre_build_hooks(&["sdist"])?;
             let build_context = build_options.into_build_context(false, false, false)?;
             let (path, _) = build_context
                 .build_source_distribution()?
@@ -303,40 +546,139 @@ fn pep517(subcommand: Pep517Command) -> Result<()> {
     Ok(())
 }
 
-fn run() -> Result<()> {
-    #[cfg(feature = "log")]
-    tracing_subscriber::fmt::init();
-
-    // Allow symlink `maturin` to `ar` to invoke `zig ar`
-    // See https://github.com/messense/cargo-zigbuild/issues/52
-    let mut args = env::args();
-    let program_path = PathBuf::from(args.next().expect("no program path"));
-    let program_name = program_path.file_stem().expect("no program name");
-    if program_name.eq_ignore_ascii_case("ar") {
-        let zig = Zig::Ar {
-            args: args.collect(),
-        };
-        zig.execute()?;
-        return Ok(());
+/// Runs the command of the last failed `maturin build` or `maturin publish` again
+fn retry(options: RetryOptions) -> Result<()> {
+    let progress = RunProgress::load(options)?;
+    eprintln!("🔁 Retrying `maturin {}`", progress.args.join(" "));
+    let args = iter::once(env!("CARGO_PKG_NAME").to_string()).chain(progress.args.clone());
+    let cli = Cli::try_parse_from(args).context("Failed to parse the arguments of the last run")?;
+    match cli.command {
+        opt @ Opt::Build { .. } => run(opt, Some(progress)),
+        #[cfg(feature = "upload")]
+        opt @ Opt::Publish { .. } => run(opt, Some(progress)),
+        _ => bail!("Only `maturin build` and `maturin publish` can be retried"),
     }
+}
 
-    let opt = Opt::parse();
+/// Assigns the builds of all targets to the shards and restricts each build context to the
+/// builds of `shard`
+fn select_shard(build_contexts: &mut [BuildContext], shard: Shard) -> Result<()> {
+    let mut builds = Vec::new();
+    for (index, build_context) in build_contexts.iter().enumerate() {
+        for name in build_context.build_names()? {
+            let build = format!("{} {}", build_context.target.target_triple(), name);
+            builds.push((build, (index, name)));
+        }
+    }
+    let total = builds.len();
+    let selected = shard.select(builds);
+    eprintln!(
+        "🧩 Shard {} builds {} of {} wheels",
+        shard,
+        selected.len(),
+        total
+    );
+    for build_context in build_contexts.iter_mut() {
+        build_context.selected_builds = Some(HashSet::new());
+    }
+    for (build, (index, name)) in selected {
+        eprintln!("🧩 Building {}", build);
+        if let Some(selected_builds) = &mut build_contexts[index].selected_builds {
+            selected_builds.insert(name);
+        }
+    }
+    Ok(())
+}
 
+/// Dispatches the subcommand. `resume` is the progress of an earlier failed run of the same
+/// `maturin build` or `maturin publish`
+fn run(opt: Opt, resume: Option<RunProgress>) -> Result<()> {
     match opt {
         Opt::Build {
             build,
             release,
             strip,
             sdist,
+            zipapp,
+            installer,
+            report,
+            skip_existing,
+            shard,
+            plan,
         } => {
-            let build_context = build.into_build_context(release, strip, false)?;
-            if sdist {
-                build_context
-                    .build_source_distribution()?
-                    .context("Failed to build source distribution, pyproject.toml not found")?;
+            if !plan {
+                build.run_pre_build_hooks(if sdist {
+                    &["sdist", "wheel"]
+                } else {
+                    &["wheel"]
+                })?;
             }
-            let wheels = build_context.build_wheels()?;
-            assert!(!wheels.is_empty());
+            let mut build_contexts = build.into_build_contexts(release, strip, false)?;
+            for build_context in &mut build_contexts {
+                build_context.skip_existing = skip_existing;
+            }
+            if plan {
+                for build_context in &build_contexts {
+                    print!("{}", build_context.build_plan()?);
+                }
+                return Ok(());
+            }
+            build_contexts[0].check_classifiers()?;
+            if let Some(shard) = shard {
+                select_shard(&mut build_contexts, shard)?;
+            }
+            let mut progress = match resume {
+                Some(progress) => progress,
+                None => RunProgress::start(&build_contexts[0].target_dir)?,
+            };
+            let mut build_report = BuildReport::default();
+            if sdist && shard.map_or(true, |shard| shard.index == 1) {
+                let path = match progress.finished_sdist() {
+                    Some(path) => path.to_path_buf(),
+                    None => {
+                        let (path, _) = build_contexts[0].build_source_distribution()?.context(
+                            "Failed to build source distribution, pyproject.toml not found",
+                        )?;
+                        progress.set_sdist(&path)?;
+                        path
+                    }
+                };
+                build_report.sdist = Some(path);
+            }
+            for build_context in &build_contexts {
+                if build_context
+                    .selected_builds
+                    .as_ref()
+                    .map_or(false, |selected| selected.is_empty())
+                {
+                    continue;
+                }
+                if let Some(wheels) = progress.finished_target(&build_context.target) {
+                    eprintln!(
+                        "🔁 Reusing the wheels built for {} by the failed run",
+                        build_context.target.target_triple()
+                    );
+                    build_report.add_target(&build_context.target, &wheels);
+                    continue;
+                }
+                if zipapp {
+                    build_context.build_zipapps()?;
+                }
+                let wheels = build_context.build_wheels()?;
+                assert!(!wheels.is_empty());
+                if installer {
+                    build_context.build_installers(&wheels)?;
+                }
+                progress.add_target(&build_context.target, &wheels)?;
+                build_report.add_target(&build_context.target, &wheels);
+            }
+            if build_contexts.len() > 1 {
+                print!("{}", build_report.summary());
+            }
+            if let Some(report) = report {
+                build_report.write(&report)?;
+            }
+            progress.finish()?;
         }
         #[cfg(feature = "upload")]
         Opt::Publish {
@@ -346,22 +688,63 @@ fn run() -> Result<()> {
             no_strip,
             no_sdist,
         } => {
-            let build_context = build.into_build_context(!debug, !no_strip, false)?;
+            build.run_pre_build_hooks(if no_sdist {
+                &["wheel"]
+            } else {
+                &["sdist", "wheel"]
+            })?;
+            let build_contexts = build.into_build_contexts(!debug, !no_strip, false)?;
+            build_contexts[0].check_classifiers()?;
 
-            if !build_context.release {
+            if debug {
                 eprintln!("⚠️  Warning: You're publishing debug wheels");
             }
 
-            let mut wheels = build_context.build_wheels()?;
+            let mut publish = publish;
+            let mut progress = match resume {
+                Some(progress) => {
+                    // The failed run may have uploaded some of the files already
+                    publish.skip_existing();
+                    progress
+                }
+                None => RunProgress::start(&build_contexts[0].target_dir)?,
+            };
+            let mut build_report = BuildReport::default();
+            let mut wheels = Vec::new();
+            for build_context in &build_contexts {
+                let target_wheels = match progress.finished_target(&build_context.target) {
+                    Some(target_wheels) => {
+                        eprintln!(
+                            "🔁 Reusing the wheels built for {} by the failed run",
+                            build_context.target.target_triple()
+                        );
+                        target_wheels
+                    }
+                    None => {
+                        let target_wheels = build_context.build_wheels()?;
+                        progress.add_target(&build_context.target, &target_wheels)?;
+                        target_wheels
+                    }
+                };
+                build_report.add_target(&build_context.target, &target_wheels);
+                wheels.extend(target_wheels);
+            }
+            if build_contexts.len() > 1 {
+                print!("{}", build_report.summary());
+            }
             if !no_sdist {
-                if let Some(sd) = build_context.build_source_distribution()? {
+                if let Some(path) = progress.finished_sdist() {
+                    wheels.push((path.to_path_buf(), "source".to_string()));
+                } else if let Some(sd) = build_contexts[0].build_source_distribution()? {
+                    progress.set_sdist(&sd.0)?;
                     wheels.push(sd);
                 }
             }
 
             let items = wheels.into_iter().map(|wheel| wheel.0).collect::<Vec<_>>();
 
-            upload_ui(&items, &publish)?
+            upload_ui(&items, &publish)?;
+            progress.finish()?;
         }
         Opt::ListPython { target } => {
             let found = if target.is_some() {
@@ -402,21 +785,57 @@ fn run() -> Result<()> {
 
             develop(bindings, cargo_options, &venv_dir, release, strip, extras)?;
         }
-        Opt::SDist { manifest_path, out } => {
+        Opt::SDist {
+            manifest_path,
+            out,
+            vendor,
+            from_git,
+            include_cargo_lock,
+            verify,
+        } => {
             let build_options = BuildOptions {
                 out,
                 cargo: CargoOptions {
                     manifest_path,
                     ..Default::default()
                 },
+                include_cargo_lock,
                 ..Default::default()
             };
-            let build_context = build_options.into_build_context(false, false, false)?;
-            build_context
+            build_options.run_pre_build_hooks(&["sdist"])?;
+            let mut build_context = build_options.into_build_context(false, false, false)?;
+            build_context.vendor |= vendor;
+            build_context.sdist_from_git |= from_git;
+            let (path, _) = build_context
                 .build_source_distribution()?
                 .context("Failed to build source distribution, pyproject.toml not found")?;
+            if verify {
+                // A target directory of its own keeps the cache of registry dependencies
+                // without replacing the artifacts of the regular builds
+                verify_source_distribution(
+                    &path,
+                    &env::current_exe()?,
+                    &build_context.target_dir.join("sdist-verify"),
+                )?;
+            }
         }
-        Opt::Pep517(subcommand) => pep517(subcommand)?,
+        Opt::SyncMetadata { options } => sync_metadata(options)?,
+        Opt::MigrateConfig { options } => migrate_config(options)?,
+        Opt::InstallEmbedded { options } => install_embedded(options)?,
+        Opt::Metadata { options } => show_metadata(options)?,
+        Opt::Targets { options } => targets(options)?,
+        Opt::Stats { options } => stats(options)?,
+        Opt::Retry { options } => retry(options)?,
+        Opt::Verify { files } => verify(&files)?,
+        Opt::VerifySdist { options } => verify_sdist(options)?,
+        Opt::Check { options } => check(&options)?,
+        Opt::Doctor { options } => doctor(options)?,
+        Opt::SelfCommand(SelfCommand::UpdateData { options }) => update_data(options)?,
+        Opt::SelfCommand(SelfCommand::Test { options }) => self_test(options)?,
+        Opt::Pep517 {
+            version_check,
+            command,
+        } => pep517(version_check, command)?,
         Opt::InitProject { path, options } => init_project(path, options)?,
         Opt::NewProject { path, options } => new_project(path, options)?,
         #[cfg(feature = "upload")]
@@ -429,7 +848,7 @@ fn run() -> Result<()> {
             upload_ui(&files, &publish)?
         }
         Opt::Completions { shell } => {
-            let mut cmd = Opt::command();
+            let mut cmd = Cli::command();
             match shell {
                 Shell::Fig => {
                     cmd.set_bin_name(env!("CARGO_BIN_NAME"));
@@ -470,11 +889,85 @@ fn main() {
         human_panic::setup_panic!();
     }
 
-    if let Err(e) = run() {
-        eprintln!("💥 maturin failed");
-        for cause in e.chain().collect::<Vec<_>>().iter() {
-            eprintln!("  Caused by: {}", cause);
+    // Allow symlink `maturin` to `ar` to invoke `zig ar`
+    // See https://github.com/messense/cargo-zigbuild/issues/52
+    let mut args = env::args();
+    let program_path = PathBuf::from(args.next().expect("no program path"));
+    let program_name = program_path.file_stem().expect("no program name");
+    if program_name.eq_ignore_ascii_case("ar") {
+        #[cfg(feature = "log")]
+        tracing_subscriber::fmt::init();
+        let zig = Zig::Ar {
+            args: args.collect(),
+        };
+        if let Err(e) = zig.execute() {
+            exit_with_error(e, ErrorFormat::Human);
+        }
+        return;
+    }
+
+    let cli = Cli::parse();
+    #[cfg(feature = "log")]
+    if let Err(e) = setup_logging(cli.log_file.as_deref()) {
+        exit_with_error(e, cli.error_format);
+    }
+    if let Err(e) = run(cli.command, None) {
+        exit_with_error(e, cli.error_format);
+    }
+}
+
+/// Logs to the console as configured with `RUST_LOG` and, if given, everything from the debug level
+/// up to the log file
+#[cfg(feature = "log")]
+fn setup_logging(log_file: Option<&Path>) -> Result<()> {
+    use std::sync::Mutex;
+    use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+    use tracing_subscriber::prelude::*;
+
+    // The output of cargo and the final error are printed to the console anyway
+    let console_filter = EnvFilter::from_default_env()
+        .add_directive(format!("{}=off", maturin::CARGO_LOG_TARGET).parse()?)
+        .add_directive(format!("{}=off", FAILURE_LOG_TARGET).parse()?);
+    let console = tracing_subscriber::fmt::layer().with_filter(console_filter);
+    let file = match log_file {
+        Some(log_file) => {
+            let file = fs_err::File::create(log_file)?;
+            let layer = tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .with_filter(LevelFilter::DEBUG);
+            Some(layer)
+        }
+        None => None,
+    };
+    tracing_subscriber::registry()
+        .with(console)
+        .with(file)
+        .try_init()?;
+    tracing::debug!("maturin {}", env!("CARGO_PKG_VERSION"));
+    Ok(())
+}
+
+/// The target of the log event with the error maturin failed with
+const FAILURE_LOG_TARGET: &str = "maturin::failure";
+
+/// Prints the error and exits with the code of its category
+fn exit_with_error(e: anyhow::Error, error_format: ErrorFormat) -> ! {
+    tracing::error!(target: FAILURE_LOG_TARGET, "maturin failed: {:?}", e);
+    let report = ErrorReport::new(&e);
+    match error_format {
+        ErrorFormat::Human => {
+            eprintln!("💥 maturin failed");
+            for cause in e.chain().collect::<Vec<_>>().iter() {
+                eprintln!("  Caused by: {}", cause);
+            }
+        }
+        ErrorFormat::Json => {
+            eprintln!(
+                "{}",
+                serde_json::to_string(&report).expect("Failed to serialize the error")
+            );
         }
-        std::process::exit(1);
     }
+    std::process::exit(report.exit_code);
 }
@@ -0,0 +1,460 @@
+//! Evaluates [PEP 508](https://peps.python.org/pep-0508/#environment-markers) environment
+//! markers such as `python_version < "3.11"` against an interpreter
+use anyhow::{bail, format_err, Context, Result};
+use pep440::Version;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::process::Command;
+
+/// Prints the values of the marker variables as json, following the reference implementation
+/// in PEP 508
+const MARKER_ENVIRONMENT_SCRIPT: &str = r#"
+import json, os, platform, sys
+
+def format_full_version(info):
+    version = "{0.major}.{0.minor}.{0.micro}".format(info)
+    if info.releaselevel != "final":
+        version += info.releaselevel[0] + str(info.serial)
+    return version
+
+print(json.dumps({
+    "implementation_name": sys.implementation.name,
+    "implementation_version": format_full_version(sys.implementation.version),
+    "os_name": os.name,
+    "platform_machine": platform.machine(),
+    "platform_python_implementation": platform.python_implementation(),
+    "platform_release": platform.release(),
+    "platform_system": platform.system(),
+    "platform_version": platform.version(),
+    "python_full_version": platform.python_version(),
+    "python_version": ".".join(platform.python_version_tuple()[:2]),
+    "sys_platform": sys.platform,
+}))
+"#;
+
+/// The values of the marker variables of an interpreter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MarkerEnvironment(HashMap<String, String>);
+
+impl MarkerEnvironment {
+    /// Queries the marker variables from the interpreter, which runs with the extra variables
+    /// in `env`
+    pub(crate) fn from_interpreter(
+        python: &Path,
+        env: &BTreeMap<String, String>,
+    ) -> Result<MarkerEnvironment> {
+        let output = Command::new(python)
+            .arg("-c")
+            .arg(MARKER_ENVIRONMENT_SCRIPT)
+            .envs(env)
+            .output()
+            .context(format!("Failed to run {:?}", python))?;
+        if !output.status.success() {
+            bail!(
+                "Failed to determine the environment markers of {:?}: {}",
+                python,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let variables = serde_json::from_slice(&output.stdout)
+            .context("Failed to parse the environment markers")?;
+        Ok(MarkerEnvironment(variables))
+    }
+
+    /// Evaluates a marker such as `python_version >= "3.8" and sys_platform != "win32"`, with
+    /// the `extra` variable set to `extra`, which is empty except when installing the
+    /// requirements of an extra. Extra names are compared normalized as specified in PEP 685.
+    pub(crate) fn evaluate(&self, marker: &str, extra: &str) -> Result<bool> {
+        let tokens = tokenize(marker)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+            environment: self,
+            extra,
+        };
+        let result = parser.or_expression()?;
+        if parser.position != tokens.len() {
+            bail!(
+                "Unexpected {:?} in the marker `{}`",
+                tokens[parser.position],
+                marker
+            );
+        }
+        Ok(result)
+    }
+
+    /// Returns the value of a marker variable other than `extra`
+    fn variable(&self, name: &str) -> Result<&str> {
+        // `python_implementation` is a deprecated alias
+        let name = if name == "python_implementation" {
+            "platform_python_implementation"
+        } else {
+            name
+        };
+        self.0
+            .get(name)
+            .map(String::as_str)
+            .ok_or_else(|| format_err!("Unknown marker variable {}", name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    OpenParen,
+    CloseParen,
+    And,
+    Or,
+    Operator(&'static str),
+    String(String),
+    Variable(String),
+}
+
+/// The comparison operators, longer ones first so they're matched before their prefixes
+const OPERATORS: &[&str] = &["===", "==", "!=", "<=", ">=", "~=", "<", ">"];
+
+fn tokenize(marker: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = marker.trim_start();
+    while let Some(next) = rest.chars().next() {
+        if next == '(' || next == ')' {
+            tokens.push(if next == '(' {
+                Token::OpenParen
+            } else {
+                Token::CloseParen
+            });
+            rest = &rest[1..];
+        } else if next == '\'' || next == '"' {
+            let end = rest[1..]
+                .find(next)
+                .with_context(|| format!("Unterminated string in the marker `{}`", marker))?;
+            tokens.push(Token::String(rest[1..=end].to_string()));
+            rest = &rest[end + 2..];
+        } else if let Some(operator) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Operator(operator));
+            rest = &rest[operator.len()..];
+        } else if next.is_ascii_alphabetic() || next == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            rest = &rest[end..];
+            match word {
+                "and" => tokens.push(Token::And),
+                "or" => tokens.push(Token::Or),
+                "in" => tokens.push(Token::Operator("in")),
+                "not" => {
+                    let after = rest.trim_start();
+                    if after.starts_with("in") && !after[2..].starts_with(char::is_alphanumeric) {
+                        tokens.push(Token::Operator("not in"));
+                        rest = &after[2..];
+                    } else {
+                        bail!("Expected `in` after `not` in the marker `{}`", marker);
+                    }
+                }
+                _ => tokens.push(Token::Variable(word.to_string())),
+            }
+        } else {
+            bail!("Unexpected character {:?} in the marker `{}`", next, marker);
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    environment: &'a MarkerEnvironment,
+    extra: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn or_expression(&mut self) -> Result<bool> {
+        let mut result = self.and_expression()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            // Evaluate both sides to report errors on either
+            result = self.and_expression()? || result;
+        }
+        Ok(result)
+    }
+
+    fn and_expression(&mut self) -> Result<bool> {
+        let mut result = self.atom()?;
+        while self.peek() == Some(&Token::And) {
+            self.position += 1;
+            result = self.atom()? && result;
+        }
+        Ok(result)
+    }
+
+    fn atom(&mut self) -> Result<bool> {
+        if self.peek() == Some(&Token::OpenParen) {
+            self.position += 1;
+            let result = self.or_expression()?;
+            match self.next() {
+                Some(Token::CloseParen) => return Ok(result),
+                other => bail!("Expected `)`, found {:?}", other),
+            }
+        }
+        let lhs_is_extra = self.peek_extra();
+        let lhs = self.value()?;
+        let operator = match self.next() {
+            Some(Token::Operator(operator)) => *operator,
+            other => bail!("Expected a comparison operator, found {:?}", other),
+        };
+        let rhs_is_extra = self.peek_extra();
+        let rhs = self.value()?;
+        if lhs_is_extra || rhs_is_extra {
+            return compare(&normalize_extra(lhs), operator, &normalize_extra(rhs));
+        }
+        compare(lhs, operator, rhs)
+    }
+
+    fn peek_extra(&self) -> bool {
+        matches!(self.peek(), Some(Token::Variable(name)) if name == "extra")
+    }
+
+    fn value(&mut self) -> Result<&'a str> {
+        match self.next() {
+            Some(Token::String(value)) => Ok(value),
+            Some(Token::Variable(name)) if name == "extra" => Ok(self.extra),
+            Some(Token::Variable(name)) => self.environment.variable(name),
+            other => bail!("Expected a string or a marker variable, found {:?}", other),
+        }
+    }
+}
+
+/// Normalizes an extra name as specified in PEP 685
+pub(crate) fn normalize_extra(extra: &str) -> String {
+    extra
+        .to_lowercase()
+        .split(['-', '_', '.'])
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Whether the marker refers to the `extra` variable
+pub(crate) fn references_extra(marker: &str) -> bool {
+    tokenize(marker)
+        .map(|tokens| {
+            tokens
+                .iter()
+                .any(|token| matches!(token, Token::Variable(name) if name == "extra"))
+        })
+        .unwrap_or(false)
+}
+
+/// Whether the version matches all of the comma separated [PEP 440 version
+/// specifiers](https://peps.python.org/pep-0440/#version-specifiers), e.g. `>=1.5,<2`
+pub(crate) fn matches_specifiers(version: &str, specifiers: &str) -> Result<bool> {
+    for specifier in specifiers.split(',').map(str::trim) {
+        let operator = ["===", "~=", "==", "!=", "<=", ">=", "<", ">"]
+            .into_iter()
+            .find(|operator| specifier.starts_with(operator))
+            .with_context(|| format!("Invalid version specifier `{}`", specifier))?;
+        let rhs = specifier[operator.len()..].trim();
+        if Version::parse(rhs.strip_suffix(".*").unwrap_or(rhs)).is_none() {
+            bail!("Invalid version `{}` in `{}`", rhs, specifiers);
+        }
+        if !compare(version, operator, rhs)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Compares as PEP 440 versions if both sides are versions, and as strings otherwise
+fn compare(lhs: &str, operator: &str, rhs: &str) -> Result<bool> {
+    match operator {
+        "in" => return Ok(rhs.contains(lhs)),
+        "not in" => return Ok(!rhs.contains(lhs)),
+        "===" => return Ok(lhs == rhs),
+        _ => {}
+    }
+    if let Some(prefix) = rhs.strip_suffix(".*") {
+        if let (Some(lhs), Some(prefix)) = (Version::parse(lhs), Version::parse(prefix)) {
+            let matches = lhs.epoch == prefix.epoch
+                && prefix
+                    .release
+                    .iter()
+                    .enumerate()
+                    .all(|(i, part)| lhs.release.get(i).copied().unwrap_or(0) == *part);
+            match operator {
+                "==" => return Ok(matches),
+                "!=" => return Ok(!matches),
+                _ => {}
+            }
+        }
+    }
+    let ordering = match (Version::parse(lhs), Version::parse(rhs)) {
+        (Some(lhs_version), Some(rhs_version)) => {
+            if operator == "~=" {
+                // `~= 3.8` means `>= 3.8, == 3.*`
+                if rhs_version.release.len() < 2 {
+                    bail!("The compatible release operator requires at least two release segments, found `{}`", rhs);
+                }
+                let prefix = &rhs_version.release[..rhs_version.release.len() - 1];
+                let matches_prefix = prefix
+                    .iter()
+                    .enumerate()
+                    .all(|(i, part)| lhs_version.release.get(i).copied().unwrap_or(0) == *part);
+                return Ok(matches_prefix && lhs_version >= rhs_version);
+            }
+            let same_release = lhs_version.epoch == rhs_version.epoch
+                && (0..lhs_version.release.len().max(rhs_version.release.len())).all(|i| {
+                    lhs_version.release.get(i).copied().unwrap_or(0)
+                        == rhs_version.release.get(i).copied().unwrap_or(0)
+                });
+            let is_pre_release = |version: &Version| version.pre.is_some() || version.dev.is_some();
+            // `<V` doesn't match the pre-releases of V, and `>V` neither its post-releases nor
+            // local versions, unless V is one itself
+            match operator {
+                "<" if same_release
+                    && is_pre_release(&lhs_version)
+                    && !is_pre_release(&rhs_version) =>
+                {
+                    return Ok(false);
+                }
+                ">" if same_release
+                    && ((lhs_version.post.is_some() && rhs_version.post.is_none())
+                        || (!lhs_version.local.is_empty()
+                            && (&lhs_version.pre, lhs_version.post, lhs_version.dev)
+                                == (&rhs_version.pre, rhs_version.post, rhs_version.dev))) =>
+                {
+                    return Ok(false);
+                }
+                _ => {}
+            }
+            lhs_version.cmp(&rhs_version)
+        }
+        _ => {
+            if operator == "~=" {
+                bail!(
+                    "Can't use `~=` with `{}` and `{}`, which aren't versions",
+                    lhs,
+                    rhs
+                );
+            }
+            lhs.cmp(rhs)
+        }
+    };
+    Ok(match operator {
+        "==" => ordering == Ordering::Equal,
+        "!=" => ordering != Ordering::Equal,
+        "<" => ordering == Ordering::Less,
+        "<=" => ordering != Ordering::Greater,
+        ">" => ordering == Ordering::Greater,
+        ">=" => ordering != Ordering::Less,
+        _ => unreachable!("Unknown operator {}", operator),
+    })
+}
+
+#[cfg(test)]
+impl MarkerEnvironment {
+    /// The environment of CPython 3.10 on x86_64 linux
+    pub(crate) fn linux_cpython_310() -> MarkerEnvironment {
+        MarkerEnvironment(
+            [
+                ("implementation_name", "cpython"),
+                ("os_name", "posix"),
+                ("platform_machine", "x86_64"),
+                ("platform_python_implementation", "CPython"),
+                ("platform_system", "Linux"),
+                ("python_full_version", "3.10.4"),
+                ("python_version", "3.10"),
+                ("sys_platform", "linux"),
+            ]
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matches_specifiers() {
+        assert!(matches_specifiers("1.5.1", ">=1.5,<2").unwrap());
+        assert!(matches_specifiers("1.5.1", ">= 1.5, < 2").unwrap());
+        assert!(!matches_specifiers("2.0.0", ">=1.5,<2").unwrap());
+        assert!(!matches_specifiers("1.4.0", ">=1.5,<2").unwrap());
+        assert!(matches_specifiers("1.5.1", "==1.5.*").unwrap());
+        assert!(matches_specifiers("1.5.1", "~=1.5").unwrap());
+        assert!(!matches_specifiers("1.5.1", "~=1.4.0").unwrap());
+        // `<V` excludes the pre-releases of V, unless V is one itself
+        assert!(!matches_specifiers("0.14.0-beta.6", ">=0.13,<0.14").unwrap());
+        assert!(!matches_specifiers("0.14.0.dev1", "<0.14").unwrap());
+        assert!(matches_specifiers("0.14.0b1", "<0.14.0rc1").unwrap());
+        assert!(matches_specifiers("0.13.1b1", "<0.14").unwrap());
+        assert!(matches_specifiers("0.14.0-beta.6", "<=0.14").unwrap());
+        // `>V` excludes the post-releases and local versions of V
+        assert!(!matches_specifiers("1.5.post1", ">1.5").unwrap());
+        assert!(!matches_specifiers("1.5+local", ">1.5").unwrap());
+        assert!(matches_specifiers("1.5.post2", ">1.5.post1").unwrap());
+        assert!(matches_specifiers("1.5.post1", ">=1.5").unwrap());
+        assert!(matches_specifiers("1.5.1", "1.5").is_err());
+        assert!(matches_specifiers("1.5.1", ">=one").is_err());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let env = MarkerEnvironment::linux_cpython_310();
+        for (marker, expected) in [
+            ("python_version < '3.11'", true),
+            ("python_version >= \"3.8\"", true),
+            // Compared as versions, not as strings
+            ("python_version > '3.9'", true),
+            ("python_full_version == '3.10.*'", true),
+            ("python_version ~= '3.8'", true),
+            ("python_full_version ~= '3.9.0'", false),
+            ("sys_platform == 'win32'", false),
+            (
+                "sys_platform != 'win32' and platform_machine == 'x86_64'",
+                true,
+            ),
+            (
+                "os_name == 'nt' or (sys_platform == 'linux' and python_version < '3.9')",
+                false,
+            ),
+            ("'linux' in sys_platform", true),
+            ("platform_machine not in 'arm64 aarch64'", true),
+            ("python_implementation == 'PyPy'", false),
+        ] {
+            assert_eq!(env.evaluate(marker, "").unwrap(), expected, "{}", marker);
+        }
+        assert!(env.evaluate("python_version <", "").is_err());
+        assert!(env.evaluate("unknown_variable == '1'", "").is_err());
+        assert!(env.evaluate("(python_version == '3.10'", "").is_err());
+    }
+
+    #[test]
+    fn test_evaluate_extra() {
+        let env = MarkerEnvironment::linux_cpython_310();
+        let marker = "extra == 'a' or extra == 'Docs_Build'";
+        assert!(!env.evaluate(marker, "").unwrap());
+        assert!(env.evaluate(marker, "a").unwrap());
+        assert!(env.evaluate(marker, "docs-build").unwrap());
+        assert!(!env.evaluate(marker, "b").unwrap());
+        assert!(!env
+            .evaluate("extra == 'a' and python_version >= '3.11'", "a")
+            .unwrap());
+        assert!(references_extra("python_version < '3.8' and extra == 'a'"));
+        assert!(!references_extra("sys_platform == 'extra'"));
+    }
+}
@@ -1,4 +1,6 @@
-use crate::{CargoToml, PyProjectToml};
+use crate::globs::{check_negations, PathGlobs};
+use crate::pyproject_toml::ProjectUrls;
+use crate::{CargoToml, ConfigError, PyProjectToml};
 use anyhow::{bail, Context, Result};
 use fs_err as fs;
 use regex::Regex;
@@ -21,8 +23,10 @@ pub struct WheelMetadata {
     pub module_name: String,
 }
 
-/// Python Package Metadata 2.1 as specified in
+/// Python Package Metadata 2.2 as specified in
 /// https://packaging.python.org/specifications/core-metadata/
+///
+/// Metadata 2.4 is emitted instead when there are `License-File` entries
 #[derive(Serialize, Deserialize, Debug, Default, Clone, Eq, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 #[allow(missing_docs)]
@@ -58,10 +62,13 @@ pub struct Metadata21 {
     pub scripts: HashMap<String, String>,
     pub gui_scripts: HashMap<String, String>,
     pub entry_points: HashMap<String, HashMap<String, String>>,
+    // https://peps.python.org/pep-0643/, only written to the PKG-INFO of source distributions
+    pub dynamic: Vec<String>,
 }
 
-const PLAINTEXT_CONTENT_TYPE: &str = "text/plain; charset=UTF-8";
-const GFM_CONTENT_TYPE: &str = "text/markdown; charset=UTF-8; variant=GFM";
+pub(crate) const PLAINTEXT_CONTENT_TYPE: &str = "text/plain; charset=UTF-8";
+pub(crate) const GFM_CONTENT_TYPE: &str = "text/markdown; charset=UTF-8; variant=GFM";
+pub(crate) const RST_CONTENT_TYPE: &str = "text/x-rst; charset=UTF-8";
 
 /// Guess a Description-Content-Type based on the file extension,
 /// defaulting to plaintext if extension is unknown or empty.
@@ -72,7 +79,7 @@ fn path_to_content_type(path: &Path) -> String {
         .map_or(String::from(PLAINTEXT_CONTENT_TYPE), |ext| {
             let ext = ext.to_string_lossy().to_lowercase();
             let type_str = match ext.as_str() {
-                "rst" => "text/x-rst; charset=UTF-8",
+                "rst" => RST_CONTENT_TYPE,
                 "md" => GFM_CONTENT_TYPE,
                 "markdown" => GFM_CONTENT_TYPE,
                 _ => PLAINTEXT_CONTENT_TYPE,
@@ -118,7 +125,7 @@ impl Metadata21 {
                     content_type,
                 }) => {
                     if file.is_some() && text.is_some() {
-                        bail!("file and text fields of 'project.readme' are mutually-exclusive, only one of them should be specified");
+                        bail!(ConfigError::new("file and text fields of 'project.readme' are mutually-exclusive, only one of them should be specified"));
                     }
                     if let Some(readme_path) = file {
                         let readme_path = pyproject_dir.join(readme_path);
@@ -131,7 +138,12 @@ impl Metadata21 {
                     if let Some(description) = text {
                         self.description = Some(description.clone());
                     }
-                    self.description_content_type = content_type.clone();
+                    // Guess the content type from the file extension if it's not given
+                    self.description_content_type = content_type.clone().or_else(|| {
+                        file.as_ref().map(|readme_path| {
+                            path_to_content_type(&pyproject_dir.join(readme_path))
+                        })
+                    });
                 }
                 None => {}
             }
@@ -142,7 +154,7 @@ impl Metadata21 {
 
             if let Some(pyproject_toml::License { file, text }) = &project.license {
                 if file.is_some() && text.is_some() {
-                    bail!("file and text fields of 'project.license' are mutually-exclusive, only one of them should be specified");
+                    bail!(ConfigError::new("file and text fields of 'project.license' are mutually-exclusive, only one of them should be specified"));
                 }
                 if let Some(license_path) = file {
                     let license_path = pyproject_dir.join(license_path);
@@ -158,70 +170,18 @@ impl Metadata21 {
             // We're already emitting the License-Files metadata without issue.
             // license-files.globs = ["LICEN[CS]E*", "COPYING*", "NOTICE*", "AUTHORS*"]
             let license_include_targets = ["LICEN[CS]E*", "COPYING*", "NOTICE*", "AUTHORS*"];
-            let escaped_manifest_string = glob::Pattern::escape(pyproject_dir.to_str().unwrap());
-            let escaped_manifest_path = Path::new(&escaped_manifest_string);
-            for pattern in license_include_targets.iter() {
-                for license_path in
-                    glob::glob(&escaped_manifest_path.join(pattern).to_string_lossy())?
-                        .filter_map(Result::ok)
-                {
-                    // if the pyproject.toml specified the license file,
-                    // then we won't list it as automatically included
-                    if !self.license_files.contains(&license_path) {
-                        println!("📦 Including license file \"{}\"", license_path.display());
-                        self.license_files.push(license_path);
-                    }
-                }
-            }
+            self.add_license_files(pyproject_dir, &license_include_targets)?;
 
             if let Some(authors) = &project.authors {
-                let mut names = Vec::with_capacity(authors.len());
-                let mut emails = Vec::with_capacity(authors.len());
-                for author in authors {
-                    match (&author.name, &author.email) {
-                        (Some(name), Some(email)) => {
-                            emails.push(format!("{} <{}>", name, email));
-                        }
-                        (Some(name), None) => {
-                            names.push(name.as_str());
-                        }
-                        (None, Some(email)) => {
-                            emails.push(email.clone());
-                        }
-                        (None, None) => {}
-                    }
-                }
-                if !names.is_empty() {
-                    self.author = Some(names.join(", "));
-                }
-                if !emails.is_empty() {
-                    self.author_email = Some(emails.join(", "));
-                }
+                let (names, emails) = split_contacts(authors);
+                self.author = names.or_else(|| self.author.take());
+                self.author_email = emails.or_else(|| self.author_email.take());
             }
 
             if let Some(maintainers) = &project.maintainers {
-                let mut names = Vec::with_capacity(maintainers.len());
-                let mut emails = Vec::with_capacity(maintainers.len());
-                for maintainer in maintainers {
-                    match (&maintainer.name, &maintainer.email) {
-                        (Some(name), Some(email)) => {
-                            emails.push(format!("{} <{}>", name, email));
-                        }
-                        (Some(name), None) => {
-                            names.push(name.as_str());
-                        }
-                        (None, Some(email)) => {
-                            emails.push(email.clone());
-                        }
-                        (None, None) => {}
-                    }
-                }
-                if !names.is_empty() {
-                    self.maintainer = Some(names.join(", "));
-                }
-                if !emails.is_empty() {
-                    self.maintainer_email = Some(emails.join(", "));
-                }
+                let (names, emails) = split_contacts(maintainers);
+                self.maintainer = names.or_else(|| self.maintainer.take());
+                self.maintainer_email = emails.or_else(|| self.maintainer_email.take());
             }
 
             if let Some(keywords) = &project.keywords {
@@ -233,7 +193,10 @@ impl Metadata21 {
             }
 
             if let Some(urls) = &project.urls {
-                self.project_url = urls.clone();
+                match pyproject_toml.project_urls() {
+                    ProjectUrls::Replace => self.project_url = urls.clone(),
+                    ProjectUrls::Merge => self.project_url.extend(urls.clone()),
+                }
             }
 
             if let Some(dependencies) = &project.dependencies {
@@ -266,17 +229,87 @@ impl Metadata21 {
             if let Some(entry_points) = &project.entry_points {
                 // Raise error on ambiguous entry points: https://www.python.org/dev/peps/pep-0621/#entry-points
                 if entry_points.contains_key("console_scripts") {
-                    bail!("console_scripts is not allowed in project.entry-points table");
+                    bail!(ConfigError::new(
+                        "console_scripts is not allowed in project.entry-points table"
+                    ));
                 }
                 if entry_points.contains_key("gui_scripts") {
-                    bail!("gui_scripts is not allowed in project.entry-points table");
+                    bail!(ConfigError::new(
+                        "gui_scripts is not allowed in project.entry-points table"
+                    ));
                 }
                 self.entry_points = entry_points.clone();
             }
         }
+
+        // Additional license files from `[tool.maturin.license-files]`, the patterns are relative
+        // to the pyproject.toml directory and may point outside of it, e.g. to the workspace root
+        if let Some(patterns) = pyproject_toml.license_files() {
+            check_negations(patterns)?;
+            for pattern in self.add_license_files(pyproject_dir, patterns)? {
+                eprintln!(
+                    "⚠️  Warning: license-files pattern \"{}\" did not match any files",
+                    pattern
+                );
+            }
+        }
         Ok(())
     }
 
+    /// Adds all files matching the glob `patterns` relative to `base_dir` as license files,
+    /// skipping directories and files that have already been added
+    ///
+    /// Returns the patterns that didn't match any files
+    fn add_license_files(
+        &mut self,
+        base_dir: &Path,
+        patterns: &[impl AsRef<str>],
+    ) -> Result<Vec<String>> {
+        let mut unmatched = Vec::new();
+        for (pattern, matches) in PathGlobs::new(base_dir, patterns)?.matches()? {
+            let mut matched = 0;
+            for license_path in matches.into_iter().filter(|path| path.is_file()) {
+                matched += 1;
+                // if the pyproject.toml specified the license file,
+                // then we won't list it as automatically included
+                if !self.license_files.contains(&license_path) {
+                    eprintln!("📦 Including license file \"{}\"", license_path.display());
+                    self.license_files.push(license_path);
+                }
+            }
+            if matched == 0 {
+                unmatched.push(pattern.to_string());
+            }
+        }
+        Ok(unmatched)
+    }
+
+    /// Returns the file names of the license files inside the `licenses` directory of the
+    /// .dist-info directory, in the same order as [Metadata21::license_files].
+    ///
+    /// License files are flattened to their file name, files with the same name
+    /// (e.g. a `LICENSE` in both the crate and the workspace root) get a numeric suffix
+    pub fn license_file_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::with_capacity(self.license_files.len());
+        for path in &self.license_files {
+            let file_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| "LICENSE".to_string());
+            let mut name = file_name.clone();
+            let mut counter = 1;
+            while names.contains(&name) {
+                name = match file_name.rsplit_once('.') {
+                    Some((stem, extension)) => format!("{}-{}.{}", stem, counter, extension),
+                    None => format!("{}-{}", file_name, counter),
+                };
+                counter += 1;
+            }
+            names.push(name);
+        }
+        names
+    }
+
     /// Uses a Cargo.toml to create the metadata for python packages
     ///
     /// manifest_path must be the directory, not the file
@@ -343,6 +376,9 @@ impl Metadata21 {
         if let Some(repository) = package.repository.as_ref() {
             project_url.insert("Source Code".to_string(), repository.clone());
         }
+        if let Some(documentation) = package.documentation.as_ref() {
+            project_url.insert("Documentation".to_string(), documentation.clone());
+        }
         let license_files = if let Some(license_file) = package.license_file.as_ref() {
             vec![manifest_path.as_ref().join(license_file)]
         } else {
@@ -350,7 +386,7 @@ impl Metadata21 {
         };
 
         let metadata = Metadata21 {
-            metadata_version: "2.1".to_owned(),
+            metadata_version: "2.2".to_owned(),
 
             // Mapped from cargo metadata
             name,
@@ -384,6 +420,12 @@ impl Metadata21 {
     /// become multiple single-valued key-value pairs. This format is needed for the pypi
     /// uploader and for the METADATA file inside wheels
     pub fn to_vec(&self) -> Vec<(String, String)> {
+        self.fields(false)
+    }
+
+    /// Like [Metadata21::to_vec], but also contains the `Dynamic` fields of PKG-INFO.
+    fn fields(&self, with_dynamic: bool) -> Vec<(String, String)> {
+        let license_file_names = self.license_file_names();
         let mut fields = vec![
             ("Metadata-Version", self.metadata_version.clone()),
             ("Name", self.name.clone()),
@@ -405,12 +447,10 @@ impl Metadata21 {
         add_vec("Requires-External", &self.requires_external);
         add_vec("Provides-Extra", &self.provides_extra);
 
-        let license_files: Vec<String> = self
-            .license_files
-            .iter()
-            .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
-            .collect();
-        add_vec("License-File", &license_files);
+        add_vec("License-File", &license_file_names);
+        if with_dynamic {
+            add_vec("Dynamic", &self.dynamic);
+        }
 
         let mut add_option = |name, value: &Option<String>| {
             if let Some(some) = value.clone() {
@@ -432,7 +472,10 @@ impl Metadata21 {
         // Project-URL is special
         // "A string containing a browsable URL for the project and a label for it, separated by a comma."
         // `Project-URL: Bug Tracker, http://bitbucket.org/tarek/distribute/issues/`
-        for (key, value) in self.project_url.iter() {
+        // Sorted, since the order of a `HashMap` is random and the metadata should be reproducible
+        let mut project_url: Vec<_> = self.project_url.iter().collect();
+        project_url.sort();
+        for (key, value) in project_url {
             fields.push(("Project-URL", format!("{}, {}", key, value)))
         }
 
@@ -443,6 +486,17 @@ impl Metadata21 {
             fields.push(("Description", description.clone()));
         }
 
+        // License-Expression and License-File were introduced with metadata 2.4
+        let has_license_fields = fields
+            .iter()
+            .any(|(key, _)| *key == "License-Expression" || *key == "License-File");
+        if has_license_fields
+            && parse_metadata_version(&self.metadata_version)
+                .map_or(true, |version| version < (2, 4))
+        {
+            fields[0].1 = "2.4".to_string();
+        }
+
         fields
             .into_iter()
             .map(|(k, v)| (k.to_string(), v))
@@ -451,7 +505,16 @@ impl Metadata21 {
 
     /// Writes the format for the metadata file inside wheels
     pub fn to_file_contents(&self) -> Result<String> {
-        let mut fields = self.to_vec();
+        Self::format_fields(self.fields(false))
+    }
+
+    /// Writes the format for the PKG-INFO file inside source distributions, which also lists
+    /// the fields that may change when building a wheel from the source distribution
+    pub fn to_sdist_file_contents(&self) -> Result<String> {
+        Self::format_fields(self.fields(true))
+    }
+
+    fn format_fields(mut fields: Vec<(String, String)>) -> Result<String> {
         let mut out = "".to_string();
         let body = match fields.last() {
             Some((key, description)) if key == "Description" => {
@@ -534,6 +597,53 @@ fn fold_header(text: &str) -> String {
     result
 }
 
+/// Splits `project.authors` or `project.maintainers` as described in the core metadata
+/// specification: Entries with only a name go into `Author`/`Maintainer`, all others into
+/// `Author-email`/`Maintainer-email` as a comma separated RFC 5322 address list
+fn split_contacts(contacts: &[pyproject_toml::Contact]) -> (Option<String>, Option<String>) {
+    let mut names = Vec::new();
+    let mut emails = Vec::new();
+    for contact in contacts {
+        let name = contact.name.as_deref().map(str::trim).unwrap_or_default();
+        let email = contact.email.as_deref().map(str::trim).unwrap_or_default();
+        match (name.is_empty(), email.is_empty()) {
+            (false, false) => emails.push(format_mailbox(name, email)),
+            (false, true) => names.push(name.to_string()),
+            (true, false) => emails.push(email.to_string()),
+            (true, true) => {}
+        }
+    }
+    let join = |values: Vec<String>| {
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    };
+    (join(names), join(emails))
+}
+
+/// Formats `name <email>`, quoting the name if it contains characters that would otherwise
+/// change the meaning of the address list, such as the comma in `"Doe, Jane" <jane@example.org>`
+fn format_mailbox(name: &str, email: &str) -> String {
+    // The `specials` of RFC 5322 section 3.2.3
+    const SPECIALS: &[char] = &[
+        '(', ')', '<', '>', '[', ']', ':', ';', '@', '\\', ',', '.', '"',
+    ];
+    if name.contains(SPECIALS) {
+        let escaped = name.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("\"{}\" <{}>", escaped, email)
+    } else {
+        format!("{} <{}>", name, email)
+    }
+}
+
+/// Parses a `major.minor` core metadata version, so that e.g. 2.10 is newer than 2.4
+fn parse_metadata_version(version: &str) -> Option<(u32, u32)> {
+    let (major, minor) = version.split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -629,7 +739,7 @@ mod test {
 
         let expected = indoc!(
             r#"
-            Metadata-Version: 2.1
+            Metadata-Version: 2.2
             Name: info-project
             Version: 0.1.0
             Summary: A test project
@@ -678,7 +788,7 @@ mod test {
 
         let expected = indoc!(
             r#"
-            Metadata-Version: 2.1
+            Metadata-Version: 2.2
             Name: info
             Version: 0.1.0
             Summary: A test project
@@ -774,6 +884,38 @@ mod test {
         assert!(pkginfo.is_ok());
     }
 
+    #[test]
+    fn test_metadata_version_and_dynamic() {
+        let mut metadata = Metadata21 {
+            metadata_version: "2.2".to_string(),
+            name: "foo".to_string(),
+            version: "0.1.0".to_string(),
+            summary: Some("computed".to_string()),
+            dynamic: vec!["Summary".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            metadata.to_file_contents().unwrap(),
+            "Metadata-Version: 2.2\nName: foo\nVersion: 0.1.0\nSummary: computed\n"
+        );
+        assert_eq!(
+            metadata.to_sdist_file_contents().unwrap(),
+            "Metadata-Version: 2.2\nName: foo\nVersion: 0.1.0\nDynamic: Summary\nSummary: computed\n"
+        );
+
+        metadata.license_files.push(PathBuf::from("LICENSE"));
+        assert!(metadata
+            .to_file_contents()
+            .unwrap()
+            .starts_with("Metadata-Version: 2.4\n"));
+        // Newer versions are compared numerically
+        metadata.metadata_version = "2.10".to_string();
+        assert!(metadata
+            .to_file_contents()
+            .unwrap()
+            .starts_with("Metadata-Version: 2.10\n"));
+    }
+
     #[test]
     fn test_merge_metadata_from_pyproject_toml_with_customized_python_source_dir() {
         let manifest_dir = PathBuf::from("test-crates").join("pyo3-mixed-py-subdir");
@@ -846,4 +988,211 @@ mod test {
         assert_eq!(metadata.license_files[2], manifest_dir.join("NOTICE.md"));
         assert_eq!(metadata.license_files[3], manifest_dir.join("AUTHORS.txt"));
     }
+
+    #[test]
+    fn test_license_files_glob_and_name_collision() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let workspace_dir = tmp_dir.path();
+        let project_dir = workspace_dir.join("crate");
+        fs::create_dir_all(project_dir.join("licenses").join("vendor")).unwrap();
+        fs::write(workspace_dir.join("LICENSE"), "workspace license").unwrap();
+        fs::write(project_dir.join("LICENSE"), "crate license").unwrap();
+        fs::write(project_dir.join("licenses").join("MIT.txt"), "MIT").unwrap();
+        fs::write(
+            project_dir.join("licenses").join("vendor").join("MIT.txt"),
+            "MIT",
+        )
+        .unwrap();
+        fs::write(
+            project_dir.join("licenses").join("LICENSE.APACHE.txt"),
+            "Apache",
+        )
+        .unwrap();
+        fs::write(
+            project_dir
+                .join("licenses")
+                .join("vendor")
+                .join("LICENSE.APACHE.txt"),
+            "Apache",
+        )
+        .unwrap();
+        fs::write(
+            project_dir.join("pyproject.toml"),
+            indoc!(
+                r#"
+                [build-system]
+                requires = ["maturin"]
+                build-backend = "maturin"
+
+                [project]
+                name = "license-glob"
+
+                [tool.maturin]
+                license-files = ["../LICENSE*", "licenses/**/*"]
+                "#
+            ),
+        )
+        .unwrap();
+        let pyproject_toml = PyProjectToml::new(project_dir.join("pyproject.toml")).unwrap();
+        let mut metadata = Metadata21 {
+            name: "license-glob".to_string(),
+            version: "0.1.0".to_string(),
+            ..Default::default()
+        };
+        metadata
+            .merge_pyproject_toml(&project_dir, &pyproject_toml)
+            .unwrap();
+
+        assert_eq!(
+            metadata.license_files,
+            vec![
+                project_dir.join("LICENSE"),
+                project_dir.join("../LICENSE"),
+                project_dir.join("licenses").join("LICENSE.APACHE.txt"),
+                project_dir.join("licenses").join("MIT.txt"),
+                project_dir
+                    .join("licenses")
+                    .join("vendor")
+                    .join("LICENSE.APACHE.txt"),
+                project_dir.join("licenses").join("vendor").join("MIT.txt"),
+            ]
+        );
+        assert_eq!(
+            metadata.license_file_names(),
+            vec![
+                "LICENSE",
+                "LICENSE-1",
+                "LICENSE.APACHE.txt",
+                "MIT.txt",
+                "LICENSE.APACHE-1.txt",
+                "MIT-1.txt"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_metadata_from_workspace_package() {
+        let manifest_dir = PathBuf::from("test-crates")
+            .join("workspace-inheritance")
+            .join("python");
+        let cargo_toml = CargoToml::from_path(manifest_dir.join("Cargo.toml")).unwrap();
+        let cargo_metadata = MetadataCommand::new()
+            .manifest_path(manifest_dir.join("Cargo.toml"))
+            .exec()
+            .unwrap();
+        let metadata =
+            Metadata21::from_cargo_toml(&cargo_toml, &manifest_dir, &cargo_metadata).unwrap();
+        assert_eq!(metadata.version, "0.1.0");
+        assert_eq!(
+            metadata.author.as_deref(),
+            Some("konstin <konstin@mailbox.org>")
+        );
+        assert_eq!(metadata.license.as_deref(), Some("MIT"));
+        assert_eq!(metadata.keywords.as_deref(), Some("python,workspace"));
+        assert_eq!(
+            metadata.project_url.get("Source Code").map(String::as_str),
+            Some("https://github.com/PyO3/maturin")
+        );
+        assert_eq!(
+            metadata.description,
+            Some(fs_err::read_to_string("test-crates/workspace-inheritance/README.md").unwrap())
+        );
+        assert_eq!(metadata.license_file_names(), vec!["LICENSE"]);
+    }
+
+    #[test]
+    fn test_merge_project_urls() {
+        let cargo_metadata = Metadata21 {
+            project_url: HashMap::from([
+                (
+                    "Source Code".to_string(),
+                    "https://github.com/a/b".to_string(),
+                ),
+                ("Documentation".to_string(), "https://docs.rs/b".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let pyproject_toml = |mode: &str| -> PyProjectToml {
+            toml_edit::easy::from_str(&format!(
+                "[build-system]\n\
+                requires = [\"maturin\"]\n\
+                [project]\n\
+                name = \"b\"\n\
+                urls = {{ Documentation = \"https://b.readthedocs.io\", Changelog = \"https://b.org/changes\" }}\n\
+                [tool.maturin]\n\
+                project-urls = \"{}\"\n",
+                mode
+            ))
+            .unwrap()
+        };
+
+        let mut metadata = cargo_metadata.clone();
+        metadata
+            .merge_pyproject_toml(".", &pyproject_toml("replace"))
+            .unwrap();
+        let mut urls: Vec<_> = metadata.project_url.keys().map(String::as_str).collect();
+        urls.sort_unstable();
+        assert_eq!(urls, ["Changelog", "Documentation"]);
+
+        let mut metadata = cargo_metadata;
+        metadata
+            .merge_pyproject_toml(".", &pyproject_toml("merge"))
+            .unwrap();
+        let mut urls: Vec<_> = metadata.project_url.keys().map(String::as_str).collect();
+        urls.sort_unstable();
+        assert_eq!(urls, ["Changelog", "Documentation", "Source Code"]);
+        assert_eq!(
+            metadata.project_url["Documentation"],
+            "https://b.readthedocs.io"
+        );
+        assert!(metadata
+            .to_file_contents()
+            .unwrap()
+            .contains("Project-URL: Changelog, https://b.org/changes\nProject-URL: Documentation, https://b.readthedocs.io\nProject-URL: Source Code, https://github.com/a/b\n"));
+    }
+
+    #[test]
+    fn test_merge_multiple_authors() {
+        let pyproject_toml: PyProjectToml = toml_edit::easy::from_str(indoc!(
+            r#"
+            [build-system]
+            requires = ["maturin"]
+
+            [project]
+            name = "authors"
+            authors = [
+                { name = "Jane Doe", email = "jane@example.org" },
+                { name = "Doe, John", email = "john@example.org" },
+                { name = "The \"Team\"", email = "team@example.org" },
+                { name = "Name Only" },
+                { name = "Another Name" },
+                { email = "email-only@example.org" },
+                { name = " " },
+            ]
+            maintainers = [{ name = "Maintainer" }]
+            "#
+        ))
+        .unwrap();
+        let mut metadata = Metadata21 {
+            author: Some("cargo author".to_string()),
+            author_email: Some("cargo author <cargo@example.org>".to_string()),
+            maintainer_email: Some("cargo@example.org".to_string()),
+            ..Default::default()
+        };
+        metadata.merge_pyproject_toml(".", &pyproject_toml).unwrap();
+        assert_eq!(metadata.author.as_deref(), Some("Name Only, Another Name"));
+        assert_eq!(
+            metadata.author_email.as_deref(),
+            Some(
+                "Jane Doe <jane@example.org>, \"Doe, John\" <john@example.org>, \
+                \"The \\\"Team\\\"\" <team@example.org>, email-only@example.org"
+            )
+        );
+        assert_eq!(metadata.maintainer.as_deref(), Some("Maintainer"));
+        // Kept from Cargo.toml
+        assert_eq!(
+            metadata.maintainer_email.as_deref(),
+            Some("cargo@example.org")
+        );
+    }
 }
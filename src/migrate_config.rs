@@ -0,0 +1,396 @@
+//! Options of `[tool.maturin]` that were renamed or moved. pyproject.toml files that still use
+//! the old key keep working with a warning until the version that removes it, and
+//! `maturin migrate-config` rewrites them.
+use crate::{ConfigError, TomlEditor};
+use anyhow::{bail, Result};
+use semver::Version;
+use std::fmt;
+use std::path::PathBuf;
+use toml_edit::{Array, Document, InlineTable, Item, Key, TableLike, Value};
+
+/// The tables below `[tool.maturin]` an option can appear in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    /// `[tool.maturin]` and its `[tool.maturin.'cfg(...)']` tables
+    Maturin,
+    /// `[tool.maturin.profiles.<name>]`
+    Profile,
+}
+
+/// How the value of the old key becomes the value of the new one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Conversion {
+    /// The value is moved as is
+    Rename,
+    /// The patterns are appended to `include` with `format = "sdist"`
+    SdistInclude,
+}
+
+/// A key that was replaced, in the order they were deprecated
+#[derive(Debug, Clone, Copy)]
+struct Deprecation {
+    old: &'static str,
+    new: &'static str,
+    scopes: &'static [Scope],
+    conversion: Conversion,
+    /// The version that deprecated the old key
+    since: &'static str,
+    /// The first version that rejects the old key, one major version after the deprecation
+    removed_in: &'static str,
+}
+
+const DEPRECATIONS: &[Deprecation] = &[
+    Deprecation {
+        old: "manylinux",
+        new: "compatibility",
+        scopes: &[Scope::Maturin, Scope::Profile],
+        conversion: Conversion::Rename,
+        since: "0.11.0",
+        removed_in: "0.15.0",
+    },
+    Deprecation {
+        old: "sdist-include",
+        new: "include",
+        scopes: &[Scope::Maturin],
+        conversion: Conversion::SdistInclude,
+        since: "0.14.0",
+        removed_in: "0.15.0",
+    },
+];
+
+/// A deprecated key that was replaced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    /// The table the key is in, e.g. `[tool.maturin.profiles.ci]`
+    pub table: String,
+    /// The deprecated key
+    pub old: &'static str,
+    /// The new key and value as written to pyproject.toml, e.g. `compatibility = "manylinux2014"`
+    pub replacement: String,
+    /// The version that deprecated the old key
+    pub since: &'static str,
+    /// The first version that rejects the old key
+    pub removed_in: &'static str,
+}
+
+impl Migration {
+    /// Whether `version` no longer accepts the old key
+    pub fn is_removed(&self, version: &Version) -> bool {
+        match Version::parse(self.removed_in) {
+            Ok(removed_in) => *version >= removed_in,
+            Err(_) => false,
+        }
+    }
+
+    /// The error for a key the running maturin no longer accepts
+    pub fn removed_error(&self) -> ConfigError {
+        ConfigError::new(format!(
+            "`{}` in {} was removed in maturin {}, use `{}` instead. \
+            Run `maturin migrate-config --write` to update pyproject.toml.",
+            self.old, self.table, self.removed_in, self.replacement
+        ))
+    }
+}
+
+impl fmt::Display for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` in {} is deprecated since maturin {} and will be removed in {}, use `{}` instead",
+            self.old, self.table, self.since, self.removed_in, self.replacement
+        )
+    }
+}
+
+/// A key as written in a table header, quoted unless it's a bare key
+fn table_key(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        key.to_string()
+    } else {
+        format!("'{}'", key)
+    }
+}
+
+/// Replaces `old` with `new`, keeping its position in the table and the comments above it
+fn replace_key(table: &mut dyn TableLike, old: &str, new: &str, item: Item) {
+    let keys: Vec<String> = table.iter().map(|(key, _)| key.to_string()).collect();
+    let position = keys.iter().position(|key| key == old).unwrap_or(keys.len());
+    // Tables keep the insertion order, so the old key and the ones after it are removed and
+    // inserted again with their formatting
+    let mut entries: Vec<(Key, Item)> = Vec::new();
+    for key in &keys[position..] {
+        if let Some((key, _)) = table.get_key_value(key) {
+            let key = key.clone();
+            if let Some(item) = table.remove(key.get()) {
+                entries.push((key, item));
+            }
+        }
+    }
+    let mut entries = entries.into_iter();
+    let new_key = match entries.next() {
+        Some((old_key, _)) => Key::new(new).with_decor(old_key.decor().clone()),
+        None => Key::new(new),
+    };
+    table.entry_format(&new_key).or_insert(item);
+    for (key, item) in entries {
+        table.entry_format(&key).or_insert(item);
+    }
+}
+
+/// Replaces the deprecated keys of one table
+fn migrate_table(
+    table: &mut dyn TableLike,
+    name: &str,
+    scope: Scope,
+    migrations: &mut Vec<Migration>,
+) -> Result<()> {
+    for deprecation in DEPRECATIONS {
+        if !deprecation.scopes.contains(&scope) {
+            continue;
+        }
+        let old = match table.get(deprecation.old) {
+            Some(old) => old.clone(),
+            None => continue,
+        };
+        let replacement = match deprecation.conversion {
+            Conversion::Rename => {
+                if table.contains_key(deprecation.new) {
+                    bail!(ConfigError::new(format!(
+                        "`{}` and `{}` are both set in {}, please remove the deprecated `{}`",
+                        deprecation.old, deprecation.new, name, deprecation.old
+                    )));
+                }
+                let replacement = old.to_string();
+                replace_key(table, deprecation.old, deprecation.new, old);
+                replacement
+            }
+            Conversion::SdistInclude => {
+                let patterns = match old.as_array() {
+                    Some(patterns) if patterns.iter().all(Value::is_str) => patterns,
+                    _ => bail!(ConfigError::new(format!(
+                        "`{}` in {} must be an array of strings",
+                        deprecation.old, name
+                    ))),
+                };
+                let entries = patterns.iter().filter_map(Value::as_str).map(|pattern| {
+                    let mut entry = InlineTable::new();
+                    entry.insert("path", pattern.into());
+                    entry.insert("format", "sdist".into());
+                    entry.fmt();
+                    entry
+                });
+                match table.get_mut(deprecation.new) {
+                    Some(Item::Value(Value::Array(include))) => {
+                        for entry in entries {
+                            include.push(entry);
+                        }
+                        let replacement = include.to_string();
+                        table.remove(deprecation.old);
+                        replacement
+                    }
+                    Some(_) => bail!(ConfigError::new(format!(
+                        "`{}` in {} must be an array",
+                        deprecation.new, name
+                    ))),
+                    None => {
+                        let include: Array = entries.collect();
+                        let replacement = include.to_string();
+                        replace_key(
+                            table,
+                            deprecation.old,
+                            deprecation.new,
+                            toml_edit::value(include),
+                        );
+                        replacement
+                    }
+                }
+            }
+        };
+        migrations.push(Migration {
+            table: name.to_string(),
+            old: deprecation.old,
+            replacement: format!("{} = {}", deprecation.new, replacement.trim()),
+            since: deprecation.since,
+            removed_in: deprecation.removed_in,
+        });
+    }
+    Ok(())
+}
+
+/// Replaces the deprecated keys in `[tool.maturin]` and its subtables with their replacements,
+/// returning what was replaced. The rest of the document stays as it was.
+pub(crate) fn migrate(document: &mut Document) -> Result<Vec<Migration>> {
+    let maturin = match document
+        .get_mut("tool")
+        .and_then(Item::as_table_like_mut)
+        .and_then(|tool| tool.get_mut("maturin"))
+        .and_then(Item::as_table_like_mut)
+    {
+        Some(maturin) => maturin,
+        None => return Ok(Vec::new()),
+    };
+    let mut migrations = Vec::new();
+    migrate_table(maturin, "[tool.maturin]", Scope::Maturin, &mut migrations)?;
+
+    let cfg_keys: Vec<String> = maturin
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| key.starts_with("cfg("))
+        .collect();
+    for key in cfg_keys {
+        if let Some(table) = maturin.get_mut(&key).and_then(Item::as_table_like_mut) {
+            let name = format!("[tool.maturin.{}]", table_key(&key));
+            migrate_table(table, &name, Scope::Maturin, &mut migrations)?;
+        }
+    }
+
+    if let Some(profiles) = maturin
+        .get_mut("profiles")
+        .and_then(Item::as_table_like_mut)
+    {
+        for (key, profile) in profiles.iter_mut() {
+            if let Some(table) = profile.as_table_like_mut() {
+                let name = format!("[tool.maturin.profiles.{}]", table_key(key.get()));
+                migrate_table(table, &name, Scope::Profile, &mut migrations)?;
+            }
+        }
+    }
+    Ok(migrations)
+}
+
+/// Options for `maturin migrate-config`
+#[derive(Debug, clap::Parser)]
+pub struct MigrateConfigOptions {
+    /// The pyproject.toml to migrate, defaults to the one in the current directory
+    #[arg(value_name = "PYPROJECT")]
+    pyproject: Option<PathBuf>,
+    /// Write the changes to pyproject.toml instead of only showing a diff
+    #[arg(long)]
+    write: bool,
+}
+
+/// Replaces the deprecated options in `[tool.maturin]` with their replacements. Shows a diff of
+/// the changes unless `--write` is passed
+pub fn migrate_config(options: MigrateConfigOptions) -> Result<()> {
+    let path = options
+        .pyproject
+        .unwrap_or_else(|| PathBuf::from("pyproject.toml"));
+    let mut editor = TomlEditor::open(path)?;
+    let migrations = migrate(editor.document_mut())?;
+    let path = editor.path().display().to_string();
+    if migrations.is_empty() {
+        eprintln!("✨ {} doesn't use any deprecated options", path);
+        return Ok(());
+    }
+
+    if options.write {
+        editor.save()?;
+        for migration in migrations {
+            eprintln!(
+                "📝 Replaced `{}` in {} with `{}`",
+                migration.old, migration.table, migration.replacement
+            );
+        }
+    } else {
+        println!("--- {}", path);
+        println!("+++ {}", path);
+        print!("{}", editor.diff());
+        eprintln!("💡 Run with `--write` to apply these changes");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_migrate() {
+        let mut document: Document = indoc!(
+            r#"
+            [tool.maturin]
+            # The platform tag
+            manylinux = "2010"
+            sdist-include = ["README.md", "data/*"]
+            include = ["LICENSE"]
+
+            [tool.maturin.'cfg(unix)']
+            manylinux = "off"
+
+            [tool.maturin.profiles.ci]
+            manylinux = "2014"
+            "#
+        )
+        .parse()
+        .unwrap();
+        let migrations = migrate(&mut document).unwrap();
+        assert_eq!(
+            document.to_string(),
+            indoc!(
+                r#"
+                [tool.maturin]
+                # The platform tag
+                compatibility = "2010"
+                include = ["LICENSE", { path = "README.md", format = "sdist" }, { path = "data/*", format = "sdist" }]
+
+                [tool.maturin.'cfg(unix)']
+                compatibility = "off"
+
+                [tool.maturin.profiles.ci]
+                compatibility = "2014"
+                "#
+            )
+        );
+        let replaced: Vec<(&str, &str, &str)> = migrations
+            .iter()
+            .map(|x| (x.table.as_str(), x.old, x.replacement.as_str()))
+            .collect();
+        assert_eq!(
+            replaced,
+            [
+                ("[tool.maturin]", "manylinux", "compatibility = \"2010\""),
+                (
+                    "[tool.maturin]",
+                    "sdist-include",
+                    "include = [\"LICENSE\", { path = \"README.md\", format = \"sdist\" }, \
+                    { path = \"data/*\", format = \"sdist\" }]"
+                ),
+                (
+                    "[tool.maturin.'cfg(unix)']",
+                    "manylinux",
+                    "compatibility = \"off\""
+                ),
+                (
+                    "[tool.maturin.profiles.ci]",
+                    "manylinux",
+                    "compatibility = \"2014\""
+                ),
+            ]
+        );
+        assert!(!migrations[0].is_removed(&Version::new(0, 14, 3)));
+        assert!(migrations[0].is_removed(&Version::new(0, 15, 0)));
+
+        // Nothing to do the second time
+        assert!(migrate(&mut document).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_migrate_conflict() {
+        let mut document: Document = indoc!(
+            r#"
+            [tool.maturin]
+            manylinux = "2010"
+            compatibility = "2014"
+            "#
+        )
+        .parse()
+        .unwrap();
+        assert!(migrate(&mut document).is_err());
+    }
+}
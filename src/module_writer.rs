@@ -1,33 +1,141 @@
 //! The wheel format is (mostly) specified in PEP 427
+use crate::build_context::scratch_dir;
+use crate::file_lock::FileLock;
+use crate::globs::{check_negations, PathGlobs};
 use crate::project_layout::ProjectLayout;
 use crate::{
-    pyproject_toml::Format, BridgeModel, Metadata21, PyProjectToml, PythonInterpreter, Target,
+    pyproject_toml::{Format, TarFormat, UnmatchedInclude, WheelConfig},
+    BridgeModel, ConfigError, Metadata21, PyProjectToml, PythonInterpreter, Target,
 };
 use anyhow::{anyhow, bail, Context, Result};
 use flate2::write::GzEncoder;
-use flate2::Compression;
+use flate2::{Compression, GzBuilder};
 use fs_err as fs;
 use fs_err::File;
 use ignore::overrides::Override;
 use ignore::WalkBuilder;
 use normpath::PathExt as _;
 use sha2::{Digest, Sha256};
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
 use std::ffi::OsStr;
 use std::fmt::Write as _;
 #[cfg(target_family = "unix")]
 use std::fs::OpenOptions;
 use std::io;
 use std::io::{Read, Write};
+use std::iter;
+use std::mem;
 #[cfg(target_family = "unix")]
-use std::os::unix::fs::OpenOptionsExt;
-use std::path::{Path, PathBuf};
-use std::process::{Command, Output};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::process::{self, Command, Output};
 use std::str;
-use tempfile::{tempdir, TempDir};
+use std::time::{Duration, SystemTime};
+use tempfile::{tempdir_in, TempDir};
 use tracing::debug;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
 use zip::{self, ZipWriter};
 
+/// How long to wait for another maturin process moving a file into the output directory
+const OUTPUT_LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The modification time and size of a file, which change when another process writes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileState {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileState {
+    /// Returns the current state of the file, or `None` if it doesn't exist
+    fn of(path: &Path) -> Option<FileState> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(FileState {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+/// The temporary file an archive is written to, so other processes never see a partially
+/// written archive at its final path. It's removed when dropped unless it was published.
+#[derive(Debug)]
+struct PartialFile {
+    path: PathBuf,
+    published: bool,
+}
+
+impl PartialFile {
+    /// Creates the temporary file for the archive at `path`
+    fn create(path: &Path) -> io::Result<(PartialFile, File)> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let partial = PartialFile {
+            path: path.with_file_name(format!(".{}.{}.part", name, process::id())),
+            published: false,
+        };
+        let file = File::create(&partial.path)?;
+        Ok((partial, file))
+    }
+
+    /// Moves the finished archive to `path`. Fails instead of silently overwriting the file if
+    /// another process, e.g. a parallel job of a build matrix sharing the output directory,
+    /// wrote a file with the same name but different contents in the meantime, i.e. when the
+    /// file changed from its `previous` state before we started writing
+    fn publish(&mut self, path: &Path, previous: Option<FileState>) -> io::Result<()> {
+        let _lock = FileLock::acquire(
+            output_lock_path(path),
+            OUTPUT_LOCK_TIMEOUT,
+            &format!("another maturin process writing {}", path.display()),
+        )
+        .map_err(other_io_error)?;
+        let current = FileState::of(path);
+        let written_concurrently = current.is_some() && current != previous;
+        if written_concurrently
+            && Sha256::digest(fs::read(path)?) != Sha256::digest(fs::read(&self.path)?)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} was written by another maturin process with different contents during \
+                    this build. Do multiple jobs build the same file into a shared output directory?",
+                    path.display()
+                ),
+            ));
+        }
+        fs::rename(&self.path, path)?;
+        self.published = true;
+        Ok(())
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if !self.published {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// The lock file for moving files into the directory of `path`. It's in the temporary directory
+/// and named by the hash of the directory, so that the output directory, e.g. `dist/`, only
+/// contains the archives and nothing else gets uploaded with `twine upload dist/*`
+fn output_lock_path(path: &Path) -> PathBuf {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let dir = dir
+        .normalize()
+        .map(|dir| dir.into_path_buf())
+        .unwrap_or_else(|_| dir.to_path_buf());
+    let hash = format!("{:x}", Sha256::digest(dir.to_string_lossy().as_bytes()));
+    env::temp_dir().join(format!("maturin-output-{}.lock", &hash[..16]))
+}
+
+/// Wraps an error for the functions returning an [io::Error], keeping its context
+fn other_io_error(err: anyhow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("{:#}", err))
+}
+
 /// Allows writing the module to a wheel or add it directly to the virtualenv
 pub trait ModuleWriter {
     /// Adds a directory relative to the module base path
@@ -76,6 +184,39 @@ pub trait ModuleWriter {
     }
 }
 
+/// Quotes a RECORD field the way python's `csv` module does, which is required for paths
+/// containing commas, quotes or line breaks
+fn csv_escape(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Formats a line of the RECORD file
+fn record_entry(filename: &str, hash: &str, len: usize) -> String {
+    format!("{},sha256={},{}\n", csv_escape(filename), hash, len)
+}
+
+/// Normalizes a file name in an archive to unicode NFC, since e.g. macOS creates NFD file names,
+/// which would make the archive differ between platforms
+fn normalize_file_name(name: &str) -> Option<String> {
+    if is_nfc(name) {
+        return None;
+    }
+    let normalized: String = name.nfc().collect();
+    eprintln!(
+        "⚠️  Warning: The file name {} ({}) is not unicode NFC normalized, it's stored as {} ({}). \
+        Set `tool.maturin.unicode-normalization = false` in pyproject.toml to keep file names unchanged",
+        name,
+        name.escape_unicode(),
+        normalized,
+        normalized.escape_unicode()
+    );
+    Some(normalized)
+}
+
 /// A [ModuleWriter] that adds the module somewhere in the filesystem, e.g. in a virtualenv
 pub struct PathWriter {
     base_path: PathBuf,
@@ -133,7 +274,7 @@ impl PathWriter {
 
         for (filename, hash, len) in self.record {
             buffer
-                .write_all(format!("{},sha256={},{}\n", filename, hash, len).as_bytes())
+                .write_all(record_entry(&filename, &hash, len).as_bytes())
                 .context(format!(
                     "Failed to write to file at {}",
                     record_file.display()
@@ -141,7 +282,7 @@ impl PathWriter {
         }
         // Write the record for the RECORD file itself
         buffer
-            .write_all(format!("{},,\n", record_file.display()).as_bytes())
+            .write_all(format!("{},,\n", csv_escape(&record_file.to_string_lossy())).as_bytes())
             .context(format!(
                 "Failed to write to file at {}",
                 record_file.display()
@@ -199,13 +340,39 @@ impl ModuleWriter for PathWriter {
     }
 }
 
+/// Files with the same contents as an earlier file in the wheel are only reported from this size
+/// on, since small duplicates such as empty `__init__.py` files are expected
+const DUPLICATE_CONTENTS_MIN_SIZE: usize = 64 * 1024;
+
+/// The header id of the extra field that pads the file header for `[tool.maturin.wheel] align`,
+/// the same that the zip crate uses
+const ALIGNMENT_FIELD_ID: u16 = 0x617a;
+
+const INVALID_EXTRA_FIELDS: &str = "Failed to write the extra fields from \
+    `[tool.maturin.wheel]`, the ids reserved by the zip specification can't be used";
+
 /// A glorified zip builder, mostly useful for writing the record file of a wheel
 pub struct WheelWriter {
     zip: ZipWriter<File>,
     record: Vec<(String, String, usize)>,
+    /// The hashes of the files already written to the wheel, keyed by their archive path
+    files: HashMap<String, String>,
+    /// The archive paths of the large files already written to the wheel, keyed by their hash
+    contents: HashMap<String, String>,
+    /// Whether large files with the same contents as an earlier file are left out
+    skip_duplicate_contents: bool,
+    /// The extra fields and alignment of the file headers
+    config: WheelConfig,
     record_file: PathBuf,
     wheel_path: PathBuf,
+    /// The state of `wheel_path` before writing, to detect concurrent writes of the same wheel
+    previous: Option<FileState>,
     excludes: Option<Override>,
+    /// Whether file names are normalized to unicode NFC
+    normalize_unicode: bool,
+    /// The temporary file the wheel is written to before it's moved to `wheel_path`, after the
+    /// zip writer so that the file is closed before it's removed
+    partial: PartialFile,
 }
 
 impl ModuleWriter for WheelWriter {
@@ -224,7 +391,46 @@ impl ModuleWriter for WheelWriter {
             return Ok(());
         }
         // The zip standard mandates using unix style paths
-        let target = target.to_str().unwrap().replace('\\', "/");
+        let mut target = target.to_str().unwrap().replace('\\', "/");
+        if self.normalize_unicode {
+            if let Some(normalized) = normalize_file_name(&target) {
+                target = normalized;
+            }
+        }
+
+        let hash = base64::encode_config(Sha256::digest(bytes), base64::URL_SAFE_NO_PAD);
+        if let Some(existing_hash) = self.files.get(&target) {
+            if existing_hash == &hash {
+                // Ignore duplicate files with identical contents
+                debug!("Skipping duplicate {}", target);
+                return Ok(());
+            }
+            bail!(
+                "Conflicting files in the wheel: {} was already added with different contents. \
+                Check your `include` patterns and python source for overlapping files",
+                target
+            );
+        }
+        if bytes.len() >= DUPLICATE_CONTENTS_MIN_SIZE {
+            if let Some(existing) = self.contents.get(&hash) {
+                if self.skip_duplicate_contents {
+                    eprintln!(
+                        "⚠️  Warning: Skipping {}, it has the same contents as {}",
+                        target, existing
+                    );
+                    return Ok(());
+                }
+                eprintln!(
+                    "⚠️  Warning: {} has the same contents as {}, so the wheel stores them twice. \
+                    Check your `include` patterns and data directory for overlapping files, \
+                    or set `skip-duplicate-contents = true` in `[tool.maturin]` to only keep \
+                    the first one",
+                    target, existing
+                );
+            } else {
+                self.contents.insert(hash.clone(), target.clone());
+            }
+        }
 
         // Unlike users which can use the develop subcommand, the tests have to go through
         // packing a zip which pip than has to unpack. This makes this 2-3 times faster
@@ -236,16 +442,39 @@ impl ModuleWriter for WheelWriter {
         let options = zip::write::FileOptions::default()
             .unix_permissions(permissions)
             .compression_method(compression_method);
-        self.zip.start_file(target.clone(), options)?;
+        self.start_file(target.clone(), options)?;
         self.zip.write_all(bytes)?;
 
-        let hash = base64::encode_config(Sha256::digest(bytes), base64::URL_SAFE_NO_PAD);
+        self.files.insert(target.clone(), hash.clone());
         self.record.push((target, hash, bytes.len()));
 
         Ok(())
     }
 }
 
+/// The file name of a wheel, `{distribution}-{version}(-{build tag})?-{tag}.whl`
+pub(crate) fn wheel_file_name(
+    metadata21: &Metadata21,
+    build_tag: Option<&str>,
+    tag: &str,
+) -> String {
+    match build_tag {
+        Some(build_tag) => format!(
+            "{}-{}-{}-{}.whl",
+            metadata21.get_distribution_escaped(),
+            metadata21.get_version_escaped(),
+            build_tag,
+            tag
+        ),
+        None => format!(
+            "{}-{}-{}.whl",
+            metadata21.get_distribution_escaped(),
+            metadata21.get_version_escaped(),
+            tag
+        ),
+    }
+}
+
 impl WheelWriter {
     /// Create a new wheel file which can be subsequently expanded
     ///
@@ -255,30 +484,128 @@ impl WheelWriter {
         wheel_dir: &Path,
         metadata21: &Metadata21,
         tags: &[String],
+        build_tag: Option<&str>,
         excludes: Option<Override>,
+        normalize_unicode: bool,
     ) -> Result<WheelWriter> {
-        let wheel_path = wheel_dir.join(format!(
-            "{}-{}-{}.whl",
-            metadata21.get_distribution_escaped(),
-            metadata21.get_version_escaped(),
-            tag
-        ));
+        Self::with_config(
+            tag,
+            wheel_dir,
+            metadata21,
+            tags,
+            build_tag,
+            excludes,
+            normalize_unicode,
+            WheelConfig::default(),
+        )
+    }
+
+    /// Like [WheelWriter::new], with the zip comment, extra fields and alignment from
+    /// `[tool.maturin.wheel]`
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_config(
+        tag: &str,
+        wheel_dir: &Path,
+        metadata21: &Metadata21,
+        tags: &[String],
+        build_tag: Option<&str>,
+        excludes: Option<Override>,
+        normalize_unicode: bool,
+        config: WheelConfig,
+    ) -> Result<WheelWriter> {
+        let extra_fields_len: usize = config
+            .extra_fields
+            .iter()
+            .map(|field| 4 + field.data.len())
+            .sum();
+        let padding_len = config.align.map_or(0, |align| 4 + usize::from(align));
+        if extra_fields_len + padding_len > usize::from(u16::MAX) {
+            bail!(ConfigError::new(
+                "The extra fields and alignment in `[tool.maturin.wheel]` don't fit into the \
+                65535 bytes of a zip file header"
+            ));
+        }
+
+        let wheel_path = wheel_dir.join(wheel_file_name(metadata21, build_tag, tag));
 
-        let file = File::create(&wheel_path)?;
+        let previous = FileState::of(&wheel_path);
+        let (partial, file) = PartialFile::create(&wheel_path)?;
+        let mut zip = ZipWriter::new(file);
+        if let Some(comment) = &config.zip_comment {
+            if comment.len() > usize::from(u16::MAX) {
+                bail!(ConfigError::new(
+                    "The zip-comment in `[tool.maturin.wheel]` is longer than 65535 bytes"
+                ));
+            }
+            zip.set_comment(comment.clone());
+        }
 
         let mut builder = WheelWriter {
-            zip: ZipWriter::new(file),
+            zip,
             record: Vec::new(),
+            files: HashMap::new(),
+            contents: HashMap::new(),
+            skip_duplicate_contents: false,
+            config,
             record_file: metadata21.get_dist_info_dir().join("RECORD"),
             wheel_path,
+            previous,
             excludes,
+            normalize_unicode,
+            partial,
         };
 
-        write_dist_info(&mut builder, metadata21, tags)?;
+        write_dist_info(&mut builder, metadata21, tags, build_tag)?;
 
         Ok(builder)
     }
 
+    /// Leave out large files with the same contents as a file that was added before, instead of
+    /// only warning about them
+    pub fn set_skip_duplicate_contents(&mut self, skip_duplicate_contents: bool) {
+        self.skip_duplicate_contents = skip_duplicate_contents;
+    }
+
+    /// Starts a file in the zip archive with the extra fields and alignment from the config
+    fn start_file(&mut self, name: String, options: zip::write::FileOptions) -> Result<()> {
+        if self.config.extra_fields.is_empty() && self.config.align.is_none() {
+            self.zip.start_file(name, options)?;
+            return Ok(());
+        }
+        let data_start = self.zip.start_file_with_extra_data(name, options)?;
+        self.write_extra_fields()?;
+        if let Some(align) = self.config.align.filter(|align| *align > 1) {
+            // The padding is only needed in the local file header, which precedes the contents
+            let extra_fields_len: u64 = self
+                .config
+                .extra_fields
+                .iter()
+                .map(|field| 4 + field.data.len() as u64)
+                .sum();
+            let align = u64::from(align);
+            let padding = (align - (data_start + extra_fields_len + 4) % align) % align;
+            self.zip.write_all(&ALIGNMENT_FIELD_ID.to_le_bytes())?;
+            self.zip.write_all(&(padding as u16).to_le_bytes())?;
+            self.zip.write_all(&vec![0; padding as usize])?;
+            self.zip
+                .end_local_start_central_extra_data()
+                .context(INVALID_EXTRA_FIELDS)?;
+            self.write_extra_fields()?;
+        }
+        self.zip.end_extra_data().context(INVALID_EXTRA_FIELDS)?;
+        Ok(())
+    }
+
+    fn write_extra_fields(&mut self) -> Result<()> {
+        for field in &self.config.extra_fields {
+            self.zip.write_all(&field.id.to_le_bytes())?;
+            self.zip
+                .write_all(&(field.data.len() as u16).to_le_bytes())?;
+            self.zip.write_all(field.data.as_bytes())?;
+        }
+        Ok(())
+    }
+
     /// Add a pth file to wheel root for editable installs
     pub fn add_pth(
         &mut self,
@@ -286,6 +613,12 @@ impl WheelWriter {
         metadata21: &Metadata21,
     ) -> Result<()> {
         if let Some(python_module) = &project_layout.python_module {
+            if !project_layout.extra_python_modules.is_empty() {
+                eprintln!(
+                    "⚠️  Warning: Editable installs only use the first python-source directory, \
+                    files from the other python-source directories won't be importable"
+                );
+            }
             let absolute_path = python_module.normalize()?.into_path_buf();
             if let Some(python_path) = absolute_path.parent().and_then(|p| p.to_str()) {
                 let name = metadata21.get_distribution_escaped();
@@ -308,7 +641,7 @@ impl WheelWriter {
         }
     }
 
-    /// Creates the record file and finishes the zip
+    /// Creates the record file, finishes the zip and moves it into the output directory
     pub fn finish(mut self) -> Result<PathBuf, io::Error> {
         let compression_method = if cfg!(feature = "faster-tests") {
             zip::CompressionMethod::Stored
@@ -318,26 +651,167 @@ impl WheelWriter {
         let options = zip::write::FileOptions::default().compression_method(compression_method);
         let record_filename = self.record_file.to_str().unwrap().replace('\\', "/");
         debug!("Adding {}", record_filename);
-        self.zip.start_file(&record_filename, options)?;
+        self.start_file(record_filename.clone(), options)
+            .map_err(other_io_error)?;
         for (filename, hash, len) in self.record {
             self.zip
-                .write_all(format!("{},sha256={},{}\n", filename, hash, len).as_bytes())?;
+                .write_all(record_entry(&filename, &hash, len).as_bytes())?;
         }
         // Write the record for the RECORD file itself
         self.zip
-            .write_all(format!("{},,\n", record_filename).as_bytes())?;
+            .write_all(format!("{},,\n", csv_escape(&record_filename)).as_bytes())?;
 
         self.zip.finish()?;
+        self.partial.publish(&self.wheel_path, self.previous)?;
         Ok(self.wheel_path)
     }
 }
 
+/// Reads back a wheel, e.g. one written by [WheelWriter], to inspect its files and metadata
+#[derive(Debug, Clone)]
+pub struct WheelReader {
+    path: PathBuf,
+    /// The names of all entries in archive order, including directory entries
+    names: Vec<String>,
+    /// The contents of the files, keyed by their archive path
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl WheelReader {
+    /// Opens the wheel and reads all of its files into memory
+    pub fn open(path: impl AsRef<Path>) -> Result<WheelReader> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let mut archive = zip::ZipArchive::new(file)
+            .with_context(|| format!("Failed to open {} as zip archive", path.display()))?;
+        let mut names = Vec::with_capacity(archive.len());
+        let mut files = Vec::with_capacity(archive.len());
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            names.push(entry.name().to_string());
+            if entry.is_dir() {
+                continue;
+            }
+            let mut buffer = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut buffer).with_context(|| {
+                format!("Failed to read {} from {}", entry.name(), path.display())
+            })?;
+            files.push((entry.name().to_string(), buffer));
+        }
+        Ok(WheelReader {
+            path: path.to_path_buf(),
+            names,
+            files,
+        })
+    }
+
+    /// The path the wheel was read from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The names of all entries in archive order, including directory entries
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    /// The archive path and contents of each file in archive order
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.files
+            .iter()
+            .map(|(name, content)| (name.as_str(), content.as_slice()))
+    }
+
+    /// Returns the contents of the file at the archive path `name`
+    pub fn read(&self, name: &str) -> Option<&[u8]> {
+        self.entries()
+            .find(|(path, _)| *path == name)
+            .map(|(_, content)| content)
+    }
+
+    /// Returns the name of the .dist-info directory, which must be the only one in the wheel
+    pub fn dist_info_dir(&self) -> Result<String> {
+        let mut dist_info_dirs: Vec<&str> = self
+            .entries()
+            .filter_map(|(name, _)| name.split_once('/'))
+            .map(|(dir, _)| dir)
+            .filter(|dir| dir.ends_with(".dist-info"))
+            .collect();
+        dist_info_dirs.sort_unstable();
+        dist_info_dirs.dedup();
+        match dist_info_dirs.as_slice() {
+            [dist_info_dir] => Ok(dist_info_dir.to_string()),
+            [] => bail!("{} has no .dist-info directory", self.path.display()),
+            _ => bail!(
+                "{} has multiple .dist-info directories: {}",
+                self.path.display(),
+                dist_info_dirs.join(", ")
+            ),
+        }
+    }
+
+    /// Returns the contents of a file in the .dist-info directory as text
+    pub fn read_dist_info(&self, name: &str) -> Result<String> {
+        let path = format!("{}/{}", self.dist_info_dir()?, name);
+        let content = self
+            .read(&path)
+            .with_context(|| format!("{} is missing {}", self.path.display(), path))?;
+        String::from_utf8(content.to_vec()).with_context(|| format!("{} is not valid UTF-8", path))
+    }
+
+    /// Parses the core metadata in the METADATA file
+    pub fn metadata(&self) -> Result<python_pkginfo::Metadata> {
+        let metadata = self.read_dist_info("METADATA")?;
+        python_pkginfo::Metadata::parse(metadata.as_bytes())
+            .with_context(|| format!("Invalid METADATA in {}", self.path.display()))
+    }
+
+    /// Returns the key value pairs of the WHEEL file
+    pub fn wheel_info(&self) -> Result<Vec<(String, String)>> {
+        let wheel = self.read_dist_info("WHEEL")?;
+        Ok(crate::verify::parse_key_values(&wheel)
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect())
+    }
+
+    /// Checks that RECORD lists every file with the correct hash and size. Returns a
+    /// description of each mismatch, so an empty list means the RECORD is valid.
+    pub fn verify_record(&self) -> Result<Vec<String>> {
+        let record_path = format!("{}/RECORD", self.dist_info_dir()?);
+        let record = self.read_dist_info("RECORD")?;
+        let mut problems = Vec::new();
+        crate::verify::verify_record(&record, &record_path, &self.file_map(), &mut problems);
+        Ok(problems)
+    }
+
+    /// The contents of the files keyed by their archive path
+    pub(crate) fn file_map(&self) -> HashMap<&str, &[u8]> {
+        self.entries().collect()
+    }
+}
+
 /// Creates a .tar.gz archive containing the source distribution
 pub struct SDistWriter {
     tar: tar::Builder<GzEncoder<File>>,
     path: PathBuf,
-    files: HashSet<PathBuf>,
+    /// The state of `path` before writing, to detect concurrent writes of the same archive
+    previous: Option<FileState>,
+    /// The entries of the archive, which are only written by [SDistWriter::finish] so that they
+    /// are sorted by path independent of the order they were added in
+    files: BTreeMap<PathBuf, (tar::Header, SDistEntry)>,
     excludes: Option<Override>,
+    /// Only the files in the set are added from the directory, see
+    /// [SDistWriter::set_allowed_files]
+    allowed_files: Option<(PathBuf, HashSet<PathBuf>)>,
+    /// The files that were left out because they aren't allowed
+    skipped_files: Vec<PathBuf>,
+    /// Whether file names are normalized to unicode NFC
+    normalize_unicode: bool,
+    tar_format: TarFormat,
+    /// The temporary file the archive is written to before it's moved to `path`, after the tar
+    /// builder so that the file is closed before it's removed
+    partial: PartialFile,
 }
 
 impl ModuleWriter for SDistWriter {
@@ -355,33 +829,51 @@ impl ModuleWriter for SDistWriter {
         if self.exclude(target) {
             return Ok(());
         }
+        let target = self.normalize(target);
+        let target = target.as_path();
 
-        if self.files.contains(target) {
+        if self.files.contains_key(target) {
             // Ignore duplicate files
             return Ok(());
         }
 
-        let mut header = tar::Header::new_gnu();
+        let mut header = self.new_header();
         header.set_size(bytes.len() as u64);
         header.set_mode(permissions);
-        header.set_cksum();
-        self.tar
-            .append_data(&mut header, target, bytes)
-            .context(format!(
-                "Failed to add {} bytes to sdist as {}",
-                bytes.len(),
-                target.display()
-            ))?;
-        self.files.insert(target.to_path_buf());
+        header.set_mtime(sdist_mtime());
+        self.files.insert(
+            target.to_path_buf(),
+            (header, SDistEntry::Bytes(bytes.to_vec())),
+        );
         Ok(())
     }
 
     fn add_file(&mut self, target: impl AsRef<Path>, source: impl AsRef<Path>) -> Result<()> {
+        let source = source.as_ref();
+        if !self.is_allowed(source) {
+            if !self.exclude(source) {
+                self.skipped_files.push(source.to_path_buf());
+            }
+            return Ok(());
+        }
+        self.add_generated_file(target, source)
+    }
+}
+
+impl SDistWriter {
+    /// Adds a file that maturin generated, e.g. with `cargo vendor`, which isn't restricted by
+    /// [SDistWriter::set_allowed_files]
+    pub fn add_generated_file(
+        &mut self,
+        target: impl AsRef<Path>,
+        source: impl AsRef<Path>,
+    ) -> Result<()> {
         let source = source.as_ref();
         if self.exclude(source) {
             return Ok(());
         }
-        let target = target.as_ref();
+        let target = self.normalize(target.as_ref());
+        let target = target.as_path();
         if source == self.path {
             eprintln!(
                 "⚠️  Warning: Attempting to include the sdist output tarball {} into itself! Check 'cargo package --list' output.",
@@ -389,30 +881,35 @@ impl ModuleWriter for SDistWriter {
             );
             return Ok(());
         }
-        if self.files.contains(target) {
+        if self.files.contains_key(target) {
             // Ignore duplicate files
             return Ok(());
         }
         debug!("Adding {} from {}", target.display(), source.display());
 
-        self.tar
-            .append_path_with_name(source, target)
-            .context(format!(
-                "Failed to add file from {} to sdist as {}",
-                source.display(),
-                target.display(),
-            ))?;
-        self.files.insert(target.to_path_buf());
+        let mut header = self.new_header();
+        let metadata = fs::metadata(source).context(format!(
+            "Failed to add file from {} to sdist as {}",
+            source.display(),
+            target.display(),
+        ))?;
+        // Only keeps whether the file is executable, the owner and times depend on the machine
+        header.set_metadata_in_mode(&metadata, tar::HeaderMode::Deterministic);
+        header.set_mtime(sdist_mtime());
+        self.files.insert(
+            target.to_path_buf(),
+            (header, SDistEntry::File(source.to_path_buf())),
+        );
         Ok(())
     }
-}
 
-impl SDistWriter {
     /// Create a source distribution .tar.gz which can be subsequently expanded
     pub fn new(
         wheel_dir: impl AsRef<Path>,
         metadata21: &Metadata21,
         excludes: Option<Override>,
+        normalize_unicode: bool,
+        tar_format: TarFormat,
     ) -> Result<Self, io::Error> {
         let path = wheel_dir.as_ref().join(format!(
             "{}-{}.tar.gz",
@@ -420,18 +917,87 @@ impl SDistWriter {
             &metadata21.get_version_escaped()
         ));
 
-        let tar_gz = File::create(&path)?;
-        let enc = GzEncoder::new(tar_gz, Compression::default());
+        let previous = FileState::of(&path);
+        let (partial, tar_gz) = PartialFile::create(&path)?;
+        // The gzip header contains a timestamp and the operating system, which would make the
+        // archive differ between otherwise identical builds
+        let mtime = source_date_epoch()
+            .map(|epoch| u32::try_from(epoch).unwrap_or(u32::MAX))
+            .unwrap_or_default();
+        let enc = GzBuilder::new()
+            .mtime(mtime)
+            .operating_system(GZIP_OS_UNKNOWN)
+            .write(tar_gz, Compression::default());
         let tar = tar::Builder::new(enc);
 
         Ok(Self {
             tar,
             path,
-            files: HashSet::new(),
+            previous,
+            files: BTreeMap::new(),
             excludes,
+            allowed_files: None,
+            skipped_files: Vec::new(),
+            normalize_unicode,
+            tar_format,
+            partial,
         })
     }
 
+    /// An empty header of the tar format
+    fn new_header(&self) -> tar::Header {
+        match self.tar_format {
+            TarFormat::Pax => tar::Header::new_ustar(),
+            TarFormat::Gnu => tar::Header::new_gnu(),
+        }
+    }
+
+    /// Appends an entry, storing paths that don't fit into the header in a pax extended header
+    /// or a GNU long name entry
+    fn append(&mut self, mut header: tar::Header, target: &Path, data: impl Read) -> Result<()> {
+        if self.tar_format == TarFormat::Gnu {
+            self.tar.append_data(&mut header, target, data)?;
+            return Ok(());
+        }
+        let path = target
+            .to_str()
+            .with_context(|| format!("{} is not valid utf-8", target.display()))?
+            .replace('\\', "/");
+        // ustar only has room for a 155 byte prefix and a 100 byte name and doesn't specify the
+        // encoding, while pax paths are always utf-8
+        if !path.is_ascii() || header.set_path(&path).is_err() {
+            let record = pax_record("path", &path);
+            let mut pax_header = tar::Header::new_ustar();
+            pax_header.set_path("././@PaxHeader")?;
+            pax_header.set_entry_type(tar::EntryType::XHeader);
+            pax_header.set_size(record.len() as u64);
+            pax_header.set_mode(0o644);
+            pax_header.set_mtime(header.mtime()?);
+            pax_header.set_cksum();
+            self.tar.append(&pax_header, record.as_bytes())?;
+            // Readers without pax support still get the (truncated) file name
+            let file_name = path.rsplit('/').next().unwrap_or_default();
+            let mut end = file_name.len().min(100);
+            while !file_name.is_char_boundary(end) {
+                end -= 1;
+            }
+            header.set_path(&file_name[..end])?;
+        }
+        header.set_cksum();
+        self.tar.append(&header, data)?;
+        Ok(())
+    }
+
+    /// Normalizes the target path to unicode NFC unless disabled
+    fn normalize(&self, target: &Path) -> PathBuf {
+        match target.to_str() {
+            Some(name) if self.normalize_unicode => normalize_file_name(name)
+                .map(PathBuf::from)
+                .unwrap_or_else(|| target.to_path_buf()),
+            _ => target.to_path_buf(),
+        }
+    }
+
     /// Returns `true` if the given path should be excluded
     fn exclude(&self, path: impl AsRef<Path>) -> bool {
         if let Some(excludes) = &self.excludes {
@@ -441,14 +1007,97 @@ impl SDistWriter {
         }
     }
 
-    /// Finished the .tar.gz archive
+    /// Only adds files from `dir` that are in `files`, e.g. the committed files of a git
+    /// repository. The paths must be canonical, files outside of `dir` are added as usual.
+    pub fn set_allowed_files(&mut self, dir: PathBuf, files: HashSet<PathBuf>) {
+        self.allowed_files = Some((dir, files));
+    }
+
+    /// The files that were left out since they aren't in [SDistWriter::set_allowed_files]
+    pub fn skipped_files(&self) -> &[PathBuf] {
+        &self.skipped_files
+    }
+
+    fn is_allowed(&self, source: &Path) -> bool {
+        match &self.allowed_files {
+            Some((dir, files)) => match source.canonicalize() {
+                Ok(source) => !source.starts_with(dir) || files.contains(&source),
+                // Let adding the file report the error
+                Err(_) => true,
+            },
+            None => true,
+        }
+    }
+
+    /// Finished the .tar.gz archive and moves it into the output directory
     pub fn finish(mut self) -> Result<PathBuf, io::Error> {
-        self.tar.finish()?;
+        for (target, (header, entry)) in mem::take(&mut self.files) {
+            match entry {
+                SDistEntry::Bytes(bytes) => {
+                    self.append(header, &target, bytes.as_slice())
+                        .context(format!(
+                            "Failed to add {} bytes to sdist as {}",
+                            bytes.len(),
+                            target.display()
+                        ))
+                }
+                SDistEntry::File(source) => File::open(&source)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|file| self.append(header, &target, file))
+                    .context(format!(
+                        "Failed to add file from {} to sdist as {}",
+                        source.display(),
+                        target.display(),
+                    )),
+            }
+            .map_err(other_io_error)?;
+        }
+        let mut file = self.tar.into_inner()?.finish()?;
+        file.flush()?;
+        drop(file);
+        self.partial.publish(&self.path, self.previous)?;
         Ok(self.path)
     }
 }
 
-fn wheel_file(tags: &[String]) -> Result<String> {
+/// The content of an entry of a source distribution
+enum SDistEntry {
+    Bytes(Vec<u8>),
+    /// A file that is read when the archive is finished
+    File(PathBuf),
+}
+
+/// The modification time of all files in a source distribution, so that building it twice gives
+/// the same archive. This is `SOURCE_DATE_EPOCH` if set, otherwise the earliest time a zip
+/// archive can store, since tools building wheels from the unpacked files may keep their times.
+fn sdist_mtime() -> u64 {
+    // 1980-01-01T00:00:00Z
+    source_date_epoch().unwrap_or(315_532_800)
+}
+
+/// Formats a pax extended header record, `<length> <key>=<value>\n` where the length is in bytes
+/// and includes itself
+fn pax_record(key: &str, value: &str) -> String {
+    let rest = format!(" {}={}\n", key, value);
+    let mut length = rest.len();
+    while (length.to_string().len() + rest.len()) != length {
+        length = length.to_string().len() + rest.len();
+    }
+    format!("{}{}", length, rest)
+}
+
+/// The operating system byte of the gzip header for "unknown", see RFC 1952
+const GZIP_OS_UNKNOWN: u8 = 255;
+
+/// The value of `SOURCE_DATE_EPOCH`, which reproducible builds use instead of the current time,
+/// see <https://reproducible-builds.org/specs/source-date-epoch/>
+pub(crate) fn source_date_epoch() -> Option<u64> {
+    env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse().ok())
+}
+
+fn wheel_file(tags: &[String], build_tag: Option<&str>) -> Result<String> {
     let mut wheel_file = format!(
         "Wheel-Version: 1.0
 Generator: {name} ({version})
@@ -458,6 +1107,10 @@ Root-Is-Purelib: false
         version = env!("CARGO_PKG_VERSION"),
     );
 
+    if let Some(build_tag) = build_tag {
+        writeln!(wheel_file, "Build: {}", build_tag)?;
+    }
+
     for tag in tags {
         writeln!(wheel_file, "Tag: {}", tag)?;
     }
@@ -490,13 +1143,14 @@ del os
 }
 
 /// Wraps some boilerplate around error handling when calling python
-fn call_python<I, S>(python: &Path, args: I) -> Result<Output>
+fn call_python<I, S>(python: &Path, args: I, env: &BTreeMap<String, String>) -> Result<Output>
 where
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
 {
     Command::new(python)
         .args(args)
+        .envs(env)
         .output()
         .context(format!("Failed to run python at {:?}", &python))
 }
@@ -524,6 +1178,11 @@ fn cffi_header(crate_dir: &Path, target_dir: &Path, tempdir: &TempDir) -> Result
         let mut config = cbindgen::Config::from_root_or_default(crate_dir);
         config.defines = HashMap::new();
         config.include_guard = None;
+        // Expanding macros builds the crate in its default target directory, which is in the
+        // source tree, so cbindgen gets a temporary one unless `CARGO_EXPAND_TARGET_DIR` is set
+        if env::var_os("CARGO_EXPAND_TARGET_DIR").is_none() {
+            config.parse.clean = true;
+        }
 
         let bindings = cbindgen::Builder::new()
             .with_config(config)
@@ -549,12 +1208,15 @@ fn cffi_header(crate_dir: &Path, target_dir: &Path, tempdir: &TempDir) -> Result
 /// how to load the shared library without the header and then writes those instructions to a
 /// file called `ffi.py`. This `ffi.py` will expose an object called `ffi`. This object is used
 /// in `__init__.py` to load the shared library into a module called `lib`.
+///
+/// `env` are additional environment variables for python, e.g. from `[tool.maturin.env]`
 pub fn generate_cffi_declarations(
     crate_dir: &Path,
     target_dir: &Path,
     python: &Path,
+    env: &BTreeMap<String, String>,
 ) -> Result<String> {
-    let tempdir = tempdir()?;
+    let tempdir = tempdir_in(scratch_dir(target_dir)?)?;
     let header = cffi_header(crate_dir, target_dir, &tempdir)?;
 
     let ffi_py = tempdir.as_ref().join("ffi.py");
@@ -576,7 +1238,7 @@ recompiler.make_py_source(ffi, "ffi", r"{ffi_py}")
         header = header.display(),
     );
 
-    let output = call_python(python, ["-c", &cffi_invocation])?;
+    let output = call_python(python, ["-c", &cffi_invocation], env)?;
     let install_cffi = if !output.status.success() {
         // First, check whether the error was cffi not being installed
         let last_line = str::from_utf8(&output.stderr)?.lines().last().unwrap_or("");
@@ -587,6 +1249,7 @@ recompiler.make_py_source(ffi, "ffi", r"{ffi_py}")
             let output = call_python(
                 python,
                 ["-c", "import sys\nprint(sys.base_prefix != sys.prefix)"],
+                env,
             )?;
 
             match str::from_utf8(&output.stdout)?.trim() {
@@ -624,6 +1287,7 @@ recompiler.make_py_source(ffi, "ffi", r"{ffi_py}")
             "--disable-pip-version-check",
             "cffi",
         ],
+        env,
     )?;
     if !output.status.success() {
         bail!(
@@ -637,7 +1301,7 @@ recompiler.make_py_source(ffi, "ffi", r"{ffi_py}")
     println!("🎁 Installed cffi");
 
     // Try again
-    let output = call_python(python, ["-c", &cffi_invocation])?;
+    let output = call_python(python, ["-c", &cffi_invocation], env)?;
     handle_cffi_call_result(python, tempdir, &ffi_py, &output)
 }
 
@@ -666,6 +1330,38 @@ fn handle_cffi_call_result(
     }
 }
 
+/// Copies `source` to `target` and checks that the copy has the same contents, retrying once.
+/// Network filesystems and file syncing tools occasionally corrupt copies, which otherwise only
+/// shows up as baffling import errors.
+pub(crate) fn copy_verified(source: &Path, target: &Path) -> Result<()> {
+    let expected = Sha256::digest(fs::read(source)?);
+    for attempt in 0..2 {
+        debug!("Copying {} to {}", source.display(), target.display());
+        fs::copy(source, target).context(format!(
+            "Failed to copy {} to {}",
+            source.display(),
+            target.display()
+        ))?;
+        if Sha256::digest(fs::read(target)?) == expected {
+            return Ok(());
+        }
+        if attempt == 0 {
+            eprintln!(
+                "⚠️  Warning: The copy of {} at {} doesn't match the original, retrying",
+                source.display(),
+                target.display()
+            );
+            let _ = fs::remove_file(target);
+        }
+    }
+    bail!(
+        "Copying {} to {} produced a corrupted file twice, check the filesystem of {}",
+        source.display(),
+        target.display(),
+        target.parent().unwrap_or(target).display()
+    )
+}
+
 /// Copies the shared library into the module, which is the only extra file needed with bindings
 #[allow(clippy::too_many_arguments)]
 pub fn write_bindings_module(
@@ -700,15 +1396,15 @@ pub fn write_bindings_module(
             debug!("Removing {}", target.display());
             let _ = fs::remove_file(&target);
 
-            debug!("Copying {} to {}", artifact.display(), target.display());
-            fs::copy(artifact, &target).context(format!(
-                "Failed to copy {} to {}",
-                artifact.display(),
-                target.display()
-            ))?;
+            copy_verified(artifact, &target)?;
         } else {
-            write_python_part(writer, python_module, pyproject_toml)
-                .context("Failed to add the python module to the package")?;
+            write_python_part(
+                writer,
+                python_module,
+                &project_layout.extra_python_modules,
+                pyproject_toml,
+            )
+            .context("Failed to add the python module to the package")?;
 
             let relative = project_layout
                 .rust_module
@@ -758,26 +1454,28 @@ pub fn write_cffi_module(
     python: &Path,
     editable: bool,
     pyproject_toml: Option<&PyProjectToml>,
+    env: &BTreeMap<String, String>,
 ) -> Result<()> {
-    let cffi_declarations = generate_cffi_declarations(crate_dir, target_dir, python)?;
+    let cffi_declarations = generate_cffi_declarations(crate_dir, target_dir, python, env)?;
 
     let module;
 
     if let Some(python_module) = &project_layout.python_module {
         if !editable {
-            write_python_part(writer, python_module, pyproject_toml)
-                .context("Failed to add the python module to the package")?;
+            write_python_part(
+                writer,
+                python_module,
+                &project_layout.extra_python_modules,
+                pyproject_toml,
+            )
+            .context("Failed to add the python module to the package")?;
         }
 
         if editable {
             let base_path = python_module.join(module_name);
             fs::create_dir_all(&base_path)?;
             let target = base_path.join("native.so");
-            fs::copy(artifact, &target).context(format!(
-                "Failed to copy {} to {}",
-                artifact.display(),
-                target.display()
-            ))?;
+            copy_verified(artifact, &target)?;
             File::create(base_path.join("__init__.py"))?.write_all(cffi_init_file().as_bytes())?;
             File::create(base_path.join("ffi.py"))?.write_all(cffi_declarations.as_bytes())?;
         }
@@ -889,52 +1587,80 @@ if __name__ == '__main__':
 }
 
 /// Adds the python part of a mixed project to the writer,
+///
+/// The contents of `extra_python_modules` are merged into the package after `python_module`.
+/// A file in a later directory overrides the one with the same path in the earlier ones, e.g. a
+/// generated module replaces a handwritten placeholder.
 pub fn write_python_part(
     writer: &mut impl ModuleWriter,
     python_module: impl AsRef<Path>,
+    extra_python_modules: &[PathBuf],
     pyproject_toml: Option<&PyProjectToml>,
 ) -> Result<()> {
     let python_module = python_module.as_ref();
-    for absolute in WalkBuilder::new(python_module).hidden(false).build() {
-        let absolute = absolute?.into_path();
-        let relative = absolute
-            .strip_prefix(python_module.parent().unwrap())
-            .unwrap();
-        if absolute.is_dir() {
-            writer.add_directory(relative)?;
-        } else {
-            // Ignore native libraries from develop, if any
-            if let Some(extension) = relative.extension() {
-                if extension.to_string_lossy() == "so" {
-                    debug!("Ignoring native library {}", relative.display());
-                    continue;
+    // The files in the package with the file they are taken from, in the order they were found
+    let mut files: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut positions: HashMap<PathBuf, usize> = HashMap::new();
+    for module in iter::once(python_module).chain(extra_python_modules.iter().map(PathBuf::as_path))
+    {
+        let walker = WalkBuilder::new(module)
+            .hidden(false)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+        for absolute in walker {
+            let absolute = absolute?.into_path();
+            let relative = absolute
+                .strip_prefix(module.parent().unwrap())
+                .unwrap()
+                .to_path_buf();
+            if absolute.is_dir() {
+                writer.add_directory(&relative)?;
+            } else {
+                // Ignore native libraries from develop, if any
+                if let Some(extension) = relative.extension() {
+                    if extension.to_string_lossy() == "so" {
+                        debug!("Ignoring native library {}", relative.display());
+                        continue;
+                    }
+                }
+                match positions.get(&relative) {
+                    Some(&position) => {
+                        debug!(
+                            "{} overrides {}",
+                            absolute.display(),
+                            files[position].1.display()
+                        );
+                        files[position].1 = absolute;
+                    }
+                    None => {
+                        positions.insert(relative.clone(), files.len());
+                        files.push((relative, absolute));
+                    }
                 }
             }
-            writer
-                .add_file(relative, &absolute)
-                .context(format!("File to add file from {}", absolute.display()))?;
         }
     }
+    for (relative, absolute) in files {
+        writer
+            .add_file_with_permissions(&relative, &absolute, permissions_of(&absolute)?)
+            .context(format!("File to add file from {}", absolute.display()))?;
+    }
 
     // Include additional files
     if let Some(pyproject) = pyproject_toml {
         let pyproject_dir = python_module.parent().unwrap();
         if let Some(glob_patterns) = pyproject.include() {
-            for pattern in glob_patterns
+            let patterns: Vec<&str> = glob_patterns
                 .iter()
                 .filter_map(|glob_pattern| glob_pattern.targets(Format::Sdist))
+                .collect();
+            for source in include_matches(pyproject_dir, &patterns, pyproject.unmatched_include())?
             {
-                println!("📦 Including files matching \"{}\"", pattern);
-                for source in glob::glob(&pyproject_dir.join(pattern).to_string_lossy())
-                    .expect("No files found for pattern")
-                    .filter_map(Result::ok)
-                {
-                    let target = source.strip_prefix(pyproject_dir)?.to_path_buf();
-                    if source.is_dir() {
-                        writer.add_directory(target)?;
-                    } else {
-                        writer.add_file(target, source)?;
-                    }
+                let target = source.strip_prefix(pyproject_dir)?.to_path_buf();
+                if source.is_dir() {
+                    writer.add_directory(target)?;
+                } else {
+                    writer.add_file(target, source)?;
                 }
             }
         }
@@ -943,11 +1669,62 @@ pub fn write_python_part(
     Ok(())
 }
 
+/// The files and directories matching the include patterns relative to the pyproject.toml
+/// directory, where a `!` pattern removes the matches of the patterns before it. A pattern that
+/// matches nothing is reported as configured with `unmatched`, since it's likely a typo that
+/// would silently drop files from the package.
+pub(crate) fn include_matches(
+    pyproject_dir: &Path,
+    patterns: &[&str],
+    unmatched: UnmatchedInclude,
+) -> Result<Vec<PathBuf>> {
+    check_negations(patterns)?;
+    let mut all_matches = Vec::new();
+    for (pattern, matches) in PathGlobs::new(pyproject_dir, patterns)?.matches()? {
+        println!("📦 Including files matching \"{}\"", pattern);
+        if matches.is_empty() {
+            match unmatched {
+                UnmatchedInclude::Warn => eprintln!(
+                    "⚠️  Warning: The include pattern \"{}\" didn't match any files. \
+                    Set `unmatched-include = \"error\"` in `[tool.maturin]` to fail the build instead",
+                    pattern
+                ),
+                UnmatchedInclude::Error => bail!(ConfigError::new(format!(
+                    "The include pattern \"{}\" didn't match any files in {}",
+                    pattern,
+                    pyproject_dir.display()
+                ))),
+            }
+        }
+        for path in matches {
+            if !all_matches.contains(&path) {
+                all_matches.push(path);
+            }
+        }
+    }
+    Ok(all_matches)
+}
+
+/// The permissions of a file from the python source in the wheel. Like in the sdist, only
+/// whether the file is executable is kept, e.g. for helper scripts shipped in the package.
+#[cfg(target_family = "unix")]
+fn permissions_of(source: &Path) -> Result<u32> {
+    let mode = fs::metadata(source)?.permissions().mode();
+    Ok(if mode & 0o111 != 0 { 0o755 } else { 0o644 })
+}
+
+/// The permissions of a file from the python source in the wheel, windows has no executable bit
+#[cfg(not(target_family = "unix"))]
+fn permissions_of(_source: &Path) -> Result<u32> {
+    Ok(0o644)
+}
+
 /// Creates the .dist-info directory and fills it with all metadata files except RECORD
 pub fn write_dist_info(
     writer: &mut impl ModuleWriter,
     metadata21: &Metadata21,
     tags: &[String],
+    build_tag: Option<&str>,
 ) -> Result<()> {
     let dist_info_dir = metadata21.get_dist_info_dir();
 
@@ -958,7 +1735,10 @@ pub fn write_dist_info(
         metadata21.to_file_contents()?.as_bytes(),
     )?;
 
-    writer.add_bytes(&dist_info_dir.join("WHEEL"), wheel_file(tags)?.as_bytes())?;
+    writer.add_bytes(
+        &dist_info_dir.join("WHEEL"),
+        wheel_file(tags, build_tag)?.as_bytes(),
+    )?;
 
     let mut entry_points = String::new();
     if !metadata21.scripts.is_empty() {
@@ -978,71 +1758,288 @@ pub fn write_dist_info(
     }
 
     if !metadata21.license_files.is_empty() {
-        let license_files_dir = dist_info_dir.join("license_files");
-        writer.add_directory(&license_files_dir)?;
-        for path in &metadata21.license_files {
-            let filename = path.file_name().with_context(|| {
-                format!("missing file name for license file {}", path.display())
-            })?;
-            writer.add_file(license_files_dir.join(filename), path)?;
+        // See https://peps.python.org/pep-0639/#add-license-file-field
+        let licenses_dir = dist_info_dir.join("licenses");
+        writer.add_directory(&licenses_dir)?;
+        for (path, name) in metadata21
+            .license_files
+            .iter()
+            .zip(metadata21.license_file_names())
+        {
+            writer.add_file(licenses_dir.join(name), path)?;
         }
     }
 
     Ok(())
 }
 
-/// If any, copies the data files from the data directory, resolving symlinks to their source.
+/// How deep the data directory may be nested, to stop at symlinks pointing to large trees
+const MAX_DATA_DIR_DEPTH: usize = 64;
+
+/// What to do with scripts without a file extension in the data directory, which windows
+/// doesn't know how to run
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScriptWrappers {
+    /// The wheel isn't for windows, add the scripts as they are
+    Skip,
+    /// Warn that the scripts are unusable on windows
+    Warn,
+    /// Add a `.bat` launcher next to each python script
+    Generate,
+}
+
+/// Launches a python script from the scripts directory with the python of the same environment,
+/// falling back to the python on PATH
+fn bat_wrapper(script_name: &str) -> String {
+    format!(
+        "@echo off\r\n\
+        if exist \"%~dp0python.exe\" (\r\n\
+        \x20   \"%~dp0python.exe\" \"%~dp0{name}\" %*\r\n\
+        ) else (\r\n\
+        \x20   python \"%~dp0{name}\" %*\r\n\
+        )\r\n",
+        name = script_name
+    )
+}
+
+/// Handles a script without a file extension according to `script_wrappers`
+fn add_script_wrapper(
+    writer: &mut impl ModuleWriter,
+    script_wrappers: ScriptWrappers,
+    relative: &Path,
+    source: &Path,
+) -> Result<()> {
+    if script_wrappers == ScriptWrappers::Skip {
+        return Ok(());
+    }
+    let script_name = relative.file_name().unwrap().to_string_lossy();
+    let has_launcher = ["bat", "cmd", "exe"]
+        .iter()
+        .any(|ext| source.with_extension(ext).is_file());
+    if has_launcher {
+        return Ok(());
+    }
+    let mut first_line = String::new();
+    io::BufRead::read_line(
+        &mut io::BufReader::new(File::open(source)?),
+        &mut first_line,
+    )
+    .unwrap_or_default();
+    let is_python = first_line.starts_with("#!") && first_line.contains("python");
+    if script_wrappers == ScriptWrappers::Generate && is_python {
+        debug!("Adding a .bat launcher for {}", relative.display());
+        writer.add_bytes(
+            relative.with_extension("bat"),
+            bat_wrapper(&script_name).as_bytes(),
+        )?;
+    } else if script_wrappers == ScriptWrappers::Generate {
+        eprintln!(
+            "⚠️  Warning: The script {} has no file extension and isn't a python script, \
+            it can't be run on windows",
+            script_name
+        );
+    } else {
+        eprintln!(
+            "⚠️  Warning: The script {} has no file extension, it can't be run on windows. \
+            Add a .bat or .exe launcher or set `script-wrappers = true` in `[tool.maturin]`",
+            script_name
+        );
+    }
+    Ok(())
+}
+
+/// If any, copies the data files from the data directories, resolving symlinks to their source.
 /// We resolve symlinks since we require this rather rigid structure while people might need
 /// to save or generate the data in other places
 ///
+/// The contents of all directories are merged into the data directory of the wheel, which is
+/// named after the first one. A file that exists in more than one of them is an error.
+///
+/// Files and directories matching the `excludes` are skipped.
+///
 /// See https://peps.python.org/pep-0427/#file-contents
-pub fn add_data(writer: &mut impl ModuleWriter, data: Option<&Path>) -> Result<()> {
-    let possible_data_dir_names = ["data", "scripts", "headers", "purelib", "platlib"];
-    if let Some(data) = data {
-        for subdir in fs::read_dir(data).context("Failed to read data dir")? {
-            let subdir = subdir?;
-            let dir_name = subdir
-                .file_name()
-                .to_str()
-                .context("Invalid data dir name")?
-                .to_string();
-            if !subdir.path().is_dir() || !possible_data_dir_names.contains(&dir_name.as_str()) {
-                bail!(
-                    "Invalid data dir entry {}. Possible are directories named {}",
-                    subdir.path().display(),
-                    possible_data_dir_names.join(", ")
-                );
+pub fn add_data(
+    writer: &mut impl ModuleWriter,
+    data: &[PathBuf],
+    excludes: Option<&Override>,
+    script_wrappers: ScriptWrappers,
+) -> Result<()> {
+    let target_dir = match data.first().and_then(|first| first.file_name()) {
+        Some(name) => PathBuf::from(name),
+        None => return Ok(()),
+    };
+    let mut added = HashMap::new();
+    for data in data {
+        add_data_dir(
+            writer,
+            data,
+            &target_dir,
+            excludes,
+            script_wrappers,
+            &mut added,
+        )?;
+    }
+    Ok(())
+}
+
+/// Copies the data directories into the source distribution at their location relative to the
+/// pyproject.toml, so `[tool.maturin.data]` also points to them when building from the sdist.
+/// Directories outside of the project, such as generated data in the target directory or paths
+/// leaving it with `..`, are skipped with a warning.
+pub(crate) fn add_sdist_data(
+    writer: &mut impl ModuleWriter,
+    data: &[PathBuf],
+    root_dir: &Path,
+    pyproject_dir: &Path,
+    excludes: Option<&Override>,
+) -> Result<()> {
+    let pyproject_dir = normalize_lexically(pyproject_dir);
+    for data in data {
+        match normalize_lexically(data).strip_prefix(&pyproject_dir) {
+            Ok(relative) => add_data_dir(
+                writer,
+                data,
+                &root_dir.join(relative),
+                excludes,
+                ScriptWrappers::Skip,
+                &mut HashMap::new(),
+            )?,
+            Err(_) => eprintln!(
+                "⚠️  Warning: The data directory {} is outside of the project, \
+                it's not included in the source distribution",
+                data.display()
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Resolves `.` and `..` in the path without accessing the filesystem
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
             }
-            debug!("Adding data from {}", subdir.path().display());
-            (|| {
-                for file in WalkBuilder::new(subdir.path())
-                    .standard_filters(false)
-                    .build()
-                {
-                    let file = file?;
-                    let relative = file.path().strip_prefix(data.parent().unwrap()).unwrap();
-
-                    if file.path_is_symlink() {
-                        // Copy the actual file contents, not the link, so that you can create a
-                        // data directory by joining different data sources
-                        let source = fs::read_link(file.path())?;
-                        writer.add_file(relative, source.parent().unwrap())?;
-                    } else if file.path().is_file() {
-                        writer.add_file(relative, file.path())?;
-                    } else if file.path().is_dir() {
-                        writer.add_directory(relative)?;
-                    } else {
-                        bail!("Can't handle data dir entry {}", file.path().display());
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Adds the contents of the data directory `data` below `target_dir`, `added` maps the files
+/// added so far to the data directory they came from
+fn add_data_dir(
+    writer: &mut impl ModuleWriter,
+    data: &Path,
+    target_dir: &Path,
+    excludes: Option<&Override>,
+    script_wrappers: ScriptWrappers,
+    added: &mut HashMap<PathBuf, PathBuf>,
+) -> Result<()> {
+    if !data.is_dir() {
+        bail!("No such data directory {}", data.display());
+    }
+    let possible_data_dir_names = ["data", "scripts", "headers", "purelib", "platlib"];
+    for subdir in fs::read_dir(data).context("Failed to read data dir")? {
+        let subdir = subdir?;
+        let dir_name = subdir
+            .file_name()
+            .to_str()
+            .context("Invalid data dir name")?
+            .to_string();
+        if !subdir.path().is_dir() || !possible_data_dir_names.contains(&dir_name.as_str()) {
+            bail!(
+                "Invalid data dir entry {}. Possible are directories named {}",
+                subdir.path().display(),
+                possible_data_dir_names.join(", ")
+            );
+        }
+        debug!("Adding data from {}", subdir.path().display());
+        let excludes = excludes.cloned();
+        (|| {
+            // Symlinks are followed so that you can create a data directory by joining
+            // different data sources, the walker errors on symlink loops
+            for file in WalkBuilder::new(subdir.path())
+                .standard_filters(false)
+                .follow_links(true)
+                .filter_entry(move |entry| match &excludes {
+                    // Don't descend into excluded directories, e.g. a virtualenv
+                    Some(excludes) => {
+                        let is_dir = entry.file_type().map_or(false, |ty| ty.is_dir());
+                        !excludes.matched(entry.path(), is_dir).is_whitelist()
                     }
+                    None => true,
+                })
+                .build()
+            {
+                let file = file?;
+                if file.depth() > MAX_DATA_DIR_DEPTH {
+                    bail!(
+                        "{} is nested more than {} directories deep",
+                        file.path().display(),
+                        MAX_DATA_DIR_DEPTH
+                    );
                 }
-                Ok(())
-            })()
-            .with_context(|| format!("Failed to include data from {}", data.display()))?
-        }
+                let relative = target_dir.join(file.path().strip_prefix(data).unwrap());
+
+                if file.path().is_file() {
+                    if let Some(other) = added.insert(relative.clone(), data.to_path_buf()) {
+                        bail!(
+                            "The data file {} exists in both {} and {}",
+                            file.path().strip_prefix(data).unwrap().display(),
+                            other.display(),
+                            data.display()
+                        );
+                    }
+                    writer.add_file(&relative, file.path())?;
+                    if dir_name == "scripts" && file.depth() == 1 && relative.extension().is_none()
+                    {
+                        add_script_wrapper(writer, script_wrappers, &relative, file.path())?;
+                    }
+                } else if file.path().is_dir() {
+                    writer.add_directory(&relative)?;
+                } else {
+                    bail!("Can't handle data dir entry {}", file.path().display());
+                }
+            }
+            Ok(())
+        })()
+        .with_context(|| format!("Failed to include data from {}", data.display()))?
     }
     Ok(())
 }
 
+/// The metadata of the `foo` 0.1.0 package the tests build their wheels for
+#[cfg(test)]
+pub(crate) fn test_metadata() -> Metadata21 {
+    Metadata21 {
+        name: "foo".to_string(),
+        version: "0.1.0".to_string(),
+        ..Default::default()
+    }
+}
+
+/// A writer for a wheel with a single tag and without build tag or excludes in `wheel_dir`
+#[cfg(test)]
+pub(crate) fn test_wheel_writer(
+    wheel_dir: &Path,
+    metadata: &Metadata21,
+    tag: &str,
+) -> Result<WheelWriter> {
+    WheelWriter::new(
+        tag,
+        wheel_dir,
+        metadata,
+        &[tag.to_string()],
+        None,
+        None,
+        true,
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use ignore::overrides::OverrideBuilder;
@@ -1057,7 +2054,7 @@ mod tests {
 
         // No excludes
         let tmp_dir = TempDir::new()?;
-        let mut writer = SDistWriter::new(&tmp_dir, &metadata, None)?;
+        let mut writer = SDistWriter::new(&tmp_dir, &metadata, None, true, TarFormat::Pax)?;
         assert!(writer.files.is_empty());
         writer.add_bytes_with_permissions("test", &[], perm)?;
         assert_eq!(writer.files.len(), 1);
@@ -1069,7 +2066,13 @@ mod tests {
         let mut excludes = OverrideBuilder::new(&tmp_dir);
         excludes.add("test*")?;
         excludes.add("!test2")?;
-        let mut writer = SDistWriter::new(&tmp_dir, &metadata, Some(excludes.build()?))?;
+        let mut writer = SDistWriter::new(
+            &tmp_dir,
+            &metadata,
+            Some(excludes.build()?),
+            true,
+            TarFormat::Pax,
+        )?;
         writer.add_bytes_with_permissions("test1", &[], perm)?;
         writer.add_bytes_with_permissions("test3", &[], perm)?;
         assert!(writer.files.is_empty());
@@ -1082,4 +2085,620 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn sdist_writer_long_paths() -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = test_metadata();
+        let long_path = format!(
+            "foo-0.1.0/local_dependencies/{}/src/{}.rs",
+            "nested/".repeat(30),
+            "x".repeat(120)
+        );
+        assert!(long_path.len() > 255);
+        // In the order they are sorted in the archive
+        let names = ["foo-0.1.0/données/café.txt", long_path.as_str()];
+        for tar_format in [TarFormat::Pax, TarFormat::Gnu] {
+            let tmp_dir = TempDir::new()?;
+            let mut writer = SDistWriter::new(&tmp_dir, &metadata, None, true, tar_format)?;
+            for name in names {
+                writer.add_bytes(name, name.as_bytes())?;
+            }
+            let path = writer.finish()?;
+            let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(File::open(path)?));
+            let mut entries = Vec::new();
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents)?;
+                entries.push((entry.path()?.to_str().unwrap().to_string(), contents));
+            }
+            let expected: Vec<_> = names
+                .iter()
+                .map(|name| (name.to_string(), name.to_string()))
+                .collect();
+            assert_eq!(entries, expected, "{:?}", tar_format);
+        }
+        assert_eq!(pax_record("path", "foo"), "12 path=foo\n");
+        // Two digits for the length would make the record 100 bytes long, so it needs three
+        let record = pax_record("path", &"x".repeat(91));
+        assert_eq!(record.len(), 101);
+        assert!(record.starts_with("101 path="));
+        Ok(())
+    }
+
+    #[test]
+    fn sdist_writer_reproducible() -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = test_metadata();
+        let source_dir = TempDir::new()?;
+        let source = source_dir.path().join("lib.rs");
+        let mut sdists = Vec::new();
+        for reversed in [false, true] {
+            // Rewriting the source and a different order must not change the archive, the
+            // entries and the gzip header get fixed timestamps
+            fs::write(&source, "pub fn foo() {}\n")?;
+            let tmp_dir = TempDir::new()?;
+            let mut writer = SDistWriter::new(&tmp_dir, &metadata, None, true, TarFormat::Pax)?;
+            if reversed {
+                writer.add_file("foo-0.1.0/src/lib.rs", &source)?;
+                writer.add_bytes("foo-0.1.0/PKG-INFO", b"Name: foo\n")?;
+            } else {
+                writer.add_bytes("foo-0.1.0/PKG-INFO", b"Name: foo\n")?;
+                writer.add_file("foo-0.1.0/src/lib.rs", &source)?;
+            }
+            let path = writer.finish()?;
+            sdists.push(fs::read(path)?);
+        }
+        assert_eq!(sdists[0], sdists[1]);
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(sdists[0].as_slice()));
+        let entries = archive
+            .entries()?
+            .map(|entry| {
+                let entry = entry?;
+                Ok((entry.path()?.into_owned(), entry.header().mtime()?))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        assert_eq!(
+            entries,
+            [
+                (PathBuf::from("foo-0.1.0/PKG-INFO"), sdist_mtime()),
+                (PathBuf::from("foo-0.1.0/src/lib.rs"), sdist_mtime())
+            ]
+        );
+        let mtime = u32::from_le_bytes(sdists[0][4..8].try_into()?);
+        assert_eq!(u64::from(mtime), source_date_epoch().unwrap_or_default());
+        assert_eq!(sdists[0][9], GZIP_OS_UNKNOWN);
+        Ok(())
+    }
+
+    #[test]
+    fn wheel_file_build_tag() -> Result<(), Box<dyn std::error::Error>> {
+        let tags = vec!["py3-none-any".to_string()];
+        let wheel = wheel_file(&tags, None)?;
+        assert!(!wheel.contains("Build:"));
+        let wheel = wheel_file(&tags, Some("1"))?;
+        assert!(wheel.contains("Build: 1\nTag: py3-none-any\n"));
+
+        let metadata = test_metadata();
+        let tmp_dir = TempDir::new()?;
+        let writer = WheelWriter::new(
+            "py3-none-any",
+            tmp_dir.path(),
+            &metadata,
+            &tags,
+            Some("1"),
+            None,
+            true,
+        )?;
+        let wheel_path = writer.finish()?;
+        assert_eq!(
+            wheel_path.file_name().unwrap(),
+            "foo-0.1.0-1-py3-none-any.whl"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn record_csv_escaping() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(csv_escape("foo/bar.py"), "foo/bar.py");
+        assert_eq!(csv_escape("foo/a,b.py"), "\"foo/a,b.py\"");
+        assert_eq!(csv_escape("foo/\"a\".py"), "\"foo/\"\"a\"\".py\"");
+
+        let metadata = test_metadata();
+        let names = [
+            "foo/a,b.py",
+            "foo/\"quoted\".py",
+            "foo/\"a\",\"b\".py",
+            "foo/new\nline.py",
+        ];
+        let tmp_dir = TempDir::new()?;
+        let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+        for name in names {
+            writer.add_bytes(name, b"")?;
+        }
+        let wheel_path = writer.finish()?;
+        assert_eq!(
+            crate::verify_distribution(&wheel_path)?,
+            Vec::<String>::new()
+        );
+
+        let mut writer = PathWriter::from_path(tmp_dir.path());
+        writer.add_directory(metadata.get_dist_info_dir())?;
+        writer.add_directory("foo")?;
+        writer.add_bytes("foo/a,b.py", b"")?;
+        writer.write_record(&metadata)?;
+        let record = fs::read_to_string(
+            tmp_dir
+                .path()
+                .join(metadata.get_dist_info_dir())
+                .join("RECORD"),
+        )?;
+        assert_eq!(
+            crate::verify::parse_csv(&record).unwrap()[0].1[0],
+            "foo/a,b.py"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unicode_normalization() -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = test_metadata();
+        // "café" with a combining acute accent, as created on macOS
+        let nfd = "foo/cafe\u{301}.py";
+        let nfc = "foo/caf\u{e9}.py";
+        let tmp_dir = TempDir::new()?;
+        let tags = vec!["py3-none-any".to_string()];
+        for (normalize_unicode, expected) in [(true, nfc), (false, nfd)] {
+            let mut writer = WheelWriter::new(
+                "py3-none-any",
+                tmp_dir.path(),
+                &metadata,
+                &tags,
+                None,
+                None,
+                normalize_unicode,
+            )?;
+            writer.add_bytes(nfd, b"")?;
+            let wheel_path = writer.finish()?;
+            let wheel = WheelReader::open(&wheel_path)?;
+            assert!(wheel.names().any(|name| name == expected));
+            let record = wheel.read_dist_info("RECORD")?;
+            assert!(record.lines().any(|line| line.starts_with(expected)));
+
+            let mut writer =
+                SDistWriter::new(&tmp_dir, &metadata, None, normalize_unicode, TarFormat::Pax)?;
+            writer.add_bytes(nfd, b"")?;
+            let sdist_path = writer.finish()?;
+            let mut archive =
+                tar::Archive::new(flate2::read::GzDecoder::new(File::open(sdist_path)?));
+            let names = archive
+                .entries()?
+                .map(|entry| Ok(entry?.path()?.to_str().unwrap().to_string()))
+                .collect::<io::Result<Vec<String>>>()?;
+            assert_eq!(names, [expected]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn add_sdist_data_outside_project() -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = test_metadata();
+        let src_dir = TempDir::new()?;
+        let project = src_dir.path().join("project");
+        fs::create_dir_all(project.join("foo.data").join("data"))?;
+        fs::create_dir_all(project.join("sub"))?;
+        fs::write(project.join("foo.data").join("data").join("a.txt"), "")?;
+        fs::create_dir_all(src_dir.path().join("outside.data").join("data"))?;
+        fs::write(
+            src_dir
+                .path()
+                .join("outside.data")
+                .join("data")
+                .join("b.txt"),
+            "",
+        )?;
+
+        let tmp_dir = TempDir::new()?;
+        let mut writer = SDistWriter::new(&tmp_dir, &metadata, None, true, TarFormat::Pax)?;
+        let data = [
+            project.join("sub").join("..").join("foo.data"),
+            project.join("..").join("outside.data"),
+        ];
+        add_sdist_data(&mut writer, &data, Path::new("foo-0.1.0"), &project, None)?;
+        let sdist_path = writer.finish()?;
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(File::open(sdist_path)?));
+        let names = archive
+            .entries()?
+            .map(|entry| Ok(entry?.path()?.to_str().unwrap().to_string()))
+            .collect::<io::Result<Vec<String>>>()?;
+        assert_eq!(names, ["foo-0.1.0/foo.data/data/a.txt"]);
+
+        // Missing data directories are only an error when they're added
+        let mut writer = SDistWriter::new(&tmp_dir, &metadata, None, true, TarFormat::Pax)?;
+        let err = add_sdist_data(
+            &mut writer,
+            &[project.join("generated.data")],
+            Path::new("foo-0.1.0"),
+            &project,
+            None,
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("No such data directory"));
+        Ok(())
+    }
+
+    #[test]
+    fn wheel_writer_duplicates() -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = test_metadata();
+        let tmp_dir = TempDir::new()?;
+        let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+        writer.add_bytes("foo/__init__.py", b"a = 1")?;
+        // Identical contents are skipped
+        writer.add_bytes("foo/__init__.py", b"a = 1")?;
+        assert_eq!(
+            writer
+                .record
+                .iter()
+                .filter(|(name, _, _)| name == "foo/__init__.py")
+                .count(),
+            1
+        );
+        // Different contents are an error
+        let err = writer.add_bytes("foo/__init__.py", b"a = 2").unwrap_err();
+        assert!(err.to_string().contains("Conflicting files in the wheel"));
+        Ok(())
+    }
+
+    #[test]
+    fn add_data_excludes_and_symlink_loops() -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = test_metadata();
+        let src_dir = TempDir::new()?;
+        let data = src_dir.path().join("foo.data");
+        fs::create_dir_all(data.join("data").join("venv").join("lib"))?;
+        fs::write(data.join("data").join("a.txt"), "")?;
+        fs::write(data.join("data").join("venv").join("lib").join("b.py"), "")?;
+
+        let mut excludes = OverrideBuilder::new(src_dir.path());
+        excludes.add("foo.data/data/venv")?;
+        let excludes = excludes.build()?;
+        let tmp_dir = TempDir::new()?;
+        let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+        add_data(
+            &mut writer,
+            &[data.clone()],
+            Some(&excludes),
+            ScriptWrappers::Skip,
+        )?;
+        let files: Vec<&str> = writer
+            .record
+            .iter()
+            .map(|(name, _, _)| name.as_str())
+            .filter(|name| name.starts_with("foo.data/"))
+            .collect();
+        assert_eq!(files, ["foo.data/data/a.txt"]);
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("..", data.join("data").join("loop"))?;
+            let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+            let err = add_data(&mut writer, &[data], None, ScriptWrappers::Skip).unwrap_err();
+            assert!(format!("{:?}", err).contains("File system loop found"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn add_data_multiple_sources() -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = test_metadata();
+        let src_dir = TempDir::new()?;
+        let data = src_dir.path().join("foo.data");
+        let generated = src_dir.path().join("target").join("data");
+        fs::create_dir_all(data.join("data"))?;
+        fs::create_dir_all(generated.join("headers"))?;
+        fs::write(data.join("data").join("a.txt"), "")?;
+        fs::write(generated.join("headers").join("foo.h"), "")?;
+
+        let tmp_dir = TempDir::new()?;
+        let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+        add_data(
+            &mut writer,
+            &[data.clone(), generated.clone()],
+            None,
+            ScriptWrappers::Skip,
+        )?;
+        let mut files: Vec<&str> = writer
+            .record
+            .iter()
+            .map(|(name, _, _)| name.as_str())
+            .filter(|name| name.starts_with("foo.data/"))
+            .collect();
+        files.sort_unstable();
+        assert_eq!(files, ["foo.data/data/a.txt", "foo.data/headers/foo.h"]);
+
+        // The same file in two sources is a conflict, even with the same contents
+        fs::create_dir_all(generated.join("data"))?;
+        fs::write(generated.join("data").join("a.txt"), "")?;
+        let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+        let err =
+            add_data(&mut writer, &[data, generated], None, ScriptWrappers::Skip).unwrap_err();
+        assert!(format!("{:?}", err).contains("exists in both"));
+        Ok(())
+    }
+
+    #[test]
+    fn add_data_script_wrappers() -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = test_metadata();
+        let src_dir = TempDir::new()?;
+        let scripts = src_dir.path().join("foo.data").join("scripts");
+        fs::create_dir_all(&scripts)?;
+        fs::write(scripts.join("pyscript"), "#!python\nprint('hi')\n")?;
+        fs::write(scripts.join("shscript"), "#!/bin/sh\necho hi\n")?;
+        fs::write(scripts.join("launched"), "#!python\nprint('hi')\n")?;
+        fs::write(scripts.join("launched.exe"), "")?;
+
+        let tmp_dir = TempDir::new()?;
+        let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+        add_data(
+            &mut writer,
+            &[src_dir.path().join("foo.data")],
+            None,
+            ScriptWrappers::Generate,
+        )?;
+        let mut files: Vec<&str> = writer
+            .record
+            .iter()
+            .map(|(name, _, _)| name.as_str())
+            .filter(|name| name.starts_with("foo.data/"))
+            .collect();
+        files.sort_unstable();
+        assert_eq!(
+            files,
+            [
+                "foo.data/scripts/launched",
+                "foo.data/scripts/launched.exe",
+                "foo.data/scripts/pyscript",
+                "foo.data/scripts/pyscript.bat",
+                "foo.data/scripts/shscript"
+            ]
+        );
+        assert!(bat_wrapper("pyscript").contains("\"%~dp0python.exe\" \"%~dp0pyscript\" %*\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn write_python_part_multiple_sources() -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = test_metadata();
+        let src_dir = TempDir::new()?;
+        let python = src_dir.path().join("python").join("foo");
+        let generated = src_dir.path().join("generated").join("foo");
+        fs::create_dir_all(python.join("sub"))?;
+        fs::create_dir_all(generated.join("sub"))?;
+        fs::write(python.join("__init__.py"), "")?;
+        fs::write(python.join("sub").join("b.py"), "")?;
+        fs::write(generated.join("sub").join("a.py"), "")?;
+        fs::write(generated.join("_gen.py"), "")?;
+
+        let tmp_dir = TempDir::new()?;
+        let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+        write_python_part(&mut writer, &python, &[generated.clone()], None)?;
+        let files: Vec<&str> = writer
+            .record
+            .iter()
+            .map(|(name, _, _)| name.as_str())
+            .filter(|name| name.starts_with("foo/"))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "foo/__init__.py",
+                "foo/sub/b.py",
+                "foo/_gen.py",
+                "foo/sub/a.py"
+            ]
+        );
+
+        // A file in a later python source overrides the earlier one
+        fs::write(generated.join("__init__.py"), "from ._gen import *\n")?;
+        let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+        write_python_part(&mut writer, &python, &[generated], None)?;
+        let files: Vec<(&str, usize)> = writer
+            .record
+            .iter()
+            .map(|(name, _, size)| (name.as_str(), *size))
+            .filter(|(name, _)| name.starts_with("foo/"))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("foo/__init__.py", 20),
+                ("foo/sub/b.py", 0),
+                ("foo/_gen.py", 0),
+                ("foo/sub/a.py", 0)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn write_python_part_keeps_executable_bit() -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = test_metadata();
+        let src_dir = TempDir::new()?;
+        let python = src_dir.path().join("foo");
+        fs::create_dir_all(&python)?;
+        fs::write(python.join("__init__.py"), "")?;
+        fs::write(python.join("helper.sh"), "#!/bin/sh\n")?;
+        fs::set_permissions(
+            python.join("helper.sh"),
+            std::fs::Permissions::from_mode(0o775),
+        )?;
+
+        let tmp_dir = TempDir::new()?;
+        let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+        write_python_part(&mut writer, &python, &[], None)?;
+        let wheel_path = writer.finish()?;
+
+        let mut archive = zip::ZipArchive::new(File::open(wheel_path)?)?;
+        let mode = |archive: &mut zip::ZipArchive<File>, name: &str| {
+            archive.by_name(name).unwrap().unix_mode().unwrap() & 0o777
+        };
+        assert_eq!(mode(&mut archive, "foo/__init__.py"), 0o644);
+        assert_eq!(mode(&mut archive, "foo/helper.sh"), 0o755);
+        Ok(())
+    }
+
+    #[test]
+    fn wheel_writer_duplicate_contents() -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = test_metadata();
+        let large = vec![42; DUPLICATE_CONTENTS_MIN_SIZE];
+        for skip_duplicate_contents in [false, true] {
+            let tmp_dir = TempDir::new()?;
+            let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+            writer.set_skip_duplicate_contents(skip_duplicate_contents);
+            writer.add_bytes("foo/__init__.py", b"")?;
+            writer.add_bytes("foo/sub/__init__.py", b"")?;
+            writer.add_bytes("foo/model.bin", &large)?;
+            writer.add_bytes("foo-0.1.0.data/data/model.bin", &large)?;
+            let files: Vec<&str> = writer
+                .record
+                .iter()
+                .map(|(name, _, _)| name.as_str())
+                .filter(|name| !name.starts_with("foo-0.1.0.dist-info/"))
+                .collect();
+            // Small files are never skipped
+            let mut expected = vec!["foo/__init__.py", "foo/sub/__init__.py", "foo/model.bin"];
+            if !skip_duplicate_contents {
+                expected.push("foo-0.1.0.data/data/model.bin");
+            }
+            assert_eq!(files, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn wheel_writer_zip_config() -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = test_metadata();
+        let tags = vec!["py3-none-any".to_string()];
+        let config = WheelConfig {
+            zip_comment: Some("build 1234 of https://example.com/foo".to_string()),
+            extra_fields: vec![crate::pyproject_toml::ZipExtraField {
+                id: 0xcafe,
+                data: "build-id=1234".to_string(),
+            }],
+            align: Some(4096),
+        };
+        let tmp_dir = TempDir::new()?;
+        let mut writer = WheelWriter::with_config(
+            "py3-none-any",
+            tmp_dir.path(),
+            &metadata,
+            &tags,
+            None,
+            None,
+            true,
+            config,
+        )?;
+        writer.add_bytes("foo/__init__.py", b"print('hi')")?;
+        let wheel_path = writer.finish()?;
+
+        let mut archive = zip::ZipArchive::new(File::open(&wheel_path)?)?;
+        assert_eq!(archive.comment(), b"build 1234 of https://example.com/foo");
+        let mut extra_field = 0xcafe_u16.to_le_bytes().to_vec();
+        extra_field.extend(13_u16.to_le_bytes());
+        extra_field.extend(b"build-id=1234");
+        for i in 0..archive.len() {
+            let file = archive.by_index(i)?;
+            assert_eq!(file.data_start() % 4096, 0, "{}", file.name());
+            assert_eq!(file.extra_data(), extra_field, "{}", file.name());
+        }
+        // The metadata and RECORD are still valid
+        assert!(WheelReader::open(&wheel_path)?.verify_record()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn include_matches_unmatched() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TempDir::new()?;
+        fs::create_dir_all(dir.path().join("data"))?;
+        fs::write(dir.path().join("data").join("a.json"), "{}")?;
+
+        let matches = include_matches(dir.path(), &["data/*.json"], UnmatchedInclude::Error)?;
+        assert_eq!(matches, [dir.path().join("data").join("a.json")]);
+        // A typo only warns by default
+        let matches = include_matches(
+            dir.path(),
+            &["data/*.json", "dta/*.json"],
+            UnmatchedInclude::Warn,
+        )?;
+        assert_eq!(matches, [dir.path().join("data").join("a.json")]);
+        let err =
+            include_matches(dir.path(), &["dta/*.json"], UnmatchedInclude::Error).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("The include pattern \"dta/*.json\" didn't match any files"));
+        assert!(err.downcast_ref::<ConfigError>().is_some());
+        // An invalid pattern is an error instead of a panic
+        assert!(include_matches(dir.path(), &["data/[a"], UnmatchedInclude::Warn).is_err());
+        // Negations remove matches of the patterns before them
+        let matches = include_matches(
+            dir.path(),
+            &["data/*", "!data/*.json"],
+            UnmatchedInclude::Warn,
+        )?;
+        assert!(matches.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn wheel_reader_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = test_metadata();
+        let tmp_dir = TempDir::new()?;
+        let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+        writer.add_bytes("foo/__init__.py", b"print('hi')")?;
+        let wheel_path = writer.finish()?;
+
+        let wheel = WheelReader::open(&wheel_path)?;
+        assert_eq!(wheel.dist_info_dir()?, "foo-0.1.0.dist-info");
+        assert_eq!(wheel.read("foo/__init__.py"), Some(&b"print('hi')"[..]));
+        assert_eq!(wheel.metadata()?.name, "foo");
+        assert!(wheel
+            .wheel_info()?
+            .contains(&("Tag".to_string(), "py3-none-any".to_string())));
+        assert!(wheel.verify_record()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn publish_detects_concurrent_writes() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let metadata = test_metadata();
+        let sdist_path = tmp_dir.path().join("foo-0.1.0.tar.gz");
+
+        // Rebuilding replaces the previous file
+        fs::write(&sdist_path, "outdated")?;
+        let mut writer = SDistWriter::new(&tmp_dir, &metadata, None, true, TarFormat::Pax)?;
+        writer.add_bytes("foo-0.1.0/PKG-INFO", b"")?;
+        assert_eq!(writer.finish()?, sdist_path);
+        assert_ne!(fs::read(&sdist_path)?, b"outdated");
+
+        // Another process wrote a different file while we were building
+        let mut writer = SDistWriter::new(&tmp_dir, &metadata, None, true, TarFormat::Pax)?;
+        writer.add_bytes("foo-0.1.0/PKG-INFO", b"")?;
+        fs::write(&sdist_path, "concurrent")?;
+        let err = writer.finish().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("was written by another maturin process"));
+        assert_eq!(fs::read(&sdist_path)?, b"concurrent");
+
+        // The partial file of a failed or abandoned build is removed
+        let mut writer = SDistWriter::new(&tmp_dir, &metadata, None, true, TarFormat::Pax)?;
+        writer.add_bytes("foo-0.1.0/PKG-INFO", b"")?;
+        drop(writer);
+        // Only the sdist is left in the output directory
+        let files: Vec<_> = fs::read_dir(tmp_dir.path())?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(files, ["foo-0.1.0.tar.gz"]);
+        Ok(())
+    }
 }
@@ -1,3 +1,4 @@
+use crate::TomlEditor;
 use anyhow::{bail, Context, Result};
 use console::style;
 use dialoguer::{theme::ColorfulTheme, Select};
@@ -111,6 +112,23 @@ impl<'a> ProjectGenerator<'a> {
         let path = directory.join(file);
         if self.overwrite || !path.exists() {
             fs::write(path, self.render_template(file)?)?;
+        } else if file == "pyproject.toml" {
+            self.update_pyproject_toml(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the maturin configuration to an existing pyproject.toml, keeping its comments and
+    /// formatting. An existing `[build-system]` is kept, [check_build_system] rejects other
+    /// build backends beforehand.
+    fn update_pyproject_toml(&self, path: &Path) -> Result<()> {
+        let template = self
+            .render_template("pyproject.toml")?
+            .parse::<toml_edit::Document>()?;
+        let mut editor = TomlEditor::open(path)?;
+        editor.insert_missing(template.as_table());
+        if editor.save()? {
+            eprintln!("📝 Added the maturin configuration to {}", path.display());
         }
         Ok(())
     }
@@ -154,9 +172,10 @@ pub fn init_project(path: Option<String>, options: GenerateProjectOptions) -> Re
     let project_path = path
         .map(Into::into)
         .map_or_else(std::env::current_dir, Ok)?;
-    if project_path.join("pyproject.toml").exists() || project_path.join("Cargo.toml").exists() {
-        bail!("`maturin init` cannot be run on existing projects");
+    if project_path.join("Cargo.toml").exists() {
+        bail!("`maturin init` cannot be run on existing cargo projects");
     }
+    check_build_system(&project_path.join("pyproject.toml"))?;
     generate_project(&project_path, options, false)?;
     println!(
         "  ✨ {} {} {}",
@@ -167,6 +186,37 @@ pub fn init_project(path: Option<String>, options: GenerateProjectOptions) -> Re
     Ok(())
 }
 
+/// Fails if an existing pyproject.toml already uses another build system, which `maturin init`
+/// would have to replace
+fn check_build_system(pyproject_toml: &Path) -> Result<()> {
+    if !pyproject_toml.is_file() {
+        return Ok(());
+    }
+    let document = fs::read_to_string(pyproject_toml)?
+        .parse::<toml_edit::Document>()
+        .with_context(|| format!("Failed to parse {}", pyproject_toml.display()))?;
+    let build_system = match document.get("build-system") {
+        Some(build_system) => build_system,
+        None => return Ok(()),
+    };
+    match build_system
+        .get("build-backend")
+        .and_then(|backend| backend.as_str())
+    {
+        Some("maturin") => Ok(()),
+        Some(backend) => bail!(
+            "{} already uses the build backend `{}`, remove its `[build-system]` table to \
+            switch to maturin",
+            pyproject_toml.display(),
+            backend
+        ),
+        None => bail!(
+            "{} already has a `[build-system]` table, remove it to switch to maturin",
+            pyproject_toml.display()
+        ),
+    }
+}
+
 fn generate_project(
     project_path: &Path,
     options: GenerateProjectOptions,
@@ -210,3 +260,96 @@ fn generate_project(
     let generator = ProjectGenerator::new(name, layout, bindings, overwrite)?;
     generator.generate(project_path)
 }
+
+/// Generates a pure Rust project from the templates of `maturin new`, e.g. for `maturin self test`
+pub(crate) fn generate_sample_project(
+    project_path: &Path,
+    name: &str,
+    bindings: &str,
+) -> Result<()> {
+    let generator = ProjectGenerator::new(
+        name.to_string(),
+        ProjectLayout::PureRust,
+        bindings.to_string(),
+        true,
+    )?;
+    generator.generate(project_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_init_existing_pyproject_toml() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let project_path = tmp_dir.path().join("existing");
+        fs::create_dir(&project_path).unwrap();
+        fs::write(
+            project_path.join("pyproject.toml"),
+            indoc!(
+                r#"
+                [project]
+                name = "existing"  # the name on pypi
+                dependencies = ["numpy"]
+                "#
+            ),
+        )
+        .unwrap();
+        let options = GenerateProjectOptions {
+            name: None,
+            mixed: false,
+            src: false,
+            bindings: Some("bin".to_string()),
+        };
+        generate_project(&project_path, options, false).unwrap();
+        let pyproject_toml = fs::read_to_string(project_path.join("pyproject.toml")).unwrap();
+        assert!(pyproject_toml.contains("[build-system]\n"));
+        assert!(pyproject_toml.contains("build-backend = \"maturin\"\n"));
+        assert!(pyproject_toml.contains("name = \"existing\"  # the name on pypi\n"));
+        assert!(pyproject_toml.contains("dependencies = [\"numpy\"]\n"));
+        assert!(pyproject_toml.contains("requires-python = \">=3.7\"\n"));
+        assert!(pyproject_toml.contains("[tool.maturin]\nbindings = \"bin\"\n"));
+        assert!(project_path.join("src").join("main.rs").is_file());
+    }
+
+    #[test]
+    fn test_init_other_build_system() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let pyproject_toml = tmp_dir.path().join("pyproject.toml");
+        let content = indoc!(
+            r#"
+            [build-system]
+            requires = ["setuptools"]
+            build-backend = "setuptools.build_meta"
+            "#
+        );
+        fs::write(&pyproject_toml, content).unwrap();
+        let options = GenerateProjectOptions {
+            name: None,
+            mixed: false,
+            src: false,
+            bindings: Some("bin".to_string()),
+        };
+        let err = init_project(Some(tmp_dir.path().display().to_string()), options).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("already uses the build backend `setuptools.build_meta`, remove its `[build-system]` table to switch to maturin"));
+        assert_eq!(fs::read_to_string(&pyproject_toml).unwrap(), content);
+        assert!(!tmp_dir.path().join("Cargo.toml").exists());
+
+        fs::write(
+            &pyproject_toml,
+            "[build-system]\nrequires = [\"setuptools\"]\n",
+        )
+        .unwrap();
+        assert!(check_build_system(&pyproject_toml).is_err());
+        fs::write(
+            &pyproject_toml,
+            "[build-system]\nrequires = [\"maturin>=0.13\"]\nbuild-backend = \"maturin\"\n",
+        )
+        .unwrap();
+        check_build_system(&pyproject_toml).unwrap();
+    }
+}
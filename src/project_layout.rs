@@ -1,8 +1,16 @@
 use crate::build_options::{extract_cargo_metadata_args, CargoOptions};
+use crate::classifiers::warn_unknown_classifiers;
+use crate::compile::LIB_CRATE_TYPES;
+use crate::dynamic_metadata::resolve_dynamic_metadata;
 use crate::polyfill::MetadataCommandExt;
-use crate::{CargoToml, Metadata21, PyProjectToml};
-use anyhow::{bail, format_err, Context, Result};
-use cargo_metadata::{Metadata, MetadataCommand};
+use crate::pyproject_toml::MetadataSource;
+use crate::readme::check_readme;
+use crate::sync_metadata::{apply_cargo_metadata, find_metadata_drift, warn_metadata_drift};
+use crate::target::host_target_triple;
+use crate::{CargoToml, ConfigError, Metadata21, PyProjectToml, Target};
+use anyhow::{bail, Context, Result};
+use cargo_metadata::{Metadata, MetadataCommand, Package};
+use fs_err as fs;
 use normpath::PathExt as _;
 use std::env;
 use std::io;
@@ -17,12 +25,15 @@ pub struct ProjectLayout {
     /// If none, we have a rust crate compiled into a shared library with only some glue python for cffi
     /// If some, we have a python package that is extended by a native rust module.
     pub python_module: Option<PathBuf>,
+    /// Additional copies of the python package from further `python-source` directories,
+    /// which are merged into `python_module` in this order
+    pub extra_python_modules: Vec<PathBuf>,
     /// Contains the canonicalized (i.e. absolute) path to the rust part of the project
     pub rust_module: PathBuf,
     /// Rust extension name
     pub extension_name: String,
-    /// The location of the wheel data, if any
-    pub data: Option<PathBuf>,
+    /// The locations of the wheel data, merged in this order
+    pub data: Vec<PathBuf>,
 }
 
 /// Project resolver
@@ -59,10 +70,10 @@ impl ProjectResolver {
         let (manifest_file, pyproject_file) =
             Self::resolve_manifest_paths(cargo_manifest_path, &cargo_options)?;
         if !manifest_file.is_file() {
-            bail!(
+            bail!(ConfigError::new(format!(
                 "{} is not the path to a Cargo.toml",
                 manifest_file.display()
-            );
+            )));
         }
         // Sanity checks in debug build
         debug_assert!(
@@ -84,12 +95,42 @@ impl ProjectResolver {
 
         let manifest_dir = manifest_file.parent().unwrap();
         let pyproject_toml: Option<PyProjectToml> = if pyproject_file.is_file() {
-            let pyproject =
-                PyProjectToml::new(&pyproject_file).context("pyproject.toml is invalid")?;
+            let mut pyproject = PyProjectToml::new(&pyproject_file)
+                .context(ConfigError::new("pyproject.toml is invalid"))?;
             pyproject.warn_missing_maturin_version();
+            pyproject.warn_deprecated_keys();
             pyproject.warn_missing_build_backend();
+            if pyproject.has_cfg_configs() {
+                let target = Target::from_target_triple(cargo_options.target.clone())?;
+                for key in pyproject.apply_cfg_configs(&target)? {
+                    eprintln!("🎯 Using the options of [tool.maturin.'{}']", key);
+                }
+            }
+            if let Some(name) = &cargo_options.maturin_profile {
+                pyproject.apply_maturin_profile(name)?;
+                eprintln!("🎛  Using the maturin profile `{}`", name);
+            }
+            if pyproject.has_target_configs() {
+                let target_triple = match &cargo_options.target {
+                    Some(target_triple) => target_triple.clone(),
+                    None => host_target_triple()?,
+                };
+                if pyproject.apply_target_config(&target_triple) {
+                    eprintln!(
+                        "🎯 Using the options of [tool.maturin.target.{}]",
+                        target_triple
+                    );
+                }
+            }
             Some(pyproject)
         } else {
+            if let Some(name) = &cargo_options.maturin_profile {
+                bail!(ConfigError::new(format!(
+                    "Can't use the maturin profile `{}` without a pyproject.toml, \
+                    profiles are defined in [tool.maturin.profiles]",
+                    name
+                )));
+            }
             None
         };
         let pyproject = pyproject_toml.as_ref();
@@ -108,20 +149,33 @@ impl ProjectResolver {
                 .context("Failed to parse Cargo.toml into python metadata")?;
         if let Some(pyproject) = pyproject {
             let pyproject_dir = pyproject_file.parent().unwrap();
+            let cargo_metadata21 = metadata21.clone();
             metadata21.merge_pyproject_toml(pyproject_dir, pyproject)?;
+
+            let package = cargo_metadata
+                .root_package()
+                .context("Expected cargo to return metadata with root_package")?;
+            let drift = find_metadata_drift(package, pyproject);
+            let metadata_source = pyproject.metadata_source();
+            warn_metadata_drift(&drift, metadata_source);
+            if metadata_source == MetadataSource::Cargo {
+                apply_cargo_metadata(&mut metadata21, &cargo_metadata21, &drift);
+            }
+            resolve_dynamic_metadata(&mut metadata21, pyproject_dir, pyproject)?;
         }
+        check_readme(&mut metadata21)?;
+        // Only `maturin build` and `maturin publish` fail, see [BuildContext::check_classifiers]
+        warn_unknown_classifiers(&metadata21.classifiers);
         let extra_metadata = cargo_toml.remaining_core_metadata();
 
         let crate_name = &cargo_toml.package.name;
-
-        // If the package name contains minuses, you must declare a module with
-        // underscores as lib name
-        let module_name = cargo_toml
-            .lib
-            .as_ref()
-            .and_then(|lib| lib.name.as_ref())
-            .unwrap_or(crate_name)
-            .to_owned();
+        let lib_name = cargo_toml.lib.as_ref().and_then(|lib| lib.name.as_deref());
+        let lib_target_name = cargo_metadata
+            .root_package()
+            .and_then(find_lib_target)
+            .map(|target| target.name.as_str());
+        let module_name = infer_module_name(crate_name, lib_name, lib_target_name);
+        warn_lib_name_collisions(&cargo_metadata, &module_name);
 
         // Only use extension name from extra metadata if it contains dot
         let extension_name = extra_metadata
@@ -136,7 +190,7 @@ impl ProjectResolver {
             manifest_dir
         };
         let py_root = match pyproject.and_then(|x| x.python_source()) {
-            Some(py_src) => py_src.to_path_buf(),
+            Some(py_src) => project_root.join(py_src),
             None => match extra_metadata.python_source.as_ref() {
                 Some(py_src) => manifest_dir.join(py_src),
                 None => match pyproject.and_then(|x| x.project_name()) {
@@ -153,24 +207,53 @@ impl ProjectResolver {
                 },
             },
         };
-        let data = match pyproject.and_then(|x| x.data()) {
-            Some(data) => {
-                if data.is_absolute() {
-                    Some(data.to_path_buf())
-                } else {
-                    Some(project_root.join(data))
-                }
-            }
-            None => extra_metadata.data.as_ref().map(|data| {
-                let data = Path::new(data);
-                if data.is_absolute() {
-                    data.to_path_buf()
-                } else {
-                    manifest_dir.join(data)
-                }
-            }),
+        let data = match pyproject.map(|x| x.data()).filter(|data| !data.is_empty()) {
+            Some(data) => data.iter().map(|data| project_root.join(data)).collect(),
+            None => extra_metadata
+                .data
+                .as_ref()
+                .map(|data| manifest_dir.join(data))
+                .into_iter()
+                .collect(),
         };
-        let project_layout = ProjectLayout::determine(project_root, extension_name, py_root, data)?;
+        let extra_py_roots = pyproject
+            .map(|x| x.python_sources())
+            .unwrap_or_default()
+            .iter()
+            .skip(1)
+            .map(|py_src| project_root.join(py_src))
+            .collect();
+        let project_layout =
+            ProjectLayout::determine(project_root, extension_name, py_root, extra_py_roots, data)?;
+
+        let notes_dir = cargo_metadata
+            .target_directory
+            .as_std_path()
+            .join("maturin")
+            .join("notes");
+        if lib_name.is_none() && lib_target_name.is_some() && crate_name.contains('-') {
+            note_once(
+                &notes_dir,
+                &format!("module-name-{}", module_name),
+                &format!(
+                    "💡 Using `{}` as module name, derived from the package name `{}`. \
+                    Earlier versions of maturin required `[lib] name = \"{}\"` in Cargo.toml, \
+                    which you can keep to be explicit",
+                    module_name, crate_name, module_name
+                ),
+            );
+        }
+        let import_name = extension_name.split('.').next().unwrap_or(extension_name);
+        // Binaries without a library aren't imported
+        let import_name_note =
+            lib_target_name.and_then(|_| import_name_note(import_name, &metadata21.name));
+        if let Some(message) = import_name_note {
+            note_once(
+                &notes_dir,
+                &format!("import-name-{}-{}", import_name, metadata21.name),
+                &message,
+            );
+        }
         Ok(Self {
             project_layout,
             cargo_toml_path: manifest_file,
@@ -186,7 +269,7 @@ impl ProjectResolver {
     }
 
     /// Get cargo manifest file path and pyproject.toml path
-    fn resolve_manifest_paths(
+    pub(crate) fn resolve_manifest_paths(
         cargo_manifest_path: Option<PathBuf>,
         cargo_options: &CargoOptions,
     ) -> Result<(PathBuf, PathBuf)> {
@@ -215,8 +298,8 @@ impl ProjectResolver {
             .into_path_buf();
         let pyproject_file = current_dir.join(PYPROJECT_TOML);
         if pyproject_file.is_file() {
-            let pyproject =
-                PyProjectToml::new(&pyproject_file).context("pyproject.toml is invalid")?;
+            let pyproject = PyProjectToml::new(&pyproject_file)
+                .context(ConfigError::new("pyproject.toml is invalid"))?;
             if let Some(path) = pyproject.manifest_path() {
                 // pyproject.toml must be placed at top directory
                 let manifest_dir = path
@@ -225,7 +308,9 @@ impl ProjectResolver {
                     .normalize()?
                     .into_path_buf();
                 if !manifest_dir.starts_with(&current_dir) {
-                    bail!("Cargo.toml can not be placed outside of the directory containing pyproject.toml");
+                    bail!(ConfigError::new(
+                        "Cargo.toml can not be placed outside of the directory containing pyproject.toml"
+                    ));
                 }
                 return Ok((path.normalize()?.into_path_buf(), pyproject_file));
             } else {
@@ -262,11 +347,12 @@ impl ProjectResolver {
         if path.exists() {
             Ok((path, current_dir.join(PYPROJECT_TOML)))
         } else {
-            Err(format_err!(
+            Err(ConfigError::new(format!(
                 "Can't find {} (in {})",
                 path.display(),
                 current_dir.display()
             ))
+            .into())
         }
     }
 
@@ -302,7 +388,8 @@ impl ProjectLayout {
         project_root: impl AsRef<Path>,
         module_name: &str,
         python_root: PathBuf,
-        data: Option<PathBuf>,
+        extra_python_roots: Vec<PathBuf>,
+        data: Vec<PathBuf>,
     ) -> Result<ProjectLayout> {
         // A dot in the module name means the extension module goes into the module folder specified by the path
         let parts: Vec<&str> = module_name.split('.').collect();
@@ -323,35 +410,58 @@ impl ProjectLayout {
             )
         };
 
-        let data = if let Some(data) = data {
-            if !data.is_dir() {
-                bail!("No such data directory {}", data.display());
-            }
-            Some(data)
+        // The data directories may be generated during the build, so they're only checked when
+        // they are added
+        let data = if !data.is_empty() {
+            data
         } else if project_root.join(format!("{}.data", module_name)).is_dir() {
-            Some(project_root.join(format!("{}.data", module_name)))
+            vec![project_root.join(format!("{}.data", module_name))]
         } else {
-            None
+            Vec::new()
         };
 
+        let package_name = python_module.file_name().unwrap();
+        let extra_python_modules: Vec<PathBuf> = extra_python_roots
+            .iter()
+            .map(|root| root.join(package_name))
+            .collect();
+
         if python_module.is_dir() {
             if !python_module.join("__init__.py").is_file()
                 && !python_module.join("__init__.pyi").is_file()
             {
-                bail!("Found a directory with the module name ({}) next to Cargo.toml, which indicates a mixed python/rust project, but the directory didn't contain an __init__.py file.", module_name)
+                bail!(ConfigError::new(format!("Found a directory with the module name ({}) next to Cargo.toml, which indicates a mixed python/rust project, but the directory didn't contain an __init__.py file.", module_name)))
+            }
+            for extra_python_module in &extra_python_modules {
+                if !extra_python_module.is_dir() {
+                    bail!(ConfigError::new(format!(
+                        "The python-source directory {} doesn't contain the python package {}",
+                        extra_python_module.parent().unwrap().display(),
+                        package_name.to_string_lossy()
+                    )));
+                }
             }
 
-            println!("🍹 Building a mixed python/rust project");
+            eprintln!("🍹 Building a mixed python/rust project");
 
             Ok(ProjectLayout {
                 python_module: Some(python_module),
+                extra_python_modules,
                 rust_module,
                 extension_name,
                 data,
             })
         } else {
+            if !extra_python_modules.is_empty() {
+                bail!(ConfigError::new(format!(
+                    "Multiple python-source directories were specified, but the first one ({}) doesn't contain the python package {}",
+                    python_root.display(),
+                    package_name.to_string_lossy()
+                )));
+            }
             Ok(ProjectLayout {
                 python_module: None,
+                extra_python_modules,
                 rust_module: project_root.to_path_buf(),
                 extension_name,
                 data,
@@ -359,3 +469,106 @@ impl ProjectLayout {
         }
     }
 }
+
+/// The library target of a package, which maturin builds as cdylib for all bindings but bin
+fn find_lib_target(package: &Package) -> Option<&cargo_metadata::Target> {
+    package.targets.iter().find(|target| {
+        target
+            .kind
+            .iter()
+            .any(|kind| kind == "cdylib" || LIB_CRATE_TYPES.contains(&kind.as_str()))
+    })
+}
+
+/// The module name is `[lib] name` or otherwise the name of the library target, which cargo
+/// derives from the package name by replacing dashes with underscores. Packages without library,
+/// i.e. bin bindings, use the package name.
+fn infer_module_name(
+    crate_name: &str,
+    lib_name: Option<&str>,
+    lib_target_name: Option<&str>,
+) -> String {
+    lib_name
+        .or(lib_target_name)
+        .map(ToString::to_string)
+        .unwrap_or_else(|| crate_name.to_string())
+}
+
+/// Warns when other members of the workspace have a library of the same name, as their builds
+/// overwrite each other's artifacts in the shared target directory
+fn warn_lib_name_collisions(cargo_metadata: &Metadata, module_name: &str) {
+    let root_id = cargo_metadata
+        .resolve
+        .as_ref()
+        .and_then(|x| x.root.as_ref());
+    let collisions: Vec<&str> = cargo_metadata
+        .workspace_members
+        .iter()
+        .filter(|id| Some(*id) != root_id)
+        .filter_map(|id| cargo_metadata.packages.iter().find(|x| &x.id == id))
+        .filter(|package| find_lib_target(package).map(|x| x.name.as_str()) == Some(module_name))
+        .map(|package| package.name.as_str())
+        .collect();
+    if !collisions.is_empty() {
+        eprintln!(
+            "⚠️  Warning: The workspace members {} also have a library named `{}`, \
+            their artifacts can overwrite each other. Set a distinct `[lib] name` in Cargo.toml",
+            collisions.join(", "),
+            module_name
+        );
+    }
+}
+
+/// Explains the difference between the distribution name, which is used to install the package,
+/// and the import name, unless they only differ by normalization
+fn import_name_note(import_name: &str, dist_name: &str) -> Option<String> {
+    let normalized = dist_name.to_lowercase().replace(['-', '.'], "_");
+    if import_name.to_lowercase() == normalized {
+        return None;
+    }
+    Some(format!(
+        "💡 The package is installed as `{}`, but imported with `import {}`",
+        dist_name, import_name
+    ))
+}
+
+/// Prints a message only the first time for a project, remembered by a marker file in the target
+/// directory
+fn note_once(notes_dir: &Path, key: &str, message: &str) {
+    let marker = notes_dir.join(key);
+    if marker.exists() {
+        return;
+    }
+    eprintln!("{}", message);
+    // Printing the note again isn't worth failing the build over
+    let _ = fs::create_dir_all(notes_dir).and_then(|_| fs::write(&marker, message));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{import_name_note, infer_module_name};
+
+    #[test]
+    fn test_infer_module_name() {
+        assert_eq!(
+            infer_module_name("my-project", None, Some("my_project")),
+            "my_project"
+        );
+        assert_eq!(
+            infer_module_name("my-project", Some("_native"), Some("_native")),
+            "_native"
+        );
+        // bin bindings
+        assert_eq!(infer_module_name("my-project", None, None), "my-project");
+    }
+
+    #[test]
+    fn test_import_name_note() {
+        assert_eq!(import_name_note("my_project", "My-Project"), None);
+        assert_eq!(import_name_note("my_project", "my.project"), None);
+        assert_eq!(
+            import_name_note("_native", "my-project").unwrap(),
+            "💡 The package is installed as `my-project`, but imported with `import _native`"
+        );
+    }
+}
@@ -1,11 +1,20 @@
 //! A pyproject.toml as specified in PEP 517
 
-use crate::PlatformTag;
-use anyhow::{format_err, Result};
+use crate::marker::MarkerEnvironment;
+use crate::migrate_config::{migrate, Migration};
+use crate::target_cfg::TargetCfg;
+use crate::{ConfigError, PlatformTag, Target};
+use anyhow::{bail, format_err, Context, Result};
 use fs_err as fs;
 use pyproject_toml::PyProjectToml as ProjectToml;
+use semver::Version;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 /// The `[tool]` section of a pyproject.toml
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -81,26 +90,417 @@ impl GlobPattern {
     }
 }
 
+/// The manifest that takes precedence for metadata that is specified in both
+/// Cargo.toml and pyproject.toml
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataSource {
+    /// pyproject.toml, the default
+    Pyproject,
+    /// Cargo.toml
+    Cargo,
+}
+
+impl Default for MetadataSource {
+    fn default() -> Self {
+        MetadataSource::Pyproject
+    }
+}
+
+impl MetadataSource {
+    /// Returns the manifest which is not the metadata source
+    pub fn other(&self) -> MetadataSource {
+        match self {
+            MetadataSource::Pyproject => MetadataSource::Cargo,
+            MetadataSource::Cargo => MetadataSource::Pyproject,
+        }
+    }
+}
+
+impl std::fmt::Display for MetadataSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetadataSource::Pyproject => write!(f, "pyproject.toml"),
+            MetadataSource::Cargo => write!(f, "Cargo.toml"),
+        }
+    }
+}
+
+/// How `project.urls` in pyproject.toml combines with the urls derived from Cargo.toml's
+/// `repository`, `homepage` and `documentation`
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectUrls {
+    /// `project.urls` replaces the urls from Cargo.toml, the default
+    Replace,
+    /// `project.urls` extends the urls from Cargo.toml, overriding entries with the same label
+    Merge,
+}
+
+impl Default for ProjectUrls {
+    fn default() -> Self {
+        ProjectUrls::Replace
+    }
+}
+
+/// The value of `[tool.maturin.python-source]`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum PythonSource {
+    /// A single directory containing the python package
+    Single(PathBuf),
+    /// Multiple directories whose contents are merged into one python package, in order
+    Multiple(Vec<PathBuf>),
+}
+
+impl PythonSource {
+    /// Returns all python source directories in merge order
+    pub fn paths(&self) -> &[PathBuf] {
+        match self {
+            PythonSource::Single(path) => std::slice::from_ref(path),
+            PythonSource::Multiple(paths) => paths,
+        }
+    }
+}
+
+/// The value of `[tool.maturin.data]`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum DataSource {
+    /// A single wheel data directory
+    Single(PathBuf),
+    /// Multiple directories whose contents are merged into the data of the wheel
+    Multiple(Vec<PathBuf>),
+}
+
+impl DataSource {
+    /// Returns all data directories in merge order
+    pub fn paths(&self) -> &[PathBuf] {
+        match self {
+            DataSource::Single(path) => std::slice::from_ref(path),
+            DataSource::Multiple(paths) => paths,
+        }
+    }
+}
+
+/// How to compute a field listed in `project.dynamic`, configured in `[tool.maturin.dynamic]`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DynamicHook {
+    /// A command printing the value to stdout, e.g. `["git", "describe", "--tags"]`
+    pub command: Option<Vec<String>>,
+    /// A python callable or attribute in `module:name` form returning the value
+    pub callable: Option<String>,
+    /// The content type of a dynamic readme, guessed from the contents if missing
+    pub content_type: Option<String>,
+}
+
+/// The format of the tar archive of source distributions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TarFormat {
+    /// POSIX.1-2001 pax, which stores long and non-ASCII paths in extended headers
+    Pax,
+    /// GNU tar, which stores long paths in `././@LongLink` entries
+    Gnu,
+}
+
+impl Default for TarFormat {
+    fn default() -> Self {
+        TarFormat::Pax
+    }
+}
+
+/// Whether the Cargo.lock is packed into source distributions, configured with
+/// `[tool.maturin] include-cargo-lock`
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum CargoLockInclusion {
+    /// Always include it and fail if there is no Cargo.lock
+    Always,
+    /// Never include it, the dependencies are resolved again when building the wheel
+    Never,
+    /// Include it if it exists, or fail with `--locked`/`--frozen` if it doesn't, the default
+    IfPresent,
+}
+
+impl Default for CargoLockInclusion {
+    fn default() -> Self {
+        CargoLockInclusion::IfPresent
+    }
+}
+
+/// What happens when a pattern in `[tool.maturin] include` matches no files, configured with
+/// `[tool.maturin] unmatched-include`
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnmatchedInclude {
+    /// Print a warning, the default
+    Warn,
+    /// Fail the build, e.g. to catch typos that would drop files from releases
+    Error,
+}
+
+impl Default for UnmatchedInclude {
+    fn default() -> Self {
+        UnmatchedInclude::Warn
+    }
+}
+
+/// The `lto` of the cargo profile, set with `[tool.maturin.lto] mode`
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LtoMode {
+    /// No link time optimization at all, not even within a crate
+    Off,
+    /// ThinLTO across all crates
+    Thin,
+    /// Full LTO across all crates, slower to link
+    Fat,
+}
+
+impl fmt::Display for LtoMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LtoMode::Off => write!(f, "off"),
+            LtoMode::Thin => write!(f, "thin"),
+            LtoMode::Fat => write!(f, "fat"),
+        }
+    }
+}
+
+/// The `[tool.maturin.lto]` section of a pyproject.toml
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LtoConfig {
+    /// The `lto` of the cargo profile, overrides the one in Cargo.toml
+    pub mode: Option<LtoMode>,
+    /// Optimize across rust and C/C++ code with rustc's `-C linker-plugin-lto`, linking with
+    /// clang and lld
+    #[serde(default)]
+    pub linker_plugin: bool,
+    /// The clang that compiles the C code and links, it must use the same LLVM version as
+    /// rustc. Defaults to `clang`, or `clang-cl` for msvc targets
+    pub clang: Option<String>,
+    /// The lld clang links with on linux and macOS, passed as `-fuse-ld`, defaults to `lld`
+    pub lld: Option<String>,
+}
+
+/// The `[tool.maturin.sdist]` section of a pyproject.toml
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SdistConfig {
+    /// The format of the tar archive, defaults to pax
+    pub tar_format: Option<TarFormat>,
+    /// Include the dependencies from `cargo vendor`, so the source distribution builds offline
+    #[serde(default)]
+    pub vendor: bool,
+    /// Only include files committed to git, like `git archive`
+    #[serde(default)]
+    pub from_git: bool,
+}
+
+/// The `[tool.maturin.wheel]` section of a pyproject.toml, for scanners and signing tools that
+/// expect additional information or alignment in the zip archive
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WheelConfig {
+    /// The comment of the zip archive, e.g. with a build id or the source url
+    pub zip_comment: Option<String>,
+    /// Extra fields added to the header of every file in the wheel
+    #[serde(default)]
+    pub extra_fields: Vec<ZipExtraField>,
+    /// Align the contents of every file in the wheel to a multiple of this many bytes
+    pub align: Option<u16>,
+}
+
+/// An extra field in the zip header of a file, see section 4.5 of the zip specification
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ZipExtraField {
+    /// The header id, which must not be one of the ids reserved by the zip specification
+    pub id: u16,
+    /// The contents of the field
+    pub data: String,
+}
+
+/// The `[tool.maturin.audit]` section of a pyproject.toml
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AuditConfig {
+    /// A json file with the policies of custom platform tags, in the format of auditwheel's
+    /// policy files, relative to the pyproject.toml directory
+    pub extra_policy: Option<PathBuf>,
+}
+
+/// The `[tool.maturin.hooks]` section of a pyproject.toml, shell commands that run in the
+/// pyproject.toml directory
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Hooks {
+    /// Commands that run before building wheels or a source distribution
+    pub pre_build: Option<Vec<String>>,
+    /// Commands that run after building wheels or a source distribution, with their paths
+    /// in `MATURIN_ARTIFACTS`
+    pub post_build: Option<Vec<String>>,
+}
+
+/// Where the version is computed from, configured in `[tool.maturin.version]`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum VersionSource {
+    /// The latest git tag, plus the distance and dirty markers like setuptools_scm
+    Git,
+}
+
+/// The `[tool.maturin.version]` section of a pyproject.toml
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct VersionConfig {
+    /// Where the version is computed from
+    pub source: VersionSource,
+    /// Files the version is written to, relative to the pyproject.toml directory.
+    ///
+    /// `.py` files get a `__version__` attribute, `.rs` files a `VERSION` const
+    #[serde(default)]
+    pub write_to: Vec<PathBuf>,
+    /// Whether versions between tags get a local version segment with the commit hash, e.g.
+    /// `1.2.4.dev5+g1234abc`. PyPI rejects uploads with a local version.
+    #[serde(default)]
+    pub local_version: bool,
+}
+
+/// A named profile in `[tool.maturin.profiles]`, selected with `--maturin-profile`
+///
+/// The values replace the ones of `[tool.maturin]`, options given on the command line still take
+/// precedence
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MaturinProfile {
+    /// Build artifacts with the specified Cargo profile
+    pub profile: Option<String>,
+    /// Space or comma separated list of features to activate
+    pub features: Option<Vec<String>>,
+    /// Activate all available features
+    pub all_features: Option<bool>,
+    /// Do not activate the `default` feature
+    pub no_default_features: Option<bool>,
+    /// Strip the library for minimum file size
+    pub strip: Option<bool>,
+    /// Control the platform tag on linux
+    pub compatibility: Option<PlatformTag>,
+    /// Additional flags passed to all compiler invocations through `RUSTFLAGS`
+    pub rustflags: Option<Vec<String>>,
+}
+
+/// The `metadata` of `[tool.maturin.target.<triple>]` or `[tool.maturin.target.'cfg(...)']`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TargetMetadata {
+    /// Requirements added to the wheels of the target, in the format of `project.dependencies`
+    pub dependencies: Option<Vec<String>>,
+}
+
+/// The options in `[tool.maturin.target.<triple>]`, which only apply when building for that target.
+/// `[tool.maturin.target.'cfg(...)']` tables only support `metadata`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TargetConfig {
+    /// Features to activate in addition to the ones in `[tool.maturin]`
+    pub features: Option<Vec<String>>,
+    /// Do not activate the `default` feature
+    pub no_default_features: Option<bool>,
+    /// Flags for `RUSTFLAGS`, after the ones in `[tool.maturin]`
+    pub rustflags: Option<Vec<String>>,
+    /// The linker, unless `CARGO_TARGET_<TRIPLE>_LINKER` is set
+    pub linker: Option<String>,
+    /// Additional arguments for the linker when linking the library or binary
+    pub link_args: Option<Vec<String>>,
+    /// Environment variables for the build, replacing the ones in `[tool.maturin.env]`
+    pub env: Option<BTreeMap<String, String>>,
+    /// Additions to the metadata of the wheels for this target
+    pub metadata: Option<TargetMetadata>,
+}
+
 /// The `[tool.maturin]` section of a pyproject.toml
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[serde(rename_all = "kebab-case")]
 pub struct ToolMaturin {
     // maturin specific options
-    // TODO(0.15.0): remove deprecated
-    sdist_include: Option<Vec<String>>,
     include: Option<Vec<GlobPattern>>,
     exclude: Option<Vec<GlobPattern>>,
     bindings: Option<String>,
-    #[serde(alias = "manylinux")]
     compatibility: Option<PlatformTag>,
     #[serde(default)]
     skip_auditwheel: bool,
+    /// Tag wheels that violate the manylinux/musllinux policy as linux instead of failing
+    #[serde(default)]
+    fallback_to_linux: bool,
+    /// Bundle the external shared libraries also into wheels with the linux tag and on macOS
+    #[serde(default)]
+    repair: bool,
     #[serde(default)]
     strip: bool,
+    /// Run the cargo build in a container of cross for the target
+    #[serde(default)]
+    container: bool,
+    /// Generate `.bat` launchers for python scripts without file extension in windows wheels
+    #[serde(default)]
+    script_wrappers: bool,
+    /// Normalize file names in wheels and source distributions to unicode NFC, defaults to true
+    unicode_normalization: Option<bool>,
+    /// Leave large files with the same contents as an earlier file out of the wheel
+    #[serde(default)]
+    skip_duplicate_contents: bool,
     /// The directory with python module, contains `<module_name>/__init__.py`
-    python_source: Option<PathBuf>,
-    /// Path to the wheel directory, defaults to `<module_name>.data`
-    data: Option<PathBuf>,
+    python_source: Option<PythonSource>,
+    /// Path to the wheel data directory, or several of them, defaults to `<module_name>.data`
+    data: Option<DataSource>,
+    /// Glob patterns of additional license files, relative to the pyproject.toml directory
+    license_files: Option<Vec<String>>,
+    /// Optional build number of the wheel, see PEP 427
+    build_tag: Option<String>,
+    /// Which manifest takes precedence for metadata specified in both Cargo.toml and pyproject.toml
+    metadata_source: Option<MetadataSource>,
+    /// Whether `project.urls` replaces or extends the urls from Cargo.toml
+    project_urls: Option<ProjectUrls>,
+    /// Hooks computing the fields in `project.dynamic` at build time
+    dynamic: Option<BTreeMap<String, DynamicHook>>,
+    /// Computes the version, e.g. from git tags
+    version: Option<VersionConfig>,
+    /// Commands that run before and after the build
+    hooks: Option<Hooks>,
+    /// Options for the source distribution
+    sdist: Option<SdistConfig>,
+    /// Options for checking the manylinux/musllinux compliance
+    audit: Option<AuditConfig>,
+    /// Options for the zip archive of the wheel
+    wheel: Option<WheelConfig>,
+    /// Whether the Cargo.lock is packed into the source distribution
+    include_cargo_lock: Option<CargoLockInclusion>,
+    /// Whether an include pattern that matches no files is a warning or an error
+    unmatched_include: Option<UnmatchedInclude>,
+    /// Record local build statistics for `maturin stats`
+    #[serde(default)]
+    stats: bool,
+    /// Reuse the library cargo built when the sources and options didn't change, without running
+    /// cargo
+    #[serde(default)]
+    cache: bool,
+    /// Additional flags passed to all compiler invocations through `RUSTFLAGS`
+    rustflags: Option<Vec<String>>,
+    /// Link time optimization, including across rust and C/C++ code
+    lto: Option<LtoConfig>,
+    /// Environment variables for cargo and the python subprocesses of the build
+    env: Option<BTreeMap<String, String>>,
+    /// Named sets of options, selected with `--maturin-profile`
+    profiles: Option<BTreeMap<String, MaturinProfile>>,
+    /// Options for specific target triples
+    target: Option<BTreeMap<String, TargetConfig>>,
+    /// The targets `maturin build` and `maturin publish` build for without `--target`
+    targets: Option<Vec<String>>,
+    /// The minimum macOS version to build for, unless MACOSX_DEPLOYMENT_TARGET is set
+    macos_deployment_target: Option<String>,
     // Some customizable cargo options
     /// Build artifacts with the specified Cargo profile
     pub profile: Option<String>,
@@ -122,6 +522,10 @@ pub struct ToolMaturin {
     pub unstable_flags: Option<Vec<String>>,
     /// Additional rustc arguments
     pub rustc_args: Option<Vec<String>>,
+    /// `[tool.maturin.'cfg(...)']` tables with options for the targets matching the expression,
+    /// other unknown keys are ignored
+    #[serde(flatten)]
+    cfg: BTreeMap<String, toml_edit::easy::Value>,
 }
 
 /// A pyproject.toml as specified in PEP 517
@@ -136,6 +540,9 @@ pub struct PyProjectToml {
     ///
     /// We use it for `[tool.maturin]`
     pub tool: Option<Tool>,
+    /// The deprecated keys that were replaced when parsing
+    #[serde(skip)]
+    deprecated_keys: Vec<Migration>,
 }
 
 impl std::ops::Deref for PyProjectToml {
@@ -146,6 +553,37 @@ impl std::ops::Deref for PyProjectToml {
     }
 }
 
+impl FromStr for PyProjectToml {
+    type Err = anyhow::Error;
+
+    /// Parses a pyproject.toml, replacing deprecated keys in `[tool.maturin]` with their
+    /// replacements and `${VAR}` in its string values with the value of the environment
+    /// variable `VAR`
+    fn from_str(contents: &str) -> Result<Self> {
+        let mut document: toml_edit::Document = contents
+            .parse()
+            .map_err(|err| format_err!("pyproject.toml is not PEP 517 compliant: {}", err))?;
+        let deprecated_keys = migrate(&mut document)?;
+        let version = Version::parse(env!("CARGO_PKG_VERSION"))?;
+        if let Some(removed) = deprecated_keys.iter().find(|x| x.is_removed(&version)) {
+            bail!(removed.removed_error());
+        }
+        let mut value: toml_edit::easy::Value = toml_edit::easy::from_str(&document.to_string())
+            .map_err(|err| format_err!("pyproject.toml is not PEP 517 compliant: {}", err))?;
+        if let Some(maturin) = value
+            .get_mut("tool")
+            .and_then(|tool| tool.get_mut("maturin"))
+        {
+            interpolate_value(maturin, "tool.maturin")?;
+        }
+        let mut pyproject: PyProjectToml = value
+            .try_into()
+            .map_err(|err| format_err!("pyproject.toml is not PEP 517 compliant: {}", err))?;
+        pyproject.deprecated_keys = deprecated_keys;
+        Ok(pyproject)
+    }
+}
+
 impl PyProjectToml {
     /// Returns the contents of a pyproject.toml with a `[build-system]` entry or an error
     ///
@@ -154,9 +592,7 @@ impl PyProjectToml {
     pub fn new(pyproject_file: impl AsRef<Path>) -> Result<PyProjectToml> {
         let path = pyproject_file.as_ref();
         let contents = fs::read_to_string(path)?;
-        let pyproject: PyProjectToml = toml_edit::easy::from_str(&contents)
-            .map_err(|err| format_err!("pyproject.toml is not PEP 517 compliant: {}", err))?;
-        Ok(pyproject)
+        contents.parse()
     }
 
     /// Returns the value of `[project.name]` in pyproject.toml
@@ -170,15 +606,6 @@ impl PyProjectToml {
         self.tool.as_ref()?.maturin.as_ref()
     }
 
-    /// Returns the value of `[tool.maturin.sdist-include]` in pyproject.toml
-    #[deprecated(
-        since = "0.14.0",
-        note = "please use `PyProjectToml::include` (<https://github.com/PyO3/maturin/pulls/1255>)"
-    )]
-    pub fn sdist_include(&self) -> Option<&Vec<String>> {
-        self.maturin()?.sdist_include.as_ref()
-    }
-
     /// Returns the value of `[tool.maturin.include]` in pyproject.toml
     pub fn include(&self) -> Option<&[GlobPattern]> {
         self.maturin()?.include.as_ref().map(AsRef::as_ref)
@@ -196,7 +623,7 @@ impl PyProjectToml {
 
     /// Returns the value of `[tool.maturin.compatibility]` in pyproject.toml
     pub fn compatibility(&self) -> Option<PlatformTag> {
-        self.maturin()?.compatibility
+        self.maturin()?.compatibility.clone()
     }
 
     /// Returns the value of `[tool.maturin.skip-auditwheel]` in pyproject.toml
@@ -206,6 +633,20 @@ impl PyProjectToml {
             .unwrap_or_default()
     }
 
+    /// Returns the value of `[tool.maturin.fallback-to-linux]` in pyproject.toml
+    pub fn fallback_to_linux(&self) -> bool {
+        self.maturin()
+            .map(|maturin| maturin.fallback_to_linux)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.repair]` in pyproject.toml
+    pub fn repair(&self) -> bool {
+        self.maturin()
+            .map(|maturin| maturin.repair)
+            .unwrap_or_default()
+    }
+
     /// Returns the value of `[tool.maturin.strip]` in pyproject.toml
     pub fn strip(&self) -> bool {
         self.maturin()
@@ -213,15 +654,427 @@ impl PyProjectToml {
             .unwrap_or_default()
     }
 
+    /// Returns the value of `[tool.maturin.container]` in pyproject.toml
+    pub fn container(&self) -> bool {
+        self.maturin()
+            .map(|maturin| maturin.container)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.script-wrappers]` in pyproject.toml
+    pub fn script_wrappers(&self) -> bool {
+        self.maturin()
+            .map(|maturin| maturin.script_wrappers)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.unicode-normalization]` in pyproject.toml
+    pub fn unicode_normalization(&self) -> bool {
+        self.maturin()
+            .and_then(|maturin| maturin.unicode_normalization)
+            .unwrap_or(true)
+    }
+
+    /// Returns the value of `[tool.maturin.skip-duplicate-contents]` in pyproject.toml
+    pub fn skip_duplicate_contents(&self) -> bool {
+        self.maturin()
+            .map(|maturin| maturin.skip_duplicate_contents)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.sdist.tar-format]` in pyproject.toml
+    pub fn tar_format(&self) -> TarFormat {
+        self.maturin()
+            .and_then(|maturin| maturin.sdist.as_ref())
+            .and_then(|sdist| sdist.tar_format)
+            .unwrap_or_default()
+    }
+
+    /// Returns the `[tool.maturin.lto]` section in pyproject.toml
+    pub fn lto(&self) -> Option<&LtoConfig> {
+        self.maturin().and_then(|maturin| maturin.lto.as_ref())
+    }
+
+    /// Returns the `[tool.maturin.wheel]` section in pyproject.toml
+    pub fn wheel_config(&self) -> Option<&WheelConfig> {
+        self.maturin().and_then(|maturin| maturin.wheel.as_ref())
+    }
+
+    /// Returns the value of `[tool.maturin.audit.extra-policy]` in pyproject.toml
+    pub fn extra_policy(&self) -> Option<&Path> {
+        self.maturin()
+            .and_then(|maturin| maturin.audit.as_ref())
+            .and_then(|audit| audit.extra_policy.as_deref())
+    }
+
+    /// Returns the value of `[tool.maturin.include-cargo-lock]` in pyproject.toml
+    pub fn include_cargo_lock(&self) -> CargoLockInclusion {
+        self.maturin()
+            .and_then(|maturin| maturin.include_cargo_lock)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.unmatched-include]` in pyproject.toml
+    pub fn unmatched_include(&self) -> UnmatchedInclude {
+        self.maturin()
+            .and_then(|maturin| maturin.unmatched_include)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.sdist.vendor]` in pyproject.toml
+    pub fn sdist_vendor(&self) -> bool {
+        self.maturin()
+            .and_then(|maturin| maturin.sdist.as_ref())
+            .map_or(false, |sdist| sdist.vendor)
+    }
+
+    /// Returns the value of `[tool.maturin.sdist.from-git]` in pyproject.toml
+    pub fn sdist_from_git(&self) -> bool {
+        self.maturin()
+            .and_then(|maturin| maturin.sdist.as_ref())
+            .map_or(false, |sdist| sdist.from_git)
+    }
+
     /// Returns the value of `[tool.maturin.python-source]` in pyproject.toml
+    ///
+    /// If multiple directories are specified, this is the first one
     pub fn python_source(&self) -> Option<&Path> {
+        self.python_sources().first().map(PathBuf::as_path)
+    }
+
+    /// Returns all directories of `[tool.maturin.python-source]` in pyproject.toml
+    pub fn python_sources(&self) -> &[PathBuf] {
+        self.maturin()
+            .and_then(|maturin| maturin.python_source.as_ref())
+            .map(PythonSource::paths)
+            .unwrap_or_default()
+    }
+
+    /// Returns the directories of `[tool.maturin.data]` in pyproject.toml
+    pub fn data(&self) -> &[PathBuf] {
+        self.maturin()
+            .and_then(|maturin| maturin.data.as_ref())
+            .map(DataSource::paths)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.license-files]` in pyproject.toml
+    pub fn license_files(&self) -> Option<&[String]> {
+        self.maturin()?.license_files.as_deref()
+    }
+
+    /// Returns the value of `[tool.maturin.build-tag]` in pyproject.toml
+    pub fn build_tag(&self) -> Option<&str> {
+        self.maturin()?.build_tag.as_deref()
+    }
+
+    /// Returns the value of `[tool.maturin.metadata-source]` in pyproject.toml
+    pub fn metadata_source(&self) -> MetadataSource {
+        self.maturin()
+            .and_then(|maturin| maturin.metadata_source)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.project-urls]` in pyproject.toml
+    pub fn project_urls(&self) -> ProjectUrls {
+        self.maturin()
+            .and_then(|maturin| maturin.project_urls)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.stats]` in pyproject.toml
+    pub fn stats(&self) -> bool {
+        self.maturin()
+            .map(|maturin| maturin.stats)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.cache]` in pyproject.toml
+    pub fn cache(&self) -> bool {
+        self.maturin()
+            .map(|maturin| maturin.cache)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.rustflags]` in pyproject.toml
+    pub fn rustflags(&self) -> &[String] {
         self.maturin()
-            .and_then(|maturin| maturin.python_source.as_deref())
+            .and_then(|maturin| maturin.rustflags.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Returns the environment variables of `[tool.maturin.env]` and
+    /// `[tool.maturin.target.<triple>.env]` for the target. Like with `[env]` in the cargo
+    /// configuration, variables that are already set in the environment are left out.
+    pub fn build_env(&self, target_triple: &str) -> BTreeMap<String, String> {
+        let mut build_env = BTreeMap::new();
+        let target_env = self
+            .target_config(target_triple)
+            .and_then(|target_config| target_config.env.as_ref());
+        for (key, value) in self
+            .maturin()
+            .and_then(|maturin| maturin.env.as_ref())
+            .into_iter()
+            .chain(target_env)
+            .flatten()
+        {
+            if env::var_os(key).is_some() {
+                continue;
+            }
+            build_env.insert(key.clone(), value.clone());
+        }
+        build_env
+    }
+
+    /// Returns the value of `[tool.maturin.profiles]` in pyproject.toml
+    pub fn maturin_profiles(&self) -> Option<&BTreeMap<String, MaturinProfile>> {
+        self.maturin()?.profiles.as_ref()
     }
 
-    /// Returns the value of `[tool.maturin.data]` in pyproject.toml
-    pub fn data(&self) -> Option<&Path> {
-        self.maturin().and_then(|maturin| maturin.data.as_deref())
+    /// Replaces the values in `[tool.maturin]` with the ones of the profile
+    /// `[tool.maturin.profiles.<name>]`, so the rest of the build doesn't need to know about
+    /// profiles
+    pub fn apply_maturin_profile(&mut self, name: &str) -> Result<()> {
+        let tool_maturin = self
+            .tool
+            .get_or_insert(Tool { maturin: None })
+            .maturin
+            .get_or_insert_with(Default::default);
+        let profile = match tool_maturin
+            .profiles
+            .as_ref()
+            .and_then(|profiles| profiles.get(name))
+        {
+            Some(profile) => profile.clone(),
+            None => {
+                let available: Vec<&str> = tool_maturin
+                    .profiles
+                    .iter()
+                    .flat_map(|profiles| profiles.keys().map(String::as_str))
+                    .collect();
+                if available.is_empty() {
+                    bail!(ConfigError::new(format!(
+                        "The maturin profile `{}` doesn't exist, \
+                        there are no profiles in [tool.maturin.profiles] in pyproject.toml",
+                        name
+                    )));
+                }
+                bail!(ConfigError::new(format!(
+                    "The maturin profile `{}` doesn't exist, available profiles are: {}",
+                    name,
+                    available.join(", ")
+                )));
+            }
+        };
+        let MaturinProfile {
+            profile: cargo_profile,
+            features,
+            all_features,
+            no_default_features,
+            strip,
+            compatibility,
+            rustflags,
+        } = profile;
+        tool_maturin.profile = cargo_profile.or_else(|| tool_maturin.profile.take());
+        tool_maturin.features = features.or_else(|| tool_maturin.features.take());
+        tool_maturin.all_features = all_features.or(tool_maturin.all_features);
+        tool_maturin.no_default_features = no_default_features.or(tool_maturin.no_default_features);
+        tool_maturin.strip = strip.unwrap_or(tool_maturin.strip);
+        tool_maturin.compatibility = compatibility.or_else(|| tool_maturin.compatibility.take());
+        tool_maturin.rustflags = rustflags.or_else(|| tool_maturin.rustflags.take());
+        Ok(())
+    }
+
+    /// Whether there are any `[tool.maturin.'cfg(...)']` tables in pyproject.toml
+    pub fn has_cfg_configs(&self) -> bool {
+        self.maturin().map_or(false, |maturin| {
+            maturin.cfg.keys().any(|key| key.starts_with("cfg("))
+        })
+    }
+
+    /// Replaces the values in `[tool.maturin]` with the ones of the
+    /// `[tool.maturin.'cfg(...)']` tables matching the target, in the order of the tables.
+    ///
+    /// Returns the expressions of the tables that were applied
+    pub(crate) fn apply_cfg_configs(&mut self, target: &Target) -> Result<Vec<String>> {
+        let tool_maturin = match self.tool.as_mut().and_then(|x| x.maturin.as_mut()) {
+            Some(tool_maturin) => tool_maturin,
+            None => return Ok(Vec::new()),
+        };
+        let cfg_configs: Vec<_> = mem::take(&mut tool_maturin.cfg)
+            .into_iter()
+            .filter(|(key, _)| key.starts_with("cfg("))
+            .collect();
+        if cfg_configs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let target_cfg = TargetCfg::from_target(target)?;
+        let mut merged = toml_edit::easy::Value::try_from(&*tool_maturin)
+            .context("Failed to serialize [tool.maturin]")?;
+        let merged_table = merged.as_table_mut().unwrap();
+        let mut applied = Vec::new();
+        for (key, cfg_config) in cfg_configs {
+            let table = cfg_config.as_table().with_context(|| {
+                ConfigError::new(format!("[tool.maturin.'{}'] must be a table", key))
+            })?;
+            if let Some(name) = table
+                .keys()
+                .find(|name| name.starts_with("cfg(") || *name == "profiles" || *name == "target")
+            {
+                bail!(ConfigError::new(format!(
+                    "[tool.maturin.'{}'] can't contain `{}`, \
+                    only options of [tool.maturin] itself can depend on the target",
+                    key, name
+                )));
+            }
+            // Check the types here to get an error that points to the table
+            toml_edit::easy::Value::Table(table.clone())
+                .try_into::<ToolMaturin>()
+                .with_context(|| ConfigError::new(format!("Invalid [tool.maturin.'{}']", key)))?;
+            let applies = target_cfg
+                .evaluate(&key)
+                .with_context(|| ConfigError::new(format!("Invalid [tool.maturin.'{}']", key)))?;
+            if applies {
+                merged_table.extend(table.clone());
+                applied.push(key);
+            }
+        }
+        if !applied.is_empty() {
+            *tool_maturin = merged
+                .try_into()
+                .context("Failed to apply the [tool.maturin.'cfg(...)'] tables")?;
+        }
+        Ok(applied)
+    }
+
+    /// Returns the value of `[tool.maturin.target.<triple>]` in pyproject.toml
+    pub fn target_config(&self, target_triple: &str) -> Option<&TargetConfig> {
+        self.maturin()?.target.as_ref()?.get(target_triple)
+    }
+
+    /// Returns the value of `[tool.maturin.macos-deployment-target]` in pyproject.toml
+    pub fn macos_deployment_target(&self) -> Option<&str> {
+        self.maturin()?.macos_deployment_target.as_deref()
+    }
+
+    /// Returns the dependencies the `metadata` of the `[tool.maturin.target.<triple>]` and the
+    /// matching `[tool.maturin.target.'cfg(...)']` tables add to the wheels of the target, in the
+    /// order of the tables
+    pub(crate) fn target_dependencies(&self, target: &Target) -> Result<Vec<String>> {
+        let target_configs = match self.maturin().and_then(|maturin| maturin.target.as_ref()) {
+            Some(target_configs) => target_configs,
+            None => return Ok(Vec::new()),
+        };
+        let mut target_cfg = None;
+        let mut dependencies = Vec::new();
+        for (key, target_config) in target_configs {
+            let applies = if key.starts_with("cfg(") {
+                let only_metadata = TargetConfig {
+                    metadata: None,
+                    ..target_config.clone()
+                } == TargetConfig::default();
+                if !only_metadata {
+                    bail!(ConfigError::new(format!(
+                        "[tool.maturin.target.'{}'] only supports `metadata`, \
+                        the other options need the target triple as key",
+                        key
+                    )));
+                }
+                let target_cfg = match &target_cfg {
+                    Some(target_cfg) => target_cfg,
+                    None => target_cfg.insert(TargetCfg::from_target(target)?),
+                };
+                target_cfg.evaluate(key).with_context(|| {
+                    ConfigError::new(format!("Invalid [tool.maturin.target.'{}']", key))
+                })?
+            } else {
+                key == target.target_triple()
+            };
+            if applies {
+                dependencies.extend(
+                    target_config
+                        .metadata
+                        .iter()
+                        .flat_map(|metadata| metadata.dependencies.iter().flatten())
+                        .cloned(),
+                );
+            }
+        }
+        Ok(dependencies)
+    }
+
+    /// Returns the value of `[tool.maturin.targets]` in pyproject.toml
+    pub fn targets(&self) -> &[String] {
+        self.maturin()
+            .and_then(|maturin| maturin.targets.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether there are any `[tool.maturin.target.<triple>]` tables in pyproject.toml
+    pub fn has_target_configs(&self) -> bool {
+        self.maturin()
+            .and_then(|maturin| maturin.target.as_ref())
+            .map(|target| !target.is_empty())
+            .unwrap_or_default()
+    }
+
+    /// Whether any `[tool.maturin.target.<..>.metadata]` table adds dependencies, which makes
+    /// the requirements depend on the target a wheel is built for
+    pub(crate) fn has_target_dependencies(&self) -> bool {
+        self.maturin()
+            .and_then(|maturin| maturin.target.as_ref())
+            .into_iter()
+            .flat_map(|target_configs| target_configs.values())
+            .filter_map(|target_config| target_config.metadata.as_ref())
+            .any(|metadata| {
+                metadata
+                    .dependencies
+                    .as_ref()
+                    .map_or(false, |dependencies| !dependencies.is_empty())
+            })
+    }
+
+    /// Adds the features and rustflags of `[tool.maturin.target.<triple>]` to the ones in
+    /// `[tool.maturin]` and replaces `no-default-features`. Returns whether there was a table for
+    /// the target. `linker` and `link-args` are read from [PyProjectToml::target_config].
+    pub fn apply_target_config(&mut self, target_triple: &str) -> bool {
+        let target_config = match self.target_config(target_triple) {
+            Some(target_config) => target_config.clone(),
+            None => return false,
+        };
+        // `target_config` returning a value means `[tool.maturin]` exists
+        let tool_maturin = self.tool.as_mut().and_then(|x| x.maturin.as_mut()).unwrap();
+        if let Some(features) = target_config.features {
+            tool_maturin
+                .features
+                .get_or_insert_with(Vec::new)
+                .extend(features);
+        }
+        tool_maturin.no_default_features = target_config
+            .no_default_features
+            .or(tool_maturin.no_default_features);
+        if let Some(rustflags) = target_config.rustflags {
+            tool_maturin
+                .rustflags
+                .get_or_insert_with(Vec::new)
+                .extend(rustflags);
+        }
+        true
+    }
+
+    /// Returns the value of `[tool.maturin.dynamic]` in pyproject.toml
+    pub fn dynamic_hooks(&self) -> Option<&BTreeMap<String, DynamicHook>> {
+        self.maturin()?.dynamic.as_ref()
+    }
+
+    /// Returns the value of `[tool.maturin.hooks]` in pyproject.toml
+    pub fn hooks(&self) -> Option<&Hooks> {
+        self.maturin()?.hooks.as_ref()
+    }
+
+    /// Returns the value of `[tool.maturin.version]` in pyproject.toml
+    pub fn version_config(&self) -> Option<&VersionConfig> {
+        self.maturin()?.version.as_ref()
     }
 
     /// Returns the value of `[tool.maturin.manifest-path]` in pyproject.toml
@@ -260,6 +1113,97 @@ impl PyProjectToml {
         true
     }
 
+    /// Warns about the deprecated keys in `[tool.maturin]`, which still work until the version
+    /// that removes them
+    ///
+    /// Returns true if there are none
+    pub fn warn_deprecated_keys(&self) -> bool {
+        for deprecated_key in &self.deprecated_keys {
+            eprintln!("⚠️  Warning: {}", deprecated_key);
+        }
+        if self.deprecated_keys.is_empty() {
+            return true;
+        }
+        eprintln!("💡 Run `maturin migrate-config --write` to update pyproject.toml");
+        false
+    }
+
+    /// Fails if `build-system.requires` pins maturin to versions that don't include the running
+    /// one, e.g. when a build frontend without build isolation picked up whatever maturin was
+    /// installed, so that the build doesn't subtly misbehave with options the version doesn't know
+    ///
+    /// Requirements with environment markers only apply if the markers match `python`, the
+    /// interpreter of the build frontend, and are skipped if it isn't known.
+    pub fn check_maturin_version(&self, python: Option<&Path>) -> Result<()> {
+        let maturin = env!("CARGO_PKG_NAME");
+        let version = env!("CARGO_PKG_VERSION");
+        let mut marker_environment = None;
+        for requirement in &self.build_system.requires {
+            let name_end = requirement
+                .find(|c: char| !(c.is_ascii_alphanumeric() || "-_.".contains(c)))
+                .unwrap_or(requirement.len());
+            // Names are compared normalized as specified in PEP 503, which for `maturin` means
+            // case insensitive
+            if !requirement[..name_end].eq_ignore_ascii_case(maturin) {
+                continue;
+            }
+            let (specifiers, marker) = match requirement[name_end..].split_once(';') {
+                Some((specifiers, marker)) => (specifiers.trim(), Some(marker.trim())),
+                None => (requirement[name_end..].trim(), None),
+            };
+            // Extras don't constrain the version
+            let specifiers = match specifiers.strip_prefix('[') {
+                Some(rest) => rest.split_once(']').map_or("", |(_, rest)| rest).trim(),
+                None => specifiers,
+            };
+            if specifiers.is_empty() {
+                continue;
+            }
+            if let Some(marker) = marker {
+                let python = match python {
+                    Some(python) => python,
+                    None => continue,
+                };
+                let marker_environment = match &mut marker_environment {
+                    Some(marker_environment) => marker_environment,
+                    None => marker_environment.insert(MarkerEnvironment::from_interpreter(
+                        python,
+                        &BTreeMap::new(),
+                    )?),
+                };
+                let applies = marker_environment.evaluate(marker, "").with_context(|| {
+                    ConfigError::new(format!(
+                        "Invalid requirement `{}` in pyproject.toml",
+                        requirement
+                    ))
+                })?;
+                if !applies {
+                    continue;
+                }
+            }
+            let matches =
+                crate::marker::matches_specifiers(version, specifiers).with_context(|| {
+                    ConfigError::new(format!(
+                        "Invalid requirement `{}` in pyproject.toml",
+                        requirement
+                    ))
+                })?;
+            if !matches {
+                bail!(ConfigError::new(format!(
+                    "pyproject.toml requires `{requirement}`, but {maturin} {version} is running as \
+                    build backend. This usually happens when building without build isolation, \
+                    e.g. with `pip install --no-build-isolation`, where the installed {maturin} is \
+                    used. Install a matching version with `pip install \"{requirement}\"` or build \
+                    with build isolation",
+                    requirement = requirement,
+                    maturin = maturin,
+                    version = version,
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Having a pyproject.toml without `build-backend` set to `maturin`
     /// may result in build errors when build from source distribution
     ///
@@ -278,14 +1222,80 @@ impl PyProjectToml {
     }
 }
 
+/// Interpolates environment variables in all strings of a toml value, `key` is the dotted path
+/// of the value used in error messages.
+///
+/// `[tool.maturin.hooks]` is left alone since the shell running the hooks expands variables
+fn interpolate_value(value: &mut toml_edit::easy::Value, key: &str) -> Result<()> {
+    use toml_edit::easy::Value;
+
+    match value {
+        Value::String(string) => {
+            *string =
+                interpolate_env(string).with_context(|| format!("Invalid value of {}", key))?;
+        }
+        Value::Array(array) => {
+            for (index, item) in array.iter_mut().enumerate() {
+                interpolate_value(item, &format!("{}[{}]", key, index))?;
+            }
+        }
+        Value::Table(table) => {
+            for (name, item) in table.iter_mut() {
+                if key == "tool.maturin" && name == "hooks" {
+                    continue;
+                }
+                interpolate_value(item, &format!("{}.{}", key, name))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replaces `${VAR}` with the value of the environment variable `VAR`, `$$` is a literal `$`
+pub(crate) fn interpolate_env(value: &str) -> Result<String> {
+    let mut interpolated = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        interpolated.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            interpolated.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .with_context(|| format!("Unterminated `${{` in `{}`", value))?;
+            let name = &after[..end];
+            let var = env::var(name).with_context(|| {
+                format!(
+                    "The environment variable {} used in `{}` is not set",
+                    name, value
+                )
+            })?;
+            interpolated.push_str(&var);
+            rest = &after[end + 1..];
+        } else {
+            interpolated.push('$');
+        }
+    }
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        pyproject_toml::{Format, Formats, GlobPattern, ToolMaturin},
-        PyProjectToml,
+        pyproject_toml::{
+            interpolate_env, CargoLockInclusion, Format, Formats, GlobPattern, LtoConfig, LtoMode,
+            ToolMaturin,
+        },
+        PlatformTag, PyProjectToml, Target,
     };
     use fs_err as fs;
     use pretty_assertions::assert_eq;
+    use std::collections::BTreeMap;
+    use std::env;
     use std::path::Path;
     use tempfile::TempDir;
 
@@ -308,11 +1318,19 @@ mod tests {
             no-default-features = true
             locked = true
             rustc-args = ["-Z", "unstable-options"]
+            include-cargo-lock = "never"
             "#,
         )
         .unwrap();
         let pyproject = PyProjectToml::new(pyproject_file).unwrap();
         assert_eq!(pyproject.manifest_path(), Some(Path::new("Cargo.toml")));
+        // `manylinux` is the deprecated name of `compatibility`
+        assert_eq!(
+            pyproject.compatibility(),
+            Some(PlatformTag::Manylinux { x: 2, y: 12 })
+        );
+        assert!(!pyproject.warn_deprecated_keys());
+        assert_eq!(pyproject.include_cargo_lock(), CargoLockInclusion::Never);
 
         let maturin = pyproject.maturin().unwrap();
         assert_eq!(maturin.profile.as_deref(), Some("dev"));
@@ -330,6 +1348,338 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_apply_maturin_profile() {
+        let pyproject_toml = r#"
+            [build-system]
+            requires = ["maturin"]
+            build-backend = "maturin"
+
+            [tool.maturin]
+            features = ["foo"]
+            strip = false
+
+            [tool.maturin.profiles.ci]
+            profile = "dist"
+            features = ["bar"]
+            strip = true
+            compatibility = "manylinux2014"
+            rustflags = ["-C", "target-cpu=x86-64-v2"]
+
+            [tool.maturin.profiles.dev]
+            no-default-features = true
+            "#;
+        let pyproject: PyProjectToml = toml_edit::easy::from_str(pyproject_toml).unwrap();
+
+        let mut ci = pyproject.clone();
+        ci.apply_maturin_profile("ci").unwrap();
+        let maturin = ci.maturin().unwrap();
+        assert_eq!(maturin.profile.as_deref(), Some("dist"));
+        assert_eq!(maturin.features, Some(vec!["bar".to_string()]));
+        assert!(ci.strip());
+        assert_eq!(
+            ci.compatibility(),
+            Some(PlatformTag::Manylinux { x: 2, y: 17 })
+        );
+        assert_eq!(ci.rustflags(), ["-C", "target-cpu=x86-64-v2"]);
+
+        let mut dev = pyproject.clone();
+        dev.apply_maturin_profile("dev").unwrap();
+        let maturin = dev.maturin().unwrap();
+        // Values that aren't in the profile are kept
+        assert_eq!(maturin.features, Some(vec!["foo".to_string()]));
+        assert_eq!(maturin.no_default_features, Some(true));
+        assert!(!dev.strip());
+
+        let err = pyproject
+            .clone()
+            .apply_maturin_profile("release")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The maturin profile `release` doesn't exist, available profiles are: ci, dev"
+        );
+    }
+
+    #[test]
+    fn test_apply_target_config() {
+        let pyproject_toml = r#"
+            [build-system]
+            requires = ["maturin"]
+            build-backend = "maturin"
+
+            [tool.maturin]
+            features = ["pyo3/extension-module"]
+            rustflags = ["-C", "debuginfo=1"]
+
+            [tool.maturin.target.x86_64-unknown-linux-gnu]
+            features = ["simd"]
+            rustflags = ["-C", "target-cpu=x86-64-v2"]
+            linker = "x86_64-linux-gnu-gcc"
+            link-args = ["-Wl,--as-needed"]
+
+            [tool.maturin.target.aarch64-apple-darwin]
+            no-default-features = true
+            "#;
+        let pyproject: PyProjectToml = toml_edit::easy::from_str(pyproject_toml).unwrap();
+        assert!(pyproject.has_target_configs());
+
+        let mut linux = pyproject.clone();
+        assert!(linux.apply_target_config("x86_64-unknown-linux-gnu"));
+        let maturin = linux.maturin().unwrap();
+        assert_eq!(
+            maturin.features,
+            Some(vec![
+                "pyo3/extension-module".to_string(),
+                "simd".to_string()
+            ])
+        );
+        assert!(maturin.no_default_features.is_none());
+        assert_eq!(
+            linux.rustflags(),
+            ["-C", "debuginfo=1", "-C", "target-cpu=x86-64-v2"]
+        );
+        let target_config = linux.target_config("x86_64-unknown-linux-gnu").unwrap();
+        assert_eq!(
+            target_config.linker.as_deref(),
+            Some("x86_64-linux-gnu-gcc")
+        );
+        assert_eq!(
+            target_config.link_args,
+            Some(vec!["-Wl,--as-needed".to_string()])
+        );
+
+        let mut macos = pyproject.clone();
+        assert!(macos.apply_target_config("aarch64-apple-darwin"));
+        let maturin = macos.maturin().unwrap();
+        assert_eq!(
+            maturin.features,
+            Some(vec!["pyo3/extension-module".to_string()])
+        );
+        assert_eq!(maturin.no_default_features, Some(true));
+        assert_eq!(macos.rustflags(), ["-C", "debuginfo=1"]);
+
+        let mut windows = pyproject;
+        assert!(!windows.apply_target_config("x86_64-pc-windows-msvc"));
+    }
+
+    #[test]
+    fn test_build_env() {
+        env::set_var("MATURIN_TEST_BUILD_ENV_PREFIX", "/opt/openssl");
+        env::set_var("MATURIN_TEST_BUILD_ENV_SET", "from-environment");
+        let pyproject: PyProjectToml = r#"
+            [build-system]
+            requires = ["maturin"]
+            build-backend = "maturin"
+
+            [tool.maturin.env]
+            OPENSSL_DIR = "${MATURIN_TEST_BUILD_ENV_PREFIX}/lib"
+            PRICE = "$$5"
+            MATURIN_TEST_BUILD_ENV_SET = "from-pyproject"
+
+            [tool.maturin.target.aarch64-unknown-linux-gnu.env]
+            OPENSSL_DIR = "${MATURIN_TEST_BUILD_ENV_PREFIX}/aarch64"
+            "#
+        .parse()
+        .unwrap();
+        let build_env = pyproject.build_env("x86_64-unknown-linux-gnu");
+        assert_eq!(
+            build_env,
+            BTreeMap::from([
+                ("OPENSSL_DIR".to_string(), "/opt/openssl/lib".to_string()),
+                ("PRICE".to_string(), "$5".to_string()),
+            ])
+        );
+        let build_env = pyproject.build_env("aarch64-unknown-linux-gnu");
+        assert_eq!(build_env["OPENSSL_DIR"], "/opt/openssl/aarch64");
+
+        assert!(interpolate_env("${MATURIN_TEST_BUILD_ENV_UNSET}").is_err());
+        assert!(interpolate_env("${MATURIN_TEST_BUILD_ENV_PREFIX").is_err());
+    }
+
+    #[test]
+    fn test_lto_config() {
+        let pyproject: PyProjectToml = r#"
+            [build-system]
+            requires = ["maturin"]
+            build-backend = "maturin"
+
+            [tool.maturin.lto]
+            mode = "thin"
+            linker-plugin = true
+            clang = "clang-17"
+            "#
+        .parse()
+        .unwrap();
+        assert_eq!(
+            pyproject.lto(),
+            Some(&LtoConfig {
+                mode: Some(LtoMode::Thin),
+                linker_plugin: true,
+                clang: Some("clang-17".to_string()),
+                lld: None,
+            })
+        );
+        let invalid: Result<PyProjectToml, _> = r#"
+            [build-system]
+            requires = ["maturin"]
+            build-backend = "maturin"
+
+            [tool.maturin.lto]
+            mode = "full"
+            "#
+        .parse();
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_interpolate_maturin_config() {
+        env::set_var("MATURIN_TEST_INTERPOLATE_DIR", "native");
+        env::set_var("MATURIN_TEST_INTERPOLATE_FEATURE", "simd");
+        let pyproject: PyProjectToml = r#"
+            [build-system]
+            requires = ["maturin"]
+            build-backend = "maturin"
+
+            [project]
+            name = "${NOT_INTERPOLATED}"
+
+            [tool.maturin]
+            manifest-path = "${MATURIN_TEST_INTERPOLATE_DIR}/Cargo.toml"
+            features = ["${MATURIN_TEST_INTERPOLATE_FEATURE}", "$${literal}"]
+
+            [tool.maturin.hooks]
+            pre-build = ["echo ${LEFT_TO_THE_SHELL}"]
+            "#
+        .parse()
+        .unwrap();
+        assert_eq!(
+            pyproject.manifest_path(),
+            Some(Path::new("native/Cargo.toml"))
+        );
+        assert_eq!(
+            pyproject.maturin().unwrap().features,
+            Some(vec!["simd".to_string(), "${literal}".to_string()])
+        );
+        assert_eq!(pyproject.project_name(), Some("${NOT_INTERPOLATED}"));
+        assert_eq!(
+            pyproject.hooks().unwrap().pre_build,
+            Some(vec!["echo ${LEFT_TO_THE_SHELL}".to_string()])
+        );
+
+        let err = r#"
+            [build-system]
+            requires = ["maturin"]
+            build-backend = "maturin"
+
+            [tool.maturin]
+            features = ["${MATURIN_TEST_INTERPOLATE_UNSET}"]
+            "#
+        .parse::<PyProjectToml>()
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Invalid value of tool.maturin.features[0]");
+    }
+
+    #[test]
+    fn test_target_dependencies() {
+        let target = Target::from_target_triple(None).unwrap();
+        let pyproject_toml = format!(
+            r#"
+            [build-system]
+            requires = ["maturin"]
+            build-backend = "maturin"
+
+            [tool.maturin.target.{}.metadata]
+            dependencies = ["host-only"]
+
+            [tool.maturin.target.aarch64-unknown-linux-musl.metadata]
+            dependencies = ["musl-only"]
+
+            [tool.maturin.target.'cfg(any(unix, windows))'.metadata]
+            dependencies = ["cffi>=1.15"]
+
+            [tool.maturin.target.'cfg(not(any(unix, windows)))'.metadata]
+            dependencies = ["never"]
+            "#,
+            target.target_triple()
+        );
+        let pyproject: PyProjectToml = toml_edit::easy::from_str(&pyproject_toml).unwrap();
+        let mut dependencies = pyproject.target_dependencies(&target).unwrap();
+        dependencies.sort();
+        assert_eq!(dependencies, ["cffi>=1.15", "host-only"]);
+        assert!(pyproject.has_target_dependencies());
+
+        let pyproject_toml = r#"
+            [build-system]
+            requires = ["maturin"]
+            build-backend = "maturin"
+
+            [tool.maturin.target.'cfg(windows)']
+            features = ["windows"]
+            "#;
+        let pyproject: PyProjectToml = toml_edit::easy::from_str(pyproject_toml).unwrap();
+        assert!(pyproject.target_dependencies(&target).is_err());
+        assert!(!pyproject.has_target_dependencies());
+    }
+
+    #[test]
+    fn test_apply_cfg_configs() {
+        let target = Target::from_target_triple(None).unwrap();
+        let mut pyproject: PyProjectToml = r#"
+            [build-system]
+            requires = ["maturin"]
+            build-backend = "maturin"
+
+            [tool.maturin]
+            features = ["base"]
+            manylinux = "off"
+            unknown-option = true
+
+            [tool.maturin.'cfg(any(unix, windows))']
+            strip = true
+            features = ["native"]
+
+            [tool.maturin.'cfg(target_os = "none")']
+            features = ["bare-metal"]
+            "#
+        .parse()
+        .unwrap();
+        assert!(pyproject.has_cfg_configs());
+        let applied = pyproject.apply_cfg_configs(&target).unwrap();
+        assert_eq!(applied, vec!["cfg(any(unix, windows))".to_string()]);
+        let maturin = pyproject.maturin().unwrap();
+        assert!(maturin.strip);
+        assert_eq!(maturin.features, Some(vec!["native".to_string()]));
+        assert_eq!(maturin.compatibility, Some(PlatformTag::Linux));
+        assert!(!pyproject.has_cfg_configs());
+
+        let mut nested: PyProjectToml = toml_edit::easy::from_str(
+            r#"
+            [build-system]
+            requires = ["maturin"]
+            build-backend = "maturin"
+
+            [tool.maturin.'cfg(unix)'.target.x86_64-unknown-linux-gnu]
+            features = ["x86"]
+            "#,
+        )
+        .unwrap();
+        assert!(nested.apply_cfg_configs(&target).is_err());
+
+        let mut invalid: PyProjectToml = toml_edit::easy::from_str(
+            r#"
+            [build-system]
+            requires = ["maturin"]
+            build-backend = "maturin"
+
+            [tool.maturin.'cfg(unix)']
+            strip = "yes"
+            "#,
+        )
+        .unwrap();
+        assert!(invalid.apply_cfg_configs(&target).is_err());
+    }
+
     #[test]
     fn test_warn_missing_maturin_version() {
         let with_constraint = PyProjectToml::new("test-crates/pyo3-pure/pyproject.toml").unwrap();
@@ -352,6 +1702,76 @@ mod tests {
         assert!(!without_constraint.warn_missing_maturin_version());
     }
 
+    #[test]
+    fn test_check_maturin_version() {
+        let pyproject = |requires: &str| {
+            toml_edit::easy::from_str::<PyProjectToml>(&format!(
+                "[build-system]\nrequires = [{}]\nbuild-backend = \"maturin\"",
+                requires
+            ))
+            .unwrap()
+        };
+        assert!(pyproject(r#""maturin>=0.13""#)
+            .check_maturin_version(None)
+            .is_ok());
+        assert!(pyproject(r#""maturin""#)
+            .check_maturin_version(None)
+            .is_ok());
+        assert!(pyproject(r#""maturin-extra>=99""#)
+            .check_maturin_version(None)
+            .is_ok());
+        assert!(pyproject(r#""maturin[zig] >=0.1""#)
+            .check_maturin_version(None)
+            .is_ok());
+        assert!(pyproject(r#""Maturin>=99""#)
+            .check_maturin_version(None)
+            .is_err());
+        // Without the interpreter the marker can't be evaluated
+        assert!(pyproject(r#""maturin>=99; python_version > '3.0'""#)
+            .check_maturin_version(None)
+            .is_ok());
+        let err = pyproject(r#""maturin>=1.5,<2""#)
+            .check_maturin_version(None)
+            .unwrap_err();
+        assert!(err.to_string().contains("requires `maturin>=1.5,<2`"));
+        assert!(pyproject(r#""maturin>=one""#)
+            .check_maturin_version(None)
+            .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_maturin_version_markers() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let python = temp_dir.path().join("python");
+        fs::write(
+            &python,
+            "#!/bin/sh\necho '{\"python_version\": \"3.10\", \"sys_platform\": \"linux\"}'\n",
+        )
+        .unwrap();
+        fs::set_permissions(&python, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let pyproject = toml_edit::easy::from_str::<PyProjectToml>(
+            r#"
+            [build-system]
+            requires = ["maturin>=99; python_version < '3'", "maturin>=0.1; python_version >= '3'"]
+            build-backend = "maturin"
+            "#,
+        )
+        .unwrap();
+        assert!(pyproject.check_maturin_version(Some(&python)).is_ok());
+        let pyproject = toml_edit::easy::from_str::<PyProjectToml>(
+            r#"
+            [build-system]
+            requires = ["maturin>=99; sys_platform == 'linux'"]
+            build-backend = "maturin"
+            "#,
+        )
+        .unwrap();
+        assert!(pyproject.check_maturin_version(Some(&python)).is_err());
+    }
+
     #[test]
     fn deserialize_include_exclude() {
         let single = r#"include = ["single"]"#;
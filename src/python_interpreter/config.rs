@@ -169,6 +169,9 @@ impl InterpreterConfig {
                 }
             }
             InterpreterKind::PyPy => abi_tag.unwrap_or_else(|| "pp73".to_string()),
+            // The python version, the version of graalpy itself is only part of the extension
+            // suffix
+            InterpreterKind::GraalPy => abi_tag.unwrap_or_else(|| format!("{}{}", major, minor)),
         };
         let file_ext = if target.is_windows() { "pyd" } else { "so" };
         let ext_suffix = if target.is_linux() || target.is_macos() {
@@ -203,6 +206,11 @@ impl InterpreterConfig {
                         file_ext,
                     )
                 }),
+                // Eg: .graalpy240-310-native-x86_64-linux.so, which can't be derived from the
+                // python version
+                InterpreterKind::GraalPy => {
+                    ext_suffix.context("missing value for ext_suffix, which graalpy needs")?
+                }
             }
         } else {
             ext_suffix.context("missing value for ext_suffix")?
@@ -227,7 +235,11 @@ shared=true
 abi3=false
 build_flags=WITH_THREAD
 suppress_build_script_link_lines=false"#,
-            implementation = self.interpreter_kind,
+            // pyo3 calls graalpy by its `platform.python_implementation()`
+            implementation = match self.interpreter_kind {
+                InterpreterKind::GraalPy => "GraalVM".to_string(),
+                kind => kind.to_string(),
+            },
             major = self.major,
             minor = self.minor,
         );
@@ -1,10 +1,32 @@
 import json
 import platform
 import sys
-import sysconfig
 import struct
 
-if platform.python_implementation() == "PyPy":
+try:
+    import sysconfig
+except ImportError:
+    # The embeddable distribution of CPython for windows can be stripped down to not include
+    # sysconfig, it only has a few config vars on windows anyway
+    sysconfig = None
+
+
+def get_config_var(name):
+    return sysconfig.get_config_var(name) if sysconfig else None
+
+
+def get_platform():
+    if sysconfig:
+        return sysconfig.get_platform()
+    if sys.platform != "win32":
+        return sys.platform
+    # The same as sysconfig.get_platform() on windows
+    if struct.calcsize("P") == 4:
+        return "win32"
+    return "win-arm64" if platform.machine().lower() == "arm64" else "win-amd64"
+
+
+if platform.python_implementation() == "PyPy" or not sysconfig:
     # Workaround for PyPy 3.6 on windows:
     #  - sysconfig.get_config_var("EXT_SUFFIX") differs to importlib until
     #    Python 3.8
@@ -13,11 +35,29 @@ if platform.python_implementation() == "PyPy":
     #
     # This workaround can probably be removed once PyPy for Python 3.8 is the
     # main PyPy version.
+    #
+    # Without sysconfig, this is also the suffix the interpreter loads
     import importlib.machinery
 
     ext_suffix = importlib.machinery.EXTENSION_SUFFIXES[0]
 else:
-    ext_suffix = sysconfig.get_config_var("EXT_SUFFIX")
+    ext_suffix = get_config_var("EXT_SUFFIX")
+
+def get_macos_link_args():
+    """The linker arguments to link extension modules explicitly against the python on macOS,
+    or None if its sysconfig links them with `-undefined dynamic_lookup` like most pythons"""
+    if sys.platform != "darwin" or not sysconfig:
+        return None
+    if "dynamic_lookup" in (get_config_var("LDSHARED") or ""):
+        return None
+    framework = get_config_var("PYTHONFRAMEWORK")
+    if framework:
+        return ["-F" + get_config_var("PYTHONFRAMEWORKPREFIX"), "-framework", framework]
+    if get_config_var("Py_ENABLE_SHARED"):
+        return ["-L" + get_config_var("LIBDIR"), "-lpython" + get_config_var("LDVERSION")]
+    # A static libpython can't be linked into extension modules
+    return None
+
 
 metadata = {
     # sys.implementation.name can differ from platform.python_implementation(), for example
@@ -26,16 +66,20 @@ metadata = {
     "executable": sys.executable or None,
     "major": sys.version_info.major,
     "minor": sys.version_info.minor,
-    "abiflags": sysconfig.get_config_var("ABIFLAGS"),
+    "abiflags": get_config_var("ABIFLAGS"),
     "interpreter": platform.python_implementation().lower(),
     "ext_suffix": ext_suffix,
-    "soabi": sysconfig.get_config_var("SOABI") or None,
-    "abi_tag": (sysconfig.get_config_var("SOABI") or "-").split("-")[1] or None,
-    "platform": sysconfig.get_platform(),
+    "soabi": get_config_var("SOABI") or None,
+    "abi_tag": (get_config_var("SOABI") or "-").split("-")[1] or None,
+    "platform": get_platform(),
     # This one isn't technically necessary, but still very useful for sanity checks
     "system": platform.system().lower(),
     # This one is for generating a config file for pyo3
     "pointer_width": struct.calcsize("P") * 8,
+    # macOS only, the wheels are tagged for the deployment target python was built for
+    "framework": bool(get_config_var("PYTHONFRAMEWORK")),
+    "macos_link_args": get_macos_link_args(),
+    "macos_deployment_target": get_config_var("MACOSX_DEPLOYMENT_TARGET") or None,
 }
 
 print(json.dumps(metadata))
@@ -1,10 +1,14 @@
 pub use self::config::InterpreterConfig;
 use crate::auditwheel::PlatformTag;
+use crate::data::{read_refreshed, Dataset, PythonVersions};
+use crate::target::Arch;
 use crate::{BridgeModel, Target};
 use anyhow::{bail, format_err, Context, Result};
+use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::Deserialize;
 use std::collections::HashSet;
+use std::env;
 use std::fmt;
 use std::io::{self, Write};
 use std::ops::Deref;
@@ -22,12 +26,31 @@ pub const MINIMUM_PYTHON_MINOR: usize = 7;
 const MAXIMUM_PYTHON_MINOR: usize = 12;
 const MAXIMUM_PYPY_MINOR: usize = 10;
 
+/// The newest python versions to look for, raised by `maturin self update-data`
+static PYTHON_VERSIONS: Lazy<PythonVersions> = Lazy::new(|| {
+    let built_in = PythonVersions {
+        cpython: MAXIMUM_PYTHON_MINOR,
+        pypy: MAXIMUM_PYPY_MINOR,
+    };
+    match read_refreshed(Dataset::PythonVersions) {
+        Some(refreshed) => {
+            let refreshed: PythonVersions =
+                serde_json::from_str(&refreshed).expect("refreshed python versions were validated");
+            PythonVersions {
+                cpython: refreshed.cpython.max(built_in.cpython),
+                pypy: refreshed.pypy.max(built_in.pypy),
+            }
+        }
+        None => built_in,
+    }
+});
+
 /// Identifies conditions where we do not want to build wheels
 fn windows_interpreter_no_build(
     major: usize,
     minor: usize,
-    target_width: usize,
-    pointer_width: usize,
+    target_arch: Arch,
+    python_arch: Arch,
     min_python_minor: usize,
 ) -> bool {
     // Python 2 support has been dropped
@@ -40,12 +63,12 @@ fn windows_interpreter_no_build(
         return true;
     }
 
-    // There can be 32-bit installations on a 64-bit machine, but we can't link
-    // those for 64-bit targets
-    if pointer_width != target_width {
-        println!(
-            "👽 {}.{} is installed as {}-bit, while the target is {}-bit. Skipping.",
-            major, minor, pointer_width, target_width
+    // There can be 32-bit installations on a 64-bit machine and x64 installations on an arm64
+    // machine, but we can't link those for the other architecture
+    if python_arch != target_arch {
+        eprintln!(
+            "👽 {}.{} is installed for {}, while the target is {}. Skipping.",
+            major, minor, python_arch, target_arch
         );
         return true;
     }
@@ -89,7 +112,11 @@ fn windows_interpreter_no_build(
 /// As well as the version numbers, etc. of the interpreters we also have to find the
 /// pointer width to make sure that the pointer width (32-bit or 64-bit) matches across
 /// platforms.
-fn find_all_windows(target: &Target, min_python_minor: usize) -> Result<Vec<String>> {
+fn find_all_windows(
+    target: &Target,
+    min_python_minor: usize,
+    alternative_implementations: bool,
+) -> Result<Vec<String>> {
     let code = "import sys; print(sys.executable or '')";
     let mut interpreter = vec![];
     let mut versions_found = HashSet::new();
@@ -129,19 +156,25 @@ fn find_all_windows(target: &Target, min_python_minor: usize) -> Result<Vec<Stri
                         .unwrap_or("64")
                         .parse::<usize>()
                         .context("Expected a digit for pointer width")?;
+                    let (python_arch, version) = if capture.get(4).is_some() {
+                        (Arch::Aarch64, format!("-V:{}.{}-arm64", major, minor))
+                    } else if pointer_width == 32 {
+                        (Arch::X86, format!("-{}.{}-32", major, minor))
+                    } else {
+                        (Arch::X86_64, format!("-{}.{}-64", major, minor))
+                    };
 
                     if windows_interpreter_no_build(
                         major,
                         minor,
-                        target.pointer_width(),
-                        pointer_width,
+                        target.target_arch(),
+                        python_arch,
                         min_python_minor,
                     ) {
                         continue;
                     }
 
                     let executable = capture.get(6).unwrap().as_str();
-                    let version = format!("-{}.{}-{}", major, minor, pointer_width);
                     let output = Command::new(executable)
                         .args(["-c", code])
                         .output()
@@ -186,12 +219,12 @@ fn find_all_windows(target: &Target, min_python_minor: usize) -> Result<Vec<Stri
             } else {
                 Path::new(&path).join("python")
             };
-            if let Some(python_info) = windows_python_info(&executable)? {
+            if let Some((python_info, python_arch)) = windows_python_info(&executable)? {
                 if windows_interpreter_no_build(
                     python_info.major,
                     python_info.minor,
-                    target.pointer_width(),
-                    python_info.pointer_width.unwrap(),
+                    target.target_arch(),
+                    python_arch,
                     min_python_minor,
                 ) {
                     continue;
@@ -203,15 +236,15 @@ fn find_all_windows(target: &Target, min_python_minor: usize) -> Result<Vec<Stri
     }
 
     // Fallback to pythonX.Y for Microsoft Store versions
-    for minor in min_python_minor..=MAXIMUM_PYTHON_MINOR {
+    for minor in min_python_minor..=PYTHON_VERSIONS.cpython {
         if !versions_found.contains(&(3, minor)) {
             let executable = format!("python3.{}.exe", minor);
-            if let Some(python_info) = windows_python_info(Path::new(&executable))? {
+            if let Some((python_info, python_arch)) = windows_python_info(Path::new(&executable))? {
                 if windows_interpreter_no_build(
                     python_info.major,
                     python_info.minor,
-                    target.pointer_width(),
-                    python_info.pointer_width.unwrap(),
+                    target.target_arch(),
+                    python_arch,
                     min_python_minor,
                 ) {
                     continue;
@@ -222,6 +255,57 @@ fn find_all_windows(target: &Target, min_python_minor: usize) -> Result<Vec<Stri
         }
     }
 
+    // The MinGW pythons of MSYS2 aren't registered with the py launcher, they load the
+    // extensions of the windows-gnu targets
+    if target.is_windows_gnu() {
+        let mut msys2_roots = vec![PathBuf::from(MSYS2_ROOT)];
+        // Set by the MSYS2 shells, e.g. `C:/msys64/ucrt64`, for installations in other places
+        if let Some(prefix) = env::var_os("MSYSTEM_PREFIX") {
+            if let Some(root) = Path::new(&prefix).parent() {
+                msys2_roots.insert(0, root.to_path_buf());
+            }
+        }
+        let mut seen = HashSet::new();
+        for root in msys2_roots {
+            for executable in msys2_python_candidates(target.target_arch(), &root) {
+                if !seen.insert(normalize_windows_path(&executable)) {
+                    continue;
+                }
+                if let Some((python_info, python_arch)) = windows_python_info(&executable)? {
+                    if windows_interpreter_no_build(
+                        python_info.major,
+                        python_info.minor,
+                        target.target_arch(),
+                        python_arch,
+                        min_python_minor,
+                    ) {
+                        continue;
+                    }
+                    interpreter.push(executable.to_string_lossy().to_string());
+                }
+            }
+        }
+    }
+
+    // PyPy and GraalPy aren't registered with the py launcher either, they're found on PATH
+    if alternative_implementations {
+        for executable in alternative_executables(min_python_minor) {
+            let executable = format!("{}.exe", executable);
+            if let Some((python_info, python_arch)) = windows_python_info(Path::new(&executable))? {
+                if windows_interpreter_no_build(
+                    python_info.major,
+                    python_info.minor,
+                    target.target_arch(),
+                    python_arch,
+                    min_python_minor,
+                ) {
+                    continue;
+                }
+                interpreter.push(executable);
+            }
+        }
+    }
+
     if interpreter.is_empty() {
         bail!(
             "Could not find any interpreters, are you sure you have python installed on your PATH?"
@@ -230,13 +314,46 @@ fn find_all_windows(target: &Target, min_python_minor: usize) -> Result<Vec<Stri
     Ok(interpreter)
 }
 
-fn windows_python_info(executable: &Path) -> Result<Option<InterpreterConfig>> {
+/// The names of the PyPy and GraalPy executables to look for, GraalPy doesn't install versioned
+/// ones
+fn alternative_executables(min_python_minor: usize) -> Vec<String> {
+    let mut executables: Vec<String> = (min_python_minor..=PYTHON_VERSIONS.pypy)
+        .map(|minor| format!("pypy3.{}", minor))
+        .collect();
+    executables.push("graalpy".to_string());
+    executables
+}
+
+/// The default install location of MSYS2, which is also preinstalled there on the windows
+/// runners of GitHub Actions
+const MSYS2_ROOT: &str = r"C:\msys64";
+
+/// The pythons of the MSYS2 environments whose MinGW toolchain builds for the architecture,
+/// e.g. `C:\msys64\ucrt64\bin\python3.exe`. The CLANG64 and CLANG32 environments use LLVM's
+/// libc++ and libunwind like the `*-pc-windows-gnullvm` targets, not the windows-gnu ones.
+fn msys2_python_candidates(arch: Arch, msys2_root: &Path) -> Vec<PathBuf> {
+    let environments: &[&str] = match arch {
+        Arch::X86_64 => &["ucrt64", "mingw64"],
+        Arch::X86 => &["mingw32"],
+        _ => &[],
+    };
+    environments
+        .iter()
+        .map(|environment| msys2_root.join(environment).join("bin").join("python3.exe"))
+        .collect()
+}
+
+/// Returns the version and the architecture of a windows python, or `None` if there is no
+/// python at that location
+fn windows_python_info(executable: &Path) -> Result<Option<(InterpreterConfig, Arch)>> {
     let python_info = Command::new(executable)
         .arg("-c")
         .arg("import sys; print(sys.version)")
         .output();
 
     let python_info = match python_info {
+        // A Windows Store alias without an installed python fails, so does a broken interpreter
+        Ok(python_info) if !python_info.status.success() => return Ok(None),
         Ok(python_info) => python_info,
         Err(err) => {
             if err.kind() == io::ErrorKind::NotFound {
@@ -252,16 +369,13 @@ fn windows_python_info(executable: &Path) -> Result<Option<InterpreterConfig>> {
     };
 
     let version_info = str::from_utf8(&python_info.stdout).unwrap();
-    let expr = Regex::new(r"(\d).(\d).(\d+)").unwrap();
+    let expr = Regex::new(r"(\d+)\.(\d+)\.(\d+)").unwrap();
     if let Some(capture) = expr.captures(version_info) {
         let major = capture.get(1).unwrap().as_str().parse::<usize>().unwrap();
         let minor = capture.get(2).unwrap().as_str().parse::<usize>().unwrap();
-        let pointer_width = if version_info.contains("64 bit (AMD64)") {
-            64
-        } else {
-            32
-        };
-        Ok(Some(InterpreterConfig {
+        let arch = windows_python_arch(version_info);
+        let pointer_width = if arch == Arch::X86 { 32 } else { 64 };
+        let config = InterpreterConfig {
             major,
             minor,
             interpreter_kind: InterpreterKind::CPython,
@@ -269,17 +383,79 @@ fn windows_python_info(executable: &Path) -> Result<Option<InterpreterConfig>> {
             ext_suffix: String::new(),
             abi_tag: None,
             pointer_width: Some(pointer_width),
-        }))
+        };
+        Ok(Some((config, arch)))
     } else {
         Ok(None)
     }
 }
 
+/// Parses the architecture from `sys.version` of a windows python, e.g.
+/// `3.11.0 (main, Oct 24 2022, 18:15:22) [MSC v.1933 64 bit (ARM64)]`. GraalPy names the
+/// architecture of the JVM instead, e.g. `[Graal, Oracle GraalVM, Java 21.0.2 (amd64)]`.
+fn windows_python_arch(version_info: &str) -> Arch {
+    if version_info.contains("64 bit (ARM64)") || version_info.contains("(aarch64)") {
+        Arch::Aarch64
+    } else if version_info.contains("64 bit (AMD64)") || version_info.contains("(amd64)") {
+        Arch::X86_64
+    } else {
+        Arch::X86
+    }
+}
+
+/// The suffix of the windows platform tags for the target architecture, e.g. `arm64` in
+/// `win_arm64`
+fn windows_arch_suffix(target: &Target) -> &'static str {
+    match target.target_arch() {
+        Arch::Aarch64 => "arm64",
+        Arch::X86 => "win32",
+        _ => "amd64",
+    }
+}
+
+/// The windows-gnu target that a MinGW python from MSYS2 loads extensions of, given its
+/// `sysconfig.get_platform()` such as `mingw_x86_64` or `mingw_x86_64_ucrt`
+pub(crate) fn mingw_target_triple(platform: &str) -> Option<&'static str> {
+    let arch = platform.strip_prefix("mingw_")?;
+    if arch.starts_with("x86_64") {
+        Some("x86_64-pc-windows-gnu")
+    } else if arch.starts_with("i686") {
+        Some("i686-pc-windows-gnu")
+    } else {
+        None
+    }
+}
+
+/// The `._pth` file of an embeddable distribution in `python_dir`, e.g. `python311._pth`
+pub(crate) fn embeddable_pth_file(python_dir: &Path, major: usize, minor: usize) -> PathBuf {
+    python_dir.join(format!("python{}{}._pth", major, minor))
+}
+
+/// Whether the path is an app execution alias of a Windows Store Python, e.g.
+/// `%LOCALAPPDATA%\Microsoft\WindowsApps\python3.10.exe`. The aliases are the only way to run
+/// those interpreters.
+pub(crate) fn is_windows_store_alias(path: &Path) -> bool {
+    normalize_windows_path(path).contains("/microsoft/windowsapps/")
+}
+
+/// Whether the path is inside the install directory of a Windows Store Python, e.g.
+/// `C:\Program Files\WindowsApps\PythonSoftwareFoundation.Python.3.10_...\Lib`. That directory
+/// is write protected and its executables can't be run directly.
+pub(crate) fn is_windows_store_install(path: &Path) -> bool {
+    normalize_windows_path(path).contains("/program files/windowsapps/")
+}
+
+fn normalize_windows_path(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase().replace('\\', "/")
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum InterpreterKind {
     CPython,
     PyPy,
+    #[serde(alias = "graalvm")]
+    GraalPy,
 }
 
 impl InterpreterKind {
@@ -292,6 +468,11 @@ impl InterpreterKind {
     pub fn is_pypy(&self) -> bool {
         matches!(self, InterpreterKind::PyPy)
     }
+
+    /// Is this a GraalPy interpreter?
+    pub fn is_graalpy(&self) -> bool {
+        matches!(self, InterpreterKind::GraalPy)
+    }
 }
 
 impl fmt::Display for InterpreterKind {
@@ -299,6 +480,7 @@ impl fmt::Display for InterpreterKind {
         match *self {
             InterpreterKind::CPython => write!(f, "CPython"),
             InterpreterKind::PyPy => write!(f, "PyPy"),
+            InterpreterKind::GraalPy => write!(f, "GraalPy"),
         }
     }
 }
@@ -310,6 +492,8 @@ impl FromStr for InterpreterKind {
         match s.to_ascii_lowercase().as_str() {
             "cpython" => Ok(InterpreterKind::CPython),
             "pypy" => Ok(InterpreterKind::PyPy),
+            // `platform.python_implementation()` of GraalPy is `GraalVM`
+            "graalpy" | "graalvm" => Ok(InterpreterKind::GraalPy),
             unknown => Err(format!("Unknown interpreter kind '{}'", unknown)),
         }
     }
@@ -331,6 +515,11 @@ struct InterpreterMetadataMessage {
     system: String,
     soabi: Option<String>,
     abi_tag: Option<String>,
+    #[serde(default)]
+    framework: bool,
+    #[serde(default)]
+    macos_link_args: Option<Vec<String>>,
+    macos_deployment_target: Option<String>,
 }
 
 /// The location and version of an interpreter
@@ -356,6 +545,15 @@ pub struct PythonInterpreter {
     pub implmentation_name: String,
     /// Comes from sysconfig var `SOABI`
     pub soabi: Option<String>,
+    /// Whether this is a macOS framework build, e.g. from the python.org installer or Homebrew,
+    /// as opposed to e.g. pyenv or conda
+    pub macos_framework: bool,
+    /// The linker arguments to link extension modules explicitly against the python on macOS,
+    /// if its sysconfig `LDSHARED` doesn't link them with `-undefined dynamic_lookup`, e.g.
+    /// `-F/Library/Frameworks -framework Python` for a framework build
+    pub macos_link_args: Option<Vec<String>>,
+    /// Comes from sysconfig var `MACOSX_DEPLOYMENT_TARGET`, the macOS version python was built for
+    pub macos_deployment_target: Option<String>,
 }
 
 impl Deref for PythonInterpreter {
@@ -396,8 +594,8 @@ fn fun_with_abiflags(
         );
     }
 
-    if message.interpreter == "pypy" {
-        // pypy does not specify abi flags
+    if matches!(message.interpreter.as_str(), "pypy" | "graalvm" | "graalpy") {
+        // pypy and graalpy do not specify abi flags
         Ok("".to_string())
     } else if message.system == "windows" {
         if matches!(message.abiflags.as_deref(), Some("") | None) {
@@ -427,11 +625,33 @@ impl PythonInterpreter {
         } else {
             match self.interpreter_kind {
                 InterpreterKind::CPython => true,
-                InterpreterKind::PyPy => false,
+                InterpreterKind::PyPy | InterpreterKind::GraalPy => false,
             }
         }
     }
 
+    /// The platform of a MinGW python from MSYS2, e.g. `mingw_x86_64_ucrt`
+    pub fn mingw_platform(&self) -> Option<&str> {
+        self.platform
+            .as_deref()
+            .filter(|platform| platform.starts_with("mingw"))
+    }
+
+    /// The `python3XY._pth` file next to the executable if this is the embeddable distribution
+    /// of CPython for windows. It replaces `sys.path`, and the stripped down layout has neither
+    /// the headers nor the import library of python.
+    pub fn embeddable_pth(&self) -> Option<PathBuf> {
+        if !self.runnable {
+            return None;
+        }
+        let pth = embeddable_pth_file(self.executable.parent()?, self.major, self.minor);
+        if pth.is_file() {
+            Some(pth)
+        } else {
+            None
+        }
+    }
+
     /// Returns the supported python environment in the PEP 425 format used for the wheel filename:
     /// {python tag}-{abi tag}-{platform tag}
     ///
@@ -450,13 +670,25 @@ impl PythonInterpreter {
             || (target.is_linux() && platform_tags.iter().any(|tag| !tag.is_portable()))
             || target.is_illumos();
         let platform = if use_sysconfig_platform {
-            if let Some(platform) = self.platform.clone() {
-                platform
-            } else {
-                target.get_platform_tag(platform_tags, universal2)?
+            match self.platform.clone() {
+                // An x64 interpreter used to cross compile for arm64 windows reports its own
+                // platform, not the one of the target
+                Some(platform)
+                    if target.is_windows()
+                        && platform.starts_with("win")
+                        && !platform.ends_with(windows_arch_suffix(target)) =>
+                {
+                    target.get_platform_tag(platform_tags, universal2)?
+                }
+                Some(platform) => platform,
+                None => target.get_platform_tag(platform_tags, universal2)?,
             }
         } else {
-            target.get_platform_tag(platform_tags, universal2)?
+            target.get_platform_tag_with_deployment_target(
+                platform_tags,
+                universal2,
+                self.macos_deployment_target.as_deref(),
+            )?
         };
         let tag = if self.implmentation_name.parse::<InterpreterKind>().is_err() {
             // Use generic tags when `sys.implementation.name` != `platform.python_implementation()`, for example Pyston
@@ -476,7 +708,8 @@ impl PythonInterpreter {
         } else {
             match self.interpreter_kind {
                 InterpreterKind::CPython => {
-                    if target.is_unix() {
+                    // MinGW pythons generate the tags like on unix
+                    if target.is_unix() || self.mingw_platform().is_some() {
                         format!(
                             "cp{major}{minor}-cp{major}{minor}{abiflags}-{platform}",
                             major = self.major,
@@ -498,14 +731,22 @@ impl PythonInterpreter {
                     // pypy uses its version as part of the ABI, e.g.
                     // pypy 3.7 7.3 => numpy-1.20.1-pp37-pypy37_pp73-manylinux2014_x86_64.whl
                     format!(
-                        "pp{major}{minor}-pypy{major}{minor}_{abi_tag}-{platform}",
+                        "pp{major}{minor}-{abi_tag}-{platform}",
+                        major = self.major,
+                        minor = self.minor,
+                        abi_tag = self.alternative_abi_tag()?,
+                        platform = platform,
+                    )
+                }
+                InterpreterKind::GraalPy => {
+                    // graalpy uses its own version as part of the ABI, e.g.
+                    // graalpy 24.0 for python 3.10 =>
+                    // graalpy310-graalpy240_310_native-manylinux_2_17_x86_64.whl
+                    format!(
+                        "graalpy{major}{minor}-{abi_tag}-{platform}",
                         major = self.major,
                         minor = self.minor,
-                        // TODO: Proper tag handling for pypy
-                        abi_tag = self
-                            .abi_tag
-                            .clone()
-                            .expect("PyPy's syconfig didn't define an `SOABI` ಠ_ಠ"),
+                        abi_tag = self.alternative_abi_tag()?,
                         platform = platform,
                     )
                 }
@@ -514,6 +755,38 @@ impl PythonInterpreter {
         Ok(tag)
     }
 
+    /// The abi tag of PyPy and GraalPy, which contains the version of the implementation, e.g.
+    /// `pypy310_pp73` or `graalpy240_310_native`. Like `packaging`, it's the start of `SOABI`,
+    /// which is also the start of the extension suffix, e.g. `.pypy310-pp73-x86_64-linux-gnu.so`.
+    fn alternative_abi_tag(&self) -> Result<String> {
+        let soabi = match &self.soabi {
+            Some(soabi) => soabi.as_str(),
+            None => self.ext_suffix.split('.').nth(1).unwrap_or_default(),
+        };
+        let (prefix, parts) = match self.interpreter_kind {
+            InterpreterKind::GraalPy => ("graalpy", 3),
+            _ => ("pypy", 2),
+        };
+        let abi_tag: Vec<&str> = soabi.split('-').take(parts).collect();
+        if abi_tag.len() == parts && abi_tag[0].starts_with(prefix) {
+            return Ok(abi_tag.join("_").replace('.', "_"));
+        }
+        // A sysconfig without SOABI and with a plain extension suffix, e.g. from a pyo3 config
+        match (self.interpreter_kind, &self.abi_tag) {
+            (InterpreterKind::PyPy, Some(abi_tag)) => {
+                Ok(format!("pypy{}{}_{}", self.major, self.minor, abi_tag))
+            }
+            _ => bail!(
+                "Can't determine the abi tag of {} {}.{}, its sysconfig doesn't define `SOABI` \
+                and the extension suffix is {}",
+                self.interpreter_kind,
+                self.major,
+                self.minor,
+                self.ext_suffix
+            ),
+        }
+    }
+
     /// Adds the ext_suffix we read from python or know (.pyd/.abi3.so) and adds it to the base name
     ///
     /// For CPython, generate extensions as follows:
@@ -546,6 +819,30 @@ impl PythonInterpreter {
         target: &Target,
         bridge: &BridgeModel,
     ) -> Result<Option<PythonInterpreter>> {
+        Self::check_executable_impl(executable, target, bridge, true)
+    }
+
+    /// Like [PythonInterpreter::check_executable], but pythons that can't be used for the
+    /// target, e.g. MinGW pythons for msvc builds, fail only if the interpreter was `explicit`ly
+    /// requested. Otherwise they are skipped with a warning, so that they don't abort the
+    /// discovery of the other interpreters.
+    fn check_executable_impl(
+        executable: impl AsRef<Path>,
+        target: &Target,
+        bridge: &BridgeModel,
+        explicit: bool,
+    ) -> Result<Option<PythonInterpreter>> {
+        let unsupported = |reason: String| {
+            if explicit {
+                bail!("{} {}", executable.as_ref().display(), reason);
+            }
+            eprintln!(
+                "⚠️  Warning: skipped the python interpreter '{}', which {}",
+                executable.as_ref().display(),
+                reason
+            );
+            Ok(None)
+        };
         let output = Command::new(executable.as_ref())
             .args(["-c", GET_INTERPRETER_METADATA])
             .output();
@@ -569,6 +866,16 @@ impl PythonInterpreter {
                             executable.as_ref().display()
                         );
                         return Ok(None);
+                    } else if stderr.contains("App Execution Aliases")
+                        || (is_windows_store_alias(executable.as_ref())
+                            && output.status.code() == Some(9009))
+                    {
+                        eprintln!(
+                            "⚠️  Warning: skipped the Windows Store alias '{}', which doesn't \
+                            point to an installed python",
+                            executable.as_ref().display()
+                        );
+                        return Ok(None);
                     } else {
                         eprintln!("{}", stderr);
                         bail!(err_msg);
@@ -587,11 +894,13 @@ impl PythonInterpreter {
                             // Try py -x.y on Windows
                             let mut metadata_py = tempfile::NamedTempFile::new()?;
                             write!(metadata_py, "{}", GET_INTERPRETER_METADATA)?;
+                            let version = if target.target_arch() == Arch::Aarch64 {
+                                format!("-V:{}-arm64", ver)
+                            } else {
+                                format!("-{}-{}", ver, target.pointer_width())
+                            };
                             let mut cmd = Command::new("cmd");
-                            cmd.arg("/c")
-                                .arg("py")
-                                .arg(format!("-{}-{}", ver, target.pointer_width()))
-                                .arg(metadata_py.path());
+                            cmd.arg("/c").arg("py").arg(version).arg(metadata_py.path());
                             let output = cmd.output();
                             match output {
                                 Ok(output) if output.status.success() => output,
@@ -616,9 +925,21 @@ impl PythonInterpreter {
             return Ok(None);
         }
 
+        // Cygwin and the MSYS environment of MSYS2 report e.g. `cygwin_nt-10.0`, unlike the MinGW
+        // pythons of MSYS2, which report `windows`
+        if message.system.starts_with("cygwin") || message.system.starts_with("msys") {
+            return unsupported(
+                "is a Cygwin or MSYS python, which isn't supported by rust. Please use a MinGW \
+                python from the UCRT64 or MINGW64 environment of MSYS2, \
+                e.g. `pacman -S mingw-w64-ucrt-x86_64-python`, or a python from python.org"
+                    .to_string(),
+            );
+        }
+
         let interpreter = match message.interpreter.as_str() {
             "cpython" => InterpreterKind::CPython,
             "pypy" => InterpreterKind::PyPy,
+            "graalvm" | "graalpy" => InterpreterKind::GraalPy,
             other => {
                 bail!("Unsupported interpreter {}", other);
             }
@@ -636,6 +957,22 @@ impl PythonInterpreter {
             Some(message.platform.to_lowercase().replace(['-', '.'], "_"))
         };
 
+        // MinGW pythons use a posix-like sysconfig on windows, including the unix naming of
+        // extensions, and expect the MinGW ABI
+        if let Some(mingw_platform) = platform.as_deref().filter(|x| x.starts_with("mingw")) {
+            if target.is_windows() && !target.is_windows_gnu() && bridge != &BridgeModel::Cffi {
+                let gnu_target = mingw_target_triple(mingw_platform).unwrap_or("*-pc-windows-gnu");
+                return unsupported(format!(
+                    "is a MinGW python ({}), which can only load extensions built for the \
+                    windows-gnu target, not for {}. Please install the target with \
+                    `rustup target add {gnu}` and build with `--target {gnu}`",
+                    mingw_platform,
+                    target.target_triple(),
+                    gnu = gnu_target
+                ));
+            }
+        }
+
         Ok(Some(PythonInterpreter {
             config: InterpreterConfig {
                 major: message.major,
@@ -648,14 +985,20 @@ impl PythonInterpreter {
                 abi_tag: message.abi_tag,
                 pointer_width: None,
             },
+            // Windows Store Pythons may report their protected install location, which can only
+            // be run through the alias
             executable: message
                 .executable
                 .map(PathBuf::from)
+                .filter(|path| !is_windows_store_install(path))
                 .unwrap_or_else(|| executable.as_ref().to_path_buf()),
             platform,
             runnable: true,
             implmentation_name: message.implementation_name,
             soabi: message.soabi,
+            macos_framework: message.framework,
+            macos_link_args: message.macos_link_args,
+            macos_deployment_target: message.macos_deployment_target,
         }))
     }
 
@@ -669,6 +1012,9 @@ impl PythonInterpreter {
             runnable: false,
             implmentation_name,
             soabi: None,
+            macos_framework: false,
+            macos_link_args: None,
+            macos_deployment_target: None,
         }
     }
 
@@ -721,28 +1067,36 @@ impl PythonInterpreter {
                 _ => MINIMUM_PYTHON_MINOR,
             },
         };
+        // Also try to find PyPy and GraalPy for cffi and pyo3 bindings
+        let alternative_implementations = matches!(bridge, BridgeModel::Cffi)
+            || bridge.is_bindings("pyo3")
+            || bridge.is_bindings("pyo3-ffi");
         let executables = if target.is_windows() {
-            find_all_windows(target, min_python_minor)?
+            find_all_windows(target, min_python_minor, alternative_implementations)?
         } else {
-            let mut executables: Vec<String> = (min_python_minor..=MAXIMUM_PYTHON_MINOR)
+            let mut executables: Vec<String> = (min_python_minor..=PYTHON_VERSIONS.cpython)
                 .map(|minor| format!("python3.{}", minor))
                 .collect();
-            // Also try to find PyPy for cffi and pyo3 bindings
-            if matches!(bridge, BridgeModel::Cffi)
-                || bridge.is_bindings("pyo3")
-                || bridge.is_bindings("pyo3-ffi")
-            {
-                executables.extend(
-                    (min_python_minor..=MAXIMUM_PYPY_MINOR).map(|minor| format!("pypy3.{}", minor)),
-                );
+            if alternative_implementations {
+                executables.extend(alternative_executables(min_python_minor));
             }
             executables
         };
-        let mut available_versions = Vec::new();
+        let mut available_versions: Vec<PythonInterpreter> = Vec::new();
         for executable in executables {
-            if let Some(version) = PythonInterpreter::check_executable(&executable, target, bridge)?
+            if let Some(version) =
+                PythonInterpreter::check_executable_impl(&executable, target, bridge, false)?
             {
-                available_versions.push(version);
+                // `graalpy` has no version in its name, and `pypy3.X` may be a link to another
+                // version
+                let duplicate = available_versions.iter().any(|found| {
+                    found.interpreter_kind == version.interpreter_kind
+                        && (found.major, found.minor) == (version.major, version.minor)
+                        && found.executable == version.executable
+                });
+                if version.minor >= min_python_minor && !duplicate {
+                    available_versions.push(version);
+                }
             }
         }
 
@@ -859,7 +1213,11 @@ impl fmt::Display for PythonInterpreter {
                 self.config.minor,
                 self.config.abiflags,
                 self.executable.display()
-            )
+            )?;
+            if self.macos_framework {
+                write!(f, " (framework)")?;
+            }
+            Ok(())
         } else {
             write!(
                 f,
@@ -872,3 +1230,262 @@ impl fmt::Display for PythonInterpreter {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::target::Os;
+
+    #[test]
+    fn test_windows_store_paths() {
+        let alias = Path::new(r"C:\Users\me\AppData\Local\Microsoft\WindowsApps\python3.10.exe");
+        let install = Path::new(
+            r"C:\Program Files\WindowsApps\PythonSoftwareFoundation.Python.3.10_3.10.2288.0_x64__qbz5n2kfra8p0\Lib\site-packages",
+        );
+        let regular = Path::new(r"C:\Users\me\AppData\Local\Programs\Python\Python310\python.exe");
+        assert!(is_windows_store_alias(alias));
+        assert!(!is_windows_store_alias(install));
+        assert!(!is_windows_store_alias(regular));
+        assert!(is_windows_store_install(install));
+        assert!(!is_windows_store_install(alias));
+        assert!(!is_windows_store_install(regular));
+    }
+
+    #[test]
+    fn test_windows_python_arch() {
+        for (version, arch) in [
+            (
+                "3.11.0 (main, Oct 24 2022, 18:15:22) [MSC v.1933 64 bit (ARM64)]",
+                Arch::Aarch64,
+            ),
+            (
+                "3.11.0 (main, Oct 24 2022, 18:26:48) [MSC v.1933 64 bit (AMD64)]",
+                Arch::X86_64,
+            ),
+            (
+                "3.10.8 (tags/v3.10.8:aaaf517, Oct 11 2022, 16:37:59) [MSC v.1933 32 bit (Intel)]",
+                Arch::X86,
+            ),
+            (
+                "3.9.18 (9c4f8ef1, Sep 27 2023, 12:20:09)\n[PyPy 7.3.13 with MSC v.1929 64 bit (AMD64)]",
+                Arch::X86_64,
+            ),
+            (
+                "3.10.13 (Mon Mar 18 18:19:32 CET 2024)\n[Graal, Oracle GraalVM, Java 22 (amd64)]",
+                Arch::X86_64,
+            ),
+        ] {
+            assert_eq!(windows_python_arch(version), arch);
+        }
+        assert!(windows_interpreter_no_build(
+            3,
+            11,
+            Arch::Aarch64,
+            Arch::X86_64,
+            7
+        ));
+        assert!(!windows_interpreter_no_build(
+            3,
+            11,
+            Arch::Aarch64,
+            Arch::Aarch64,
+            7
+        ));
+    }
+
+    #[test]
+    fn test_alternative_implementation_tags() {
+        let linux =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let manylinux = [PlatformTag::Manylinux { x: 2, y: 17 }];
+        let pypy = PythonInterpreter {
+            soabi: Some("pypy310-pp73-x86_64-linux-gnu".to_string()),
+            ..PythonInterpreter::from_config(InterpreterConfig {
+                major: 3,
+                minor: 10,
+                interpreter_kind: InterpreterKind::PyPy,
+                abiflags: String::new(),
+                ext_suffix: ".pypy310-pp73-x86_64-linux-gnu.so".to_string(),
+                abi_tag: Some("pp73".to_string()),
+                pointer_width: Some(64),
+            })
+        };
+        assert_eq!(
+            pypy.get_tag(&linux, &manylinux, false).unwrap(),
+            "pp310-pypy310_pp73-manylinux_2_17_x86_64.manylinux2014_x86_64"
+        );
+        // The bundled sysconfig has no SOABI, the abi tag comes from the extension suffix
+        let windows =
+            Target::from_target_triple(Some("x86_64-pc-windows-msvc".to_string())).unwrap();
+        let pypy_windows =
+            InterpreterConfig::lookup(Os::Windows, Arch::X86_64, InterpreterKind::PyPy, (3, 9))
+                .unwrap();
+        assert_eq!(
+            PythonInterpreter::from_config(pypy_windows.clone())
+                .get_tag(&windows, &[], false)
+                .unwrap(),
+            "pp39-pypy39_pp73-win_amd64"
+        );
+
+        let graalpy = PythonInterpreter {
+            implmentation_name: "graalpy".to_string(),
+            ..PythonInterpreter::from_config(InterpreterConfig {
+                major: 3,
+                minor: 10,
+                interpreter_kind: "GraalVM".parse().unwrap(),
+                abiflags: String::new(),
+                ext_suffix: ".graalpy240-310-native-x86_64-linux.so".to_string(),
+                abi_tag: Some("310".to_string()),
+                pointer_width: Some(64),
+            })
+        };
+        assert!(!graalpy.has_stable_api());
+        assert_eq!(
+            graalpy.get_tag(&linux, &manylinux, false).unwrap(),
+            "graalpy310-graalpy240_310_native-manylinux_2_17_x86_64.manylinux2014_x86_64"
+        );
+        assert!(graalpy
+            .pyo3_config_file()
+            .starts_with("implementation=GraalVM\n"));
+        let graalpy_without_suffix = PythonInterpreter {
+            config: InterpreterConfig {
+                ext_suffix: ".so".to_string(),
+                ..graalpy.config.clone()
+            },
+            ..graalpy
+        };
+        assert!(graalpy_without_suffix
+            .get_tag(&linux, &manylinux, false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_mingw_tag() {
+        assert_eq!(
+            mingw_target_triple("mingw_x86_64_ucrt"),
+            Some("x86_64-pc-windows-gnu")
+        );
+        assert_eq!(
+            mingw_target_triple("mingw_i686"),
+            Some("i686-pc-windows-gnu")
+        );
+        assert_eq!(mingw_target_triple("win_amd64"), None);
+
+        let mingw_python = PythonInterpreter {
+            platform: Some("mingw_x86_64_ucrt".to_string()),
+            ..PythonInterpreter::from_config(InterpreterConfig {
+                major: 3,
+                minor: 10,
+                interpreter_kind: InterpreterKind::CPython,
+                abiflags: String::new(),
+                ext_suffix: ".cp310-mingw_x86_64_ucrt.pyd".to_string(),
+                abi_tag: None,
+                pointer_width: Some(64),
+            })
+        };
+        assert_eq!(mingw_python.mingw_platform(), Some("mingw_x86_64_ucrt"));
+        let x64_gnu =
+            Target::from_target_triple(Some("x86_64-pc-windows-gnu".to_string())).unwrap();
+        assert_eq!(
+            mingw_python.get_tag(&x64_gnu, &[], false).unwrap(),
+            "cp310-cp310-mingw_x86_64_ucrt"
+        );
+        assert_eq!(
+            mingw_python.get_library_name("pyo3_pure"),
+            "pyo3_pure.cp310-mingw_x86_64_ucrt.pyd"
+        );
+    }
+
+    #[test]
+    fn test_msys2_python_candidates() {
+        let root = Path::new(MSYS2_ROOT);
+        assert_eq!(
+            msys2_python_candidates(Arch::X86_64, root),
+            [
+                root.join("ucrt64").join("bin").join("python3.exe"),
+                root.join("mingw64").join("bin").join("python3.exe"),
+            ]
+        );
+        assert_eq!(
+            msys2_python_candidates(Arch::X86, root),
+            [root.join("mingw32").join("bin").join("python3.exe")]
+        );
+        assert!(msys2_python_candidates(Arch::Aarch64, root).is_empty());
+    }
+
+    #[test]
+    fn test_macos_deployment_target_tag() {
+        let homebrew_python = PythonInterpreter {
+            macos_framework: true,
+            macos_deployment_target: Some("13.0".to_string()),
+            ..PythonInterpreter::from_config(InterpreterConfig {
+                major: 3,
+                minor: 11,
+                interpreter_kind: InterpreterKind::CPython,
+                abiflags: String::new(),
+                ext_suffix: ".cpython-311-darwin.so".to_string(),
+                abi_tag: None,
+                pointer_width: Some(64),
+            })
+        };
+        let mut arm64 =
+            Target::from_target_triple(Some("aarch64-apple-darwin".to_string())).unwrap();
+        if std::env::var_os("MACOSX_DEPLOYMENT_TARGET").is_none() {
+            assert_eq!(
+                homebrew_python.get_tag(&arm64, &[], false).unwrap(),
+                "cp311-cp311-macosx_13_0_arm64"
+            );
+        }
+        // The override wins over the interpreter, but not over the minimum of rust
+        arm64.set_macos_deployment_target(Some("12.3".to_string()));
+        assert_eq!(
+            homebrew_python.get_tag(&arm64, &[], false).unwrap(),
+            "cp311-cp311-macosx_12_3_arm64"
+        );
+        arm64.set_macos_deployment_target(Some("10.9".to_string()));
+        assert_eq!(
+            homebrew_python.get_tag(&arm64, &[], false).unwrap(),
+            "cp311-cp311-macosx_11_0_arm64"
+        );
+        arm64.set_macos_deployment_target(Some("12".to_string()));
+        assert!(homebrew_python.get_tag(&arm64, &[], false).is_err());
+    }
+
+    #[test]
+    fn test_windows_arm64_tag() {
+        let x64_python = PythonInterpreter {
+            platform: Some("win_amd64".to_string()),
+            ..PythonInterpreter::from_config(InterpreterConfig {
+                major: 3,
+                minor: 11,
+                interpreter_kind: InterpreterKind::CPython,
+                abiflags: String::new(),
+                ext_suffix: ".cp311-win_amd64.pyd".to_string(),
+                abi_tag: None,
+                pointer_width: Some(64),
+            })
+        };
+        let arm64 =
+            Target::from_target_triple(Some("aarch64-pc-windows-msvc".to_string())).unwrap();
+        assert_eq!(
+            x64_python.get_tag(&arm64, &[], false).unwrap(),
+            "cp311-none-win_arm64"
+        );
+        let x64 = Target::from_target_triple(Some("x86_64-pc-windows-msvc".to_string())).unwrap();
+        assert_eq!(
+            x64_python.get_tag(&x64, &[], false).unwrap(),
+            "cp311-none-win_amd64"
+        );
+        // MinGW builds load into the same MSVC built python
+        let x64_gnu =
+            Target::from_target_triple(Some("x86_64-pc-windows-gnu".to_string())).unwrap();
+        assert_eq!(
+            x64_python.get_tag(&x64_gnu, &[], false).unwrap(),
+            "cp311-none-win_amd64"
+        );
+        assert_eq!(
+            x64_python.get_library_name("pyo3_pure"),
+            "pyo3_pure.cp311-win_amd64.pyd"
+        );
+    }
+}
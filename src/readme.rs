@@ -0,0 +1,239 @@
+//! Checks that the readme will render on PyPI, similar to `twine check`
+use crate::metadata::{GFM_CONTENT_TYPE, PLAINTEXT_CONTENT_TYPE, RST_CONTENT_TYPE};
+use crate::Metadata21;
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Roles which only exist in sphinx, docutils fails with "Unknown interpreted text role"
+static SPHINX_ROLE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r":(ref|doc|class|func|meth|mod|attr|exc|obj|data|const|term|envvar|py:[a-z]+):`")
+        .unwrap()
+});
+
+/// Directives which only exist in sphinx or which are disabled on PyPI
+static UNSUPPORTED_DIRECTIVE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^\s*\.\.\s+(toctree|automodule|autoclass|autofunction|automethod|autodata|literalinclude|versionadded|versionchanged|deprecated|seealso|glossary|only|highlight|include|raw)::",
+    )
+    .unwrap()
+});
+
+/// A markdown link or image, i.e. `[text](url)`
+static MARKDOWN_LINK: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[[^\]\n]+\]\([^)\s]+\)").unwrap());
+
+/// Validates the readme content type and checks reStructuredText readmes for the most common
+/// problems that make PyPI reject the upload. If the content type is missing, it's guessed
+/// from the readme contents, since PyPI would otherwise assume reStructuredText.
+pub fn check_readme(metadata21: &mut Metadata21) -> Result<()> {
+    let description = match &metadata21.description {
+        Some(description) => description,
+        None => return Ok(()),
+    };
+    let content_type = match &metadata21.description_content_type {
+        Some(content_type) => content_type.clone(),
+        None => {
+            let content_type = detect_content_type(description).to_string();
+            eprintln!("📖 Detected {} as content type of the readme", content_type);
+            metadata21.description_content_type = Some(content_type.clone());
+            content_type
+        }
+    };
+    let mime = validate_content_type(&content_type)?;
+    if mime == "text/x-rst" {
+        if looks_like_markdown(description) {
+            eprintln!(
+                "⚠️  Warning: The readme has the content type {}, but looks like markdown. \
+                It will not render correctly on PyPI",
+                content_type
+            );
+        }
+        for problem in rst_problems(description) {
+            eprintln!(
+                "⚠️  Warning: The readme will not render on PyPI, which rejects the upload: {}",
+                problem
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns a description of each reStructuredText problem that will make PyPI reject the readme
+pub(crate) fn rst_problems(description: &str) -> Vec<String> {
+    check_rst(description)
+        .into_iter()
+        .map(|(line, problem)| format!("line {}: {}", line, problem))
+        .collect()
+}
+
+/// Checks that PyPI can render the content type and returns its mime type
+pub(crate) fn validate_content_type(content_type: &str) -> Result<&str> {
+    let mut parts = content_type.split(';').map(str::trim);
+    let mime = parts.next().unwrap_or_default();
+    if !["text/plain", "text/x-rst", "text/markdown"].contains(&mime) {
+        bail!(
+            "The readme content type {} is not supported by PyPI, use one of text/plain, \
+            text/x-rst and text/markdown",
+            content_type
+        );
+    }
+    for parameter in parts.filter(|part| !part.is_empty()) {
+        let (key, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+        match key.trim().to_ascii_lowercase().as_str() {
+            "charset" if !value.trim().eq_ignore_ascii_case("utf-8") => {
+                bail!(
+                    "The readme content type {} has the charset {}, but PyPI only supports UTF-8",
+                    content_type,
+                    value.trim()
+                );
+            }
+            "variant"
+                if mime == "text/markdown" && !["GFM", "CommonMark"].contains(&value.trim()) =>
+            {
+                bail!(
+                    "The readme content type {} has the markdown variant {}, but PyPI only \
+                    supports GFM and CommonMark",
+                    content_type,
+                    value.trim()
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(mime)
+}
+
+/// Guesses the content type of a readme without file extension
+fn detect_content_type(description: &str) -> &'static str {
+    if looks_like_markdown(description) {
+        GFM_CONTENT_TYPE
+    } else if description.lines().any(|line| line.starts_with(".. "))
+        || titles(description).next().is_some()
+    {
+        RST_CONTENT_TYPE
+    } else {
+        PLAINTEXT_CONTENT_TYPE
+    }
+}
+
+/// Whether the text has markdown headings, code fences or links
+pub(crate) fn looks_like_markdown(description: &str) -> bool {
+    description
+        .lines()
+        .any(|line| line.starts_with("# ") || line.starts_with("## ") || line.starts_with("```"))
+        || MARKDOWN_LINK.is_match(description)
+}
+
+/// Whether the line is a reStructuredText section adornment such as `=====`
+fn is_adornment(line: &str) -> bool {
+    let line = line.trim_end();
+    let mut chars = line.chars();
+    match chars.next() {
+        Some(first) => first.is_ascii_punctuation() && line.len() >= 2 && chars.all(|c| c == first),
+        None => false,
+    }
+}
+
+/// Returns the 1-based line number, title and underline of each section title
+fn titles(description: &str) -> impl Iterator<Item = (usize, &str, &str)> {
+    let lines: Vec<&str> = description.lines().collect();
+    (0..lines.len().saturating_sub(1)).filter_map(move |i| {
+        let (title, underline) = (lines[i], lines[i + 1]);
+        let is_title = !title.trim().is_empty()
+            && !title.starts_with(char::is_whitespace)
+            && !is_adornment(title)
+            && is_adornment(underline)
+            && (i == 0 || lines[i - 1].trim().is_empty() || is_adornment(lines[i - 1]));
+        if is_title {
+            Some((i + 1, title, underline))
+        } else {
+            None
+        }
+    })
+}
+
+/// Finds the reStructuredText constructs docutils errors on when rendering on PyPI
+fn check_rst(description: &str) -> Vec<(usize, String)> {
+    let mut problems = Vec::new();
+    for (line, title, underline) in titles(description) {
+        // docutils treats shorter underlines as text
+        let underline_len = underline.trim_end().chars().count();
+        if underline_len >= 4 && underline_len < title.trim_end().chars().count() {
+            problems.push((line + 1, "Title underline too short".to_string()));
+        }
+    }
+    for (i, line) in description.lines().enumerate() {
+        if let Some(captures) = SPHINX_ROLE.captures(line) {
+            problems.push((
+                i + 1,
+                format!("Unknown interpreted text role \"{}\"", &captures[1]),
+            ));
+        }
+        if let Some(captures) = UNSUPPORTED_DIRECTIVE.captures(line) {
+            problems.push((
+                i + 1,
+                format!("Unknown or disabled directive \"{}\"", &captures[1]),
+            ));
+        }
+    }
+    problems.sort();
+    problems
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_detect_content_type() {
+        assert_eq!(detect_content_type("# Foo\n\nbar"), GFM_CONTENT_TYPE);
+        assert_eq!(
+            detect_content_type("See [the docs](https://example.org)"),
+            GFM_CONTENT_TYPE
+        );
+        assert_eq!(detect_content_type("Foo\n===\n\nbar"), RST_CONTENT_TYPE);
+        assert_eq!(
+            detect_content_type("Just some text"),
+            PLAINTEXT_CONTENT_TYPE
+        );
+    }
+
+    #[test]
+    fn test_validate_content_type() {
+        assert_eq!(
+            validate_content_type(GFM_CONTENT_TYPE).unwrap(),
+            "text/markdown"
+        );
+        assert_eq!(validate_content_type("text/x-rst").unwrap(), "text/x-rst");
+        assert!(validate_content_type("text/html").is_err());
+        assert!(validate_content_type("text/plain; charset=latin-1").is_err());
+        assert!(validate_content_type("text/markdown; variant=Original").is_err());
+    }
+
+    #[test]
+    fn test_check_rst() {
+        let readme = indoc!(
+            r#"
+            A long title
+            =====
+
+            Some text, see :ref:`install`.
+
+            .. toctree::
+
+                Indented
+                ==
+            "#
+        );
+        assert_eq!(
+            check_rst(readme),
+            [
+                (2, "Title underline too short".to_string()),
+                (4, "Unknown interpreted text role \"ref\"".to_string()),
+                (6, "Unknown or disabled directive \"toctree\"".to_string()),
+            ]
+        );
+        assert!(check_rst("Title\n=====\n\nText\n").is_empty());
+    }
+}
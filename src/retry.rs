@@ -0,0 +1,204 @@
+//! The progress of `maturin build` and `maturin publish`, saved to the target directory so that
+//! `maturin retry` can resume a failed run without building the finished targets again
+use crate::project_layout::ProjectResolver;
+use crate::{BuildReport, BuiltWheelMetadata, CargoOptions, Target};
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Options for `maturin retry`
+#[derive(Debug, Default, clap::Parser)]
+pub struct RetryOptions {
+    /// The path to the Cargo.toml of the project whose last run failed
+    #[arg(short = 'm', long, value_name = "PATH")]
+    manifest_path: Option<PathBuf>,
+}
+
+/// The progress file in the target directory
+pub fn progress_path(target_dir: &Path) -> PathBuf {
+    target_dir.join("maturin").join("retry.json")
+}
+
+/// What a `maturin build` or `maturin publish` run did so far
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunProgress {
+    /// The arguments of the run without the program name. Passwords are left out, they have to
+    /// come from the environment or the prompt again.
+    pub args: Vec<String>,
+    /// The wheels of the finished targets and the source distribution, if it was built
+    pub report: BuildReport,
+    /// Where the progress is saved
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl RunProgress {
+    /// Starts recording the progress of a run with the arguments of this process
+    pub fn start(target_dir: &Path) -> Result<Self> {
+        let progress = Self {
+            args: without_passwords(env::args().skip(1)),
+            report: BuildReport::default(),
+            path: progress_path(target_dir),
+        };
+        progress.save()?;
+        Ok(progress)
+    }
+
+    /// Reads the progress of the last failed run of the project
+    pub fn load(options: RetryOptions) -> Result<Self> {
+        let ProjectResolver { cargo_metadata, .. } =
+            ProjectResolver::resolve(options.manifest_path, CargoOptions::default())?;
+        let path = progress_path(cargo_metadata.target_directory.as_std_path());
+        if !path.is_file() {
+            bail!(
+                "There is nothing to retry, the last `maturin build` or `maturin publish` of \
+                this project succeeded"
+            );
+        }
+        let contents = fs::read_to_string(&path)?;
+        let mut progress: Self = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        progress.path = path;
+        Ok(progress)
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(&self.path, json)
+            .with_context(|| format!("Failed to save the progress to {}", self.path.display()))
+    }
+
+    /// The wheels an earlier run built for the target, unless one of them has gone missing since
+    pub fn finished_target(&self, target: &Target) -> Option<Vec<BuiltWheelMetadata>> {
+        let target_report = self
+            .report
+            .targets
+            .iter()
+            .find(|report| report.target == target.target_triple())?;
+        if !target_report
+            .wheels
+            .iter()
+            .all(|wheel| wheel.path.is_file())
+        {
+            return None;
+        }
+        Some(
+            target_report
+                .wheels
+                .iter()
+                .map(|wheel| (wheel.path.clone(), wheel.tag.clone()))
+                .collect(),
+        )
+    }
+
+    /// The source distribution an earlier run built, if it still exists
+    pub fn finished_sdist(&self) -> Option<&Path> {
+        self.report.sdist.as_deref().filter(|path| path.is_file())
+    }
+
+    /// Records the wheels built for a target
+    pub fn add_target(&mut self, target: &Target, wheels: &[BuiltWheelMetadata]) -> Result<()> {
+        let triple = target.target_triple();
+        self.report.targets.retain(|report| report.target != triple);
+        self.report.add_target(target, wheels);
+        self.save()
+    }
+
+    /// Records the built source distribution
+    pub fn set_sdist(&mut self, path: &Path) -> Result<()> {
+        self.report.sdist = Some(path.to_path_buf());
+        self.save()
+    }
+
+    /// The run succeeded, so there's nothing left to retry
+    pub fn finish(self) -> Result<()> {
+        if self.path.is_file() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Removes the values of `-p`/`--password` before a `--`, which is the only secret that can be
+/// passed as argument
+fn without_passwords(mut args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut filtered = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            filtered.push(arg);
+            filtered.extend(args);
+            break;
+        }
+        if arg == "-p" || arg == "--password" {
+            args.next();
+        } else if arg.starts_with("--password=") || (arg.starts_with("-p") && arg.len() > 2) {
+            // The password is part of the argument
+        } else {
+            filtered.push(arg);
+        }
+    }
+    filtered
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_without_passwords() {
+        let args = [
+            "publish",
+            "-p",
+            "hunter2",
+            "--password=hunter2",
+            "-phunter2",
+            "-u",
+            "user",
+            "--",
+            "-p",
+            "rustc-arg",
+        ];
+        assert_eq!(
+            without_passwords(args.iter().map(ToString::to_string)),
+            ["publish", "-u", "user", "--", "-p", "rustc-arg"]
+        );
+    }
+
+    #[test]
+    fn test_progress_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = Target::from_target_triple(None).unwrap();
+        let wheel = dir.path().join("foo-0.1.0-py3-none-any.whl");
+        fs::write(&wheel, "").unwrap();
+        let mut progress = RunProgress {
+            args: vec!["build".to_string()],
+            report: BuildReport::default(),
+            path: progress_path(dir.path()),
+        };
+        progress
+            .add_target(&target, &[(wheel.clone(), "py3".to_string())])
+            .unwrap();
+
+        let contents = fs::read_to_string(progress_path(dir.path())).unwrap();
+        let mut loaded: RunProgress = serde_json::from_str(&contents).unwrap();
+        loaded.path = progress_path(dir.path());
+        assert_eq!(loaded, progress);
+        assert_eq!(
+            loaded.finished_target(&target),
+            Some(vec![(wheel.clone(), "py3".to_string())])
+        );
+        assert_eq!(loaded.finished_sdist(), None);
+
+        // A wheel that was deleted since has to be built again
+        fs::remove_file(&wheel).unwrap();
+        assert_eq!(loaded.finished_target(&target), None);
+
+        loaded.finish().unwrap();
+        assert!(!progress_path(dir.path()).exists());
+    }
+}
@@ -0,0 +1,124 @@
+//! `maturin self test` builds small sample projects for each kind of bindings, so that problems
+//! of the build environment can be told apart from problems of a project
+use crate::new_project::generate_sample_project;
+use crate::{BuildOptions, CargoOptions};
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use std::path::{Path, PathBuf};
+
+/// The bindings of the sample projects, which are known to build in a working environment
+const SAMPLE_BINDINGS: [&str; 3] = ["pyo3", "cffi", "bin"];
+
+/// Options for `maturin self test`
+#[derive(Debug, Default, clap::Parser)]
+pub struct SelfTestOptions {
+    /// The python interpreters to build the samples for, defaults to all that are found
+    #[arg(short, long, num_args = 0.., action = clap::ArgAction::Append)]
+    interpreter: Vec<PathBuf>,
+    /// The target triple to build the samples for, defaults to the host
+    #[arg(long, value_name = "TRIPLE", env = "CARGO_BUILD_TARGET")]
+    target: Option<String>,
+    /// Create the sample projects in this directory and keep them, instead of using a
+    /// temporary directory
+    #[arg(long, value_name = "DIR")]
+    dir: Option<PathBuf>,
+}
+
+/// Builds the sample project with `bindings` in `dir`, returning the names of the built wheels
+fn build_sample(dir: &Path, bindings: &str, options: &SelfTestOptions) -> Result<Vec<String>> {
+    let name = format!("maturin-self-test-{}", bindings);
+    let project_dir = dir.join(&name);
+    generate_sample_project(&project_dir, &name, bindings)
+        .context("Failed to generate the sample project")?;
+    let build_options = BuildOptions {
+        interpreter: options.interpreter.clone(),
+        find_interpreter: options.interpreter.is_empty(),
+        out: Some(dir.join("wheels")),
+        cargo: CargoOptions {
+            manifest_path: Some(project_dir.join("Cargo.toml")),
+            targets: options.target.iter().cloned().collect(),
+            // The samples share their dependencies
+            target_dir: Some(dir.join("target")),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let build_context = build_options.into_build_context(false, false, false)?;
+    let wheels = build_context.build_wheels()?;
+    Ok(wheels
+        .iter()
+        .filter_map(|(path, _)| path.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .collect())
+}
+
+/// Builds the sample projects and reports which kinds of bindings work
+pub fn self_test(options: SelfTestOptions) -> Result<()> {
+    let temp_dir;
+    let dir = match &options.dir {
+        Some(dir) => {
+            fs::create_dir_all(dir)?;
+            dir.clone()
+        }
+        None => {
+            temp_dir = tempfile::tempdir()?;
+            temp_dir.path().to_path_buf()
+        }
+    };
+
+    let mut results = Vec::new();
+    for bindings in SAMPLE_BINDINGS {
+        eprintln!("🧪 Building a sample project with {} bindings", bindings);
+        let result = build_sample(&dir, bindings, &options);
+        results.push((bindings, result));
+    }
+
+    eprintln!();
+    for (bindings, result) in &results {
+        match result {
+            Ok(wheels) => {
+                eprintln!("✅ {}", bindings);
+                for wheel in wheels {
+                    eprintln!("  - {}", wheel);
+                }
+            }
+            Err(err) => {
+                eprintln!("❌ {}", bindings);
+                for cause in err.chain() {
+                    eprintln!("  - {}", cause);
+                }
+            }
+        }
+    }
+    let failed: Vec<&str> = results
+        .iter()
+        .filter(|(_, result)| result.is_err())
+        .map(|(bindings, _)| *bindings)
+        .collect();
+    if !failed.is_empty() {
+        bail!(
+            "The sample projects with {} bindings failed to build. As they are known to build, \
+            this points to a problem of the build environment rather than of your project, \
+            `maturin doctor` may know more",
+            failed.join(", ")
+        );
+    }
+    eprintln!(
+        "✨ All sample projects built, so problems building your project are likely caused by \
+        the project itself"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_bin_sample() {
+        let dir = tempfile::tempdir().unwrap();
+        let wheels = build_sample(dir.path(), "bin", &SelfTestOptions::default()).unwrap();
+        assert_eq!(wheels.len(), 1);
+        assert!(wheels[0].starts_with("maturin_self_test_bin-0.1.0-py3-none-"));
+    }
+}
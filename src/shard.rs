@@ -0,0 +1,96 @@
+//! Splits the builds of the target × interpreter matrix of one `maturin build` between parallel
+//! CI jobs with `--shard <index>/<count>`
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// One of `count` shards, `index` counts from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    /// Which shard this is, from 1 to `count`
+    pub index: usize,
+    /// How many shards there are
+    pub count: usize,
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid shard '{}', expected e.g. '2/5'", s);
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        let index: usize = index.trim().parse().map_err(|_| invalid())?;
+        let count: usize = count.trim().parse().map_err(|_| invalid())?;
+        if count == 0 || index == 0 || index > count {
+            return Err(format!(
+                "Invalid shard '{}', the index must be between 1 and the number of shards",
+                s
+            ));
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl Display for Shard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+impl Shard {
+    /// The items of this shard. The items are sorted by their name and dealt out round-robin,
+    /// so every item ends up in exactly one shard as long as all shards see the same names,
+    /// no matter in which order they were found.
+    pub fn select<T>(&self, mut items: Vec<(String, T)>) -> Vec<(String, T)> {
+        items.sort_by(|(a, _), (b, _)| a.cmp(b));
+        items
+            .into_iter()
+            .enumerate()
+            .filter(|(position, _)| position % self.count == self.index - 1)
+            .map(|(_, item)| item)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_shard() {
+        assert_eq!("2/5".parse(), Ok(Shard { index: 2, count: 5 }));
+        assert_eq!("1/1".parse(), Ok(Shard { index: 1, count: 1 }));
+        assert!("0/5".parse::<Shard>().is_err());
+        assert!("6/5".parse::<Shard>().is_err());
+        assert!("2".parse::<Shard>().is_err());
+        assert!("a/b".parse::<Shard>().is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let names = [
+            "x86_64-unknown-linux-gnu CPython 3.9",
+            "aarch64-unknown-linux-gnu CPython 3.9",
+            "x86_64-unknown-linux-gnu CPython 3.10",
+            "aarch64-unknown-linux-gnu CPython 3.10",
+            "x86_64-unknown-linux-gnu PyPy 3.9",
+        ];
+        let items: Vec<(String, usize)> = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name.to_string(), index))
+            .collect();
+        let mut selected = Vec::new();
+        for index in 1..=3 {
+            let shard = Shard { index, count: 3 };
+            let mut reversed = items.clone();
+            reversed.reverse();
+            // The order the builds were found in doesn't matter
+            assert_eq!(shard.select(items.clone()), shard.select(reversed));
+            let shard_items = shard.select(items.clone());
+            assert!(shard_items.len() <= 2);
+            selected.extend(shard_items.into_iter().map(|(_, index)| index));
+        }
+        selected.sort_unstable();
+        assert_eq!(selected, [0, 1, 2, 3, 4]);
+    }
+}
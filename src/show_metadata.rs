@@ -0,0 +1,65 @@
+//! Prints the effective python package metadata of a project without building it
+use crate::build_options::{apply_abi3_requires_python, find_bridge};
+use crate::project_layout::ProjectResolver;
+use crate::{BridgeModel, CargoOptions, Metadata21};
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+/// Options for `maturin metadata`
+#[derive(Debug, clap::Parser)]
+pub struct MetadataOptions {
+    /// The path to the Cargo.toml
+    #[arg(short = 'm', long = "manifest-path", value_name = "PATH")]
+    manifest_path: Option<PathBuf>,
+    /// Print the metadata as json instead of in the format of the METADATA file
+    #[arg(long)]
+    json: bool,
+}
+
+/// Resolves the metadata the wheels of the project would have, after merging Cargo.toml and
+/// pyproject.toml and computing the version from git tags. The hooks of `[tool.maturin.dynamic]`
+/// don't run, they only run when building.
+pub fn resolve_metadata(manifest_path: Option<PathBuf>) -> Result<Metadata21> {
+    let ProjectResolver {
+        pyproject_toml,
+        mut metadata21,
+        cargo_metadata,
+        ..
+    } = ProjectResolver::resolve(manifest_path, CargoOptions::default())?;
+    let bindings = pyproject_toml.as_ref().and_then(|x| x.bindings());
+    if let BridgeModel::BindingsAbi3(major, minor) = find_bridge(&cargo_metadata, bindings)? {
+        apply_abi3_requires_python(&mut metadata21, major, minor);
+    }
+    Ok(metadata21)
+}
+
+/// Prints the resolved metadata to stdout, so release tooling can inspect the name, version and
+/// dependencies without building anything. All other output goes to stderr.
+pub fn show_metadata(options: MetadataOptions) -> Result<()> {
+    let metadata21 = resolve_metadata(options.manifest_path)?;
+    if options.json {
+        let json = serde_json::to_string_pretty(&metadata21)
+            .context("Failed to serialize the metadata")?;
+        println!("{}", json);
+    } else {
+        print!("{}", metadata21.to_file_contents()?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve_metadata() {
+        let metadata21 = resolve_metadata(Some("test-crates/pyo3-pure/Cargo.toml".into())).unwrap();
+        assert_eq!(metadata21.name, "pyo3_pure");
+        assert_eq!(metadata21.version, "0.1.0+abc123de");
+        // From the abi3-py37 feature of pyo3
+        assert_eq!(metadata21.requires_python.as_deref(), Some(">=3.7"));
+        assert!(metadata21
+            .requires_dist
+            .contains(&"boltons; (sys_platform == 'win32') and extra == 'test'".to_string()));
+    }
+}
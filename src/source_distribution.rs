@@ -1,18 +1,26 @@
-use crate::module_writer::{add_data, ModuleWriter};
+use crate::globs::PathGlobs;
+use crate::module_writer::{add_sdist_data, include_matches, ModuleWriter};
 use crate::polyfill::MetadataCommandExt;
-use crate::{pyproject_toml::Format, BuildContext, PyProjectToml, SDistWriter};
+use crate::pyproject_toml::{CargoLockInclusion, Format};
+use crate::{BuildContext, Metadata21, PyProjectToml, SDistWriter, TomlEditor};
 use anyhow::{bail, Context, Result};
-use cargo_metadata::{Metadata, MetadataCommand};
+use cargo_metadata::{DependencyKind, Metadata, MetadataCommand};
 use fs_err as fs;
 use ignore::overrides::Override;
 use normpath::PathExt as _;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
 use std::str;
+use tempfile::TempDir;
 use tracing::debug;
 
 const LOCAL_DEPENDENCIES_FOLDER: &str = "local_dependencies";
+/// The folder in the source distribution for the license files from outside of the project
+const EXTERNAL_LICENSES_FOLDER: &str = "external_licenses";
+/// The directory with the crates from `cargo vendor` in source distributions built with `--vendor`
+const VENDOR_FOLDER: &str = "vendor";
 /// Inheritable workspace fields, see
 /// https://github.com/rust-lang/cargo/blob/13ae438cf079da58272edc71f4d4968043dbd27b/src/cargo/util/toml/mod.rs#L1140-L1158
 const WORKSPACE_INHERITABLE_FIELDS: &[&str] = &[
@@ -59,107 +67,47 @@ fn rewrite_cargo_toml(
         manifest_path.display()
     ))?;
     let mut rewritten = false;
-    //  ˇˇˇˇˇˇˇˇˇˇˇˇ dep_category
-    // [dependencies]
-    // some_path_dep = { path = "../some_path_dep" }
-    //                          ^^^^^^^^^^^^^^^^^^ table[&dep_name]["path"]
-    // ^^^^^^^^^^^^^ dep_name
+    // Dependencies can also be declared per target, e.g. in `[target.'cfg(unix)'.dependencies]`
+    let targets: Vec<String> = data
+        .get("target")
+        .and_then(|x| x.as_table_like())
+        .map(|targets| targets.iter().map(|(key, _)| key.to_string()).collect())
+        .unwrap_or_default();
     for dep_category in &["dependencies", "dev-dependencies", "build-dependencies"] {
+        let workspace_deps = workspace_manifest
+            .get("workspace")
+            .and_then(|x| x.get(dep_category))
+            .and_then(|x| x.as_table_like());
         if let Some(table) = data.get_mut(dep_category).and_then(|x| x.as_table_mut()) {
-            let workspace_deps = workspace_manifest
-                .get("workspace")
-                .and_then(|x| x.get(dep_category))
-                .and_then(|x| x.as_table_like());
-            let dep_names: Vec<_> = table.iter().map(|(key, _)| key.to_string()).collect();
-            for dep_name in dep_names {
-                let workspace_inherit = table
-                    .get(&dep_name)
-                    .and_then(|x| x.get("workspace"))
-                    .and_then(|x| x.as_bool())
-                    .unwrap_or_default();
-
-                if !workspace_inherit {
-                    // There should either be no value for path, or it should be a string
-                    if table.get(&dep_name).and_then(|x| x.get("path")).is_none() {
-                        continue;
-                    }
-                    if !table[&dep_name]["path"].is_str() {
-                        bail!(
-                            "In {}, {} {} has a path value that is not a string",
-                            manifest_path.display(),
-                            dep_category,
-                            dep_name
-                        )
-                    }
-                    if !known_path_deps.contains_key(&dep_name) {
-                        bail!(
-                            "cargo metadata does not know about the path for {}.{} present in {}, \
-                            which should never happen ಠ_ಠ",
-                            dep_category,
-                            dep_name,
-                            manifest_path.display()
-                        );
-                    }
-                } else {
-                    // If a workspace inherited dependency isn't a path dep,
-                    // we need to replace `workspace = true` with its full requirement spec.
-                    if !known_path_deps.contains_key(&dep_name) {
-                        if let Some(workspace_dep) = workspace_deps.and_then(|x| x.get(&dep_name)) {
-                            let mut workspace_dep = workspace_dep.clone();
-                            // Merge optional and features from the current Cargo.toml
-                            if table[&dep_name].get("optional").is_some() {
-                                workspace_dep["optional"] = table[&dep_name]["optional"].clone();
-                            }
-                            if let Some(features) =
-                                table[&dep_name].get("features").and_then(|x| x.as_array())
-                            {
-                                let existing_features = workspace_dep
-                                    .as_table_like_mut()
-                                    .unwrap()
-                                    .entry("features")
-                                    .or_insert_with(|| {
-                                        toml_edit::Item::Value(toml_edit::Array::new().into())
-                                    })
-                                    .as_array_mut()
-                                    .with_context(|| {
-                                        format!(
-                                            "In {}, {} {} has a features value that is not an array",
-                                            manifest_path.display(),
-                                            dep_category,
-                                            dep_name
-                                        )
-                                    })?;
-                                existing_features.extend(features);
-                            }
-                            table[&dep_name] = workspace_dep;
-                            rewritten = true;
-                        } else {
-                            bail!(
-                                "In {}, {} {} is marked as `workspace = true`, but it is found neither in \
-                                the workspace manifest nor in the known path dependencies",
-                                manifest_path.display(),
-                                dep_category,
-                                dep_name
-                            )
-                        }
-                        continue;
-                    }
-                }
-                // This is the location of the targeted crate in the source distribution
-                table[&dep_name]["path"] = if root_crate {
-                    toml_edit::value(format!("{}/{}", local_deps_folder, dep_name))
-                } else {
-                    // Cargo.toml contains relative paths, and we're already in LOCAL_DEPENDENCIES_FOLDER
-                    toml_edit::value(format!("../{}", dep_name))
-                };
-                if workspace_inherit {
-                    // Remove workspace inheritance now that we converted it into a path dependency
-                    table[&dep_name]
-                        .as_table_like_mut()
-                        .unwrap()
-                        .remove("workspace");
-                }
-                rewritten = true;
+            let table_rewritten = rewrite_dependencies(
+                table,
+                manifest_path,
+                dep_category,
+                workspace_deps,
+                known_path_deps,
+                &local_deps_folder,
+                root_crate,
+            )?;
+            // All dev-dependencies of a path dependency may have been removed
+            if table_rewritten && table.is_empty() {
+                data.remove(dep_category);
+            }
+            rewritten |= table_rewritten;
+        }
+        for target in &targets {
+            if let Some(table) = data["target"][target.as_str()]
+                .get_mut(dep_category)
+                .and_then(|x| x.as_table_mut())
+            {
+                rewritten |= rewrite_dependencies(
+                    table,
+                    manifest_path,
+                    &format!("target.'{}'.{}", target, dep_category),
+                    workspace_deps,
+                    known_path_deps,
+                    &local_deps_folder,
+                    root_crate,
+                )?;
             }
         }
     }
@@ -179,6 +127,14 @@ fn rewrite_cargo_toml(
             if workspace_inherited {
                 if let Some(workspace_value) = workspace_package.and_then(|ws| ws.get(key)) {
                     package[key] = workspace_value.clone();
+                    // `version.workspace = true` has no space between the key and `=`
+                    if let Some(decor) = package.key_decor_mut(key) {
+                        decor.set_suffix(" ");
+                    }
+                    // Like `cargo package`, we put inherited files next to Cargo.toml
+                    if let Some(file_name) = inherited_file_name(key, workspace_value) {
+                        package[key] = toml_edit::value(file_name);
+                    }
                     rewritten = true;
                 }
             }
@@ -188,47 +144,54 @@ fn rewrite_cargo_toml(
     if root_crate {
         // Update workspace members
         if let Some(workspace) = data.get_mut("workspace").and_then(|x| x.as_table_mut()) {
-            if let Some(members) = workspace.get_mut("members").and_then(|x| x.as_array_mut()) {
-                if known_path_deps.is_empty() {
-                    // Remove workspace members when there isn't any path dep
-                    workspace.remove("members");
-                    if workspace.is_empty() {
-                        // Remove workspace all together if it's empty
-                        data.remove("workspace");
-                    }
-                    rewritten = true;
-                } else {
-                    let mut new_members = toml_edit::Array::new();
-                    for member in members.iter() {
-                        if let toml_edit::Value::String(ref s) = member {
-                            let path = Path::new(s.value());
-                            if let Some(name) = path.file_name().and_then(|x| x.to_str()) {
-                                if known_path_deps.contains_key(name) {
-                                    new_members
-                                        .push(format!("{}/{}", LOCAL_DEPENDENCIES_FOLDER, name));
+            // Members that aren't path dependencies of the root crate aren't in the source
+            // distribution. `default-members` must be a subset of `members`, so it's pruned too.
+            for key in ["members", "default-members"] {
+                if let Some(members) = workspace.get_mut(key).and_then(|x| x.as_array_mut()) {
+                    if known_path_deps.is_empty() {
+                        // Remove workspace members when there isn't any path dep
+                        workspace.remove(key);
+                        rewritten = true;
+                    } else {
+                        let mut new_members = toml_edit::Array::new();
+                        for member in members.iter() {
+                            if let toml_edit::Value::String(ref s) = member {
+                                let path = Path::new(s.value());
+                                if let Some(name) = path.file_name().and_then(|x| x.to_str()) {
+                                    if known_path_deps.contains_key(name) {
+                                        new_members.push(format!(
+                                            "{}/{}",
+                                            LOCAL_DEPENDENCIES_FOLDER, name
+                                        ));
+                                    }
                                 }
                             }
                         }
-                    }
-                    if !new_members.is_empty() {
-                        workspace["members"] = toml_edit::value(new_members);
+                        if new_members.is_empty() {
+                            workspace.remove(key);
+                        } else {
+                            workspace[key] = toml_edit::value(new_members);
+                        }
                         rewritten = true;
                     }
                 }
             }
+            if workspace.is_empty() {
+                // Remove workspace all together if it's empty
+                data.remove("workspace");
+            }
         }
     } else {
         // Update package.workspace
         // https://rust-lang.github.io/rfcs/1525-cargo-workspace.html#implicit-relations
         // https://doc.rust-lang.org/cargo/reference/manifest.html#the-workspace-field
+        // Local dependencies are moved into LOCAL_DEPENDENCIES_FOLDER, so the workspace they
+        // point to, e.g. the one of a path dependency outside of the root crate's workspace,
+        // doesn't exist in the source distribution.
+        // See https://github.com/PyO3/maturin/issues/838
         if let Some(package) = data.get_mut("package").and_then(|x| x.as_table_mut()) {
-            if let Some(workspace) = package.get("workspace").and_then(|x| x.as_str()) {
-                // This is enough to fix https://github.com/PyO3/maturin/issues/838
-                // Other cases can be fixed on demand
-                if workspace == ".." || workspace == "../" {
-                    package.remove("workspace");
-                    rewritten = true;
-                }
+            if package.remove("workspace").is_some() {
+                rewritten = true;
             }
         }
     }
@@ -239,18 +202,321 @@ fn rewrite_cargo_toml(
     }
 }
 
+/// Points the path dependencies in a dependency table to their location in the source
+/// distribution and replaces `workspace = true` of other dependencies with the requirement from
+/// the workspace. Returns whether anything changed.
+///
+/// Path dependencies are identified by their package name, which differs from the key for
+/// renamed dependencies, e.g. `foo = { path = "../bar", package = "bar" }`
+fn rewrite_dependencies(
+    table: &mut toml_edit::Table,
+    manifest_path: &Path,
+    dep_category: &str,
+    workspace_deps: Option<&dyn toml_edit::TableLike>,
+    known_path_deps: &HashMap<String, PathBuf>,
+    local_deps_folder: &str,
+    root_crate: bool,
+) -> Result<bool> {
+    let mut rewritten = false;
+    // Cargo doesn't build the dev-dependencies of path dependencies, so the ones that are path
+    // dependencies themselves are left out of the source distribution
+    let skipped_dev_dependencies = !root_crate && dep_category.ends_with("dev-dependencies");
+    //  ˇˇˇˇˇˇˇˇˇˇˇˇ dep_category
+    // [dependencies]
+    // some_path_dep = { path = "../some_path_dep" }
+    //                          ^^^^^^^^^^^^^^^^^^ table[&dep_name]["path"]
+    // ^^^^^^^^^^^^^ dep_name
+    let dep_names: Vec<_> = table.iter().map(|(key, _)| key.to_string()).collect();
+    for dep_name in dep_names {
+        let workspace_inherit = table
+            .get(&dep_name)
+            .and_then(|x| x.get("workspace"))
+            .and_then(|x| x.as_bool())
+            .unwrap_or_default();
+        let workspace_dep = workspace_deps
+            .filter(|_| workspace_inherit)
+            .and_then(|x| x.get(&dep_name));
+        let package_name = table
+            .get(&dep_name)
+            .and_then(|x| x.get("package"))
+            .or_else(|| workspace_dep.and_then(|x| x.get("package")))
+            .and_then(|x| x.as_str())
+            .unwrap_or(&dep_name)
+            .to_string();
+
+        if !workspace_inherit {
+            // There should either be no value for path, or it should be a string
+            if table.get(&dep_name).and_then(|x| x.get("path")).is_none() {
+                continue;
+            }
+            if !table[&dep_name]["path"].is_str() {
+                bail!(
+                    "In {}, {} {} has a path value that is not a string",
+                    manifest_path.display(),
+                    dep_category,
+                    dep_name
+                )
+            }
+            if !known_path_deps.contains_key(&package_name) {
+                if skipped_dev_dependencies {
+                    table.remove(&dep_name);
+                    rewritten = true;
+                    continue;
+                }
+                bail!(
+                    "cargo metadata does not know about the path for {}.{} present in {}, \
+                    which should never happen ಠ_ಠ",
+                    dep_category,
+                    dep_name,
+                    manifest_path.display()
+                );
+            }
+        } else {
+            // If a workspace inherited dependency isn't a path dep,
+            // we need to replace `workspace = true` with its full requirement spec.
+            if !known_path_deps.contains_key(&package_name) {
+                if skipped_dev_dependencies && workspace_dep.and_then(|x| x.get("path")).is_some() {
+                    table.remove(&dep_name);
+                    rewritten = true;
+                    continue;
+                }
+                if let Some(workspace_dep) = workspace_dep {
+                    let mut workspace_dep = workspace_dep.clone();
+                    // Merge optional and features from the current Cargo.toml
+                    if table[&dep_name].get("optional").is_some() {
+                        workspace_dep["optional"] = table[&dep_name]["optional"].clone();
+                    }
+                    if let Some(features) =
+                        table[&dep_name].get("features").and_then(|x| x.as_array())
+                    {
+                        let existing_features = workspace_dep
+                            .as_table_like_mut()
+                            .unwrap()
+                            .entry("features")
+                            .or_insert_with(|| {
+                                toml_edit::Item::Value(toml_edit::Array::new().into())
+                            })
+                            .as_array_mut()
+                            .with_context(|| {
+                                format!(
+                                    "In {}, {} {} has a features value that is not an array",
+                                    manifest_path.display(),
+                                    dep_category,
+                                    dep_name
+                                )
+                            })?;
+                        existing_features.extend(features);
+                    }
+                    table[&dep_name] = workspace_dep;
+                    if let Some(decor) = table.key_decor_mut(&dep_name) {
+                        decor.set_suffix(" ");
+                    }
+                    rewritten = true;
+                } else {
+                    bail!(
+                        "In {}, {} {} is marked as `workspace = true`, but it is found neither in \
+                        the workspace manifest nor in the known path dependencies",
+                        manifest_path.display(),
+                        dep_category,
+                        dep_name
+                    )
+                }
+                continue;
+            }
+        }
+        // This is the location of the targeted crate in the source distribution
+        table[&dep_name]["path"] = if root_crate {
+            toml_edit::value(format!("{}/{}", local_deps_folder, package_name))
+        } else {
+            // Cargo.toml contains relative paths, and we're already in LOCAL_DEPENDENCIES_FOLDER
+            toml_edit::value(format!("../{}", package_name))
+        };
+        if workspace_inherit {
+            // Remove workspace inheritance now that we converted it into a path dependency
+            table[&dep_name]
+                .as_table_like_mut()
+                .unwrap()
+                .remove("workspace");
+            if package_name != dep_name {
+                table[&dep_name]["package"] = toml_edit::value(package_name);
+            }
+        }
+        rewritten = true;
+    }
+    Ok(rewritten)
+}
+
+/// Returns the file name of an inherited `readme` or `license-file`, whose path is relative to
+/// the workspace root
+fn inherited_file_name(key: &str, workspace_value: &toml_edit::Item) -> Option<String> {
+    if key != "readme" && key != "license-file" {
+        return None;
+    }
+    let path = Path::new(workspace_value.as_str()?);
+    Some(path.file_name()?.to_str()?.to_string())
+}
+
+/// Points `tool.maturin.manifest-path` of the pyproject.toml to the Cargo.toml in a subdirectory,
+/// so building from the source distribution finds it even when it was passed with
+/// `--manifest-path` on the command line, and sets `project.name` to the name given with
+/// `--dist-name`
+fn rewrite_pyproject_toml(
+    pyproject_toml_path: &Path,
+    abs_manifest_path: Option<&Path>,
+    dist_name: Option<&str>,
+    license_files: Option<&[String]>,
+) -> Result<String> {
+    let pyproject_dir = pyproject_toml_path.parent().unwrap();
+    let mut pyproject_toml = TomlEditor::open(pyproject_toml_path)?;
+    if let Some(dist_name) = dist_name {
+        pyproject_toml.set(&["project", "name"], toml_edit::value(dist_name))?;
+    }
+    if let Some(license_files) = license_files {
+        let license_files: toml_edit::Array = license_files.iter().map(String::as_str).collect();
+        pyproject_toml.set(
+            &["tool", "maturin", "license-files"],
+            toml_edit::value(license_files),
+        )?;
+    }
+    let abs_manifest_path = match abs_manifest_path {
+        Some(abs_manifest_path) => abs_manifest_path,
+        None => return Ok(pyproject_toml.document().to_string()),
+    };
+    let keys = ["tool", "maturin", "manifest-path"];
+    let existing = pyproject_toml
+        .get(&keys)
+        .and_then(|x| x.as_str())
+        .and_then(|x| pyproject_dir.join(x).normalize().ok())
+        .map(|x| x.into_path_buf());
+    if existing.as_deref() != Some(abs_manifest_path) {
+        // Paths in pyproject.toml always use forward slashes
+        let relative_manifest_path = abs_manifest_path
+            .strip_prefix(pyproject_dir)
+            .unwrap()
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        pyproject_toml.set(&keys, toml_edit::value(relative_manifest_path))?;
+    }
+    Ok(pyproject_toml.document().to_string())
+}
+
+/// The license files with their path in the source distribution. The files inside of the project
+/// keep their path, so that the patterns which found them still match. The ones outside of it,
+/// e.g. from `../LICENSE*` in `[tool.maturin] license-files`, go to [EXTERNAL_LICENSES_FOLDER]
+/// under the name they have in the wheel.
+fn license_file_targets(
+    metadata21: &Metadata21,
+    pyproject_dir: &Path,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut targets = Vec::new();
+    for (source, name) in metadata21
+        .license_files
+        .iter()
+        .zip(metadata21.license_file_names())
+    {
+        let source = source.normalize()?.into_path_buf();
+        let target = match source.strip_prefix(pyproject_dir) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => Path::new(EXTERNAL_LICENSES_FOLDER).join(name),
+        };
+        targets.push((source, target));
+    }
+    Ok(targets)
+}
+
+/// The `[tool.maturin] license-files` for the pyproject.toml of the source distribution, in which
+/// the patterns pointing outside of the project are replaced by the paths of the files they
+/// matched in the source distribution, like `manifest-path`. `None` if no pattern points outside
+/// of the project.
+fn sdist_license_files(
+    patterns: &[String],
+    pyproject_dir: &Path,
+    targets: &[(PathBuf, PathBuf)],
+) -> Result<Option<Vec<String>>> {
+    let is_outside = |pattern: &str| {
+        pattern
+            .split(['/', '\\'])
+            .any(|component| component == "..")
+    };
+    if !patterns.iter().any(|pattern| is_outside(pattern)) {
+        return Ok(None);
+    }
+    let path_globs = PathGlobs::new(pyproject_dir, patterns)?;
+    let matches: HashMap<&str, Vec<PathBuf>> = path_globs.matches()?.into_iter().collect();
+    let mut sdist_patterns = Vec::new();
+    for pattern in patterns {
+        if !is_outside(pattern) {
+            sdist_patterns.push(pattern.clone());
+            continue;
+        }
+        // Negated patterns have no matches, the files they removed aren't listed anyway
+        for path in matches.get(pattern.as_str()).into_iter().flatten() {
+            let path = path.normalize()?.into_path_buf();
+            if let Some((_, target)) = targets.iter().find(|(source, _)| *source == path) {
+                // Paths in pyproject.toml always use forward slashes
+                let target = target
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                if !sdist_patterns.contains(&target) {
+                    sdist_patterns.push(target);
+                }
+            }
+        }
+    }
+    Ok(Some(sdist_patterns))
+}
+
+/// Finds the inherited `readme` and `license-file` of the crate in the workspace root, since
+/// `cargo package --list` lists them as if they were next to Cargo.toml
+fn inherited_files(
+    manifest_path: &Path,
+    workspace_manifest: &toml_edit::Document,
+    workspace_root: &Path,
+) -> Result<HashMap<PathBuf, PathBuf>> {
+    let manifest: toml_edit::Document = fs::read_to_string(manifest_path)?.parse()?;
+    let workspace_package = workspace_manifest
+        .get("workspace")
+        .and_then(|x| x.get("package"));
+    let mut files = HashMap::new();
+    for key in ["readme", "license-file"] {
+        let workspace_inherited = manifest
+            .get("package")
+            .and_then(|x| x.get(key))
+            .and_then(|x| x.get("workspace"))
+            .and_then(|x| x.as_bool())
+            .unwrap_or_default();
+        let workspace_value = match workspace_package.and_then(|ws| ws.get(key)) {
+            Some(workspace_value) if workspace_inherited => workspace_value,
+            _ => continue,
+        };
+        if let Some(file_name) = inherited_file_name(key, workspace_value) {
+            let source = workspace_root.join(workspace_value.as_str().unwrap());
+            files.insert(PathBuf::from(file_name), source);
+        }
+    }
+    Ok(files)
+}
+
 /// Copies the files of a crate to a source distribution, recursively adding path dependencies
 /// and rewriting path entries in Cargo.toml
 ///
 /// Runs `cargo package --list --allow-dirty` to obtain a list of files to package.
+#[allow(clippy::too_many_arguments)]
 fn add_crate_to_source_distribution(
     writer: &mut SDistWriter,
     pyproject_toml_path: impl AsRef<Path>,
     manifest_path: impl AsRef<Path>,
     workspace_manifest: &toml_edit::Document,
+    workspace_root: &Path,
     prefix: impl AsRef<Path>,
     known_path_deps: &HashMap<String, PathBuf>,
     root_crate: bool,
+    dist_name: Option<&str>,
+    license_files: Option<&[String]>,
 ) -> Result<()> {
     let manifest_path = manifest_path.as_ref();
     let pyproject_toml_path = pyproject_toml_path.as_ref();
@@ -278,6 +544,9 @@ fn add_crate_to_source_distribution(
         .context("Cargo printed invalid utf-8 ಠ_ಠ")?
         .lines()
         .map(Path::new)
+        // The lock file of the build is added according to `include-cargo-lock`, the ones of
+        // path dependencies aren't used
+        .filter(|path| *path != Path::new("Cargo.lock"))
         .collect();
 
     let abs_manifest_path = manifest_path.normalize()?.into_path_buf();
@@ -287,12 +556,18 @@ fn add_crate_to_source_distribution(
         && abs_manifest_dir != pyproject_dir
         && abs_manifest_dir.starts_with(pyproject_dir);
 
+    let inherited_files = inherited_files(manifest_path, workspace_manifest, workspace_root)?;
     // manifest_dir should be a relative path
     let manifest_dir = manifest_path.parent().unwrap();
-    let mut target_source: Vec<(PathBuf, PathBuf)> = file_list
+    let target_source: Vec<(PathBuf, PathBuf)> = file_list
         .iter()
         .map(|relative_to_manifests| {
-            let relative_to_cwd = manifest_dir.join(relative_to_manifests);
+            let relative_to_cwd = match inherited_files.get(*relative_to_manifests) {
+                Some(source) if !manifest_dir.join(relative_to_manifests).exists() => {
+                    source.clone()
+                }
+                _ => manifest_dir.join(relative_to_manifests),
+            };
             if root_crate && cargo_toml_in_subdir {
                 let relative_to_project_root = abs_manifest_dir
                     .strip_prefix(pyproject_dir)
@@ -323,25 +598,28 @@ fn add_crate_to_source_distribution(
         })
         .collect();
 
-    if root_crate
-        && !target_source
-            .iter()
-            .any(|(target, _)| target == Path::new("pyproject.toml"))
-    {
-        // Add pyproject.toml to the source distribution
-        // if Cargo.toml is in subdirectory of pyproject.toml directory
-        if cargo_toml_in_subdir {
-            target_source.push((
-                PathBuf::from("pyproject.toml"),
-                pyproject_toml_path.to_path_buf(),
-            ));
-        } else {
+    // Add pyproject.toml to the source distribution
+    // if Cargo.toml is in subdirectory of pyproject.toml directory
+    let rewritten_pyproject_toml =
+        if root_crate && (cargo_toml_in_subdir || dist_name.is_some() || license_files.is_some()) {
+            Some(rewrite_pyproject_toml(
+                pyproject_toml_path,
+                Some(abs_manifest_path.as_path()).filter(|_| cargo_toml_in_subdir),
+                dist_name,
+                license_files,
+            )?)
+        } else if root_crate
+            && !target_source
+                .iter()
+                .any(|(target, _)| target == Path::new("pyproject.toml"))
+        {
             bail!(
                 "pyproject.toml was not included by `cargo package`. \
-                     Please make sure pyproject.toml is not excluded or build without `--sdist`"
+                 Please make sure pyproject.toml is not excluded or build without `--sdist`"
             )
-        }
-    }
+        } else {
+            None
+        };
 
     let local_deps_folder = if cargo_toml_in_subdir {
         let level = abs_manifest_dir
@@ -371,15 +649,29 @@ fn add_crate_to_source_distribution(
         prefix.join(manifest_path.file_name().unwrap())
     };
     writer.add_bytes(cargo_toml, rewritten_cargo_toml.as_bytes())?;
+    let pyproject_rewritten = rewritten_pyproject_toml.is_some();
+    if let Some(rewritten_pyproject_toml) = rewritten_pyproject_toml {
+        writer.add_bytes(
+            prefix.join("pyproject.toml"),
+            rewritten_pyproject_toml.as_bytes(),
+        )?;
+    }
 
     for (target, source) in target_source {
+        if pyproject_rewritten && target == Path::new("pyproject.toml") {
+            continue;
+        }
         writer.add_file(prefix.join(target), source)?;
     }
 
     Ok(())
 }
 
-/// Finds all path dependencies of the crate
+/// Finds all path dependencies of the crate, i.e. the transitive closure of path dependencies
+/// starting from the root crate. Other members of the workspace aren't part of it.
+///
+/// Cargo only builds the dev-dependencies of the root crate, so the ones of path dependencies are
+/// skipped, together with the crates only they depend on
 fn find_path_deps(cargo_metadata: &Metadata) -> Result<HashMap<String, PathBuf>> {
     let root = cargo_metadata
         .root_package()
@@ -389,6 +681,12 @@ fn find_path_deps(cargo_metadata: &Metadata) -> Result<HashMap<String, PathBuf>>
     let mut stack: Vec<&cargo_metadata::Package> = vec![root];
     while let Some(top) = stack.pop() {
         for dependency in &top.dependencies {
+            if top.id != root.id && dependency.kind == DependencyKind::Development {
+                continue;
+            }
+            if path_deps.contains_key(&dependency.name) {
+                continue;
+            }
             if let Some(path) = &dependency.path {
                 // we search for the respective package by `manifest_path`, there seems
                 // to be no way to query the dependency graph given `dependency`
@@ -411,6 +709,166 @@ fn find_path_deps(cargo_metadata: &Metadata) -> Result<HashMap<String, PathBuf>>
     Ok(path_deps)
 }
 
+/// Runs `cargo vendor` and adds the vendored crates with a `.cargo/config.toml` that replaces the
+/// sources with them, so building the source distribution needs no network access.
+///
+/// Returns the vendor directory, which must be kept until the archive is finished.
+fn add_vendored_dependencies(
+    writer: &mut SDistWriter,
+    build_context: &BuildContext,
+    root_dir: &Path,
+) -> Result<TempDir> {
+    let vendor_dir = tempfile::tempdir_in(build_context.scratch_dir()?)?;
+    let mut command = Command::new("cargo");
+    command
+        .args(["vendor", "--manifest-path"])
+        .arg(&build_context.manifest_path);
+    if build_context.cargo_options.locked || build_context.cargo_options.frozen {
+        command.arg("--locked");
+    }
+    if build_context.cargo_options.offline {
+        command.arg("--offline");
+    }
+    let output = command
+        .arg(vendor_dir.path())
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Failed to run `cargo vendor`")?;
+    if !output.status.success() {
+        bail!("`cargo vendor` failed with {}", output.status);
+    }
+    // cargo prints the source replacement configuration, which points to the absolute path of
+    // the vendor directory. Paths in `.cargo/config.toml` are relative to the parent of `.cargo`.
+    let config: String = str::from_utf8(&output.stdout)
+        .context("Cargo printed invalid utf-8 ಠ_ಠ")?
+        .lines()
+        .map(|line| {
+            if line.trim_start().starts_with("directory = ") {
+                format!("directory = \"{}\"\n", VENDOR_FOLDER)
+            } else {
+                format!("{}\n", line)
+            }
+        })
+        .collect();
+
+    // The checksums of the vendored crates cover all of their files, so nothing may be skipped
+    for entry in ignore::WalkBuilder::new(vendor_dir.path())
+        .standard_filters(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+    {
+        let source = entry?.into_path();
+        if source.is_file() {
+            let relative = source.strip_prefix(vendor_dir.path()).unwrap();
+            writer.add_generated_file(root_dir.join(VENDOR_FOLDER).join(relative), &source)?;
+        }
+    }
+    writer.add_bytes(
+        root_dir.join(".cargo").join("config.toml"),
+        config.trim_start().as_bytes(),
+    )?;
+    eprintln!("📦 Vendored the dependencies into the source distribution");
+    Ok(vendor_dir)
+}
+
+/// The files committed to the git repository of the project, for `--sdist-from-git`
+struct CommittedFiles {
+    /// The canonical path of the repository root
+    root: PathBuf,
+    /// The canonical paths of the files in `HEAD`
+    committed: HashSet<PathBuf>,
+    /// The canonical paths of the tracked files that differ from `HEAD`
+    modified: HashSet<PathBuf>,
+}
+
+impl CommittedFiles {
+    fn new(project_root: &Path) -> Result<Self> {
+        let git = |args: &[&str]| -> Result<String> {
+            let output = Command::new("git")
+                .args(args)
+                .current_dir(project_root)
+                .output()
+                .context("Failed to run git, which is required for --sdist-from-git")?;
+            if !output.status.success() {
+                bail!(
+                    "`git {}` failed in {}: {}",
+                    args.join(" "),
+                    project_root.display(),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            String::from_utf8(output.stdout).context("git printed invalid utf-8")
+        };
+        let root = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim()).canonicalize()?;
+        let paths = |output: String| -> HashSet<PathBuf> {
+            output
+                .split('\0')
+                .filter(|path| !path.is_empty())
+                .map(|path| root.join(path))
+                .collect()
+        };
+        let committed = paths(git(&[
+            "ls-tree",
+            "-r",
+            "-z",
+            "--name-only",
+            "--full-tree",
+            "HEAD",
+        ])?);
+        let modified = paths(git(&["diff", "--name-only", "-z", "--no-renames", "HEAD"])?);
+        Ok(Self {
+            root,
+            committed,
+            modified,
+        })
+    }
+
+    /// The committed files without uncommitted changes
+    fn unmodified(&self) -> HashSet<PathBuf> {
+        self.committed.difference(&self.modified).cloned().collect()
+    }
+
+    /// Fails if any of the packaged files has uncommitted changes, and reports the files that
+    /// were left out because they aren't committed
+    fn check(&self, writer: &SDistWriter, manifests: &[&Path]) -> Result<()> {
+        let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let mut modified: Vec<PathBuf> = writer
+            .skipped_files()
+            .iter()
+            .map(PathBuf::as_path)
+            .chain(manifests.iter().copied())
+            .map(canonical)
+            .filter(|path| self.modified.contains(path))
+            .collect();
+        modified.sort();
+        modified.dedup();
+        if !modified.is_empty() {
+            bail!(
+                "Can't build the source distribution from git since these files have \
+                uncommitted changes, commit or stash them:\n{}",
+                modified
+                    .iter()
+                    .map(|path| format!("  - {}", path.display()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+        let mut skipped: Vec<&PathBuf> = writer.skipped_files().iter().collect();
+        skipped.sort();
+        skipped.dedup();
+        if !skipped.is_empty() {
+            eprintln!(
+                "📦 Left out {} file(s) that aren't committed to git:",
+                skipped.len()
+            );
+            for path in skipped {
+                eprintln!("  - {}", path.display());
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Creates a source distribution, packing the root crate and all local dependencies
 ///
 /// The source distribution format is specified in
@@ -422,7 +880,22 @@ pub fn source_distribution(
     pyproject: &PyProjectToml,
     excludes: Option<Override>,
 ) -> Result<PathBuf> {
-    let metadata21 = &build_context.metadata21;
+    // The requirements of `[tool.maturin.target.<..>.metadata]` only apply to the wheels of that
+    // target, the source distribution can be built for any of them, so the requirements of a
+    // wheel built from it aren't static
+    let mut metadata21 = build_context.metadata21.clone();
+    metadata21
+        .requires_dist
+        .retain(|x| !build_context.target_requires_dist.contains(x));
+    if (!build_context.target_requires_dist.is_empty() || pyproject.has_target_dependencies())
+        && !metadata21
+            .dynamic
+            .iter()
+            .any(|field| field == "Requires-Dist")
+    {
+        metadata21.dynamic.push("Requires-Dist".to_string());
+    }
+    let metadata21 = &metadata21;
     let manifest_path = &build_context.manifest_path;
     let pyproject_toml_path = build_context
         .pyproject_toml_path
@@ -437,12 +910,25 @@ pub fn source_distribution(
 
     let known_path_deps = find_path_deps(&build_context.cargo_metadata)?;
 
-    let mut writer = SDistWriter::new(&build_context.out, metadata21, excludes)?;
+    let mut writer = SDistWriter::new(
+        &build_context.out,
+        metadata21,
+        excludes.clone(),
+        pyproject.unicode_normalization(),
+        pyproject.tar_format(),
+    )?;
     let root_dir = PathBuf::from(format!(
         "{}-{}",
         &metadata21.get_distribution_escaped(),
         &metadata21.get_version_escaped()
     ));
+    let committed_files = if build_context.sdist_from_git {
+        let committed_files = CommittedFiles::new(pyproject_toml_path.parent().unwrap())?;
+        writer.set_allowed_files(committed_files.root.clone(), committed_files.unmodified());
+        Some(committed_files)
+    } else {
+        None
+    };
 
     // Add local path dependencies
     let mut path_dep_workspace_manifests = HashMap::new();
@@ -479,9 +965,12 @@ pub fn source_distribution(
             &pyproject_toml_path,
             path_dep,
             path_dep_workspace_manifest,
+            path_dep_metadata.workspace_root.as_std_path(),
             &root_dir.join(LOCAL_DEPENDENCIES_FOLDER).join(name),
             &known_path_deps,
             false,
+            None,
+            None,
         )
         .context(format!(
             "Failed to add local dependency {} at {} to the source distribution",
@@ -490,40 +979,121 @@ pub fn source_distribution(
         ))?;
     }
 
+    // The distribution name differs from `project.name` with `--dist-name`
+    let dist_name = match &pyproject.project {
+        Some(project) if project.name != metadata21.name => Some(metadata21.name.as_str()),
+        Some(_) => None,
+        None => {
+            if build_context.crate_name != metadata21.name {
+                eprintln!(
+                    "⚠️  Warning: pyproject.toml has no [project] table to record the \
+                    distribution name `{}` in, wheels built from the source distribution are \
+                    named `{}`",
+                    metadata21.name, build_context.crate_name
+                );
+            }
+            None
+        }
+    };
+    let pyproject_dir = pyproject_toml_path.parent().unwrap();
+    let mut license_files = license_file_targets(metadata21, pyproject_dir)?;
+    let sdist_license_files = match pyproject.license_files() {
+        Some(patterns) => sdist_license_files(patterns, pyproject_dir, &license_files)?,
+        None => None,
+    };
+    // Cargo packages the `license-file` of the crate itself, even from the workspace root
+    license_files.retain(|(_, target)| {
+        !target.starts_with(EXTERNAL_LICENSES_FOLDER)
+            || sdist_license_files
+                .iter()
+                .flatten()
+                .any(|pattern| Path::new(pattern) == target)
+    });
     // Add the main crate
     add_crate_to_source_distribution(
         &mut writer,
         &pyproject_toml_path,
         manifest_path,
         &workspace_manifest,
+        build_context.cargo_metadata.workspace_root.as_std_path(),
         &root_dir,
         &known_path_deps,
         true,
+        dist_name,
+        sdist_license_files.as_deref(),
     )?;
 
     let abs_manifest_path = manifest_path.normalize()?.into_path_buf();
     let abs_manifest_dir = abs_manifest_path.parent().unwrap();
-    let cargo_lock_path = abs_manifest_dir.join("Cargo.lock");
+    let project_root = pyproject_toml_path.parent().unwrap();
+    let mut cargo_lock_path = abs_manifest_dir.join("Cargo.lock");
+    // A crate in a subdirectory of the project is usually a member of a workspace whose root is
+    // the project root. The workspace root isn't part of the source distribution, so its lock file
+    // goes next to the crate.
+    let workspace_root = build_context.cargo_metadata.workspace_root.as_std_path();
+    if !cargo_lock_path.exists() && workspace_root.starts_with(project_root) {
+        cargo_lock_path = workspace_root.join("Cargo.lock");
+    }
     let cargo_lock_required =
         build_context.cargo_options.locked || build_context.cargo_options.frozen;
-    if cargo_lock_required || cargo_lock_path.exists() {
-        let project_root = pyproject_toml_path.parent().unwrap();
-        let relative_cargo_lock = if cargo_lock_path.starts_with(project_root) {
-            cargo_lock_path.strip_prefix(project_root).unwrap()
+    let include_cargo_lock = match build_context.include_cargo_lock {
+        CargoLockInclusion::Always => {
+            if !cargo_lock_path.exists() {
+                bail!(
+                    "Cargo.lock is not found at {}, but `include-cargo-lock` is set to `always`. \
+                    Run `cargo generate-lockfile` to create it",
+                    cargo_lock_path.display()
+                );
+            }
+            true
+        }
+        CargoLockInclusion::Never => {
+            if cargo_lock_required {
+                bail!(
+                    "The source distribution can't be built with --locked or --frozen \
+                    when `include-cargo-lock` is set to `never`"
+                );
+            }
+            if cargo_lock_path.exists() {
+                eprintln!(
+                    "⚠️  Warning: Cargo.lock is not included in the source distribution, \
+                    so building it may pick up different dependency versions"
+                );
+            }
+            false
+        }
+        CargoLockInclusion::IfPresent => cargo_lock_required || cargo_lock_path.exists(),
+    };
+    if include_cargo_lock {
+        let relative_cargo_lock = if abs_manifest_dir.starts_with(project_root) {
+            abs_manifest_dir.strip_prefix(project_root).unwrap()
         } else {
-            cargo_lock_path.strip_prefix(abs_manifest_dir).unwrap()
+            Path::new("")
         };
-        writer.add_file(root_dir.join(relative_cargo_lock), &cargo_lock_path)?;
-    } else {
+        writer.add_file(
+            root_dir.join(relative_cargo_lock).join("Cargo.lock"),
+            &cargo_lock_path,
+        )?;
+    } else if build_context.include_cargo_lock == CargoLockInclusion::IfPresent {
         eprintln!(
             "⚠️  Warning: Cargo.lock is not found, it is recommended \
             to include it in the source distribution"
         );
     }
 
-    let pyproject_dir = pyproject_toml_path.parent().unwrap();
     // Add python source files
-    if let Some(python_source) = build_context.project_layout.python_module.as_ref() {
+    let project_layout = &build_context.project_layout;
+    for python_source in project_layout
+        .python_module
+        .iter()
+        .chain(&project_layout.extra_python_modules)
+    {
+        if !python_source.starts_with(pyproject_dir) {
+            bail!(
+                "The python source directory {} must be placed inside of the directory containing pyproject.toml",
+                python_source.display()
+            );
+        }
         for entry in ignore::Walk::new(python_source) {
             let source = entry?.into_path();
             // Technically, `ignore` crate should handle this,
@@ -560,13 +1130,13 @@ pub fn source_distribution(
             writer.add_file(root_dir.join(license), pyproject_dir.join(license))?;
         }
     }
+    for (source, target) in &license_files {
+        writer.add_file(root_dir.join(target), source)?;
+    }
 
-    let mut include = |pattern| -> Result<()> {
-        println!("📦 Including files matching \"{}\"", pattern);
-        for source in glob::glob(&pyproject_dir.join(pattern).to_string_lossy())
-            .expect("No files found for pattern")
-            .filter_map(Result::ok)
-        {
+    let unmatched_include = pyproject.unmatched_include();
+    let mut include = |patterns: &[&str]| -> Result<()> {
+        for source in include_matches(pyproject_dir, patterns, unmatched_include)? {
             let target = root_dir.join(source.strip_prefix(pyproject_dir).unwrap());
             if source.is_dir() {
                 writer.add_directory(target)?;
@@ -577,31 +1147,46 @@ pub fn source_distribution(
         Ok(())
     };
 
-    #[allow(deprecated)]
-    if let Some(include_targets) = pyproject.sdist_include() {
-        eprintln!(
-            "⚠️  Warning: `[tool.maturin.sdist-include]` is deprecated, please use `[tool.maturin.include]`"
-        );
-        for pattern in include_targets {
-            include(pattern.as_str())?;
-        }
-    }
-
     if let Some(glob_patterns) = pyproject.include() {
-        for pattern in glob_patterns
+        let patterns: Vec<&str> = glob_patterns
             .iter()
             .filter_map(|glob_pattern| glob_pattern.targets(Format::Sdist))
-        {
-            include(pattern)?;
-        }
+            .collect();
+        include(&patterns)?;
     }
 
+    let _vendor_dir = if build_context.vendor {
+        Some(add_vendored_dependencies(
+            &mut writer,
+            build_context,
+            &root_dir,
+        )?)
+    } else {
+        None
+    };
+
     writer.add_bytes(
         root_dir.join("PKG-INFO"),
-        metadata21.to_file_contents()?.as_bytes(),
+        metadata21.to_sdist_file_contents()?.as_bytes(),
     )?;
 
-    add_data(&mut writer, build_context.project_layout.data.as_deref())?;
+    add_sdist_data(
+        &mut writer,
+        &build_context.project_layout.data,
+        &root_dir,
+        pyproject_dir,
+        excludes.as_ref(),
+    )?;
+    if let Some(committed_files) = &committed_files {
+        // The manifests are rewritten rather than copied, so they aren't in the skipped files
+        let mut manifests = vec![
+            pyproject_toml_path.as_path(),
+            manifest_path.as_path(),
+            workspace_manifest_path.as_std_path(),
+        ];
+        manifests.extend(known_path_deps.values().map(PathBuf::as_path));
+        committed_files.check(&writer, &manifests)?;
+    }
     let source_distribution_path = writer.finish()?;
 
     println!(
@@ -611,3 +1196,70 @@ pub fn source_distribution(
 
     Ok(source_distribution_path)
 }
+
+/// Builds a wheel from the source distribution like a build frontend does when there's no
+/// matching wheel: it's unpacked into a temporary directory and `maturin pep517 build-wheel` runs
+/// in it. Files missing from the source distribution only show up this way, often only when
+/// a user without a wheel tries to install it.
+///
+/// `maturin` is the executable used as build backend and `target_dir` the cargo target directory
+/// of the build. Returns the file name of the built wheel.
+pub fn verify_source_distribution(
+    sdist_path: &Path,
+    maturin: &Path,
+    target_dir: &Path,
+) -> Result<String> {
+    let top_level = sdist_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(".tar.gz"))
+        .with_context(|| {
+            format!(
+                "{} is not a .tar.gz source distribution",
+                sdist_path.display()
+            )
+        })?;
+    let unpack_dir = tempfile::tempdir()?;
+    tar::Archive::new(flate2::read::GzDecoder::new(fs::File::open(sdist_path)?))
+        .unpack(unpack_dir.path())
+        .with_context(|| format!("Failed to unpack {}", sdist_path.display()))?;
+    let source_dir = unpack_dir.path().join(top_level);
+    // The build runs in the unpacked source distribution
+    let target_dir = env::current_dir()?.join(target_dir);
+
+    eprintln!(
+        "🔍 Verifying that a wheel builds from {}",
+        sdist_path.display()
+    );
+    // The same arguments as the PEP 517 frontend in maturin/__init__.py, except for the
+    // interpreter which is the default one instead of the one running pip
+    let output = Command::new(maturin)
+        .args(["pep517", "build-wheel", "--compatibility", "off"])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .current_dir(&source_dir)
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("Failed to run {}", maturin.display()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    print!("{}", stdout);
+    if !output.status.success() {
+        bail!(
+            "Building a wheel from the source distribution {} failed, files the build needs \
+            may be missing from it. `tar -tzf {}` lists the files it contains",
+            sdist_path.display(),
+            sdist_path.display()
+        );
+    }
+    let wheel_path = stdout
+        .lines()
+        .last()
+        .context("The build backend didn't print the path of the built wheel")?;
+    let wheel_name = Path::new(wheel_path.trim())
+        .file_name()
+        .context("The build backend printed an invalid wheel path")?
+        .to_string_lossy()
+        .to_string();
+    eprintln!("✅ Built {} from the source distribution", wheel_name);
+    Ok(wheel_name)
+}
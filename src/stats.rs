@@ -0,0 +1,362 @@
+//! Opt-in local build statistics, recorded to a file in the target directory and shown with
+//! `maturin stats`. Nothing is ever sent anywhere.
+use crate::dynamic_metadata::date_from_epoch;
+use crate::project_layout::ProjectResolver;
+use crate::{BuildContext, BuiltWheelMetadata, CargoOptions};
+use anyhow::{Context, Result};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Set to `1` to record build statistics without `tool.maturin.stats`
+const STATS_ENV_VAR: &str = "MATURIN_STATS";
+
+/// The cargo artifacts of the current build that were already up to date
+static FRESH_ARTIFACTS: AtomicUsize = AtomicUsize::new(0);
+/// The cargo artifacts of the current build that had to be compiled
+static COMPILED_ARTIFACTS: AtomicUsize = AtomicUsize::new(0);
+
+/// A built wheel and its size
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactSize {
+    /// The file name of the wheel
+    pub name: String,
+    /// The size in bytes
+    pub size: u64,
+}
+
+/// The statistics of a single `build_wheels` run, one json object per line in the stats file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildRecord {
+    /// Seconds since the unix epoch when the build finished
+    pub timestamp: u64,
+    /// The maturin version that built the wheels
+    pub maturin_version: String,
+    /// The target triple
+    pub target: String,
+    /// Whether the wheels were built in release mode
+    pub release: bool,
+    /// The cargo profile, missing in the records of older maturin versions
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// The wall time of the build in seconds
+    pub duration_secs: f64,
+    /// The cargo artifacts that were already up to date
+    pub cargo_fresh: usize,
+    /// The cargo artifacts that had to be compiled
+    pub cargo_compiled: usize,
+    /// The built wheels
+    pub artifacts: Vec<ArtifactSize>,
+}
+
+impl BuildRecord {
+    /// The share of the cargo artifacts that didn't need to be rebuilt
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let total = self.cargo_fresh + self.cargo_compiled;
+        if total == 0 {
+            None
+        } else {
+            Some(self.cargo_fresh as f64 / total as f64)
+        }
+    }
+
+    /// The profile shown next to the target, none for release builds
+    fn profile_label(&self) -> Option<&str> {
+        match self.profile.as_deref() {
+            Some("release") => None,
+            Some("dev") | Some("test") => Some("debug"),
+            Some(profile) => Some(profile),
+            None if self.release => None,
+            None => Some("debug"),
+        }
+    }
+
+    /// The combined size of all wheels in bytes
+    pub fn total_size(&self) -> u64 {
+        self.artifacts.iter().map(|artifact| artifact.size).sum()
+    }
+}
+
+/// The location of the stats file of a project
+pub fn stats_path(target_dir: &Path) -> PathBuf {
+    target_dir.join("maturin").join("stats.jsonl")
+}
+
+/// Whether builds should be recorded, either through `tool.maturin.stats` or `MATURIN_STATS=1`
+pub(crate) fn stats_enabled(pyproject_stats: bool) -> bool {
+    match std::env::var(STATS_ENV_VAR) {
+        Ok(value) => value == "1" || value.eq_ignore_ascii_case("true"),
+        Err(_) => pyproject_stats,
+    }
+}
+
+/// Resets the cargo artifact counters at the start of a build
+pub(crate) fn reset_cargo_artifacts() {
+    FRESH_ARTIFACTS.store(0, Ordering::Relaxed);
+    COMPILED_ARTIFACTS.store(0, Ordering::Relaxed);
+}
+
+/// Counts an artifact reported by cargo, `fresh` artifacts didn't need to be rebuilt
+pub(crate) fn count_cargo_artifact(fresh: bool) {
+    if fresh {
+        FRESH_ARTIFACTS.fetch_add(1, Ordering::Relaxed);
+    } else {
+        COMPILED_ARTIFACTS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Appends the statistics of a finished build to the stats file
+pub(crate) fn record_build(
+    context: &BuildContext,
+    duration: Duration,
+    wheels: &[BuiltWheelMetadata],
+) -> Result<()> {
+    let artifacts = wheels
+        .iter()
+        .map(|(path, _)| {
+            Ok(ArtifactSize {
+                name: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                size: fs::metadata(path)?.len(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let record = BuildRecord {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or_default(),
+        maturin_version: env!("CARGO_PKG_VERSION").to_string(),
+        target: context.target.target_triple().to_string(),
+        release: context.release,
+        profile: Some(context.cargo_profile().to_string()),
+        duration_secs: duration.as_secs_f64(),
+        cargo_fresh: FRESH_ARTIFACTS.load(Ordering::Relaxed),
+        cargo_compiled: COMPILED_ARTIFACTS.load(Ordering::Relaxed),
+        artifacts,
+    };
+    append_record(&stats_path(&context.target_dir), &record)
+}
+
+fn append_record(path: &Path, record: &BuildRecord) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+/// Reads all records of the stats file, skipping lines that can't be parsed, e.g. from a
+/// build that was interrupted while writing
+pub fn read_records(path: &Path) -> Result<Vec<BuildRecord>> {
+    let contents = fs::read_to_string(path)?;
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Options for `maturin stats`
+#[derive(Debug, clap::Parser)]
+pub struct StatsOptions {
+    /// The path to the Cargo.toml
+    #[arg(short = 'm', long = "manifest-path", value_name = "PATH")]
+    manifest_path: Option<PathBuf>,
+    /// The number of most recent builds to show
+    #[arg(short = 'n', long, default_value = "20")]
+    last: usize,
+    /// Print the records as json lines instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+/// Shows the recorded builds of the project with the trends of the build duration, the cargo
+/// cache hit rate and the wheel sizes
+pub fn stats(options: StatsOptions) -> Result<()> {
+    let ProjectResolver { cargo_metadata, .. } =
+        ProjectResolver::resolve(options.manifest_path, CargoOptions::default())?;
+    let path = stats_path(cargo_metadata.target_directory.as_std_path());
+    if !path.is_file() {
+        eprintln!(
+            "💡 No builds recorded yet. Set `stats = true` in `[tool.maturin]` or \
+            `{}=1` to record build statistics to {}",
+            STATS_ENV_VAR,
+            path.display()
+        );
+        return Ok(());
+    }
+    let records = read_records(&path).context("Failed to read the build statistics")?;
+    let records = &records[records.len().saturating_sub(options.last)..];
+    if options.json {
+        for record in records {
+            println!("{}", serde_json::to_string(record)?);
+        }
+    } else {
+        print!("{}", format_report(records));
+    }
+    Ok(())
+}
+
+/// Formats a table of the builds followed by the trends
+fn format_report(records: &[BuildRecord]) -> String {
+    let mut output = format!(
+        "{:<20}  {:>9}  {:>10}  {:>10}  {}\n",
+        "finished (UTC)", "duration", "cache hits", "size", "target"
+    );
+    for record in records {
+        output.push_str(&format!(
+            "{:<20}  {:>8.1}s  {:>10}  {:>10}  {}{}\n",
+            format_timestamp(record.timestamp),
+            record.duration_secs,
+            record
+                .cache_hit_rate()
+                .map(|rate| format!("{:.0}%", rate * 100.0))
+                .unwrap_or_else(|| "-".to_string()),
+            format_size(record.total_size()),
+            record.target,
+            record
+                .profile_label()
+                .map(|profile| format!(" ({})", profile))
+                .unwrap_or_default(),
+        ));
+    }
+    if records.len() > 1 {
+        let durations: Vec<f64> = records.iter().map(|x| x.duration_secs).collect();
+        let hit_rates: Vec<f64> = records
+            .iter()
+            .map(|x| x.cache_hit_rate().unwrap_or_default())
+            .collect();
+        let sizes: Vec<f64> = records.iter().map(|x| x.total_size() as f64).collect();
+        output.push('\n');
+        output.push_str(&format!("duration    {}\n", sparkline(&durations)));
+        output.push_str(&format!("cache hits  {}\n", sparkline(&hit_rates)));
+        output.push_str(&format!("size        {}\n", sparkline(&sizes)));
+    }
+    output
+}
+
+/// Draws the values as a line of block characters scaled between the minimum and maximum
+fn sparkline(values: &[f64]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|value| {
+            if max > min {
+                let index = ((value - min) / (max - min) * (BLOCKS.len() - 1) as f64).round();
+                BLOCKS[index as usize]
+            } else {
+                BLOCKS[BLOCKS.len() / 2]
+            }
+        })
+        .collect()
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Formats seconds since the unix epoch as `YYYY-MM-DD HH:MM:SS` in UTC
+fn format_timestamp(timestamp: u64) -> String {
+    let seconds = timestamp % 86400;
+    format!(
+        "{} {:02}:{:02}:{:02}",
+        date_from_epoch(timestamp, "-"),
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(timestamp: u64, duration_secs: f64, cargo_fresh: usize, size: u64) -> BuildRecord {
+        BuildRecord {
+            timestamp,
+            maturin_version: "0.14.0".to_string(),
+            target: "x86_64-unknown-linux-gnu".to_string(),
+            release: true,
+            profile: Some("release".to_string()),
+            duration_secs,
+            cargo_fresh,
+            cargo_compiled: 4 - cargo_fresh,
+            artifacts: vec![ArtifactSize {
+                name: "a-0.1.0-cp37-abi3-manylinux_2_17_x86_64.whl".to_string(),
+                size,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_records_roundtrip() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = stats_path(tmp_dir.path());
+        let records = [
+            record(1665705600, 61.5, 0, 2048),
+            record(1665705900, 3.2, 3, 2100),
+        ];
+        for record in &records {
+            append_record(&path, record).unwrap();
+        }
+        // An interrupted write
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"timestamp\": 1")
+            .unwrap();
+        assert_eq!(read_records(&path).unwrap(), records);
+
+        let report = format_report(&records);
+        assert!(report.contains(
+            "2022-10-14 00:00:00       61.5s          0%     2.0 KiB  x86_64-unknown-linux-gnu\n"
+        ));
+        assert!(report.contains("2022-10-14 00:05:00        3.2s         75%     2.1 KiB"));
+        assert!(report.contains("duration    █▁\n"));
+        assert!(report.contains("cache hits  ▁█\n"));
+    }
+
+    #[test]
+    fn test_profile_label() {
+        let mut record = record(1665705600, 61.5, 0, 2048);
+        assert_eq!(record.profile_label(), None);
+        record.profile = Some("release-lto".to_string());
+        assert_eq!(record.profile_label(), Some("release-lto"));
+        record.profile = Some("dev".to_string());
+        assert_eq!(record.profile_label(), Some("debug"));
+        // Records of older versions only know whether it was a release build
+        record.profile = None;
+        record.release = false;
+        assert_eq!(record.profile_label(), Some("debug"));
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(951825600), "2000-02-29 12:00:00");
+        assert_eq!(format_timestamp(1735689599), "2024-12-31 23:59:59");
+    }
+}
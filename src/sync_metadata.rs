@@ -0,0 +1,534 @@
+//! Detects and fixes python package metadata that is specified differently in
+//! Cargo.toml and pyproject.toml
+use crate::project_layout::ProjectResolver;
+use crate::pyproject_toml::MetadataSource;
+use crate::{CargoOptions, Metadata21, PyProjectToml, TomlEditor};
+use anyhow::{Context, Result};
+use cargo_metadata::Package;
+use std::fmt;
+use std::path::PathBuf;
+
+/// The metadata fields which can be specified in both Cargo.toml and pyproject.toml
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum MetadataField {
+    /// `package.description` and `project.description`
+    Description,
+    /// `package.license` and `project.license.text`
+    License,
+    /// `package.authors` and `project.authors`
+    Authors,
+    /// `package.repository` and `project.urls`
+    Repository,
+    /// `package.version` and `project.version`
+    Version,
+}
+
+impl fmt::Display for MetadataField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataField::Description => write!(f, "description"),
+            MetadataField::License => write!(f, "license"),
+            MetadataField::Authors => write!(f, "authors"),
+            MetadataField::Repository => write!(f, "repository"),
+            MetadataField::Version => write!(f, "version"),
+        }
+    }
+}
+
+/// A metadata field that has different values in Cargo.toml and pyproject.toml
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MetadataDrift {
+    /// The field that differs
+    pub field: MetadataField,
+    /// The value in Cargo.toml
+    pub cargo: String,
+    /// The value in pyproject.toml
+    pub pyproject: String,
+}
+
+/// `project.urls` keys that are treated as the equivalent of `package.repository`
+const REPOSITORY_URL_KEYS: &[&str] = &["repository", "source", "source code"];
+
+/// Returns the `project.urls` key and value that corresponds to `package.repository`
+fn pyproject_repository(pyproject_toml: &PyProjectToml) -> Option<(&str, &str)> {
+    let urls = pyproject_toml.project.as_ref()?.urls.as_ref()?;
+    let mut keys: Vec<&String> = urls.keys().collect();
+    // HashMap iteration order is random, make sure we pick the same key every time
+    keys.sort();
+    keys.into_iter()
+        .find(|key| REPOSITORY_URL_KEYS.contains(&key.to_lowercase().as_str()))
+        .map(|key| (key.as_str(), urls[key].as_str()))
+}
+
+/// Formats the `project.authors` the same way as cargo's `package.authors`
+fn pyproject_authors(pyproject_toml: &PyProjectToml) -> Option<Vec<String>> {
+    let authors = pyproject_toml.project.as_ref()?.authors.as_ref()?;
+    Some(
+        authors
+            .iter()
+            .filter_map(|author| match (&author.name, &author.email) {
+                (Some(name), Some(email)) => Some(format!("{} <{}>", name, email)),
+                (Some(name), None) => Some(name.clone()),
+                (None, Some(email)) => Some(email.clone()),
+                (None, None) => None,
+            })
+            .collect(),
+    )
+}
+
+fn normalize_version(version: &str) -> String {
+    Metadata21 {
+        version: version.to_string(),
+        ..Default::default()
+    }
+    .get_pep440_version()
+}
+
+/// Compares the metadata fields which are set in both Cargo.toml and pyproject.toml
+/// and returns all fields whose values differ
+pub fn find_metadata_drift(
+    package: &Package,
+    pyproject_toml: &PyProjectToml,
+) -> Vec<MetadataDrift> {
+    let project = match &pyproject_toml.project {
+        Some(project) => project,
+        None => return Vec::new(),
+    };
+    let mut drift = Vec::new();
+    let mut compare = |field, cargo: Option<String>, pyproject: Option<String>| {
+        if let (Some(cargo), Some(pyproject)) = (cargo, pyproject) {
+            if cargo != pyproject {
+                drift.push(MetadataDrift {
+                    field,
+                    cargo,
+                    pyproject,
+                });
+            }
+        }
+    };
+
+    compare(
+        MetadataField::Description,
+        package.description.as_ref().map(|x| x.trim().to_string()),
+        project.description.as_ref().map(|x| x.trim().to_string()),
+    );
+    compare(
+        MetadataField::License,
+        package.license.clone(),
+        project
+            .license
+            .as_ref()
+            .and_then(|license| license.text.clone()),
+    );
+    let cargo_authors = if package.authors.is_empty() {
+        None
+    } else {
+        let mut authors = package.authors.clone();
+        authors.sort();
+        Some(authors.join(", "))
+    };
+    let pyproject_authors = pyproject_authors(pyproject_toml)
+        .filter(|authors| !authors.is_empty())
+        .map(|mut authors| {
+            authors.sort();
+            authors.join(", ")
+        });
+    compare(MetadataField::Authors, cargo_authors, pyproject_authors);
+    compare(
+        MetadataField::Repository,
+        package
+            .repository
+            .as_ref()
+            .map(|x| x.trim_end_matches('/').to_string()),
+        pyproject_repository(pyproject_toml).map(|(_, url)| url.trim_end_matches('/').to_string()),
+    );
+    compare(
+        MetadataField::Version,
+        Some(normalize_version(&package.version.to_string())),
+        project.version.as_deref().map(normalize_version),
+    );
+    drift
+}
+
+/// Prints a warning for every field that differs between Cargo.toml and pyproject.toml
+pub fn warn_metadata_drift(drift: &[MetadataDrift], source: MetadataSource) {
+    for MetadataDrift {
+        field,
+        cargo,
+        pyproject,
+    } in drift
+    {
+        eprintln!(
+            "⚠️  Warning: The {} in Cargo.toml (\"{}\") doesn't match the one in pyproject.toml (\"{}\"), \
+            using the value from {}. Run `maturin sync-metadata` to update {}.",
+            field,
+            cargo,
+            pyproject,
+            source,
+            source.other(),
+        );
+    }
+}
+
+/// Overwrites the fields in `metadata21` that differ with the values from Cargo.toml
+/// when Cargo.toml is the authoritative metadata source
+pub fn apply_cargo_metadata(
+    metadata21: &mut Metadata21,
+    cargo_metadata21: &Metadata21,
+    drift: &[MetadataDrift],
+) {
+    for MetadataDrift { field, .. } in drift {
+        match field {
+            MetadataField::Description => metadata21.summary = cargo_metadata21.summary.clone(),
+            MetadataField::License => metadata21.license = cargo_metadata21.license.clone(),
+            MetadataField::Authors => {
+                metadata21.author = cargo_metadata21.author.clone();
+                metadata21.author_email = cargo_metadata21.author_email.clone();
+            }
+            MetadataField::Repository => {
+                metadata21
+                    .project_url
+                    .retain(|key, _| !REPOSITORY_URL_KEYS.contains(&key.to_lowercase().as_str()));
+                metadata21.project_url.extend(
+                    cargo_metadata21
+                        .project_url
+                        .iter()
+                        .filter(|(key, _)| {
+                            REPOSITORY_URL_KEYS.contains(&key.to_lowercase().as_str())
+                        })
+                        .map(|(key, value)| (key.clone(), value.clone())),
+                );
+            }
+            MetadataField::Version => metadata21.version = cargo_metadata21.version.clone(),
+        }
+    }
+}
+
+/// Writes the cargo values of the given fields into pyproject.toml
+fn update_pyproject_toml(
+    editor: &mut TomlEditor,
+    package: &Package,
+    fields: &[MetadataField],
+    repository_key: Option<&str>,
+) -> Result<()> {
+    for field in fields {
+        let key = match field {
+            MetadataField::Repository => "urls".to_string(),
+            field => field.to_string(),
+        };
+        if editor.is_dynamic(&key) {
+            eprintln!(
+                "⚠️  Warning: project.{} is marked as dynamic in pyproject.toml, skipping it",
+                key
+            );
+            continue;
+        }
+        match field {
+            MetadataField::Description => {
+                if let Some(description) = &package.description {
+                    editor.set(
+                        &["project", "description"],
+                        toml_edit::value(description.trim()),
+                    )?;
+                }
+            }
+            MetadataField::License => {
+                if let Some(license) = &package.license {
+                    let mut table = toml_edit::InlineTable::new();
+                    table.insert("text", license.as_str().into());
+                    editor.set(&["project", "license"], toml_edit::value(table))?;
+                }
+            }
+            MetadataField::Authors => {
+                let mut authors = toml_edit::Array::new();
+                for author in &package.authors {
+                    let mut table = toml_edit::InlineTable::new();
+                    match author.split_once('<') {
+                        Some((name, email)) => {
+                            let name = name.trim();
+                            if !name.is_empty() {
+                                table.insert("name", name.into());
+                            }
+                            table.insert("email", email.trim().trim_end_matches('>').into());
+                        }
+                        None if author.contains('@') => {
+                            table.insert("email", author.trim().into());
+                        }
+                        None => {
+                            table.insert("name", author.trim().into());
+                        }
+                    }
+                    authors.push(table);
+                }
+                editor.set(&["project", "authors"], toml_edit::value(authors))?;
+            }
+            MetadataField::Repository => {
+                if let Some(repository) = &package.repository {
+                    let key = repository_key.unwrap_or("Repository");
+                    editor.set(
+                        &["project", "urls", key],
+                        toml_edit::value(repository.as_str()),
+                    )?;
+                }
+            }
+            MetadataField::Version => {
+                editor.set_version(&normalize_version(&package.version.to_string()))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the pyproject.toml values of the given fields into Cargo.toml
+fn update_cargo_toml(
+    editor: &mut TomlEditor,
+    pyproject_toml: &PyProjectToml,
+    fields: &[MetadataField],
+) -> Result<()> {
+    let project = match &pyproject_toml.project {
+        Some(project) => project,
+        None => return Ok(()),
+    };
+    for field in fields {
+        if editor.is_workspace_inherited(&field.to_string()) {
+            eprintln!(
+                "⚠️  Warning: package.{} is inherited from the workspace, please update it in the workspace Cargo.toml",
+                field
+            );
+            continue;
+        }
+        match field {
+            MetadataField::Description => {
+                if let Some(description) = &project.description {
+                    editor.set(
+                        &["package", "description"],
+                        toml_edit::value(description.trim()),
+                    )?;
+                }
+            }
+            MetadataField::License => {
+                if let Some(license) = project.license.as_ref().and_then(|x| x.text.as_ref()) {
+                    editor.set(&["package", "license"], toml_edit::value(license.as_str()))?;
+                }
+            }
+            MetadataField::Authors => {
+                if let Some(authors) = pyproject_authors(pyproject_toml) {
+                    editor.set(
+                        &["package", "authors"],
+                        toml_edit::value(authors.into_iter().collect::<toml_edit::Array>()),
+                    )?;
+                }
+            }
+            MetadataField::Repository => {
+                if let Some((_, url)) = pyproject_repository(pyproject_toml) {
+                    editor.set(&["package", "repository"], toml_edit::value(url))?;
+                }
+            }
+            MetadataField::Version => {
+                if let Some(version) = &project.version {
+                    match semver::Version::parse(version) {
+                        Ok(version) => editor.set_version(&version.to_string())?,
+                        Err(_) => eprintln!(
+                            "⚠️  Warning: pyproject.toml version {} is not a valid semver version, \
+                            please update package.version in Cargo.toml manually",
+                            version
+                        ),
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Options for `maturin sync-metadata`
+#[derive(Debug, clap::Parser)]
+pub struct SyncMetadataOptions {
+    /// The path to the Cargo.toml
+    #[arg(short = 'm', long = "manifest-path", value_name = "PATH")]
+    manifest_path: Option<PathBuf>,
+    /// The manifest to copy the metadata from, defaults to `tool.maturin.metadata-source`
+    #[arg(long, value_enum)]
+    from: Option<MetadataSource>,
+    /// The metadata fields to copy, defaults to all fields that differ
+    #[arg(long = "field", value_enum, action = clap::ArgAction::Append)]
+    fields: Vec<MetadataField>,
+    /// Write the changes to the manifest instead of only showing a diff
+    #[arg(long)]
+    write: bool,
+}
+
+/// Copies metadata from the authoritative manifest (`[tool.maturin] metadata-source` or `--from`)
+/// to the other one. Shows a diff of the changes unless `--write` is passed
+pub fn sync_metadata(options: SyncMetadataOptions) -> Result<()> {
+    let ProjectResolver {
+        cargo_toml_path,
+        pyproject_toml_path,
+        pyproject_toml,
+        cargo_metadata,
+        ..
+    } = ProjectResolver::resolve(options.manifest_path, CargoOptions::default())?;
+    let pyproject_toml = pyproject_toml.context("Can't sync metadata without a pyproject.toml")?;
+    let package = cargo_metadata
+        .root_package()
+        .context("Expected cargo to return metadata with root_package")?;
+
+    let fields: Vec<MetadataField> = if options.fields.is_empty() {
+        find_metadata_drift(package, &pyproject_toml)
+            .iter()
+            .map(|x| x.field)
+            .collect()
+    } else {
+        options.fields
+    };
+    if fields.is_empty() {
+        eprintln!("✨ Cargo.toml and pyproject.toml metadata are in sync");
+        return Ok(());
+    }
+    let source = options
+        .from
+        .unwrap_or_else(|| pyproject_toml.metadata_source());
+    let path = match source {
+        MetadataSource::Cargo => pyproject_toml_path,
+        MetadataSource::Pyproject => cargo_toml_path,
+    };
+    let mut editor = TomlEditor::open(path)?;
+    match source {
+        MetadataSource::Cargo => {
+            let repository_key = pyproject_repository(&pyproject_toml).map(|(key, _)| key);
+            update_pyproject_toml(&mut editor, package, &fields, repository_key)?;
+        }
+        MetadataSource::Pyproject => update_cargo_toml(&mut editor, &pyproject_toml, &fields)?,
+    }
+    let path = editor.path().display().to_string();
+    if !editor.is_modified() {
+        eprintln!("✨ {} is already up to date", path);
+        return Ok(());
+    }
+
+    if options.write {
+        editor.save()?;
+        for field in fields {
+            eprintln!("📝 Updated {} in {} from {}", field, path, source);
+        }
+    } else {
+        println!("--- {}", path);
+        println!("+++ {}", path);
+        print!("{}", editor.diff());
+        eprintln!("💡 Run with `--write` to apply these changes");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cargo_metadata::MetadataCommand;
+    use fs_err as fs;
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+    use std::path::Path;
+
+    fn create_project(dir: &Path) -> (Package, PyProjectToml) {
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src").join("lib.rs"), "").unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            indoc!(
+                r#"
+                [package]
+                name = "drift"
+                version = "0.1.0-alpha.1"
+                authors = ["konstin <konstin@mailbox.org>"]
+                description = "A description"
+                license = "MIT"
+                repository = "https://github.com/PyO3/maturin"
+                edition = "2021"
+                "#
+            ),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("pyproject.toml"),
+            indoc!(
+                r#"
+                [build-system]
+                requires = ["maturin"]
+                build-backend = "maturin"
+
+                [project]
+                name = "drift"
+                version = "0.1.0a1"
+                description = "Another description"
+                license = { text = "MIT OR Apache-2.0" }
+                authors = [{ name = "konstin", email = "konstin@mailbox.org" }]
+
+                [project.urls]
+                Source = "https://github.com/PyO3/maturin/"
+                "#
+            ),
+        )
+        .unwrap();
+        let cargo_metadata = MetadataCommand::new()
+            .manifest_path(dir.join("Cargo.toml"))
+            .no_deps()
+            .exec()
+            .unwrap();
+        let package = cargo_metadata.root_package().unwrap().clone();
+        let pyproject_toml = PyProjectToml::new(dir.join("pyproject.toml")).unwrap();
+        (package, pyproject_toml)
+    }
+
+    #[test]
+    fn test_find_metadata_drift() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let (package, pyproject_toml) = create_project(tmp_dir.path());
+        let drift = find_metadata_drift(&package, &pyproject_toml);
+        assert_eq!(
+            drift,
+            vec![
+                MetadataDrift {
+                    field: MetadataField::Description,
+                    cargo: "A description".to_string(),
+                    pyproject: "Another description".to_string(),
+                },
+                MetadataDrift {
+                    field: MetadataField::License,
+                    cargo: "MIT".to_string(),
+                    pyproject: "MIT OR Apache-2.0".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_update_manifests() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let (package, pyproject_toml) = create_project(tmp_dir.path());
+        let fields = [MetadataField::Description, MetadataField::License];
+
+        let mut editor = TomlEditor::open(tmp_dir.path().join("Cargo.toml")).unwrap();
+        update_cargo_toml(&mut editor, &pyproject_toml, &fields).unwrap();
+        let cargo_toml = editor.document();
+        assert_eq!(
+            cargo_toml["package"]["description"].as_str(),
+            Some("Another description")
+        );
+        assert_eq!(
+            cargo_toml["package"]["license"].as_str(),
+            Some("MIT OR Apache-2.0")
+        );
+
+        let mut editor = TomlEditor::open(tmp_dir.path().join("pyproject.toml")).unwrap();
+        update_pyproject_toml(&mut editor, &package, &fields, None).unwrap();
+        let pyproject = editor.document();
+        assert_eq!(
+            pyproject["project"]["description"].as_str(),
+            Some("A description")
+        );
+        assert_eq!(
+            pyproject["project"]["license"]["text"].as_str(),
+            Some("MIT")
+        );
+    }
+}
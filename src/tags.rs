@@ -0,0 +1,204 @@
+//! Computes the wheel tags and file names maturin uses without building anything, so tools such
+//! as upload validators or naming checks in CI agree with maturin
+use crate::module_writer::wheel_file_name;
+use crate::{Metadata21, PlatformTag, PythonInterpreter, Target};
+use anyhow::{Context, Result};
+use std::fmt::{Display, Formatter};
+
+/// The python a wheel is built for
+#[derive(Debug, Clone, Copy)]
+pub enum WheelAbi<'a> {
+    /// An extension module for one interpreter, e.g. `cp311-cp311`
+    Interpreter(&'a PythonInterpreter),
+    /// An extension module using the stable api of CPython `major.minor` and later, e.g.
+    /// `cp37-abi3`. The interpreter the wheel is built with, if any, provides the macOS
+    /// deployment target and the platform of MinGW pythons. PyPy and the other interpreters
+    /// without stable api get their own tag like [WheelAbi::Interpreter].
+    Abi3 {
+        /// The major version of the oldest supported python
+        major: u8,
+        /// The minor version of the oldest supported python
+        minor: u8,
+        /// The interpreter the wheel is built with
+        interpreter: Option<&'a PythonInterpreter>,
+    },
+    /// No extension module for a specific python, `py3-none`, as used by cffi and binaries
+    None,
+}
+
+/// The platform compatibility of a wheel, like `--compatibility` and `--universal2`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Compatibility {
+    /// The platform tags, e.g. `manylinux_2_17`, empty for the plain tag of the target, e.g.
+    /// `linux_x86_64` like `--compatibility linux`. Several tags give a compressed tag set.
+    pub platform_tags: Vec<PlatformTag>,
+    /// Whether it's a universal2 wheel for macOS
+    pub universal2: bool,
+}
+
+/// The `{python tag}-{abi tag}-{platform tag}` triple of a wheel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WheelTag {
+    /// The python tag, e.g. `cp311`
+    pub python: String,
+    /// The abi tag, e.g. `cp311`, `abi3` or `none`
+    pub abi: String,
+    /// The platform tag, e.g. `manylinux_2_17_x86_64.manylinux2014_x86_64`
+    pub platform: String,
+}
+
+impl WheelTag {
+    fn parse(tag: &str) -> Result<WheelTag> {
+        let mut parts = tag.splitn(3, '-');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(python), Some(abi), Some(platform)) => Ok(WheelTag {
+                python: python.to_string(),
+                abi: abi.to_string(),
+                platform: platform.to_string(),
+            }),
+            _ => None.with_context(|| format!("Invalid wheel tag {}", tag)),
+        }
+    }
+
+    /// The file name maturin gives the wheel of the distribution `name` in `version`, with the
+    /// name and version escaped according to PEP 427
+    pub fn file_name(&self, name: &str, version: &str, build_tag: Option<&str>) -> String {
+        let metadata21 = Metadata21 {
+            name: name.to_string(),
+            version: version.to_string(),
+            ..Default::default()
+        };
+        wheel_file_name(&metadata21, build_tag, &self.to_string())
+    }
+}
+
+impl Display for WheelTag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}-{}", self.python, self.abi, self.platform)
+    }
+}
+
+/// The tag of an abi3 wheel, e.g. `cp37-abi3-manylinux_2_17_x86_64`
+fn abi3_tag(
+    interpreter: Option<&PythonInterpreter>,
+    target: &Target,
+    compatibility: &Compatibility,
+    major: u8,
+    minor: u8,
+) -> Result<String> {
+    // MinGW pythons only install wheels with their own platform tag
+    let platform = match interpreter.and_then(PythonInterpreter::mingw_platform) {
+        Some(mingw_platform) => mingw_platform.to_string(),
+        None => target.get_platform_tag_with_deployment_target(
+            &compatibility.platform_tags,
+            compatibility.universal2,
+            interpreter.and_then(|interpreter| interpreter.macos_deployment_target.as_deref()),
+        )?,
+    };
+    Ok(format!("cp{}{}-abi3-{}", major, minor, platform))
+}
+
+/// Returns the tag maturin gives the wheel for `interpreter` and `target` with the platform
+/// `compatibility`, the same one `maturin build` uses. On linux, auditwheel may still pick the
+/// platform tag from the built library when no platform tag is passed, see `maturin targets`.
+pub fn compute(
+    interpreter: WheelAbi,
+    target: &Target,
+    compatibility: &Compatibility,
+) -> Result<WheelTag> {
+    let linux;
+    let compatibility = if compatibility.platform_tags.is_empty() && target.is_linux() {
+        linux = Compatibility {
+            platform_tags: vec![PlatformTag::Linux],
+            universal2: compatibility.universal2,
+        };
+        &linux
+    } else {
+        compatibility
+    };
+    let tag = match interpreter {
+        WheelAbi::Abi3 {
+            major,
+            minor,
+            interpreter,
+        } if interpreter.map_or(true, PythonInterpreter::has_stable_api) => {
+            abi3_tag(interpreter, target, compatibility, major, minor)?
+        }
+        WheelAbi::Abi3 {
+            interpreter: Some(interpreter),
+            ..
+        }
+        | WheelAbi::Interpreter(interpreter) => interpreter.get_tag(
+            target,
+            &compatibility.platform_tags,
+            compatibility.universal2,
+        )?,
+        WheelAbi::Abi3 { .. } | WheelAbi::None => {
+            target
+                .get_universal_tags(&compatibility.platform_tags, compatibility.universal2)?
+                .0
+        }
+    };
+    WheelTag::parse(&tag)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::python_interpreter::{InterpreterConfig, InterpreterKind};
+
+    #[test]
+    fn test_compute() {
+        let linux =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let manylinux = Compatibility {
+            platform_tags: vec![PlatformTag::Manylinux { x: 2, y: 17 }],
+            universal2: false,
+        };
+        let python = PythonInterpreter::from_config(InterpreterConfig {
+            major: 3,
+            minor: 11,
+            interpreter_kind: InterpreterKind::CPython,
+            abiflags: String::new(),
+            ext_suffix: ".cpython-311-x86_64-linux-gnu.so".to_string(),
+            abi_tag: Some("311".to_string()),
+            pointer_width: Some(64),
+        });
+        let tag = compute(WheelAbi::Interpreter(&python), &linux, &manylinux).unwrap();
+        assert_eq!(
+            tag,
+            WheelTag {
+                python: "cp311".to_string(),
+                abi: "cp311".to_string(),
+                platform: "manylinux_2_17_x86_64.manylinux2014_x86_64".to_string(),
+            }
+        );
+        assert_eq!(
+            tag.file_name("foo-bar", "0.1.0-alpha.1", None),
+            "foo_bar-0.1.0a1-cp311-cp311-manylinux_2_17_x86_64.manylinux2014_x86_64.whl"
+        );
+        let abi3 = WheelAbi::Abi3 {
+            major: 3,
+            minor: 7,
+            interpreter: None,
+        };
+        assert_eq!(
+            compute(abi3, &linux, &manylinux).unwrap().to_string(),
+            "cp37-abi3-manylinux_2_17_x86_64.manylinux2014_x86_64"
+        );
+        assert_eq!(
+            compute(WheelAbi::None, &linux, &Compatibility::default())
+                .unwrap()
+                .to_string(),
+            "py3-none-linux_x86_64"
+        );
+
+        let macos = Target::from_target_triple(Some("aarch64-apple-darwin".to_string())).unwrap();
+        let universal2 = Compatibility {
+            universal2: true,
+            ..Default::default()
+        };
+        let tag = compute(WheelAbi::None, &macos, &universal2).unwrap();
+        assert!(tag.platform.ends_with("_universal2"), "{}", tag);
+    }
+}
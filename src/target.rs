@@ -12,6 +12,7 @@ use std::path::PathBuf;
 use std::str;
 use target_lexicon::{Environment, Triple};
 
+pub(crate) const RUST_1_63_0: semver::Version = semver::Version::new(1, 63, 0);
 pub(crate) const RUST_1_64_0: semver::Version = semver::Version::new(1, 64, 0);
 
 /// All supported operating system
@@ -142,6 +143,8 @@ pub struct Target {
     pub(crate) rustc_version: VersionMeta,
     /// Is user specified `--target`
     pub(crate) user_specified: bool,
+    /// `--macos-deployment-target`, or `macos-deployment-target` from pyproject.toml
+    macos_deployment_target: Option<String>,
 }
 
 impl Target {
@@ -215,16 +218,72 @@ impl Target {
             rustc_version,
             user_specified: target_triple.is_some(),
             cross_compiling: false,
+            macos_deployment_target: None,
         };
         target.cross_compiling = is_cross_compiling(&target)?;
         Ok(target)
     }
 
+    /// Overrides the macOS deployment target of the build, which otherwise comes from
+    /// `MACOSX_DEPLOYMENT_TARGET` or the interpreter
+    pub fn set_macos_deployment_target(&mut self, deployment_target: Option<String>) {
+        self.macos_deployment_target = deployment_target;
+    }
+
+    /// The explicitly requested macOS deployment target, `--macos-deployment-target` before
+    /// `MACOSX_DEPLOYMENT_TARGET`
+    pub fn requested_macos_deployment_target(&self) -> Option<String> {
+        self.macos_deployment_target
+            .clone()
+            .or_else(|| env::var("MACOSX_DEPLOYMENT_TARGET").ok())
+    }
+
+    /// Returns the macOS deployment target for the x86_64 and the arm64 parts of a build, in
+    /// that order. Without an explicit deployment target, the one python was built for is used,
+    /// which is e.g. 10.9 for the python.org installers but the build machine's macOS version for
+    /// Homebrew. Versions older than rust supports are raised to the minimum.
+    pub(crate) fn macos_deployment_targets(
+        &self,
+        interpreter_deployment_target: Option<&str>,
+        universal2: bool,
+    ) -> Result<((usize, usize), (usize, usize))> {
+        let requested = self.requested_macos_deployment_target();
+        macosx_deployment_target(
+            requested.as_deref().or(interpreter_deployment_target),
+            universal2,
+        )
+    }
+
+    /// Returns the macOS deployment target of this target's architecture
+    pub(crate) fn macos_deployment_target(
+        &self,
+        interpreter_deployment_target: Option<&str>,
+        universal2: bool,
+    ) -> Result<(usize, usize)> {
+        let (x86_64, arm64) =
+            self.macos_deployment_targets(interpreter_deployment_target, universal2)?;
+        Ok(match self.arch {
+            Arch::Aarch64 => arm64,
+            _ => x86_64,
+        })
+    }
+
     /// Returns the platform part of the tag for the wheel name
     pub fn get_platform_tag(
         &self,
         platform_tags: &[PlatformTag],
         universal2: bool,
+    ) -> Result<String> {
+        self.get_platform_tag_with_deployment_target(platform_tags, universal2, None)
+    }
+
+    /// Returns the platform part of the tag for the wheel name, with the macOS deployment target
+    /// of the interpreter as fallback
+    pub(crate) fn get_platform_tag_with_deployment_target(
+        &self,
+        platform_tags: &[PlatformTag],
+        universal2: bool,
+        interpreter_deployment_target: Option<&str>,
     ) -> Result<String> {
         let tag = match (&self.os, &self.arch) {
             // FreeBSD
@@ -317,7 +376,7 @@ impl Target {
             }
             // macOS
             (Os::Macos, Arch::X86_64) => {
-                let ((x86_64_major, x86_64_minor), (arm64_major, arm64_minor)) = macosx_deployment_target(env::var("MACOSX_DEPLOYMENT_TARGET").ok().as_deref(), universal2)?;
+                let ((x86_64_major, x86_64_minor), (arm64_major, arm64_minor)) = self.macos_deployment_targets(interpreter_deployment_target, universal2)?;
                 if universal2 {
                     format!(
                         "macosx_{x86_64_major}_{x86_64_minor}_x86_64.macosx_{arm64_major}_{arm64_minor}_arm64.macosx_{x86_64_major}_{x86_64_minor}_universal2",
@@ -331,7 +390,7 @@ impl Target {
                 }
             }
             (Os::Macos, Arch::Aarch64) => {
-                let ((x86_64_major, x86_64_minor), (arm64_major, arm64_minor)) = macosx_deployment_target(env::var("MACOSX_DEPLOYMENT_TARGET").ok().as_deref(), universal2)?;
+                let ((x86_64_major, x86_64_minor), (arm64_major, arm64_minor)) = self.macos_deployment_targets(interpreter_deployment_target, universal2)?;
                 if universal2 {
                     format!(
                         "macosx_{x86_64_major}_{x86_64_minor}_x86_64.macosx_{arm64_major}_{arm64_minor}_arm64.macosx_{x86_64_major}_{x86_64_minor}_universal2",
@@ -531,6 +590,11 @@ impl Target {
         self.env == Environment::Msvc
     }
 
+    /// Returns true if the current platform is windows with the MinGW toolchain (windows-gnu)
+    pub fn is_windows_gnu(&self) -> bool {
+        self.is_windows() && self.env == Environment::Gnu
+    }
+
     /// Returns true if the current platform is illumos
     pub fn is_illumos(&self) -> bool {
         self.os == Os::Illumos
@@ -620,7 +684,7 @@ impl Target {
     ) -> PathBuf {
         if self.is_unix() {
             match interpreter.interpreter_kind {
-                InterpreterKind::CPython => {
+                InterpreterKind::CPython | InterpreterKind::GraalPy => {
                     let python_dir = format!("python{}.{}", interpreter.major, interpreter.minor);
                     venv_base
                         .as_ref()
@@ -664,6 +728,11 @@ impl Target {
     }
 }
 
+/// Returns the target triple of the host, as reported by rustc
+pub(crate) fn host_target_triple() -> Result<String> {
+    Ok(rustc_version_meta()?.host)
+}
+
 fn rustc_version_meta() -> Result<VersionMeta> {
     let meta = rustc_version::version_meta().map_err(|err| match err {
         rustc_version::Error::CouldNotExecuteCommand(e)
@@ -689,12 +758,17 @@ fn macosx_deployment_target(
     let mut x86_64_ver = x86_64_default;
     let mut arm64_ver = arm64_default;
     if let Some(deploy_target) = deploy_target {
-        let err_ctx = "MACOSX_DEPLOYMENT_TARGET is invalid";
+        let err_ctx = || {
+            format!(
+                "The macOS deployment target `{}` is invalid, expected e.g. 10.12",
+                deploy_target
+            )
+        };
         let mut parts = deploy_target.split('.');
-        let major = parts.next().context(err_ctx)?;
-        let major: usize = major.parse().context(err_ctx)?;
-        let minor = parts.next().context(err_ctx)?;
-        let minor: usize = minor.parse().context(err_ctx)?;
+        let major = parts.next().with_context(err_ctx)?;
+        let major: usize = major.parse().with_context(err_ctx)?;
+        let minor = parts.next().with_context(err_ctx)?;
+        let minor: usize = minor.parse().with_context(err_ctx)?;
         if (major, minor) > x86_64_default {
             x86_64_ver = (major, minor);
         }
@@ -0,0 +1,94 @@
+//! Evaluates `cfg(...)` expressions such as `cfg(all(unix, target_arch = "x86_64"))` against a
+//! target, the same way cargo does for `[target.'cfg(...)'.dependencies]`
+use crate::Target;
+use anyhow::{bail, Context, Result};
+use cargo_platform::{Cfg, Platform};
+use std::env;
+use std::process::Command;
+use std::str::FromStr;
+
+/// The cfg values of a target, e.g. `unix` or `target_os = "linux"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TargetCfg(Vec<Cfg>);
+
+impl TargetCfg {
+    /// Queries the cfg values of the target from rustc, which also works for targets whose
+    /// standard library isn't installed
+    pub(crate) fn from_target(target: &Target) -> Result<TargetCfg> {
+        let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+        let output = Command::new(&rustc)
+            .args(["--print", "cfg", "--target", target.target_triple()])
+            .output()
+            .context("Failed to run rustc to get the cfg values of the target")?;
+        if !output.status.success() {
+            bail!(
+                "Failed to get the cfg values of {}: {}",
+                target.target_triple(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        TargetCfg::parse(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Parses the output of `rustc --print cfg`, one `name` or `name="value"` per line
+    pub(crate) fn parse(cfg: &str) -> Result<TargetCfg> {
+        let cfg = cfg
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                Cfg::from_str(line).with_context(|| format!("Invalid cfg value `{}`", line))
+            })
+            .collect::<Result<_>>()?;
+        Ok(TargetCfg(cfg))
+    }
+
+    /// Evaluates an expression in the `cfg(...)` form
+    pub(crate) fn evaluate(&self, expression: &str) -> Result<bool> {
+        let platform = Platform::from_str(expression.trim())
+            .with_context(|| format!("Failed to parse `{}`", expression))?;
+        match platform {
+            Platform::Cfg(cfg_expr) => Ok(cfg_expr.matches(&self.0)),
+            Platform::Name(_) => bail!("Expected `cfg(...)`, found `{}`", expression),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let cfg = TargetCfg::parse(
+            "debug_assertions\npanic=\"unwind\"\ntarget_arch=\"x86_64\"\n\
+            target_env=\"msvc\"\ntarget_family=\"windows\"\ntarget_os=\"windows\"\n\
+            target_pointer_width=\"64\"\nwindows\n",
+        )
+        .unwrap();
+        for (expression, expected) in [
+            ("cfg(windows)", true),
+            ("cfg(unix)", false),
+            ("cfg(target_os = \"windows\")", true),
+            ("cfg(target_env = \"gnu\")", false),
+            ("cfg(all(windows, target_arch = \"x86_64\"))", true),
+            ("cfg(any(unix, target_pointer_width = \"32\"))", false),
+            ("cfg(not(target_os = \"macos\"))", true),
+            (
+                "cfg(all(not(unix), any(target_env = \"msvc\", target_env = \"gnu\"),))",
+                true,
+            ),
+        ] {
+            assert_eq!(
+                cfg.evaluate(expression).unwrap(),
+                expected,
+                "{}",
+                expression
+            );
+        }
+        assert!(cfg.evaluate("windows").is_err());
+        assert!(cfg.evaluate("cfg(target_os = )").is_err());
+        assert!(cfg.evaluate("cfg(not(unix, windows))").is_err());
+        assert!(cfg.evaluate("cfg(all(unix)").is_err());
+    }
+}
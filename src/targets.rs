@@ -0,0 +1,130 @@
+//! Prints the wheels `maturin build` would build for each target and interpreter without
+//! building them, so CI can generate its job matrix from the project configuration
+use crate::BuildOptions;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Options for `maturin targets`
+#[derive(Debug, clap::Parser)]
+pub struct TargetsOptions {
+    /// Print the wheels as json
+    #[arg(long)]
+    json: bool,
+    #[command(flatten)]
+    build: BuildOptions,
+}
+
+/// The wheels that would be built for each target, printed by `maturin targets --json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetsReport {
+    /// The wheels of each target, in the order they would be built
+    pub targets: Vec<PlannedTarget>,
+}
+
+/// The wheels that would be built for one target
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedTarget {
+    /// The target triple
+    pub target: String,
+    /// The wheels of the target
+    pub wheels: Vec<PlannedWheel>,
+}
+
+/// A wheel that would be built, see [crate::BuildContext::planned_wheels]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedWheel {
+    /// The name of the build, e.g. `CPython 3.10`, as used by `--shard`
+    pub build: String,
+    /// The interpreter the wheel is built with, none for cffi and plain binaries
+    pub interpreter: Option<PlannedInterpreter>,
+    /// The wheel tag, e.g. `cp310-cp310-manylinux_2_17_x86_64`
+    pub tag: String,
+    /// The file name of the wheel
+    pub file_name: String,
+    /// Where the wheel would be written
+    pub path: PathBuf,
+    /// Whether auditwheel picks the platform tag from the built library, in which case `tag` and
+    /// the file name show the linux tag instead
+    pub platform_tag_from_auditwheel: bool,
+}
+
+/// A python interpreter a wheel is built with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedInterpreter {
+    /// `CPython` or `PyPy`
+    pub kind: String,
+    /// The python version, e.g. `3.10`
+    pub version: String,
+    /// The path to the interpreter, none if there is no interpreter, e.g. for abi3 wheels
+    /// without `-i` or when cross compiling
+    pub executable: Option<PathBuf>,
+}
+
+/// Resolves the targets and interpreters like `maturin build` and prints the wheels to stdout.
+/// All other output goes to stderr.
+pub fn targets(options: TargetsOptions) -> Result<()> {
+    let build_contexts = options.build.into_build_contexts(false, false, false)?;
+    let mut report = TargetsReport::default();
+    for build_context in &build_contexts {
+        report.targets.push(PlannedTarget {
+            target: build_context.target.target_triple().to_string(),
+            wheels: build_context.planned_wheels()?,
+        });
+    }
+    if options.json {
+        let json =
+            serde_json::to_string_pretty(&report).context("Failed to serialize the targets")?;
+        println!("{}", json);
+    } else {
+        for target in &report.targets {
+            println!("{}", target.target);
+            for wheel in &target.wheels {
+                let note = if wheel.platform_tag_from_auditwheel {
+                    " (platform tag picked by auditwheel)"
+                } else {
+                    ""
+                };
+                println!("  {}: {}{}", wheel.build, wheel.file_name, note);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BuildOptions, CargoOptions, PlatformTag};
+
+    #[test]
+    fn test_planned_wheels() {
+        let build_options = BuildOptions {
+            platform_tag: vec![PlatformTag::Manylinux { x: 2, y: 17 }],
+            cargo: CargoOptions {
+                manifest_path: Some("test-crates/pyo3-pure/Cargo.toml".into()),
+                target: Some("x86_64-unknown-linux-gnu".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let build_context = build_options
+            .into_build_context(false, false, false)
+            .unwrap();
+        // abi3 on linux doesn't need an interpreter
+        let wheels = build_context.planned_wheels().unwrap();
+        assert_eq!(wheels.len(), 1);
+        let wheel = &wheels[0];
+        assert_eq!(wheel.build, "abi3 Python ≥ 3.7");
+        assert_eq!(
+            wheel.tag,
+            "cp37-abi3-manylinux_2_17_x86_64.manylinux2014_x86_64"
+        );
+        assert_eq!(
+            wheel.file_name,
+            "pyo3_pure-0.1.0+abc123de-cp37-abi3-manylinux_2_17_x86_64.manylinux2014_x86_64.whl"
+        );
+        assert_eq!(wheel.path, build_context.out.join(&wheel.file_name));
+        assert!(!wheel.platform_tag_from_auditwheel);
+        assert_eq!(build_context.build_names().unwrap(), [wheel.build.clone()]);
+    }
+}
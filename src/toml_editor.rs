@@ -0,0 +1,299 @@
+//! Format preserving edits of Cargo.toml and pyproject.toml, so the comments and formatting in
+//! user files survive `maturin init` and `maturin sync-metadata`
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{Document, Item, Table};
+
+/// A Cargo.toml or pyproject.toml that is edited in place, keeping everything that isn't
+/// explicitly changed as it was
+#[derive(Debug, Clone)]
+pub struct TomlEditor {
+    path: PathBuf,
+    original: String,
+    document: Document,
+}
+
+impl TomlEditor {
+    /// Reads and parses the file at `path`
+    pub fn open(path: impl Into<PathBuf>) -> Result<TomlEditor> {
+        let path = path.into();
+        let original = fs::read_to_string(&path)?;
+        let document = original
+            .parse::<Document>()
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(TomlEditor {
+            path,
+            original,
+            document,
+        })
+    }
+
+    /// The path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The parsed document, including the pending changes
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// The parsed document for changes that aren't covered by the other methods
+    pub fn document_mut(&mut self) -> &mut Document {
+        &mut self.document
+    }
+
+    /// Returns the item at the dotted key, e.g. `["tool", "maturin", "bindings"]`
+    pub fn get(&self, keys: &[&str]) -> Option<&Item> {
+        let (last, tables) = keys.split_last()?;
+        let mut table = self.document.as_table() as &dyn toml_edit::TableLike;
+        for key in tables {
+            table = table.get(key)?.as_table_like()?;
+        }
+        table.get(last)
+    }
+
+    /// Sets the item at the dotted key, creating the missing tables on the way. Tables that only
+    /// exist to hold a subtable, such as `tool` for `[tool.maturin]`, don't get a header.
+    pub fn set(&mut self, keys: &[&str], item: impl Into<Item>) -> Result<()> {
+        let (last, tables) = keys.split_last().context("Can't set an empty key")?;
+        let mut table = self.document.as_table_mut() as &mut dyn toml_edit::TableLike;
+        for (depth, key) in tables.iter().enumerate() {
+            let entry = table.entry(key).or_insert_with(|| {
+                let mut new_table = Table::new();
+                new_table.set_implicit(depth + 1 < tables.len());
+                Item::Table(new_table)
+            });
+            table = match entry.as_table_like_mut() {
+                Some(table) => table,
+                None => bail!(
+                    "Can't set {} in {}, {} is not a table",
+                    keys.join("."),
+                    self.path.display(),
+                    keys[..=depth].join(".")
+                ),
+            };
+        }
+        let mut item = item.into();
+        // Keep the comments around a value that is replaced
+        if let (Some(Item::Value(existing)), Item::Value(new)) = (table.get(last), &mut item) {
+            *new.decor_mut() = existing.decor().clone();
+        }
+        table.insert(last, item);
+        Ok(())
+    }
+
+    /// Adds all keys from `other` that are missing, without changing existing values
+    pub fn insert_missing(&mut self, other: &Table) {
+        insert_missing(self.document.as_table_mut(), other);
+    }
+
+    /// Whether `project.dynamic` in a pyproject.toml lists the field
+    pub fn is_dynamic(&self, field: &str) -> bool {
+        self.get(&["project", "dynamic"])
+            .and_then(Item::as_array)
+            .map(|dynamic| dynamic.iter().any(|x| x.as_str() == Some(field)))
+            .unwrap_or_default()
+    }
+
+    /// Whether the `package` field in a Cargo.toml is inherited from the workspace
+    pub fn is_workspace_inherited(&self, field: &str) -> bool {
+        self.get(&["package", field, "workspace"])
+            .and_then(Item::as_bool)
+            .unwrap_or_default()
+    }
+
+    /// Sets `package.version` in a Cargo.toml or `project.version` in a pyproject.toml. The
+    /// version must already be in the format of the manifest, i.e. semver or PEP 440.
+    pub fn set_version(&mut self, version: &str) -> Result<()> {
+        if self.document.contains_key("package") {
+            if self.is_workspace_inherited("version") {
+                bail!(
+                    "package.version in {} is inherited from the workspace, \
+                    please update it in the workspace Cargo.toml",
+                    self.path.display()
+                );
+            }
+            self.set(&["package", "version"], toml_edit::value(version))
+        } else {
+            if self.is_dynamic("version") {
+                bail!(
+                    "project.version is marked as dynamic in {}",
+                    self.path.display()
+                );
+            }
+            self.set(&["project", "version"], toml_edit::value(version))
+        }
+    }
+
+    /// Whether the document differs from the file on disk
+    pub fn is_modified(&self) -> bool {
+        self.document.to_string() != self.original
+    }
+
+    /// A unified diff of the pending changes
+    pub fn diff(&self) -> String {
+        diff_lines(&self.original, &self.document.to_string())
+    }
+
+    /// Writes the changes back to the file, returns whether there were any
+    pub fn save(&mut self) -> Result<bool> {
+        let updated = self.document.to_string();
+        if updated == self.original {
+            return Ok(false);
+        }
+        fs::write(&self.path, &updated)?;
+        self.original = updated;
+        Ok(true)
+    }
+}
+
+fn insert_missing(table: &mut Table, other: &Table) {
+    for (key, item) in other.iter() {
+        match (table.get_mut(key), item) {
+            (Some(Item::Table(existing)), Item::Table(other)) => insert_missing(existing, other),
+            (Some(_), _) => {}
+            (None, item) => {
+                table.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+/// Returns a unified diff with two lines of context of the changes from `old` to `new`
+pub(crate) fn diff_lines(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    // Longest common subsequence table, manifests are small enough for the quadratic approach
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] > lcs[i + 1][j]) {
+            lines.push(('+', new[j]));
+            j += 1;
+        } else {
+            lines.push(('-', old[i]));
+            i += 1;
+        }
+    }
+
+    const CONTEXT: usize = 2;
+    let changed: Vec<usize> = (0..lines.len()).filter(|&x| lines[x].0 != ' ').collect();
+    let mut output = String::new();
+    let mut last_printed = None;
+    for (index, (tag, line)) in lines.iter().enumerate() {
+        let near_change = changed
+            .iter()
+            .any(|&changed| index + CONTEXT >= changed && index <= changed + CONTEXT);
+        if !near_change {
+            continue;
+        }
+        if let Some(last) = last_printed {
+            if index > last + 1 {
+                output.push_str("...\n");
+            }
+        }
+        output.push(*tag);
+        output.push_str(line);
+        output.push('\n');
+        last_printed = Some(index);
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_edits_keep_formatting() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("pyproject.toml");
+        fs::write(
+            &path,
+            indoc!(
+                r#"
+                # The build backend
+                [build-system]
+                requires = ["setuptools"]  # replaced by maturin
+
+                [project]
+                name = "edited"
+                version = "0.1.0"  # bumped on release
+                "#
+            ),
+        )
+        .unwrap();
+        let mut editor = TomlEditor::open(&path).unwrap();
+        editor.set_version("0.2.0").unwrap();
+        editor
+            .set(&["tool", "maturin", "bindings"], toml_edit::value("bin"))
+            .unwrap();
+        let template: Document = indoc!(
+            r#"
+            [project]
+            name = "template"
+            requires-python = ">=3.7"
+            "#
+        )
+        .parse()
+        .unwrap();
+        editor.insert_missing(template.as_table());
+        assert!(editor.is_modified());
+        assert!(editor.save().unwrap());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            indoc!(
+                r#"
+                # The build backend
+                [build-system]
+                requires = ["setuptools"]  # replaced by maturin
+
+                [project]
+                name = "edited"
+                version = "0.2.0"  # bumped on release
+                requires-python = ">=3.7"
+
+                [tool.maturin]
+                bindings = "bin"
+                "#
+            )
+        );
+        assert!(!editor.save().unwrap());
+
+        editor
+            .set(
+                &["project", "dynamic"],
+                toml_edit::value(toml_edit::Array::from_iter(["version"])),
+            )
+            .unwrap();
+        assert!(editor.set_version("0.3.0").is_err());
+        assert!(editor
+            .set(&["project", "name", "x"], toml_edit::value(1))
+            .is_err());
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let old = "a\nb\nc\nd\ne\nf\ng\n";
+        let new = "a\nb\nc\nD\ne\nf\ng\n";
+        assert_eq!(diff_lines(old, new), " b\n c\n-d\n+D\n e\n f\n");
+    }
+}
@@ -50,6 +50,11 @@ pub struct PublishOpt {
 }
 
 impl PublishOpt {
+    /// Continue uploading files if one already exists, e.g. when retrying a failed upload
+    pub fn skip_existing(&mut self) {
+        self.skip_existing = true;
+    }
+
     const DEFAULT_REPOSITORY_URL: &'static str = "https://upload.pypi.org/legacy/";
     const TEST_REPOSITORY_URL: &'static str = "https://test.pypi.org/legacy/";
 }
@@ -258,6 +263,39 @@ fn canonicalize_name(name: &str) -> String {
         .to_lowercase()
 }
 
+/// An http agent using the proxy from `HTTPS_PROXY` or `HTTP_PROXY`, if set
+#[allow(clippy::result_large_err)]
+pub(crate) fn http_agent() -> Result<ureq::Agent, UploadError> {
+    let http_proxy = env::var("HTTPS_PROXY")
+        .or_else(|_| env::var("https_proxy"))
+        .or_else(|_| env::var("HTTP_PROXY"))
+        .or_else(|_| env::var("http_proxy"));
+
+    #[cfg(not(feature = "native-tls"))]
+    let agent = {
+        let mut builder = ureq::builder();
+        if let Ok(proxy) = http_proxy {
+            let proxy = ureq::Proxy::new(proxy)?;
+            builder = builder.proxy(proxy);
+        };
+        builder.build()
+    };
+
+    #[cfg(feature = "native-tls")]
+    let agent = {
+        use std::sync::Arc;
+        let mut builder =
+            ureq::builder().tls_connector(Arc::new(native_tls_crate::TlsConnector::new()?));
+        if let Ok(proxy) = http_proxy {
+            let proxy = ureq::Proxy::new(proxy)?;
+            builder = builder.proxy(proxy);
+        };
+        builder.build()
+    };
+
+    Ok(agent)
+}
+
 /// Uploads a single wheel to the registry
 #[allow(clippy::result_large_err)]
 pub fn upload(registry: &Registry, wheel_path: &Path) -> Result<(), UploadError> {
@@ -338,32 +376,7 @@ pub fn upload(registry: &Registry, wheel_path: &Path) -> Result<(), UploadError>
 
     let encoded = base64::encode(&format!("{}:{}", registry.username, registry.password));
 
-    let http_proxy = env::var("HTTPS_PROXY")
-        .or_else(|_| env::var("https_proxy"))
-        .or_else(|_| env::var("HTTP_PROXY"))
-        .or_else(|_| env::var("http_proxy"));
-
-    #[cfg(not(feature = "native-tls"))]
-    let agent = {
-        let mut builder = ureq::builder();
-        if let Ok(proxy) = http_proxy {
-            let proxy = ureq::Proxy::new(proxy)?;
-            builder = builder.proxy(proxy);
-        };
-        builder.build()
-    };
-
-    #[cfg(feature = "native-tls")]
-    let agent = {
-        use std::sync::Arc;
-        let mut builder =
-            ureq::builder().tls_connector(Arc::new(native_tls_crate::TlsConnector::new()?));
-        if let Ok(proxy) = http_proxy {
-            let proxy = ureq::Proxy::new(proxy)?;
-            builder = builder.proxy(proxy);
-        };
-        builder.build()
-    };
+    let agent = http_agent()?;
 
     let response = agent
         .post(registry.url.as_str())
@@ -452,7 +465,7 @@ pub fn upload_ui(items: &[PathBuf], publish: &PublishOpt) -> Result<()> {
                     }
                 }
 
-                bail!("Username and/or password are wrong");
+                return Err(UploadError::AuthenticationError.into());
             }
             Err(err) => {
                 let filename = i.file_name().unwrap_or(i.as_os_str());
@@ -0,0 +1,642 @@
+//! Checks built wheels and source distributions for common packaging problems
+//! without installing them
+use crate::{Metadata21, WheelReader};
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use fs_err::File;
+use goblin::mach::Mach;
+use goblin::Object;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The components of a wheel filename as specified in
+/// <https://peps.python.org/pep-0427/#file-name-convention>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WheelFilename {
+    pub(crate) distribution: String,
+    pub(crate) version: String,
+    pub(crate) build_tag: Option<String>,
+    pub(crate) python_tag: String,
+    pub(crate) abi_tag: String,
+    pub(crate) platform_tag: String,
+}
+
+impl WheelFilename {
+    pub(crate) fn parse(filename: &str) -> Option<Self> {
+        let stem = filename.strip_suffix(".whl")?;
+        let parts: Vec<&str> = stem.split('-').collect();
+        let (distribution, version, build_tag, tags) = match parts.as_slice() {
+            [distribution, version, tags @ ..] if tags.len() == 3 => {
+                (distribution, version, None, tags)
+            }
+            [distribution, version, build_tag, tags @ ..] if tags.len() == 3 => {
+                (distribution, version, Some(build_tag.to_string()), tags)
+            }
+            _ => return None,
+        };
+        Some(WheelFilename {
+            distribution: distribution.to_string(),
+            version: version.to_string(),
+            build_tag,
+            python_tag: tags[0].to_string(),
+            abi_tag: tags[1].to_string(),
+            platform_tag: tags[2].to_string(),
+        })
+    }
+
+    /// Expands compressed tag sets such as `py2.py3-none-any`
+    pub(crate) fn tags(&self) -> HashSet<String> {
+        let mut tags = HashSet::new();
+        for python_tag in self.python_tag.split('.') {
+            for abi_tag in self.abi_tag.split('.') {
+                for platform_tag in self.platform_tag.split('.') {
+                    tags.insert(format!("{}-{}-{}", python_tag, abi_tag, platform_tag));
+                }
+            }
+        }
+        tags
+    }
+}
+
+/// Parses a `Key: Value` formatted file such as WHEEL into a list of key value pairs
+pub(crate) fn parse_key_values(content: &str) -> Vec<(&str, &str)> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .collect()
+}
+
+/// Parses a CSV file as written by python's `csv` module into its records and the 1-based
+/// line number each record starts on. Quoted fields may contain commas, doubled quotes and
+/// line breaks. Returns the line number of the record with unterminated quotes or quotes
+/// inside of an unquoted field as error.
+pub(crate) fn parse_csv(content: &str) -> Result<Vec<(usize, Vec<String>)>, usize> {
+    let mut records = Vec::new();
+    let mut chars = content.chars().peekable();
+    let mut line = 1;
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        loop {
+            if field.is_empty() && chars.peek() == Some(&'"') {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                        None => return Err(start),
+                    }
+                }
+                if !matches!(chars.peek(), None | Some(',') | Some('\r') | Some('\n')) {
+                    return Err(start);
+                }
+            }
+            match chars.next() {
+                Some(',') => fields.push(std::mem::take(&mut field)),
+                Some('\r') if chars.peek() == Some(&'\n') => continue,
+                Some('\n') | None => {
+                    line += 1;
+                    fields.push(field);
+                    break;
+                }
+                Some('"') => return Err(start),
+                Some(c) => field.push(c),
+            }
+        }
+        // Skip empty lines
+        if fields.len() > 1 || !fields[0].is_empty() {
+            records.push((start, fields));
+        }
+    }
+    Ok(records)
+}
+
+/// Checks that RECORD lists every file in `files` with the correct hash and size
+pub(crate) fn verify_record(
+    record: &str,
+    record_path: &str,
+    files: &HashMap<&str, &[u8]>,
+    problems: &mut Vec<String>,
+) {
+    let mut recorded = HashSet::new();
+    let records = match parse_csv(record) {
+        Ok(records) => records,
+        Err(line) => {
+            problems.push(format!("Invalid quoting in RECORD line {}", line));
+            return;
+        }
+    };
+    for (line, fields) in records {
+        let (path, hash, size) = match fields.as_slice() {
+            [path, hash, size] => (path, hash, size),
+            _ => {
+                problems.push(format!(
+                    "Invalid RECORD line {}: expected path, hash and size",
+                    line
+                ));
+                continue;
+            }
+        };
+        recorded.insert(path.clone());
+        if path == record_path {
+            // The RECORD file can't contain its own hash
+            continue;
+        }
+        let content = match files.get(path.as_str()) {
+            Some(content) => content,
+            None => {
+                problems.push(format!(
+                    "{} is listed in RECORD but missing in the wheel",
+                    path
+                ));
+                continue;
+            }
+        };
+        match hash.split_once('=') {
+            Some(("sha256", expected)) => {
+                let actual =
+                    base64::encode_config(Sha256::digest(content), base64::URL_SAFE_NO_PAD);
+                if actual != expected {
+                    problems.push(format!("RECORD hash mismatch for {}", path));
+                }
+            }
+            Some((algorithm, _)) => problems.push(format!(
+                "Unsupported RECORD hash algorithm {} for {}",
+                algorithm, path
+            )),
+            None => problems.push(format!("Missing RECORD hash for {}", path)),
+        }
+        if size.parse::<usize>().ok() != Some(content.len()) {
+            problems.push(format!(
+                "RECORD size mismatch for {}: expected {}, found {}",
+                path,
+                size,
+                content.len()
+            ));
+        }
+    }
+    let mut unrecorded: Vec<&&str> = files
+        .keys()
+        .filter(|path| {
+            // Signatures are allowed to be missing from RECORD
+            !(recorded.contains(**path)
+                || path.ends_with("RECORD.jws")
+                || path.ends_with("RECORD.p7s"))
+        })
+        .collect();
+    unrecorded.sort();
+    for path in unrecorded {
+        problems.push(format!("{} is missing in RECORD", path));
+    }
+}
+
+fn verify_metadata(
+    content: &[u8],
+    distribution: &str,
+    version: &str,
+    problems: &mut Vec<String>,
+) -> Option<python_pkginfo::Metadata> {
+    let metadata = match python_pkginfo::Metadata::parse(content) {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            problems.push(format!("Invalid METADATA: {}", err));
+            return None;
+        }
+    };
+    if metadata.name.is_empty() {
+        problems.push("METADATA is missing the Name field".to_string());
+    }
+    if metadata.version.is_empty() {
+        problems.push("METADATA is missing the Version field".to_string());
+    }
+    let metadata21 = Metadata21 {
+        name: metadata.name.clone(),
+        version: metadata.version.clone(),
+        ..Default::default()
+    };
+    if !metadata21
+        .get_distribution_escaped()
+        .eq_ignore_ascii_case(distribution)
+    {
+        problems.push(format!(
+            "METADATA name {} doesn't match the filename distribution {}",
+            metadata.name, distribution
+        ));
+    }
+    if metadata21.get_version_escaped() != version {
+        problems.push(format!(
+            "METADATA version {} doesn't match the filename version {}",
+            metadata.version, version
+        ));
+    }
+    Some(metadata)
+}
+
+fn verify_wheel_file(content: &str, filename: &WheelFilename, problems: &mut Vec<String>) {
+    let fields = parse_key_values(content);
+    let get = |key: &str| {
+        fields
+            .iter()
+            .filter(|(field, _)| *field == key)
+            .map(|(_, value)| *value)
+            .collect::<Vec<_>>()
+    };
+    match get("Wheel-Version").as_slice() {
+        [version] if version.starts_with("1.") => {}
+        [version] => problems.push(format!("Unsupported Wheel-Version {} in WHEEL", version)),
+        _ => problems.push("WHEEL must contain exactly one Wheel-Version".to_string()),
+    }
+    match get("Root-Is-Purelib").as_slice() {
+        ["true"] | ["false"] => {}
+        _ => problems.push("WHEEL must contain Root-Is-Purelib set to true or false".to_string()),
+    }
+    let tags: HashSet<String> = get("Tag").into_iter().map(ToString::to_string).collect();
+    let expected_tags = filename.tags();
+    if tags != expected_tags {
+        let mut tags: Vec<_> = tags.into_iter().collect();
+        let mut expected_tags: Vec<_> = expected_tags.into_iter().collect();
+        tags.sort();
+        expected_tags.sort();
+        problems.push(format!(
+            "The tags in WHEEL ({}) don't match the tags in the filename ({})",
+            tags.join(", "),
+            expected_tags.join(", ")
+        ));
+    }
+    let build = get("Build");
+    if build.first().copied() != filename.build_tag.as_deref() {
+        problems.push(format!(
+            "The build tag in WHEEL ({}) doesn't match the build tag in the filename ({})",
+            build.first().unwrap_or(&"none"),
+            filename.build_tag.as_deref().unwrap_or("none")
+        ));
+    }
+}
+
+/// Returns the platform tag architecture names that are compatible with the binary
+/// and whether it's a linux, macOS or windows binary
+pub(crate) fn binary_platform(content: &[u8]) -> Option<(&'static str, Vec<&'static str>)> {
+    use goblin::elf::header::{EM_386, EM_AARCH64, EM_ARM, EM_PPC64, EM_S390, EM_X86_64};
+    use goblin::mach::cputype::{CPU_TYPE_ARM64, CPU_TYPE_X86_64};
+    use goblin::pe::header::{COFF_MACHINE_ARM64, COFF_MACHINE_X86, COFF_MACHINE_X86_64};
+
+    match Object::parse(content).ok()? {
+        Object::Elf(elf) => {
+            let arch = match elf.header.e_machine {
+                EM_X86_64 => vec!["x86_64", "amd64"],
+                EM_386 => vec!["i686", "i386"],
+                EM_AARCH64 => vec!["aarch64", "arm64"],
+                EM_ARM => vec!["armv7l", "armv6l"],
+                EM_PPC64 if elf.little_endian => vec!["ppc64le"],
+                EM_PPC64 => vec!["ppc64"],
+                EM_S390 => vec!["s390x"],
+                _ => return None,
+            };
+            Some(("elf", arch))
+        }
+        Object::Mach(Mach::Binary(macho)) => {
+            let arch = match macho.header.cputype() {
+                CPU_TYPE_X86_64 => vec!["x86_64", "intel", "universal2"],
+                CPU_TYPE_ARM64 => vec!["arm64", "universal2"],
+                _ => return None,
+            };
+            Some(("macos", arch))
+        }
+        Object::Mach(Mach::Fat(_)) => Some(("macos", vec!["universal2", "intel"])),
+        Object::PE(pe) => {
+            let arch = match pe.header.coff_header.machine {
+                COFF_MACHINE_X86_64 => vec!["win_amd64"],
+                COFF_MACHINE_X86 => vec!["win32"],
+                COFF_MACHINE_ARM64 => vec!["win_arm64"],
+                _ => return None,
+            };
+            Some(("windows", arch))
+        }
+        _ => None,
+    }
+}
+
+fn verify_binary(path: &str, content: &[u8], filename: &WheelFilename, problems: &mut Vec<String>) {
+    let (kind, archs) = match binary_platform(content) {
+        Some(platform) => platform,
+        None => return,
+    };
+    for platform_tag in filename.platform_tag.split('.') {
+        let matches = match kind {
+            "windows" => archs.contains(&platform_tag),
+            "macos" => {
+                platform_tag.starts_with("macosx_")
+                    && archs.iter().any(|arch| platform_tag.ends_with(arch))
+            }
+            _ => {
+                !platform_tag.starts_with("macosx_")
+                    && !platform_tag.starts_with("win")
+                    && archs.iter().any(|arch| platform_tag.ends_with(arch))
+            }
+        };
+        if !matches {
+            problems.push(format!(
+                "{} is a {} binary for {}, which doesn't match the platform tag {}",
+                path, kind, archs[0], platform_tag
+            ));
+        }
+    }
+
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    if filename.abi_tag == "abi3" && file_name.contains(".cpython-") {
+        problems.push(format!(
+            "{} is a version specific extension module in an abi3 wheel",
+            path
+        ));
+    } else if let Some(version) = file_name
+        .split('.')
+        .find_map(|part| part.strip_prefix("cpython-"))
+        .and_then(|rest| rest.split('-').next())
+    {
+        let python_tag = format!("cp{}", version.trim_end_matches(['d', 'm'].as_ref()));
+        if !filename.python_tag.split('.').any(|tag| tag == python_tag) {
+            problems.push(format!(
+                "{} is built for {}, which doesn't match the python tag {}",
+                path, python_tag, filename.python_tag
+            ));
+        }
+    }
+}
+
+/// Checks a wheel for problems and returns a description for each problem found
+fn verify_wheel(path: &Path) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+    let wheel_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context("Wheel path must be a valid UTF-8 file name")?;
+    let filename = match WheelFilename::parse(wheel_name) {
+        Some(filename) => filename,
+        None => {
+            problems.push(format!("{} is not a valid wheel filename", wheel_name));
+            return Ok(problems);
+        }
+    };
+    let wheel = WheelReader::open(path)?;
+    let files = wheel.file_map();
+
+    let dist_info_dir = format!("{}-{}.dist-info", filename.distribution, filename.version);
+    let record_path = format!("{}/RECORD", dist_info_dir);
+    let read_text = |name: &str, problems: &mut Vec<String>| -> Option<String> {
+        let path = format!("{}/{}", dist_info_dir, name);
+        match files.get(path.as_str()) {
+            Some(content) => match std::str::from_utf8(content) {
+                Ok(content) => Some(content.to_string()),
+                Err(_) => {
+                    problems.push(format!("{} is not valid UTF-8", path));
+                    None
+                }
+            },
+            None => {
+                problems.push(format!("The wheel is missing {}", path));
+                None
+            }
+        }
+    };
+
+    if let Some(record) = read_text("RECORD", &mut problems) {
+        verify_record(&record, &record_path, &files, &mut problems);
+    }
+    if let Some(metadata) = read_text("METADATA", &mut problems) {
+        verify_metadata(
+            metadata.as_bytes(),
+            &filename.distribution,
+            &filename.version,
+            &mut problems,
+        );
+    }
+    if let Some(wheel) = read_text("WHEEL", &mut problems) {
+        verify_wheel_file(&wheel, &filename, &mut problems);
+    }
+
+    let mut binaries: Vec<(&&str, &&[u8])> = files
+        .iter()
+        .filter(|(name, _)| {
+            name.ends_with(".so")
+                || name.ends_with(".pyd")
+                || name.ends_with(".dylib")
+                || name.ends_with(".dll")
+                || name.contains(".so.")
+        })
+        .collect();
+    binaries.sort();
+    if filename.platform_tag == "any" && !binaries.is_empty() {
+        problems.push(format!(
+            "The wheel is tagged as platform independent but contains shared libraries: {}",
+            binaries
+                .iter()
+                .map(|(name, _)| **name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    } else {
+        for (name, content) in binaries {
+            verify_binary(name, content, &filename, &mut problems);
+        }
+    }
+    Ok(problems)
+}
+
+/// Checks a source distribution for problems and returns a description for each problem found
+fn verify_sdist(path: &Path) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+    let sdist_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context("Source distribution path must be a valid UTF-8 file name")?;
+    let top_level = sdist_name.trim_end_matches(".tar.gz");
+    let (distribution, version) = match top_level.rsplit_once('-') {
+        Some(parts) => parts,
+        None => {
+            problems.push(format!(
+                "{} is not a valid source distribution filename",
+                sdist_name
+            ));
+            return Ok(problems);
+        }
+    };
+
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(path)?));
+    let mut pkg_info = None;
+    let mut has_pyproject_toml = false;
+    for entry in archive
+        .entries()
+        .with_context(|| format!("Failed to read {} as tar.gz archive", path.display()))?
+    {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_path_buf();
+        if !entry_path.starts_with(top_level) {
+            problems.push(format!(
+                "{} is outside of the top level directory {}",
+                entry_path.display(),
+                top_level
+            ));
+            continue;
+        }
+        let relative = entry_path.strip_prefix(top_level)?;
+        if relative == Path::new("PKG-INFO") {
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer)?;
+            pkg_info = Some(buffer);
+        } else if relative == Path::new("pyproject.toml") {
+            has_pyproject_toml = true;
+        }
+    }
+
+    match pkg_info {
+        Some(pkg_info) => {
+            verify_metadata(&pkg_info, distribution, version, &mut problems);
+        }
+        None => problems.push(format!(
+            "The source distribution is missing {}/PKG-INFO",
+            top_level
+        )),
+    }
+    if !has_pyproject_toml {
+        problems.push(format!(
+            "The source distribution is missing {}/pyproject.toml",
+            top_level
+        ));
+    }
+    Ok(problems)
+}
+
+/// Checks a wheel or source distribution and returns a description for each problem found
+pub fn verify_distribution(path: &Path) -> Result<Vec<String>> {
+    let name = path.to_string_lossy();
+    if name.ends_with(".whl") {
+        verify_wheel(path)
+    } else if name.ends_with(".tar.gz") {
+        verify_sdist(path)
+    } else {
+        bail!(
+            "{} is neither a wheel nor a source distribution",
+            path.display()
+        )
+    }
+}
+
+/// Checks the given wheels and source distributions and reports all problems found
+pub fn verify(paths: &[PathBuf]) -> Result<()> {
+    let mut failed = 0;
+    for path in paths {
+        let problems = verify_distribution(path)
+            .with_context(|| format!("Failed to verify {}", path.display()))?;
+        if problems.is_empty() {
+            eprintln!("✅ {} passed all checks", path.display());
+        } else {
+            failed += 1;
+            eprintln!("❌ {} has {} problem(s):", path.display(), problems.len());
+            for problem in problems {
+                eprintln!("  - {}", problem);
+            }
+        }
+    }
+    if failed > 0 {
+        bail!(
+            "{} of {} distribution(s) failed verification",
+            failed,
+            paths.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::module_writer::{test_metadata, test_wheel_writer};
+    use crate::ModuleWriter;
+    use pretty_assertions::assert_eq;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_wheel_filename() {
+        let filename = WheelFilename::parse("foo-0.1.0-1-py2.py3-none-any.whl").unwrap();
+        assert_eq!(filename.distribution, "foo");
+        assert_eq!(filename.build_tag.as_deref(), Some("1"));
+        let mut tags: Vec<_> = filename.tags().into_iter().collect();
+        tags.sort();
+        assert_eq!(tags, vec!["py2-none-any", "py3-none-any"]);
+        assert!(WheelFilename::parse("foo-0.1.0.whl").is_none());
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        assert_eq!(
+            parse_csv("foo/a.py,sha256=abc,12\r\n\nRECORD,,\n").unwrap(),
+            [
+                (
+                    1,
+                    vec![
+                        "foo/a.py".to_string(),
+                        "sha256=abc".to_string(),
+                        "12".to_string()
+                    ]
+                ),
+                (3, vec!["RECORD".to_string(), String::new(), String::new()]),
+            ]
+        );
+        assert_eq!(
+            parse_csv("\"foo/a,\"\"b\"\"\nc.py\",sha256=abc,12\nRECORD,,").unwrap(),
+            [
+                (
+                    1,
+                    vec![
+                        "foo/a,\"b\"\nc.py".to_string(),
+                        "sha256=abc".to_string(),
+                        "12".to_string()
+                    ]
+                ),
+                (3, vec!["RECORD".to_string(), String::new(), String::new()]),
+            ]
+        );
+        assert_eq!(parse_csv("RECORD,,\n\"foo/a.py,sha256=abc,12"), Err(2));
+        assert_eq!(parse_csv("foo/\"a\".py,sha256=abc,12"), Err(1));
+        assert_eq!(parse_csv("\"foo\"a.py,sha256=abc,12"), Err(1));
+    }
+
+    #[test]
+    fn test_verify_wheel() -> Result<()> {
+        let metadata = test_metadata();
+        let tmp_dir = TempDir::new()?;
+        let mut writer = test_wheel_writer(tmp_dir.path(), &metadata, "py3-none-any")?;
+        writer.add_bytes("foo/__init__.py", b"a = 1")?;
+        let wheel_path = writer.finish()?;
+        assert_eq!(verify_distribution(&wheel_path)?, Vec::<String>::new());
+
+        // Add a file that is not listed in RECORD
+        let file = fs_err::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&wheel_path)?;
+        let mut zip = zip::ZipWriter::new_append(file)?;
+        zip.start_file("foo/extra.py", zip::write::FileOptions::default())?;
+        zip.write_all(b"b = 2")?;
+        zip.finish()?;
+        assert_eq!(
+            verify_distribution(&wheel_path)?,
+            vec!["foo/extra.py is missing in RECORD".to_string()]
+        );
+        Ok(())
+    }
+}
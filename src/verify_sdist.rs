@@ -0,0 +1,219 @@
+//! Checks that a source distribution is reproducible and complete by building it again from the
+//! current tree, for `maturin verify-sdist`
+use crate::build_context::hash_file;
+use crate::pyproject_toml::CargoLockInclusion;
+use crate::{BuildOptions, CargoOptions};
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use fs_err::File;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Options for `maturin verify-sdist`
+#[derive(Debug, clap::Parser)]
+pub struct VerifySdistOptions {
+    /// The source distribution to check
+    #[arg(value_name = "SDIST")]
+    sdist: PathBuf,
+    /// The path to the Cargo.toml
+    #[arg(short = 'm', long = "manifest-path", value_name = "PATH")]
+    manifest_path: Option<PathBuf>,
+    /// The source distribution was built with `--vendor`
+    #[arg(long)]
+    vendor: bool,
+    /// The source distribution was built with `--from-git`
+    #[arg(long)]
+    from_git: bool,
+    /// The source distribution was built with this `--include-cargo-lock`
+    #[arg(long, value_enum, value_name = "WHEN")]
+    include_cargo_lock: Option<CargoLockInclusion>,
+}
+
+/// How the files of two source distributions differ, by their path below the top level directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SdistDiff {
+    /// Files of the rebuilt source distribution that are missing from the checked one
+    pub missing: Vec<PathBuf>,
+    /// Files of the checked source distribution that the rebuilt one doesn't have
+    pub extra: Vec<PathBuf>,
+    /// Files whose contents or permissions differ
+    pub changed: Vec<PathBuf>,
+}
+
+impl SdistDiff {
+    fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// The mode and sha256 digest of each file in the source distribution, without the top level
+/// `{name}-{version}` directory
+fn sdist_files(sdist: &Path) -> Result<BTreeMap<PathBuf, (u32, String)>> {
+    let mut files = BTreeMap::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(sdist)?));
+    for entry in archive
+        .entries()
+        .with_context(|| format!("Failed to read {}", sdist.display()))?
+    {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path: PathBuf = entry.path()?.components().skip(1).collect();
+        let mode = entry.header().mode()?;
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        files.insert(path, (mode, format!("{:x}", Sha256::digest(&contents))));
+    }
+    Ok(files)
+}
+
+/// Compares the files of the checked source distribution with the ones of the rebuilt one
+pub(crate) fn compare_sdists(checked: &Path, rebuilt: &Path) -> Result<SdistDiff> {
+    let checked = sdist_files(checked)?;
+    let rebuilt = sdist_files(rebuilt)?;
+    let mut diff = SdistDiff::default();
+    for (path, file) in &rebuilt {
+        match checked.get(path) {
+            None => diff.missing.push(path.clone()),
+            Some(checked_file) if checked_file != file => diff.changed.push(path.clone()),
+            Some(_) => {}
+        }
+    }
+    diff.extra = checked
+        .keys()
+        .filter(|path| !rebuilt.contains_key(*path))
+        .cloned()
+        .collect();
+    Ok(diff)
+}
+
+/// Builds the source distribution again from the current tree and fails unless it's identical
+/// to `sdist`. If it isn't, lists the files that are missing according to the include rules,
+/// the ones that shouldn't be included and the ones that changed.
+pub fn verify_sdist(options: VerifySdistOptions) -> Result<()> {
+    let build_options = BuildOptions {
+        cargo: CargoOptions {
+            manifest_path: options.manifest_path,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    build_options.run_pre_build_hooks(&["sdist"])?;
+    let mut build_context = build_options.into_build_context(false, false, false)?;
+    build_context.vendor |= options.vendor;
+    build_context.sdist_from_git |= options.from_git;
+    if let Some(include_cargo_lock) = options.include_cargo_lock {
+        build_context.include_cargo_lock = include_cargo_lock;
+    }
+    let out = tempfile::tempdir()?;
+    build_context.out = out.path().to_path_buf();
+    let (rebuilt, _) = build_context
+        .build_source_distribution()?
+        .context("Failed to build source distribution, pyproject.toml not found")?;
+
+    let sdist = &options.sdist;
+    if hash_file(sdist)? == hash_file(&rebuilt)? {
+        eprintln!(
+            "✅ {} is reproducible, it is identical to the source distribution built from the \
+            current tree",
+            sdist.display()
+        );
+        return Ok(());
+    }
+    let diff = compare_sdists(sdist, &rebuilt)?;
+    if diff.is_empty() {
+        bail!(
+            "{} contains the same files as the source distribution built from the current tree, \
+            but the archives differ, e.g. in the file name, the order of the files or their \
+            timestamps. Set SOURCE_DATE_EPOCH to the same value for both builds.",
+            sdist.display()
+        );
+    }
+    let sections = [
+        ("Missing files, included by the current tree", &diff.missing),
+        ("Extra files, not included by the current tree", &diff.extra),
+        ("Changed files", &diff.changed),
+    ];
+    for (title, paths) in sections {
+        if !paths.is_empty() {
+            eprintln!("{}:", title);
+            for path in paths {
+                eprintln!("  {}", path.display());
+            }
+        }
+    }
+    bail!(
+        "{} differs from the source distribution built from the current tree: {} missing, {} \
+        extra and {} changed file(s)",
+        sdist.display(),
+        diff.missing.len(),
+        diff.extra.len(),
+        diff.changed.len()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn write_sdist(path: &Path, top_level: &str, files: &[(&str, &str, u32)]) {
+        let mut tar = tar::Builder::new(GzEncoder::new(
+            File::create(path).unwrap(),
+            Compression::default(),
+        ));
+        for (name, contents, mode) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(*mode);
+            header.set_cksum();
+            tar.append_data(
+                &mut header,
+                format!("{}/{}", top_level, name),
+                contents.as_bytes(),
+            )
+            .unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_compare_sdists() {
+        let dir = tempfile::tempdir().unwrap();
+        let checked = dir.path().join("checked.tar.gz");
+        let rebuilt = dir.path().join("rebuilt.tar.gz");
+        write_sdist(
+            &checked,
+            "foo-0.1.0",
+            &[
+                ("PKG-INFO", "Version: 0.1.0", 0o644),
+                ("src/lib.rs", "fn main() {}", 0o644),
+                ("build.sh", "make", 0o644),
+                ("notes.txt", "", 0o644),
+            ],
+        );
+        write_sdist(
+            &rebuilt,
+            "foo-0.1.1",
+            &[
+                ("PKG-INFO", "Version: 0.1.1", 0o644),
+                ("src/lib.rs", "fn main() {}", 0o644),
+                ("build.sh", "make", 0o755),
+                ("LICENSE", "MIT", 0o644),
+            ],
+        );
+        assert_eq!(
+            compare_sdists(&checked, &rebuilt).unwrap(),
+            SdistDiff {
+                missing: vec![PathBuf::from("LICENSE")],
+                extra: vec![PathBuf::from("notes.txt")],
+                changed: vec![PathBuf::from("PKG-INFO"), PathBuf::from("build.sh")],
+            }
+        );
+        assert!(compare_sdists(&checked, &checked).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+//! The wheels `maturin build` wrote to an output directory, recorded with the sources they were
+//! built from so that `maturin build --skip-existing` can skip the builds that are up-to-date
+use crate::{BuildContext, BuiltWheelMetadata};
+use anyhow::{Context, Result};
+use fs_err as fs;
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// The name of the manifest in the output directory
+const MANIFEST_FILE: &str = ".maturin-wheels.json";
+
+/// A wheel in the output directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Which build wrote the wheel, the target triple and e.g. the interpreter
+    pub build: String,
+    /// The version of the package
+    pub version: String,
+    /// The hash of the sources and build options, see [source_hash]
+    pub source_hash: String,
+    /// The file name of the wheel in the output directory
+    pub wheel: String,
+    /// The python version tag, e.g. `cp37` or `py3`
+    pub tag: String,
+}
+
+/// The wheels in an output directory, one per build
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WheelManifest {
+    /// The last wheel of each build
+    pub wheels: Vec<ManifestEntry>,
+}
+
+impl WheelManifest {
+    /// Reads the manifest of the output directory. A missing or unreadable manifest is empty,
+    /// which only means that nothing can be skipped.
+    pub fn load(out: &Path) -> Self {
+        fs::read_to_string(out.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// The wheel the build wrote from the same version and sources, if it still exists
+    pub fn find(
+        &self,
+        out: &Path,
+        build: &str,
+        version: &str,
+        source_hash: &str,
+    ) -> Option<BuiltWheelMetadata> {
+        let entry = self.wheels.iter().find(|entry| {
+            entry.build == build && entry.version == version && entry.source_hash == source_hash
+        })?;
+        let path = out.join(&entry.wheel);
+        if path.is_file() {
+            Some((path, entry.tag.clone()))
+        } else {
+            None
+        }
+    }
+
+    /// Records the wheel of a build, replacing the one from an earlier run
+    pub fn record(out: &Path, entry: ManifestEntry) -> Result<()> {
+        let mut manifest = Self::load(out);
+        manifest.wheels.retain(|other| other.build != entry.build);
+        manifest.wheels.push(entry);
+        let path = out.join(MANIFEST_FILE);
+        let json = serde_json::to_string_pretty(&manifest)?;
+        fs::write(&path, json)
+            .with_context(|| format!("Failed to write the wheel manifest {}", path.display()))
+    }
+}
+
+/// The options that change the built wheels
+pub(crate) fn build_options(context: &BuildContext) -> String {
+    format!(
+        "{} {:?} {} {} {:?} {:?} {:?} {} {} {:?} {:?} {} {} {} {}",
+        env!("CARGO_PKG_VERSION"),
+        context.bridge,
+        context.release,
+        context.strip,
+        context.cargo_options,
+        context.rustflags,
+        context.platform_tag,
+        context.universal2,
+        context.editable,
+        context.build_tag,
+        context.env,
+        context.zig,
+        context.skip_auditwheel,
+        context.fallback_to_linux,
+        context.repair,
+    )
+}
+
+/// Hashes the files of the project that aren't ignored by git, together with the options that
+/// change the built wheels. Path dependencies outside of the workspace aren't included.
+pub fn source_hash(context: &BuildContext) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(build_options(context));
+
+    let mut roots: Vec<PathBuf> = vec![context.cargo_metadata.workspace_root.clone().into()];
+    if let Some(pyproject_dir) = context.pyproject_toml_path.parent() {
+        roots.push(pyproject_dir.to_path_buf());
+    }
+    roots.extend(context.project_layout.python_module.iter().cloned());
+    roots.extend(context.project_layout.extra_python_modules.iter().cloned());
+    roots.extend(context.project_layout.data.iter().cloned());
+    let mut files = Vec::new();
+    for root in &roots {
+        // Nested roots are already covered by the outer one
+        if roots
+            .iter()
+            .any(|other| other != root && root.starts_with(other))
+        {
+            continue;
+        }
+        let target_dir = context.target_dir.clone();
+        let out = context.out.clone();
+        let walker = WalkBuilder::new(root)
+            .filter_entry(move |entry| entry.path() != target_dir && entry.path() != out)
+            .build();
+        for entry in walker {
+            let entry = entry?;
+            if entry
+                .file_type()
+                .map_or(false, |file_type| file_type.is_file())
+            {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    // The lock file changes what is built, even when git ignores it
+    let lock_file = context.cargo_metadata.workspace_root.join("Cargo.lock");
+    if lock_file.is_file() {
+        files.push(lock_file.into());
+    }
+    files.sort();
+    files.dedup();
+    for file in files {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update(fs::read(&file)?);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wheel_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path();
+        let wheel = "foo-0.1.0-cp310-cp310-linux_x86_64.whl";
+        let entry = ManifestEntry {
+            build: "x86_64-unknown-linux-gnu CPython 3.10".to_string(),
+            version: "0.1.0".to_string(),
+            source_hash: "abc".to_string(),
+            wheel: wheel.to_string(),
+            tag: "cp310".to_string(),
+        };
+        WheelManifest::record(out, entry.clone()).unwrap();
+
+        let manifest = WheelManifest::load(out);
+        assert_eq!(manifest.wheels, [entry.clone()]);
+        // The wheel has been deleted
+        assert_eq!(manifest.find(out, &entry.build, "0.1.0", "abc"), None);
+        fs::write(out.join(wheel), "").unwrap();
+        assert_eq!(
+            manifest.find(out, &entry.build, "0.1.0", "abc"),
+            Some((out.join(wheel), "cp310".to_string()))
+        );
+        // The sources or the version changed
+        assert_eq!(manifest.find(out, &entry.build, "0.1.0", "def"), None);
+        assert_eq!(manifest.find(out, &entry.build, "0.2.0", "abc"), None);
+
+        // A new build of the same interpreter replaces the old wheel
+        let newer = ManifestEntry {
+            source_hash: "def".to_string(),
+            ..entry
+        };
+        WheelManifest::record(out, newer.clone()).unwrap();
+        assert_eq!(WheelManifest::load(out).wheels, [newer]);
+    }
+}
@@ -0,0 +1,129 @@
+//! Single file distribution of binaries as [PEP 441](https://peps.python.org/pep-0441/) zipapps
+use anyhow::{Context, Result};
+use fs_err as fs;
+use fs_err::File;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::Path;
+use tracing::debug;
+use zip::ZipWriter;
+
+/// The zipapp entrypoint, which extracts the binary into the user cache directory on the first
+/// run and then replaces itself with the binary
+const LAUNCHER: &str = r#"import os
+import subprocess
+import sys
+import tempfile
+import zipfile
+
+BINARY = "{bin_name}"
+HASH = "{hash}"
+
+
+def cache_dir():
+    if sys.platform == "win32":
+        base = os.environ.get("LOCALAPPDATA") or os.path.expanduser("~")
+    elif sys.platform == "darwin":
+        base = os.path.expanduser("~/Library/Caches")
+    else:
+        base = os.environ.get("XDG_CACHE_HOME") or os.path.expanduser("~/.cache")
+    return os.path.join(base, "maturin-zipapp", BINARY + "-" + HASH)
+
+
+def extract():
+    directory = cache_dir()
+    binary = os.path.join(directory, BINARY)
+    if os.path.isfile(binary):
+        return binary
+    os.makedirs(directory, exist_ok=True)
+    archive = os.path.dirname(os.path.abspath(__file__))
+    with zipfile.ZipFile(archive) as zipapp:
+        content = zipapp.read("bin/" + BINARY)
+    # Extract to a temporary file first so concurrent runs never see a partial binary
+    fd, tmp = tempfile.mkstemp(dir=directory)
+    with os.fdopen(fd, "wb") as f:
+        f.write(content)
+    os.chmod(tmp, 0o755)
+    os.replace(tmp, binary)
+    return binary
+
+
+def main():
+    binary = extract()
+    args = [binary] + sys.argv[1:]
+    if sys.platform == "win32":
+        sys.exit(subprocess.call(args))
+    os.execv(binary, args)
+
+
+if __name__ == "__main__":
+    main()
+"#;
+
+/// Writes a zipapp to `zipapp_path` that runs the binary at `artifact`
+///
+/// The binary is stored as `bin/<bin_name>` next to a `__main__.py` launcher, which extracts
+/// it to a cache directory keyed by the hash of the binary, so it's only extracted once.
+pub fn write_zipapp(zipapp_path: &Path, artifact: &Path, bin_name: &str) -> Result<()> {
+    debug!(
+        "Adding {} to the zipapp {}",
+        artifact.display(),
+        zipapp_path.display()
+    );
+    let binary = fs::read(artifact)?;
+    let hash = format!("{:x}", Sha256::digest(&binary));
+    let launcher = LAUNCHER
+        .replace("{bin_name}", bin_name)
+        .replace("{hash}", &hash[..16]);
+
+    let mut file = File::create(zipapp_path)?;
+    // Allows running the zipapp directly, python ignores everything before the zip archive
+    file.write_all(b"#!/usr/bin/env python3\n")?;
+    let mut zip = ZipWriter::new(file);
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    zip.start_file("__main__.py", options.unix_permissions(0o644))?;
+    zip.write_all(launcher.as_bytes())?;
+    zip.start_file(format!("bin/{}", bin_name), options.unix_permissions(0o755))?;
+    zip.write_all(&binary)?;
+    let mut file = zip
+        .finish()
+        .with_context(|| format!("Failed to write {}", zipapp_path.display()))?;
+    file.flush()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(zipapp_path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::process::Command;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_zipapp() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let binary = temp_dir.path().join("hello");
+        fs::write(&binary, "#!/bin/sh\necho \"hello $@\"\nexit 3\n").unwrap();
+        let zipapp = temp_dir.path().join("hello.pyz");
+        write_zipapp(&zipapp, &binary, "hello").unwrap();
+
+        // Run twice to use both a fresh and an already extracted binary
+        for _ in 0..2 {
+            let output = Command::new("python3")
+                .arg(&zipapp)
+                .args(["a", "b"])
+                .env("XDG_CACHE_HOME", temp_dir.path().join("cache"))
+                .env("HOME", temp_dir.path())
+                .output()
+                .unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+            assert_eq!(String::from_utf8_lossy(&output.stdout), "hello a b\n");
+            assert_eq!(output.status.code(), Some(3));
+        }
+    }
+}
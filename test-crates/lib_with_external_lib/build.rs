@@ -0,0 +1,19 @@
+//! Builds `libexternal.so`, a shared library that isn't part of any linux system
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let status = cc::Build::new()
+        .get_compiler()
+        .to_command()
+        .args(["-shared", "-fPIC", "-Wl,-soname,libexternal.so", "-o"])
+        .arg(out_dir.join("libexternal.so"))
+        .arg("src/external.c")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    println!("cargo:rerun-if-changed=src/external.c");
+    println!("cargo:rustc-link-search=native={}", out_dir.display());
+    println!("cargo:rustc-link-lib=dylib=external");
+}
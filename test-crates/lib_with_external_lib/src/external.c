@@ -0,0 +1 @@
+int external_add(int x, int y) { return x + y; }
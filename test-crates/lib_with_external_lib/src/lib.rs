@@ -0,0 +1,19 @@
+use std::os::raw::c_int;
+
+use pyo3::prelude::*;
+
+extern "C" {
+    fn external_add(x: c_int, y: c_int) -> c_int;
+}
+
+#[pyfunction]
+fn add(x: c_int, y: c_int) -> c_int {
+    unsafe { external_add(x, y) }
+}
+
+#[pymodule]
+fn lib_with_external_lib(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_wrapped(wrap_pyfunction!(add))?;
+
+    Ok(())
+}
@@ -0,0 +1,5 @@
+fn main() {
+    println!("{}", adder::add(2, 2));
+    #[cfg(unix)]
+    println!("{}", transitive_path_dep::is_sum(2, 2, 4));
+}
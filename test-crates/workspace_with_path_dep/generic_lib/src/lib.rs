@@ -1,3 +1,11 @@
 pub fn foo() -> &'static str {
     "foo"
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_foo() {
+        test_utils::assert_foo(super::foo());
+    }
+}
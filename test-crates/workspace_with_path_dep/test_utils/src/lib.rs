@@ -0,0 +1,3 @@
+pub fn assert_foo(value: &str) {
+    assert_eq!(value, "foo");
+}
@@ -129,3 +129,44 @@ pub fn invalid_manylinux_does_not_panic() -> Result<()> {
 
     Ok(())
 }
+
+/// Rust's std needs glibc 2.17, so a manylinux2010 wheel violates its policy and is either
+/// rejected or, with `--fallback-to-linux`, tagged as linux
+pub fn manylinux_fallback_to_linux() -> Result<()> {
+    let cli = |fallback: bool| {
+        let mut cli = vec![
+            "build",
+            "-m",
+            "test-crates/pyo3-pure/Cargo.toml",
+            "-i=python",
+            "--compatibility",
+            "manylinux2010",
+            "--target-dir",
+            "test-crates/targets/manylinux_fallback_to_linux",
+            "--out",
+            "test-crates/targets/manylinux_fallback_to_linux",
+        ];
+        if fallback {
+            cli.push("--fallback-to-linux");
+        }
+        BuildOptions::try_parse_from(cli)
+    };
+
+    let result = cli(false)?
+        .into_build_context(false, cfg!(feature = "faster-tests"), false)?
+        .build_wheels();
+    match result {
+        Err(err) => assert_eq!(err.to_string(), "Error ensuring manylinux_2_12 compliance"),
+        Ok(_) => bail!("Should have errored"),
+    }
+
+    let wheels = cli(true)?
+        .into_build_context(false, cfg!(feature = "faster-tests"), false)?
+        .build_wheels()?;
+    for (wheel, _) in wheels {
+        let filename = wheel.file_name().unwrap().to_string_lossy();
+        assert!(filename.contains("-linux_"), "{}", filename);
+    }
+
+    Ok(())
+}
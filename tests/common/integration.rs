@@ -71,7 +71,7 @@ pub fn test_integration(
     let wheels = build_context.build_wheels()?;
 
     // For abi3 on unix, we didn't use a python interpreter, but we need one here
-    let interpreter = if build_context.interpreter.is_empty() {
+    let interpreter = if build_context.interpreters()?.is_empty() {
         let error_message = "python3 should be a python interpreter";
         let venv_interpreter = PythonInterpreter::check_executable(
             python_interp.as_deref().unwrap_or("python3"),
@@ -82,7 +82,7 @@ pub fn test_integration(
         .context(error_message)?;
         vec![venv_interpreter]
     } else {
-        build_context.interpreter
+        build_context.interpreters()?.to_vec()
     };
     // We can do this since we know that wheels are built and returned in the
     // order they are in the build context
@@ -188,7 +188,8 @@ pub fn test_integration_conda(package: impl AsRef<Path>, bindings: Option<String
     let wheels = build_context.build_wheels()?;
 
     let mut conda_wheels: Vec<(PathBuf, PathBuf)> = vec![];
-    for ((filename, _), python_interpreter) in wheels.iter().zip(build_context.interpreter) {
+    for ((filename, _), python_interpreter) in wheels.iter().zip(build_context.interpreters()?) {
+        let python_interpreter = python_interpreter.clone();
         let executable = python_interpreter.executable;
         if executable.to_str().unwrap().contains("maturin-env-") {
             conda_wheels.push((filename.clone(), executable))
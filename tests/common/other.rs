@@ -1,6 +1,7 @@
 use anyhow::{Context, Result};
 use clap::Parser;
 use flate2::read::GzDecoder;
+use maturin::pyproject_toml::CargoLockInclusion;
 use maturin::{BuildOptions, CargoOptions, PlatformTag};
 use pretty_assertions::assert_eq;
 use std::collections::BTreeSet;
@@ -86,6 +87,108 @@ pub fn test_musl() -> Result<bool> {
     Ok(true)
 }
 
+/// Builds a sample crate (pyo3-mixed) for aarch64 in a manylinux-cross container, given that
+/// cross and docker are installed. The host interpreter configures pyo3 through a config file,
+/// since its path doesn't exist in the container.
+///
+/// The bool in the Ok() response says whether the test was actually run
+pub fn test_container_build() -> Result<bool> {
+    use std::process::{Command, Stdio};
+
+    for (program, arg) in [("cross", "--version"), ("docker", "info")] {
+        let available = Command::new(program)
+            .arg(arg)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_or(false, |status| status.success());
+        if !available {
+            return Ok(false);
+        }
+    }
+
+    // The first arg gets ignored
+    let options: BuildOptions = BuildOptions::try_parse_from([
+        "build",
+        "--manifest-path",
+        "test-crates/pyo3-mixed/Cargo.toml",
+        "--interpreter",
+        "python3",
+        "--use-container",
+        "--target",
+        "aarch64-unknown-linux-gnu",
+        "--compatibility",
+        "manylinux2014",
+        "--quiet",
+        "--target-dir",
+        "test-crates/targets/test_container_build",
+        "--out",
+        "test-crates/wheels/test_container_build",
+    ])?;
+
+    let build_context = options.into_build_context(false, cfg!(feature = "faster-tests"), false)?;
+    let wheels = build_context.build_wheels()?;
+    assert_eq!(wheels.len(), 1);
+    let file_name = wheels[0].0.file_name().unwrap().to_string_lossy();
+    assert!(
+        file_name.ends_with("manylinux_2_17_aarch64.manylinux2014_aarch64.whl"),
+        "{}",
+        file_name
+    );
+
+    Ok(true)
+}
+
+/// With `--repair`, a wheel with the linux tag gets the library outside of the system library
+/// directories it links bundled, and the extension module loads it from there
+pub fn test_repair_linux() -> Result<()> {
+    use goblin::elf::Elf;
+
+    // The first arg gets ignored
+    let options: BuildOptions = BuildOptions::try_parse_from([
+        "build",
+        "--manifest-path",
+        "test-crates/lib_with_external_lib/Cargo.toml",
+        "--interpreter",
+        "python3",
+        "--compatibility",
+        "linux",
+        "--repair",
+        "--quiet",
+        "--target-dir",
+        "test-crates/targets/test_repair_linux",
+        "--out",
+        "test-crates/wheels/test_repair_linux",
+    ])?;
+
+    let build_context = options.into_build_context(false, cfg!(feature = "faster-tests"), false)?;
+    let wheels = build_context.build_wheels()?;
+    assert_eq!(wheels.len(), 1);
+    let mut wheel = ZipArchive::new(File::open(&wheels[0].0)?)?;
+    let external = wheel
+        .file_names()
+        .filter_map(|name| name.strip_prefix("lib_with_external_lib.libs/"))
+        .find(|name| name.starts_with("libexternal-"))
+        .context("libexternal wasn't bundled")?
+        .to_string();
+    let module = wheel
+        .file_names()
+        .find(|name| name.ends_with(".so") && !name.contains(".libs/"))
+        .context("The wheel has no extension module")?
+        .to_string();
+    let mut buffer = Vec::new();
+    wheel.by_name(&module)?.read_to_end(&mut buffer)?;
+    let elf = Elf::parse(&buffer)?;
+    assert!(
+        elf.libraries.contains(&external.as_str()),
+        "{:?}",
+        elf.libraries
+    );
+    assert_eq!(elf.rpaths, vec!["$ORIGIN/../lib_with_external_lib.libs"]);
+
+    Ok(())
+}
+
 /// Test that we ignore non-existent Cargo.lock file listed by `cargo package --list`,
 /// which seems to only occur with workspaces.
 /// See https://github.com/rust-lang/cargo/issues/7938#issuecomment-593280660 and
@@ -112,6 +215,140 @@ pub fn test_workspace_cargo_lock() -> Result<()> {
     Ok(())
 }
 
+/// Test that `--build-dir` puts the cargo target directory and the wheels into the build
+/// directory
+pub fn test_build_dir() -> Result<()> {
+    let build_dir = Path::new("test-crates/targets/test_build_dir");
+    let _ = fs_err::remove_dir_all(build_dir);
+    // The first arg gets ignored
+    let options: BuildOptions = BuildOptions::try_parse_from([
+        "build",
+        "--manifest-path",
+        "test-crates/hello-world/Cargo.toml",
+        "--compatibility",
+        "linux",
+        "--quiet",
+        "--build-dir",
+        "test-crates/targets/test_build_dir",
+    ])?;
+
+    let build_context = options.into_build_context(false, cfg!(feature = "faster-tests"), false)?;
+    assert_eq!(build_context.target_dir, build_dir.join("target"));
+    let wheels = build_context.build_wheels()?;
+    assert_eq!(wheels.len(), 1);
+    assert_eq!(
+        wheels[0].0.parent(),
+        Some(build_dir.join("wheels").as_path())
+    );
+    assert!(wheels[0].0.is_file());
+    assert!(build_dir
+        .join("target/debug")
+        .join(format!("hello-world{}", std::env::consts::EXE_SUFFIX))
+        .is_file());
+    Ok(())
+}
+
+pub fn test_skip_existing() -> Result<()> {
+    let build_dir = Path::new("test-crates/targets/test_skip_existing");
+    let _ = fs_err::remove_dir_all(build_dir);
+    // The first arg gets ignored
+    let options: BuildOptions = BuildOptions::try_parse_from([
+        "build",
+        "--manifest-path",
+        "test-crates/hello-world/Cargo.toml",
+        "--compatibility",
+        "linux",
+        "--quiet",
+        "--build-dir",
+        "test-crates/targets/test_skip_existing",
+    ])?;
+    let manifest = build_dir.join("wheels/.maturin-wheels.json");
+
+    // Without --skip-existing, nothing is recorded
+    let build_context =
+        options
+            .clone()
+            .into_build_context(false, cfg!(feature = "faster-tests"), false)?;
+    build_context.build_wheels()?;
+    assert!(!manifest.exists());
+
+    let mut build_context =
+        options.into_build_context(false, cfg!(feature = "faster-tests"), false)?;
+    build_context.skip_existing = true;
+    let wheels = build_context.build_wheels()?;
+    assert!(manifest.is_file());
+    let modified = fs_err::metadata(&wheels[0].0)?.modified()?;
+
+    // The second build reuses the wheel instead of writing it again
+    let rebuilt = build_context.build_wheels()?;
+    assert_eq!(rebuilt[0].0, wheels[0].0);
+    assert_eq!(fs_err::metadata(&rebuilt[0].0)?.modified()?, modified);
+    Ok(())
+}
+
+pub fn test_artifact_cache() -> Result<()> {
+    use fs_err as fs;
+
+    let project = tempfile::tempdir()?;
+    let target_dir = Path::new("test-crates/targets/test_artifact_cache");
+    let _ = fs::remove_dir_all(target_dir);
+    fs::create_dir(project.path().join("src"))?;
+    fs::write(
+        project.path().join("Cargo.toml"),
+        "[package]\nname = \"artifact-cache\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+        [lib]\ncrate-type = [\"cdylib\"]\n",
+    )?;
+    let lib_rs = project.path().join("src/lib.rs");
+    fs::write(
+        &lib_rs,
+        "#[no_mangle]\npub extern \"C\" fn answer() -> i32 { 42 }\n",
+    )?;
+    let pyproject_toml = |cache: bool| {
+        fs::write(
+            project.path().join("pyproject.toml"),
+            format!(
+                "[build-system]\nrequires = [\"maturin\"]\nbuild-backend = \"maturin\"\n\n\
+                [tool.maturin]\nbindings = \"cffi\"\ncache = {}\n",
+                cache
+            ),
+        )
+    };
+
+    // Whether the library of the build came from the cache
+    let cache_hit = || -> Result<bool> {
+        let build_options = BuildOptions {
+            cargo: CargoOptions {
+                manifest_path: Some(project.path().join("Cargo.toml")),
+                quiet: true,
+                target_dir: Some(target_dir.to_path_buf()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let build_context = build_options.into_build_context(false, false, false)?;
+        let artifact = build_context.compile_cdylib(None, None)?;
+        Ok(artifact.path.starts_with(target_dir.join("maturin-cache")))
+    };
+
+    // The cache is opt-in
+    pyproject_toml(false)?;
+    assert!(!cache_hit()?);
+    assert!(!cache_hit()?);
+    assert!(!target_dir.join("maturin-cache").exists());
+
+    pyproject_toml(true)?;
+    assert!(!cache_hit()?);
+    assert!(cache_hit()?);
+    // Changing the sources misses the cache
+    fs::write(
+        &lib_rs,
+        "#[no_mangle]\npub extern \"C\" fn answer() -> i32 { 43 }\n",
+    )?;
+    assert!(!cache_hit()?);
+    assert!(cache_hit()?);
+    Ok(())
+}
+
 pub fn test_source_distribution(
     package: impl AsRef<Path>,
     expected_files: Vec<&str>,
@@ -171,6 +408,272 @@ pub fn test_source_distribution(
     Ok(())
 }
 
+/// Builds a source distribution with `--sdist-from-git` from a fresh git repository with an
+/// untracked file, which must be left out, and then with an uncommitted change, which must fail
+pub fn test_source_distribution_from_git() -> Result<()> {
+    use fs_err as fs;
+    use std::process::Command;
+
+    let project = tempfile::tempdir()?;
+    let out = tempfile::tempdir()?;
+    fs::create_dir(project.path().join("src"))?;
+    fs::write(
+        project.path().join("Cargo.toml"),
+        "[package]\nname = \"from-git\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )?;
+    fs::write(project.path().join("src/main.rs"), "fn main() {}\n")?;
+    fs::write(
+        project.path().join("pyproject.toml"),
+        "[build-system]\nrequires = [\"maturin\"]\nbuild-backend = \"maturin\"\n\n\
+        [tool.maturin]\nbindings = \"bin\"\n",
+    )?;
+    let git = |args: &[&str]| -> Result<()> {
+        let status = Command::new("git")
+            .args([
+                "-c",
+                "user.name=maturin",
+                "-c",
+                "user.email=maturin@example.com",
+            ])
+            .args(args)
+            .current_dir(project.path())
+            .status()?;
+        anyhow::ensure!(status.success(), "git {:?} failed", args);
+        Ok(())
+    };
+    git(&["init", "-q"])?;
+    git(&["add", "Cargo.toml", "src/main.rs", "pyproject.toml"])?;
+    git(&["commit", "-q", "-m", "Initial commit"])?;
+    fs::write(project.path().join("secret.txt"), "hunter2\n")?;
+
+    let build_options = BuildOptions {
+        out: Some(out.path().to_path_buf()),
+        sdist_from_git: true,
+        cargo: CargoOptions {
+            manifest_path: Some(project.path().join("Cargo.toml")),
+            quiet: true,
+            target_dir: Some(PathBuf::from("test-crates/targets/sdist_from_git")),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let build_context = build_options
+        .clone()
+        .into_build_context(false, false, false)?;
+    let (path, _) = build_context
+        .build_source_distribution()?
+        .context("Failed to build source distribution")?;
+    let mut archive = Archive::new(GzDecoder::new(File::open(path)?));
+    let mut files = BTreeSet::new();
+    for entry in archive.entries()? {
+        files.insert(format!("{}", entry?.path()?.display()));
+    }
+    // The Cargo.lock that cargo generated isn't committed either
+    assert_eq!(
+        files,
+        BTreeSet::from_iter(
+            [
+                "from_git-0.1.0/Cargo.toml",
+                "from_git-0.1.0/PKG-INFO",
+                "from_git-0.1.0/pyproject.toml",
+                "from_git-0.1.0/src/main.rs",
+            ]
+            .map(ToString::to_string)
+        )
+    );
+
+    fs::write(
+        project.path().join("src/main.rs"),
+        "fn main() { todo!() }\n",
+    )?;
+    let build_context = build_options.into_build_context(false, false, false)?;
+    let err = build_context.build_source_distribution().unwrap_err();
+    assert!(format!("{:?}", err).contains("uncommitted changes"));
+    Ok(())
+}
+
+pub fn test_source_distribution_cargo_lock() -> Result<()> {
+    use fs_err as fs;
+
+    let project = tempfile::tempdir()?;
+    let out = tempfile::tempdir()?;
+    fs::create_dir(project.path().join("src"))?;
+    fs::write(
+        project.path().join("Cargo.toml"),
+        "[package]\nname = \"cargo-lock\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )?;
+    fs::write(project.path().join("src/main.rs"), "fn main() {}\n")?;
+    fs::write(
+        project.path().join("pyproject.toml"),
+        "[build-system]\nrequires = [\"maturin\"]\nbuild-backend = \"maturin\"\n\n\
+        [tool.maturin]\nbindings = \"bin\"\ninclude-cargo-lock = \"never\"\n",
+    )?;
+
+    let sdist_files = |include_cargo_lock| -> Result<BTreeSet<String>> {
+        let build_options = BuildOptions {
+            out: Some(out.path().to_path_buf()),
+            include_cargo_lock,
+            cargo: CargoOptions {
+                manifest_path: Some(project.path().join("Cargo.toml")),
+                quiet: true,
+                target_dir: Some(PathBuf::from("test-crates/targets/sdist_cargo_lock")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let build_context = build_options.into_build_context(false, false, false)?;
+        let (path, _) = build_context
+            .build_source_distribution()?
+            .context("Failed to build source distribution")?;
+        let mut archive = Archive::new(GzDecoder::new(File::open(path)?));
+        let mut files = BTreeSet::new();
+        for entry in archive.entries()? {
+            files.insert(format!("{}", entry?.path()?.display()));
+        }
+        Ok(files)
+    };
+    // cargo has generated the lock file, but pyproject.toml excludes it
+    let files = sdist_files(None)?;
+    assert!(project.path().join("Cargo.lock").is_file());
+    assert!(
+        !files.contains("cargo_lock-0.1.0/Cargo.lock"),
+        "{:?}",
+        files
+    );
+    // The command line overrides pyproject.toml
+    let files = sdist_files(Some(CargoLockInclusion::Always))?;
+    assert!(files.contains("cargo_lock-0.1.0/Cargo.lock"), "{:?}", files);
+    Ok(())
+}
+
+/// Verifies that a wheel builds from the source distribution of a crate that embeds a data file,
+/// which must fail once the data file is excluded from the source distribution
+pub fn test_verify_source_distribution() -> Result<()> {
+    use fs_err as fs;
+    use maturin::verify_source_distribution;
+
+    let project = tempfile::tempdir()?;
+    let out = tempfile::tempdir()?;
+    fs::create_dir(project.path().join("src"))?;
+    let cargo_toml =
+        "[package]\nname = \"sdist-verify\"\nversion = \"0.1.0\"\nedition = \"2021\"\n";
+    fs::write(project.path().join("Cargo.toml"), cargo_toml)?;
+    fs::write(
+        project.path().join("src/main.rs"),
+        "fn main() { print!(\"{}\", include_str!(\"../data.txt\")) }\n",
+    )?;
+    fs::write(project.path().join("data.txt"), "data\n")?;
+    fs::write(
+        project.path().join("pyproject.toml"),
+        "[build-system]\nrequires = [\"maturin\"]\nbuild-backend = \"maturin\"\n\n\
+        [tool.maturin]\nbindings = \"bin\"\n",
+    )?;
+    let target_dir = Path::new("test-crates/targets/sdist_verify");
+    let build_sdist = || -> Result<PathBuf> {
+        let build_options = BuildOptions {
+            out: Some(out.path().to_path_buf()),
+            cargo: CargoOptions {
+                manifest_path: Some(project.path().join("Cargo.toml")),
+                quiet: true,
+                target_dir: Some(target_dir.to_path_buf()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let build_context = build_options.into_build_context(false, false, false)?;
+        let (path, _) = build_context
+            .build_source_distribution()?
+            .context("Failed to build source distribution")?;
+        Ok(path)
+    };
+    let maturin = Path::new(env!("CARGO_BIN_EXE_maturin"));
+
+    let wheel = verify_source_distribution(&build_sdist()?, maturin, target_dir)?;
+    assert!(wheel.starts_with("sdist_verify-0.1.0-py3-none-"));
+
+    fs::write(
+        project.path().join("Cargo.toml"),
+        format!("{}exclude = [\"data.txt\"]\n", cargo_toml),
+    )?;
+    let err = verify_source_distribution(&build_sdist()?, maturin, target_dir).unwrap_err();
+    assert!(err.to_string().contains("may be missing from it"));
+    Ok(())
+}
+
+pub fn test_source_distribution_license_files() -> Result<()> {
+    use fs_err as fs;
+
+    let root = tempfile::tempdir()?;
+    let project = root.path().join("project");
+    let out = tempfile::tempdir()?;
+    fs::create_dir_all(project.join("src"))?;
+    fs::create_dir_all(project.join("licenses/vendor"))?;
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"sdist-licenses\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )?;
+    fs::write(project.join("src/main.rs"), "fn main() {}\n")?;
+    fs::write(project.join("LICENSE"), "project")?;
+    fs::write(root.path().join("LICENSE"), "workspace")?;
+    fs::write(project.join("licenses/vendor/MIT.txt"), "MIT")?;
+    fs::write(
+        project.join("pyproject.toml"),
+        "[build-system]\nrequires = [\"maturin\"]\nbuild-backend = \"maturin\"\n\n\
+        [project]\nname = \"sdist-licenses\"\nversion = \"0.1.0\"\n\n\
+        [tool.maturin]\nbindings = \"bin\"\nlicense-files = [\"../LICENSE*\", \"licenses/**/*\"]\n",
+    )?;
+    let target_dir = Path::new("test-crates/targets/sdist_license_files");
+
+    // The license files of the wheel, with their contents
+    let build = |sdist: bool, manifest_path: PathBuf| -> Result<Vec<(String, String)>> {
+        let build_options = BuildOptions {
+            out: Some(out.path().to_path_buf()),
+            cargo: CargoOptions {
+                manifest_path: Some(manifest_path),
+                quiet: true,
+                target_dir: Some(target_dir.to_path_buf()),
+                ..Default::default()
+            },
+            platform_tag: vec![PlatformTag::Linux],
+            ..Default::default()
+        };
+        let build_context = build_options.into_build_context(false, false, false)?;
+        if sdist {
+            let (path, _) = build_context
+                .build_source_distribution()?
+                .context("Failed to build source distribution")?;
+            Archive::new(GzDecoder::new(File::open(path)?)).unpack(out.path())?;
+            return Ok(Vec::new());
+        }
+        let wheels = build_context.build_wheels()?;
+        let mut wheel = ZipArchive::new(File::open(&wheels[0].0)?)?;
+        let mut licenses = Vec::new();
+        for index in 0..wheel.len() {
+            let mut file = wheel.by_index(index)?;
+            if let Some((_, license)) = file.name().split_once(".dist-info/licenses/") {
+                let license = license.to_string();
+                let mut contents = String::new();
+                file.read_to_string(&mut contents)?;
+                licenses.push((license, contents));
+            }
+        }
+        licenses.sort();
+        Ok(licenses)
+    };
+
+    let expected = vec![
+        ("LICENSE".to_string(), "project".to_string()),
+        ("LICENSE-1".to_string(), "workspace".to_string()),
+        ("MIT.txt".to_string(), "MIT".to_string()),
+    ];
+    assert_eq!(build(false, project.join("Cargo.toml"))?, expected);
+    build(true, project.join("Cargo.toml"))?;
+    let unpacked = out.path().join("sdist_licenses-0.1.0");
+    assert!(unpacked.join("licenses/vendor/MIT.txt").is_file());
+    assert_eq!(build(false, unpacked.join("Cargo.toml"))?, expected);
+    Ok(())
+}
+
 pub fn check_wheel_files(
     package: impl AsRef<Path>,
     expected_files: Vec<&str>,
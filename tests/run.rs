@@ -391,6 +391,12 @@ fn invalid_manylinux_does_not_panic() {
     handle_result(errors::invalid_manylinux_does_not_panic())
 }
 
+#[test]
+#[cfg_attr(not(all(target_os = "linux", target_env = "gnu")), ignore)]
+fn manylinux_fallback_to_linux() {
+    handle_result(errors::manylinux_fallback_to_linux())
+}
+
 #[test]
 #[cfg_attr(not(target_os = "linux"), ignore)]
 fn musl() {
@@ -400,11 +406,46 @@ fn musl() {
     }
 }
 
+#[test]
+#[cfg_attr(not(target_os = "linux"), ignore)]
+fn container_build() {
+    let ran = handle_result(other::test_container_build());
+    if !ran {
+        eprintln!("⚠️  Warning: cross and/or docker not installed, test didn't run");
+    }
+}
+
+#[test]
+#[cfg_attr(not(target_os = "linux"), ignore)]
+fn repair_linux() {
+    handle_result(other::test_repair_linux())
+}
+
 #[test]
 fn workspace_cargo_lock() {
     handle_result(other::test_workspace_cargo_lock())
 }
 
+#[test]
+fn build_dir() {
+    handle_result(other::test_build_dir())
+}
+
+#[test]
+fn sdist_license_files() {
+    handle_result(other::test_source_distribution_license_files())
+}
+
+#[test]
+fn skip_existing() {
+    handle_result(other::test_skip_existing())
+}
+
+#[test]
+fn artifact_cache() {
+    handle_result(other::test_artifact_cache())
+}
+
 #[test]
 fn workspace_members_non_local_dep_sdist() {
     let cargo_toml = indoc!(
@@ -465,6 +506,42 @@ fn lib_with_path_dep_sdist() {
     ))
 }
 
+#[test]
+fn renamed_path_dep_sdist() {
+    handle_result(other::test_source_distribution(
+        "test-crates/sdist_with_renamed_path_dep",
+        vec![
+            "sdist_with_renamed_path_dep-0.1.0/local_dependencies/some_path_dep/Cargo.toml",
+            "sdist_with_renamed_path_dep-0.1.0/local_dependencies/some_path_dep/src/lib.rs",
+            "sdist_with_renamed_path_dep-0.1.0/local_dependencies/transitive_path_dep/Cargo.toml",
+            "sdist_with_renamed_path_dep-0.1.0/local_dependencies/transitive_path_dep/src/lib.rs",
+            "sdist_with_renamed_path_dep-0.1.0/Cargo.toml",
+            "sdist_with_renamed_path_dep-0.1.0/Cargo.lock",
+            "sdist_with_renamed_path_dep-0.1.0/pyproject.toml",
+            "sdist_with_renamed_path_dep-0.1.0/src/main.rs",
+            "sdist_with_renamed_path_dep-0.1.0/PKG-INFO",
+        ],
+        Some((
+            Path::new("sdist_with_renamed_path_dep-0.1.0/Cargo.toml"),
+            indoc!(
+                r#"
+                [package]
+                name = "sdist_with_renamed_path_dep"
+                version = "0.1.0"
+                edition = "2021"
+
+                [dependencies]
+                adder = { path = "local_dependencies/some_path_dep", package = "some_path_dep" }
+
+                [target.'cfg(unix)'.dependencies]
+                transitive_path_dep = { path = "local_dependencies/transitive_path_dep" }
+                "#
+            ),
+        )),
+        "sdist-renamed-path-dep",
+    ))
+}
+
 #[test]
 fn pyo3_mixed_src_layout_sdist() {
     handle_result(other::test_source_distribution(
@@ -484,6 +561,55 @@ fn pyo3_mixed_src_layout_sdist() {
     ))
 }
 
+#[test]
+fn sdist_from_git() {
+    handle_result(other::test_source_distribution_from_git())
+}
+
+#[test]
+fn sdist_cargo_lock() {
+    handle_result(other::test_source_distribution_cargo_lock())
+}
+
+#[test]
+fn sdist_verify() {
+    handle_result(other::test_verify_source_distribution())
+}
+
+#[test]
+fn workspace_subdir_crate_sdist() {
+    handle_result(other::test_source_distribution(
+        "test-crates/workspace_subdir_crate/crates/python",
+        vec![
+            "workspace_subdir_crate-0.1.0/local_dependencies/common/Cargo.toml",
+            "workspace_subdir_crate-0.1.0/local_dependencies/common/src/lib.rs",
+            "workspace_subdir_crate-0.1.0/crates/python/Cargo.toml",
+            "workspace_subdir_crate-0.1.0/crates/python/Cargo.lock",
+            "workspace_subdir_crate-0.1.0/crates/python/src/main.rs",
+            "workspace_subdir_crate-0.1.0/pyproject.toml",
+            "workspace_subdir_crate-0.1.0/PKG-INFO",
+        ],
+        Some((
+            Path::new("workspace_subdir_crate-0.1.0/pyproject.toml"),
+            indoc!(
+                r#"
+                [build-system]
+                requires = ["maturin>=0.13,<0.14"]
+                build-backend = "maturin"
+
+                [project]
+                name = "workspace-subdir-crate"
+
+                [tool.maturin]
+                bindings = "bin"
+                manifest-path = "crates/python/Cargo.toml"
+                "#
+            ),
+        )),
+        "sdist-workspace-subdir-crate",
+    ))
+}
+
 #[test]
 fn pyo3_mixed_include_exclude_sdist() {
     handle_result(other::test_source_distribution(
@@ -497,13 +623,14 @@ fn pyo3_mixed_include_exclude_sdist() {
             "pyo3_mixed_include_exclude-2.1.3/check_installed/check_installed.py",
             // "pyo3_mixed_include_exclude-2.1.3/pyo3_mixed_include_exclude/exclude_this_file, excluded
             "pyo3_mixed_include_exclude-2.1.3/pyo3_mixed_include_exclude/__init__.py",
+            "pyo3_mixed_include_exclude-2.1.3/pyo3_mixed_include_exclude/cargo_exclude_this_file", // python source, Cargo.toml doesn't apply
             "pyo3_mixed_include_exclude-2.1.3/pyo3_mixed_include_exclude/include_this_file", // included
             "pyo3_mixed_include_exclude-2.1.3/pyo3_mixed_include_exclude/python_module/__init__.py",
             "pyo3_mixed_include_exclude-2.1.3/pyo3_mixed_include_exclude/python_module/double.py",
             "pyo3_mixed_include_exclude-2.1.3/pyproject.toml",
             "pyo3_mixed_include_exclude-2.1.3/src/lib.rs",
             // "pyo3_mixed_include_exclude-2.1.3/tests/test_pyo3_mixed_include_exclude.py", excluded
-            "pyo3_mixed_include_exclude-2.1.3/tox.ini",
+            // "pyo3_mixed_include_exclude-2.1.3/tox.ini", excluded by Cargo.toml
         ],
         None,
         "sdist-pyo3-mixed-include-exclude",
@@ -520,6 +647,7 @@ fn pyo3_mixed_include_exclude_wheel_files() {
             "pyo3_mixed_include_exclude-2.1.3.dist-info/WHEEL",
             "pyo3_mixed_include_exclude-2.1.3.dist-info/entry_points.txt",
             "pyo3_mixed_include_exclude/__init__.py",
+            "pyo3_mixed_include_exclude/cargo_exclude_this_file",
             "pyo3_mixed_include_exclude/include_this_file",
             "pyo3_mixed_include_exclude/python_module/__init__.py",
             "pyo3_mixed_include_exclude/python_module/double.py",
@@ -543,7 +671,22 @@ fn workspace_with_path_dep_sdist() {
             "workspace_with_path_dep-0.1.0/src/lib.rs",
             "workspace_with_path_dep-0.1.0/PKG-INFO",
         ],
-        None,
+        Some((
+            Path::new("workspace_with_path_dep-0.1.0/local_dependencies/generic_lib/Cargo.toml"),
+            indoc!(
+                r#"
+                [package]
+                name = "generic_lib"
+                version = "0.1.0"
+                edition = "2021"
+
+                # See more keys and their definitions at https://doc.rust-lang.org/cargo/reference/manifest.html
+
+                [dependencies]
+                transitive_lib = { path = "../transitive_lib" }
+                "#
+            ),
+        )),
         "sdist-workspace-with-path-dep",
     ))
 }
@@ -557,11 +700,39 @@ fn workspace_inheritance_sdist() {
             "workspace_inheritance-0.1.0/local_dependencies/generic_lib/Cargo.toml",
             "workspace_inheritance-0.1.0/local_dependencies/generic_lib/src/lib.rs",
             "workspace_inheritance-0.1.0/Cargo.toml",
+            "workspace_inheritance-0.1.0/LICENSE",
+            "workspace_inheritance-0.1.0/README.md",
             "workspace_inheritance-0.1.0/pyproject.toml",
             "workspace_inheritance-0.1.0/src/lib.rs",
             "workspace_inheritance-0.1.0/PKG-INFO",
         ],
-        None,
+        Some((
+            Path::new("workspace_inheritance-0.1.0/Cargo.toml"),
+            indoc!(
+                r#"
+                [package]
+                name = "workspace-inheritance"
+                version = "0.1.0"
+                authors = ["konstin <konstin@mailbox.org>"]
+                license = "MIT"
+                repository = "https://github.com/PyO3/maturin"
+                keywords = ["python", "workspace"]
+                readme = "README.md"
+                license-file = "LICENSE"
+                edition = "2021"
+
+                # See more keys and their definitions at https://doc.rust-lang.org/cargo/reference/manifest.html
+                [lib]
+                name = "workspace_inheritance"
+                crate-type = ["cdylib"]
+
+                [dependencies]
+                pyo3 = { version = "0.17.3", features = ["extension-module"] }
+                generic_lib.path = "local_dependencies/generic_lib"
+                libc = { version = "0.2", features = ["std","extra_traits"] , optional = true}
+                "#
+            ),
+        )),
         "sdist-workspace-inheritance",
     ))
 }